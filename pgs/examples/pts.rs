@@ -0,0 +1,29 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+// Prints when each display set of a SUP file is presented:
+//
+//     cargo run -p pgs --example pts -- subtitles.sup
+
+use pgs::{prelude::*, ts_to_timestamp};
+use std::{env, fs::File, io::BufReader, process::exit};
+
+fn main() {
+
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: pts SUP-FILE");
+            exit(2)
+        }
+    };
+    let mut input = BufReader::new(File::open(path).expect("Could not open input file."));
+
+    for display_set in input.display_sets(&ReadOptions::default()) {
+        let display_set = display_set.expect("Could not read display set.");
+        println!("{}", ts_to_timestamp(display_set.pts));
+    }
+}
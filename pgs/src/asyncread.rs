@@ -0,0 +1,85 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    displayset::{DisplaySet, DisplaySetAssembler, ReadResult as DisplaySetReadResult},
+    segment::{
+        parse_segment,
+        payload_size,
+        ReadError as SegmentReadError,
+        ReadOptions,
+        ReadResult as SegmentReadResult,
+        Segment,
+        HEADER_LENGTH,
+        MAGIC_NUMBER,
+    },
+};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// Asynchronous counterparts of ReadSegmentExt and ReadDisplaySetExt, which parse exactly as they
+// do and fail with the same errors. They live apart from those as std's Cursor is AsyncRead too,
+// and would otherwise have two of every method wherever both modules are imported whole.
+
+#[allow(async_fn_in_trait)]
+pub trait AsyncReadSegExt {
+    async fn read_segment(&mut self) -> SegmentReadResult<Segment>;
+    async fn read_segment_with(&mut self, options: &ReadOptions) -> SegmentReadResult<Segment>;
+}
+
+impl<T: AsyncRead + Unpin> AsyncReadSegExt for T {
+
+    async fn read_segment(&mut self) -> SegmentReadResult<Segment> {
+        self.read_segment_with(&ReadOptions::default()).await
+    }
+
+    async fn read_segment_with(&mut self, options: &ReadOptions) -> SegmentReadResult<Segment> {
+
+        if self.read_u16().await? != MAGIC_NUMBER {
+            return Err(SegmentReadError::UnrecognizedMagicNumber)
+        }
+
+        let mut header = [0u8; HEADER_LENGTH];
+        self.read_exact(&mut header).await?;
+
+        let mut payload = vec![0u8; payload_size(&header)];
+        self.read_exact(&mut payload).await?;
+
+        parse_segment(&header, &payload, options)
+    }
+}
+
+#[allow(async_fn_in_trait)]
+pub trait AsyncReadDisplaySetExt {
+    async fn read_display_set(&mut self) -> DisplaySetReadResult<DisplaySet>;
+    async fn read_display_set_with(
+        &mut self,
+        options: &ReadOptions,
+    ) -> DisplaySetReadResult<DisplaySet>;
+}
+
+impl<T: AsyncRead + Unpin> AsyncReadDisplaySetExt for T {
+
+    async fn read_display_set(&mut self) -> DisplaySetReadResult<DisplaySet> {
+        self.read_display_set_with(&ReadOptions::default()).await
+    }
+
+    async fn read_display_set_with(
+        &mut self,
+        options: &ReadOptions,
+    ) -> DisplaySetReadResult<DisplaySet> {
+
+        let mut assembler = DisplaySetAssembler::new(options);
+
+        loop {
+            if let Some(display_set) = assembler.push(self.read_segment_with(options).await?)? {
+                return Ok(display_set)
+            }
+        }
+    }
+}
@@ -0,0 +1,251 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use super::super::{
+    displayset::{
+        Cid,
+        CompositionObject,
+        Object,
+        Palette,
+        PaletteEntry,
+        ReadDisplaySetExt,
+        ReadError as DisplaySetReadError,
+        Window,
+        WriteDisplaySetExt,
+    },
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    segment::{CompositionState, EndSegment, ReadSegmentExt, Sequence, WriteSegmentExt},
+};
+use std::{
+    future::Future,
+    io::{Cursor, ErrorKind, Result as IoResult},
+    pin::{pin, Pin},
+    task::{Context, Poll, Waker},
+};
+use rand::{thread_rng, Rng};
+use tokio::io::ReadBuf;
+
+// Hands out at most seven bytes at a time, and only every other time it is polled.
+struct ChunkedStream {
+    data: Vec<u8>,
+    position: usize,
+    pending: bool,
+}
+
+impl ChunkedStream {
+
+    fn new(data: &[u8]) -> Self {
+        Self { data: data.to_vec(), position: 0, pending: false }
+    }
+}
+
+impl AsyncRead for ChunkedStream {
+
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<IoResult<()>> {
+
+        let this = self.get_mut();
+
+        this.pending = !this.pending;
+
+        if this.pending {
+            cx.waker().wake_by_ref();
+            return Poll::Pending
+        }
+
+        let end = this.data.len().min(this.position + 7).min(this.position + buf.remaining());
+
+        buf.put_slice(&this.data[this.position..end]);
+        this.position = end;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output
+        }
+    }
+}
+
+// What a read came to, with IO errors reduced to their kind, as std and tokio describe the same
+// kind of failure differently.
+fn outcome<T>(result: DisplaySetReadResult<T>) -> Result<T, String> {
+    result.map_err(|err| match err {
+        DisplaySetReadError::SegmentError { source: SegmentReadError::IoError { source } } => {
+            format!("{:?}", source.kind())
+        }
+        DisplaySetReadError::SegmentError { source } => source.to_string(),
+        err => err.to_string(),
+    })
+}
+
+// Reads display sets until the first error, both ways.
+fn read_both(data: &[u8], options: &ReadOptions) -> (Vec<Result<DisplaySet, String>>, usize) {
+
+    let mut cursor = Cursor::new(data);
+    let mut stream = ChunkedStream::new(data);
+    let mut sync = vec![];
+    let mut asynchronous = vec![];
+
+    loop {
+
+        let result = outcome(ReadDisplaySetExt::read_display_set_with(&mut cursor, options));
+        let done = result.is_err();
+
+        sync.push(result);
+
+        if done {
+            break
+        }
+    }
+    loop {
+
+        let result = outcome(block_on(stream.read_display_set_with(options)));
+        let done = result.is_err();
+
+        asynchronous.push(result);
+
+        if done {
+            break
+        }
+    }
+
+    assert_eq!(asynchronous, sync);
+
+    (sync, stream.position)
+}
+
+fn display_set(pts: u32, state: CompositionState) -> DisplaySet {
+
+    let mut rng = thread_rng();
+    let mut display_set = DisplaySet {
+        pts,
+        dts: pts.saturating_sub(900),
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+    let mut palette = Palette::default();
+
+    // Noise without index 0 takes a byte per pixel, so this object is split into two fragments.
+    let lines = (0..90)
+        .map(|_| (0..1_000).map(|_| rng.gen_range(1..=255)).collect::<Vec<u8>>())
+        .collect::<Vec<Vec<u8>>>();
+
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+    display_set.composition.state = state;
+    display_set.windows.insert(WindowId(0), Window { x: 0, y: 900, width: 1_000, height: 90 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width: 1_000, height: 90, sequence: Sequence::Single, lines },
+    );
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 0, y: 900, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+fn stream() -> Vec<u8> {
+
+    let mut buffer = vec![];
+
+    buffer.write_display_set(&display_set(90_000, CompositionState::EpochStart)).unwrap();
+    buffer.write_display_set(&display_set(180_000, CompositionState::AcquisitionPoint)).unwrap();
+    buffer.write_display_set(&DisplaySet { pts: 270_000, ..Default::default() }).unwrap();
+
+    buffer
+}
+
+#[test]
+fn test_async_segments_match_sync() {
+
+    let buffer = stream();
+    let options = ReadOptions { keep_raw: true, ..Default::default() };
+    let mut cursor = Cursor::new(&buffer);
+    let mut stream = ChunkedStream::new(&buffer);
+    let mut count = 0;
+
+    while (cursor.position() as usize) < buffer.len() {
+
+        let segment = ReadSegmentExt::read_segment_with(&mut cursor, &options).unwrap();
+
+        assert_eq!(block_on(stream.read_segment_with(&options)).unwrap(), segment);
+        count += 1;
+    }
+
+    assert_eq!(count, 15);
+    assert!(matches!(
+        block_on(stream.read_segment()),
+        Err(SegmentReadError::IoError { source }) if source.kind() == ErrorKind::UnexpectedEof,
+    ));
+}
+
+#[test]
+fn test_async_display_sets_match_sync() {
+
+    let buffer = stream();
+    let (display_sets, position) = read_both(&buffer, &ReadOptions::default());
+
+    assert_eq!(display_sets.len(), 4);
+    assert_eq!(display_sets[1].as_ref().unwrap().pts, 180_000);
+    assert_eq!(display_sets[3], Err("UnexpectedEof".to_string()));
+    assert_eq!(position, buffer.len());
+
+    let options = ReadOptions { keep_raw: true, lenient: true, ..Default::default() };
+    let (display_sets, _) = read_both(&buffer, &options);
+    let segments = display_sets.iter()
+        .filter_map(|display_set| display_set.as_ref().ok()?.raw_segments())
+        .flatten()
+        .cloned()
+        .collect::<Vec<Vec<u8>>>();
+
+    assert_eq!(segments.concat(), buffer);
+}
+
+#[test]
+fn test_async_errors_match_sync() {
+
+    let buffer = stream();
+
+    // Cut off partway through every kind of segment, including within object fragments.
+    for length in (0..buffer.len()).step_by(997).chain([1, 12, 13, 14, 40]) {
+        read_both(&buffer[..length], &ReadOptions::default());
+    }
+
+    let mut noisy = vec![];
+
+    noisy.write_segment(&Segment::End(EndSegment::default())).unwrap();
+    noisy.extend_from_slice(&buffer);
+
+    let (display_sets, _) = read_both(&noisy, &ReadOptions::default());
+
+    assert_eq!(
+        display_sets,
+        vec![Err(DisplaySetReadError::MissingPresentationCompositionSegment.to_string())],
+    );
+    assert!(read_both(&noisy, &ReadOptions { lenient: true, ..Default::default() }).0[0].is_ok());
+
+    let (display_sets, _) = read_both(&[0x50, 0x48, 0, 0], &ReadOptions::default());
+
+    assert_eq!(
+        display_sets,
+        vec![Err(SegmentReadError::UnrecognizedMagicNumber.to_string())],
+    );
+}
@@ -0,0 +1,60 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    displayset::{
+        DisplaySet,
+        WriteDisplaySetExt,
+        WriteError as DisplaySetWriteError,
+        WriteResult as DisplaySetWriteResult,
+    },
+    segment::{Segment, WriteResult as SegmentWriteResult, WriteSegmentExt},
+};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+// Asynchronous counterparts of WriteSegmentExt and WriteDisplaySetExt. Each writes into a buffer
+// first with the same code those do, so that nothing reaches the stream unless all of it can be
+// written, and then hands the whole of it over at once.
+
+#[allow(async_fn_in_trait)]
+pub trait AsyncWriteSegExt {
+    async fn write_segment(&mut self, segment: &Segment) -> SegmentWriteResult<()>;
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWriteSegExt for T {
+
+    async fn write_segment(&mut self, segment: &Segment) -> SegmentWriteResult<()> {
+
+        let mut buffer = vec![];
+
+        WriteSegmentExt::write_segment(&mut buffer, segment)?;
+        self.write_all(&buffer).await?;
+
+        Ok(())
+    }
+}
+
+#[allow(async_fn_in_trait)]
+pub trait AsyncWriteDisplaySetExt {
+    async fn write_display_set(&mut self, display_set: &DisplaySet) -> DisplaySetWriteResult<()>;
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWriteDisplaySetExt for T {
+
+    async fn write_display_set(&mut self, display_set: &DisplaySet) -> DisplaySetWriteResult<()> {
+
+        let mut buffer = vec![];
+
+        WriteDisplaySetExt::write_display_set(&mut buffer, display_set)?;
+        self.write_all(&buffer).await
+            .map_err(|err| DisplaySetWriteError::SegmentError { source: err.into() })?;
+
+        Ok(())
+    }
+}
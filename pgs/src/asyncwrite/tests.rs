@@ -0,0 +1,168 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use super::super::{
+    displayset::{Cid, CompositionObject, Object, Palette, PaletteEntry, Window},
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    segment::{CompositionState, EndSegment, Sequence, WriteError as SegmentWriteError},
+};
+use std::{
+    future::Future,
+    io::{Error as IoError, ErrorKind, Result as IoResult},
+    pin::{pin, Pin},
+    task::{Context, Poll, Waker},
+};
+use rand::{thread_rng, Rng};
+
+// Takes at most seven bytes at a time, and only every other time it is polled. Past its limit,
+// it fails instead.
+struct ChunkedSink {
+    data: Vec<u8>,
+    limit: usize,
+    pending: bool,
+}
+
+impl ChunkedSink {
+
+    fn new(limit: usize) -> Self {
+        Self { data: vec![], limit, pending: false }
+    }
+}
+
+impl AsyncWrite for ChunkedSink {
+
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<IoResult<usize>> {
+
+        let this = self.get_mut();
+
+        this.pending = !this.pending;
+
+        if this.pending {
+            cx.waker().wake_by_ref();
+            return Poll::Pending
+        }
+        if this.data.len() >= this.limit {
+            return Poll::Ready(Err(IoError::new(ErrorKind::WriteZero, "sink is full")))
+        }
+
+        let length = buf.len().min(7).min(this.limit - this.data.len());
+
+        this.data.extend_from_slice(&buf[..length]);
+
+        Poll::Ready(Ok(length))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output
+        }
+    }
+}
+
+fn display_set(pts: u32, state: CompositionState) -> DisplaySet {
+
+    let mut rng = thread_rng();
+    let mut display_set = DisplaySet {
+        pts,
+        dts: pts.saturating_sub(900),
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+    let mut palette = Palette::default();
+
+    // Noise without index 0 takes a byte per pixel, so this object is split into two fragments.
+    let lines = (0..90)
+        .map(|_| (0..1_000).map(|_| rng.gen_range(1..=255)).collect::<Vec<u8>>())
+        .collect::<Vec<Vec<u8>>>();
+
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+    display_set.composition.state = state;
+    display_set.windows.insert(WindowId(0), Window { x: 0, y: 900, width: 1_000, height: 90 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width: 1_000, height: 90, sequence: Sequence::Single, lines },
+    );
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 0, y: 900, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+#[test]
+fn test_async_writes_match_sync() {
+
+    let display_sets = [
+        display_set(90_000, CompositionState::EpochStart),
+        display_set(180_000, CompositionState::AcquisitionPoint),
+        DisplaySet { pts: 270_000, ..Default::default() },
+    ];
+    let end = Segment::End(EndSegment { pts: 360_000, ..Default::default() });
+    let mut sync = vec![];
+    let mut sink = ChunkedSink::new(usize::MAX);
+
+    for display_set in display_sets.iter() {
+        WriteDisplaySetExt::write_display_set(&mut sync, display_set).unwrap();
+        block_on(AsyncWriteDisplaySetExt::write_display_set(&mut sink, display_set)).unwrap();
+    }
+
+    WriteSegmentExt::write_segment(&mut sync, &end).unwrap();
+    block_on(AsyncWriteSegExt::write_segment(&mut sink, &end)).unwrap();
+
+    assert_eq!(sink.data, sync);
+}
+
+#[test]
+fn test_async_write_errors() {
+
+    let mut display_set = display_set(90_000, CompositionState::EpochStart);
+    let mut sink = ChunkedSink::new(100);
+
+    assert!(matches!(
+        block_on(sink.write_display_set(&display_set)),
+        Err(DisplaySetWriteError::SegmentError { source: SegmentWriteError::IoError { source } })
+            if source.kind() == ErrorKind::WriteZero,
+    ));
+    assert_eq!(sink.data.len(), 100);
+
+    // More windows than a WDS can count, which only turns up after the PCS is written. None of
+    // the display set reaches the stream.
+    for id in 0..=255 {
+        display_set.windows.insert(WindowId(id), Window { x: 0, y: 0, width: 1, height: 1 });
+    }
+
+    let mut sink = ChunkedSink::new(usize::MAX);
+
+    assert!(matches!(
+        block_on(sink.write_display_set(&display_set)),
+        Err(DisplaySetWriteError::SegmentError {
+            source: SegmentWriteError::TooManyWindowDefinitions,
+        }),
+    ));
+    assert!(sink.data.is_empty());
+}
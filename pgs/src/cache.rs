@@ -0,0 +1,201 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    color::{rgb_bytes, ColorMatrix, Range},
+    displayset::{DisplaySet, Object, Palette},
+    id::{ObjectId, PaletteId},
+};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    rc::Rc,
+};
+
+pub type SharedCache = Rc<RefCell<ObjectCache>>;
+
+// Identifies an object as drawn with a particular palette and matrix. Generations count
+// definitions over the whole run rather than trusting the stream's version numbers, which can
+// repeat.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct CacheKey {
+    pub object_id: ObjectId,
+    pub object_generation: u64,
+    pub palette_id: PaletteId,
+    pub palette_generation: u64,
+    pub matrix: ColorMatrix,
+}
+
+// Follows which definition of each object and palette is current. Everything that feeds the
+// same display sets in the same order through one of these arrives at the same keys, so they
+// can share a cache.
+#[derive(Clone, Debug, Default)]
+pub struct Generations {
+    next: u64,
+    objects: BTreeMap<ObjectId, u64>,
+    palettes: BTreeMap<PaletteId, u64>,
+}
+
+impl Generations {
+
+    pub fn apply(&mut self, display_set: &DisplaySet) {
+        for vid in display_set.objects.keys() {
+            self.next += 1;
+            self.objects.insert(vid.id, self.next);
+        }
+        for vid in display_set.palettes.keys() {
+            self.next += 1;
+            self.palettes.insert(vid.id, self.next);
+        }
+    }
+
+    pub fn key(
+        &self,
+        object_id: ObjectId,
+        palette_id: PaletteId,
+        matrix: ColorMatrix,
+    ) -> Option<CacheKey> {
+        Some(
+            CacheKey {
+                object_id,
+                object_generation: *self.objects.get(&object_id)?,
+                palette_id,
+                palette_generation: *self.palettes.get(&palette_id)?,
+                matrix,
+            }
+        )
+    }
+}
+
+// Holds objects converted to RGBA, dropping the least recently used ones once their pixels
+// exceed the budget. A budget of zero turns caching off. Without a matrix, each display set's is
+// guessed from its width. The range is the same for the whole run.
+#[derive(Debug, Default)]
+pub struct ObjectCache {
+    budget: usize,
+    used: usize,
+    tick: u64,
+    entries: BTreeMap<CacheKey, (u64, Rc<Vec<u8>>)>,
+    recency: BTreeMap<u64, CacheKey>,
+    hits: u64,
+    misses: u64,
+    matrix: Option<ColorMatrix>,
+    range: Range,
+}
+
+impl ObjectCache {
+
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    pub fn shared(budget: usize) -> SharedCache {
+        Rc::new(RefCell::new(Self::new(budget)))
+    }
+
+    pub fn set_matrix(&mut self, matrix: Option<ColorMatrix>) {
+        self.matrix = matrix;
+    }
+
+    pub fn matrix(&self, width: u16) -> ColorMatrix {
+        self.matrix.unwrap_or_else(|| ColorMatrix::for_width(width))
+    }
+
+    pub fn set_range(&mut self, range: Range) {
+        self.range = range;
+    }
+
+    pub fn range(&self) -> Range {
+        self.range
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub fn get_or_insert_with(
+        &mut self,
+        key: CacheKey,
+        convert: impl FnOnce() -> Vec<u8>,
+    ) -> Rc<Vec<u8>> {
+
+        self.tick += 1;
+
+        if let Some((last_used, rgba)) = self.entries.get_mut(&key) {
+
+            self.recency.remove(last_used);
+            self.recency.insert(self.tick, key);
+            *last_used = self.tick;
+            self.hits += 1;
+
+            return rgba.clone()
+        }
+
+        let rgba = Rc::new(convert());
+
+        self.misses += 1;
+
+        if rgba.len() > self.budget {
+            return rgba
+        }
+
+        while self.used + rgba.len() > self.budget {
+            match self.recency.pop_first() {
+                Some((_, oldest)) => {
+                    if let Some((_, evicted)) = self.entries.remove(&oldest) {
+                        self.used -= evicted.len();
+                    }
+                }
+                None => break,
+            }
+        }
+
+        self.used += rgba.len();
+        self.recency.insert(self.tick, key);
+        self.entries.insert(key, (self.tick, rgba.clone()));
+
+        rgba
+    }
+}
+
+// The whole object with each pixel's palette entry looked up. Indices the palette does not
+// define come out fully transparent.
+pub fn object_rgba(
+    object: &Object,
+    palette: &Palette,
+    matrix: ColorMatrix,
+    range: Range,
+) -> Vec<u8> {
+
+    let mut rgba = vec![0u8; object.width as usize * object.height as usize * 4];
+    let lookup = palette.entries.iter()
+        .map(|(&index, entry)| {
+            let rgb = rgb_bytes(entry, matrix, range);
+            (index, [rgb[0], rgb[1], rgb[2], entry.alpha])
+        })
+        .collect::<BTreeMap<u8, [u8; 4]>>();
+
+    for (y, line) in object.lines.iter().enumerate().take(object.height as usize) {
+        for (x, index) in line.iter().enumerate().take(object.width as usize) {
+            if let Some(pixel) = lookup.get(index) {
+                let offset = (y * object.width as usize + x) * 4;
+                rgba[offset..offset + 4].copy_from_slice(pixel);
+            }
+        }
+    }
+
+    rgba
+}
@@ -0,0 +1,100 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::{
+    *,
+    super::{
+        displayset::PaletteEntry,
+        id::VersionedId,
+    },
+};
+
+fn key(object_id: u16) -> CacheKey {
+    CacheKey {
+        object_id: ObjectId(object_id),
+        object_generation: 1,
+        palette_id: PaletteId(0),
+        palette_generation: 2,
+        matrix: ColorMatrix::Bt709,
+    }
+}
+
+#[test]
+fn test_evicts_least_recently_used() {
+
+    let mut cache = ObjectCache::new(8);
+
+    cache.get_or_insert_with(key(0), || vec![0; 4]);
+    cache.get_or_insert_with(key(1), || vec![1; 4]);
+    cache.get_or_insert_with(key(0), || unreachable!());
+    cache.get_or_insert_with(key(2), || vec![2; 4]);
+
+    assert_eq!(cache.entries.keys().copied().collect::<Vec<CacheKey>>(), vec![key(0), key(2)]);
+    assert_eq!(cache.used, 8);
+    assert_eq!((cache.hits(), cache.misses()), (1, 3));
+}
+
+#[test]
+fn test_zero_budget_disables_caching() {
+
+    let mut cache = ObjectCache::new(0);
+
+    assert_eq!(*cache.get_or_insert_with(key(0), || vec![7; 4]), vec![7; 4]);
+    assert_eq!(*cache.get_or_insert_with(key(0), || vec![8; 4]), vec![8; 4]);
+    assert!(cache.entries.is_empty());
+    assert_eq!((cache.hits(), cache.misses()), (0, 2));
+}
+
+#[test]
+fn test_generations() {
+
+    let mut display_set = DisplaySet::default();
+    let mut generations = Generations::default();
+
+    display_set.objects.insert(VersionedId { id: ObjectId(3), version: 0 }, Object::default());
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, Palette::default());
+    generations.apply(&display_set);
+
+    let first = generations.key(ObjectId(3), PaletteId(0), ColorMatrix::Bt709).unwrap();
+
+    // Reusing a version number still counts as a new definition.
+    generations.apply(&display_set);
+
+    assert_ne!(generations.key(ObjectId(3), PaletteId(0), ColorMatrix::Bt709).unwrap(), first);
+    assert_ne!(
+        generations.key(ObjectId(3), PaletteId(0), ColorMatrix::Bt601).unwrap().matrix,
+        first.matrix,
+    );
+    assert_eq!(generations.key(ObjectId(4), PaletteId(0), ColorMatrix::Bt709), None);
+}
+
+#[test]
+fn test_object_rgba() {
+
+    let mut palette = Palette::default();
+
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+
+    let object = Object { width: 2, height: 1, lines: vec![vec![1, 9]], ..Default::default() };
+
+    assert_eq!(
+        object_rgba(&object, &palette, ColorMatrix::Bt709, Range::Limited),
+        vec![255, 255, 255, 255, 0, 0, 0, 0],
+    );
+}
+
+#[test]
+fn test_matrix_follows_width_unless_set() {
+
+    let mut cache = ObjectCache::new(0);
+
+    assert_eq!(cache.matrix(720), ColorMatrix::Bt601);
+    assert_eq!(cache.matrix(1920), ColorMatrix::Bt709);
+
+    cache.set_matrix(Some(ColorMatrix::Bt2020));
+
+    assert_eq!(cache.matrix(720), ColorMatrix::Bt2020);
+}
@@ -0,0 +1,547 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    ts_to_timestamp,
+    displayset::{missing_indices, DisplaySet, ReadWarning, Window},
+    id::WindowId,
+    segment::CompositionState,
+    timeline::{coverage, EpochState},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.pad(
+            match self {
+                Severity::Info => "info",
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            }
+        )
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub pts: u32,
+    pub message: String,
+}
+
+pub struct Context<'a> {
+    pub display_set: &'a DisplaySet,
+    pub previous: Option<&'a DisplaySet>,
+    pub state: &'a EpochState,
+}
+
+pub trait Rule {
+    fn id(&self) -> &'static str;
+    fn name(&self) -> &'static str;
+    fn severity(&self) -> Severity;
+    fn check(&self, context: &Context, messages: &mut Vec<String>);
+}
+
+pub struct Checker {
+    rules: Vec<Box<dyn Rule>>,
+    dispositions: BTreeMap<&'static str, Option<Severity>>,
+    state: EpochState,
+    previous: Option<DisplaySet>,
+}
+
+impl Default for Checker {
+
+    fn default() -> Self {
+
+        let mut checker = Self::empty();
+
+        checker.register(Box::new(WindowExceedsCanvas));
+        checker.register(Box::new(ObjectExceedsCanvas));
+        checker.register(Box::new(WindowOverlap));
+        checker.register(Box::new(MissingEpochStart));
+        checker.register(Box::new(CompositionNumberDiscontinuity));
+        checker.register(Box::new(MidEpochResize));
+        checker.register(Box::new(UndefinedObject));
+        checker.register(Box::new(NonIncreasingPts));
+        checker.register(Box::new(UndefinedWindow));
+        checker.register(Box::new(UnclearedBeforeEpochStart));
+        checker.register(Box::new(ExcessiveCoverage::default()));
+        checker.register(Box::new(StrayEnd));
+        checker.register(Box::new(SupersededEmptyPcs));
+        checker.register(Box::new(MissingPaletteIndex));
+
+        checker
+    }
+}
+
+impl Checker {
+
+    pub fn empty() -> Self {
+        Self {
+            rules: Vec::new(),
+            dispositions: BTreeMap::new(),
+            state: EpochState::default(),
+            previous: None,
+        }
+    }
+
+    pub fn register(&mut self, rule: Box<dyn Rule>) {
+        self.rules.push(rule);
+    }
+
+    // Swaps out the registered rule with the same ID, such as to configure a built-in one.
+    pub fn replace(&mut self, rule: Box<dyn Rule>) -> bool {
+        match self.rules.iter_mut().find(|existing| existing.id() == rule.id()) {
+            Some(existing) => {
+                *existing = rule;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &dyn Rule> {
+        self.rules.iter().map(|rule| rule.as_ref())
+    }
+
+    pub fn rule(&self, id: &str) -> Option<&dyn Rule> {
+        self.rules().find(|rule| rule.id() == id)
+    }
+
+    pub fn suppress(&mut self, id: &str) -> bool {
+        self.set_disposition(id, None)
+    }
+
+    pub fn set_severity(&mut self, id: &str, severity: Severity) -> bool {
+        self.set_disposition(id, Some(severity))
+    }
+
+    fn set_disposition(&mut self, id: &str, disposition: Option<Severity>) -> bool {
+        match self.rules.iter().find(|rule| rule.id() == id) {
+            Some(rule) => {
+                self.dispositions.insert(rule.id(), disposition);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn check(&mut self, display_set: &DisplaySet) -> Vec<Finding> {
+
+        let mut findings = Vec::new();
+
+        self.state.apply(display_set);
+
+        let context = Context {
+            display_set,
+            previous: self.previous.as_ref(),
+            state: &self.state,
+        };
+
+        for rule in self.rules.iter() {
+
+            let severity = match self.dispositions.get(rule.id()) {
+                Some(Some(severity)) => *severity,
+                Some(None) => continue,
+                None => rule.severity(),
+            };
+            let mut messages = Vec::new();
+
+            rule.check(&context, &mut messages);
+
+            for message in messages {
+                findings.push(
+                    Finding {
+                        rule: rule.id(),
+                        severity,
+                        pts: display_set.pts,
+                        message,
+                    }
+                );
+            }
+        }
+
+        self.previous = Some(display_set.clone());
+
+        findings
+    }
+}
+
+pub fn windows_overlap(window_1: &Window, window_2: &Window) -> bool {
+    (window_1.x as u32) < window_2.x as u32 + window_2.width as u32
+        && (window_2.x as u32) < window_1.x as u32 + window_1.width as u32
+        && (window_1.y as u32) < window_2.y as u32 + window_2.height as u32
+        && (window_2.y as u32) < window_1.y as u32 + window_1.height as u32
+}
+
+struct WindowExceedsCanvas;
+
+impl Rule for WindowExceedsCanvas {
+
+    fn id(&self) -> &'static str { "PGS001" }
+
+    fn name(&self) -> &'static str { "window-exceeds-canvas" }
+
+    fn severity(&self) -> Severity { Severity::Error }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+
+        let display_set = context.display_set;
+
+        for (id, window) in display_set.windows.iter() {
+            if window.x as u32 + window.width as u32 > display_set.width as u32
+                || window.y as u32 + window.height as u32 > display_set.height as u32 {
+                messages.push(format!(
+                    "window {} at {},{} sized {}x{} exceeds the {}x{} canvas",
+                    id, window.x, window.y, window.width, window.height,
+                    display_set.width, display_set.height,
+                ));
+            }
+        }
+    }
+}
+
+struct ObjectExceedsCanvas;
+
+impl Rule for ObjectExceedsCanvas {
+
+    fn id(&self) -> &'static str { "PGS002" }
+
+    fn name(&self) -> &'static str { "object-exceeds-canvas" }
+
+    fn severity(&self) -> Severity { Severity::Error }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+
+        let display_set = context.display_set;
+
+        for (cid, composition_object) in display_set.composition.objects.iter() {
+            if let Some(object) = context.state.objects.get(&cid.object_id) {
+
+                let (width, height) = match &composition_object.crop {
+                    Some(crop) => (crop.width, crop.height),
+                    None => (object.width, object.height),
+                };
+
+                if composition_object.x as u32 + width as u32 > display_set.width as u32
+                    || composition_object.y as u32 + height as u32 > display_set.height as u32 {
+                    messages.push(format!(
+                        "object {} at {},{} sized {}x{} exceeds the {}x{} canvas",
+                        cid.object_id, composition_object.x, composition_object.y, width, height,
+                        display_set.width, display_set.height,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+struct WindowOverlap;
+
+impl Rule for WindowOverlap {
+
+    fn id(&self) -> &'static str { "PGS003" }
+
+    fn name(&self) -> &'static str { "window-overlap" }
+
+    fn severity(&self) -> Severity { Severity::Error }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+
+        let windows = context.display_set.windows.iter().collect::<Vec<(&WindowId, &Window)>>();
+
+        for (index, (id_1, window_1)) in windows.iter().enumerate() {
+            for (id_2, window_2) in windows[index + 1..].iter() {
+                if windows_overlap(window_1, window_2) {
+                    messages.push(format!("windows {} and {} overlap", id_1, id_2));
+                }
+            }
+        }
+    }
+}
+
+struct MissingEpochStart;
+
+impl Rule for MissingEpochStart {
+
+    fn id(&self) -> &'static str { "PGS004" }
+
+    fn name(&self) -> &'static str { "missing-epoch-start" }
+
+    fn severity(&self) -> Severity { Severity::Error }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+        if context.previous.is_none()
+            && context.display_set.composition.state != CompositionState::EpochStart {
+            messages.push("stream does not begin with an epoch start".to_string());
+        }
+    }
+}
+
+struct CompositionNumberDiscontinuity;
+
+impl Rule for CompositionNumberDiscontinuity {
+
+    fn id(&self) -> &'static str { "PGS005" }
+
+    fn name(&self) -> &'static str { "composition-number-discontinuity" }
+
+    fn severity(&self) -> Severity { Severity::Warning }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+        if let Some(previous) = context.previous {
+
+            let expected = previous.composition.number.wrapping_add(1);
+
+            if context.display_set.composition.number != expected {
+                messages.push(format!(
+                    "composition number {} follows {}",
+                    context.display_set.composition.number, previous.composition.number,
+                ));
+            }
+        }
+    }
+}
+
+struct MidEpochResize;
+
+impl Rule for MidEpochResize {
+
+    fn id(&self) -> &'static str { "PGS006" }
+
+    fn name(&self) -> &'static str { "mid-epoch-resize" }
+
+    fn severity(&self) -> Severity { Severity::Warning }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+
+        let display_set = context.display_set;
+
+        if let Some(previous) = context.previous {
+            if display_set.composition.state != CompositionState::EpochStart
+                && (display_set.width != previous.width
+                    || display_set.height != previous.height) {
+                messages.push(format!(
+                    "resolution changed from {}x{} to {}x{} without an epoch start",
+                    previous.width, previous.height, display_set.width, display_set.height,
+                ));
+            }
+        }
+    }
+}
+
+struct UndefinedObject;
+
+impl Rule for UndefinedObject {
+
+    fn id(&self) -> &'static str { "PGS007" }
+
+    fn name(&self) -> &'static str { "undefined-object" }
+
+    fn severity(&self) -> Severity { Severity::Error }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+        for cid in context.display_set.composition.objects.keys() {
+            if !context.state.objects.contains_key(&cid.object_id) {
+                messages.push(format!(
+                    "composition references object {} which is not defined in this epoch",
+                    cid.object_id,
+                ));
+            }
+        }
+    }
+}
+
+struct NonIncreasingPts;
+
+impl Rule for NonIncreasingPts {
+
+    fn id(&self) -> &'static str { "PGS008" }
+
+    fn name(&self) -> &'static str { "non-increasing-pts" }
+
+    fn severity(&self) -> Severity { Severity::Error }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+        if let Some(previous) = context.previous {
+            if context.display_set.pts <= previous.pts {
+                messages.push(format!(
+                    "PTS {} does not follow previous PTS {}",
+                    context.display_set.pts, previous.pts,
+                ));
+            }
+        }
+    }
+}
+
+struct UndefinedWindow;
+
+impl Rule for UndefinedWindow {
+
+    fn id(&self) -> &'static str { "PGS009" }
+
+    fn name(&self) -> &'static str { "undefined-window" }
+
+    fn severity(&self) -> Severity { Severity::Error }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+        for cid in context.display_set.composition.objects.keys() {
+            if !context.state.windows.contains_key(&cid.window_id) {
+                messages.push(format!(
+                    "composition references window {} which is not defined in this epoch",
+                    cid.window_id,
+                ));
+            }
+        }
+    }
+}
+
+struct UnclearedBeforeEpochStart;
+
+impl Rule for UnclearedBeforeEpochStart {
+
+    fn id(&self) -> &'static str { "PGS010" }
+
+    fn name(&self) -> &'static str { "uncleared-before-epoch-start" }
+
+    fn severity(&self) -> Severity { Severity::Warning }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+        if let Some(previous) = context.previous {
+            if context.display_set.composition.state == CompositionState::EpochStart
+                && !previous.composition.objects.is_empty() {
+                messages.push(format!(
+                    "epoch starts while the composition from {} is still on screen",
+                    ts_to_timestamp(previous.pts),
+                ));
+            }
+        }
+    }
+}
+
+pub struct ExcessiveCoverage {
+    pub max_percent: f64,
+}
+
+impl Default for ExcessiveCoverage {
+
+    fn default() -> Self {
+        Self { max_percent: 40.0 }
+    }
+}
+
+impl Rule for ExcessiveCoverage {
+
+    fn id(&self) -> &'static str { "PGS011" }
+
+    fn name(&self) -> &'static str { "excessive-coverage" }
+
+    fn severity(&self) -> Severity { Severity::Warning }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+
+        let coverage = coverage(context.state, context.display_set);
+        let pixels = coverage.pixels * 100.0;
+        let windows = coverage.windows * 100.0;
+
+        if pixels > self.max_percent || windows > self.max_percent {
+            messages.push(format!(
+                "subtitles cover {:.1}% of the canvas and their windows {:.1}%, above {}%",
+                pixels, windows, self.max_percent,
+            ));
+        }
+    }
+}
+
+struct StrayEnd;
+
+impl Rule for StrayEnd {
+
+    fn id(&self) -> &'static str { "PGS012" }
+
+    fn name(&self) -> &'static str { "stray-end" }
+
+    fn severity(&self) -> Severity { Severity::Info }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+        for warning in context.display_set.warnings.iter() {
+            if *warning == ReadWarning::StrayEnd {
+                messages.push("END segment without a display set precedes this one".to_string());
+            }
+        }
+    }
+}
+
+struct SupersededEmptyPcs;
+
+impl Rule for SupersededEmptyPcs {
+
+    fn id(&self) -> &'static str { "PGS013" }
+
+    fn name(&self) -> &'static str { "superseded-empty-pcs" }
+
+    fn severity(&self) -> Severity { Severity::Info }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+        for warning in context.display_set.warnings.iter() {
+            if *warning == ReadWarning::SupersededEmptyPcs {
+                messages.push("empty PCS was immediately replaced by this one".to_string());
+            }
+        }
+    }
+}
+
+struct MissingPaletteIndex;
+
+impl Rule for MissingPaletteIndex {
+
+    fn id(&self) -> &'static str { "PGS014" }
+
+    fn name(&self) -> &'static str { "missing-palette-index" }
+
+    fn severity(&self) -> Severity { Severity::Error }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+
+        let palette = match context.state.palette(context.display_set) {
+            Some(palette) => palette,
+            None => return,
+        };
+
+        for cid in context.display_set.composition.objects.keys() {
+            if let Some(object) = context.state.objects.get(&cid.object_id) {
+
+                let missing = missing_indices(object, palette);
+
+                if !missing.is_empty() {
+                    messages.push(format!(
+                        "object {} draws with palette indices {} that the palette does not define",
+                        cid.object_id,
+                        missing.iter()
+                            .map(|index| index.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", "),
+                    ));
+                }
+            }
+        }
+    }
+}
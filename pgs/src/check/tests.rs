@@ -0,0 +1,278 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::{
+    *,
+    super::displayset::{Cid, CompositionObject, Object, Palette, PaletteEntry},
+    super::displayset::ReadDisplaySetExt,
+    super::id::{ObjectId, PaletteId, VersionedId, WindowId},
+    super::segment::ReadOptions,
+};
+use std::io::Cursor;
+
+fn display_set(pts: u32, number: u16, state: CompositionState) -> DisplaySet {
+
+    let mut display_set = DisplaySet {
+        pts,
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+
+    display_set.composition.number = number;
+    display_set.composition.state = state;
+    display_set.windows.insert(WindowId(0), Window { x: 100, y: 900, width: 400, height: 100 });
+
+    display_set
+}
+
+fn rules(findings: &[Finding]) -> Vec<&'static str> {
+    findings.iter().map(|finding| finding.rule).collect()
+}
+
+#[test]
+fn test_clean_stream() {
+
+    let mut checker = Checker::default();
+
+    assert!(checker.check(&display_set(900, 0, CompositionState::EpochStart)).is_empty());
+    assert!(checker.check(&display_set(1800, 1, CompositionState::Normal)).is_empty());
+}
+
+#[test]
+fn test_window_rules() {
+
+    let mut checker = Checker::default();
+    let mut first = display_set(900, 0, CompositionState::EpochStart);
+
+    first.windows.insert(WindowId(1), Window { x: 1800, y: 950, width: 200, height: 100 });
+    first.windows.insert(WindowId(2), Window { x: 450, y: 850, width: 100, height: 100 });
+
+    let findings = checker.check(&first);
+
+    assert_eq!(rules(&findings), vec!["PGS001", "PGS003"]);
+    assert_eq!(findings[1].message, "windows 0 and 2 overlap");
+}
+
+#[test]
+fn test_overlap_requires_intersection() {
+
+    let window = Window { x: 100, y: 100, width: 100, height: 100 };
+
+    assert!(!windows_overlap(&window, &Window { x: 200, y: 100, width: 50, height: 50 }));
+    assert!(!windows_overlap(&window, &Window { x: 200, y: 200, width: 50, height: 50 }));
+    assert!(windows_overlap(&window, &Window { x: 150, y: 150, width: 10, height: 10 }));
+    assert!(windows_overlap(&window, &Window { x: 50, y: 140, width: 200, height: 20 }));
+}
+
+#[test]
+fn test_overlap_edge_cases() {
+
+    let window = Window { x: 100, y: 100, width: 100, height: 100 };
+    let at = |x, y, width, height| Window { x, y, width, height };
+
+    // Sharing an edge or only a corner is not overlapping, whichever side it is on.
+    for other in [
+        at(200, 120, 50, 50), at(50, 120, 50, 50), at(120, 200, 50, 50), at(120, 50, 50, 50),
+        at(200, 200, 50, 50), at(50, 50, 50, 50), at(200, 50, 50, 50), at(50, 200, 50, 50),
+    ].iter() {
+        assert!(!windows_overlap(&window, other), "{:?}", other);
+        assert!(!windows_overlap(other, &window), "{:?}", other);
+    }
+
+    // Containment, identity, a single shared pixel, and a cross where neither has a corner
+    // inside the other.
+    for other in [
+        at(150, 150, 10, 10), at(50, 50, 200, 200), window.clone(), at(199, 199, 50, 50),
+        at(140, 50, 20, 200),
+    ].iter() {
+        assert!(windows_overlap(&window, other), "{:?}", other);
+        assert!(windows_overlap(other, &window), "{:?}", other);
+    }
+}
+
+#[test]
+fn test_stream_rules() {
+
+    let mut checker = Checker::default();
+    let mut resized = display_set(900, 5, CompositionState::Normal);
+
+    resized.width = 1280;
+    resized.height = 720;
+    resized.windows.clear();
+    resized.composition.objects.insert(
+        Cid { object_id: ObjectId(3), window_id: WindowId(0) },
+        CompositionObject::default(),
+    );
+
+    assert_eq!(
+        rules(&checker.check(&display_set(900, 0, CompositionState::Normal))),
+        vec!["PGS004"],
+    );
+    assert_eq!(
+        rules(&checker.check(&resized)),
+        vec!["PGS005", "PGS006", "PGS007", "PGS008"],
+    );
+}
+
+#[test]
+fn test_dispositions() {
+
+    let mut checker = Checker::default();
+
+    assert!(checker.suppress("PGS004"));
+    assert!(checker.set_severity("PGS005", Severity::Error));
+    assert!(!checker.suppress("PGS999"));
+    assert!(checker.check(&display_set(900, 0, CompositionState::Normal)).is_empty());
+
+    let findings = checker.check(&display_set(1800, 7, CompositionState::Normal));
+
+    assert_eq!(rules(&findings), vec!["PGS005"]);
+    assert_eq!(findings[0].severity, Severity::Error);
+}
+
+struct WideCanvas;
+
+impl Rule for WideCanvas {
+
+    fn id(&self) -> &'static str { "CUSTOM1" }
+
+    fn name(&self) -> &'static str { "wide-canvas" }
+
+    fn severity(&self) -> Severity { Severity::Info }
+
+    fn check(&self, context: &Context, messages: &mut Vec<String>) {
+        if context.display_set.width > 1280 {
+            messages.push("canvas is wider than 1280".to_string());
+        }
+    }
+}
+
+#[test]
+fn test_custom_rule() {
+
+    let mut checker = Checker::empty();
+
+    checker.register(Box::new(WideCanvas));
+
+    assert_eq!(checker.rule("CUSTOM1").unwrap().name(), "wide-canvas");
+    assert_eq!(
+        checker.check(&display_set(900, 0, CompositionState::EpochStart)),
+        vec![
+            Finding {
+                rule: "CUSTOM1",
+                severity: Severity::Info,
+                pts: 900,
+                message: "canvas is wider than 1280".to_string(),
+            },
+        ],
+    );
+}
+
+#[test]
+fn test_uncleared_before_epoch_start() {
+
+    let mut checker = Checker::default();
+    let mut shown = display_set(900, 0, CompositionState::EpochStart);
+
+    shown.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        crate::displayset::Object { width: 10, height: 10, ..Default::default() },
+    );
+    shown.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 100, y: 900, crop: None, forced: false },
+    );
+
+    assert!(checker.check(&shown).is_empty());
+    assert_eq!(
+        rules(&checker.check(&display_set(1800, 1, CompositionState::EpochStart))),
+        vec!["PGS010"],
+    );
+}
+
+#[test]
+fn test_excessive_coverage() {
+
+    let mut checker = Checker::default();
+    let mut first = display_set(900, 0, CompositionState::EpochStart);
+
+    first.windows.insert(WindowId(0), Window { x: 0, y: 0, width: 1920, height: 540 });
+    first.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 0, y: 0, crop: None, forced: false },
+    );
+
+    let findings = checker.check(&first);
+
+    assert_eq!(rules(&findings), vec!["PGS007", "PGS011"]);
+    assert_eq!(
+        findings[1].message,
+        "subtitles cover 0.0% of the canvas and their windows 50.0%, above 40%",
+    );
+
+    let mut checker = Checker::default();
+
+    assert!(checker.replace(Box::new(ExcessiveCoverage { max_percent: 60.0 })));
+    assert_eq!(rules(&checker.check(&first)), vec!["PGS007"]);
+}
+
+#[test]
+fn test_structural_noise() {
+
+    let mut checker = Checker::default();
+    let mut first = display_set(900, 0, CompositionState::EpochStart);
+
+    first.warnings = vec![
+        crate::displayset::ReadWarning::StrayEnd,
+        crate::displayset::ReadWarning::SupersededEmptyPcs,
+    ].into();
+
+    let findings = checker.check(&first);
+
+    assert_eq!(rules(&findings), vec!["PGS012", "PGS013"]);
+    assert!(findings.iter().all(|finding| finding.severity == Severity::Info));
+}
+
+#[test]
+fn test_missing_palette_index() {
+
+    let mut checker = Checker::default();
+    let mut first = display_set(900, 0, CompositionState::EpochStart);
+    let mut palette = Palette::default();
+
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+    first.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    first.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width: 4, height: 1, lines: vec![vec![1, 7, 3, 7]], ..Default::default() },
+    );
+    first.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 100, y: 900, crop: None, forced: false },
+    );
+
+    let findings = checker.check(&first);
+
+    assert_eq!(rules(&findings), vec!["PGS014"]);
+    assert_eq!(
+        findings[0].message,
+        "object 0 draws with palette indices 3, 7 that the palette does not define",
+    );
+}
+
+#[test]
+fn test_noise_fixture() {
+
+    let input = include_bytes!("../../../test-data/noise.sup");
+    let options = ReadOptions { lenient: true, ..Default::default() };
+    let mut checker = Checker::default();
+    let findings = Cursor::new(&input[..]).display_sets(&options)
+        .flat_map(|display_set| checker.check(&display_set.unwrap()))
+        .collect::<Vec<Finding>>();
+
+    assert_eq!(rules(&findings), vec!["PGS012", "PGS013"]);
+}
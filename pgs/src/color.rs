@@ -0,0 +1,553 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::displayset::PaletteEntry;
+
+// Code values, as palette entries store them.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct YcbcrPixel {
+    pub y: u8,
+    pub cb: u8,
+    pub cr: u8,
+}
+
+// Gamma-encoded RGB, with black at zero and white at one in each channel. Everything that does
+// not care about light, such as HSV and tinting, works here.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RgbPixel {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+}
+
+// RGB in linear light relative to white, as BT.1886 decodes it. Scaling brightness and mixing
+// primaries belong here.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinearRgbPixel {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+}
+
+// Which luma coefficients a stream's YCbCr was derived with. Standard definition sources, such
+// as remuxed DVDs, use BT.601.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ColorMatrix {
+    #[default]
+    Bt709,
+    Bt601,
+    Bt2020,
+}
+
+impl ColorMatrix {
+
+    pub const NAMES: &'static [&'static str] = &["bt709", "bt601", "bt2020"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bt709" => Some(ColorMatrix::Bt709),
+            "bt601" => Some(ColorMatrix::Bt601),
+            "bt2020" => Some(ColorMatrix::Bt2020),
+            _ => None,
+        }
+    }
+
+    // The usual guess when a stream doesn't say: BT.601 up to standard definition widths.
+    pub fn for_width(width: u16) -> Self {
+        if width <= 720 {
+            ColorMatrix::Bt601
+        } else {
+            ColorMatrix::Bt709
+        }
+    }
+
+    fn coefficients(self) -> &'static Coefficients {
+        match self {
+            ColorMatrix::Bt709 => &BT709,
+            ColorMatrix::Bt601 => &BT601,
+            ColorMatrix::Bt2020 => &BT2020,
+        }
+    }
+}
+
+// Where black, white, and the chroma extremes sit among the code values. PGS palettes are limited
+// range, but some tools write full range ones.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Range {
+    #[default]
+    Limited,
+    Full,
+}
+
+impl Range {
+
+    pub const NAMES: &'static [&'static str] = &["limited", "full"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "limited" => Some(Range::Limited),
+            "full" => Some(Range::Full),
+            _ => None,
+        }
+    }
+
+    // The codes for black and white.
+    pub fn luma(self) -> (u8, u8) {
+        match self {
+            Range::Limited => (16, 235),
+            Range::Full => (0, 255),
+        }
+    }
+
+    // The lowest and highest chroma codes.
+    pub fn chroma(self) -> (u8, u8) {
+        match self {
+            Range::Limited => (16, 240),
+            Range::Full => (0, 255),
+        }
+    }
+
+    // How far chroma reaches from 128 compared to limited range, which spans 224 codes rather
+    // than 256.
+    fn chroma_scale(self) -> f64 {
+        match self {
+            Range::Limited => 1.0,
+            Range::Full => 256.0 / 224.0,
+        }
+    }
+}
+
+// Forward coefficients give luma and both chroma channels from RGB. The inverse ones give red
+// from Cr, green from Cb and Cr, and blue from Cb.
+struct Coefficients {
+    y: [f64; 3],
+    cb: [f64; 3],
+    cr: [f64; 3],
+    inverse: [f64; 4],
+}
+
+const BT709: Coefficients = Coefficients {
+    y: [0.2126, 0.7152, 0.0722],
+    cb: [-0.09991, -0.33609, 0.436],
+    cr: [0.615, -0.55861, -0.05639],
+    inverse: [1.28033, 0.21482, 0.38059, 2.12798],
+};
+const BT601: Coefficients = Coefficients {
+    y: [0.299, 0.587, 0.114],
+    cb: [-0.14714, -0.28886, 0.436],
+    cr: [0.615, -0.51499, -0.10001],
+    inverse: [1.13984, 0.39465, 0.58060, 2.03211],
+};
+const BT2020: Coefficients = Coefficients {
+    y: [0.2627, 0.6780, 0.0593],
+    cb: [-0.12176, -0.31424, 0.436],
+    cr: [0.615, -0.56554, -0.04946],
+    inverse: [1.19886, 0.18871, 0.46451, 2.15757],
+};
+
+pub fn rgb_pixel(input: YcbcrPixel, matrix: ColorMatrix, range: Range) -> RgbPixel {
+
+    let inverse = matrix.coefficients().inverse;
+    let y = expand(input.y as f64 / 255.0, range);
+    let cb = (input.cb as f64 - 128.0) / 128.0 / range.chroma_scale();
+    let cr = (input.cr as f64 - 128.0) / 128.0 / range.chroma_scale();
+
+    RgbPixel {
+        red:   y + inverse[0] * cr,
+        green: y - inverse[1] * cb - inverse[2] * cr,
+        blue:  y + inverse[3] * cb,
+    }
+}
+
+pub fn ycbcr_pixel(rgb: RgbPixel, matrix: ColorMatrix, range: Range) -> YcbcrPixel {
+
+    let Coefficients { y, cb, cr, .. } = matrix.coefficients();
+    let chroma_scale = range.chroma_scale();
+
+    YcbcrPixel {
+        y:
+           ((compress(
+                y[0] * rgb.red
+                + y[1] * rgb.green
+                + y[2] * rgb.blue,
+                range,
+            ) * 255.0) - 0.25).clamp(0.0, 255.0).round() as u8,
+            // The '- 0.25' is an absolutely ridiculous hack to ensure that all possible YCbCr
+            // combinations map to RGB and back to their original values.
+        cb:
+            ((
+                (cb[0] * rgb.red
+                + cb[1] * rgb.green
+                + cb[2] * rgb.blue) * chroma_scale
+                + 1.0
+            ) * 128.0).clamp(0.0, 255.0).round() as u8,
+        cr:
+            ((
+                (cr[0] * rgb.red
+                + cr[1] * rgb.green
+                + cr[2] * rgb.blue) * chroma_scale
+                + 1.0
+            ) * 128.0).clamp(0.0, 255.0).round() as u8,
+    }
+}
+
+pub fn rgb_bytes(entry: &PaletteEntry, matrix: ColorMatrix, range: Range) -> [u8; 3] {
+
+    let rgb = rgb_pixel(YcbcrPixel { y: entry.y, cb: entry.cb, cr: entry.cr }, matrix, range);
+
+    [
+        (rgb.red * 255.0).round().clamp(0.0, 255.0) as u8,
+        (rgb.green * 255.0).round().clamp(0.0, 255.0) as u8,
+        (rgb.blue * 255.0).round().clamp(0.0, 255.0) as u8,
+    ]
+}
+
+// The inverse of rgb_bytes, with alpha carried over.
+pub fn palette_entry(rgba: [u8; 4], matrix: ColorMatrix, range: Range) -> PaletteEntry {
+
+    let ycbcr = ycbcr_pixel(
+        RgbPixel {
+            red: rgba[0] as f64 / 255.0,
+            green: rgba[1] as f64 / 255.0,
+            blue: rgba[2] as f64 / 255.0,
+        },
+        matrix,
+        range,
+    );
+
+    PaletteEntry { y: ycbcr.y, cr: ycbcr.cr, cb: ycbcr.cb, alpha: rgba[3] }
+}
+
+// SDR is mastered for a display whose white is this bright.
+pub const SDR_REFERENCE_WHITE_NITS: f64 = 100.0;
+
+// BT.1886 with a black level of zero, from signal to light relative to white. Values outside of
+// zero to one are mirrored and extended rather than clipped, so that colors outside the gamut
+// survive a round trip.
+pub fn bt1886_eotf(signal: f64) -> f64 {
+    signal.signum() * signal.abs().powf(2.4)
+}
+
+// The inverse of bt1886_eotf.
+pub fn bt1886_oetf(light: f64) -> f64 {
+    light.signum() * light.abs().powf(1.0 / 2.4)
+}
+
+pub fn linear_rgb_pixel(rgb: RgbPixel) -> LinearRgbPixel {
+    LinearRgbPixel {
+        red: bt1886_eotf(rgb.red),
+        green: bt1886_eotf(rgb.green),
+        blue: bt1886_eotf(rgb.blue),
+    }
+}
+
+// The inverse of linear_rgb_pixel.
+pub fn gamma_rgb_pixel(light: LinearRgbPixel) -> RgbPixel {
+    RgbPixel {
+        red: bt1886_oetf(light.red),
+        green: bt1886_oetf(light.green),
+        blue: bt1886_oetf(light.blue),
+    }
+}
+
+// Multiplies the light of the entry's color by the factor and leaves alpha alone. Each channel is
+// only clipped where the scaling pushed it, so a factor of one changes nothing beyond rounding.
+pub fn scale_luminance(
+    entry: &PaletteEntry,
+    factor: f64,
+    matrix: ColorMatrix,
+    range: Range,
+) -> PaletteEntry {
+
+    let rgb = rgb_pixel(YcbcrPixel { y: entry.y, cb: entry.cb, cr: entry.cr }, matrix, range);
+    let light = linear_rgb_pixel(rgb);
+    let scaled = gamma_rgb_pixel(LinearRgbPixel {
+        red: light.red * factor,
+        green: light.green * factor,
+        blue: light.blue * factor,
+    });
+    let clip = |scaled: f64, signal: f64| scaled.clamp(signal.min(0.0), signal.max(1.0));
+
+    legal_entry(
+        RgbPixel {
+            red: clip(scaled.red, rgb.red),
+            green: clip(scaled.green, rgb.green),
+            blue: clip(scaled.blue, rgb.blue),
+        },
+        entry.alpha,
+        matrix,
+        range,
+    )
+}
+
+// Below this saturation, hue shifts and saturation changes fade out, so that the nearly gray
+// pixels of anti-aliased edges don't have their chroma noise turned into visible color.
+pub const RECOLOR_SATURATION_KNEE: f64 = 0.25;
+
+// Rotates the entry's hue by the given number of degrees and multiplies its saturation and value,
+// working in HSV. Alpha is left alone.
+pub fn recolor(
+    entry: &PaletteEntry,
+    hue_shift: f64,
+    saturation: f64,
+    value: f64,
+    matrix: ColorMatrix,
+    range: Range,
+) -> PaletteEntry {
+
+    let rgb = rgb_pixel(YcbcrPixel { y: entry.y, cb: entry.cb, cr: entry.cr }, matrix, range);
+    let (hue, old_saturation, old_value) = hsv(rgb);
+    let weight = (old_saturation / RECOLOR_SATURATION_KNEE).min(1.0);
+    let new_saturation = old_saturation * (1.0 + (saturation - 1.0) * weight);
+
+    legal_entry(
+        hsv_rgb(hue + hue_shift * weight, new_saturation.min(1.0), old_value * value),
+        entry.alpha,
+        matrix,
+        range,
+    )
+}
+
+// Multiplies the entry's color by the tint, so that white takes on the tint, black stays black,
+// and grays in between become shades of it. Alpha is left alone.
+pub fn tint(
+    entry: &PaletteEntry,
+    tint: [u8; 3],
+    matrix: ColorMatrix,
+    range: Range,
+) -> PaletteEntry {
+
+    let rgb = rgb_pixel(YcbcrPixel { y: entry.y, cb: entry.cb, cr: entry.cr }, matrix, range);
+
+    legal_entry(
+        RgbPixel {
+            red: rgb.red.clamp(0.0, 1.0) * tint[0] as f64 / 255.0,
+            green: rgb.green.clamp(0.0, 1.0) * tint[1] as f64 / 255.0,
+            blue: rgb.blue.clamp(0.0, 1.0) * tint[2] as f64 / 255.0,
+        },
+        entry.alpha,
+        matrix,
+        range,
+    )
+}
+
+// Hue in degrees, with saturation and value from zero to one. Channels outside of zero to one are
+// clipped first.
+fn hsv(rgb: RgbPixel) -> (f64, f64, f64) {
+
+    let red = rgb.red.clamp(0.0, 1.0);
+    let green = rgb.green.clamp(0.0, 1.0);
+    let blue = rgb.blue.clamp(0.0, 1.0);
+    let max = red.max(green).max(blue);
+    let delta = max - red.min(green).min(blue);
+
+    if delta <= 0.0 {
+        return (0.0, 0.0, max)
+    }
+
+    let hue = if max == red {
+        (green - blue) / delta
+    } else if max == green {
+        (blue - red) / delta + 2.0
+    } else {
+        (red - green) / delta + 4.0
+    };
+
+    ((hue * 60.0).rem_euclid(360.0), delta / max, max)
+}
+
+fn hsv_rgb(hue: f64, saturation: f64, value: f64) -> RgbPixel {
+
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let low = value - chroma;
+    let (red, green, blue) = match sector as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    RgbPixel { red: red + low, green: green + low, blue: blue + low }
+}
+
+// Encodes the color and keeps it within the range's legal codes.
+fn legal_entry(rgb: RgbPixel, alpha: u8, matrix: ColorMatrix, range: Range) -> PaletteEntry {
+
+    let ycbcr = ycbcr_pixel(rgb, matrix, range);
+    let (black, white) = range.luma();
+    let (low, high) = range.chroma();
+
+    PaletteEntry {
+        y: ycbcr.y.clamp(black, white),
+        cr: ycbcr.cr.clamp(low, high),
+        cb: ycbcr.cb.clamp(low, high),
+        alpha,
+    }
+}
+
+// BT.2408 puts HDR reference white, and so the white of converted SDR, at this many nits.
+pub const HDR_REFERENCE_WHITE_NITS: f64 = 203.0;
+// The display brightness HLG is graded for when nothing else is known.
+pub const HLG_NOMINAL_PEAK_NITS: f64 = 1000.0;
+
+const PQ_M1: f64 = 2610.0 / 16384.0;
+const PQ_M2: f64 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f64 = 3424.0 / 4096.0;
+const PQ_C2: f64 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f64 = 2392.0 / 4096.0 * 32.0;
+
+const HLG_A: f64 = 0.17883277;
+const HLG_B: f64 = 0.28466892;
+const HLG_C: f64 = 0.55991073;
+
+// How SDR white is carried into HDR, with the number of nits it should land at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    Pq(f64),
+    Hlg(f64),
+}
+
+impl ToneMap {
+
+    // Accepts "pq:NITS", "hlg", or "hlg:NITS". HLG defaults to HDR reference white and cannot go
+    // past its nominal peak.
+    pub fn parse(value: &str) -> Option<Self> {
+
+        let mut parts = value.splitn(2, ':');
+        let curve = parts.next()?;
+        let nits = match parts.next() {
+            Some(nits) => Some(nits.parse::<f64>().ok().filter(|nits| *nits > 0.0)?),
+            None => None,
+        };
+
+        match (curve, nits) {
+            ("pq", Some(nits)) if nits <= 10_000.0 => Some(ToneMap::Pq(nits)),
+            ("hlg", None) => Some(ToneMap::Hlg(HDR_REFERENCE_WHITE_NITS)),
+            ("hlg", Some(nits)) if nits <= HLG_NOMINAL_PEAK_NITS => Some(ToneMap::Hlg(nits)),
+            _ => None,
+        }
+    }
+}
+
+// SMPTE ST 2084, from signal to nits.
+pub fn pq_eotf(signal: f64) -> f64 {
+
+    let power = signal.clamp(0.0, 1.0).powf(1.0 / PQ_M2);
+
+    10_000.0 * ((power - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * power)).powf(1.0 / PQ_M1)
+}
+
+// The inverse of pq_eotf.
+pub fn pq_oetf(nits: f64) -> f64 {
+
+    let power = (nits / 10_000.0).clamp(0.0, 1.0).powf(PQ_M1);
+
+    ((PQ_C1 + PQ_C2 * power) / (1.0 + PQ_C3 * power)).powf(PQ_M2)
+}
+
+// BT.2100 HLG, from scene light relative to peak to signal.
+pub fn hlg_oetf(light: f64) -> f64 {
+
+    let light = light.clamp(0.0, 1.0);
+
+    if light <= 1.0 / 12.0 {
+        (3.0 * light).sqrt()
+    } else {
+        HLG_A * (12.0 * light - HLG_B).ln() + HLG_C
+    }
+}
+
+// The gamma of the HLG OOTF on a display with the given peak.
+pub fn hlg_system_gamma(peak_nits: f64) -> f64 {
+    1.2 + 0.42 * (peak_nits / 1000.0).log10()
+}
+
+// Converts an SDR entry, decoded with the given matrix and range, for display over HDR video.
+// Each channel is taken to linear light through BT.1886, moved into BT.2020 primaries, and encoded
+// with the transfer function, so the color keeps its hue and only its brightness changes. The
+// result stays in the same range. Alpha is left alone.
+pub fn tone_map(
+    entry: &PaletteEntry,
+    tone_map: ToneMap,
+    matrix: ColorMatrix,
+    range: Range,
+) -> PaletteEntry {
+
+    let rgb = rgb_pixel(YcbcrPixel { y: entry.y, cb: entry.cb, cr: entry.cr }, matrix, range);
+    let white_nits = match tone_map {
+        ToneMap::Pq(nits) | ToneMap::Hlg(nits) => nits,
+    };
+    let light = linear_rgb_pixel(RgbPixel {
+        red: rgb.red.clamp(0.0, 1.0),
+        green: rgb.green.clamp(0.0, 1.0),
+        blue: rgb.blue.clamp(0.0, 1.0),
+    });
+    let light = [light.red, light.green, light.blue].map(|light| light * white_nits);
+    let light = [
+        0.6274 * light[0] + 0.3293 * light[1] + 0.0433 * light[2],
+        0.0691 * light[0] + 0.9195 * light[1] + 0.0114 * light[2],
+        0.0164 * light[0] + 0.0880 * light[1] + 0.8956 * light[2],
+    ];
+    let light = light.map(|nits| nits.max(0.0));
+    let [red, green, blue] = match tone_map {
+        ToneMap::Pq(_) => light.map(pq_oetf),
+        ToneMap::Hlg(_) => hlg_encode(light),
+    };
+    let y = 0.2627 * red + 0.6780 * green + 0.0593 * blue;
+    let (black, white) = range.luma();
+    let (low, high) = range.chroma();
+    let chroma_width = 224.0 * range.chroma_scale();
+    let code = |value: f64, low: u8, high: u8| value.round().clamp(low as f64, high as f64) as u8;
+
+    PaletteEntry {
+        y: code(black as f64 + (white - black) as f64 * y, black, white),
+        cr: code(128.0 + chroma_width * (red - y) / 1.4746, low, high),
+        cb: code(128.0 + chroma_width * (blue - y) / 1.8814, low, high),
+        alpha: entry.alpha,
+    }
+}
+
+// Undoes the OOTF of a display at HLG's nominal peak before encoding, so that the display shows
+// the nits it was given.
+fn hlg_encode(nits: [f64; 3]) -> [f64; 3] {
+
+    let display = nits.map(|nits| nits / HLG_NOMINAL_PEAK_NITS);
+    let luminance = 0.2627 * display[0] + 0.6780 * display[1] + 0.0593 * display[2];
+
+    if luminance <= 0.0 {
+        return [0.0; 3]
+    }
+
+    let gain = luminance.powf(1.0 / hlg_system_gamma(HLG_NOMINAL_PEAK_NITS) - 1.0);
+
+    display.map(|light| hlg_oetf(light * gain))
+}
+
+fn compress(value: f64, range: Range) -> f64 {
+
+    let (black, white) = range.luma();
+
+    (value * (white - black) as f64 + black as f64) / 255.0
+}
+
+fn expand(value: f64, range: Range) -> f64 {
+
+    let (black, white) = range.luma();
+    let (black, white) = (black as f64 / 255.0, white as f64 / 255.0);
+
+    match value {
+        v if v < black => 0.0,
+        v if v > white => 1.0,
+        _ => (value - black) / (white - black),
+    }
+}
@@ -0,0 +1,340 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+const BT709: ColorMatrix = ColorMatrix::Bt709;
+const LIMITED: Range = Range::Limited;
+
+#[test]
+fn test_every_possible_yuv_combination() {
+
+    for y in 16..235 {
+        for cb in 0..=255 {
+            for cr in 0..=255 {
+
+                let yuv = YcbcrPixel { y, cb, cr };
+
+                assert_eq!(yuv, ycbcr_pixel(rgb_pixel(yuv, BT709, LIMITED), BT709, LIMITED));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_every_matrix_round_trips_within_one_code() {
+    for &matrix in [ColorMatrix::Bt709, ColorMatrix::Bt601, ColorMatrix::Bt2020].iter() {
+        for y in 16..=235 {
+            for cb in 16..=240 {
+                for cr in 16..=240 {
+
+                    let yuv = YcbcrPixel { y, cb, cr };
+                    let back = ycbcr_pixel(rgb_pixel(yuv, matrix, LIMITED), matrix, LIMITED);
+
+                    assert!((back.y as i16 - y as i16).abs() <= 1, "{:?} {:?}", matrix, yuv);
+                    assert!((back.cb as i16 - cb as i16).abs() <= 1, "{:?} {:?}", matrix, yuv);
+                    assert!((back.cr as i16 - cr as i16).abs() <= 1, "{:?} {:?}", matrix, yuv);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_full_range_round_trips_within_one_code() {
+    for y in 0..=255 {
+        for cb in (0..=255).step_by(3) {
+            for cr in (0..=255).step_by(3) {
+
+                let yuv = YcbcrPixel { y, cb, cr };
+                let back = ycbcr_pixel(rgb_pixel(yuv, BT709, Range::Full), BT709, Range::Full);
+
+                assert!((back.y as i16 - y as i16).abs() <= 1, "{:?}", yuv);
+                assert!((back.cb as i16 - cb as i16).abs() <= 1, "{:?}", yuv);
+                assert!((back.cr as i16 - cr as i16).abs() <= 1, "{:?}", yuv);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_full_range_white_is_not_crushed() {
+
+    let white = PaletteEntry { y: 255, cb: 128, cr: 128, alpha: 255 };
+    let grey = PaletteEntry { y: 235, cb: 128, cr: 128, alpha: 255 };
+
+    assert_eq!(rgb_bytes(&white, BT709, Range::Full), [255, 255, 255]);
+    assert_eq!(rgb_bytes(&grey, BT709, Range::Full), [235, 235, 235]);
+    assert_eq!(rgb_bytes(&grey, BT709, LIMITED), [255, 255, 255]);
+    assert_eq!(scale_luminance(&white, 1.0, BT709, Range::Full), white);
+    assert_eq!(palette_entry([255, 255, 255, 255], BT709, Range::Full), white);
+    assert_eq!(
+        palette_entry([0, 0, 0, 255], BT709, Range::Full),
+        PaletteEntry { y: 0, ..white },
+    );
+}
+
+#[test]
+fn test_full_range_chroma_is_wider() {
+
+    let blue = RgbPixel { red: 0.0, green: 0.0, blue: 1.0 };
+    let limited = ycbcr_pixel(blue, BT709, LIMITED);
+    let full = ycbcr_pixel(blue, BT709, Range::Full);
+
+    assert_eq!(limited.cb, 184);
+    assert_eq!(full.cb, 192);
+    assert_eq!(Range::from_name("full"), Some(Range::Full));
+    assert_eq!(Range::from_name("tv"), None);
+}
+
+#[test]
+fn test_matrices_differ() {
+
+    let red = RgbPixel { red: 1.0, green: 0.0, blue: 0.0 };
+
+    assert_eq!(ycbcr_pixel(red, ColorMatrix::Bt709, LIMITED).y, 62);
+    assert_eq!(ycbcr_pixel(red, ColorMatrix::Bt601, LIMITED).y, 81);
+    assert_eq!(ycbcr_pixel(red, ColorMatrix::Bt2020, LIMITED).y, 73);
+}
+
+#[test]
+fn test_matrix_for_width() {
+    assert_eq!(ColorMatrix::for_width(720), ColorMatrix::Bt601);
+    assert_eq!(ColorMatrix::for_width(1280), ColorMatrix::Bt709);
+    assert_eq!(ColorMatrix::from_name("bt2020"), Some(ColorMatrix::Bt2020));
+    assert_eq!(ColorMatrix::from_name("bt.709"), None);
+}
+
+#[test]
+fn test_palette_entry_round_trips() {
+    for &rgba in [[0, 0, 0, 255], [255, 255, 255, 128], [200, 100, 50, 1]].iter() {
+
+        let entry = palette_entry(rgba, BT709, LIMITED);
+        let rgb = rgb_bytes(&entry, BT709, LIMITED);
+
+        assert_eq!(entry.alpha, rgba[3]);
+        for channel in 0..3 {
+            assert!((rgb[channel] as i16 - rgba[channel] as i16).abs() <= 2, "{:?}", rgba);
+        }
+    }
+}
+
+#[test]
+fn test_bt1886_round_trips() {
+    for &signal in [-0.25, 0.0, 0.001, 0.5, 1.0, 1.25].iter() {
+        assert!((bt1886_oetf(bt1886_eotf(signal)) - signal).abs() < 1e-12);
+    }
+    assert!((bt1886_eotf(0.5) - 0.189_464).abs() < 1e-6);
+}
+
+#[test]
+fn test_linear_round_trips() {
+    for y in (16..=235).step_by(7) {
+        for cb in (0..=255).step_by(17) {
+            for cr in (0..=255).step_by(17) {
+
+                let yuv = YcbcrPixel { y, cb, cr };
+                let rgb = rgb_pixel(yuv, BT709, LIMITED);
+                let light = linear_rgb_pixel(rgb);
+
+                assert_eq!(
+                    ycbcr_pixel(gamma_rgb_pixel(light), BT709, LIMITED),
+                    yuv,
+                    "{:?}",
+                    light,
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_linear_rgb_pixel() {
+
+    let light = linear_rgb_pixel(RgbPixel { red: 1.0, green: 0.5, blue: -0.5 });
+
+    assert!((light.red - 1.0).abs() < 1e-12);
+    assert!((light.green - 0.189_464).abs() < 1e-6);
+    assert!((light.blue + 0.189_464).abs() < 1e-6);
+}
+
+#[test]
+fn test_scale_luminance_by_one_is_a_no_op() {
+    for y in (16..=235).step_by(3) {
+        for cb in (16..=240).step_by(8) {
+            for cr in (16..=240).step_by(8) {
+
+                let entry = PaletteEntry { y, cb, cr, alpha: 200 };
+                let scaled = scale_luminance(&entry, 1.0, BT709, LIMITED);
+
+                assert!((scaled.y as i16 - y as i16).abs() <= 1, "{:?}", entry);
+                assert!((scaled.cb as i16 - cb as i16).abs() <= 1, "{:?}", entry);
+                assert!((scaled.cr as i16 - cr as i16).abs() <= 1, "{:?}", entry);
+                assert_eq!(scaled.alpha, 200);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_scale_luminance_keeps_range_limits() {
+
+    let white = PaletteEntry { y: 235, cb: 128, cr: 128, alpha: 255 };
+    let black = PaletteEntry { y: 16, cb: 128, cr: 128, alpha: 255 };
+
+    for &factor in [0.25, 1.0, 2.0, 100.0].iter() {
+        assert_eq!(scale_luminance(&black, factor, BT709, LIMITED), black);
+    }
+    for &factor in [1.0, 2.0, 100.0].iter() {
+        assert_eq!(scale_luminance(&white, factor, BT709, LIMITED), white);
+    }
+    assert_eq!(scale_luminance(&white, 0.0, BT709, LIMITED), black);
+    assert_eq!(
+        scale_luminance(&PaletteEntry { y: 0, ..black.clone() }, 1.0, BT709, LIMITED),
+        black,
+    );
+}
+
+#[test]
+fn test_scale_luminance_works_in_linear_light() {
+
+    let white = PaletteEntry { y: 235, cb: 128, cr: 128, alpha: 128 };
+
+    // Half the light is about three quarters of the signal.
+    assert_eq!(scale_luminance(&white, 0.5, BT709, LIMITED), PaletteEntry { y: 180, ..white });
+}
+
+#[test]
+fn test_tone_map_parse() {
+    assert_eq!(ToneMap::parse("pq:203"), Some(ToneMap::Pq(203.0)));
+    assert_eq!(ToneMap::parse("pq:10000"), Some(ToneMap::Pq(10_000.0)));
+    assert_eq!(ToneMap::parse("hlg"), Some(ToneMap::Hlg(HDR_REFERENCE_WHITE_NITS)));
+    assert_eq!(ToneMap::parse("hlg:300"), Some(ToneMap::Hlg(300.0)));
+    assert_eq!(ToneMap::parse("pq"), None);
+    assert_eq!(ToneMap::parse("pq:0"), None);
+    assert_eq!(ToneMap::parse("pq:-5"), None);
+    assert_eq!(ToneMap::parse("pq:10001"), None);
+    assert_eq!(ToneMap::parse("hlg:1001"), None);
+    assert_eq!(ToneMap::parse("hlg:"), None);
+    assert_eq!(ToneMap::parse("sdr:100"), None);
+}
+
+#[test]
+fn test_pq_round_trip() {
+
+    assert!(pq_eotf(0.0).abs() < 1e-9);
+    assert!((pq_eotf(1.0) - 10_000.0).abs() < 1e-6);
+
+    for &nits in [0.005, 1.0, 100.0, 203.0, 1000.0, 4000.0].iter() {
+        assert!((pq_eotf(pq_oetf(nits)) - nits).abs() < nits * 1e-9);
+    }
+}
+
+#[test]
+fn test_hlg_oetf() {
+    assert_eq!(hlg_oetf(0.0), 0.0);
+    assert!((hlg_oetf(1.0 / 12.0) - 0.5).abs() < 1e-6);
+    assert!((hlg_oetf(1.0) - 1.0).abs() < 1e-6);
+    assert!((hlg_system_gamma(1000.0) - 1.2).abs() < 1e-9);
+}
+
+#[test]
+fn test_tone_map_reference_white() {
+
+    let white = PaletteEntry { y: 235, cb: 128, cr: 128, alpha: 200 };
+    let black = PaletteEntry { y: 16, cb: 128, cr: 128, alpha: 200 };
+
+    // BT.2408 puts 203 nits at 58% of PQ and HDR reference white at 75% of HLG.
+    assert_eq!(tone_map(&white, ToneMap::Pq(203.0), BT709, LIMITED).y, 143);
+    assert_eq!(tone_map(&white, ToneMap::Hlg(203.0), BT709, LIMITED).y, 180);
+    assert_eq!(
+        tone_map(&white, ToneMap::Pq(10_000.0), BT709, LIMITED),
+        PaletteEntry { y: 235, ..white },
+    );
+    assert_eq!(tone_map(&black, ToneMap::Pq(203.0), BT709, LIMITED), black);
+    assert_eq!(tone_map(&black, ToneMap::Hlg(203.0), BT709, LIMITED), black);
+}
+
+#[test]
+fn test_tone_map_keeps_hue() {
+
+    let grey = palette_entry([128, 128, 128, 255], BT709, LIMITED);
+    let red = palette_entry([255, 0, 0, 255], BT709, LIMITED);
+    let blue = palette_entry([0, 0, 255, 255], BT709, LIMITED);
+
+    for &mode in [ToneMap::Pq(203.0), ToneMap::Hlg(203.0)].iter() {
+
+        let grey = tone_map(&grey, mode, BT709, LIMITED);
+        let red = tone_map(&red, mode, BT709, LIMITED);
+        let blue = tone_map(&blue, mode, BT709, LIMITED);
+
+        assert_eq!((grey.cb, grey.cr), (128, 128));
+        assert!(red.cr > 128 && red.cb < 128 && red.cr - 128 > 128 - red.cb, "{:?}", red);
+        assert!(blue.cb > 128 && blue.cb > blue.cr, "{:?}", blue);
+    }
+}
+
+#[test]
+fn test_recolor_without_changes_is_a_no_op() {
+    for &rgba in [[255, 255, 255, 255], [200, 100, 50, 9], [0, 0, 0, 80], [30, 60, 220, 1]].iter() {
+
+        let entry = palette_entry(rgba, BT709, LIMITED);
+        let recolored = recolor(&entry, 0.0, 1.0, 1.0, BT709, LIMITED);
+
+        assert!((recolored.y as i16 - entry.y as i16).abs() <= 1, "{:?}", rgba);
+        assert!((recolored.cb as i16 - entry.cb as i16).abs() <= 1, "{:?}", rgba);
+        assert!((recolored.cr as i16 - entry.cr as i16).abs() <= 1, "{:?}", rgba);
+        assert_eq!(recolored.alpha, rgba[3]);
+    }
+}
+
+#[test]
+fn test_recolor_shifts_hue() {
+
+    let red = palette_entry([255, 0, 0, 255], BT709, LIMITED);
+
+    assert_eq!(
+        rgb_bytes(&recolor(&red, 120.0, 1.0, 1.0, BT709, LIMITED), BT709, LIMITED),
+        [0, 255, 0],
+    );
+    assert_eq!(
+        rgb_bytes(&recolor(&red, -120.0, 1.0, 0.5, BT709, LIMITED), BT709, LIMITED),
+        [0, 0, 128],
+    );
+
+    let gray = recolor(&red, 0.0, 0.0, 1.0, BT709, LIMITED);
+
+    assert_eq!((gray.cb, gray.cr), (128, 128));
+}
+
+#[test]
+fn test_recolor_leaves_near_gray_alone() {
+
+    let edge = palette_entry([128, 124, 128, 255], BT709, LIMITED);
+    let recolored = recolor(&edge, 180.0, 4.0, 1.0, BT709, LIMITED);
+
+    assert!((recolored.cb as i16 - edge.cb as i16).abs() <= 2, "{:?}", recolored);
+    assert!((recolored.cr as i16 - edge.cr as i16).abs() <= 2, "{:?}", recolored);
+}
+
+#[test]
+fn test_tint() {
+
+    let white = palette_entry([255, 255, 255, 200], BT709, LIMITED);
+    let black = palette_entry([0, 0, 0, 200], BT709, LIMITED);
+    let gray = palette_entry([128, 128, 128, 255], BT709, LIMITED);
+    let yellow = [255, 255, 0];
+    let close = |entry: &PaletteEntry, rgb: [u8; 3]| {
+        rgb_bytes(entry, BT709, LIMITED).iter()
+            .zip(rgb.iter())
+            .all(|(&actual, &expected)| (actual as i16 - expected as i16).abs() <= 2)
+    };
+
+    assert!(close(&tint(&white, yellow, BT709, LIMITED), [255, 255, 0]));
+    assert!(close(&tint(&gray, yellow, BT709, LIMITED), [128, 128, 0]));
+    assert_eq!(tint(&white, yellow, BT709, LIMITED).alpha, 200);
+    assert_eq!(tint(&black, yellow, BT709, LIMITED), black);
+}
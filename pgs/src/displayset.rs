@@ -13,44 +13,296 @@ mod displaysetwrite;
 pub use displaysetread::*;
 pub use displaysetwrite::*;
 
-use std::collections::BTreeMap;
-use super::segment::{Crop, CompositionState, Sequence};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Debug, Display, Formatter, Result as FmtResult},
+    hash::Hasher,
+};
+use super::{
+    ts_to_timestamp,
+    check::windows_overlap,
+    color::{
+        recolor,
+        scale_luminance,
+        tint,
+        tone_map,
+        ColorMatrix,
+        Range,
+        ToneMap,
+        SDR_REFERENCE_WHITE_NITS,
+    },
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    segment::{Crop, CompositionState, DebugBytes, DebugChunks, Fnv, FrameRate, Raw, Sequence},
+    timeline::EpochState,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DisplaySet {
     pub pts: u32,
     pub dts: u32,
     pub width: u16,
     pub height: u16,
-    pub frame_rate: u8,
-    pub palette_update_id: Option<u8>,
-    pub windows: BTreeMap<u8, Window>,
-    pub palettes: BTreeMap<Vid<u8>, Palette>,
-    pub objects: BTreeMap<Vid<u16>, Object>,
+    pub frame_rate: FrameRate,
+    // Set when the composition only swaps in this palette for the objects already on screen, as
+    // fades do.
+    pub palette_update_id: Option<PaletteId>,
+    pub windows: BTreeMap<WindowId, Window>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::pairs"))]
+    pub palettes: BTreeMap<VersionedId<PaletteId>, Palette>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::pairs"))]
+    pub objects: BTreeMap<VersionedId<ObjectId>, Object>,
     pub composition: Composition,
+    // Segments of kinds the crate does not model, in the order they were read. They are written
+    // back just before the END segment.
+    pub unknown_segments: Vec<UnknownSegment>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub segment_dts: SegmentDts,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub warnings: ReadWarnings,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub raw: Raw,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+impl DisplaySet {
+
+    // The segments this display set was read from, in order, provided it was read with
+    // ReadOptions::keep_raw and has not been changed since.
+    pub fn raw_segments(&self) -> Option<&[Vec<u8>]> {
+        self.raw.get(self)
+    }
+
+    // A palette update that defines nothing but palettes, which is written as just a PCS, its
+    // PDSs, and an END.
+    pub fn is_palette_update_only(&self) -> bool {
+        self.palette_update_id.is_some() && self.windows.is_empty() && self.objects.is_empty()
+    }
+
+    // The DTS of a segment that lies the given distance from the display set's own.
+    pub fn dts_at(&self, offset: i64) -> u32 {
+        (self.dts as i64 + offset).clamp(0, u32::MAX as i64) as u32
+    }
+
+    // Everything that would keep this display set from showing as intended. The state must
+    // already have this display set applied, so that definitions from earlier in the epoch are
+    // taken into account.
+    pub fn validate(&self, state: &EpochState) -> Vec<Diagnostic> {
+
+        let pts = self.pts;
+        let mut diagnostics = Vec::new();
+        let windows = self.windows.iter().collect::<Vec<(&WindowId, &Window)>>();
+
+        for (index, (&id_1, window_1)) in windows.iter().enumerate() {
+            for (&id_2, window_2) in windows[index + 1..].iter() {
+                if windows_overlap(window_1, window_2) {
+                    diagnostics.push(Diagnostic::WindowOverlap { pts, window_ids: (id_1, id_2) });
+                }
+            }
+        }
+
+        for (cid, composition_object) in self.composition.objects.iter() {
+
+            let window = state.windows.get(&cid.window_id);
+            let object = state.objects.get(&cid.object_id);
+
+            if window.is_none() {
+                diagnostics.push(
+                    Diagnostic::CompositionReferencesMissingWindow { pts, window_id: cid.window_id }
+                );
+            }
+
+            let object = match object {
+                Some(object) => object,
+                None => {
+                    diagnostics.push(
+                        Diagnostic::CompositionReferencesMissingObject {
+                            pts,
+                            object_id: cid.object_id,
+                        }
+                    );
+                    continue
+                }
+            };
+            let (width, height) = match &composition_object.crop {
+                Some(crop) => (crop.width, crop.height),
+                None => (object.width, object.height),
+            };
+
+            if composition_object.x as u32 + width as u32 > self.width as u32
+                || composition_object.y as u32 + height as u32 > self.height as u32 {
+                diagnostics.push(
+                    Diagnostic::ObjectOutOfBounds {
+                        pts,
+                        object_id: cid.object_id,
+                        x: composition_object.x,
+                        y: composition_object.y,
+                        width,
+                        height,
+                    }
+                );
+            }
+            if let Some(window) = window {
+                if width > window.width || height > window.height {
+                    diagnostics.push(
+                        Diagnostic::ObjectLargerThanWindow {
+                            pts,
+                            object_id: cid.object_id,
+                            window_id: cid.window_id,
+                            width,
+                            height,
+                            window_width: window.width,
+                            window_height: window.height,
+                        }
+                    );
+                }
+            }
+            if let Some(palette) = state.palette(self) {
+
+                let indices = missing_indices(object, palette);
+
+                if !indices.is_empty() {
+                    diagnostics.push(
+                        Diagnostic::PaletteEntryMissing {
+                            pts,
+                            object_id: cid.object_id,
+                            indices: indices.into_iter().collect(),
+                        }
+                    );
+                }
+            }
+        }
+
+        diagnostics
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Diagnostic {
+    WindowOverlap {
+        pts: u32,
+        window_ids: (WindowId, WindowId),
+    },
+    // Shown beyond the edge of the canvas, given the size after any cropping.
+    ObjectOutOfBounds {
+        pts: u32,
+        object_id: ObjectId,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+    },
+    CompositionReferencesMissingObject {
+        pts: u32,
+        object_id: ObjectId,
+    },
+    CompositionReferencesMissingWindow {
+        pts: u32,
+        window_id: WindowId,
+    },
+    PaletteEntryMissing {
+        pts: u32,
+        object_id: ObjectId,
+        indices: Vec<u8>,
+    },
+    ObjectLargerThanWindow {
+        pts: u32,
+        object_id: ObjectId,
+        window_id: WindowId,
+        width: u16,
+        height: u16,
+        window_width: u16,
+        window_height: u16,
+    },
+}
+
+impl Diagnostic {
+
+    pub fn pts(&self) -> u32 {
+        match self {
+            Diagnostic::WindowOverlap { pts, .. }
+            | Diagnostic::ObjectOutOfBounds { pts, .. }
+            | Diagnostic::CompositionReferencesMissingObject { pts, .. }
+            | Diagnostic::CompositionReferencesMissingWindow { pts, .. }
+            | Diagnostic::PaletteEntryMissing { pts, .. }
+            | Diagnostic::ObjectLargerThanWindow { pts, .. } => *pts,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Diagnostic::WindowOverlap { window_ids: (id_1, id_2), .. } => {
+                write!(f, "windows {} and {} overlap", id_1, id_2)?
+            }
+            Diagnostic::ObjectOutOfBounds { object_id, x, y, width, height, .. } => {
+                write!(
+                    f,
+                    "object {} at {},{} sized {}x{} extends past the canvas",
+                    object_id, x, y, width, height,
+                )?
+            }
+            Diagnostic::CompositionReferencesMissingObject { object_id, .. } => {
+                write!(f, "composition references undefined object {}", object_id)?
+            }
+            Diagnostic::CompositionReferencesMissingWindow { window_id, .. } => {
+                write!(f, "composition references undefined window {}", window_id)?
+            }
+            Diagnostic::PaletteEntryMissing { object_id, indices, .. } => {
+                write!(
+                    f,
+                    "object {} draws with palette indices {} that its palette lacks",
+                    object_id,
+                    indices.iter().map(|index| index.to_string()).collect::<Vec<_>>().join(", "),
+                )?
+            }
+            Diagnostic::ObjectLargerThanWindow {
+                object_id, window_id, width, height, window_width, window_height, ..
+            } => {
+                write!(
+                    f,
+                    "object {} sized {}x{} is larger than window {} sized {}x{}",
+                    object_id, width, height, window_id, window_width, window_height,
+                )?
+            }
+        }
+
+        write!(f, " at {}", ts_to_timestamp(self.pts()))
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Composition {
     pub number: u16,
     pub state: CompositionState,
+    #[cfg_attr(feature = "serde", serde(with = "crate::pairs"))]
     pub objects: BTreeMap<Cid, CompositionObject>,
 }
 
 #[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Cid {
-    pub object_id: u16,
-    pub window_id: u8,
+    pub object_id: ObjectId,
+    pub window_id: WindowId,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CompositionObject {
     pub x: u16,
     pub y: u16,
     pub crop: Option<Crop>,
+    // Shown even when subtitles are turned off.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub forced: bool,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Window {
     pub x: u16,
     pub y: u16,
@@ -58,12 +310,134 @@ pub struct Window {
     pub height: u16,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Palette {
     pub entries: BTreeMap<u8, PaletteEntry>
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+impl Palette {
+
+    // A hash of the entries alone, for telling whether palettes are the same whatever their IDs
+    // and versions, and from one run to the next. It is the 64-bit FNV-1a hash of each entry in
+    // index order, as its index, Y, Cr, Cb, and alpha bytes.
+    pub fn content_hash(&self) -> u64 {
+
+        let mut hasher = Fnv::default();
+
+        for (&id, entry) in self.entries.iter() {
+            hasher.write(&[id, entry.y, entry.cr, entry.cb, entry.alpha]);
+        }
+
+        hasher.finish()
+    }
+
+    // Replaces every entry, transparent ones included, with what the function makes of it.
+    pub fn map_entries(&mut self, mut f: impl FnMut(PaletteEntry) -> PaletteEntry) {
+        for entry in self.entries.values_mut() {
+            *entry = f(entry.clone());
+        }
+    }
+
+    // Multiplies the light of every visible entry by the factor, working in linear light. Alpha is
+    // left alone, and so are fully transparent entries, whose color is never seen.
+    pub fn scale_luminance(&mut self, factor: f64, matrix: ColorMatrix, range: Range) {
+        for entry in self.entries.values_mut().filter(|entry| entry.alpha != 0) {
+            *entry = scale_luminance(entry, factor, matrix, range);
+        }
+    }
+
+    // Scales for a display whose white is the given number of nits, taking the palette as SDR
+    // mastered for 100 nit white.
+    pub fn scale_to_peak(&mut self, peak_nits: f64, matrix: ColorMatrix, range: Range) {
+        self.scale_luminance(peak_nits / SDR_REFERENCE_WHITE_NITS, matrix, range)
+    }
+
+    // Converts every visible entry for display over HDR video.
+    pub fn tone_map(&mut self, mode: ToneMap, matrix: ColorMatrix, range: Range) {
+        for entry in self.entries.values_mut().filter(|entry| entry.alpha != 0) {
+            *entry = tone_map(entry, mode, matrix, range);
+        }
+    }
+
+    // Shifts the hue of every visible entry by the given degrees and multiplies its saturation and
+    // value. Nearly gray entries are mostly left alone.
+    pub fn recolor(
+        &mut self,
+        hue_shift: f64,
+        saturation: f64,
+        value: f64,
+        matrix: ColorMatrix,
+        range: Range,
+    ) {
+        self.map_entries(|entry| match entry.alpha {
+            0 => entry,
+            _ => recolor(&entry, hue_shift, saturation, value, matrix, range),
+        })
+    }
+
+    // Multiplies the color of every visible entry by the tint.
+    pub fn tint(&mut self, rgb: [u8; 3], matrix: ColorMatrix, range: Range) {
+        self.map_entries(|entry| match entry.alpha {
+            0 => entry,
+            _ => tint(&entry, rgb, matrix, range),
+        })
+    }
+}
+
+// A segment read with ReadOptions::keep_unknown, which takes its PTS and DTS from the display set
+// it belongs to.
+#[derive(Clone, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UnknownSegment {
+    pub kind: u8,
+    pub data: Vec<u8>,
+}
+
+impl Debug for UnknownSegment {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("UnknownSegment")
+            .field("kind", &self.kind)
+            .field("data", &DebugBytes(&self.data))
+            .finish()
+    }
+}
+
+// How far the DTS of each segment after the PCS lies from the display set's own, for streams that
+// give segments decode times of their own. Keeping differences means that whatever moves a
+// display set in time moves its segments along with it. A segment without an entry, or with an
+// entry of zero, is written with the display set's DTS.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SegmentDts {
+    pub windows: i64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::pairs"))]
+    pub palettes: BTreeMap<VersionedId<PaletteId>, i64>,
+    // One for each fragment the object was read in. Fragments past those take the last.
+    #[cfg_attr(feature = "serde", serde(with = "crate::pairs"))]
+    pub objects: BTreeMap<VersionedId<ObjectId>, Vec<i64>>,
+    // In the order of DisplaySet::unknown_segments.
+    pub unknown_segments: Vec<i64>,
+    pub end: i64,
+}
+
+impl SegmentDts {
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn object(&self, vid: &VersionedId<ObjectId>, fragment: usize) -> i64 {
+        self.objects.get(vid)
+            .and_then(|offsets| offsets.get(fragment).or(offsets.last()))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PaletteEntry {
     pub y: u8,
     pub cr: u8,
@@ -71,7 +445,8 @@ pub struct PaletteEntry {
     pub alpha: u8,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Object {
     pub width: u16,
     pub height: u16,
@@ -79,8 +454,442 @@ pub struct Object {
     pub lines: Vec<Vec<u8>>,
 }
 
-#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub struct Vid<T> {
-    pub id: T,
-    pub version: u8,
+impl Object {
+
+    // A hash of the bitmap alone, for telling whether objects are the same whatever their IDs and
+    // versions, and from one run to the next. It is the 64-bit FNV-1a hash of the width and height
+    // as big-endian u16 values, then of each line as its length, a big-endian u32, and its pixels.
+    // The sequence is left out, as is however the data happened to be encoded.
+    pub fn content_hash(&self) -> u64 {
+
+        let mut hasher = Fnv::default();
+
+        hasher.write(&self.width.to_be_bytes());
+        hasher.write(&self.height.to_be_bytes());
+
+        for line in self.lines.iter() {
+            hasher.write(&(line.len() as u32).to_be_bytes());
+            hasher.write(line);
+        }
+
+        hasher.finish()
+    }
+}
+
+impl Debug for Object {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("Object")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("sequence", &self.sequence)
+            .field("lines", &DebugChunks(&self.lines))
+            .finish()
+    }
+}
+
+pub fn prune_palettes(epoch: &mut [DisplaySet]) -> usize {
+
+    let referenced = epoch.iter()
+        .flat_map(|display_set| display_set.objects.values())
+        .flat_map(|object| object.lines.iter())
+        .flat_map(|line| line.iter().copied())
+        .collect::<BTreeSet<u8>>();
+    let mut pruned = 0;
+
+    for display_set in epoch.iter_mut() {
+        for palette in display_set.palettes.values_mut() {
+            let count = palette.entries.len();
+            palette.entries.retain(|id, _| referenced.contains(id));
+            pruned += count - palette.entries.len();
+        }
+    }
+
+    pruned
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndexFix {
+    // The lowest palette entry that is fully transparent, if the palette has one.
+    Transparent,
+    // The defined entry closest by index, preferring the lower one on a tie.
+    Nearest,
+}
+
+// The palette indices that the object draws with but the palette does not define.
+pub fn missing_indices(object: &Object, palette: &Palette) -> BTreeSet<u8> {
+    object.lines.iter()
+        .flat_map(|line| line.iter().copied())
+        .filter(|index| !palette.entries.contains_key(index))
+        .collect()
+}
+
+// Rewrites objects so that they only draw with indices their palette defines, judging each
+// object version by the palette in effect the first time it is shown. Returns how many distinct
+// indices were remapped.
+pub fn fix_missing_indices(epoch: &mut [DisplaySet], fix: IndexFix) -> usize {
+
+    let mut state = EpochState::default();
+    let mut definitions = BTreeMap::<ObjectId, (usize, VersionedId<ObjectId>)>::new();
+    let mut mappings = BTreeMap::<(usize, VersionedId<ObjectId>), BTreeMap<u8, u8>>::new();
+
+    for (position, display_set) in epoch.iter().enumerate() {
+
+        state.apply(display_set);
+
+        for vid in display_set.objects.keys() {
+            definitions.insert(vid.id, (position, *vid));
+        }
+
+        let palette = match state.palette(display_set) {
+            Some(palette) => palette,
+            None => continue,
+        };
+
+        for cid in display_set.composition.objects.keys() {
+
+            let (definition, object) = match (
+                definitions.get(&cid.object_id),
+                state.objects.get(&cid.object_id),
+            ) {
+                (Some(definition), Some(object)) => (definition, object),
+                _ => continue,
+            };
+
+            if mappings.contains_key(definition) {
+                continue
+            }
+
+            let mapping = missing_indices(object, palette).into_iter()
+                .filter_map(|index| Some((index, replacement_index(palette, index, fix)?)))
+                .collect::<BTreeMap<u8, u8>>();
+
+            mappings.insert(*definition, mapping);
+        }
+    }
+
+    let mut fixed = 0;
+
+    for ((position, vid), mapping) in mappings.iter() {
+        if let Some(object) = epoch[*position].objects.get_mut(vid) {
+            for line in object.lines.iter_mut() {
+                for index in line.iter_mut() {
+                    if let Some(replacement) = mapping.get(index) {
+                        *index = *replacement;
+                    }
+                }
+            }
+            fixed += mapping.len();
+        }
+    }
+
+    fixed
+}
+
+fn replacement_index(palette: &Palette, index: u8, fix: IndexFix) -> Option<u8> {
+    match fix {
+        IndexFix::Transparent => {
+            palette.entries.iter()
+                .find(|(_, entry)| entry.alpha == 0)
+                .map(|(&id, _)| id)
+        }
+        IndexFix::Nearest => {
+            palette.entries.keys()
+                .copied()
+                .min_by_key(|&id| ((id as i16 - index as i16).abs(), id))
+        }
+    }
+}
+
+// Removes each display set that repeats the one before it apart from its timestamps, other than
+// epoch starts, and pulls later composition numbers back so that they keep counting up by one
+// wherever they did before. Returns how many display sets were removed.
+pub fn dedup_display_sets(display_sets: &mut Vec<DisplaySet>) -> usize {
+
+    let mut kept = Vec::<DisplaySet>::with_capacity(display_sets.len());
+    let mut removed = 0;
+
+    for mut display_set in display_sets.drain(..) {
+
+        if display_set.composition.state != CompositionState::EpochStart
+            && kept.last().is_some_and(|previous| same_content(previous, &display_set)) {
+            removed += 1;
+            continue
+        }
+
+        display_set.composition.number =
+            display_set.composition.number.wrapping_sub(removed as u16);
+        kept.push(display_set);
+    }
+
+    *display_sets = kept;
+
+    removed
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SharedObjects {
+    // Object definitions removed.
+    pub dropped: usize,
+    pub demoted: usize,
+    // How much smaller the display sets are once written.
+    pub bytes: u64,
+}
+
+// Removes each object definition that repeats exactly what the decoder already holds for the same
+// object ID from earlier in the epoch, so that compositions show that definition instead. Epoch
+// starts keep every definition, as decoders empty their object buffers at them, and acquisition
+// points that lose any are demoted to normal cases, as players can no longer start decoding at
+// them.
+pub fn share_objects(epoch: &mut [DisplaySet]) -> SharedObjects {
+
+    let mut held = BTreeMap::<ObjectId, Object>::new();
+    let mut shared = SharedObjects::default();
+    let written_len = |display_set: &DisplaySet| {
+        let mut buffer = vec![];
+        buffer.write_display_set(display_set).map_or(0, |_| buffer.len() as u64)
+    };
+
+    for display_set in epoch.iter_mut() {
+
+        if display_set.composition.state == CompositionState::EpochStart {
+            held.clear();
+        } else {
+
+            let definitions = |id: ObjectId| {
+                display_set.objects.keys().filter(|vid| vid.id == id).count()
+            };
+            let repeated = display_set.objects.iter()
+                .filter(|(vid, object)| {
+                    held.get(&vid.id) == Some(object) && definitions(vid.id) == 1
+                })
+                .map(|(vid, _)| *vid)
+                .collect::<Vec<VersionedId<ObjectId>>>();
+
+            if !repeated.is_empty() {
+
+                let before = written_len(display_set);
+
+                for vid in repeated.iter() {
+                    display_set.objects.remove(vid);
+                }
+                if display_set.composition.state == CompositionState::AcquisitionPoint {
+                    display_set.composition.state = CompositionState::Normal;
+                    shared.demoted += 1;
+                }
+
+                shared.dropped += repeated.len();
+                shared.bytes += before.saturating_sub(written_len(display_set));
+            }
+        }
+
+        for (vid, object) in display_set.objects.iter() {
+            held.insert(vid.id, object.clone());
+        }
+    }
+
+    shared
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EpochRepairs {
+    pub renumbered: usize,
+    pub promoted: usize,
+    pub demoted_to_acquisition_point: usize,
+    pub demoted_to_normal: usize,
+}
+
+// Repairs display sets one at a time in stream order, for when the whole stream is not at hand;
+// see repair_epochs.
+#[derive(Clone, Debug, Default)]
+pub struct EpochRepairer {
+    state: EpochState,
+    size: Option<(u16, u16)>,
+    next_number: u16,
+    pub repairs: EpochRepairs,
+}
+
+impl EpochRepairer {
+
+    pub fn repair(&mut self, display_set: &mut DisplaySet) {
+
+        let size = (display_set.width, display_set.height);
+        let state = display_set.composition.state;
+
+        if self.size != Some(size) {
+            if state != CompositionState::EpochStart {
+                display_set.composition.state = CompositionState::EpochStart;
+                self.repairs.promoted += 1;
+            }
+        } else if state == CompositionState::EpochStart && self.continues_epoch(display_set) {
+            if display_set.objects.is_empty() {
+                display_set.composition.state = CompositionState::Normal;
+                self.repairs.demoted_to_normal += 1;
+            } else {
+                display_set.composition.state = CompositionState::AcquisitionPoint;
+                self.repairs.demoted_to_acquisition_point += 1;
+            }
+        }
+
+        if display_set.composition.number != self.next_number {
+            display_set.composition.number = self.next_number;
+            self.repairs.renumbered += 1;
+        }
+
+        self.next_number = self.next_number.wrapping_add(1);
+        self.size = Some(size);
+        self.state.apply(display_set);
+    }
+
+    // Whether carrying on the current epoch loses nothing: the windows stay the same, and every
+    // object and palette defined is one the epoch already holds.
+    fn continues_epoch(&self, display_set: &DisplaySet) -> bool {
+        display_set.windows == self.state.windows
+            && display_set.objects.iter()
+                .all(|(vid, object)| self.state.objects.get(&vid.id) == Some(object))
+            && display_set.palettes.iter()
+                .all(|(vid, palette)| self.state.palettes.get(&vid.id) == Some(palette))
+    }
+}
+
+// Numbers compositions from zero up, makes the first display set of the stream and the first
+// after any change of resolution epoch starts, and turns later epoch starts that define nothing
+// new into acquisition points, or into normal cases if they define no objects at all.
+pub fn repair_epochs(display_sets: &mut [DisplaySet]) -> EpochRepairs {
+
+    let mut repairer = EpochRepairer::default();
+
+    for display_set in display_sets.iter_mut() {
+        repairer.repair(display_set);
+    }
+
+    repairer.repairs
+}
+
+// The decoder model's rates, in bits per second, for decoding object data and for writing to the
+// graphics plane.
+pub const OBJECT_DECODE_RATE: u64 = 128_000_000;
+pub const PLANE_WRITE_RATE: u64 = 3_200_000_000;
+
+// How long the decoder model takes from starting on a display set to having it on screen, in
+// 90 kHz ticks, given the epoch state after applying it. An epoch start first clears the whole
+// plane and anything else the windows it draws in. Then each composed object that the display set
+// defines is decoded, and each window is written out after the last of its objects.
+pub fn decode_duration(display_set: &DisplaySet, state: &EpochState) -> u32 {
+
+    let ticks = |area: u64, rate: u64| (90_000 * 8 * area).div_ceil(rate);
+    let window_area = |window_id: WindowId| state.windows.get(&window_id)
+        .map_or(0, |window| window.width as u64 * window.height as u64);
+    let mut duration = match display_set.composition.state {
+        CompositionState::EpochStart => ticks(
+            display_set.width as u64 * display_set.height as u64,
+            PLANE_WRITE_RATE,
+        ),
+        _ => state.windows.keys()
+            .map(|&window_id| ticks(window_area(window_id), PLANE_WRITE_RATE))
+            .sum(),
+    };
+    let cids = display_set.composition.objects.keys().collect::<Vec<&Cid>>();
+
+    for (index, cid) in cids.iter().enumerate() {
+        if let Some((_, object)) = display_set.objects.iter()
+            .find(|(vid, _)| vid.id == cid.object_id) {
+            duration += ticks(object.width as u64 * object.height as u64, OBJECT_DECODE_RATE);
+        }
+        if cids.get(index + 1).is_none_or(|next| next.window_id != cid.window_id) {
+            duration += ticks(window_area(cid.window_id), PLANE_WRITE_RATE);
+        }
+    }
+
+    duration.min(u32::MAX as u64) as u32
+}
+
+// Sets the DTS of display sets one at a time in stream order to their PTS less their decode
+// duration, never going back before the DTS of the one before. Every segment of a display set is
+// given the same DTS, that of its PCS, which is also what the model gives its WDS, its PDSs, and
+// its first ODS, so any DTS segments were read with is dropped.
+#[derive(Clone, Debug, Default)]
+pub struct DtsScheduler {
+    state: EpochState,
+    last_dts: u32,
+    pub changed: usize,
+}
+
+impl DtsScheduler {
+
+    pub fn schedule(&mut self, display_set: &mut DisplaySet) {
+
+        self.state.apply(display_set);
+
+        let dts = display_set.pts
+            .saturating_sub(decode_duration(display_set, &self.state))
+            .max(self.last_dts.min(display_set.pts));
+
+        if display_set.dts != dts || !display_set.segment_dts.is_empty() {
+            display_set.dts = dts;
+            display_set.segment_dts = SegmentDts::default();
+            self.changed += 1;
+        }
+
+        self.last_dts = dts;
+    }
+}
+
+// Recomputes the DTS of every display set; see DtsScheduler. Returns how many changed.
+pub fn recompute_dts(display_sets: &mut [DisplaySet]) -> usize {
+
+    let mut scheduler = DtsScheduler::default();
+
+    for display_set in display_sets.iter_mut() {
+        scheduler.schedule(display_set);
+    }
+
+    scheduler.changed
+}
+
+fn same_content(a: &DisplaySet, b: &DisplaySet) -> bool {
+    a.width == b.width
+        && a.height == b.height
+        && a.frame_rate == b.frame_rate
+        && a.palette_update_id == b.palette_update_id
+        && a.windows == b.windows
+        && a.palettes == b.palettes
+        && a.objects == b.objects
+        && a.composition.state == b.composition.state
+        && a.composition.objects == b.composition.objects
+}
+
+pub fn frame_duration(frame_rate: FrameRate) -> u32 {
+    // Only the high nibble of the code is looked at, as some encoders set the low one.
+    match FrameRate::from_code(frame_rate.code() & 0xF0) {
+        FrameRate::Fps24 => 3750,
+        FrameRate::Fps25 => 3600,
+        FrameRate::Fps29_97 => 3003,
+        FrameRate::Fps50 => 1800,
+        FrameRate::Fps59_94 => 1502,
+        _ => 3754,
+    }
+}
+
+pub fn clear_display_set(previous: &DisplaySet, pts: u32) -> DisplaySet {
+    DisplaySet {
+        pts,
+        dts: pts,
+        width: previous.width,
+        height: previous.height,
+        frame_rate: previous.frame_rate,
+        palette_update_id: None,
+        windows: previous.windows.clone(),
+        palettes: BTreeMap::new(),
+        objects: BTreeMap::new(),
+        composition: Composition {
+            number: previous.composition.number.wrapping_add(1),
+            state: CompositionState::Normal,
+            objects: BTreeMap::new(),
+        },
+        unknown_segments: Vec::new(),
+        segment_dts: SegmentDts::default(),
+        warnings: ReadWarnings::default(),
+        raw: Raw::default(),
+    }
 }
@@ -12,19 +12,36 @@ use super::{
     Object,
     Palette,
     PaletteEntry,
-    Vid,
+    SegmentDts,
+    UnknownSegment,
     Window,
+    super::id::{ObjectId, PaletteId, VersionedId, WindowId},
     super::segment::{
+        CompositionState,
+        Limit,
+        ObjectHeader,
+        PresentationCompositionSegment,
+        Raw,
         ReadError as SegmentReadError,
+        ReadOptions,
+        ReadResult as SegmentReadResult,
         ReadSegmentExt,
+        ResyncReader,
         Segment,
+        Sequence,
+        rle_decompress,
     },
+    super::timestamp::PtsUnwrapper,
 };
 use std::{
     collections::BTreeMap,
-    io::Read,
+    hash::{Hash, Hasher},
+    io::{ErrorKind, Read, Result as IoResult},
+    ops::{Deref, DerefMut},
 };
 use thiserror::Error as ThisError;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 pub type ReadResult<T> = Result<T, ReadError>;
 
@@ -39,10 +56,14 @@ pub enum ReadError {
     MissingPresentationCompositionSegment,
     #[error("PTS is not consistent with presentation composition segment")]
     InconsistentPts,
-    #[error("DTS is not consistent with presentation composition segment")]
-    InconsistentDts,
     #[error("unexpected presentation composition segment within display set")]
     UnexpectedPresentationCompositionSegment,
+    #[error("end segment does not end any display set")]
+    UnexpectedEndSegment,
+    #[error("display set is not ended by an end segment")]
+    MissingEndSegment,
+    #[error("segment out of order within display set")]
+    SegmentOutOfOrder,
     #[error("duplicate window ID detected")]
     DuplicateWindowId,
     #[error("duplicate palette ID and version detected")]
@@ -55,128 +76,700 @@ pub enum ReadError {
     CompositionReferencesUnknownWindowId,
     #[error("palette update references unknown palette ID")]
     PaletteUpdateReferencesUnknownPaletteId,
+    #[error("object definition starts over before its previous fragments are complete")]
+    ObjectSequenceInterrupted,
+    #[error("object definition starts without its data length and size")]
+    MissingObjectHeader,
+    #[error("object fragment does not continue any started object")]
+    OrphanedObjectFragment,
+    #[error("object fragments do not add up to their declared data length")]
+    ObjectDataLengthMismatch,
+    #[error("display set ends before an object's last fragment")]
+    IncompleteObject,
+    #[error("{limit}")]
+    LimitExceeded {
+        limit: Limit,
+    },
+    #[error("{source} (segment at {offset:#X})")]
+    At {
+        offset: u64,
+        source: Box<ReadError>,
+    },
+}
+
+impl ReadError {
+
+    // The resource limit that stopped the read, whether hit while decoding a segment or while
+    // assembling the display set.
+    pub fn limit(&self) -> Option<Limit> {
+        match self.bare() {
+            ReadError::LimitExceeded { limit } => Some(*limit),
+            ReadError::SegmentError { source } => match source.bare() {
+                SegmentReadError::LimitExceeded { limit } => Some(*limit),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Notes where in the stream the segment that the display set failed on began. Segment errors
+    // say so themselves, and IO errors are left as they are.
+    pub fn at(self, offset: u64) -> Self {
+        match self {
+            ReadError::SegmentError { source } => {
+                ReadError::SegmentError { source: source.at(offset) }
+            }
+            ReadError::At { .. } => self,
+            _ => ReadError::At { offset, source: Box::new(self) },
+        }
+    }
+
+    // The error itself, without where it happened.
+    pub fn bare(&self) -> &Self {
+        match self {
+            ReadError::At { source, .. } => source.bare(),
+            _ => self,
+        }
+    }
+}
+
+// Structural noise that some encoders emit and players ignore, absorbed when reading leniently.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ReadWarning {
+    // An END segment with no display set of its own, usually a duplicate of the previous one.
+    StrayEnd,
+    // A PCS without composition objects that was immediately superseded by another one.
+    SupersededEmptyPcs,
+    // A display set that is not an epoch start but differs in size from the one before it, which
+    // was the given size. Only readers that follow a stream from one display set to the next,
+    // such as DisplaySets and LossyReader, can tell.
+    ResizedMidEpoch {
+        width: u16,
+        height: u16,
+    },
+}
+
+// The warnings raised while reading a display set. Like Raw, they describe how the display set was
+// read rather than what it holds, so they take no part in comparisons or hashing.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct ReadWarnings(pub Vec<ReadWarning>);
+
+impl Deref for ReadWarnings {
+
+    type Target = Vec<ReadWarning>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for ReadWarnings {
+
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<Vec<ReadWarning>> for ReadWarnings {
+
+    fn from(warnings: Vec<ReadWarning>) -> Self {
+        Self(warnings)
+    }
+}
+
+impl PartialEq for ReadWarnings {
+
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for ReadWarnings {}
+
+impl Hash for ReadWarnings {
+
+    fn hash<H: Hasher>(&self, _: &mut H) {}
 }
 
 pub trait ReadDisplaySetExt {
     fn read_display_set(&mut self) -> ReadResult<DisplaySet>;
+    fn read_display_set_with(&mut self, options: &ReadOptions) -> ReadResult<DisplaySet>;
+    fn read_display_set_counted(
+        &mut self,
+        offset: &mut u64,
+        options: &ReadOptions,
+    ) -> ReadResult<DisplaySet>;
+    fn display_sets(&mut self, options: &ReadOptions) -> DisplaySets<'_, Self> where Self: Sized;
 }
 
 impl<T: Read> ReadDisplaySetExt for T {
 
     fn read_display_set(&mut self) -> ReadResult<DisplaySet> {
+        self.read_display_set_with(&ReadOptions::default())
+    }
 
-        let mut windows = BTreeMap::<u8, Window>::new();
-        let mut palettes = BTreeMap::<Vid<u8>, Palette>::new();
-        let mut objects = BTreeMap::<Vid<u16>, Object>::new();
-        let mut composition_objects = BTreeMap::<Cid, CompositionObject>::new();
-        let first_seg = self.read_segment()?;
-        let pcs = match first_seg {
-            Segment::PresentationComposition(pcs) => pcs,
-            _ => return Err(ReadError::MissingPresentationCompositionSegment),
-        };
-        let pts = pcs.pts;
-        let dts = pcs.dts;
+    fn read_display_set_with(&mut self, options: &ReadOptions) -> ReadResult<DisplaySet> {
+        read_display_set_from(|| Ok((self.read_segment_with(options)?, None)), options, None)
+    }
+
+    // Reads a display set from the given offset into the stream, advancing it past whatever was
+    // read. Errors other than IO errors say where the segment they came up in began.
+    fn read_display_set_counted(
+        &mut self,
+        offset: &mut u64,
+        options: &ReadOptions,
+    ) -> ReadResult<DisplaySet> {
+        read_display_set_from(
+            || {
+                let start = *offset;
+                Ok((self.read_segment_counted(offset, options)?, Some(start)))
+            },
+            options,
+            None,
+        )
+    }
+
+    fn display_sets(&mut self, options: &ReadOptions) -> DisplaySets<'_, Self> {
+        DisplaySets {
+            inner: self,
+            options: *options,
+            offset: 0,
+            unwrapper: PtsUnwrapper::default(),
+            pts: None,
+            size: None,
+            done: false,
+        }
+    }
+}
+
+// Iterates over the display sets of a stream up to its end. A stream that ends between display
+// sets just ends the iteration, even after a stray END segment when reading leniently, whereas
+// one that ends partway through a display set yields an error. So does anything else that keeps
+// a display set from being read, after which the iteration ends.
+pub struct DisplaySets<'a, R> {
+    inner: &'a mut R,
+    options: ReadOptions,
+    offset: u64,
+    unwrapper: PtsUnwrapper,
+    pts: Option<u64>,
+    // The width and height of the display set yielded last.
+    size: Option<(u16, u16)>,
+    done: bool,
+}
+
+impl<R: Read> DisplaySets<'_, R> {
+
+    pub fn get_ref(&self) -> &R {
+        self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner
+    }
+
+    // Where the next display set will be read from, counting from where the iteration began.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    // The PTS of the display set yielded last, counted on past each time the clock has wrapped
+    // around since the iteration began, or None before the first.
+    pub fn unwrapped_pts(&self) -> Option<u64> {
+        self.pts
+    }
+}
+
+impl<R: Read> Iterator for DisplaySets<'_, R> {
+
+    type Item = ReadResult<DisplaySet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        if self.done {
+            return None
+        }
+
+        let mut assembler = DisplaySetAssembler::new(&self.options);
+
+        assembler.previous_size = self.size;
 
         loop {
 
-            let segment = self.read_segment()?;
+            let start = self.offset;
+            let result = match self.inner.read_segment_counted(&mut self.offset, &self.options) {
+                Err(SegmentReadError::IoError { source })
+                    if source.kind() == ErrorKind::UnexpectedEof
+                        && self.offset == start
+                        && !assembler.started() => {
+                    self.done = true;
+                    return None
+                }
+                Err(err) => Err(err.into()),
+                Ok(segment) => assembler.push(segment).map_err(|err| err.at(start)),
+            };
 
-            match segment {
-                Segment::PresentationComposition(_) => {
-                    return Err(ReadError::UnexpectedPresentationCompositionSegment)
+            match result {
+                Ok(Some(display_set)) => {
+                    self.pts = Some(self.unwrapper.unwrap_pts(display_set.pts));
+                    self.size = Some((display_set.width, display_set.height));
+                    return Some(Ok(display_set))
                 }
-                Segment::WindowDefinition(wds) => {
-                    if wds.pts != pts {
-                        return Err(ReadError::InconsistentPts)
-                    }
-                    if wds.dts != dts {
-                        return Err(ReadError::InconsistentDts)
-                    }
-                    for wd in wds.windows.iter() {
-                        if windows.contains_key(&wd.id) {
-                            return Err(ReadError::DuplicateWindowId)
-                        }
-                        windows.insert(
-                            wd.id,
-                            Window {
-                                x: wd.x,
-                                y: wd.y,
-                                width: wd.width,
-                                height: wd.height,
-                            },
-                        );
-                    }
+                Ok(None) => {}
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err))
                 }
-                Segment::PaletteDefinition(pds) => {
-                    if pds.pts != pts {
-                        return Err(ReadError::InconsistentPts)
-                    }
-                    if pds.dts != dts {
-                        return Err(ReadError::InconsistentDts)
-                    }
-                    let vid = Vid {
-                        id: pds.id,
-                        version: pds.version,
-                    };
-                    if palettes.contains_key(&vid) {
-                        return Err(ReadError::DuplicatePaletteVid)
+            }
+        }
+    }
+}
+
+// Reads display sets from a stream that may be corrupt. Whatever cannot be read is dropped, along
+// with the rest of its epoch, and reading picks up again at the next epoch start.
+pub struct LossyReader<R: Read> {
+    inner: ResyncReader<R>,
+    resuming: bool,
+    dropped: usize,
+    // The width and height of the display set returned last.
+    size: Option<(u16, u16)>,
+}
+
+impl<R: Read> LossyReader<R> {
+
+    pub fn new(inner: R) -> Self {
+        Self { inner: ResyncReader::new(inner), resuming: false, dropped: 0, size: None }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut()
+    }
+
+    // Bytes skipped while looking for the next segment after a corrupt one.
+    pub fn skipped(&self) -> u64 {
+        self.inner.skipped()
+    }
+
+    pub fn resyncs(&self) -> usize {
+        self.inner.resyncs()
+    }
+
+    // Display sets that could not be read, plus those dropped while waiting for an epoch start.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    // Errors other than IO errors and exceeded limits are recovered from. IO errors, including
+    // reaching the end of the stream, are returned as they are.
+    pub fn read_display_set_lossy(&mut self, options: &ReadOptions) -> ReadResult<DisplaySet> {
+
+        loop {
+
+            let inner = &mut self.inner;
+
+            let next_segment = || {
+                let start = inner.offset();
+                Ok((inner.read_segment_resync(options)?, Some(start)))
+            };
+
+            match read_display_set_from(next_segment, options, self.size) {
+                Ok(display_set) => {
+                    if !self.resuming
+                        || display_set.composition.state == CompositionState::EpochStart {
+                        self.resuming = false;
+                        self.size = Some((display_set.width, display_set.height));
+                        return Ok(display_set)
                     }
-                    palettes.insert(
-                        vid,
-                        Palette {
-                            entries: pds.entries.iter().map(|pe|
-                                (pe.id, PaletteEntry {
-                                    y: pe.y,
-                                    cr: pe.cr,
-                                    cb: pe.cb,
-                                    alpha: pe.alpha,
-                                })
-                            ).collect::<BTreeMap<u8, PaletteEntry>>()
-                        },
-                    );
+                    self.dropped += 1;
+                }
+                Err(err @ ReadError::SegmentError { source: SegmentReadError::IoError { .. } }) => {
+                    return Err(err)
                 }
-                Segment::ObjectDefinition(ods) => {
-                    if ods.pts != pts {
-                        return Err(ReadError::InconsistentPts)
+                Err(err) if err.limit().is_some() => {
+                    return Err(err)
+                }
+                Err(_) => {
+                    // Segments left over from the same display set fail on their own, so only
+                    // the first failure counts.
+                    if !self.resuming {
+                        self.dropped += 1;
                     }
-                    if ods.dts != dts {
-                        return Err(ReadError::InconsistentDts)
+                    self.resuming = true;
+                }
+            }
+        }
+    }
+
+    pub fn display_sets_lossy(&mut self, options: &ReadOptions) -> LossyDisplaySets<'_, R> {
+        LossyDisplaySets { inner: self, options: *options, done: false }
+    }
+}
+
+// Iterates over the display sets that read_display_set_lossy returns. The iteration ends with the
+// stream, dropping a display set that the stream ends partway through, or after yielding any
+// other error that cannot be recovered from.
+pub struct LossyDisplaySets<'a, R: Read> {
+    inner: &'a mut LossyReader<R>,
+    options: ReadOptions,
+    done: bool,
+}
+
+impl<R: Read> LossyDisplaySets<'_, R> {
+
+    pub fn get_ref(&self) -> &LossyReader<R> {
+        self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut LossyReader<R> {
+        self.inner
+    }
+}
+
+impl<R: Read> Iterator for LossyDisplaySets<'_, R> {
+
+    type Item = ReadResult<DisplaySet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        if self.done {
+            return None
+        }
+
+        match self.inner.read_display_set_lossy(&self.options) {
+            Ok(display_set) => Some(Ok(display_set)),
+            Err(ReadError::SegmentError { source: SegmentReadError::IoError { source } })
+                if source.kind() == ErrorKind::UnexpectedEof => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for LossyReader<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.inner.read(buf)
+    }
+}
+
+// Each segment comes with where it began, if that is known.
+fn read_display_set_from<F: FnMut() -> SegmentReadResult<(Segment, Option<u64>)>>(
+    mut next_segment: F,
+    options: &ReadOptions,
+    previous_size: Option<(u16, u16)>,
+) -> ReadResult<DisplaySet> {
+
+    let mut assembler = DisplaySetAssembler::new(options);
+
+    assembler.previous_size = previous_size;
+
+    loop {
+
+        let (segment, offset) = next_segment()?;
+        let pushed = assembler.push(segment);
+
+        if let Some(display_set) = match offset {
+            Some(offset) => pushed.map_err(|err| err.at(offset))?,
+            None => pushed?,
+        } {
+            return Ok(display_set)
+        }
+    }
+}
+
+// Builds a display set from its segments one at a time, so that every reader applies the same
+// rules however it gets them.
+pub(crate) struct DisplaySetAssembler {
+    options: ReadOptions,
+    pcs: Option<PresentationCompositionSegment>,
+    windows: BTreeMap<WindowId, Window>,
+    palettes: BTreeMap<VersionedId<PaletteId>, Palette>,
+    objects: BTreeMap<VersionedId<ObjectId>, Object>,
+    // Objects whose first fragment has been read, by ID, as fragments of different objects
+    // may be interleaved.
+    fragments: BTreeMap<ObjectId, (u8, ObjectHeader, Vec<u8>)>,
+    unknown_segments: Vec<UnknownSegment>,
+    segment_dts: SegmentDts,
+    raw_segments: Vec<Vec<u8>>,
+    warnings: Vec<ReadWarning>,
+    // Objects share the display set's decoded pixel budget, and its budget of object data.
+    pixel_budget: usize,
+    data_budget: usize,
+    // Where the last window, palette, or object segment taken falls in the order Blu-ray lays
+    // them out in, for ReadOptions::strict_order.
+    order: u8,
+    // The width and height of the display set before this one, if it is known.
+    pub(crate) previous_size: Option<(u16, u16)>,
+}
+
+impl DisplaySetAssembler {
+
+    pub(crate) fn new(options: &ReadOptions) -> Self {
+        Self {
+            options: *options,
+            pcs: None,
+            windows: BTreeMap::new(),
+            palettes: BTreeMap::new(),
+            objects: BTreeMap::new(),
+            fragments: BTreeMap::new(),
+            unknown_segments: Vec::new(),
+            segment_dts: SegmentDts::default(),
+            raw_segments: Vec::new(),
+            warnings: Vec::new(),
+            pixel_budget: options.limits.max_decoded_pixels,
+            data_budget: options.limits.max_object_data,
+            order: 0,
+            previous_size: None,
+        }
+    }
+
+    // Whether any segment of the display set has been taken, other than stray ones.
+    pub(crate) fn started(&self) -> bool {
+        self.pcs.is_some()
+    }
+
+    // Takes the next segment, returning the display set once its end segment has been taken.
+    pub(crate) fn push(&mut self, mut segment: Segment) -> ReadResult<Option<DisplaySet>> {
+
+        let options = self.options;
+
+        if self.pcs.is_none() && matches!(segment, Segment::End(_)) {
+            if options.require_end {
+                return Err(ReadError::UnexpectedEndSegment)
+            }
+            if options.lenient {
+                self.warnings.push(ReadWarning::StrayEnd);
+                return Ok(None)
+            }
+        }
+
+        self.raw_segments.extend(segment.take_raw());
+
+        let pcs = match &self.pcs {
+            Some(pcs) => pcs,
+            None => match segment {
+                Segment::PresentationComposition(pcs) => {
+                    self.pcs = Some(pcs);
+                    return Ok(None)
+                }
+                _ => return Err(ReadError::MissingPresentationCompositionSegment),
+            },
+        };
+        let (pts, dts) = (pcs.pts, pcs.dts as i64);
+        let nothing_composed = pcs.composition_objects.is_empty();
+
+        if options.strict_order {
+            let order = match segment {
+                Segment::WindowDefinition(_) => 1,
+                Segment::PaletteDefinition(_) => 2,
+                Segment::ObjectDefinition(_) => 3,
+                _ => self.order,
+            };
+            if order < self.order {
+                return Err(ReadError::SegmentOutOfOrder)
+            }
+            self.order = order;
+        }
+
+        match segment {
+            Segment::PresentationComposition(next_pcs) => {
+                if options.require_end {
+                    return Err(ReadError::MissingEndSegment)
+                }
+                if options.lenient
+                    && nothing_composed
+                    && self.windows.is_empty()
+                    && self.palettes.is_empty()
+                    && self.objects.is_empty()
+                    && self.fragments.is_empty()
+                    && self.unknown_segments.is_empty() {
+                    self.warnings.push(ReadWarning::SupersededEmptyPcs);
+                    self.raw_segments.drain(..self.raw_segments.len().saturating_sub(1));
+                    self.pcs = Some(next_pcs);
+                } else {
+                    return Err(ReadError::UnexpectedPresentationCompositionSegment)
+                }
+            }
+            Segment::WindowDefinition(wds) => {
+                if wds.pts != pts {
+                    return Err(ReadError::InconsistentPts)
+                }
+                self.segment_dts.windows = wds.dts as i64 - dts;
+                for wd in wds.windows.iter() {
+                    if self.windows.contains_key(&wd.id) {
+                        return Err(ReadError::DuplicateWindowId)
                     }
-                    let vid = Vid {
-                        id: ods.id,
-                        version: ods.version,
-                    };
-                    if objects.contains_key(&vid) {
-                        return Err(ReadError::DuplicateObjectVid)
+                    if self.windows.len() >= options.limits.max_windows {
+                        return Err(ReadError::LimitExceeded { limit: Limit::Windows })
                     }
-                    objects.insert(
-                        vid,
-                        Object {
-                            width: ods.width,
-                            height: ods.height,
-                            sequence: ods.sequence,
-                            lines: ods.lines,
+                    self.windows.insert(
+                        wd.id,
+                        Window {
+                            x: wd.x,
+                            y: wd.y,
+                            width: wd.width,
+                            height: wd.height,
                         },
                     );
                 }
-                Segment::End(es) => {
-                    if es.pts != pts {
-                        return Err(ReadError::InconsistentPts)
+            }
+            Segment::PaletteDefinition(pds) => {
+                if pds.pts != pts {
+                    return Err(ReadError::InconsistentPts)
+                }
+                let vid = VersionedId {
+                    id: pds.id,
+                    version: pds.version,
+                };
+                if self.palettes.contains_key(&vid) {
+                    return Err(ReadError::DuplicatePaletteVid)
+                }
+                if pds.dts as i64 != dts {
+                    self.segment_dts.palettes.insert(vid, pds.dts as i64 - dts);
+                }
+                if self.palettes.len() >= options.limits.max_palettes {
+                    return Err(ReadError::LimitExceeded { limit: Limit::Palettes })
+                }
+                self.palettes.insert(
+                    vid,
+                    Palette {
+                        entries: pds.entries.iter().map(|pe|
+                            (pe.id, PaletteEntry {
+                                y: pe.y,
+                                cr: pe.cr,
+                                cb: pe.cb,
+                                alpha: pe.alpha,
+                            })
+                        ).collect::<BTreeMap<u8, PaletteEntry>>()
+                    },
+                );
+            }
+            Segment::ObjectDefinition(ods) => {
+                if ods.pts != pts {
+                    return Err(ReadError::InconsistentPts)
+                }
+                let vid = VersionedId {
+                    id: ods.id,
+                    version: ods.version,
+                };
+                let offset = ods.dts as i64 - dts;
+                let (header, data) = match ods.sequence {
+                    Sequence::Single | Sequence::First => {
+                        if self.objects.contains_key(&vid) {
+                            return Err(ReadError::DuplicateObjectVid)
+                        }
+                        if self.fragments.contains_key(&ods.id) {
+                            return Err(ReadError::ObjectSequenceInterrupted)
+                        }
+                        if self.objects.len() + self.fragments.len() >= options.limits.max_objects {
+                            return Err(ReadError::LimitExceeded { limit: Limit::Objects })
+                        }
+                        let header = ods.header.ok_or(ReadError::MissingObjectHeader)?;
+                        self.data_budget = self.data_budget.checked_sub(header.data_length)
+                            .ok_or(ReadError::LimitExceeded { limit: Limit::ObjectData })?;
+                        self.segment_dts.objects.insert(vid, vec![offset]);
+                        if ods.sequence == Sequence::First {
+                            self.fragments.insert(ods.id, (ods.version, header, ods.data));
+                            return Ok(None)
+                        }
+                        (header, ods.data)
                     }
-                    if es.dts != dts {
-                        return Err(ReadError::InconsistentDts)
+                    Sequence::Middle | Sequence::Last => {
+                        let (version, header, mut data) = match self.fragments.remove(&ods.id) {
+                            Some(fragment) if fragment.0 == ods.version => fragment,
+                            _ => return Err(ReadError::OrphanedObjectFragment),
+                        };
+                        self.segment_dts.objects.entry(vid).or_default().push(offset);
+                        data.extend_from_slice(&ods.data);
+                        if data.len() > header.data_length {
+                            return Err(ReadError::ObjectDataLengthMismatch)
+                        }
+                        if ods.sequence == Sequence::Middle {
+                            self.fragments.insert(ods.id, (version, header, data));
+                            return Ok(None)
+                        }
+                        if data.len() != header.data_length {
+                            return Err(ReadError::ObjectDataLengthMismatch)
+                        }
+                        (header, data)
                     }
-                    break
+                };
+                let lines = rle_decompress(&data, self.pixel_budget)?;
+                self.pixel_budget -= lines.iter().map(|line| line.len()).sum::<usize>();
+                self.objects.insert(
+                    vid,
+                    Object {
+                        width: header.width,
+                        height: header.height,
+                        sequence: Sequence::Single,
+                        lines,
+                    },
+                );
+            }
+            Segment::End(es) => {
+                if es.pts != pts {
+                    return Err(ReadError::InconsistentPts)
+                }
+                self.segment_dts.end = es.dts as i64 - dts;
+                if !self.fragments.is_empty() {
+                    return Err(ReadError::IncompleteObject)
                 }
+                let mut next = DisplaySetAssembler::new(&options);
+
+                next.previous_size = self.pcs.as_ref().map(|pcs| (pcs.width, pcs.height));
+
+                let assembler = std::mem::replace(self, next);
+
+                return assembler.finish().map(Some)
+            }
+            Segment::Unknown(us) => {
+                if us.pts != pts {
+                    return Err(ReadError::InconsistentPts)
+                }
+                self.segment_dts.unknown_segments.push(us.dts as i64 - dts);
+                self.unknown_segments.push(UnknownSegment { kind: us.kind, data: us.data });
             }
         }
 
-        for co in pcs.composition_objects.iter() {
-            if !objects.keys().any(|vid| vid.id == co.object_id) {
-                return Err(ReadError::CompositionReferencesUnknownObjectId)
+        Ok(None)
+    }
+
+    fn finish(mut self) -> ReadResult<DisplaySet> {
+
+        let pcs = self.pcs.take().unwrap();
+        let size = (pcs.width, pcs.height);
+
+        if pcs.composition_state != CompositionState::EpochStart {
+            if let Some((width, height)) = self.previous_size.filter(|&previous| previous != size) {
+                self.warnings.push(ReadWarning::ResizedMidEpoch { width, height });
             }
-            if !windows.contains_key(&co.window_id) {
-                return Err(ReadError::CompositionReferencesUnknownWindowId)
+        }
+
+        let mut composition_objects = BTreeMap::<Cid, CompositionObject>::new();
+
+        for co in pcs.composition_objects.iter() {
+            // A palette update shows objects that are already on screen, in windows defined
+            // earlier. A normal case may also show objects that an earlier display set of its
+            // epoch defined, but epoch starts and acquisition points, which players can start
+            // decoding from, cannot.
+            if pcs.palette_update_id.is_none() {
+                if pcs.composition_state != CompositionState::Normal
+                    && !self.objects.keys().any(|vid| vid.id == co.object_id) {
+                    return Err(ReadError::CompositionReferencesUnknownObjectId)
+                }
+                if !self.windows.contains_key(&co.window_id) {
+                    return Err(ReadError::CompositionReferencesUnknownWindowId)
+                }
             }
             composition_objects.insert(
                 Cid {
@@ -187,6 +780,7 @@ impl<T: Read> ReadDisplaySetExt for T {
                     x: co.x,
                     y: co.y,
                     crop: co.crop.clone(),
+                    forced: co.forced,
                 },
             );
         }
@@ -197,29 +791,39 @@ impl<T: Read> ReadDisplaySetExt for T {
             objects: composition_objects,
         };
 
-        match pcs.palette_update_id {
-            Some(palette_update_id) => {
-                if !palettes.keys().any(|vid| vid.id == palette_update_id) {
-                    return Err(ReadError::PaletteUpdateReferencesUnknownPaletteId)
-                }
-            }
-            None => {
+        if let Some(palette_update_id) = pcs.palette_update_id {
+            if !self.palettes.keys().any(|vid| vid.id == palette_update_id) {
+                return Err(ReadError::PaletteUpdateReferencesUnknownPaletteId)
             }
         }
 
-        Ok(
-            DisplaySet {
-                pts,
-                dts,
-                width: pcs.width,
-                height: pcs.height,
-                frame_rate: pcs.frame_rate,
-                palette_update_id: pcs.palette_update_id,
-                windows,
-                palettes,
-                objects,
-                composition,
-            }
-        )
+        // Only what differs from the display set's DTS is kept.
+        self.segment_dts.objects.retain(|_, offsets| offsets.iter().any(|&offset| offset != 0));
+        if self.segment_dts.unknown_segments.iter().all(|&offset| offset == 0) {
+            self.segment_dts.unknown_segments.clear();
+        }
+
+        let mut display_set = DisplaySet {
+            pts: pcs.pts,
+            dts: pcs.dts,
+            width: pcs.width,
+            height: pcs.height,
+            frame_rate: pcs.frame_rate,
+            palette_update_id: pcs.palette_update_id,
+            windows: self.windows,
+            palettes: self.palettes,
+            objects: self.objects,
+            composition,
+            unknown_segments: self.unknown_segments,
+            segment_dts: self.segment_dts,
+            warnings: self.warnings.into(),
+            raw: Raw::default(),
+        };
+
+        if self.options.keep_raw {
+            display_set.raw = Raw::new(self.raw_segments, &display_set);
+        }
+
+        Ok(display_set)
     }
 }
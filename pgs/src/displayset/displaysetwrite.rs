@@ -10,14 +10,20 @@ use super::{
         CompositionObject,
         EndSegment,
         ObjectDefinitionSegment,
+        ObjectHeader,
         PaletteDefinitionSegment,
         PaletteEntry,
         PresentationCompositionSegment,
+        Raw,
         WindowDefinition,
         WindowDefinitionSegment,
         WriteError as SegmentWriteError,
         WriteSegmentExt,
         Segment,
+        Sequence,
+        UnknownSegment,
+        rle_compress,
+        split_object_definition,
     },
 };
 use std::io::Write;
@@ -62,12 +68,15 @@ impl<T: Write> WriteDisplaySetExt for T {
                     x: co.x,
                     y: co.y,
                     crop: co.crop.clone(),
+                    forced: co.forced,
                 }
             ).collect::<Vec<CompositionObject>>(),
+            raw: Raw::default(),
         };
+        let offsets = &display_set.segment_dts;
         let wds = WindowDefinitionSegment {
             pts: display_set.pts,
-            dts: display_set.dts,
+            dts: display_set.dts_at(offsets.windows),
             windows: display_set.windows.iter().map(|(&window_id, window)|
                 WindowDefinition {
                     id: window_id,
@@ -77,11 +86,12 @@ impl<T: Write> WriteDisplaySetExt for T {
                     height: window.height,
                 }
             ).collect::<Vec<WindowDefinition>>(),
+            raw: Raw::default(),
         };
         let pdss = display_set.palettes.iter().map(|(vid, palette)|
             PaletteDefinitionSegment {
                 pts: display_set.pts,
-                dts: display_set.dts,
+                dts: display_set.dts_at(offsets.palettes.get(vid).copied().unwrap_or(0)),
                 id: vid.id,
                 version: vid.version,
                 entries: palette.entries.iter().map(|(&id, entry)|
@@ -93,33 +103,64 @@ impl<T: Write> WriteDisplaySetExt for T {
                         alpha: entry.alpha,
                     }
                 ).collect::<Vec<PaletteEntry>>(),
+                raw: Raw::default(),
             }
         ).collect::<Vec<PaletteDefinitionSegment>>();
-        let odss = display_set.objects.iter().map(|(vid, object)|
-            ObjectDefinitionSegment {
-                pts: display_set.pts,
-                dts: display_set.dts,
-                id: vid.id,
-                version: vid.version,
-                sequence: object.sequence,
-                width: object.width,
-                height: object.height,
-                lines: object.lines.clone(),
-            }
-        ).collect::<Vec<ObjectDefinitionSegment>>();
+        let odss = display_set.objects.iter().flat_map(|(vid, object)| {
+
+            let data = rle_compress(&object.lines);
+
+            split_object_definition(
+                ObjectDefinitionSegment {
+                    pts: display_set.pts,
+                    dts: display_set.dts,
+                    id: vid.id,
+                    version: vid.version,
+                    sequence: Sequence::Single,
+                    header: Some(
+                        ObjectHeader {
+                            data_length: data.len(),
+                            width: object.width,
+                            height: object.height,
+                        }
+                    ),
+                    data,
+                    raw: Raw::default(),
+                }
+            ).into_iter().enumerate().map(move |(fragment, mut ods)| {
+                ods.dts = display_set.dts_at(offsets.object(vid, fragment));
+                ods
+            })
+        }).collect::<Vec<ObjectDefinitionSegment>>();
 
         self.write_segment(&Segment::PresentationComposition(pcs))?;
-        self.write_segment(&Segment::WindowDefinition(wds))?;
+        if !display_set.is_palette_update_only() {
+            self.write_segment(&Segment::WindowDefinition(wds))?;
+        }
         for pds in pdss.iter() {
             self.write_segment(&Segment::PaletteDefinition(pds.clone()))?;
         }
         for ods in odss.iter() {
             self.write_segment(&Segment::ObjectDefinition(ods.clone()))?;
         }
+        for (index, unknown_segment) in display_set.unknown_segments.iter().enumerate() {
+            self.write_segment(&Segment::Unknown(
+                UnknownSegment {
+                    pts: display_set.pts,
+                    dts: display_set.dts_at(
+                        offsets.unknown_segments.get(index).copied().unwrap_or(0)
+                    ),
+                    kind: unknown_segment.kind,
+                    data: unknown_segment.data.clone(),
+                    raw: Raw::default(),
+                }
+            ))?;
+        }
         self.write_segment(&Segment::End(
             EndSegment {
                 pts: display_set.pts,
-                dts: display_set.dts,
+                dts: display_set.dts_at(offsets.end),
+                raw: Raw::default(),
             }
         ))?;
 
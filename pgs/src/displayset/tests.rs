@@ -6,12 +6,40 @@
 
 use super::{
     *,
-    super::segment::{CompositionState, Crop},
+    super::segment::{
+        CompositionObject as SegmentCompositionObject,
+        CompositionState,
+        Crop,
+        EndSegment,
+        Limit,
+        Limits,
+        PresentationCompositionSegment,
+        MAX_FIRST_FRAGMENT_DATA,
+        MAX_FRAGMENT_DATA,
+        ObjectDefinitionSegment,
+        ObjectHeader,
+        PaletteDefinitionSegment,
+        PaletteEntry as SegmentPaletteEntry,
+        Raw,
+        ReadError as SegmentReadError,
+        ReadOptions,
+        ReadSegmentExt,
+        Segment,
+        Sequence,
+        UnknownSegment as SegmentUnknownSegment,
+        WindowDefinition,
+        WindowDefinitionSegment,
+        WriteSegmentExt,
+        FrameRate,
+    },
+    super::epoch::group_epochs,
+    super::rle::encode,
+    super::timestamp::PTS_WRAP,
     displaysetread::ReadDisplaySetExt,
     displaysetwrite::WriteDisplaySetExt,
 };
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashSet},
     io::Cursor,
 };
 use rand::{thread_rng, Rng};
@@ -27,16 +55,20 @@ fn test_ds_cycle_empty() {
         dts: rng.gen(),
         width: rng.gen(),
         height: rng.gen(),
-        frame_rate: rng.gen(),
+        frame_rate: FrameRate::from_code(rng.gen()),
         palette_update_id: None,
-        windows: BTreeMap::<u8, Window>::new(),
-        palettes: BTreeMap::<Vid<u8>, Palette>::new(),
-        objects: BTreeMap::<Vid<u16>, Object>::new(),
+        windows: BTreeMap::<WindowId, Window>::new(),
+        palettes: BTreeMap::<VersionedId<PaletteId>, Palette>::new(),
+        objects: BTreeMap::<VersionedId<ObjectId>, Object>::new(),
         composition: Composition {
             number: rng.gen(),
             state: CompositionState::EpochStart,
             objects: BTreeMap::<Cid, CompositionObject>::new(),
         },
+        unknown_segments: vec![],
+        segment_dts: SegmentDts::default(),
+        warnings: ReadWarnings::default(),
+        raw: Raw::default(),
     };
 
     buffer.write_display_set(&display_set).unwrap();
@@ -53,26 +85,27 @@ fn test_ds_cycle_not_empty() {
     let mut rng = thread_rng();
     let mut buffer = vec![];
     let mut composition_objects = BTreeMap::<Cid, CompositionObject>::new();
-    let mut windows = BTreeMap::<u8, Window>::new();
-    let mut palettes = BTreeMap::<Vid<u8>, Palette>::new();
+    let mut windows = BTreeMap::<WindowId, Window>::new();
+    let mut palettes = BTreeMap::<VersionedId<PaletteId>, Palette>::new();
     let mut palette_entries = BTreeMap::<u8, PaletteEntry>::new();
-    let mut objects = BTreeMap::<Vid<u16>, Object>::new();
+    let mut objects = BTreeMap::<VersionedId<ObjectId>, Object>::new();
 
     composition_objects.insert(
         Cid {
-            object_id: 1,
-            window_id: 1,
+            object_id: ObjectId(1),
+            window_id: WindowId(1),
         },
         CompositionObject {
             x: rng.gen(),
             y: rng.gen(),
             crop: None,
+            forced: rng.gen(),
         },
     );
     composition_objects.insert(
         Cid {
-            object_id: 2,
-            window_id: 2,
+            object_id: ObjectId(2),
+            window_id: WindowId(2),
         },
         CompositionObject {
             x: rng.gen(),
@@ -83,12 +116,13 @@ fn test_ds_cycle_not_empty() {
                 width: rng.gen(),
                 height: rng.gen(),
             }),
+            forced: rng.gen(),
         },
     );
     composition_objects.insert(
         Cid {
-            object_id: 3,
-            window_id: 3,
+            object_id: ObjectId(3),
+            window_id: WindowId(3),
         },
         CompositionObject {
             x: rng.gen(),
@@ -99,11 +133,12 @@ fn test_ds_cycle_not_empty() {
                 width: rng.gen(),
                 height: rng.gen(),
             }),
+            forced: rng.gen(),
         },
     );
 
     windows.insert(
-        1,
+        WindowId(1),
         Window {
             x: rng.gen(),
             y: rng.gen(),
@@ -112,7 +147,7 @@ fn test_ds_cycle_not_empty() {
         },
     );
     windows.insert(
-        2,
+        WindowId(2),
         Window {
             x: rng.gen(),
             y: rng.gen(),
@@ -121,7 +156,7 @@ fn test_ds_cycle_not_empty() {
         },
     );
     windows.insert(
-        3,
+        WindowId(3),
         Window {
             x: rng.gen(),
             y: rng.gen(),
@@ -159,8 +194,8 @@ fn test_ds_cycle_not_empty() {
     );
 
     palettes.insert(
-        Vid {
-            id: 1,
+        VersionedId {
+            id: PaletteId(1),
             version: 1,
         },
         Palette {
@@ -169,8 +204,8 @@ fn test_ds_cycle_not_empty() {
     );
 
     objects.insert(
-        Vid {
-            id: 1,
+        VersionedId {
+            id: ObjectId(1),
             version: 1,
         },
         Object {
@@ -181,8 +216,8 @@ fn test_ds_cycle_not_empty() {
         },
     );
     objects.insert(
-        Vid {
-            id: 2,
+        VersionedId {
+            id: ObjectId(2),
             version: 1,
         },
         Object {
@@ -193,8 +228,8 @@ fn test_ds_cycle_not_empty() {
         },
     );
     objects.insert(
-        Vid {
-            id: 3,
+        VersionedId {
+            id: ObjectId(3),
             version: 1,
         },
         Object {
@@ -210,7 +245,7 @@ fn test_ds_cycle_not_empty() {
         dts: rng.gen(),
         width: rng.gen(),
         height: rng.gen(),
-        frame_rate: rng.gen(),
+        frame_rate: FrameRate::from_code(rng.gen()),
         palette_update_id: None,
         windows,
         palettes,
@@ -220,6 +255,10 @@ fn test_ds_cycle_not_empty() {
             state: CompositionState::EpochStart,
             objects: composition_objects,
         },
+        unknown_segments: vec![],
+        segment_dts: SegmentDts::default(),
+        warnings: ReadWarnings::default(),
+        raw: Raw::default(),
     };
 
     buffer.write_display_set(&display_set).unwrap();
@@ -229,3 +268,1790 @@ fn test_ds_cycle_not_empty() {
 
     assert_eq!(cycled_display_set, display_set);
 }
+
+#[test]
+fn test_prune_palettes() {
+
+    let mut palette = Palette::default();
+
+    for id in 0..4 {
+        palette.entries.insert(id, PaletteEntry { y: id, cr: 128, cb: 128, alpha: 255 });
+    }
+
+    let mut epoch = vec![
+        DisplaySet {
+            palettes: vec![(VersionedId { id: PaletteId(0), version: 0 }, palette.clone())]
+                .into_iter()
+                .collect(),
+            objects: vec![(
+                VersionedId { id: ObjectId(0), version: 0 },
+                Object {
+                    width: 2,
+                    height: 1,
+                    sequence: Sequence::Single,
+                    lines: vec![vec![1, 1]],
+                },
+            )].into_iter().collect(),
+            ..Default::default()
+        },
+        DisplaySet {
+            palettes: vec![(VersionedId { id: PaletteId(0), version: 1 }, palette)]
+                .into_iter()
+                .collect(),
+            objects: vec![(
+                VersionedId { id: ObjectId(1), version: 0 },
+                Object {
+                    width: 1,
+                    height: 1,
+                    sequence: Sequence::Single,
+                    lines: vec![vec![3]],
+                },
+            )].into_iter().collect(),
+            ..Default::default()
+        },
+    ];
+
+    assert_eq!(prune_palettes(&mut epoch), 4);
+
+    for display_set in epoch.iter() {
+        for palette in display_set.palettes.values() {
+            assert_eq!(palette.entries.keys().copied().collect::<Vec<u8>>(), vec![1, 3]);
+        }
+    }
+}
+
+fn indexed_epoch() -> Vec<DisplaySet> {
+
+    let mut palette = Palette::default();
+
+    palette.entries.insert(0, PaletteEntry { y: 16, cr: 128, cb: 128, alpha: 0 });
+    palette.entries.insert(4, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+    palette.entries.insert(8, PaletteEntry { y: 128, cr: 128, cb: 128, alpha: 255 });
+
+    let mut first = DisplaySet {
+        palettes: vec![(VersionedId { id: PaletteId(0), version: 0 }, palette)]
+            .into_iter()
+            .collect(),
+        objects: vec![(
+            VersionedId { id: ObjectId(0), version: 0 },
+            Object {
+                width: 4,
+                height: 1,
+                sequence: Sequence::Single,
+                lines: vec![vec![4, 5, 6, 9]],
+            },
+        )].into_iter().collect(),
+        ..Default::default()
+    };
+    let mut second = DisplaySet::default();
+    let cid = Cid { object_id: ObjectId(0), window_id: WindowId(0) };
+
+    first.composition.state = CompositionState::EpochStart;
+    first.composition.objects.insert(cid.clone(), Default::default());
+    second.composition.objects.insert(cid, Default::default());
+
+    vec![first, second]
+}
+
+#[test]
+fn test_missing_indices() {
+
+    let epoch = indexed_epoch();
+    let object = &epoch[0].objects[&VersionedId { id: ObjectId(0), version: 0 }];
+    let palette = &epoch[0].palettes[&VersionedId { id: PaletteId(0), version: 0 }];
+
+    assert_eq!(missing_indices(object, palette).into_iter().collect::<Vec<u8>>(), vec![5, 6, 9]);
+}
+
+#[test]
+fn test_fix_missing_indices() {
+
+    let vid = VersionedId { id: ObjectId(0), version: 0 };
+    let lines = |epoch: &[DisplaySet]| epoch[0].objects[&vid].lines.clone();
+    let mut epoch = indexed_epoch();
+
+    assert_eq!(fix_missing_indices(&mut epoch, IndexFix::Transparent), 3);
+    assert_eq!(lines(&epoch), vec![vec![4, 0, 0, 0]]);
+
+    let mut epoch = indexed_epoch();
+
+    assert_eq!(fix_missing_indices(&mut epoch, IndexFix::Nearest), 3);
+    assert_eq!(lines(&epoch), vec![vec![4, 4, 4, 8]]);
+    assert_eq!(fix_missing_indices(&mut epoch, IndexFix::Nearest), 0);
+}
+
+#[test]
+fn test_clear_display_set() {
+
+    let mut previous = DisplaySet {
+        pts: 90_000,
+        dts: 80_000,
+        width: 1920,
+        height: 1080,
+        frame_rate: FrameRate::Fps23_976,
+        ..Default::default()
+    };
+
+    previous.composition.number = 41;
+    previous.windows.insert(WindowId(0), Window { x: 1, y: 2, width: 3, height: 4 });
+    previous.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject::default(),
+    );
+
+    let clear = clear_display_set(&previous, 180_000 - frame_duration(previous.frame_rate));
+
+    assert_eq!(clear.pts, 176_246);
+    assert_eq!(clear.composition.number, 42);
+    assert_eq!(clear.composition.state, CompositionState::Normal);
+    assert!(clear.composition.objects.is_empty());
+    assert_eq!(clear.windows, previous.windows);
+    assert_eq!(frame_duration(FrameRate::Fps25), 3600);
+    assert_eq!(frame_duration(FrameRate::Other(0x21)), 3750);
+    assert_eq!(frame_duration(FrameRate::Other(0x00)), 3754);
+}
+
+#[test]
+fn test_keep_raw() {
+
+    let mut buffer = vec![];
+    let mut windows = BTreeMap::new();
+
+    windows.insert(WindowId(0), Window { x: 1, y: 2, width: 3, height: 4 });
+    buffer.write_display_set(
+        &DisplaySet {
+            pts: 900,
+            width: 1920,
+            height: 1080,
+            windows,
+            ..Default::default()
+        }
+    ).unwrap();
+
+    assert_eq!(Cursor::new(&buffer).read_display_set().unwrap().raw_segments(), None);
+
+    let mut display_set = Cursor::new(&buffer)
+        .read_display_set_with(&ReadOptions { keep_raw: true, ..Default::default() })
+        .unwrap();
+    let segments = display_set.raw_segments().unwrap();
+
+    assert_eq!(segments.len(), 3);
+    assert_eq!(segments.concat(), buffer);
+
+    display_set.windows.get_mut(&WindowId(0)).unwrap().x = 5;
+
+    assert_eq!(display_set.raw_segments(), None);
+}
+
+#[test]
+fn test_read_display_set_counted() {
+
+    let mut buffer = vec![];
+    let mut windows = BTreeMap::new();
+
+    windows.insert(WindowId(0), Window { x: 1, y: 2, width: 3, height: 4 });
+
+    let display_set = DisplaySet {
+        pts: 900,
+        width: 1920,
+        height: 1080,
+        windows,
+        ..Default::default()
+    };
+
+    buffer.write_display_set(&display_set).unwrap();
+
+    let length = buffer.len() as u64;
+
+    buffer.write_display_set(&display_set).unwrap();
+    // The second display set's WDS, which follows its 24-byte PCS, gets a PTS of its own.
+    buffer[length as usize + 24 + 5] = 0xFF;
+
+    let mut cursor = Cursor::new(&buffer);
+    let mut offset = 0;
+    let options = ReadOptions::default();
+
+    assert_eq!(cursor.read_display_set_counted(&mut offset, &options).unwrap(), display_set);
+    assert_eq!(offset, length);
+
+    let err = cursor.read_display_set_counted(&mut offset, &options).unwrap_err();
+
+    assert!(matches!(err, ReadError::At { offset, .. } if offset == length + 24));
+    assert!(matches!(err.bare(), ReadError::InconsistentPts));
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "PTS is not consistent with presentation composition segment (segment at {:#X})",
+            length + 24,
+        ),
+    );
+}
+
+#[test]
+fn test_unknown_segments_pass_through() {
+
+    let mut buffer = vec![];
+    let options = ReadOptions { keep_unknown: true, ..Default::default() };
+
+    buffer.write_segment(&Segment::PresentationComposition(
+        PresentationCompositionSegment { pts: 900, width: 1920, height: 1080, ..Default::default() }
+    )).unwrap();
+    buffer.write_segment(&Segment::Unknown(
+        SegmentUnknownSegment { pts: 900, kind: 0x99, data: vec![1, 2, 3], ..Default::default() }
+    )).unwrap();
+    buffer.write_segment(&Segment::End(EndSegment { pts: 900, ..Default::default() })).unwrap();
+
+    assert!(Cursor::new(&buffer).read_display_set().is_err());
+
+    let mut display_set = Cursor::new(&buffer).read_display_set_with(&options).unwrap();
+
+    assert_eq!(
+        display_set.unknown_segments,
+        vec![UnknownSegment { kind: 0x99, data: vec![1, 2, 3] }],
+    );
+
+    // Unknown segments follow the display set's timing, and are written just before the END.
+    display_set.pts = 1800;
+
+    let mut rewritten = vec![];
+
+    rewritten.write_display_set(&display_set).unwrap();
+
+    let mut cursor = Cursor::new(&rewritten);
+    let segments = (0..4)
+        .map(|_| cursor.read_segment_with(&options).unwrap())
+        .collect::<Vec<Segment>>();
+
+    assert!(matches!(
+        &segments[2],
+        Segment::Unknown(us) if us.pts == 1800 && us.kind == 0x99 && us.data == [1, 2, 3]
+    ));
+    assert!(matches!(segments[3], Segment::End(_)));
+}
+
+fn noisy_stream() -> Vec<u8> {
+
+    let mut buffer = vec![];
+    let mut windows = BTreeMap::new();
+
+    windows.insert(WindowId(0), Window { x: 1, y: 2, width: 3, height: 4 });
+
+    let display_set = DisplaySet {
+        pts: 900,
+        width: 1920,
+        height: 1080,
+        windows,
+        ..Default::default()
+    };
+
+    buffer.write_display_set(&display_set).unwrap();
+    buffer.write_segment(&Segment::End(EndSegment { pts: 900, ..Default::default() })).unwrap();
+    buffer.write_segment(&Segment::PresentationComposition(
+        PresentationCompositionSegment {
+            pts: 1800,
+            width: 1920,
+            height: 1080,
+            ..Default::default()
+        }
+    )).unwrap();
+    buffer.write_display_set(&DisplaySet { pts: 1800, ..display_set }).unwrap();
+
+    buffer
+}
+
+#[test]
+fn test_display_sets() {
+
+    let mut buffer = noisy_stream();
+    let options = ReadOptions { lenient: true, ..Default::default() };
+
+    // A stray END at the very end ends the stream like any other.
+    buffer.write_segment(&Segment::End(EndSegment { pts: 1800, ..Default::default() })).unwrap();
+
+    let pts = Cursor::new(&buffer).display_sets(&options)
+        .map(|display_set| display_set.map(|display_set| display_set.pts))
+        .collect::<ReadResult<Vec<u32>>>()
+        .unwrap();
+
+    assert_eq!(pts, [900, 1800]);
+
+    // Cut off partway through the last display set, after the whole of its PCS.
+    let truncated = &buffer[..buffer.len() - 13 - 13];
+    let mut cursor = Cursor::new(truncated);
+    let mut display_sets = cursor.display_sets(&options);
+
+    assert_eq!(display_sets.next().unwrap().unwrap().pts, 900);
+    assert!(matches!(
+        display_sets.next(),
+        Some(Err(ReadError::SegmentError { source: SegmentReadError::IoError { .. } })),
+    ));
+    assert!(display_sets.next().is_none());
+    assert_eq!(display_sets.offset(), truncated.len() as u64);
+}
+
+#[test]
+fn test_display_sets_unwrap_pts() {
+
+    let mut buffer = vec![];
+
+    for &pts in [u32::MAX - 90_000, 90_000, 45_000, 180_000].iter() {
+        buffer.write_display_set(&DisplaySet { pts, ..Default::default() }).unwrap();
+    }
+
+    let mut cursor = Cursor::new(&buffer);
+    let mut display_sets = cursor.display_sets(&ReadOptions::default());
+    let mut unwrapped = vec![display_sets.unwrapped_pts()];
+
+    while let Some(display_set) = display_sets.next() {
+        display_set.unwrap();
+        unwrapped.push(display_sets.unwrapped_pts());
+    }
+
+    // Stepping back a little is not taken for a wraparound.
+    assert_eq!(
+        unwrapped,
+        [
+            None,
+            Some(u32::MAX as u64 - 90_000),
+            Some(PTS_WRAP + 90_000),
+            Some(PTS_WRAP + 45_000),
+            Some(PTS_WRAP + 180_000),
+        ],
+    );
+}
+
+#[test]
+fn test_strict_read_rejects_noise() {
+
+    let buffer = noisy_stream();
+    let mut cursor = Cursor::new(&buffer);
+
+    assert!(cursor.read_display_set().unwrap().warnings.is_empty());
+    assert!(matches!(
+        cursor.read_display_set(),
+        Err(ReadError::MissingPresentationCompositionSegment),
+    ));
+}
+
+#[test]
+fn test_lenient_read_absorbs_noise() {
+
+    let buffer = noisy_stream();
+    let options = ReadOptions { lenient: true, ..Default::default() };
+    let mut cursor = Cursor::new(&buffer);
+
+    assert!(cursor.read_display_set_with(&options).unwrap().warnings.is_empty());
+
+    let display_set = cursor.read_display_set_with(&options).unwrap();
+
+    assert_eq!(display_set.pts, 1800);
+    assert_eq!(display_set.windows.len(), 1);
+    assert_eq!(
+        *display_set.warnings,
+        vec![ReadWarning::StrayEnd, ReadWarning::SupersededEmptyPcs],
+    );
+    assert_eq!(
+        display_set,
+        DisplaySet { warnings: ReadWarnings::default(), ..display_set.clone() },
+    );
+
+    let mut rewritten = vec![];
+
+    rewritten.write_display_set(&display_set).unwrap();
+
+    assert_eq!(Cursor::new(&rewritten).read_display_set().unwrap().pts, 1800);
+    assert!(cursor.read_display_set_with(&options).is_err());
+}
+
+#[test]
+fn test_noise_fixture() {
+
+    let input = include_bytes!("../../../test-data/noise.sup");
+    let options = ReadOptions { lenient: true, ..Default::default() };
+    let display_sets = Cursor::new(&input[..]).display_sets(&options)
+        .collect::<ReadResult<Vec<DisplaySet>>>()
+        .unwrap();
+
+    assert!(Cursor::new(&input[..]).display_sets(&ReadOptions::default())
+        .collect::<ReadResult<Vec<DisplaySet>>>()
+        .is_err());
+    assert_eq!(display_sets.len(), 2);
+    assert!(display_sets[0].warnings.is_empty());
+    assert_eq!(
+        *display_sets[1].warnings,
+        vec![ReadWarning::StrayEnd, ReadWarning::SupersededEmptyPcs],
+    );
+
+    let mut output = vec![];
+
+    for display_set in display_sets.iter() {
+        output.write_display_set(display_set).unwrap();
+    }
+
+    let rewritten = Cursor::new(&output).display_sets(&ReadOptions::default())
+        .collect::<ReadResult<Vec<DisplaySet>>>()
+        .unwrap();
+
+    // Neither the second END nor the empty PCS is written back.
+    assert_eq!(rewritten, display_sets);
+    assert!(rewritten.iter().all(|display_set| display_set.warnings.is_empty()));
+    assert_eq!(output.len(), input.len() - 13 - (13 + 11));
+}
+
+#[test]
+fn test_end_segments_can_be_required() {
+
+    let input = &include_bytes!("../../../test-data/noise.sup")[..];
+    let lenient = ReadOptions { lenient: true, ..Default::default() };
+    let strict = ReadOptions { require_end: true, ..lenient };
+    let read = |input: &[u8], options: &ReadOptions| {
+        Cursor::new(input).display_sets(options)
+            .collect::<ReadResult<Vec<DisplaySet>>>()
+            .map_err(|err| format!("{:?}", err.bare()))
+    };
+
+    assert_eq!(read(input, &lenient).unwrap().len(), 2);
+    assert_eq!(read(input, &strict).unwrap_err(), "UnexpectedEndSegment");
+
+    // Without its duplicated END, the fixture still has a display set that is never ended.
+    let mut cursor = Cursor::new(input);
+
+    cursor.read_display_set().unwrap();
+
+    let end = cursor.position() as usize;
+    let input = [&input[..end], &input[end + 13..]].concat();
+
+    assert_eq!(read(&input, &lenient).unwrap().len(), 2);
+    assert_eq!(read(&input, &strict).unwrap_err(), "MissingEndSegment");
+}
+
+#[test]
+fn test_fade_fixture_cycle() {
+
+    let input = include_bytes!("../../../test-data/fade.sup");
+    let display_sets = Cursor::new(&input[..]).display_sets(&ReadOptions::default())
+        .collect::<ReadResult<Vec<DisplaySet>>>()
+        .unwrap();
+    let mut output = vec![];
+
+    for display_set in display_sets.iter() {
+        output.write_display_set(display_set).unwrap();
+    }
+
+    let updates = display_sets.iter()
+        .filter(|display_set| display_set.is_palette_update_only())
+        .map(|display_set| display_set.palettes.keys().next().unwrap().version)
+        .collect::<Vec<u8>>();
+
+    assert_eq!(updates, [1, 2, 3, 4]);
+    assert!(output == input[..]);
+}
+
+// A display set that players take as it is, but that bends the spec three ways: an unusual frame
+// rate, a palette defined before the windows, and a segment of a kind the crate does not know.
+fn deviant_display_set() -> Vec<u8> {
+
+    let pts = 900;
+    let segments = [
+        Segment::PresentationComposition(PresentationCompositionSegment {
+            pts,
+            width: 1920,
+            height: 1080,
+            frame_rate: FrameRate::Other(0x11),
+            ..Default::default()
+        }),
+        Segment::PaletteDefinition(PaletteDefinitionSegment { pts, ..Default::default() }),
+        Segment::WindowDefinition(WindowDefinitionSegment {
+            pts,
+            windows: vec![WindowDefinition { width: 4, height: 2, ..Default::default() }],
+            ..Default::default()
+        }),
+        Segment::Unknown(SegmentUnknownSegment { pts, kind: 0x99, ..Default::default() }),
+        Segment::End(EndSegment { pts, ..Default::default() }),
+    ];
+    let mut buffer = vec![];
+
+    for segment in segments.iter() {
+        buffer.write_segment(segment).unwrap();
+    }
+
+    buffer
+}
+
+#[test]
+fn test_strictness_is_chosen_by_the_caller() {
+
+    let buffer = deviant_display_set();
+    let permissive = ReadOptions { keep_unknown: true, lenient: true, ..Default::default() };
+    let read = |options: &ReadOptions| Cursor::new(&buffer).read_display_set_with(options);
+    let segment_error = |options: &ReadOptions| match read(options) {
+        Err(ReadError::SegmentError { source }) => Some(source.bare().to_string()),
+        _ => None,
+    };
+
+    let display_set = read(&permissive).unwrap();
+
+    assert_eq!(display_set.frame_rate, FrameRate::Other(0x11));
+    assert_eq!(display_set.windows.len(), 1);
+    assert_eq!(display_set.palettes.len(), 1);
+    assert_eq!(display_set.unknown_segments.len(), 1);
+
+    // Each check fails the read with an error of its own.
+    assert_eq!(
+        segment_error(&ReadOptions { keep_unknown: false, ..permissive }),
+        Some(SegmentReadError::UnrecognizedKind.to_string()),
+    );
+    assert_eq!(
+        segment_error(&ReadOptions { strict_frame_rate: true, ..permissive }),
+        Some(SegmentReadError::UnrecognizedFrameRate.to_string()),
+    );
+    assert!(matches!(
+        read(&ReadOptions { strict_order: true, ..permissive }).as_ref().map_err(ReadError::bare),
+        Err(ReadError::SegmentOutOfOrder),
+    ));
+
+    // Without a frame rate to object to, what the crate writes passes every check.
+    let display_set = DisplaySet { frame_rate: FrameRate::Fps23_976, ..display_set };
+    let strict = ReadOptions {
+        strict_frame_rate: true,
+        strict_order: true,
+        require_end: true,
+        keep_unknown: true,
+        ..Default::default()
+    };
+    let mut rewritten = vec![];
+
+    rewritten.write_display_set(&display_set).unwrap();
+
+    assert_eq!(Cursor::new(&rewritten).read_display_set_with(&strict).unwrap(), display_set);
+}
+
+fn limited(limits: Limits) -> ReadOptions {
+    ReadOptions { limits, ..Default::default() }
+}
+
+#[test]
+fn test_read_limits() {
+
+    let mut display_set = DisplaySet { pts: 900, width: 1920, height: 1080, ..Default::default() };
+
+    for id in 0..3u8 {
+        display_set.windows.insert(WindowId(id), Window { x: 0, y: 0, width: 1, height: 1 });
+        display_set.palettes.insert(
+            VersionedId { id: PaletteId(id), version: 0 },
+            Palette::default(),
+        );
+        display_set.objects.insert(
+            VersionedId { id: ObjectId(id as u16), version: 0 },
+            Object { width: 4, height: 1, sequence: Sequence::Single, lines: vec![vec![1; 4]] },
+        );
+    }
+
+    let mut buffer = vec![];
+
+    buffer.write_display_set(&display_set).unwrap();
+
+    let read = |limits| Cursor::new(&buffer).read_display_set_with(&limited(limits));
+    let defaults = Limits::default();
+
+    assert!(read(Limits { max_windows: 3, max_palettes: 3, max_objects: 3, ..defaults }).is_ok());
+    assert_eq!(
+        read(Limits { max_windows: 2, ..defaults }).unwrap_err().limit(),
+        Some(Limit::Windows),
+    );
+    assert_eq!(
+        read(Limits { max_palettes: 2, ..defaults }).unwrap_err().limit(),
+        Some(Limit::Palettes),
+    );
+    assert_eq!(
+        read(Limits { max_objects: 2, ..defaults }).unwrap_err().limit(),
+        Some(Limit::Objects),
+    );
+
+    // Each object fits on its own, but not all three together.
+    assert!(read(Limits { max_decoded_pixels: 12, ..defaults }).is_ok());
+    assert_eq!(
+        read(Limits { max_decoded_pixels: 11, ..defaults }).unwrap_err().limit(),
+        Some(Limit::DecodedPixels),
+    );
+
+    let data = encode(&[1; 4], 4, 1).unwrap().len() * 3;
+
+    assert!(read(Limits { max_object_data: data, ..defaults }).is_ok());
+    assert_eq!(
+        read(Limits { max_object_data: data - 1, ..defaults }).unwrap_err().limit(),
+        Some(Limit::ObjectData),
+    );
+}
+
+#[test]
+fn test_declared_object_data_is_limited() {
+
+    let mut buffer = vec![];
+
+    buffer.write_segment(&Segment::PresentationComposition(
+        PresentationCompositionSegment { width: 1920, height: 1080, ..Default::default() }
+    )).unwrap();
+
+    // First fragments that each declare as much data as a fragment can, without ever sending it.
+    for id in 0..256 {
+        buffer.write_segment(&Segment::ObjectDefinition(ObjectDefinitionSegment {
+            id: ObjectId(id),
+            sequence: Sequence::First,
+            header: Some(ObjectHeader { data_length: 16_777_211, width: 1, height: 1 }),
+            data: vec![0; 16],
+            ..Default::default()
+        })).unwrap();
+    }
+
+    let mut cursor = Cursor::new(&buffer);
+
+    assert_eq!(cursor.read_display_set().unwrap_err().limit(), Some(Limit::ObjectData));
+    // Only the second fragment was needed to tell.
+    assert_eq!(cursor.position(), 24 + 2 * 40);
+}
+
+#[test]
+fn test_large_object_is_split() {
+
+    let mut rng = thread_rng();
+    let mut display_set = DisplaySet {
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+
+    // Noise without index 0 takes a byte per pixel, so this is a little over 200 KB of RLE data.
+    let lines = (0..200)
+        .map(|_| (0..1_000).map(|_| rng.gen_range(1..=255)).collect::<Vec<u8>>())
+        .collect::<Vec<Vec<u8>>>();
+    let data = encode(&lines.concat(), 1_000, 200).unwrap();
+
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(3), version: 1 },
+        Object { width: 1_000, height: 200, lines, ..Default::default() },
+    );
+
+    let mut buffer = vec![];
+
+    buffer.write_display_set(&display_set).unwrap();
+
+    let mut cursor = Cursor::new(&buffer);
+    let mut fragments = vec![];
+
+    while (cursor.position() as usize) < buffer.len() {
+        if let Segment::ObjectDefinition(ods) = cursor.read_segment().unwrap() {
+            fragments.push(ods);
+        }
+    }
+
+    assert_eq!(
+        fragments.iter().map(|ods| (ods.sequence, ods.data.len())).collect::<Vec<_>>(),
+        vec![
+            (Sequence::First, MAX_FIRST_FRAGMENT_DATA),
+            (Sequence::Middle, MAX_FRAGMENT_DATA),
+            (Sequence::Middle, MAX_FRAGMENT_DATA),
+            (Sequence::Last, data.len() - MAX_FIRST_FRAGMENT_DATA - 2 * MAX_FRAGMENT_DATA),
+        ],
+    );
+    assert!(fragments.iter().all(|ods| (ods.id, ods.version) == (ObjectId(3), 1)));
+    assert_eq!(
+        fragments[0].header,
+        Some(ObjectHeader { data_length: data.len(), width: 1_000, height: 200 }),
+    );
+    assert!(fragments[1..].iter().all(|ods| ods.header.is_none()));
+    assert_eq!(
+        fragments.iter().flat_map(|ods| ods.data.iter().copied()).collect::<Vec<u8>>(),
+        data,
+    );
+    assert_eq!(Cursor::new(&buffer).read_display_set().unwrap(), display_set);
+}
+
+// The DTS of every segment in the buffer.
+fn segment_dts(buffer: &[u8]) -> Vec<u32> {
+
+    let mut cursor = Cursor::new(buffer);
+    let mut dts = vec![];
+    let options = ReadOptions { keep_unknown: true, ..Default::default() };
+
+    while (cursor.position() as usize) < buffer.len() {
+        dts.push(
+            match cursor.read_segment_with(&options).unwrap() {
+                Segment::PresentationComposition(pcs) => pcs.dts,
+                Segment::WindowDefinition(wds) => wds.dts,
+                Segment::PaletteDefinition(pds) => pds.dts,
+                Segment::ObjectDefinition(ods) => ods.dts,
+                Segment::End(es) => es.dts,
+                Segment::Unknown(us) => us.dts,
+            }
+        );
+    }
+
+    dts
+}
+
+#[test]
+fn test_segment_dts_round_trip() {
+
+    let mut rng = thread_rng();
+    let mut display_set = valid_display_set();
+    let lines = (0..100)
+        .map(|_| (0..1_000).map(|_| rng.gen_range(1..=255)).collect::<Vec<u8>>())
+        .collect::<Vec<Vec<u8>>>();
+
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(1), version: 0 },
+        Object { width: 1_000, height: 100, lines, ..Default::default() },
+    );
+    display_set.unknown_segments.push(UnknownSegment { kind: 0x99, data: vec![1] });
+
+    let mut buffer = vec![];
+
+    buffer.write_display_set(&display_set).unwrap();
+
+    // As discs have them: the PCS and WDS decode first, then each PDS and ODS fragment in turn,
+    // and the END once the last of them is done.
+    let mut start = 0;
+    let mut dts = 80_000;
+
+    while start < buffer.len() {
+        buffer[start + 6..start + 10].copy_from_slice(&u32::to_be_bytes(dts));
+        if buffer[start + 10] != 0x16 {
+            dts += 1_000;
+        }
+        start += 13 + u16::from_be_bytes([buffer[start + 11], buffer[start + 12]]) as usize;
+    }
+
+    let original = segment_dts(&buffer);
+
+    assert_eq!(original.len(), 8);
+
+    let options = ReadOptions { keep_unknown: true, ..Default::default() };
+    let mut display_set = Cursor::new(&buffer).read_display_set_with(&options).unwrap();
+
+    assert_eq!(display_set.dts, 80_000);
+    assert_eq!(
+        display_set.segment_dts,
+        SegmentDts {
+            windows: 0,
+            palettes: [(VersionedId { id: PaletteId(0), version: 0 }, 1_000)].into(),
+            objects: [
+                (VersionedId { id: ObjectId(0), version: 0 }, vec![2_000]),
+                (VersionedId { id: ObjectId(1), version: 0 }, vec![3_000, 4_000]),
+            ].into(),
+            unknown_segments: vec![5_000],
+            end: 6_000,
+        },
+    );
+
+    let mut rewritten = vec![];
+
+    rewritten.write_display_set(&display_set).unwrap();
+
+    assert_eq!(rewritten, buffer);
+
+    // Segments keep their distance from the display set as it moves.
+    display_set.pts += 90_000;
+    display_set.dts += 90_000;
+    rewritten.clear();
+    rewritten.write_display_set(&display_set).unwrap();
+
+    assert_eq!(
+        segment_dts(&rewritten),
+        original.iter().map(|dts| dts + 90_000).collect::<Vec<u32>>(),
+    );
+
+    // Recomputing DTS gives every segment the display set's.
+    recompute_dts(std::slice::from_mut(&mut display_set));
+    rewritten.clear();
+    rewritten.write_display_set(&display_set).unwrap();
+
+    assert!(display_set.segment_dts.is_empty());
+    assert_eq!(segment_dts(&rewritten), vec![display_set.dts; 8]);
+}
+
+// An object definition fragment of the given ID, carrying the RLE data of a 2x2 object.
+fn fragment(id: u16, sequence: Sequence, data: &[u8]) -> ObjectDefinitionSegment {
+    ObjectDefinitionSegment {
+        id: ObjectId(id),
+        sequence,
+        header: if sequence.is_first() {
+            Some(ObjectHeader { data_length: 8, width: 2, height: 2 })
+        } else {
+            None
+        },
+        data: data.to_vec(),
+        ..Default::default()
+    }
+}
+
+fn read_fragments(fragments: &[ObjectDefinitionSegment]) -> ReadResult<DisplaySet> {
+
+    let mut buffer = vec![];
+
+    buffer.write_segment(&Segment::PresentationComposition(
+        PresentationCompositionSegment { width: 1920, height: 1080, ..Default::default() }
+    )).unwrap();
+    for ods in fragments.iter() {
+        buffer.write_segment(&Segment::ObjectDefinition(ods.clone())).unwrap();
+    }
+    buffer.write_segment(&Segment::End(EndSegment::default())).unwrap();
+
+    Cursor::new(buffer).read_display_set()
+}
+
+// Two lines of 1 1, then 2 2.
+const DATA: [u8; 8] = [0x01, 0x01, 0x00, 0x00, 0x02, 0x02, 0x00, 0x00];
+
+fn object_lines(display_set: &DisplaySet, id: u16) -> Vec<Vec<u8>> {
+    display_set.objects[&VersionedId { id: ObjectId(id), version: 0 }].lines.clone()
+}
+
+#[test]
+fn test_single_fragment() {
+
+    let display_set = read_fragments(&[fragment(0, Sequence::Single, &DATA)]).unwrap();
+
+    assert_eq!(object_lines(&display_set, 0), vec![vec![1, 1], vec![2, 2]]);
+}
+
+#[test]
+fn test_two_fragments() {
+
+    let display_set = read_fragments(&[
+        fragment(0, Sequence::First, &DATA[..3]),
+        fragment(0, Sequence::Last, &DATA[3..]),
+    ]).unwrap();
+
+    assert_eq!(object_lines(&display_set, 0), vec![vec![1, 1], vec![2, 2]]);
+    assert_eq!(
+        display_set.objects[&VersionedId { id: ObjectId(0), version: 0 }].sequence,
+        Sequence::Single,
+    );
+}
+
+#[test]
+fn test_interleaved_fragments() {
+
+    let reversed = [0x02, 0x02, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00];
+    let display_set = read_fragments(&[
+        fragment(0, Sequence::First, &DATA[..2]),
+        fragment(1, Sequence::First, &reversed[..5]),
+        fragment(0, Sequence::Middle, &DATA[2..6]),
+        fragment(1, Sequence::Last, &reversed[5..]),
+        fragment(0, Sequence::Last, &DATA[6..]),
+    ]).unwrap();
+
+    assert_eq!(object_lines(&display_set, 0), vec![vec![1, 1], vec![2, 2]]);
+    assert_eq!(object_lines(&display_set, 1), vec![vec![2, 2], vec![1, 1]]);
+}
+
+#[test]
+fn test_bad_fragments() {
+
+    let cases = [
+        (
+            vec![fragment(0, Sequence::First, &DATA[..3]), fragment(0, Sequence::First, &DATA)],
+            "ObjectSequenceInterrupted",
+        ),
+        (
+            vec![fragment(0, Sequence::First, &DATA[..3]), fragment(0, Sequence::Single, &DATA)],
+            "ObjectSequenceInterrupted",
+        ),
+        (vec![fragment(0, Sequence::Last, &DATA)], "OrphanedObjectFragment"),
+        (
+            vec![fragment(0, Sequence::First, &DATA[..3]), fragment(1, Sequence::Last, &DATA[3..])],
+            "OrphanedObjectFragment",
+        ),
+        (
+            vec![fragment(0, Sequence::First, &DATA[..3]), fragment(0, Sequence::Last, &DATA[4..])],
+            "ObjectDataLengthMismatch",
+        ),
+        (
+            vec![fragment(0, Sequence::First, &DATA[..3]), fragment(0, Sequence::Middle, &DATA)],
+            "ObjectDataLengthMismatch",
+        ),
+        (
+            vec![
+                fragment(0, Sequence::First, &DATA[..3]),
+                fragment(0, Sequence::Middle, &DATA[3..]),
+            ],
+            "IncompleteObject",
+        ),
+    ];
+
+    for (fragments, error) in cases.iter() {
+        assert_eq!(format!("{:?}", read_fragments(fragments).unwrap_err()), *error);
+    }
+}
+
+#[test]
+fn test_first_fragment_without_header() {
+
+    let mut assembler = DisplaySetAssembler::new(&ReadOptions::default());
+
+    assembler.push(Segment::PresentationComposition(
+        PresentationCompositionSegment { width: 1920, height: 1080, ..Default::default() }
+    )).unwrap();
+
+    // The parser never produces this, but a hand-built segment can.
+    for sequence in [Sequence::Single, Sequence::First].iter() {
+        let ods = ObjectDefinitionSegment { header: None, ..fragment(0, *sequence, &DATA) };
+        assert_eq!(
+            format!("{:?}", assembler.push(Segment::ObjectDefinition(ods)).unwrap_err()),
+            "MissingObjectHeader",
+        );
+    }
+}
+
+// A 1920x1080 display set showing a 4x2 object in a matching window, with a complete palette.
+fn valid_display_set() -> DisplaySet {
+
+    let mut display_set = DisplaySet {
+        pts: 90_000,
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+    let mut palette = Palette::default();
+
+    palette.entries.insert(1, PaletteEntry::default());
+    display_set.windows.insert(WindowId(0), Window { x: 100, y: 900, width: 4, height: 2 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width: 4, height: 2, lines: vec![vec![1; 4]; 2], ..Default::default() },
+    );
+    display_set.composition.state = CompositionState::EpochStart;
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 100, y: 900, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+fn diagnose(display_set: &DisplaySet) -> Vec<Diagnostic> {
+
+    let mut state = EpochState::default();
+
+    state.apply(display_set);
+
+    display_set.validate(&state)
+}
+
+#[test]
+fn test_validate_valid() {
+    assert_eq!(diagnose(&valid_display_set()), vec![]);
+}
+
+#[test]
+fn test_validate_window_overlap() {
+
+    let mut display_set = valid_display_set();
+
+    display_set.windows.insert(WindowId(3), Window { x: 102, y: 901, width: 10, height: 10 });
+    display_set.windows.insert(WindowId(4), Window { x: 104, y: 900, width: 10, height: 10 });
+
+    assert_eq!(
+        diagnose(&display_set),
+        vec![
+            Diagnostic::WindowOverlap { pts: 90_000, window_ids: (WindowId(0), WindowId(3)) },
+            Diagnostic::WindowOverlap { pts: 90_000, window_ids: (WindowId(3), WindowId(4)) },
+        ],
+    );
+}
+
+#[test]
+fn test_validate_object_out_of_bounds() {
+
+    let mut display_set = valid_display_set();
+
+    display_set.windows.get_mut(&WindowId(0)).unwrap().x = 1917;
+    display_set.composition.objects.values_mut().next().unwrap().x = 1917;
+
+    assert_eq!(
+        diagnose(&display_set),
+        vec![
+            Diagnostic::ObjectOutOfBounds {
+                pts: 90_000, object_id: ObjectId(0), x: 1917, y: 900, width: 4, height: 2,
+            },
+        ],
+    );
+    assert_eq!(
+        diagnose(&display_set)[0].to_string(),
+        "object 0 at 1917,900 sized 4x2 extends past the canvas at 00:00:01.000",
+    );
+}
+
+#[test]
+fn test_validate_missing_references() {
+
+    let mut display_set = valid_display_set();
+
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(7), window_id: WindowId(2) },
+        CompositionObject::default(),
+    );
+
+    assert_eq!(
+        diagnose(&display_set),
+        vec![
+            Diagnostic::CompositionReferencesMissingWindow { pts: 90_000, window_id: WindowId(2) },
+            Diagnostic::CompositionReferencesMissingObject { pts: 90_000, object_id: ObjectId(7) },
+        ],
+    );
+}
+
+#[test]
+fn test_validate_palette_entry_missing() {
+
+    let mut display_set = valid_display_set();
+
+    display_set.objects.values_mut().next().unwrap().lines[1] = vec![1, 2, 3, 2];
+
+    assert_eq!(
+        diagnose(&display_set),
+        vec![
+            Diagnostic::PaletteEntryMissing {
+                pts: 90_000,
+                object_id: ObjectId(0),
+                indices: vec![2, 3],
+            },
+        ],
+    );
+}
+
+#[test]
+fn test_validate_object_larger_than_window() {
+
+    let mut display_set = valid_display_set();
+
+    display_set.windows.get_mut(&WindowId(0)).unwrap().height = 1;
+
+    assert_eq!(
+        diagnose(&display_set),
+        vec![
+            Diagnostic::ObjectLargerThanWindow {
+                pts: 90_000,
+                object_id: ObjectId(0),
+                window_id: WindowId(0),
+                width: 4,
+                height: 2,
+                window_width: 4,
+                window_height: 1,
+            },
+        ],
+    );
+
+    // Cropping the object down to the window is fine.
+    display_set.composition.objects.values_mut().next().unwrap().crop =
+        Some(Crop { x: 0, y: 0, width: 4, height: 1 });
+
+    assert_eq!(diagnose(&display_set), vec![]);
+}
+
+fn acquisition_point(pts: u32, number: u16) -> DisplaySet {
+
+    let mut display_set = valid_display_set();
+
+    display_set.pts = pts;
+    display_set.dts = pts - 1_000;
+    display_set.composition.number = number;
+    display_set.composition.state = CompositionState::AcquisitionPoint;
+
+    display_set
+}
+
+fn numbers(display_sets: &[DisplaySet]) -> Vec<(u32, u16)> {
+    display_sets.iter()
+        .map(|display_set| (display_set.pts, display_set.composition.number))
+        .collect()
+}
+
+#[test]
+fn test_dedup_display_sets() {
+
+    let mut changed = acquisition_point(450_000, 5);
+
+    changed.composition.objects.values_mut().next().unwrap().x += 1;
+
+    let mut display_sets = vec![
+        valid_display_set(),
+        acquisition_point(180_000, 1),
+        acquisition_point(270_000, 2),
+        acquisition_point(360_000, 4),
+        changed,
+        acquisition_point(540_000, 6),
+        acquisition_point(630_000, 7),
+        clear_display_set(&valid_display_set(), 720_000),
+    ];
+
+    display_sets[7].composition.number = 8;
+
+    // The gap between 2 and 4 is kept.
+    assert_eq!(dedup_display_sets(&mut display_sets), 3);
+    assert_eq!(
+        numbers(&display_sets),
+        vec![(90_000, 0), (180_000, 1), (450_000, 3), (540_000, 4), (720_000, 5)],
+    );
+}
+
+#[test]
+fn test_dedup_keeps_epoch_starts() {
+
+    let mut display_sets = vec![valid_display_set(), valid_display_set()];
+
+    display_sets[1].pts = 180_000;
+    display_sets[1].composition.number = 1;
+
+    assert_eq!(dedup_display_sets(&mut display_sets), 0);
+    assert_eq!(numbers(&display_sets), vec![(90_000, 0), (180_000, 1)]);
+}
+
+#[test]
+fn test_dedup_wraps_composition_numbers() {
+
+    let mut display_sets = vec![
+        acquisition_point(90_000, 65_535),
+        acquisition_point(180_000, 0),
+        acquisition_point(270_000, 1),
+    ];
+
+    display_sets[2].palette_update_id = Some(PaletteId(0));
+
+    assert_eq!(dedup_display_sets(&mut display_sets), 1);
+    assert_eq!(numbers(&display_sets), vec![(90_000, 65_535), (270_000, 0)]);
+}
+
+fn states(display_sets: &[DisplaySet]) -> Vec<(u16, CompositionState)> {
+    display_sets.iter()
+        .map(|display_set| (display_set.composition.number, display_set.composition.state))
+        .collect()
+}
+
+#[test]
+fn test_repair_epochs() {
+
+    let mut redrawn = valid_display_set();
+    let mut empty_epoch_start = clear_display_set(&valid_display_set(), 360_000);
+    let mut resized = acquisition_point(540_000, 0);
+    let mut moved = valid_display_set();
+
+    redrawn.pts = 450_000;
+    redrawn.objects.values_mut().next().unwrap().lines[0][0] = 0;
+    empty_epoch_start.composition.state = CompositionState::EpochStart;
+    resized.width = 1280;
+    resized.height = 720;
+    moved.width = 1280;
+    moved.height = 720;
+    moved.windows.get_mut(&WindowId(0)).unwrap().y = 600;
+    moved.composition.number = 1;
+
+    let mut display_sets = vec![
+        acquisition_point(90_000, 7),
+        valid_display_set(),
+        clear_display_set(&valid_display_set(), 270_000),
+        empty_epoch_start,
+        redrawn,
+        resized,
+        moved,
+    ];
+
+    display_sets[1].composition.number = 3;
+
+    assert_eq!(
+        repair_epochs(&mut display_sets),
+        EpochRepairs {
+            renumbered: 7,
+            promoted: 2,
+            demoted_to_acquisition_point: 1,
+            demoted_to_normal: 1,
+        },
+    );
+    assert_eq!(
+        states(&display_sets),
+        vec![
+            (0, CompositionState::EpochStart),
+            (1, CompositionState::AcquisitionPoint),
+            (2, CompositionState::Normal),
+            (3, CompositionState::Normal),
+            (4, CompositionState::EpochStart),
+            (5, CompositionState::EpochStart),
+            (6, CompositionState::EpochStart),
+        ],
+    );
+}
+
+#[test]
+fn test_repair_epochs_leaves_sound_stream_alone() {
+
+    let mut display_sets = vec![
+        valid_display_set(),
+        acquisition_point(180_000, 1),
+        clear_display_set(&acquisition_point(180_000, 1), 270_000),
+    ];
+    let original = display_sets.clone();
+
+    assert_eq!(repair_epochs(&mut display_sets), EpochRepairs::default());
+    assert_eq!(display_sets, original);
+}
+
+#[test]
+fn test_epoch_repairer_wraps_composition_numbers() {
+
+    let mut repairer = EpochRepairer::default();
+    let mut display_sets = (0..65_537)
+        .map(|number| acquisition_point(90_000, number as u16))
+        .collect::<Vec<DisplaySet>>();
+
+    display_sets[0].composition.state = CompositionState::EpochStart;
+
+    for display_set in display_sets.iter_mut() {
+        repairer.repair(display_set);
+    }
+
+    assert_eq!(repairer.repairs, EpochRepairs::default());
+    assert_eq!(display_sets[65_536].composition.number, 0);
+}
+
+#[test]
+fn test_palette_scale_luminance() {
+
+    let mut palette = Palette::default();
+
+    palette.entries.insert(0, PaletteEntry { y: 200, cr: 100, cb: 150, alpha: 0 });
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 64 });
+
+    let mut halved = palette.clone();
+    let mut dimmed = palette.clone();
+
+    halved.scale_luminance(0.5, ColorMatrix::Bt709, Range::Limited);
+    dimmed.scale_to_peak(50.0, ColorMatrix::Bt709, Range::Limited);
+
+    assert_eq!(halved, dimmed);
+    assert_eq!(halved.entries[&0], palette.entries[&0]);
+    assert_eq!(halved.entries[&1], PaletteEntry { y: 180, cr: 128, cb: 128, alpha: 64 });
+}
+
+#[test]
+fn test_palette_recolor_skips_transparent_entries() {
+
+    let mut palette = Palette::default();
+
+    palette.entries.insert(0, PaletteEntry { y: 81, cr: 240, cb: 90, alpha: 0 });
+    palette.entries.insert(1, PaletteEntry { y: 81, cr: 240, cb: 90, alpha: 255 });
+
+    let mut recolored = palette.clone();
+    let mut counted = 0;
+
+    recolored.recolor(180.0, 1.0, 1.0, ColorMatrix::Bt709, Range::Limited);
+    palette.clone().map_entries(|entry| {
+        counted += 1;
+        entry
+    });
+
+    assert_eq!(counted, 2);
+    assert_eq!(recolored.entries[&0], palette.entries[&0]);
+    assert!(recolored.entries[&1].cr < 128);
+}
+
+fn composed(pts: u32, state: CompositionState, windows: &[(u8, u16, u16)]) -> DisplaySet {
+
+    let mut display_set = DisplaySet { pts, width: 1920, height: 1080, ..Default::default() };
+
+    display_set.composition.state = state;
+
+    for &(id, width, height) in windows.iter() {
+        display_set.windows.insert(WindowId(id), Window { x: 0, y: 0, width, height });
+        display_set.objects.insert(
+            VersionedId { id: ObjectId(id as u16), version: 0 },
+            Object {
+                width,
+                height,
+                lines: vec![vec![0; width as usize]; height as usize],
+                ..Default::default()
+            },
+        );
+        display_set.composition.objects.insert(
+            Cid { object_id: ObjectId(id as u16), window_id: WindowId(id) },
+            CompositionObject { x: 0, y: 0, crop: None, forced: false },
+        );
+    }
+
+    display_set
+}
+
+fn scheduled_duration(display_sets: &[DisplaySet]) -> u32 {
+
+    let mut state = EpochState::default();
+
+    for display_set in display_sets.iter() {
+        state.apply(display_set);
+    }
+
+    decode_duration(display_sets.last().unwrap(), &state)
+}
+
+#[test]
+fn test_decode_duration() {
+
+    // Clearing the plane takes 467 ticks, decoding the object 102, and writing the window 5.
+    let epoch_start = composed(90_000, CompositionState::EpochStart, &[(0, 300, 60)]);
+
+    assert_eq!(scheduled_duration(std::slice::from_ref(&epoch_start)), 467 + 102 + 5);
+
+    // Clearing the windows takes 3 and 8 ticks, decoding the objects 57 and 180, and writing the
+    // windows 3 and 8.
+    let two_windows = composed(90_000, CompositionState::Normal, &[(0, 200, 50), (1, 400, 80)]);
+
+    assert_eq!(scheduled_duration(&[two_windows]), 3 + 8 + 57 + 3 + 180 + 8);
+
+    // A palette update decodes nothing, and a clear only clears the window.
+    let mut palette_update = epoch_start.clone();
+
+    palette_update.composition.state = CompositionState::Normal;
+    palette_update.palette_update_id = Some(PaletteId(0));
+    palette_update.objects.clear();
+    palette_update.windows.clear();
+
+    assert_eq!(scheduled_duration(&[epoch_start.clone(), palette_update]), 5 + 5);
+    assert_eq!(
+        scheduled_duration(&[epoch_start.clone(), clear_display_set(&epoch_start, 180_000)]),
+        5,
+    );
+}
+
+#[test]
+fn test_recompute_dts() {
+
+    let epoch_start = composed(90_000, CompositionState::EpochStart, &[(0, 300, 60)]);
+    let mut display_sets = vec![
+        epoch_start.clone(),
+        clear_display_set(&epoch_start, 180_000),
+        // Too soon after the clear to start decoding once it is done.
+        composed(180_003, CompositionState::EpochStart, &[(0, 300, 60)]),
+        // Too early to be given any decoding time at all.
+        composed(100, CompositionState::EpochStart, &[(0, 300, 60)]),
+    ];
+
+    assert_eq!(recompute_dts(&mut display_sets), 4);
+    assert_eq!(
+        display_sets.iter().map(|display_set| display_set.dts).collect::<Vec<u32>>(),
+        vec![90_000 - 574, 180_000 - 5, 180_000 - 5, 100],
+    );
+    assert_eq!(recompute_dts(&mut display_sets), 0);
+}
+
+// Three epochs, each shown and then cleared, with the offset each display set starts at.
+fn epochs_stream() -> (Vec<u8>, Vec<usize>) {
+
+    let mut buffer = vec![];
+    let mut offsets = vec![];
+
+    for epoch in 0..3 {
+
+        let shown = DisplaySet { pts: (epoch + 1) * 90_000, ..valid_display_set() };
+
+        offsets.push(buffer.len());
+        buffer.write_display_set(&shown).unwrap();
+        offsets.push(buffer.len());
+        buffer.write_display_set(&clear_display_set(&shown, shown.pts + 45_000)).unwrap();
+    }
+
+    (buffer, offsets)
+}
+
+fn read_lossy(stream: &[u8]) -> (Vec<u32>, u64, usize) {
+
+    let mut reader = LossyReader::new(Cursor::new(stream));
+    let pts = reader.display_sets_lossy(&ReadOptions::default())
+        .map(|display_set| display_set.unwrap().pts)
+        .collect();
+
+    (pts, reader.skipped(), reader.dropped())
+}
+
+#[test]
+fn test_lossy_read_of_sound_stream() {
+
+    let (stream, _) = epochs_stream();
+
+    assert_eq!(
+        read_lossy(&stream),
+        (vec![90_000, 135_000, 180_000, 225_000, 270_000, 315_000], 0, 0),
+    );
+}
+
+#[test]
+fn test_lossy_read_recovers_from_corruption() {
+
+    let (stream, offsets) = epochs_stream();
+    // The second epoch start's PCS magic, the first clear's PCS kind, and the last epoch start's
+    // END magic.
+    let end_offset = offsets[5] - 13;
+    let cases = [
+        (offsets[2], vec![90_000, 135_000, 270_000, 315_000], 32, 2),
+        (offsets[1] + 10, vec![90_000, 180_000, 225_000, 270_000, 315_000], 24, 1),
+        (end_offset, vec![90_000, 135_000, 180_000, 225_000], 13, 2),
+    ];
+
+    for (offset, pts, skipped, dropped) in cases.iter() {
+
+        let mut corrupt = stream.clone();
+
+        corrupt[*offset] ^= 0xFF;
+
+        assert_eq!(read_lossy(&corrupt), (pts.clone(), *skipped, *dropped));
+    }
+}
+
+#[test]
+fn test_lossy_read_skips_junk() {
+
+    let (stream, offsets) = epochs_stream();
+    let junk = [0xDE, 0xAD, 0xBE, 0xEF, 0x50];
+    let corrupt = [&stream[..offsets[4]], &junk, &stream[offsets[4]..]].concat();
+
+    assert_eq!(read_lossy(&corrupt).0, read_lossy(&stream).0);
+    assert_eq!(read_lossy(&corrupt).1, junk.len() as u64);
+}
+
+// The segments of a 4x2 object being shown and then faded out over four palette updates, each of
+// which is only a PCS, a PDS, and an END.
+fn fade_stream() -> Vec<u8> {
+
+    let mut buffer = vec![];
+
+    buffer.write_display_set(&valid_display_set()).unwrap();
+
+    for step in 1..=4u8 {
+
+        let pts = 90_000 + step as u32 * 1_800;
+
+        buffer.write_segment(&Segment::PresentationComposition(
+            PresentationCompositionSegment {
+                pts,
+                width: 1920,
+                height: 1080,
+                composition_number: step as u16,
+                composition_state: CompositionState::Normal,
+                palette_update_id: Some(PaletteId(0)),
+                composition_objects: vec![SegmentCompositionObject {
+                    object_id: ObjectId(0),
+                    window_id: WindowId(0),
+                    x: 100,
+                    y: 900,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        )).unwrap();
+        buffer.write_segment(&Segment::PaletteDefinition(
+            PaletteDefinitionSegment {
+                pts,
+                id: PaletteId(0),
+                version: step,
+                entries: vec![SegmentPaletteEntry {
+                    id: 1,
+                    alpha: 255 - step * 63,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        )).unwrap();
+        buffer.write_segment(&Segment::End(EndSegment { pts, ..Default::default() })).unwrap();
+    }
+
+    buffer
+}
+
+#[test]
+fn test_palette_updates_cycle_byte_identically() {
+
+    let stream = fade_stream();
+    let mut cursor = Cursor::new(&stream);
+    let mut display_sets = vec![];
+
+    while (cursor.position() as usize) < stream.len() {
+        display_sets.push(cursor.read_display_set().unwrap());
+    }
+
+    assert_eq!(display_sets.len(), 5);
+    assert!(!display_sets[0].is_palette_update_only());
+    assert!(display_sets[1..].iter().all(|display_set| display_set.is_palette_update_only()));
+
+    let mut rewritten = vec![];
+
+    for display_set in display_sets.iter() {
+        rewritten.write_display_set(display_set).unwrap();
+    }
+
+    assert_eq!(rewritten, stream);
+}
+
+#[test]
+fn test_normal_case_may_refer_back_to_objects() {
+
+    let shown = valid_display_set();
+    let mut repeated = DisplaySet { pts: 180_000, ..shown.clone() };
+    let mut buffer = vec![];
+
+    repeated.objects.clear();
+    repeated.composition.state = CompositionState::Normal;
+    buffer.write_display_set(&shown).unwrap();
+    buffer.write_display_set(&repeated).unwrap();
+    repeated.composition.state = CompositionState::AcquisitionPoint;
+    buffer.write_display_set(&repeated).unwrap();
+
+    let mut cursor = Cursor::new(buffer);
+
+    assert_eq!(cursor.read_display_set().unwrap(), shown);
+    assert_eq!(cursor.read_display_set().unwrap().composition.objects, shown.composition.objects);
+    assert!(matches!(
+        cursor.read_display_set(),
+        Err(ReadError::CompositionReferencesUnknownObjectId),
+    ));
+}
+
+#[test]
+fn test_share_objects() {
+
+    let mut changed = acquisition_point(360_000, 3);
+    let mut object = changed.objects.values().next().unwrap().clone();
+
+    object.lines[0][0] = 0;
+    changed.objects.clear();
+    changed.objects.insert(VersionedId { id: ObjectId(0), version: 1 }, object);
+
+    let mut epoch = vec![
+        valid_display_set(),
+        acquisition_point(180_000, 1),
+        clear_display_set(&valid_display_set(), 225_000),
+        acquisition_point(270_000, 2),
+        changed,
+        acquisition_point(450_000, 4),
+        // A new epoch starts with nothing to share.
+        DisplaySet { pts: 540_000, ..valid_display_set() },
+    ];
+    let written = |epoch: &[DisplaySet]| {
+        let mut buffer = vec![];
+        for display_set in epoch.iter() {
+            buffer.write_display_set(display_set).unwrap();
+        }
+        buffer
+    };
+    let before = written(&epoch).len() as u64;
+    let shared = share_objects(&mut epoch);
+    let after = written(&epoch);
+
+    assert_eq!(
+        shared,
+        SharedObjects { dropped: 2, demoted: 2, bytes: before - after.len() as u64 },
+    );
+    assert!(shared.bytes > 0);
+    assert_eq!(
+        epoch.iter()
+            .map(|display_set| (display_set.composition.state, display_set.objects.len()))
+            .collect::<Vec<(CompositionState, usize)>>(),
+        vec![
+            (CompositionState::EpochStart, 1),
+            (CompositionState::Normal, 0),
+            (CompositionState::Normal, 0),
+            (CompositionState::Normal, 0),
+            (CompositionState::AcquisitionPoint, 1),
+            (CompositionState::AcquisitionPoint, 1),
+            (CompositionState::EpochStart, 1),
+        ],
+    );
+
+    // Players see the same thing as before.
+    let mut cursor = Cursor::new(after);
+    let read = (0..epoch.len())
+        .map(|_| cursor.read_display_set().unwrap())
+        .collect::<Vec<DisplaySet>>();
+
+    for epoch in group_epochs(read).unwrap() {
+        epoch.check().unwrap();
+    }
+}
+
+#[test]
+fn test_share_objects_of_fixture() {
+
+    let input = include_bytes!("../../../test-data/repeated-object.sup");
+    let mut display_sets = Cursor::new(&input[..]).display_sets(&ReadOptions::default())
+        .collect::<ReadResult<Vec<DisplaySet>>>()
+        .unwrap();
+    let shared = share_objects(&mut display_sets);
+    let mut output = vec![];
+
+    for display_set in display_sets.iter() {
+        output.write_display_set(display_set).unwrap();
+    }
+
+    assert_eq!(
+        shared,
+        SharedObjects { dropped: 2, demoted: 2, bytes: (input.len() - output.len()) as u64 },
+    );
+    assert_eq!(
+        display_sets.iter()
+            .map(|display_set| display_set.objects.len())
+            .collect::<Vec<usize>>(),
+        [1, 0, 0, 0, 1, 0],
+    );
+
+    let read = Cursor::new(&output).display_sets(&ReadOptions::default())
+        .collect::<ReadResult<Vec<DisplaySet>>>()
+        .unwrap();
+
+    assert_eq!(read, display_sets);
+
+    for epoch in group_epochs(read).unwrap() {
+        epoch.check().unwrap();
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip() {
+
+    let mut display_set = valid_display_set();
+
+    display_set.segment_dts.objects.insert(VersionedId { id: ObjectId(0), version: 0 }, vec![9]);
+
+    let json = serde_json::to_string(&display_set).unwrap();
+
+    // Maps keyed by versioned IDs or CIDs are lists of pairs, and the rest are maps.
+    assert!(json.contains("\"palettes\":[[{\"id\":0,\"version\":0},{\"entries\":{\"1\":"));
+    assert!(json.contains("\"objects\":[[{\"object_id\":0,\"window_id\":0},{\"x\":100,"));
+    assert!(json.contains("\"objects\":[[{\"id\":0,\"version\":0},[9]]]"));
+    assert!(json.contains("\"frame_rate\":16,"));
+    assert_eq!(serde_json::from_str::<DisplaySet>(&json).unwrap(), display_set);
+    assert!(json.contains(",\"warnings\":[]"));
+    assert_eq!(
+        serde_json::from_str::<DisplaySet>(&json.replace(",\"warnings\":[]", "")).unwrap(),
+        display_set,
+    );
+
+    let mut buffer = vec![];
+
+    buffer.write_display_set(&display_set).unwrap();
+
+    let segments = Cursor::new(&buffer).segments(&ReadOptions::default())
+        .collect::<Result<Vec<Segment>, SegmentReadError>>()
+        .unwrap();
+    let json = serde_json::to_string(&segments).unwrap();
+
+    assert_eq!(serde_json::from_str::<Vec<Segment>>(&json).unwrap(), segments);
+}
+
+#[test]
+fn test_objects_deduplicate_and_debug_briefly() {
+
+    let object = |value: u8| Object {
+        width: 1_000,
+        height: 80,
+        lines: vec![vec![value; 1_000]; 80],
+        ..Default::default()
+    };
+    let objects = vec![object(1), object(2), object(1)].into_iter().collect::<HashSet<Object>>();
+    let line = format!("1000 bytes [{}..]", "01 ".repeat(16));
+
+    assert_eq!(objects.len(), 2);
+    // Only the first four lines are shown, and only the start of each.
+    assert_eq!(
+        format!("{:?}", object(1)),
+        format!(
+            "Object {{ width: 1000, height: 80, sequence: Single, lines: [{}, ..] }}",
+            vec![line; 4].join(", "),
+        ),
+    );
+}
+
+#[test]
+fn test_content_hash() {
+
+    let object = Object { width: 2, height: 1, lines: vec![vec![1, 3]], ..Default::default() };
+    let mut palette = Palette::default();
+
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+
+    // The hash is documented, so it must not change from one build to the next.
+    assert_eq!(object.content_hash(), 0x8F02_3E86_AFC0_12BE);
+    assert_eq!(palette.content_hash(), 0x0B43_3ABD_4FD5_2096);
+    assert_eq!(Palette::default().content_hash(), 0xCBF2_9CE4_8422_2325);
+
+    // What an object or palette is defined as is all that counts.
+    let mut display_set = DisplaySet::default();
+
+    display_set.objects.insert(VersionedId { id: ObjectId(0), version: 0 }, object.clone());
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(3), version: 7 },
+        Object { sequence: Sequence::First, ..object.clone() },
+    );
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette.clone());
+    display_set.palettes.insert(VersionedId { id: PaletteId(1), version: 2 }, palette.clone());
+
+    assert!(
+        display_set.objects.values().all(|other| other.content_hash() == object.content_hash())
+    );
+    assert!(
+        display_set.palettes.values().all(|other| other.content_hash() == palette.content_hash())
+    );
+
+    // Anything drawn differently hashes differently, even with the same pixels in all.
+    let reshaped = Object { width: 1, height: 2, lines: vec![vec![1], vec![3]], ..object.clone() };
+    let ragged = Object { lines: vec![vec![1, 3], vec![]], ..object.clone() };
+
+    assert_ne!(reshaped.content_hash(), object.content_hash());
+    assert_ne!(ragged.content_hash(), object.content_hash());
+
+    let hash = palette.content_hash();
+
+    palette.entries.get_mut(&1).unwrap().alpha = 254;
+
+    assert_ne!(palette.content_hash(), hash);
+}
+
+#[test]
+fn test_stream_readers_note_resizing_within_epochs() {
+
+    let shown = valid_display_set();
+    let resized = DisplaySet {
+        width: 1280,
+        height: 720,
+        ..clear_display_set(&shown, 135_000)
+    };
+    let restarted = DisplaySet { pts: 180_000, width: 1280, height: 720, ..valid_display_set() };
+    let options = ReadOptions::default();
+    let mut buffer = vec![];
+
+    for display_set in [&shown, &resized, &restarted].iter() {
+        buffer.write_display_set(display_set).unwrap();
+    }
+
+    let expected = vec![
+        vec![],
+        vec![ReadWarning::ResizedMidEpoch { width: 1920, height: 1080 }],
+        vec![],
+    ];
+    let iterated = Cursor::new(&buffer).display_sets(&options)
+        .map(|display_set| display_set.unwrap().warnings.0)
+        .collect::<Vec<Vec<ReadWarning>>>();
+
+    assert_eq!(iterated, expected);
+
+    let lossy = LossyReader::new(Cursor::new(&buffer)).display_sets_lossy(&options)
+        .map(|display_set| display_set.unwrap().warnings.0)
+        .collect::<Vec<Vec<ReadWarning>>>();
+
+    assert_eq!(lossy, expected);
+
+
+    // Reading a display set on its own, nothing is known of the one before it.
+    let mut cursor = Cursor::new(&buffer);
+
+    cursor.read_display_set().unwrap();
+    assert!(cursor.read_display_set().unwrap().warnings.is_empty());
+}
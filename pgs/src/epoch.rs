@@ -0,0 +1,291 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    displayset::{
+        DisplaySet,
+        Object,
+        Palette,
+        ReadDisplaySetExt,
+        ReadError as DisplaySetReadError,
+        Window,
+        WriteDisplaySetExt,
+        WriteError as DisplaySetWriteError,
+    },
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    segment::{CompositionState, ReadError as SegmentReadError, ReadOptions},
+    timeline::EpochState,
+};
+use std::{
+    collections::BTreeMap,
+    io::{ErrorKind, Read, Write},
+};
+use thiserror::Error as ThisError;
+
+pub type EpochResult<T> = Result<T, EpochError>;
+
+#[derive(ThisError, Debug)]
+pub enum EpochError {
+    #[error("display set read error")]
+    ReadError {
+        #[from]
+        source: DisplaySetReadError,
+    },
+    #[error("display set write error")]
+    WriteError {
+        #[from]
+        source: DisplaySetWriteError,
+    },
+    #[error("epoch does not begin with an epoch start")]
+    MissingEpochStart,
+    #[error("epoch start within an epoch")]
+    UnexpectedEpochStart,
+    #[error("display set {index} redefines object {vid} with different content")]
+    ConflictingObject {
+        index: usize,
+        vid: VersionedId<ObjectId>,
+    },
+    #[error("display set {index} redefines palette {vid} with different content")]
+    ConflictingPalette {
+        index: usize,
+        vid: VersionedId<PaletteId>,
+    },
+    #[error("display set {index} composes object {object_id}, which the epoch never defines")]
+    UndefinedObject {
+        index: usize,
+        object_id: ObjectId,
+    },
+    #[error("display set {index} composes into window {window_id}, which the epoch never defines")]
+    UndefinedWindow {
+        index: usize,
+        window_id: WindowId,
+    },
+    #[error("display set {index} updates to palette {palette_id}, which the epoch never defines")]
+    UndefinedPalette {
+        index: usize,
+        palette_id: PaletteId,
+    },
+}
+
+// An epoch start and the acquisition points and normal cases that follow it, up to the next epoch
+// start. Later display sets can refer back to whatever an earlier one in the same epoch defined.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Epoch {
+    display_sets: Vec<DisplaySet>,
+}
+
+impl Epoch {
+
+    pub fn new(start: DisplaySet) -> EpochResult<Self> {
+
+        if start.composition.state != CompositionState::EpochStart {
+            return Err(EpochError::MissingEpochStart)
+        }
+
+        Ok(Self { display_sets: vec![start] })
+    }
+
+    pub fn push(&mut self, display_set: DisplaySet) -> EpochResult<()> {
+
+        if display_set.composition.state == CompositionState::EpochStart {
+            return Err(EpochError::UnexpectedEpochStart)
+        }
+
+        self.display_sets.push(display_set);
+
+        Ok(())
+    }
+
+    pub fn start(&self) -> &DisplaySet {
+        &self.display_sets[0]
+    }
+
+    pub fn display_sets(&self) -> &[DisplaySet] {
+        &self.display_sets
+    }
+
+    pub fn into_display_sets(self) -> Vec<DisplaySet> {
+        self.display_sets
+    }
+
+    // The definition of an object in effect at the display set with the given index, which is
+    // the one made last by that display set or any before it.
+    pub fn object_at(&self, index: usize, object_id: ObjectId) -> Option<&Object> {
+        self.display_sets[..=index].iter()
+            .rev()
+            .find_map(|display_set| {
+                display_set.objects.iter()
+                    .rev()
+                    .find(|(vid, _)| vid.id == object_id)
+                    .map(|(_, object)| object)
+            })
+    }
+
+    pub fn palette_at(&self, index: usize, palette_id: PaletteId) -> Option<&Palette> {
+        self.display_sets[..=index].iter()
+            .rev()
+            .find_map(|display_set| {
+                display_set.palettes.iter()
+                    .rev()
+                    .find(|(vid, _)| vid.id == palette_id)
+                    .map(|(_, palette)| palette)
+            })
+    }
+
+    pub fn window_at(&self, index: usize, window_id: WindowId) -> Option<&Window> {
+        self.display_sets[..=index].iter()
+            .rev()
+            .find_map(|display_set| display_set.windows.get(&window_id))
+    }
+
+    // Layers every display set up to and including the one with the given index over the epoch
+    // start. Fails on the first display set that defines an object or palette version again with
+    // different content, or that refers to something the epoch has not defined by then.
+    pub fn state_at(&self, index: usize) -> EpochResult<EpochState> {
+
+        let mut state = EpochState::default();
+        let mut objects = BTreeMap::<VersionedId<ObjectId>, &Object>::new();
+        let mut palettes = BTreeMap::<VersionedId<PaletteId>, &Palette>::new();
+
+        for (index, display_set) in self.display_sets[..=index].iter().enumerate() {
+
+            for (&vid, object) in display_set.objects.iter() {
+                if objects.insert(vid, object).is_some_and(|earlier| earlier != object) {
+                    return Err(EpochError::ConflictingObject { index, vid })
+                }
+            }
+            for (&vid, palette) in display_set.palettes.iter() {
+                if palettes.insert(vid, palette).is_some_and(|earlier| earlier != palette) {
+                    return Err(EpochError::ConflictingPalette { index, vid })
+                }
+            }
+
+            state.apply(display_set);
+
+            for cid in display_set.composition.objects.keys() {
+                if !state.objects.contains_key(&cid.object_id) {
+                    return Err(EpochError::UndefinedObject { index, object_id: cid.object_id })
+                }
+                if !state.windows.contains_key(&cid.window_id) {
+                    return Err(EpochError::UndefinedWindow { index, window_id: cid.window_id })
+                }
+            }
+            if let Some(palette_id) = display_set.palette_update_id {
+                if !state.palettes.contains_key(&palette_id) {
+                    return Err(EpochError::UndefinedPalette { index, palette_id })
+                }
+            }
+        }
+
+        Ok(state)
+    }
+
+    // Resolves the whole epoch; see state_at.
+    pub fn check(&self) -> EpochResult<()> {
+        self.state_at(self.display_sets.len() - 1).map(|_| ())
+    }
+}
+
+// Splits display sets into epochs at each epoch start. The first must be one.
+pub fn group_epochs(
+    display_sets: impl IntoIterator<Item = DisplaySet>,
+) -> EpochResult<Vec<Epoch>> {
+
+    let mut epochs = Vec::<Epoch>::new();
+
+    for display_set in display_sets {
+        match epochs.last_mut() {
+            Some(epoch) if display_set.composition.state != CompositionState::EpochStart => {
+                epoch.push(display_set)?
+            }
+            _ => epochs.push(Epoch::new(display_set)?),
+        }
+    }
+
+    Ok(epochs)
+}
+
+// Reads whole epochs, holding on to each epoch start that ends one until the next is read.
+pub struct EpochReader<R: Read> {
+    inner: R,
+    next: Option<DisplaySet>,
+}
+
+impl<R: Read> EpochReader<R> {
+
+    pub fn new(inner: R) -> Self {
+        Self { inner, next: None }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+pub trait ReadEpochExt {
+    fn read_epoch(&mut self) -> EpochResult<Epoch>;
+    fn read_epoch_with(&mut self, options: &ReadOptions) -> EpochResult<Epoch>;
+}
+
+impl<R: Read> ReadEpochExt for EpochReader<R> {
+
+    fn read_epoch(&mut self) -> EpochResult<Epoch> {
+        self.read_epoch_with(&ReadOptions::default())
+    }
+
+    // A stream that does not begin with an epoch start fails once for each display set before
+    // the first one. The end of the stream ends the last epoch, and is only returned as an error
+    // once that epoch has been. Epochs are not checked; see Epoch::check.
+    fn read_epoch_with(&mut self, options: &ReadOptions) -> EpochResult<Epoch> {
+
+        let start = match self.next.take() {
+            Some(display_set) => display_set,
+            None => self.inner.read_display_set_with(options)?,
+        };
+        let mut epoch = Epoch::new(start)?;
+
+        loop {
+
+            let display_set = match self.inner.read_display_set_with(options) {
+                Ok(display_set) => display_set,
+                Err(DisplaySetReadError::SegmentError {
+                    source: SegmentReadError::IoError { source },
+                }) if source.kind() == ErrorKind::UnexpectedEof => return Ok(epoch),
+                Err(err) => return Err(err.into()),
+            };
+
+            if display_set.composition.state == CompositionState::EpochStart {
+                self.next = Some(display_set);
+                return Ok(epoch)
+            }
+
+            epoch.push(display_set)?;
+        }
+    }
+}
+
+pub trait WriteEpochExt {
+    fn write_epoch(&mut self, epoch: &Epoch) -> EpochResult<()>;
+}
+
+impl<T: Write> WriteEpochExt for T {
+
+    fn write_epoch(&mut self, epoch: &Epoch) -> EpochResult<()> {
+
+        for display_set in epoch.display_sets.iter() {
+            self.write_display_set(display_set)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::{
+    *,
+    super::displayset::{clear_display_set, Cid, CompositionObject, PaletteEntry},
+};
+use std::io::Cursor;
+
+fn shown_display_set(pts: u32) -> DisplaySet {
+
+    let mut display_set = DisplaySet { pts, width: 1920, height: 1080, ..Default::default() };
+    let mut palette = Palette::default();
+
+    palette.entries.insert(1, PaletteEntry::default());
+    display_set.windows.insert(WindowId(0), Window { x: 100, y: 900, width: 4, height: 2 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width: 4, height: 2, lines: vec![vec![1; 4]; 2], ..Default::default() },
+    );
+    display_set.composition.state = CompositionState::EpochStart;
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 100, y: 900, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+// Shows the epoch's object again, redefined with a new version.
+fn redefined_display_set(shown: &DisplaySet, pts: u32) -> DisplaySet {
+
+    let mut display_set = clear_display_set(shown, pts);
+
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 1 },
+        Object { width: 2, height: 2, lines: vec![vec![1; 2]; 2], ..Default::default() },
+    );
+    display_set.composition.objects = shown.composition.objects.clone();
+
+    display_set
+}
+
+fn epoch() -> Epoch {
+
+    let shown = shown_display_set(90_000);
+    let mut epoch = Epoch::new(shown.clone()).unwrap();
+
+    epoch.push(redefined_display_set(&shown, 135_000)).unwrap();
+    epoch.push(clear_display_set(&shown, 180_000)).unwrap();
+
+    epoch
+}
+
+#[test]
+fn test_epoch_must_start_with_epoch_start() {
+
+    let shown = shown_display_set(90_000);
+    let mut epoch = Epoch::new(shown.clone()).unwrap();
+
+    assert!(matches!(
+        Epoch::new(clear_display_set(&shown, 180_000)),
+        Err(EpochError::MissingEpochStart),
+    ));
+    assert!(matches!(epoch.push(shown), Err(EpochError::UnexpectedEpochStart)));
+}
+
+#[test]
+fn test_later_versions_override_earlier_ones() {
+
+    let epoch = epoch();
+
+    assert_eq!(epoch.object_at(0, ObjectId(0)).unwrap().width, 4);
+    assert_eq!(epoch.object_at(1, ObjectId(0)).unwrap().width, 2);
+    assert_eq!(epoch.object_at(2, ObjectId(0)).unwrap().width, 2);
+    assert_eq!(epoch.object_at(2, ObjectId(1)), None);
+    assert_eq!(epoch.window_at(2, WindowId(0)).unwrap().x, 100);
+    assert!(epoch.palette_at(2, PaletteId(0)).is_some());
+
+    let state = epoch.state_at(1).unwrap();
+
+    assert_eq!(state.objects[&ObjectId(0)].width, 2);
+    assert_eq!(state.display_sets, 2);
+    assert!(epoch.check().is_ok());
+}
+
+#[test]
+fn test_undefined_references() {
+
+    let shown = shown_display_set(90_000);
+    let mut epoch = Epoch::new(shown.clone()).unwrap();
+    let mut display_set = redefined_display_set(&shown, 135_000);
+
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(1), window_id: WindowId(0) },
+        CompositionObject::default(),
+    );
+    epoch.push(display_set).unwrap();
+
+    assert!(matches!(
+        epoch.check(),
+        Err(EpochError::UndefinedObject { index: 1, object_id: ObjectId(1) }),
+    ));
+    assert!(epoch.state_at(0).is_ok());
+
+    let mut epoch = Epoch::new(shown.clone()).unwrap();
+    let mut display_set = clear_display_set(&shown, 135_000);
+
+    display_set.palette_update_id = Some(PaletteId(1));
+    epoch.push(display_set).unwrap();
+
+    assert!(matches!(
+        epoch.check(),
+        Err(EpochError::UndefinedPalette { index: 1, palette_id: PaletteId(1) }),
+    ));
+}
+
+#[test]
+fn test_conflicting_definitions() {
+
+    let shown = shown_display_set(90_000);
+    let mut epoch = Epoch::new(shown.clone()).unwrap();
+    let mut display_set = redefined_display_set(&shown, 135_000);
+
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width: 4, height: 2, lines: vec![vec![0; 4]; 2], ..Default::default() },
+    );
+    epoch.push(display_set).unwrap();
+
+    assert!(matches!(
+        epoch.check(),
+        Err(EpochError::ConflictingObject { index: 1, vid })
+            if vid == VersionedId { id: ObjectId(0), version: 0 },
+    ));
+
+    let mut epoch = Epoch::new(shown.clone()).unwrap();
+    let mut display_set = shown.clone();
+
+    // Defining the same version again the same way is merely redundant.
+    display_set.composition.state = CompositionState::AcquisitionPoint;
+    epoch.push(display_set).unwrap();
+
+    assert!(epoch.check().is_ok());
+}
+
+#[test]
+fn test_group_epochs() {
+
+    let display_sets = epoch().into_display_sets();
+    let epochs = group_epochs(display_sets.iter().chain(display_sets.iter()).cloned()).unwrap();
+
+    assert_eq!(epochs, vec![epoch(), epoch()]);
+    assert!(matches!(
+        group_epochs(display_sets.into_iter().skip(1)),
+        Err(EpochError::MissingEpochStart),
+    ));
+}
+
+#[test]
+fn test_epoch_cycle() {
+
+    let mut buffer = vec![];
+    let second = Epoch::new(shown_display_set(270_000)).unwrap();
+
+    buffer.write_epoch(&epoch()).unwrap();
+    buffer.write_epoch(&second).unwrap();
+
+    let mut reader = EpochReader::new(Cursor::new(buffer));
+
+    assert_eq!(reader.read_epoch().unwrap(), epoch());
+    assert_eq!(reader.read_epoch().unwrap(), second);
+    assert!(matches!(reader.read_epoch(), Err(EpochError::ReadError { .. })));
+    assert_eq!(reader.get_ref().position(), reader.get_ref().get_ref().len() as u64);
+}
+
+#[test]
+fn test_read_epoch_without_epoch_start() {
+
+    let mut buffer = vec![];
+    let display_sets = epoch().into_display_sets();
+
+    for display_set in display_sets[1..].iter().chain(display_sets.iter()) {
+        buffer.write_display_set(display_set).unwrap();
+    }
+
+    let mut reader = EpochReader::new(Cursor::new(buffer));
+
+    assert!(matches!(reader.read_epoch(), Err(EpochError::MissingEpochStart)));
+    assert!(matches!(reader.read_epoch(), Err(EpochError::MissingEpochStart)));
+    assert_eq!(reader.read_epoch().unwrap(), epoch());
+}
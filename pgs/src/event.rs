@@ -0,0 +1,347 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    displayset::{Composition, DisplaySet, Object, Palette, Window},
+    id::{ObjectId, WindowId},
+    segment::CompositionState,
+    timeline::EpochState,
+};
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    fmt::{Display, Formatter, Result as FmtResult},
+    str::FromStr,
+};
+
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+// Identifies an event, meaning a display set that shows at least one composition object, by what
+// its epoch draws rather than by where or when. Its text form is the epoch hash as 16 lowercase
+// hexadecimal digits, a dash, and the event's zero-based ordinal among the events of its epoch,
+// such as "9f3a0c5e71d2b486-3".
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct EventId {
+    pub epoch_hash: u64,
+    pub ordinal: u32,
+}
+
+impl Display for EventId {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{:016x}-{}", self.epoch_hash, self.ordinal)
+    }
+}
+
+impl FromStr for EventId {
+
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, ()> {
+
+        let (hash, ordinal) = value.split_once('-').ok_or(())?;
+
+        if hash.len() != 16 {
+            return Err(())
+        }
+
+        Ok(
+            EventId {
+                epoch_hash: u64::from_str_radix(hash, 16).map_err(|_| ())?,
+                ordinal: ordinal.parse().map_err(|_| ())?,
+            }
+        )
+    }
+}
+
+// 64-bit FNV-1a over every object definition of the epoch, taken display set by display set and
+// then in ascending object ID and version order. Each object contributes its ID, width, and
+// height as big-endian 16-bit values, followed by each of its lines as a big-endian 32-bit length
+// and then the line's palette indices. Versions, palettes, windows, positions, and timestamps are
+// left out so that cropping, placement, retiming, and palette changes keep the same IDs.
+pub fn epoch_hash(epoch: &[DisplaySet]) -> u64 {
+
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes.iter() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    };
+
+    for display_set in epoch.iter() {
+        for (vid, object) in display_set.objects.iter() {
+
+            feed(&vid.id.0.to_be_bytes());
+            feed(&object.width.to_be_bytes());
+            feed(&object.height.to_be_bytes());
+
+            for line in object.lines.iter() {
+                feed(&(line.len() as u32).to_be_bytes());
+                feed(line);
+            }
+        }
+    }
+
+    hash
+}
+
+// One entry per display set of the epoch, which is only given an ID if it is an event.
+pub fn event_ids(epoch: &[DisplaySet]) -> Vec<Option<EventId>> {
+
+    let epoch_hash = epoch_hash(epoch);
+    let mut ordinal = 0;
+
+    epoch.iter()
+        .map(|display_set| {
+            if display_set.composition.objects.is_empty() {
+                None
+            } else {
+                ordinal += 1;
+                Some(EventId { epoch_hash, ordinal: ordinal - 1 })
+            }
+        })
+        .collect()
+}
+
+// A subtitle as viewers see it: what appears at one time and stays until another. Definitions are
+// only the ones the composition draws with, as they stood when the event started.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Event {
+    pub start_pts: u32,
+    // None when the stream ends while the event is still showing.
+    pub end_pts: Option<u32>,
+    pub composition: Composition,
+    pub objects: BTreeMap<ObjectId, Object>,
+    pub palette: Palette,
+    // Later palettes that changed the event's colors without changing what it draws, such as
+    // fades, each with the timestamp it took effect at.
+    pub palette_updates: Vec<(u32, Palette)>,
+    pub windows: BTreeMap<WindowId, Window>,
+}
+
+impl Event {
+
+    pub fn duration(&self) -> Option<u32> {
+        self.end_pts.map(|end_pts| end_pts.wrapping_sub(self.start_pts))
+    }
+}
+
+// Pairs each display set that shows something with the one that clears or replaces it. Display
+// sets that only change the palette, or repeat what is already showing, extend the current event
+// instead. An epoch start always begins a new one.
+pub struct Events<I> {
+    display_sets: I,
+    state: EpochState,
+    current: Option<Event>,
+}
+
+pub fn events<I>(display_sets: I) -> Events<I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: Borrow<DisplaySet>,
+{
+    Events {
+        display_sets: display_sets.into_iter(),
+        state: EpochState::default(),
+        current: None,
+    }
+}
+
+impl<I> Iterator for Events<I>
+where
+    I: Iterator,
+    I::Item: Borrow<DisplaySet>,
+{
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+
+        for display_set in self.display_sets.by_ref() {
+
+            let display_set = display_set.borrow();
+
+            self.state.apply(display_set);
+
+            if let Some(current) = self.current.as_mut() {
+                if continues(current, &self.state, display_set) {
+
+                    let palette = self.state.palette(display_set).cloned().unwrap_or_default();
+                    let last = current.palette_updates.last()
+                        .map_or(&current.palette, |(_, palette)| palette);
+
+                    if palette != *last {
+                        current.palette_updates.push((display_set.pts, palette));
+                    }
+
+                    continue
+                }
+            }
+
+            let started = start(&self.state, display_set);
+
+            if let Some(mut finished) = std::mem::replace(&mut self.current, started) {
+                finished.end_pts = Some(display_set.pts);
+                return Some(finished)
+            }
+        }
+
+        self.current.take()
+    }
+}
+
+fn start(state: &EpochState, display_set: &DisplaySet) -> Option<Event> {
+
+    if display_set.composition.objects.is_empty() {
+        return None
+    }
+
+    let (objects, windows) = drawn(state, &display_set.composition);
+
+    Some(
+        Event {
+            start_pts: display_set.pts,
+            end_pts: None,
+            composition: display_set.composition.clone(),
+            objects,
+            palette: state.palette(display_set).cloned().unwrap_or_default(),
+            palette_updates: Vec::new(),
+            windows,
+        }
+    )
+}
+
+fn continues(event: &Event, state: &EpochState, display_set: &DisplaySet) -> bool {
+
+    if display_set.composition.state == CompositionState::EpochStart
+        || display_set.composition.objects != event.composition.objects {
+        return false
+    }
+
+    let (objects, windows) = drawn(state, &display_set.composition);
+
+    objects == event.objects && windows == event.windows
+}
+
+fn drawn(
+    state: &EpochState,
+    composition: &Composition,
+) -> (BTreeMap<ObjectId, Object>, BTreeMap<WindowId, Window>) {
+
+    let mut objects = BTreeMap::new();
+    let mut windows = BTreeMap::new();
+
+    for cid in composition.objects.keys() {
+        if let Some(object) = state.objects.get(&cid.object_id) {
+            objects.insert(cid.object_id, object.clone());
+        }
+        if let Some(window) = state.windows.get(&cid.window_id) {
+            windows.insert(cid.window_id, window.clone());
+        }
+    }
+
+    (objects, windows)
+}
+
+// Quality control minimums, in 90 kHz ticks. A gap of zero is a chain of one event straight into
+// the next and is never too short.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TimingRules {
+    pub min_duration: u32,
+    pub min_gap: u32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimingAdjustment {
+    // The event's clear was pushed later.
+    Extended { index: usize, by: u32 },
+    // The event's clear was pulled earlier to leave room before the next event.
+    Shortened { index: usize, by: u32 },
+    // The event is still this much shorter than the minimum, either because the next event comes
+    // too soon or because there is no clear to move when the next event replaces it.
+    TooShort { index: usize, by: u32 },
+    // The gap after the event is still this much shorter than the minimum, as pulling its clear
+    // any earlier would make the event shorter than the minimum duration.
+    TooClose { index: usize, by: u32 },
+    // The event already runs past the start of the next one and is left alone.
+    Overlapping { index: usize },
+}
+
+impl TimingAdjustment {
+
+    // The index of the event adjusted.
+    pub fn index(&self) -> usize {
+        match *self {
+            TimingAdjustment::Extended { index, .. }
+            | TimingAdjustment::Shortened { index, .. }
+            | TimingAdjustment::TooShort { index, .. }
+            | TimingAdjustment::TooClose { index, .. }
+            | TimingAdjustment::Overlapping { index } => index,
+        }
+    }
+}
+
+// Moves the ends of events so that each one lasts at least the minimum duration and leaves at
+// least the minimum gap before the next one, given the start of whatever follows the last. An
+// event is only extended into its gap as far as the minimum gap allows, and only pulled back as far
+// as the minimum duration allows. Its end never crosses the next event's start, so neither
+// overlaps nor reordering can result. Events that end by being replaced have no clear of their own
+// and keep their ends, as do events that never end.
+pub fn enforce_timing(
+    events: &mut [Event],
+    next: Option<u32>,
+    rules: TimingRules,
+) -> Vec<TimingAdjustment> {
+
+    let mut adjustments = Vec::new();
+
+    for index in 0..events.len() {
+
+        let next_start = events.get(index + 1).map(|event| event.start_pts).or(next);
+        let event = &mut events[index];
+        let end = match event.end_pts {
+            Some(end) => end,
+            None => continue,
+        };
+
+        if next_start.is_some_and(|next_start| end > next_start) {
+            adjustments.push(TimingAdjustment::Overlapping { index });
+            continue
+        }
+
+        let wanted = event.start_pts.saturating_add(rules.min_duration);
+        let new_end = match next_start {
+            Some(next_start) if next_start == end => end,
+            Some(next_start) if next_start - end < rules.min_gap => {
+                next_start.saturating_sub(rules.min_gap).max(wanted).min(end)
+            }
+            Some(next_start) => wanted.clamp(end, next_start - rules.min_gap),
+            None => wanted.max(end),
+        };
+
+        if new_end > end {
+            adjustments.push(TimingAdjustment::Extended { index, by: new_end - end });
+        } else if new_end < end {
+            adjustments.push(TimingAdjustment::Shortened { index, by: end - new_end });
+        }
+        if new_end < wanted {
+            adjustments.push(TimingAdjustment::TooShort { index, by: wanted - new_end });
+        }
+        if let Some(gap) = next_start.map(|next_start| next_start - new_end) {
+            if gap > 0 && gap < rules.min_gap {
+                adjustments.push(TimingAdjustment::TooClose { index, by: rules.min_gap - gap });
+            }
+        }
+
+        event.end_pts = Some(new_end);
+    }
+
+    adjustments
+}
@@ -0,0 +1,324 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use super::super::{
+    displayset::{Cid, CompositionObject, Object, PaletteEntry},
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    segment::Sequence,
+};
+
+fn epoch() -> Vec<DisplaySet> {
+
+    let mut shown = DisplaySet { pts: 900, ..Default::default() };
+
+    shown.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width: 2, height: 1, sequence: Sequence::Single, lines: vec![vec![1, 1]] },
+    );
+    shown.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 100, y: 900, crop: None, forced: false },
+    );
+
+    let mut shown_again = DisplaySet { pts: 2700, ..Default::default() };
+
+    shown_again.composition.objects = shown.composition.objects.clone();
+
+    vec![shown, DisplaySet { pts: 1800, ..Default::default() }, shown_again]
+}
+
+#[test]
+fn test_epoch_hash_is_documented_fnv() {
+
+    // FNV-1a 64 over 00 00 00 02 00 01 00 00 00 02 01 01.
+    assert_eq!(epoch_hash(&epoch()), 0x7996_E594_ABD9_8460);
+    assert_eq!(epoch_hash(&[]), FNV_OFFSET_BASIS);
+}
+
+#[test]
+fn test_event_ids() {
+
+    let ids = event_ids(&epoch());
+    let hash = epoch_hash(&epoch());
+
+    assert_eq!(
+        ids,
+        vec![
+            Some(EventId { epoch_hash: hash, ordinal: 0 }),
+            None,
+            Some(EventId { epoch_hash: hash, ordinal: 1 }),
+        ],
+    );
+    assert_eq!(ids[2].unwrap().to_string(), "7996e594abd98460-1");
+    assert_eq!("7996e594abd98460-1".parse::<EventId>(), Ok(ids[2].unwrap()));
+    assert!("7996e594abd98460".parse::<EventId>().is_err());
+    assert!("7996e594-1".parse::<EventId>().is_err());
+}
+
+#[test]
+fn test_event_ids_survive_repositioning() {
+
+    let mut moved = epoch();
+
+    for display_set in moved.iter_mut() {
+        display_set.pts += 90_000;
+        for composition_object in display_set.composition.objects.values_mut() {
+            composition_object.x = 0;
+        }
+    }
+
+    assert_eq!(event_ids(&moved), event_ids(&epoch()));
+
+    moved[0].objects.values_mut().next().unwrap().lines[0][1] = 2;
+
+    assert_ne!(epoch_hash(&moved), epoch_hash(&epoch()));
+}
+
+fn palette(alpha: u8) -> Palette {
+
+    let mut palette = Palette::default();
+
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha });
+
+    palette
+}
+
+// An epoch start drawing one object whose single line is the given indices.
+fn shown(pts: u32, line: Vec<u8>) -> DisplaySet {
+
+    let mut display_set = DisplaySet { pts, ..Default::default() };
+    let width = line.len() as u16;
+
+    display_set.windows.insert(WindowId(0), Window { x: 100, y: 900, width, height: 1 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette(255));
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width, height: 1, sequence: Sequence::Single, lines: vec![line] },
+    );
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 100, y: 900, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+fn cleared(pts: u32) -> DisplaySet {
+
+    let mut display_set = DisplaySet { pts, ..Default::default() };
+
+    display_set.composition.state = CompositionState::Normal;
+
+    display_set
+}
+
+// Changes only the palette of what the previous display set showed.
+fn palette_update(shown: &DisplaySet, pts: u32, alpha: u8) -> DisplaySet {
+
+    let mut display_set = DisplaySet {
+        pts,
+        palette_update_id: Some(PaletteId(0)),
+        ..Default::default()
+    };
+
+    display_set.composition.state = CompositionState::Normal;
+    display_set.composition.objects = shown.composition.objects.clone();
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 1 }, palette(alpha));
+
+    display_set
+}
+
+fn spans(events: &[Event]) -> Vec<(u32, Option<u32>)> {
+    events.iter().map(|event| (event.start_pts, event.end_pts)).collect()
+}
+
+#[test]
+fn test_events_pair_shows_with_clears() {
+
+    let stream = vec![
+        cleared(0),
+        shown(900, vec![1, 1]),
+        cleared(1800),
+        cleared(2000),
+        shown(2700, vec![1, 0]),
+        cleared(3600),
+    ];
+    let paired = events(&stream).collect::<Vec<Event>>();
+
+    assert_eq!(spans(&paired), vec![(900, Some(1800)), (2700, Some(3600))]);
+    assert_eq!(paired[0].duration(), Some(900));
+    assert_eq!(paired[1].objects[&ObjectId(0)].lines, vec![vec![1, 0]]);
+    assert_eq!(paired[1].windows[&WindowId(0)].width, 2);
+    assert_eq!(paired[1].palette, palette(255));
+    assert!(paired[1].palette_updates.is_empty());
+}
+
+#[test]
+fn test_events_end_where_they_are_replaced() {
+
+    let mut replaced = shown(1800, vec![2, 2]);
+
+    replaced.composition.state = CompositionState::Normal;
+
+    let stream = vec![shown(900, vec![1, 1]), replaced, cleared(2700)];
+    let paired = events(stream).collect::<Vec<Event>>();
+
+    assert_eq!(spans(&paired), vec![(900, Some(1800)), (1800, Some(2700))]);
+    assert_eq!(paired[1].objects[&ObjectId(0)].lines, vec![vec![2, 2]]);
+}
+
+#[test]
+fn test_last_event_has_no_end() {
+
+    let paired = events(vec![shown(900, vec![1])]).collect::<Vec<Event>>();
+
+    assert_eq!(spans(&paired), vec![(900, None)]);
+    assert_eq!(paired[0].duration(), None);
+    assert_eq!(events(Vec::<DisplaySet>::new()).count(), 0);
+}
+
+#[test]
+fn test_palette_updates_extend_the_event() {
+
+    let first = shown(900, vec![1, 1]);
+    let stream = vec![
+        palette_update(&first, 1000, 128),
+        palette_update(&first, 1100, 128),
+        palette_update(&first, 1200, 0),
+        cleared(1800),
+    ];
+    let paired = events(std::iter::once(first).chain(stream)).collect::<Vec<Event>>();
+
+    assert_eq!(spans(&paired), vec![(900, Some(1800))]);
+    assert_eq!(paired[0].palette, palette(255));
+    assert_eq!(paired[0].palette_updates, vec![(1000, palette(128)), (1200, palette(0))]);
+}
+
+#[test]
+fn test_acquisition_points_repeating_the_event_extend_it() {
+
+    let mut repeated = shown(1800, vec![1, 1]);
+
+    repeated.composition.state = CompositionState::AcquisitionPoint;
+
+    let stream = vec![shown(900, vec![1, 1]), repeated, cleared(2700)];
+
+    assert_eq!(spans(&events(&stream).collect::<Vec<Event>>()), vec![(900, Some(2700))]);
+}
+
+#[test]
+fn test_epoch_starts_always_begin_new_events() {
+
+    let stream = vec![shown(900, vec![1, 1]), shown(1800, vec![1, 1]), cleared(2700)];
+
+    assert_eq!(
+        spans(&events(&stream).collect::<Vec<Event>>()),
+        vec![(900, Some(1800)), (1800, Some(2700))],
+    );
+}
+
+#[test]
+fn test_moving_the_object_begins_a_new_event() {
+
+    let first = shown(900, vec![1, 1]);
+    let mut moved = palette_update(&first, 1800, 255);
+
+    moved.palette_update_id = None;
+    moved.palettes.clear();
+    moved.composition.objects.values_mut().next().unwrap().y = 800;
+
+    let stream = vec![first, moved, cleared(2700)];
+    let paired = events(&stream).collect::<Vec<Event>>();
+
+    assert_eq!(spans(&paired), vec![(900, Some(1800)), (1800, Some(2700))]);
+    assert_eq!(paired[1].palette, palette(255));
+    assert_eq!(paired[1].composition.objects.values().next().unwrap().y, 800);
+}
+
+fn timed(spans: &[(u32, Option<u32>)]) -> Vec<Event> {
+    spans.iter()
+        .map(|&(start_pts, end_pts)| Event { start_pts, end_pts, ..Default::default() })
+        .collect()
+}
+
+const RULES: TimingRules = TimingRules { min_duration: 90_000, min_gap: 7_200 };
+
+#[test]
+fn test_enforce_timing_extends_short_events() {
+
+    let mut events = timed(&[(0, Some(45_000)), (200_000, Some(250_000))]);
+
+    assert_eq!(
+        enforce_timing(&mut events, None, RULES),
+        vec![
+            TimingAdjustment::Extended { index: 0, by: 45_000 },
+            TimingAdjustment::Extended { index: 1, by: 40_000 },
+        ],
+    );
+    assert_eq!(spans(&events), vec![(0, Some(90_000)), (200_000, Some(290_000))]);
+}
+
+#[test]
+fn test_enforce_timing_extends_only_into_the_gap() {
+
+    let mut events = timed(&[(0, Some(45_000)), (60_000, Some(200_000))]);
+
+    assert_eq!(
+        enforce_timing(&mut events, Some(300_000), RULES),
+        vec![
+            TimingAdjustment::Extended { index: 0, by: 7_800 },
+            TimingAdjustment::TooShort { index: 0, by: 37_200 },
+        ],
+    );
+    assert_eq!(spans(&events), vec![(0, Some(52_800)), (60_000, Some(200_000))]);
+}
+
+#[test]
+fn test_enforce_timing_pulls_clears_earlier() {
+
+    let mut events = timed(&[(0, Some(100_000)), (103_000, Some(200_000))]);
+
+    assert_eq!(
+        enforce_timing(&mut events, Some(200_100), RULES),
+        vec![
+            TimingAdjustment::Shortened { index: 0, by: 4_200 },
+            TimingAdjustment::Shortened { index: 1, by: 7_000 },
+            TimingAdjustment::TooClose { index: 1, by: 100 },
+        ],
+    );
+    assert_eq!(spans(&events), vec![(0, Some(95_800)), (103_000, Some(193_000))]);
+}
+
+#[test]
+fn test_enforce_timing_leaves_chains_and_overlaps_alone() {
+
+    let mut events = timed(&[(0, Some(45_000)), (45_000, Some(100_000)), (90_000, None)]);
+
+    assert_eq!(
+        enforce_timing(&mut events, None, RULES),
+        vec![
+            TimingAdjustment::TooShort { index: 0, by: 45_000 },
+            TimingAdjustment::Overlapping { index: 1 },
+        ],
+    );
+    assert_eq!(spans(&events), vec![(0, Some(45_000)), (45_000, Some(100_000)), (90_000, None)]);
+}
+
+#[test]
+fn test_enforce_timing_never_pulls_below_the_minimum_duration() {
+
+    let mut events = timed(&[(0, Some(1_000)), (1_500, None)]);
+
+    assert_eq!(
+        enforce_timing(&mut events, None, RULES),
+        vec![
+            TimingAdjustment::TooShort { index: 0, by: 89_000 },
+            TimingAdjustment::TooClose { index: 0, by: 6_700 },
+        ],
+    );
+    assert_eq!(spans(&events), vec![(0, Some(1_000)), (1_500, None)]);
+}
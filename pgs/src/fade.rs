@@ -0,0 +1,220 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    displayset::{DisplaySet, Palette, PaletteEntry},
+    id::{PaletteId, VersionedId},
+};
+use std::collections::BTreeMap;
+use thiserror::Error as ThisError;
+
+pub type FadeResult<T> = Result<T, FadeError>;
+
+#[derive(ThisError, Debug)]
+pub enum FadeError {
+    #[error("palette {id} would need more than 256 versions in one epoch")]
+    TooManyPaletteVersions {
+        id: PaletteId,
+    },
+}
+
+// Inserts palette updates between the keyframes of each fade so that it steps at the given frame
+// rate, returning how many were inserted. Each palette defined in the epoch is then versioned
+// anew, and if one would run out of versions, the epoch is left as it was.
+pub fn smooth_fades(epoch: &mut Vec<DisplaySet>, fps: f64) -> FadeResult<usize> {
+
+    let interval = 90_000.0 / fps;
+    let mut output = Vec::<DisplaySet>::with_capacity(epoch.len());
+    let mut palettes = BTreeMap::<PaletteId, (u32, Palette)>::new();
+    let mut index = 0;
+    let mut inserted = 0;
+
+    while index < epoch.len() {
+
+        let run = fade_run(epoch, index, &palettes);
+
+        if run.len() > 1 {
+
+            let palette_id = epoch[index].palette_update_id.unwrap();
+
+            for pair in run.windows(2) {
+
+                let (start_pts, start_palette) = &pair[0];
+                let (end_pts, end_palette) = &pair[1];
+                let target = &epoch[index];
+                let offset = target.pts.saturating_sub(target.dts);
+                let mut step = 1;
+
+                loop {
+
+                    let pts = *start_pts as f64 + interval * step as f64;
+
+                    if pts >= *end_pts as f64 {
+                        break
+                    }
+
+                    let fraction = (pts - *start_pts as f64) / (*end_pts - *start_pts) as f64;
+                    let mut display_set = target.clone();
+
+                    display_set.pts = pts.round() as u32;
+                    display_set.dts = display_set.pts.saturating_sub(offset);
+                    display_set.palettes = vec![(
+                        VersionedId { id: palette_id, version: 0 },
+                        interpolate(start_palette, end_palette, fraction),
+                    )].into_iter().collect();
+                    output.push(display_set);
+                    inserted += 1;
+                    step += 1;
+                }
+
+                output.push(epoch[index].clone());
+                index += 1;
+            }
+
+            palettes.insert(palette_id, run[run.len() - 1].clone());
+        } else {
+
+            let display_set = &epoch[index];
+
+            for (vid, palette) in display_set.palettes.iter() {
+                palettes.insert(vid.id, (display_set.pts, palette.clone()));
+            }
+
+            output.push(display_set.clone());
+            index += 1;
+        }
+    }
+
+    if inserted > 0 {
+        renumber_palette_versions(&mut output)?;
+        *epoch = output;
+    }
+
+    Ok(inserted)
+}
+
+// Collects the keyframes of a fade made of at least two consecutive palette updates starting at
+// the given index, beginning with the palette that was in effect beforehand.
+fn fade_run(
+    epoch: &[DisplaySet],
+    start: usize,
+    palettes: &BTreeMap<PaletteId, (u32, Palette)>,
+) -> Vec<(u32, Palette)> {
+
+    let palette_id = match epoch[start].palette_update_id {
+        Some(id) => id,
+        None => return vec![],
+    };
+    let mut keyframes = match palettes.get(&palette_id) {
+        Some(keyframe) => vec![keyframe.clone()],
+        None => return vec![],
+    };
+
+    for display_set in epoch[start..].iter() {
+        if display_set.palette_update_id != Some(palette_id) {
+            break
+        }
+        match display_set.palettes.iter().find(|(vid, _)| vid.id == palette_id) {
+            Some((_, palette)) => keyframes.push((display_set.pts, palette.clone())),
+            None => break,
+        }
+    }
+
+    if keyframes.len() > 2 && is_ramp(&keyframes) {
+        keyframes
+    } else {
+        vec![]
+    }
+}
+
+fn is_ramp(keyframes: &[(u32, Palette)]) -> bool {
+
+    let mut changed = false;
+
+    for (id, first) in keyframes[0].1.entries.iter() {
+
+        let values = keyframes.iter()
+            .filter_map(|(_, palette)| palette.entries.get(id))
+            .collect::<Vec<&PaletteEntry>>();
+
+        if values.len() != keyframes.len() {
+            continue
+        }
+
+        let alpha = values.iter().map(|entry| entry.alpha).collect::<Vec<u8>>();
+        let luma = values.iter().map(|entry| entry.y).collect::<Vec<u8>>();
+
+        if !is_monotonic(&alpha) || !is_monotonic(&luma) {
+            return false
+        }
+
+        changed |= values.iter().any(|entry| *entry != first);
+    }
+
+    changed
+}
+
+fn is_monotonic(values: &[u8]) -> bool {
+    values.windows(2).all(|pair| pair[0] <= pair[1])
+        || values.windows(2).all(|pair| pair[0] >= pair[1])
+}
+
+fn interpolate(start: &Palette, end: &Palette, fraction: f64) -> Palette {
+
+    let mut palette = end.clone();
+
+    for (id, entry) in palette.entries.iter_mut() {
+        if let Some(from) = start.entries.get(id) {
+            entry.y = to_luma(
+                to_linear(from.y) + (to_linear(entry.y) - to_linear(from.y)) * fraction
+            );
+            entry.cr = lerp(from.cr, entry.cr, fraction);
+            entry.cb = lerp(from.cb, entry.cb, fraction);
+            entry.alpha = lerp(from.alpha, entry.alpha, fraction);
+        }
+    }
+
+    palette
+}
+
+fn lerp(from: u8, to: u8, fraction: f64) -> u8 {
+    (from as f64 + (to as f64 - from as f64) * fraction).round().clamp(0.0, 255.0) as u8
+}
+
+fn to_linear(luma: u8) -> f64 {
+    ((luma as f64 - 16.0) / 219.0).clamp(0.0, 1.0).powf(2.4)
+}
+
+fn to_luma(linear: f64) -> u8 {
+    (linear.clamp(0.0, 1.0).powf(1.0 / 2.4) * 219.0 + 16.0).round() as u8
+}
+
+fn renumber_palette_versions(epoch: &mut [DisplaySet]) -> FadeResult<()> {
+
+    let mut versions = BTreeMap::<PaletteId, u8>::new();
+
+    for display_set in epoch.iter_mut() {
+
+        let mut palettes = BTreeMap::new();
+
+        for (vid, palette) in display_set.palettes.iter() {
+            let version = match versions.get(&vid.id) {
+                Some(version) => version.checked_add(1)
+                    .ok_or(FadeError::TooManyPaletteVersions { id: vid.id })?,
+                None => vid.version,
+            };
+            versions.insert(vid.id, version);
+            palettes.insert(VersionedId { id: vid.id, version }, palette.clone());
+        }
+
+        display_set.palettes = palettes;
+    }
+
+    Ok(())
+}
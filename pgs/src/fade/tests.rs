@@ -0,0 +1,153 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::{
+    *,
+    super::{
+        displayset::{Cid, CompositionObject, Object},
+        id::{ObjectId, PaletteId, VersionedId, WindowId},
+        segment::{CompositionState, Sequence},
+    },
+};
+
+fn palette(y: u8, alpha: u8) -> Palette {
+
+    let mut palette = Palette::default();
+
+    palette.entries.insert(0, PaletteEntry { y: 16, cr: 128, cb: 128, alpha: 0 });
+    palette.entries.insert(1, PaletteEntry { y, cr: 128, cb: 128, alpha });
+
+    palette
+}
+
+fn fade(steps: &[(u8, u8)]) -> Vec<DisplaySet> {
+
+    let mut epoch = vec![];
+    let cid = Cid { object_id: ObjectId(0), window_id: WindowId(0) };
+    let mut start = DisplaySet {
+        pts: 90_000,
+        dts: 89_000,
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+
+    start.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette(235, 255));
+    start.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object {
+            width: 1,
+            height: 1,
+            sequence: Sequence::Single,
+            lines: vec![vec![1]],
+        },
+    );
+    start.composition.state = CompositionState::EpochStart;
+    start.composition.objects.insert(cid.clone(), CompositionObject::default());
+    epoch.push(start);
+
+    for (index, &(y, alpha)) in steps.iter().enumerate() {
+
+        let mut update = DisplaySet {
+            pts: 90_000 + 9_000 * (index as u32 + 1),
+            width: 1920,
+            height: 1080,
+            palette_update_id: Some(PaletteId(0)),
+            ..Default::default()
+        };
+
+        update.dts = update.pts;
+        update.palettes.insert(
+            VersionedId { id: PaletteId(0), version: index as u8 + 1 },
+            palette(y, alpha),
+        );
+        update.composition.state = CompositionState::Normal;
+        update.composition.objects.insert(cid.clone(), CompositionObject::default());
+        epoch.push(update);
+    }
+
+    epoch
+}
+
+#[test]
+fn test_fade_out_interpolated() {
+
+    let mut epoch = fade(&[(180, 170), (100, 85), (16, 0)]);
+
+    assert_eq!(smooth_fades(&mut epoch, 30.0).unwrap(), 6);
+    assert_eq!(epoch.len(), 10);
+    assert_eq!(
+        epoch.iter().map(|display_set| display_set.pts).collect::<Vec<u32>>(),
+        vec![
+            90_000, 93_000, 96_000, 99_000, 102_000, 105_000, 108_000, 111_000, 114_000,
+            117_000,
+        ],
+    );
+
+    let alphas = epoch.iter()
+        .map(|display_set| display_set.palettes.values().next().unwrap().entries[&1].alpha)
+        .collect::<Vec<u8>>();
+    let lumas = epoch.iter()
+        .map(|display_set| display_set.palettes.values().next().unwrap().entries[&1].y)
+        .collect::<Vec<u8>>();
+    let versions = epoch.iter()
+        .map(|display_set| display_set.palettes.keys().next().unwrap().version)
+        .collect::<Vec<u8>>();
+
+    assert_eq!(alphas, vec![255, 227, 198, 170, 142, 113, 85, 57, 28, 0]);
+    assert!(lumas.windows(2).all(|pair| pair[0] > pair[1]));
+    assert_eq!(versions, (0..10).collect::<Vec<u8>>());
+    assert!(
+        epoch[1..].iter().all(|display_set| display_set.palette_update_id == Some(PaletteId(0)))
+    );
+    assert!(epoch.iter().all(|display_set| display_set.dts <= display_set.pts));
+}
+
+#[test]
+fn test_fade_already_smooth() {
+
+    let mut epoch = fade(&[(180, 170), (100, 85), (16, 0)]);
+    let expected = epoch.clone();
+
+    assert_eq!(smooth_fades(&mut epoch, 10.0).unwrap(), 0);
+    assert_eq!(epoch, expected);
+}
+
+#[test]
+fn test_non_monotonic_not_a_fade() {
+
+    let mut epoch = fade(&[(180, 0), (235, 255), (180, 0)]);
+    let expected = epoch.clone();
+
+    assert_eq!(smooth_fades(&mut epoch, 30.0).unwrap(), 0);
+    assert_eq!(epoch, expected);
+}
+
+#[test]
+fn test_linear_light_luma() {
+
+    let start = palette(16, 255);
+    let end = palette(235, 255);
+    let midpoint = interpolate(&start, &end, 0.5);
+
+    assert_eq!(midpoint.entries[&1].y, (0.5_f64.powf(1.0 / 2.4) * 219.0 + 16.0).round() as u8);
+    assert_eq!(interpolate(&start, &end, 0.0), start);
+    assert_eq!(interpolate(&start, &end, 1.0), end);
+}
+
+#[test]
+fn test_palette_versions_do_not_wrap() {
+
+    let mut epoch = fade(&[(180, 170), (100, 85), (16, 0)]);
+    let original = epoch.clone();
+
+    // Steps of a millisecond would take the palette past version 255.
+    assert!(matches!(
+        smooth_fades(&mut epoch, 1_000.0),
+        Err(FadeError::TooManyPaletteVersions { id: PaletteId(0) }),
+    ));
+    assert_eq!(epoch, original);
+}
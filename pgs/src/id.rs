@@ -0,0 +1,63 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use core::fmt::{Display, Formatter, Result as FmtResult};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// Objects, windows, and palettes each have their own ID space, so these are kept apart to keep
+// one from being looked up as another.
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct ObjectId(pub u16);
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct WindowId(pub u8);
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct PaletteId(pub u8);
+
+// An object or palette as of one of its definitions within an epoch.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct VersionedId<T> {
+    pub id: T,
+    pub version: u8,
+}
+
+impl Display for ObjectId {
+
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Display for WindowId {
+
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Display for PaletteId {
+
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<T: Display> Display for VersionedId<T> {
+
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{} version {}", self.id, self.version)
+    }
+}
@@ -0,0 +1,36 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+#[test]
+fn test_display() {
+    assert_eq!(ObjectId(300).to_string(), "300");
+    assert_eq!(WindowId(1).to_string(), "1");
+    assert_eq!(PaletteId(7).to_string(), "7");
+    assert_eq!(VersionedId { id: ObjectId(2), version: 5 }.to_string(), "2 version 5");
+}
+
+#[test]
+fn test_versions_order_after_ids() {
+
+    let mut vids = vec![
+        VersionedId { id: PaletteId(1), version: 0 },
+        VersionedId { id: PaletteId(0), version: 2 },
+        VersionedId { id: PaletteId(0), version: 1 },
+    ];
+
+    vids.sort();
+
+    assert_eq!(
+        vids,
+        vec![
+            VersionedId { id: PaletteId(0), version: 1 },
+            VersionedId { id: PaletteId(0), version: 2 },
+            VersionedId { id: PaletteId(1), version: 0 },
+        ],
+    );
+}
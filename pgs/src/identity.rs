@@ -0,0 +1,345 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    displayset::{
+        ReadDisplaySetExt,
+        ReadError as DisplaySetReadError,
+        WriteDisplaySetExt,
+        WriteError as DisplaySetWriteError,
+    },
+    segment::{
+        ReadError as SegmentReadError,
+        ReadOptions,
+        ReadSegmentExt,
+        SegmentKind,
+        WriteSegmentExt,
+    },
+};
+use std::{
+    collections::BTreeSet,
+    io::{Cursor, ErrorKind},
+};
+use thiserror::Error as ThisError;
+
+pub type IdentityResult<T> = Result<T, IdentityError>;
+
+#[derive(ThisError, Debug)]
+pub enum IdentityError {
+    #[error("input could not be read")]
+    ReadError {
+        #[from]
+        source: DisplaySetReadError,
+    },
+    #[error("input could not be written back")]
+    WriteError {
+        #[from]
+        source: DisplaySetWriteError,
+    },
+}
+
+// Which round trip a difference turned up in.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Pass {
+    // Each segment read and written back on its own.
+    Segment,
+    // Each display set assembled from its segments and written back as segments again.
+    DisplaySet,
+}
+
+// A field of a segment that did not come back as it was read. A segment that was dropped or
+// added altogether differs in its "segment" field, and has no bytes on the side it is missing
+// from.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SegmentDiff {
+    pub pass: Pass,
+    // Where the original segment falls in the input. An added segment has the index of the one
+    // it was written before.
+    pub index: usize,
+    // None for what is too short to have a kind.
+    pub kind: Option<SegmentKind>,
+    pub field: &'static str,
+    pub original: Vec<u8>,
+    pub rewritten: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Identity {
+    pub segments: usize,
+    pub display_sets: usize,
+    pub diffs: Vec<SegmentDiff>,
+}
+
+impl Identity {
+
+    pub fn is_lossless(&self) -> bool {
+        self.diffs.is_empty()
+    }
+
+    // The kinds of segment that did not come back as they were read.
+    pub fn kinds(&self) -> BTreeSet<Option<SegmentKind>> {
+        self.diffs.iter().map(|diff| diff.kind).collect()
+    }
+}
+
+// Reads a PGS stream and writes it back without changing anything, first segment by segment and
+// then display set by display set, listing every field that comes back different. Reading stops
+// at the end of the input as it does anywhere else, so whatever follows the last whole display
+// set is listed as dropped. Any other error reading the input, or writing back what was read,
+// is returned instead.
+pub fn check_identity(input: &[u8], options: &ReadOptions) -> IdentityResult<Identity> {
+
+    // Raw bytes take no part in writing, but would make every display set hold two copies.
+    let options = ReadOptions { keep_raw: false, ..*options };
+    let mut identity = Identity::default();
+    let mut cursor = Cursor::new(input);
+
+    while (cursor.position() as usize) < input.len() {
+
+        let start = cursor.position() as usize;
+        let display_set = match cursor.read_display_set_with(&options) {
+            Ok(display_set) => display_set,
+            Err(DisplaySetReadError::SegmentError {
+                source: SegmentReadError::IoError { source },
+            }) if source.kind() == ErrorKind::UnexpectedEof => {
+                for original in split_segments(&input[start..]) {
+                    identity.diffs.push(dropped(Pass::DisplaySet, identity.segments, original));
+                    identity.segments += 1;
+                }
+                break
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let originals = split_segments(&input[start..cursor.position() as usize]);
+        let mut buffer = vec![];
+
+        for (offset, original) in originals.iter().enumerate() {
+
+            let segment = Cursor::new(original)
+                .read_segment_with(&options)
+                .map_err(DisplaySetReadError::from)?;
+            let mut rewritten = vec![];
+
+            rewritten.write_segment(&segment).map_err(DisplaySetWriteError::from)?;
+            identity.diffs.extend(
+                diff_segment(Pass::Segment, identity.segments + offset, original, &rewritten)
+            );
+        }
+
+        buffer.write_display_set(&display_set)?;
+
+        let rewrittens = split_segments(&buffer);
+        let mut next = 0;
+
+        for pair in align(&originals, &rewrittens) {
+            let index = identity.segments + next;
+            match pair {
+                (Some(original), Some(rewritten)) => {
+                    identity.diffs.extend(
+                        diff_segment(
+                            Pass::DisplaySet,
+                            index,
+                            originals[original],
+                            rewrittens[rewritten],
+                        )
+                    );
+                    next += 1;
+                }
+                (Some(original), None) => {
+                    identity.diffs.push(dropped(Pass::DisplaySet, index, originals[original]));
+                    next += 1;
+                }
+                (None, Some(rewritten)) => {
+                    identity.diffs.push(SegmentDiff {
+                        pass: Pass::DisplaySet,
+                        index,
+                        kind: kind(rewrittens[rewritten]),
+                        field: "segment",
+                        original: vec![],
+                        rewritten: rewrittens[rewritten].to_vec(),
+                    });
+                }
+                (None, None) => {}
+            }
+        }
+
+        identity.segments += originals.len();
+        identity.display_sets += 1;
+    }
+
+    Ok(identity)
+}
+
+// Splits bytes into segments by the size each header gives, without checking anything else. A
+// segment cut short by the end of the bytes is kept as it is.
+fn split_segments(bytes: &[u8]) -> Vec<&[u8]> {
+
+    let mut segments = vec![];
+    let mut start = 0;
+
+    while start < bytes.len() {
+
+        let end = match bytes.get(start + 11..start + 13) {
+            Some(size) => start + 13 + u16::from_be_bytes([size[0], size[1]]) as usize,
+            None => bytes.len(),
+        };
+        let end = end.min(bytes.len());
+
+        segments.push(&bytes[start..end]);
+        start = end;
+    }
+
+    segments
+}
+
+fn kind(segment: &[u8]) -> Option<SegmentKind> {
+    segment.get(10).copied().map(SegmentKind::from_code)
+}
+
+fn dropped(pass: Pass, index: usize, original: &[u8]) -> SegmentDiff {
+    SegmentDiff {
+        pass,
+        index,
+        kind: kind(original),
+        field: "segment",
+        original: original.to_vec(),
+        rewritten: vec![],
+    }
+}
+
+// Pairs original and rewritten segments up in order, so that one dropped or added segment does
+// not throw off the rest of the display set. Only segments of the same kind are paired, and
+// identical ones are paired before any others.
+fn align(originals: &[&[u8]], rewrittens: &[&[u8]]) -> Vec<(Option<usize>, Option<usize>)> {
+
+    let (rows, columns) = (originals.len(), rewrittens.len());
+    let score = |row: usize, column: usize| {
+        if originals[row] == rewrittens[column] {
+            2
+        } else if kind(originals[row]) == kind(rewrittens[column]) {
+            1
+        } else {
+            0
+        }
+    };
+    // The best score of pairing up what follows each pair of positions.
+    let mut best = vec![vec![0usize; columns + 1]; rows + 1];
+
+    for row in (0..rows).rev() {
+        for column in (0..columns).rev() {
+            best[row][column] = best[row + 1][column].max(best[row][column + 1]);
+            if score(row, column) > 0 {
+                best[row][column] =
+                    best[row][column].max(score(row, column) + best[row + 1][column + 1]);
+            }
+        }
+    }
+
+    let mut pairs = vec![];
+    let (mut row, mut column) = (0, 0);
+
+    while row < rows || column < columns {
+        if row < rows
+            && column < columns
+            && score(row, column) > 0
+            && best[row][column] == score(row, column) + best[row + 1][column + 1] {
+            pairs.push((Some(row), Some(column)));
+            row += 1;
+            column += 1;
+        } else if column == columns
+            || (row < rows && best[row + 1][column] >= best[row][column + 1]) {
+            pairs.push((Some(row), None));
+            row += 1;
+        } else {
+            pairs.push((None, Some(column)));
+            column += 1;
+        }
+    }
+
+    pairs
+}
+
+// Where each field of a segment lies, by its kind, the header first. The last field runs to the
+// end of the segment.
+fn layout(segment: &[u8]) -> Vec<(&'static str, usize, usize)> {
+
+    let mut fields = vec![
+        ("magic number", 0, 2),
+        ("PTS", 2, 6),
+        ("DTS", 6, 10),
+        ("kind", 10, 11),
+        ("size", 11, 13),
+    ];
+    let payload: &[(&'static str, usize, usize)] = match kind(segment) {
+        Some(SegmentKind::PresentationComposition) => &[
+            ("width", 0, 2),
+            ("height", 2, 4),
+            ("frame rate", 4, 5),
+            ("composition number", 5, 7),
+            ("composition state", 7, 8),
+            ("palette update flag", 8, 9),
+            ("palette update ID", 9, 10),
+            ("composition object count", 10, 11),
+            ("composition objects", 11, usize::MAX),
+        ],
+        Some(SegmentKind::WindowDefinition) => &[
+            ("window count", 0, 1),
+            ("windows", 1, usize::MAX),
+        ],
+        Some(SegmentKind::PaletteDefinition) => &[
+            ("palette ID", 0, 1),
+            ("palette version", 1, 2),
+            ("palette entries", 2, usize::MAX),
+        ],
+        // Only an object's first fragment has a header.
+        Some(SegmentKind::ObjectDefinition)
+            if segment.get(16).is_some_and(|flag| flag & 0x80 != 0) => &[
+            ("object ID", 0, 2),
+            ("object version", 2, 3),
+            ("sequence flag", 3, 4),
+            ("data length", 4, 7),
+            ("object width", 7, 9),
+            ("object height", 9, 11),
+            ("object data", 11, usize::MAX),
+        ],
+        Some(SegmentKind::ObjectDefinition) => &[
+            ("object ID", 0, 2),
+            ("object version", 2, 3),
+            ("sequence flag", 3, 4),
+            ("object data", 4, usize::MAX),
+        ],
+        _ => &[
+            ("payload", 0, usize::MAX),
+        ],
+    };
+
+    fields.extend(
+        payload.iter().map(|&(name, start, end)| (name, 13 + start, end.saturating_add(13)))
+    );
+
+    fields
+}
+
+fn diff_segment(pass: Pass, index: usize, original: &[u8], rewritten: &[u8]) -> Vec<SegmentDiff> {
+
+    let kind = kind(original);
+    let field = |bytes: &[u8], start: usize, end: usize| {
+        bytes[start.min(bytes.len())..end.min(bytes.len())].to_vec()
+    };
+
+    layout(original).into_iter()
+        .filter_map(|(name, start, end)| {
+            let (original, rewritten) = (field(original, start, end), field(rewritten, start, end));
+            if original == rewritten {
+                return None
+            }
+            Some(SegmentDiff { pass, index, kind, field: name, original, rewritten })
+        })
+        .collect()
+}
@@ -0,0 +1,350 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use super::super::{
+    displayset::{Cid, CompositionObject, DisplaySet, Object, Palette, PaletteEntry, Window},
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    segment::{
+        CompositionObject as SegmentCompositionObject,
+        EndSegment,
+        ObjectDefinitionSegment,
+        ObjectHeader,
+        PaletteDefinitionSegment,
+        PaletteEntry as SegmentPaletteEntry,
+        PresentationCompositionSegment,
+        Segment,
+        WindowDefinition,
+        WindowDefinitionSegment,
+    },
+};
+use rand::{thread_rng, Rng};
+
+fn display_set(pts: u32) -> DisplaySet {
+
+    let mut rng = thread_rng();
+    let mut display_set = DisplaySet { pts, width: 1920, height: 1080, ..Default::default() };
+    let mut palette = Palette::default();
+
+    for id in 1..=3 {
+        palette.entries.insert(id, PaletteEntry { y: 16 * id, cr: 128, cb: 128, alpha: 255 });
+    }
+
+    // An object too big for one segment, so that its fragments are checked as well.
+    let lines = (0..80)
+        .map(|_| (0..1_000).map(|_| rng.gen_range(1..=3)).collect::<Vec<u8>>())
+        .collect::<Vec<Vec<u8>>>();
+
+    display_set.windows.insert(WindowId(0), Window { x: 0, y: 0, width: 1_000, height: 80 });
+    display_set.windows.insert(WindowId(1), Window { x: 0, y: 900, width: 4, height: 2 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width: 1_000, height: 80, lines, ..Default::default() },
+    );
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(1), version: 0 },
+        Object {
+            width: 4,
+            height: 2,
+            lines: vec![vec![1; 4], vec![2, 2, 0, 3]],
+            ..Default::default()
+        },
+    );
+
+    for (object_id, window_id, y) in [(0, 0, 0), (1, 1, 900)].iter() {
+        display_set.composition.objects.insert(
+            Cid { object_id: ObjectId(*object_id), window_id: WindowId(*window_id) },
+            CompositionObject { x: 0, y: *y, crop: None, forced: false },
+        );
+    }
+
+    display_set
+}
+
+fn stream() -> Vec<u8> {
+
+    let mut buffer = vec![];
+
+    buffer.write_display_set(&display_set(90_000)).unwrap();
+    buffer.write_display_set(&DisplaySet { pts: 180_000, ..Default::default() }).unwrap();
+
+    buffer
+}
+
+fn segment_bytes(segment: Segment) -> Vec<u8> {
+
+    let mut buffer = vec![];
+
+    buffer.write_segment(&segment).unwrap();
+
+    buffer
+}
+
+// A display set written segment by segment, so that each can be written in ways the display set
+// writer never would.
+fn handmade(pcs: PresentationCompositionSegment, segments: Vec<Segment>) -> Vec<u8> {
+
+    let mut buffer = segment_bytes(Segment::PresentationComposition(pcs));
+
+    for segment in segments {
+        buffer.extend(segment_bytes(segment));
+    }
+
+    buffer.extend(segment_bytes(Segment::End(EndSegment::default())));
+
+    buffer
+}
+
+fn pcs(composition_objects: Vec<SegmentCompositionObject>) -> PresentationCompositionSegment {
+    PresentationCompositionSegment {
+        width: 1920,
+        height: 1080,
+        composition_objects,
+        ..Default::default()
+    }
+}
+
+fn composition_object(object_id: u16, window_id: u8) -> SegmentCompositionObject {
+    SegmentCompositionObject {
+        object_id: ObjectId(object_id),
+        window_id: WindowId(window_id),
+        ..Default::default()
+    }
+}
+
+fn wds(ids: &[u8]) -> Segment {
+    Segment::WindowDefinition(WindowDefinitionSegment {
+        windows: ids.iter()
+            .map(|&id| {
+                WindowDefinition { id: WindowId(id), width: 4, height: 2, ..Default::default() }
+            })
+            .collect(),
+        ..Default::default()
+    })
+}
+
+fn pds(ids: &[u8]) -> Segment {
+    Segment::PaletteDefinition(PaletteDefinitionSegment {
+        entries: ids.iter()
+            .map(|&id| SegmentPaletteEntry { id, y: 235, cr: 128, cb: 128, alpha: 255 })
+            .collect(),
+        ..Default::default()
+    })
+}
+
+fn ods(id: u16, data: &[u8]) -> Segment {
+    Segment::ObjectDefinition(ObjectDefinitionSegment {
+        id: ObjectId(id),
+        header: Some(ObjectHeader { data_length: data.len(), width: 4, height: 1 }),
+        data: data.to_vec(),
+        ..Default::default()
+    })
+}
+
+fn fields(identity: &Identity, pass: Pass) -> Vec<(usize, Option<SegmentKind>, &'static str)> {
+    identity.diffs.iter()
+        .filter(|diff| diff.pass == pass)
+        .map(|diff| (diff.index, diff.kind, diff.field))
+        .collect()
+}
+
+#[test]
+fn test_what_the_crate_writes_is_reproduced() {
+
+    let identity = check_identity(&stream(), &ReadOptions::default()).unwrap();
+
+    assert_eq!(identity.display_sets, 2);
+    assert_eq!(identity.segments, 10);
+    assert!(identity.is_lossless(), "{:?}", identity.diffs);
+}
+
+#[test]
+fn test_composition_objects_out_of_order() {
+
+    let input = handmade(
+        pcs(vec![composition_object(1, 0), composition_object(0, 0)]),
+        vec![wds(&[0]), pds(&[1]), ods(0, &[0, 0x84, 1, 0, 0]), ods(1, &[0, 0x84, 1, 0, 0])],
+    );
+    let identity = check_identity(&input, &ReadOptions::default()).unwrap();
+
+    assert_eq!(fields(&identity, Pass::Segment), vec![]);
+    assert_eq!(
+        fields(&identity, Pass::DisplaySet),
+        vec![(0, Some(SegmentKind::PresentationComposition), "composition objects")],
+    );
+    assert_eq!(identity.diffs[0].original[..2], [0, 1]);
+    assert_eq!(identity.diffs[0].rewritten[..2], [0, 0]);
+}
+
+#[test]
+fn test_ignored_palette_update_id() {
+
+    let mut input = handmade(pcs(vec![]), vec![wds(&[0])]);
+
+    // The palette update flag is clear, so the ID after it means nothing and is written as 0.
+    input[13 + 9] = 7;
+
+    let identity = check_identity(&input, &ReadOptions::default()).unwrap();
+    let expected = vec![(0, Some(SegmentKind::PresentationComposition), "palette update ID")];
+
+    assert_eq!(fields(&identity, Pass::Segment), expected);
+    assert_eq!(fields(&identity, Pass::DisplaySet), expected);
+    assert_eq!(identity.diffs[0].original, [7]);
+    assert_eq!(identity.diffs[0].rewritten, [0]);
+}
+
+#[test]
+fn test_windows_out_of_order() {
+
+    let identity = check_identity(
+        &handmade(pcs(vec![]), vec![wds(&[1, 0])]),
+        &ReadOptions::default(),
+    ).unwrap();
+
+    assert_eq!(fields(&identity, Pass::Segment), vec![]);
+    assert_eq!(
+        fields(&identity, Pass::DisplaySet),
+        vec![(1, Some(SegmentKind::WindowDefinition), "windows")],
+    );
+}
+
+#[test]
+fn test_palette_entries_out_of_order() {
+
+    let identity = check_identity(
+        &handmade(pcs(vec![]), vec![wds(&[0]), pds(&[2, 1])]),
+        &ReadOptions::default(),
+    ).unwrap();
+
+    assert_eq!(
+        fields(&identity, Pass::DisplaySet),
+        vec![(2, Some(SegmentKind::PaletteDefinition), "palette entries")],
+    );
+}
+
+#[test]
+fn test_object_data_encoded_differently() {
+
+    // Four pixels of index 1 written one by one, where the crate writes a single run.
+    let identity = check_identity(
+        &handmade(pcs(vec![composition_object(0, 0)]), vec![
+            wds(&[0]),
+            pds(&[1]),
+            ods(0, &[1, 1, 1, 1, 0, 0]),
+        ]),
+        &ReadOptions::default(),
+    ).unwrap();
+    let diffs = identity.diffs.iter()
+        .filter(|diff| diff.pass == Pass::DisplaySet)
+        .collect::<Vec<&SegmentDiff>>();
+
+    assert_eq!(fields(&identity, Pass::Segment), vec![]);
+    assert_eq!(
+        diffs.iter().map(|diff| diff.field).collect::<Vec<&str>>(),
+        vec!["size", "data length", "object data"],
+    );
+    assert!(diffs.iter().all(|diff| diff.index == 3));
+    assert_eq!(diffs[2].original, [1, 1, 1, 1, 0, 0]);
+    assert_eq!(diffs[2].rewritten, [0, 0x84, 1, 0, 0]);
+}
+
+#[test]
+fn test_stray_end_is_dropped() {
+
+    let mut input = segment_bytes(Segment::End(EndSegment::default()));
+
+    input.extend(stream());
+
+    let identity = check_identity(&input, &ReadOptions { lenient: true, ..Default::default() })
+        .unwrap();
+
+    // Only the stray END is missing; the display set it came before lines up otherwise.
+    assert_eq!(
+        fields(&identity, Pass::DisplaySet),
+        vec![(0, Some(SegmentKind::End), "segment")],
+    );
+    assert!(identity.diffs[0].rewritten.is_empty());
+    assert_eq!(identity.kinds().into_iter().collect::<Vec<_>>(), vec![Some(SegmentKind::End)]);
+    assert!(check_identity(&input, &ReadOptions::default()).is_err());
+}
+
+#[test]
+fn test_truncated_input() {
+
+    let input = stream();
+    let identity = check_identity(&input[..input.len() - 2], &ReadOptions::default()).unwrap();
+
+    // The last display set has a PCS, a WDS, and an END, and the END is cut short.
+    assert_eq!(identity.display_sets, 1);
+    assert_eq!(
+        fields(&identity, Pass::DisplaySet),
+        vec![
+            (7, Some(SegmentKind::PresentationComposition), "segment"),
+            (8, Some(SegmentKind::WindowDefinition), "segment"),
+            (9, Some(SegmentKind::End), "segment"),
+        ],
+    );
+}
+
+#[test]
+fn test_align() {
+
+    let segment = |kind: u8| {
+        let mut segment = vec![0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, kind, 0, 0];
+        segment[10] = kind;
+        segment
+    };
+    let (pcs, wds, pds, end) = (segment(0x16), segment(0x17), segment(0x14), segment(0x80));
+    let originals = [&end[..], &pcs, &wds, &end];
+    let rewrittens = [&pcs[..], &wds, &pds, &end];
+
+    assert_eq!(
+        align(&originals, &rewrittens),
+        vec![
+            (Some(0), None),
+            (Some(1), Some(0)),
+            (Some(2), Some(1)),
+            (None, Some(2)),
+            (Some(3), Some(3)),
+        ],
+    );
+}
+
+#[test]
+fn test_fixtures_are_reproduced() {
+
+    for (name, input) in [
+        ("fragmented-object.sup", &include_bytes!("../../../test-data/fragmented-object.sup")[..]),
+        ("fade.sup", &include_bytes!("../../../test-data/fade.sup")[..]),
+        ("repeated-object.sup", &include_bytes!("../../../test-data/repeated-object.sup")[..]),
+    ].iter() {
+
+        let identity = check_identity(input, &ReadOptions::default()).unwrap();
+
+        assert!(identity.is_lossless(), "{}: {:?}", name, identity.diffs);
+    }
+}
+
+#[test]
+fn test_noise_fixture() {
+
+    let input = include_bytes!("../../../test-data/noise.sup");
+    let identity = check_identity(&input[..], &ReadOptions { lenient: true, ..Default::default() })
+        .unwrap();
+
+    assert_eq!(identity.display_sets, 2);
+    assert_eq!(identity.segments, 10);
+    assert_eq!(fields(&identity, Pass::Segment), vec![]);
+    // The empty PCS is the same as the one that replaces it, so the second is listed as dropped.
+    assert_eq!(
+        fields(&identity, Pass::DisplaySet),
+        vec![
+            (5, Some(SegmentKind::End), "segment"),
+            (7, Some(SegmentKind::PresentationComposition), "segment"),
+        ],
+    );
+}
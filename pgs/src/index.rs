@@ -0,0 +1,151 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    displayset::{DisplaySet, ReadDisplaySetExt, ReadError as DisplaySetReadError},
+    segment::{CompositionState, ReadError as SegmentReadError, ReadOptions},
+    timeline::EpochState,
+};
+use std::io::{Error as IoError, ErrorKind, Read, Seek, SeekFrom};
+use thiserror::Error as ThisError;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub type IndexResult<T> = Result<T, IndexError>;
+
+#[derive(ThisError, Debug)]
+pub enum IndexError {
+    #[error("display set read error")]
+    ReadError {
+        #[from]
+        source: DisplaySetReadError,
+    },
+    #[error("index IO error")]
+    IoError {
+        #[from]
+        source: IoError,
+    },
+}
+
+// Where a display set begins in the stream it was read from.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IndexEntry {
+    pub pts: u32,
+    pub offset: u64,
+    pub epoch_start: bool,
+}
+
+// Every display set of a stream, in stream order, so that any one of them can be read again
+// without reading everything before it.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Index {
+    // The length of the stream the index was built from, which tells a cached index apart from one
+    // that no longer fits its file.
+    pub length: u64,
+    pub entries: Vec<IndexEntry>,
+}
+
+// A display set read back through an index, along with what its epoch holds once it has been
+// applied, and the PTS of the display set that follows it, if any.
+#[derive(Clone, Debug)]
+pub struct Located {
+    pub display_set: DisplaySet,
+    pub state: EpochState,
+    pub end: Option<u32>,
+}
+
+// Reads every display set of a stream from where it is now, noting where each begins. The end
+// of the stream ends the index; any other error reading it is returned. Display sets must be read
+// back later with the same options, as lenient reading can skip past segments before one begins.
+pub fn build_index<R: Read + Seek>(source: &mut R, options: &ReadOptions) -> IndexResult<Index> {
+
+    let options = ReadOptions { keep_raw: false, ..*options };
+    let mut index = Index::default();
+
+    loop {
+
+        let offset = source.stream_position()?;
+        let display_set = match source.read_display_set_with(&options) {
+            Ok(display_set) => display_set,
+            Err(DisplaySetReadError::SegmentError {
+                source: SegmentReadError::IoError { source },
+            }) if source.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        index.entries.push(
+            IndexEntry {
+                pts: display_set.pts,
+                offset,
+                epoch_start: display_set.composition.state == CompositionState::EpochStart,
+            }
+        );
+    }
+
+    index.length = source.seek(SeekFrom::End(0))?;
+
+    Ok(index)
+}
+
+impl Index {
+
+    // The display set showing at the timestamp, which is the last one to begin by then. PTS
+    // values are taken to rise throughout the stream, as they must.
+    pub fn position(&self, ts: u32) -> Option<usize> {
+        self.entries.partition_point(|entry| entry.pts <= ts).checked_sub(1)
+    }
+
+    // The epoch start that the display set at the position depends on, or the first display set
+    // of a stream that does not begin with one.
+    pub fn epoch_start(&self, position: usize) -> usize {
+        self.entries[..=position].iter()
+            .rposition(|entry| entry.epoch_start)
+            .unwrap_or(0)
+    }
+
+    // Seeks to the epoch start before the timestamp and reads forward to the display set showing
+    // at it, so that whatever earlier display sets of the epoch defined is known. Returns None
+    // when nothing has begun by the timestamp.
+    pub fn locate<R: Read + Seek>(
+        &self,
+        source: &mut R,
+        ts: u32,
+        options: &ReadOptions,
+    ) -> IndexResult<Option<Located>> {
+
+        let position = match self.position(ts) {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        let start = self.epoch_start(position);
+        let options = ReadOptions { keep_raw: false, ..*options };
+        let mut state = EpochState::default();
+        let mut display_set = DisplaySet::default();
+
+        source.seek(SeekFrom::Start(self.entries[start].offset))?;
+
+        for _ in start..=position {
+            display_set = source.read_display_set_with(&options)?;
+            state.apply_with(&display_set, &options.limits)
+                .map_err(|limit| DisplaySetReadError::LimitExceeded { limit })?;
+        }
+
+        Ok(
+            Some(
+                Located {
+                    display_set,
+                    state,
+                    end: self.entries.get(position + 1).map(|entry| entry.pts),
+                }
+            )
+        )
+    }
+}
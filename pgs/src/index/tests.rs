@@ -0,0 +1,213 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use super::super::{
+    displayset::{
+        clear_display_set,
+        Cid,
+        CompositionObject,
+        Object,
+        Palette,
+        PaletteEntry,
+        Window,
+        WriteDisplaySetExt,
+    },
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+};
+use std::io::Cursor;
+
+fn shown_display_set(pts: u32, width: u16) -> DisplaySet {
+
+    let mut display_set = DisplaySet { pts, width: 1920, height: 1080, ..Default::default() };
+    let mut palette = Palette::default();
+
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+    display_set.windows.insert(WindowId(0), Window { x: 100, y: 900, width: 4, height: 2 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width, height: 2, lines: vec![vec![1; width as usize]; 2], ..Default::default() },
+    );
+    display_set.composition.state = CompositionState::EpochStart;
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 100, y: 900, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+// Two epochs, of which the first fades its object, then shows it again alongside one defined
+// later, and the second shows its object again without defining it over.
+fn display_sets() -> Vec<DisplaySet> {
+
+    let first = shown_display_set(90_000, 4);
+    let mut fade = clear_display_set(&first, 180_000);
+    let mut added = clear_display_set(&first, 270_000);
+    let second = shown_display_set(900_000, 2);
+    let mut again = clear_display_set(&second, 990_000);
+
+    fade.palettes.insert(
+        VersionedId { id: PaletteId(0), version: 1 },
+        Palette {
+            entries: vec![(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 128 })]
+                .into_iter()
+                .collect(),
+        },
+    );
+    fade.palette_update_id = Some(PaletteId(0));
+    fade.composition.objects = first.composition.objects.clone();
+
+    added.windows.insert(WindowId(1), Window { x: 100, y: 100, width: 4, height: 1 });
+    added.objects.insert(
+        VersionedId { id: ObjectId(1), version: 0 },
+        Object { width: 4, height: 1, lines: vec![vec![1; 4]], ..Default::default() },
+    );
+    added.composition.objects = first.composition.objects.clone();
+    added.composition.objects.insert(
+        Cid { object_id: ObjectId(1), window_id: WindowId(1) },
+        CompositionObject { x: 100, y: 100, crop: None, forced: false },
+    );
+
+    again.composition.objects = second.composition.objects.clone();
+
+    vec![
+        first.clone(),
+        fade,
+        added,
+        clear_display_set(&first, 360_000),
+        second,
+        again,
+    ]
+}
+
+fn stream() -> Cursor<Vec<u8>> {
+
+    let mut buffer = vec![];
+
+    for display_set in display_sets() {
+        buffer.write_display_set(&display_set).unwrap();
+    }
+
+    Cursor::new(buffer)
+}
+
+// What reading the whole stream from the start finds showing at the timestamp.
+fn scanned(ts: u32) -> Option<(DisplaySet, EpochState)> {
+
+    let mut source = stream();
+    let mut state = EpochState::default();
+    let mut showing = None;
+
+    for _ in 0..display_sets().len() {
+
+        let display_set = source.read_display_set().unwrap();
+
+        if display_set.pts > ts {
+            break
+        }
+
+        state.apply(&display_set);
+        showing = Some((display_set, state.clone()));
+    }
+
+    showing
+}
+
+#[test]
+fn test_build_index() {
+
+    let mut source = stream();
+    let index = build_index(&mut source, &ReadOptions::default()).unwrap();
+
+    assert_eq!(index.length, source.get_ref().len() as u64);
+    assert_eq!(
+        index.entries.iter()
+            .map(|entry| (entry.pts, entry.epoch_start))
+            .collect::<Vec<(u32, bool)>>(),
+        vec![
+            (90_000, true),
+            (180_000, false),
+            (270_000, false),
+            (360_000, false),
+            (900_000, true),
+            (990_000, false),
+        ],
+    );
+    assert_eq!(index.entries[0].offset, 0);
+    assert!(index.entries.windows(2).all(|pair| pair[0].offset < pair[1].offset));
+}
+
+#[test]
+fn test_locate_matches_a_linear_scan() {
+
+    let mut source = stream();
+    let index = build_index(&mut source, &ReadOptions::default()).unwrap();
+
+    for &ts in [90_000, 100_000, 180_000, 200_000, 300_000, 899_999, 900_000, 1_000_000].iter() {
+
+        let located = index.locate(&mut source, ts, &ReadOptions::default()).unwrap().unwrap();
+        let (display_set, state) = scanned(ts).unwrap();
+
+        assert!(located.display_set.pts <= ts, "{}", ts);
+        assert!(located.end.is_none_or(|end| ts < end), "{}", ts);
+        assert_eq!(located.display_set, display_set, "{}", ts);
+        assert_eq!(located.state.objects, state.objects, "{}", ts);
+        assert_eq!(located.state.windows, state.windows, "{}", ts);
+        assert_eq!(located.state.palettes, state.palettes, "{}", ts);
+    }
+}
+
+#[test]
+fn test_locate_resolves_earlier_definitions() {
+
+    let mut source = stream();
+    let index = build_index(&mut source, &ReadOptions::default()).unwrap();
+    let located = index.locate(&mut source, 300_000, &ReadOptions::default()).unwrap().unwrap();
+
+    // Only the second object is defined by the display set showing both.
+    assert_eq!(located.display_set.pts, 270_000);
+    assert_eq!(located.end, Some(360_000));
+    assert_eq!(
+        located.display_set.objects.keys().map(|vid| vid.id).collect::<Vec<ObjectId>>(),
+        vec![ObjectId(1)],
+    );
+    assert_eq!(located.state.objects.keys().copied().collect::<Vec<ObjectId>>(), vec![
+        ObjectId(0),
+        ObjectId(1),
+    ]);
+    assert_eq!(located.state.palettes[&PaletteId(0)].entries[&1].alpha, 128);
+
+    // The second epoch starts over with its own object of the same ID.
+    let located = index.locate(&mut source, u32::MAX, &ReadOptions::default()).unwrap().unwrap();
+
+    assert_eq!(located.end, None);
+    assert_eq!(located.state.objects[&ObjectId(0)].width, 2);
+}
+
+#[test]
+fn test_locate_before_anything_begins() {
+
+    let mut source = stream();
+    let index = build_index(&mut source, &ReadOptions::default()).unwrap();
+
+    assert_eq!(index.position(89_999), None);
+    assert!(index.locate(&mut source, 0, &ReadOptions::default()).unwrap().is_none());
+    assert!(
+        Index::default().locate(&mut source, 90_000, &ReadOptions::default()).unwrap().is_none()
+    );
+}
+
+#[test]
+fn test_epoch_start() {
+
+    let index = build_index(&mut stream(), &ReadOptions::default()).unwrap();
+
+    assert_eq!((0..6).map(|position| index.epoch_start(position)).collect::<Vec<usize>>(), vec![
+        0, 0, 0, 0, 4, 4,
+    ]);
+}
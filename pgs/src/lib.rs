@@ -4,18 +4,65 @@
  * SPDX-License-Identifier: OSL-3.0
  */
 
+// Without std, only segments can be parsed, and only from byte slices.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "async")]
+pub mod asyncread;
+#[cfg(feature = "async")]
+pub mod asyncwrite;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod check;
+#[cfg(feature = "std")]
+pub mod color;
+#[cfg(feature = "std")]
 pub mod displayset;
+#[cfg(feature = "std")]
+pub mod epoch;
+#[cfg(feature = "std")]
+pub mod event;
+#[cfg(feature = "std")]
+pub mod fade;
+pub mod id;
+#[cfg(feature = "std")]
+pub mod identity;
+#[cfg(feature = "std")]
+pub mod index;
+#[cfg(all(feature = "std", feature = "serde"))]
+mod pairs;
+#[cfg(feature = "std")]
+pub mod pes;
+#[cfg(feature = "std")]
+pub mod png;
+pub mod prelude;
+#[cfg(feature = "std")]
+pub mod progress;
+pub mod rle;
 pub mod segment;
+#[cfg(feature = "std")]
+pub mod style;
+#[cfg(feature = "std")]
+pub mod timeline;
+#[cfg(feature = "std")]
+pub mod timestamp;
+#[cfg(feature = "std")]
+pub mod ts;
 
-pub fn ts_to_timestamp(ts: u32) -> String {
+#[cfg(feature = "std")]
+pub use timestamp::{ticks_to_timestamp, timestamp_to_ts, ts_to_timestamp};
 
-    let mut ms = ts / 90;
-    let h = ms / 3_600_000;
-    ms -= h * 3_600_000;
-    let m = ms / 60_000;
-    ms -= m * 60_000;
-    let s = ms / 1_000;
-    ms -= s * 1_000;
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-    format!("{:02}:{:02}:{:02}.{:03}", h, m, s, ms)
-}
+// Cargo features this build was compiled with.
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "std")]
+    "std",
+    #[cfg(feature = "serde")]
+    "serde",
+    #[cfg(feature = "async")]
+    "async",
+];
@@ -0,0 +1,79 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use byteorder::{BigEndian, WriteBytesExt};
+use std::io::{Result as IoResult, Write};
+
+// Private stream 1, which is where Blu-ray muxers expect presentation graphics.
+pub const STREAM_ID: u8 = 0xBD;
+
+pub(crate) const START_CODE: [u8; 3] = [0x00, 0x00, 0x01];
+pub(crate) const PTS_LENGTH: usize = 5;
+// The flag and header length bytes that follow the packet length, plus the PTS if there is one.
+const MAX_PAYLOAD: usize = u16::MAX as usize - 3 - PTS_LENGTH;
+
+// Wraps a run of segments in as many PES packets as it takes. Only the first packet carries the
+// PTS, so that a reader sees the whole run as a single access unit.
+pub fn write_pes(output: &mut impl Write, pts: u32, payload: &[u8]) -> IoResult<()> {
+
+    let mut chunks = payload.chunks(MAX_PAYLOAD);
+    let first = chunks.next().unwrap_or(&[]);
+
+    write_packet(output, Some(pts), first)?;
+
+    for chunk in chunks {
+        write_packet(output, None, chunk)?;
+    }
+
+    Ok(())
+}
+
+fn write_packet(output: &mut impl Write, pts: Option<u32>, payload: &[u8]) -> IoResult<()> {
+
+    let header_length = if pts.is_some() { PTS_LENGTH } else { 0 };
+
+    output.write_all(&START_CODE)?;
+    output.write_u8(STREAM_ID)?;
+    output.write_u16::<BigEndian>((3 + header_length + payload.len()) as u16)?;
+    // Marker bits, with the first packet flagged as aligned to the start of a segment.
+    output.write_u8(if pts.is_some() { 0x85 } else { 0x81 })?;
+    output.write_u8(if pts.is_some() { 0x80 } else { 0x00 })?;
+    output.write_u8(header_length as u8)?;
+
+    if let Some(pts) = pts {
+        output.write_all(&encode_pts(pts))?;
+    }
+
+    output.write_all(payload)
+}
+
+// The 33-bit timestamp split into 3, 15, and 15 bits, each followed by a marker bit.
+fn encode_pts(pts: u32) -> [u8; PTS_LENGTH] {
+
+    let pts = pts as u64;
+    let high = (((pts >> 15) & 0x7FFF) << 1 | 1) as u16;
+    let low = ((pts & 0x7FFF) << 1 | 1) as u16;
+
+    [
+        0x21 | ((pts >> 29) & 0x0E) as u8,
+        (high >> 8) as u8,
+        high as u8,
+        (low >> 8) as u8,
+        low as u8,
+    ]
+}
+
+// The inverse of encode_pts, ignoring the marker bits.
+pub(crate) fn decode_pts(bytes: &[u8]) -> u64 {
+    (((bytes[0] >> 1) & 0x07) as u64) << 30
+        | (bytes[1] as u64) << 22
+        | ((bytes[2] >> 1) as u64) << 15
+        | (bytes[3] as u64) << 7
+        | (bytes[4] >> 1) as u64
+}
@@ -0,0 +1,54 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+#[test]
+fn test_encode_pts() {
+    assert_eq!(encode_pts(0), [0x21, 0x00, 0x01, 0x00, 0x01]);
+    assert_eq!(encode_pts(90_000), [0x21, 0x00, 0x05, 0xBF, 0x21]);
+    assert_eq!(encode_pts(u32::MAX), [0x27, 0xFF, 0xFF, 0xFF, 0xFF]);
+}
+
+#[test]
+fn test_decode_pts() {
+    for &pts in [0, 1, 90_000, 0x8000_0000, u32::MAX].iter() {
+        assert_eq!(decode_pts(&encode_pts(pts)), pts as u64);
+    }
+    assert_eq!(decode_pts(&[0x2F, 0xFF, 0xFF, 0xFF, 0xFF]), 0x1_FFFF_FFFF);
+}
+
+#[test]
+fn test_write_pes() {
+
+    let mut buffer = vec![];
+
+    write_pes(&mut buffer, 90_000, &[0x50, 0x47]).unwrap();
+
+    assert_eq!(
+        buffer,
+        [
+            0x00, 0x00, 0x01, 0xBD, 0x00, 0x0A, 0x85, 0x80, 0x05,
+            0x21, 0x00, 0x05, 0xBF, 0x21,
+            0x50, 0x47,
+        ],
+    );
+}
+
+#[test]
+fn test_write_pes_split() {
+
+    let payload = vec![0xAA; MAX_PAYLOAD + 10];
+    let mut buffer = vec![];
+
+    write_pes(&mut buffer, 0, &payload).unwrap();
+
+    let second = 9 + PTS_LENGTH + MAX_PAYLOAD;
+
+    assert_eq!(buffer[4..6], [0xFF, 0xFF]);
+    assert_eq!(buffer[second..second + 9], [0x00, 0x00, 0x01, 0xBD, 0x00, 0x0D, 0x81, 0x00, 0x00]);
+    assert_eq!(buffer.len(), second + 9 + 10);
+}
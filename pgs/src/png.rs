@@ -0,0 +1,388 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+mod inflate;
+
+use inflate::inflate;
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Error as IoError, Read, Result as IoResult, Write};
+use thiserror::Error as ThisError;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+#[derive(ThisError, Debug)]
+pub enum PngError {
+    #[error("PNG IO error")]
+    IoError {
+        #[from]
+        source: IoError,
+    },
+    #[error("not a PNG file")]
+    NotPng,
+    #[error("PNG chunk has a bad CRC")]
+    BadCrc,
+    #[error("PNG is missing its header")]
+    MissingHeader,
+    #[error("unsupported PNG format: color type {color_type}, bit depth {bit_depth}")]
+    UnsupportedFormat {
+        color_type: u8,
+        bit_depth: u8,
+    },
+    #[error("interlaced PNGs are not supported")]
+    Interlaced,
+    #[error("PNG image data is corrupt")]
+    BadImageData,
+    #[error("PNG image data ends early")]
+    TruncatedImageData,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PngImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+// Writes an 8-bit RGB image. The image data is stored rather than compressed, which keeps this
+// self-contained at the cost of file size.
+pub fn write_png(output: &mut impl Write, width: u32, height: u32, rgb: &[u8]) -> IoResult<()> {
+    write_image(output, width, height, 2, 3, rgb)
+}
+
+pub fn write_png_rgba(
+    output: &mut impl Write,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+) -> IoResult<()> {
+    write_image(output, width, height, 6, 4, rgba)
+}
+
+fn write_image(
+    output: &mut impl Write,
+    width: u32,
+    height: u32,
+    color_type: u8,
+    channels: usize,
+    pixels: &[u8],
+) -> IoResult<()> {
+
+    let stride = width as usize * channels;
+    let mut header = Vec::with_capacity(13);
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+
+    header.write_u32::<BigEndian>(width)?;
+    header.write_u32::<BigEndian>(height)?;
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+    for line in pixels.chunks(stride.max(1)).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+
+    output.write_all(&SIGNATURE)?;
+    write_chunk(output, b"IHDR", &header)?;
+    write_chunk(output, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(output, b"IEND", &[])?;
+
+    Ok(())
+}
+
+// Reads any non-interlaced PNG into 8-bit RGBA. Sixteen-bit samples keep their high byte.
+pub fn read_png(input: &mut impl Read) -> Result<PngImage, PngError> {
+
+    let mut signature = [0u8; 8];
+
+    input.read_exact(&mut signature)?;
+    if signature != SIGNATURE {
+        return Err(PngError::NotPng)
+    }
+
+    let mut header = None;
+    let mut palette = Vec::<[u8; 4]>::new();
+    let mut transparency = Vec::new();
+    let mut compressed = Vec::new();
+
+    loop {
+
+        let length = input.read_u32::<BigEndian>()? as usize;
+        let mut chunk = vec![0u8; length + 4];
+
+        input.read_exact(&mut chunk)?;
+        if input.read_u32::<BigEndian>()? != crc32(&chunk) {
+            return Err(PngError::BadCrc)
+        }
+
+        let data = &chunk[4..];
+
+        match &chunk[..4] {
+            b"IHDR" if length == 13 => header = Some(Header::parse(data)?),
+            b"PLTE" => {
+                palette = data.chunks_exact(3)
+                    .map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                    .collect();
+            }
+            b"tRNS" => transparency = data.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => (),
+        }
+    }
+
+    let header = header.ok_or(PngError::MissingHeader)?;
+
+    for (entry, &alpha) in palette.iter_mut().zip(transparency.iter()) {
+        entry[3] = alpha;
+    }
+
+    let raw = inflate(&compressed)?;
+    let lines = unfilter(&header, &raw)?;
+    let mut rgba = Vec::with_capacity(header.width as usize * header.height as usize * 4);
+
+    for line in lines.iter() {
+        for x in 0..header.width as usize {
+            rgba.extend_from_slice(&header.pixel(line, x, &palette, &transparency)?);
+        }
+    }
+
+    Ok(PngImage { width: header.width, height: header.height, rgba })
+}
+
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+
+    fn parse(data: &[u8]) -> Result<Self, PngError> {
+
+        let header = Header {
+            width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            bit_depth: data[8],
+            color_type: data[9],
+        };
+        let supported = match header.color_type {
+            0 => [1, 2, 4, 8, 16].contains(&header.bit_depth),
+            3 => [1, 2, 4, 8].contains(&header.bit_depth),
+            2 | 4 | 6 => [8, 16].contains(&header.bit_depth),
+            _ => false,
+        };
+
+        if !supported || data[10] != 0 || data[11] != 0 {
+            return Err(PngError::UnsupportedFormat {
+                color_type: header.color_type,
+                bit_depth: header.bit_depth,
+            })
+        }
+        if data[12] != 0 {
+            return Err(PngError::Interlaced)
+        }
+
+        Ok(header)
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    fn sample(&self, line: &[u8], index: usize) -> u16 {
+        match self.bit_depth {
+            16 => u16::from_be_bytes([line[index * 2], line[index * 2 + 1]]),
+            8 => line[index] as u16,
+            depth => {
+                let bit = index * depth as usize;
+                let shift = 8 - depth as usize - bit % 8;
+                ((line[bit / 8] >> shift) & ((1 << depth) - 1)) as u16
+            }
+        }
+    }
+
+    // Scales a sample to eight bits.
+    fn scale(&self, sample: u16) -> u8 {
+        match self.bit_depth {
+            16 => (sample >> 8) as u8,
+            depth => (sample as u32 * 255 / ((1 << depth) - 1)) as u8,
+        }
+    }
+
+    fn pixel(
+        &self,
+        line: &[u8],
+        x: usize,
+        palette: &[[u8; 4]],
+        transparency: &[u8],
+    ) -> Result<[u8; 4], PngError> {
+
+        let channels = self.channels();
+        let samples = (0..channels)
+            .map(|channel| self.sample(line, x * channels + channel))
+            .collect::<Vec<u16>>();
+        // Grayscale and RGB images name a single fully transparent color.
+        let keyed = |values: &[u16]| {
+            transparency.len() == values.len() * 2
+                && values.iter().enumerate().all(|(index, &value)| {
+                    u16::from_be_bytes([transparency[index * 2], transparency[index * 2 + 1]])
+                        == value
+                })
+        };
+
+        Ok(match self.color_type {
+            0 => {
+                let gray = self.scale(samples[0]);
+                [gray, gray, gray, if keyed(&samples) { 0 } else { 255 }]
+            }
+            2 => {
+                let alpha = if keyed(&samples) { 0 } else { 255 };
+                [self.scale(samples[0]), self.scale(samples[1]), self.scale(samples[2]), alpha]
+            }
+            3 => *palette.get(samples[0] as usize).ok_or(PngError::BadImageData)?,
+            4 => {
+                let gray = self.scale(samples[0]);
+                [gray, gray, gray, self.scale(samples[1])]
+            }
+            _ => [
+                self.scale(samples[0]),
+                self.scale(samples[1]),
+                self.scale(samples[2]),
+                self.scale(samples[3]),
+            ],
+        })
+    }
+}
+
+fn unfilter(header: &Header, raw: &[u8]) -> Result<Vec<Vec<u8>>, PngError> {
+
+    let stride = (header.width as usize * header.bits_per_pixel()).div_ceil(8);
+    // How far back the corresponding byte of the previous pixel is, at least one.
+    let distance = header.bits_per_pixel().div_ceil(8);
+    let mut lines = Vec::<Vec<u8>>::with_capacity(header.height as usize);
+    let mut rows = raw.chunks(stride + 1);
+
+    for _ in 0..header.height {
+
+        let row = rows.next().filter(|row| row.len() == stride + 1)
+            .ok_or(PngError::TruncatedImageData)?;
+        let mut line = row[1..].to_vec();
+        let above = lines.last().cloned().unwrap_or_else(|| vec![0; stride]);
+
+        for index in 0..stride {
+
+            let left = if index >= distance { line[index - distance] } else { 0 };
+            let upper_left = if index >= distance { above[index - distance] } else { 0 };
+
+            line[index] = line[index].wrapping_add(match row[0] {
+                0 => 0,
+                1 => left,
+                2 => above[index],
+                3 => ((left as u16 + above[index] as u16) / 2) as u8,
+                4 => paeth(left, above[index], upper_left),
+                _ => return Err(PngError::BadImageData),
+            });
+        }
+
+        lines.push(line);
+    }
+
+    Ok(lines)
+}
+
+fn paeth(left: u8, above: u8, upper_left: u8) -> u8 {
+
+    let estimate = left as i16 + above as i16 - upper_left as i16;
+    let to_left = (estimate - left as i16).abs();
+    let to_above = (estimate - above as i16).abs();
+    let to_upper_left = (estimate - upper_left as i16).abs();
+
+    if to_left <= to_above && to_left <= to_upper_left {
+        left
+    } else if to_above <= to_upper_left {
+        above
+    } else {
+        upper_left
+    }
+}
+
+fn write_chunk(output: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> IoResult<()> {
+
+    let mut crc_data = Vec::with_capacity(data.len() + 4);
+
+    crc_data.extend_from_slice(kind);
+    crc_data.extend_from_slice(data);
+
+    output.write_u32::<BigEndian>(data.len() as u32)?;
+    output.write_all(&crc_data)?;
+    output.write_u32::<BigEndian>(crc32(&crc_data))?;
+
+    Ok(())
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+
+    let mut output = vec![0x78, 0x01];
+    let mut blocks = data.chunks(65_535).peekable();
+
+    if blocks.peek().is_none() {
+        output.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        output.push(if blocks.peek().is_none() { 0x01 } else { 0x00 });
+        output.write_u16::<LittleEndian>(block.len() as u16).unwrap();
+        output.write_u16::<LittleEndian>(!(block.len() as u16)).unwrap();
+        output.extend_from_slice(block);
+    }
+
+    output.write_u32::<BigEndian>(adler32(data)).unwrap();
+
+    output
+}
+
+fn crc32(data: &[u8]) -> u32 {
+
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+
+    let mut a = 1u32;
+    let mut b = 0u32;
+
+    for chunk in data.chunks(5_552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65_521;
+        b %= 65_521;
+    }
+
+    (b << 16) | a
+}
@@ -0,0 +1,285 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+use super::PngError;
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order in which a dynamic block lists the code lengths of its code length alphabet.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u8,
+}
+
+impl<'a> BitReader<'a> {
+
+    fn bit(&mut self) -> Result<u32, PngError> {
+
+        let byte = *self.data.get(self.position).ok_or(PngError::TruncatedImageData)?;
+        let bit = (byte >> self.bit) & 1;
+
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.position += 1;
+        }
+
+        Ok(bit as u32)
+    }
+
+    fn bits(&mut self, count: u8) -> Result<u32, PngError> {
+
+        let mut value = 0;
+
+        for index in 0..count {
+            value |= self.bit()? << index;
+        }
+
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+}
+
+// A canonical Huffman code, stored as how many codes there are of each length and the symbols in
+// code order.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+
+    fn new(lengths: &[u8]) -> Result<Self, PngError> {
+
+        let mut counts = [0u16; MAX_BITS + 1];
+        let mut offsets = [0u16; MAX_BITS + 2];
+        let mut symbols = vec![0; lengths.len()];
+        let mut left = 1i32;
+
+        for &length in lengths.iter() {
+            counts[length as usize] += 1;
+        }
+        for &count in counts[1..].iter() {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err(PngError::BadImageData)
+            }
+        }
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, input: &mut BitReader) -> Result<u16, PngError> {
+
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+
+        for length in 1..=MAX_BITS {
+
+            let count = self.counts[length] as i32;
+
+            code |= input.bit()? as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize])
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(PngError::BadImageData)
+    }
+}
+
+// Decompresses a zlib stream, ignoring its checksum in favor of the PNG chunk CRCs.
+pub fn inflate(input: &[u8]) -> Result<Vec<u8>, PngError> {
+
+    if input.len() < 2
+        || input[0] & 0x0F != 8
+        || !u16::from_be_bytes([input[0], input[1]]).is_multiple_of(31)
+        || input[1] & 0x20 != 0 {
+        return Err(PngError::BadImageData)
+    }
+
+    let mut reader = BitReader { data: &input[2..], position: 0, bit: 0 };
+    let mut output = Vec::new();
+
+    loop {
+
+        let last = reader.bit()? == 1;
+
+        match reader.bits(2)? {
+            0 => stored_block(&mut reader, &mut output)?,
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                compressed_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                compressed_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(PngError::BadImageData),
+        }
+
+        if last {
+            break
+        }
+    }
+
+    Ok(output)
+}
+
+fn stored_block(input: &mut BitReader, output: &mut Vec<u8>) -> Result<(), PngError> {
+
+    input.align();
+
+    let header = input.data.get(input.position..input.position + 4)
+        .ok_or(PngError::TruncatedImageData)?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+
+    if length != !u16::from_le_bytes([header[2], header[3]]) {
+        return Err(PngError::BadImageData)
+    }
+
+    let start = input.position + 4;
+    let data = input.data.get(start..start + length as usize)
+        .ok_or(PngError::TruncatedImageData)?;
+
+    output.extend_from_slice(data);
+    input.position = start + length as usize;
+
+    Ok(())
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), PngError> {
+
+    let mut lengths = [0u8; 288];
+
+    lengths[..144].iter_mut().for_each(|length| *length = 8);
+    lengths[144..256].iter_mut().for_each(|length| *length = 9);
+    lengths[256..280].iter_mut().for_each(|length| *length = 7);
+    lengths[280..].iter_mut().for_each(|length| *length = 8);
+
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(input: &mut BitReader) -> Result<(Huffman, Huffman), PngError> {
+
+    let literal_count = input.bits(5)? as usize + 257;
+    let distance_count = input.bits(5)? as usize + 1;
+    let code_length_count = input.bits(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+
+    for &index in CODE_LENGTH_ORDER[..code_length_count].iter() {
+        code_lengths[index] = input.bits(3)? as u8;
+    }
+
+    let code_length_code = Huffman::new(&code_lengths)?;
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+
+    while lengths.len() < literal_count + distance_count {
+
+        let (length, repeat) = match code_length_code.decode(input)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or(PngError::BadImageData)?, 3 + input.bits(2)?),
+            17 => (0, 3 + input.bits(3)?),
+            _ => (0, 11 + input.bits(7)?),
+        };
+
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+
+    if lengths.len() > literal_count + distance_count || lengths[256] == 0 {
+        return Err(PngError::BadImageData)
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn compressed_block(
+    input: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), PngError> {
+
+    loop {
+
+        let symbol = literals.decode(input)? as usize;
+
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue
+        }
+        if symbol == 256 {
+            return Ok(())
+        }
+
+        let index = symbol - 257;
+
+        if index >= LENGTH_BASE.len() {
+            return Err(PngError::BadImageData)
+        }
+
+        let length = LENGTH_BASE[index] as usize + input.bits(LENGTH_EXTRA[index])? as usize;
+        let index = distances.decode(input)? as usize;
+
+        if index >= DISTANCE_BASE.len() {
+            return Err(PngError::BadImageData)
+        }
+
+        let distance = DISTANCE_BASE[index] as usize + input.bits(DISTANCE_EXTRA[index])? as usize;
+
+        if distance > output.len() {
+            return Err(PngError::BadImageData)
+        }
+
+        let start = output.len() - distance;
+
+        for offset in 0..length {
+            output.push(output[start + offset]);
+        }
+    }
+}
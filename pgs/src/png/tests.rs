@@ -0,0 +1,163 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+#[test]
+fn test_checksums() {
+    assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+}
+
+#[test]
+fn test_write_png() {
+
+    let mut buffer = vec![];
+
+    write_png(&mut buffer, 2, 1, &[255, 0, 0, 0, 0, 255]).unwrap();
+
+    assert_eq!(buffer[..8], SIGNATURE);
+    assert_eq!(buffer[12..16], *b"IHDR");
+    assert_eq!(buffer[16..24], [0, 0, 0, 2, 0, 0, 0, 1]);
+    assert_eq!(
+        buffer[buffer.len() - 12..],
+        [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82],
+    );
+}
+
+#[test]
+fn test_write_png_rgba() {
+
+    let mut buffer = vec![];
+
+    write_png_rgba(&mut buffer, 1, 1, &[255, 0, 0, 128]).unwrap();
+
+    assert_eq!(buffer[24..26], [8, 6]);
+}
+
+#[test]
+fn test_zlib_stored_blocks() {
+
+    let data = vec![7u8; 70_000];
+    let output = zlib_stored(&data);
+
+    // Two stored blocks, each with a five byte header, plus the zlib header and checksum.
+    assert_eq!(output.len(), data.len() + 2 + 5 * 2 + 4);
+    assert_eq!(output[2], 0x00);
+    assert_eq!(output[2 + 5 + 65_535], 0x01);
+}
+
+#[test]
+fn test_inflate_stored_blocks() {
+
+    let data = (0..70_000).map(|index| index as u8).collect::<Vec<u8>>();
+
+    assert_eq!(inflate(&zlib_stored(&data)).unwrap(), data);
+    assert!(inflate(&zlib_stored(&[])).unwrap().is_empty());
+}
+
+#[test]
+fn test_inflate_fixed_block() {
+
+    let compressed = [
+        0x78, 0xDA, 0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0xC8, 0x40, 0x27, 0x01, 0x68, 0x03, 0x08,
+        0xB1,
+    ];
+
+    assert_eq!(inflate(&compressed).unwrap(), b"hello hello hello hello");
+}
+
+#[test]
+fn test_inflate_dynamic_block() {
+
+    let compressed = [
+        0x78, 0xDA, 0x75, 0xCB, 0xD1, 0x09, 0x80, 0x30, 0x0C, 0x45, 0xD1, 0x55, 0x32, 0x80, 0x8B,
+        0x38, 0x46, 0xAB, 0xAF, 0x26, 0xD0, 0x9A, 0x62, 0x02, 0x59, 0x5F, 0xF2, 0x25, 0x82, 0x7E,
+        0x9F, 0x7B, 0x57, 0xA7, 0x28, 0x46, 0xCE, 0xA0, 0x0A, 0x73, 0xD2, 0x46, 0x2E, 0x03, 0xB6,
+        0x90, 0x3C, 0x12, 0x7A, 0xFD, 0x51, 0x39, 0x90, 0x10, 0x62, 0xBB, 0x8E, 0x2F, 0x69, 0xAA,
+        0x5D, 0x8C, 0x4F, 0xD8, 0x7B, 0xC4, 0xD4, 0x8D, 0x33, 0xA8, 0xE8, 0x82, 0x96, 0x76, 0x03,
+        0x6D, 0x79, 0x30, 0xD1,
+    ];
+
+    assert_eq!(
+        inflate(&compressed).unwrap(),
+        &b"It was the best of times, it was the worst of times, it was the age of wisdom, \
+        it was the age of foolishness, it was the epoch of belief, it"[..],
+    );
+}
+
+#[test]
+fn test_inflate_rejects_truncated_data() {
+    assert!(matches!(
+        inflate(&[0x78, 0xDA, 0xCB, 0x48, 0xCD]),
+        Err(PngError::TruncatedImageData),
+    ));
+}
+
+#[test]
+fn test_read_png_round_trips() {
+
+    let rgba = [255, 0, 0, 128, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+    let mut buffer = vec![];
+
+    write_png_rgba(&mut buffer, 3, 2, &rgba).unwrap();
+
+    assert_eq!(
+        read_png(&mut buffer.as_slice()).unwrap(),
+        PngImage { width: 3, height: 2, rgba: rgba.to_vec() },
+    );
+}
+
+#[test]
+fn test_read_png_filters() {
+
+    // Two rows of RGBA, the first with the sub filter and the second with the Paeth filter.
+    let buffer = [
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x08, 0x06, 0x00, 0x00, 0x00, 0x72,
+        0xB6, 0x0D, 0x24, 0x00, 0x00, 0x00, 0x19, 0x49, 0x44, 0x41, 0x54, 0x78, 0xDA, 0x63, 0xFC,
+        0xCF, 0xC0, 0xF0, 0x9F, 0xF1, 0x3F, 0x43, 0x23, 0x0B, 0x23, 0x90, 0xE6, 0x12, 0x95, 0xD7,
+        0x00, 0x00, 0x37, 0x08, 0x04, 0xEC, 0x41, 0x28, 0xD9, 0x62, 0x00, 0x00, 0x00, 0x00, 0x49,
+        0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+    ];
+
+    assert_eq!(
+        read_png(&mut &buffer[..]).unwrap().rgba,
+        [255, 0, 0, 255, 0, 255, 0, 128, 0, 0, 255, 0, 10, 20, 30, 40],
+    );
+}
+
+#[test]
+fn test_read_png_palette_with_transparency() {
+
+    // Three two-bit palette indices, with alpha given for the first two entries.
+    let buffer = [
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0x00, 0x00, 0x00, 0x66,
+        0x8E, 0xFC, 0x27, 0x00, 0x00, 0x00, 0x09, 0x50, 0x4C, 0x54, 0x45, 0x01, 0x02, 0x03, 0xC8,
+        0x64, 0x32, 0x09, 0x09, 0x09, 0x8B, 0x31, 0xA5, 0x7F, 0x00, 0x00, 0x00, 0x02, 0x74, 0x52,
+        0x4E, 0x53, 0x00, 0x80, 0x9B, 0x2B, 0x4E, 0x18, 0x00, 0x00, 0x00, 0x0A, 0x49, 0x44, 0x41,
+        0x54, 0x78, 0xDA, 0x63, 0x90, 0x00, 0x00, 0x00, 0x1A, 0x00, 0x19, 0x80, 0x00, 0x8E, 0xBB,
+        0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+    ];
+
+    assert_eq!(
+        read_png(&mut &buffer[..]).unwrap().rgba,
+        [1, 2, 3, 0, 200, 100, 50, 128, 9, 9, 9, 255],
+    );
+}
+
+#[test]
+fn test_read_png_rejects_bad_crc() {
+
+    let mut buffer = vec![];
+
+    write_png_rgba(&mut buffer, 1, 1, &[0, 0, 0, 0]).unwrap();
+    buffer[29] ^= 1;
+
+    assert!(matches!(read_png(&mut buffer.as_slice()), Err(PngError::BadCrc)));
+    assert!(matches!(read_png(&mut &b"GIF89a.."[..]), Err(PngError::NotPng)));
+}
@@ -0,0 +1,18 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+// The extension traits that read and write segments, display sets, and epochs, along with the
+// types they read and write, so that `use pgs::prelude::*;` is all most callers need. The async
+// traits are left out, as their methods share names with these and would be ambiguous for any
+// type that is both Read and AsyncRead; they are imported from asyncread and asyncwrite instead.
+
+pub use super::segment::{FrameRate, ReadOptions, Segment, SegmentKind};
+#[cfg(feature = "std")]
+pub use super::{
+    displayset::{DisplaySet, ReadDisplaySetExt, WriteDisplaySetExt},
+    epoch::{Epoch, EpochReader, ReadEpochExt, WriteEpochExt},
+    segment::{ReadSegmentExt, WriteSegmentExt},
+};
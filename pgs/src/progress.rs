@@ -0,0 +1,104 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    io::{Read, Result as IoResult},
+    time::{Duration, Instant},
+};
+
+// Counts the bytes read through it. Meant to go beneath any buffering, where it only sees each
+// buffer refill.
+pub struct CountingReader<R: Read> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+
+    pub fn new(inner: R) -> Self {
+        Self { inner, count: 0 }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+
+        let read = self.inner.read(buf)?;
+
+        self.count += read as u64;
+
+        Ok(read)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ProgressUpdate {
+    // Bytes of input read so far.
+    pub offset: u64,
+    pub pts: u32,
+    pub display_sets: usize,
+}
+
+pub trait Progress {
+    fn report(&mut self, update: &ProgressUpdate);
+}
+
+impl<F: FnMut(&ProgressUpdate)> Progress for F {
+
+    fn report(&mut self, update: &ProgressUpdate) {
+        self(update)
+    }
+}
+
+// Passes along an update once enough display sets have been read or enough time has gone by
+// since the last one, whichever comes first.
+pub struct ProgressThrottle<P: Progress> {
+    inner: P,
+    every: usize,
+    interval: Duration,
+    display_sets: usize,
+    reported_display_sets: usize,
+    reported_at: Instant,
+}
+
+impl<P: Progress> ProgressThrottle<P> {
+
+    pub fn new(inner: P, every: usize, interval: Duration) -> Self {
+        Self {
+            inner,
+            every,
+            interval,
+            display_sets: 0,
+            reported_display_sets: 0,
+            reported_at: Instant::now(),
+        }
+    }
+
+    // Called after each display set is read.
+    pub fn display_set(&mut self, offset: u64, pts: u32) {
+
+        self.display_sets += 1;
+
+        if self.display_sets - self.reported_display_sets >= self.every
+            || self.reported_at.elapsed() >= self.interval {
+            self.inner.report(&ProgressUpdate { offset, pts, display_sets: self.display_sets });
+            self.reported_display_sets = self.display_sets;
+            self.reported_at = Instant::now();
+        }
+    }
+}
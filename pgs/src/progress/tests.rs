@@ -0,0 +1,87 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use std::io::BufReader;
+
+#[test]
+fn test_counting_reader_beneath_buffer() {
+
+    let data = vec![7u8; 100_000];
+    let mut reader = BufReader::with_capacity(8_192, CountingReader::new(&data[..]));
+    let mut byte = [0u8; 1];
+
+    reader.read_exact(&mut byte).unwrap();
+
+    assert_eq!(reader.get_ref().count(), 8_192);
+
+    let mut rest = vec![];
+
+    reader.read_to_end(&mut rest).unwrap();
+
+    assert_eq!(rest.len(), 99_999);
+    assert_eq!(reader.get_ref().count(), 100_000);
+}
+
+#[test]
+fn test_throttle_every() {
+
+    let mut updates = vec![];
+    let mut throttle = ProgressThrottle::new(
+        |update: &ProgressUpdate| updates.push(*update),
+        3,
+        Duration::from_secs(3_600),
+    );
+
+    for index in 0..7u32 {
+        throttle.display_set(index as u64 * 100, index * 90_000);
+    }
+
+    assert_eq!(
+        updates,
+        vec![
+            ProgressUpdate { offset: 200, pts: 180_000, display_sets: 3 },
+            ProgressUpdate { offset: 500, pts: 450_000, display_sets: 6 },
+        ],
+    );
+}
+
+#[test]
+fn test_throttle_interval() {
+
+    let mut count = 0;
+    let mut throttle = ProgressThrottle::new(
+        |_: &ProgressUpdate| count += 1,
+        usize::MAX,
+        Duration::ZERO,
+    );
+
+    for _ in 0..4 {
+        throttle.display_set(0, 0);
+    }
+
+    assert_eq!(count, 4);
+}
+
+#[test]
+fn test_counting_reader_counts_what_passes_through() {
+
+    let data = (0..=255u8).collect::<Vec<u8>>();
+    let mut reader = CountingReader::new(&data[..]);
+    let mut buffer = [0u8; 100];
+
+    assert_eq!(reader.count(), 0);
+    assert_eq!(reader.read(&mut buffer).unwrap(), 100);
+    assert_eq!(reader.count(), 100);
+    assert_eq!(reader.read(&mut buffer).unwrap(), 100);
+    assert_eq!(reader.read(&mut buffer).unwrap(), 56);
+    assert_eq!(buffer[55], 255);
+    assert_eq!(reader.count(), 256);
+
+    // Reads at the end of the input add nothing.
+    assert_eq!(reader.read(&mut buffer).unwrap(), 0);
+    assert_eq!(reader.count(), 256);
+}
@@ -0,0 +1,210 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use alloc::vec::Vec;
+use core::slice::Iter;
+use thiserror::Error as ThisError;
+
+// The longest run a single code can describe.
+const MAX_RUN: usize = 0x3FFF;
+
+#[derive(ThisError, Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DecodeError {
+    #[error("incomplete RLE sequence")]
+    IncompleteSequence,
+    #[error("RLE data ends partway through a line")]
+    IncompleteLine,
+    #[error("RLE line {line} does not match the object width")]
+    LineWidthMismatch {
+        line: usize,
+    },
+    #[error("RLE data has {lines} lines where the object height calls for {height}")]
+    LineCountMismatch {
+        lines: usize,
+        height: u16,
+    },
+}
+
+#[derive(ThisError, Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EncodeError {
+    #[error("cannot encode an image without pixels")]
+    EmptyImage,
+    #[error("image has {length} pixels where its dimensions call for {expected}")]
+    LengthMismatch {
+        length: usize,
+        expected: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Run {
+    Pixels {
+        length: usize,
+        index: u8,
+    },
+    EndOfLine,
+}
+
+// Walks the codes of an RLE stream. A nonzero byte is a single pixel of that index. Otherwise,
+// the zero is followed by a byte whose top two bits say whether the run is of index 0 or of an
+// index given in a trailing byte, and whether its length is the remaining six bits or those six
+// bits followed by another eight. A zero in place of that byte ends the line.
+pub(crate) struct Runs<'a> {
+    input: Iter<'a, u8>,
+}
+
+impl<'a> Runs<'a> {
+
+    pub(crate) fn new(input: &'a [u8]) -> Self {
+        Runs { input: input.iter() }
+    }
+
+    fn next_byte(&mut self) -> Result<u8, DecodeError> {
+        self.input.next().copied().ok_or(DecodeError::IncompleteSequence)
+    }
+}
+
+impl Iterator for Runs<'_> {
+
+    type Item = Result<Run, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        let byte_1 = *self.input.next()?;
+
+        if byte_1 != 0x00 {
+            return Some(Ok(Run::Pixels { length: 1, index: byte_1 }))
+        }
+
+        let mut run = || {
+
+            let byte_2 = self.next_byte()?;
+
+            if byte_2 == 0x00 {
+                return Ok(Run::EndOfLine)
+            }
+
+            let short = (byte_2 & 0x3F) as usize;
+            let length = if byte_2 & 0x40 == 0 {
+                short
+            } else {
+                short << 8 | self.next_byte()? as usize
+            };
+            let index = if byte_2 & 0x80 == 0 {
+                0
+            } else {
+                self.next_byte()?
+            };
+
+            Ok(Run::Pixels { length, index })
+        };
+
+        Some(run())
+    }
+}
+
+// Decodes RLE data into one palette index per pixel, row by row. Every line must hold exactly
+// `width` pixels and there must be exactly `height` lines, so the output never grows past
+// `width` times `height` bytes whatever the data claims.
+pub fn decode(data: &[u8], width: u16, height: u16) -> Result<Vec<u8>, DecodeError> {
+
+    let mut output = Vec::new();
+    let mut lines = 0;
+    let mut length = 0;
+
+    for run in Runs::new(data) {
+        match run? {
+            Run::Pixels { length: run_length, index } => {
+                if lines == height as usize {
+                    return Err(DecodeError::LineCountMismatch { lines: lines + 1, height })
+                }
+                if length + run_length > width as usize {
+                    return Err(DecodeError::LineWidthMismatch { line: lines })
+                }
+                output.resize(output.len() + run_length, index);
+                length += run_length;
+            }
+            Run::EndOfLine => {
+                if length != width as usize {
+                    return Err(DecodeError::LineWidthMismatch { line: lines })
+                }
+                if lines == height as usize {
+                    return Err(DecodeError::LineCountMismatch { lines: lines + 1, height })
+                }
+                lines += 1;
+                length = 0;
+            }
+        }
+    }
+
+    if length > 0 {
+        return Err(DecodeError::IncompleteLine)
+    }
+    if lines != height as usize {
+        return Err(DecodeError::LineCountMismatch { lines, height })
+    }
+
+    Ok(output)
+}
+
+// Encodes one palette index per pixel, row by row, as RLE data with each line ended by its marker.
+pub fn encode(pixels: &[u8], width: u16, height: u16) -> Result<Vec<u8>, EncodeError> {
+
+    if width == 0 || height == 0 {
+        return Err(EncodeError::EmptyImage)
+    }
+
+    let expected = width as usize * height as usize;
+
+    if pixels.len() != expected {
+        return Err(EncodeError::LengthMismatch { length: pixels.len(), expected })
+    }
+
+    let mut output = Vec::new();
+
+    for line in pixels.chunks(width as usize) {
+        encode_line(&mut output, line);
+    }
+
+    Ok(output)
+}
+
+pub(crate) fn encode_line(output: &mut Vec<u8>, line: &[u8]) {
+
+    let mut start = 0;
+
+    while start < line.len() {
+
+        let index = line[start];
+        let length = line[start..].iter().take_while(|&&next| next == index).count();
+
+        // Runs longer than a code can hold are split, which can leave a short remainder.
+        for chunk_start in (0..length).step_by(MAX_RUN) {
+            encode_run(output, index, MAX_RUN.min(length - chunk_start));
+        }
+
+        start += length;
+    }
+
+    output.push(0x00);
+    output.push(0x00);
+}
+
+// Uses whichever code is shortest for the run. Index 0 can never be a single raw byte.
+fn encode_run(output: &mut Vec<u8>, index: u8, length: usize) {
+    match (index, length) {
+        (0, 1..=63) => output.extend_from_slice(&[0x00, length as u8]),
+        (0, _) => output.extend_from_slice(&[0x00, 0x40 | (length >> 8) as u8, length as u8]),
+        (_, 1..=2) => output.resize(output.len() + length, index),
+        (_, 3..=63) => output.extend_from_slice(&[0x00, 0x80 | length as u8, index]),
+        (_, _) => {
+            output.extend_from_slice(&[0x00, 0xC0 | (length >> 8) as u8, length as u8, index])
+        }
+    }
+}
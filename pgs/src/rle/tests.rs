@@ -0,0 +1,157 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use rand::{thread_rng, Rng};
+
+#[test]
+fn test_runs() {
+
+    let data = [
+        0x05, 0x00, 0x03, 0x00, 0x40, 0x80, 0x00, 0x82, 0x07, 0x00, 0xC1, 0x00, 0x09, 0x00, 0x00,
+    ];
+
+    assert_eq!(
+        Runs::new(&data).collect::<Result<Vec<Run>, DecodeError>>(),
+        Ok(vec![
+            Run::Pixels { length: 1, index: 5 },
+            Run::Pixels { length: 3, index: 0 },
+            Run::Pixels { length: 128, index: 0 },
+            Run::Pixels { length: 2, index: 7 },
+            Run::Pixels { length: 256, index: 9 },
+            Run::EndOfLine,
+        ]),
+    );
+}
+
+#[test]
+fn test_decode() {
+
+    // Two lines of four: 1 0 0 2, then four of index 3.
+    let data = [0x01, 0x00, 0x02, 0x02, 0x00, 0x00, 0x00, 0x84, 0x03, 0x00, 0x00];
+
+    assert_eq!(decode(&data, 4, 2), Ok(vec![1, 0, 0, 2, 3, 3, 3, 3]));
+    assert_eq!(decode(&[0x00, 0x00], 0, 1), Ok(vec![]));
+    assert_eq!(decode(&[], 7, 0), Ok(vec![]));
+}
+
+#[test]
+fn test_decode_checks_dimensions() {
+
+    let data = [0x01, 0x00, 0x02, 0x02, 0x00, 0x00, 0x00, 0x84, 0x03, 0x00, 0x00];
+
+    assert_eq!(decode(&data, 5, 2), Err(DecodeError::LineWidthMismatch { line: 0 }));
+    assert_eq!(decode(&data, 3, 2), Err(DecodeError::LineWidthMismatch { line: 0 }));
+    assert_eq!(decode(&data, 4, 3), Err(DecodeError::LineCountMismatch { lines: 2, height: 3 }));
+    assert_eq!(decode(&data, 4, 1), Err(DecodeError::LineCountMismatch { lines: 2, height: 1 }));
+    assert_eq!(decode(&data[..9], 4, 2), Err(DecodeError::IncompleteLine));
+}
+
+#[test]
+fn test_decode_incomplete_sequences() {
+    for data in [&[0x00][..], &[0x00, 0x40], &[0x00, 0x80], &[0x00, 0xC0, 0x01]].iter() {
+        assert_eq!(decode(data, 64, 1), Err(DecodeError::IncompleteSequence));
+    }
+}
+
+#[test]
+fn test_decode_bomb() {
+
+    // Every four bytes claim 16,383 pixels, which would be about a gigabyte in total, but the
+    // first run is already wider than the object.
+    let data = [0x00, 0xFF, 0xFF, 0x01].repeat(65_536);
+
+    assert_eq!(decode(&data, 1920, 1080), Err(DecodeError::LineWidthMismatch { line: 0 }));
+}
+
+#[test]
+fn test_decode_random_data() {
+
+    let mut rng = thread_rng();
+
+    for _ in 0..10_000 {
+
+        let length = rng.gen_range(0..64);
+        let data = (0..length)
+            .map(|_| if rng.gen_bool(0.5) { 0x00 } else { rng.gen() })
+            .collect::<Vec<u8>>();
+        let (width, height) = (rng.gen_range(0..32), rng.gen_range(0..8));
+
+        if let Ok(pixels) = decode(&data, width, height) {
+            assert_eq!(pixels.len(), width as usize * height as usize);
+        }
+    }
+}
+
+#[test]
+fn test_encode_picks_shortest_codes() {
+    assert_eq!(encode(&[5], 1, 1), Ok(vec![0x05, 0x00, 0x00]));
+    assert_eq!(encode(&[5, 5], 2, 1), Ok(vec![0x05, 0x05, 0x00, 0x00]));
+    assert_eq!(encode(&[5, 5, 5], 3, 1), Ok(vec![0x00, 0x83, 0x05, 0x00, 0x00]));
+    assert_eq!(encode(&[0], 1, 1), Ok(vec![0x00, 0x01, 0x00, 0x00]));
+    assert_eq!(encode(&[0; 63], 63, 1), Ok(vec![0x00, 0x3F, 0x00, 0x00]));
+    assert_eq!(encode(&[0; 64], 64, 1), Ok(vec![0x00, 0x40, 0x40, 0x00, 0x00]));
+    assert_eq!(encode(&[9; 64], 64, 1), Ok(vec![0x00, 0xC0, 0x40, 0x09, 0x00, 0x00]));
+}
+
+#[test]
+fn test_encode_splits_long_runs() {
+
+    let pixels = [7; 16_385];
+
+    assert_eq!(
+        encode(&pixels, 16_385, 1),
+        Ok(vec![0x00, 0xFF, 0xFF, 0x07, 0x07, 0x07, 0x00, 0x00]),
+    );
+    assert_eq!(decode(&encode(&[0; 65_535], 65_535, 1).unwrap(), 65_535, 1), Ok(vec![0; 65_535]));
+}
+
+#[test]
+fn test_encode_rejects_bad_dimensions() {
+    assert_eq!(encode(&[], 0, 4), Err(EncodeError::EmptyImage));
+    assert_eq!(encode(&[], 4, 0), Err(EncodeError::EmptyImage));
+    assert_eq!(encode(&[1; 7], 4, 2), Err(EncodeError::LengthMismatch { length: 7, expected: 8 }));
+}
+
+#[test]
+fn test_encode_decode_cycle() {
+
+    let mut rng = thread_rng();
+
+    for _ in 0..200 {
+
+        let width = rng.gen_range(1..40_000);
+        let height = rng.gen_range(1..4);
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+
+        // Lines of a single color, of noise, and of long runs of few colors.
+        for _ in 0..height {
+
+            let mut line = Vec::with_capacity(width as usize);
+
+            match rng.gen_range(0..3) {
+                0 => line.resize(width as usize, rng.gen()),
+                1 => line.extend((0..width).map(|_| rng.gen::<u8>())),
+                _ => {
+                    while line.len() < width as usize {
+                        let length = rng.gen_range(1..20_000).min(width as usize - line.len());
+                        line.resize(line.len() + length, rng.gen_range(0..3));
+                    }
+                }
+            }
+
+            pixels.extend(line);
+        }
+
+        let data = encode(&pixels, width, height).unwrap();
+        // Each pixel in a code of its own, which takes two bytes for index 0 and one otherwise.
+        let trivial = pixels.iter().map(|&index| if index == 0 { 2 } else { 1 }).sum::<usize>()
+            + 2 * height as usize;
+
+        assert!(data.len() <= trivial, "{} > {}", data.len(), trivial);
+        assert_eq!(decode(&data, width, height), Ok(pixels));
+    }
+}
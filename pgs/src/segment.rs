@@ -7,66 +7,435 @@
 #[cfg(test)]
 mod tests;
 
+mod segmentparse;
+#[cfg(feature = "std")]
 mod segmentread;
+#[cfg(feature = "std")]
 mod segmentwrite;
 
+pub use segmentparse::*;
+#[cfg(feature = "std")]
 pub use segmentread::*;
+#[cfg(feature = "std")]
 pub use segmentwrite::*;
 
-#[derive(Clone, Debug, Hash, PartialEq)]
+use super::id::{ObjectId, PaletteId, WindowId};
+use alloc::{vec, vec::Vec};
+use core::{
+    fmt::{Debug, Display, Formatter, Result as FmtResult},
+    hash::{Hash, Hasher},
+};
+use thiserror::Error as ThisError;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Segment {
     PresentationComposition(PresentationCompositionSegment),
     WindowDefinition(WindowDefinitionSegment),
     PaletteDefinition(PaletteDefinitionSegment),
     ObjectDefinition(ObjectDefinitionSegment),
     End(EndSegment),
+    // Only read with ReadOptions::keep_unknown.
+    Unknown(UnknownSegment),
+}
+
+impl Segment {
+
+    pub fn kind(&self) -> SegmentKind {
+        match self {
+            Segment::PresentationComposition(_) => SegmentKind::PresentationComposition,
+            Segment::WindowDefinition(_) => SegmentKind::WindowDefinition,
+            Segment::PaletteDefinition(_) => SegmentKind::PaletteDefinition,
+            Segment::ObjectDefinition(_) => SegmentKind::ObjectDefinition,
+            Segment::End(_) => SegmentKind::End,
+            Segment::Unknown(us) => SegmentKind::Unknown(us.kind),
+        }
+    }
+
+    // The header and payload this segment was read from, provided it was read with
+    // ReadOptions::keep_raw and has not been changed since.
+    pub fn raw(&self) -> Option<&[u8]> {
+
+        let segments = match self {
+            Segment::PresentationComposition(pcs) => pcs.raw.get(pcs),
+            Segment::WindowDefinition(wds) => wds.raw.get(wds),
+            Segment::PaletteDefinition(pds) => pds.raw.get(pds),
+            Segment::ObjectDefinition(ods) => ods.raw.get(ods),
+            Segment::End(es) => es.raw.get(es),
+            Segment::Unknown(us) => us.raw.get(us),
+        };
+
+        segments.and_then(|segments| segments.first()).map(|segment| segment.as_slice())
+    }
+
+    pub(crate) fn set_raw(&mut self, bytes: Vec<u8>) {
+        match self {
+            Segment::PresentationComposition(pcs) => pcs.raw = Raw::new(vec![bytes], pcs),
+            Segment::WindowDefinition(wds) => wds.raw = Raw::new(vec![bytes], wds),
+            Segment::PaletteDefinition(pds) => pds.raw = Raw::new(vec![bytes], pds),
+            Segment::ObjectDefinition(ods) => ods.raw = Raw::new(vec![bytes], ods),
+            Segment::End(es) => es.raw = Raw::new(vec![bytes], es),
+            Segment::Unknown(us) => us.raw = Raw::new(vec![bytes], us),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn take_raw(&mut self) -> Option<Vec<u8>> {
+
+        let bytes = self.raw().map(|bytes| bytes.to_vec());
+
+        match self {
+            Segment::PresentationComposition(pcs) => pcs.raw = Raw::default(),
+            Segment::WindowDefinition(wds) => wds.raw = Raw::default(),
+            Segment::PaletteDefinition(pds) => pds.raw = Raw::default(),
+            Segment::ObjectDefinition(ods) => ods.raw = Raw::default(),
+            Segment::End(es) => es.raw = Raw::default(),
+            Segment::Unknown(us) => us.raw = Raw::default(),
+        }
+
+        bytes
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SegmentKind {
+    PresentationComposition,
+    WindowDefinition,
+    PaletteDefinition,
+    ObjectDefinition,
+    End,
+    // Any other code, which the crate does not model.
+    Unknown(u8),
+}
+
+impl SegmentKind {
+
+    // The kind a segment header gives, which follows the magic number, PTS, and DTS.
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x14 => SegmentKind::PaletteDefinition,
+            0x15 => SegmentKind::ObjectDefinition,
+            0x16 => SegmentKind::PresentationComposition,
+            0x17 => SegmentKind::WindowDefinition,
+            0x80 => SegmentKind::End,
+            code => SegmentKind::Unknown(code),
+        }
+    }
+
+    pub const fn code(self) -> u8 {
+        match self {
+            SegmentKind::PaletteDefinition => 0x14,
+            SegmentKind::ObjectDefinition => 0x15,
+            SegmentKind::PresentationComposition => 0x16,
+            SegmentKind::WindowDefinition => 0x17,
+            SegmentKind::End => 0x80,
+            SegmentKind::Unknown(code) => code,
+        }
+    }
+}
+
+impl From<u8> for SegmentKind {
+
+    fn from(code: u8) -> Self {
+        SegmentKind::from_code(code)
+    }
+}
+
+impl From<SegmentKind> for u8 {
+
+    fn from(kind: SegmentKind) -> Self {
+        kind.code()
+    }
+}
+
+impl Display for SegmentKind {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            SegmentKind::PresentationComposition => f.pad("PCS"),
+            SegmentKind::WindowDefinition => f.pad("WDS"),
+            SegmentKind::PaletteDefinition => f.pad("PDS"),
+            SegmentKind::ObjectDefinition => f.pad("ODS"),
+            SegmentKind::End => f.pad("END"),
+            SegmentKind::Unknown(code) => write!(f, "0x{:02X}", code),
+        }
+    }
+}
+
+// What precedes the payload of a segment, which is enough to tell where the next one begins
+// without parsing this one.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SegmentHeader {
+    pub pts: u32,
+    pub dts: u32,
+    pub kind: SegmentKind,
+    // The size of the payload alone.
+    pub size: u16,
+}
+
+impl SegmentHeader {
+
+    // How many bytes the whole segment takes up, magic number and header included.
+    pub fn length(&self) -> usize {
+        13 + self.size as usize
+    }
+}
+
+// The bytes that a value was read from. These take no part in comparisons or hashing, and a
+// fingerprint of the value is kept alongside them so that they are withheld once it changes.
+#[derive(Clone, Default)]
+pub struct Raw {
+    segments: Vec<Vec<u8>>,
+    fingerprint: u64,
+}
+
+impl Raw {
+
+    pub(crate) fn new(segments: Vec<Vec<u8>>, value: &impl Hash) -> Self {
+        Self {
+            segments,
+            fingerprint: fingerprint(value),
+        }
+    }
+
+    pub(crate) fn get(&self, value: &impl Hash) -> Option<&[Vec<u8>]> {
+        if !self.segments.is_empty() && self.fingerprint == fingerprint(value) {
+            Some(&self.segments)
+        } else {
+            None
+        }
+    }
+}
+
+impl PartialEq for Raw {
+
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Raw {}
+
+impl Hash for Raw {
+
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+impl Debug for Raw {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("Raw")
+            .field("segments", &DebugChunks(&self.segments))
+            .field("fingerprint", &self.fingerprint)
+            .finish()
+    }
+}
+
+fn fingerprint(value: &impl Hash) -> u64 {
+
+    let mut hasher = Fnv::default();
+
+    value.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+// FNV-1a, which needs nothing from std and is plenty to notice that a value has changed.
+pub(crate) struct Fnv(u64);
+
+impl Default for Fnv {
+
+    fn default() -> Self {
+        Self(0xCBF2_9CE4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+}
+
+// How many bytes of a payload, and how many of an object's lines, Debug shows. Object data can run
+// to megabytes, which would bury everything else a value holds.
+const DEBUG_BYTES: usize = 16;
+const DEBUG_CHUNKS: usize = 4;
+
+// Shows bytes by their length and the first few of them in hex.
+pub(crate) struct DebugBytes<'a>(pub(crate) &'a [u8]);
+
+impl Debug for DebugBytes<'_> {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+
+        write!(f, "{} bytes [", self.0.len())?;
+
+        for (index, byte) in self.0.iter().take(DEBUG_BYTES).enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{:02X}", byte)?;
+        }
+        if self.0.len() > DEBUG_BYTES {
+            write!(f, " ..")?;
+        }
+
+        write!(f, "]")
+    }
+}
+
+// Shows the first few of a run of byte strings, such as an object's lines, each as DebugBytes.
+pub(crate) struct DebugChunks<'a>(pub(crate) &'a [Vec<u8>]);
+
+impl Debug for DebugChunks<'_> {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+
+        let mut list = f.debug_list();
+
+        list.entries(self.0.iter().take(DEBUG_CHUNKS).map(|chunk| DebugBytes(chunk)));
+
+        if self.0.len() > DEBUG_CHUNKS {
+            list.finish_non_exhaustive()
+        } else {
+            list.finish()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum CompositionState {
     Normal,
     AcquisitionPoint,
+    #[default]
     EpochStart,
 }
 
-impl Default for CompositionState {
-    fn default() -> Self { Self::EpochStart }
-}
-
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+// Where an ODS falls among the segments that an object's data is split across.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Sequence {
+    #[default]
     Single,
     First,
+    // Neither first nor last, which only objects split across three or more segments have.
+    Middle,
     Last,
 }
 
-impl Default for Sequence {
-    fn default() -> Self { Self::Single }
+impl Sequence {
+
+    // Whether this fragment starts an object and so carries its header.
+    pub fn is_first(self) -> bool {
+        matches!(self, Sequence::Single | Sequence::First)
+    }
+
+    pub fn is_last(self) -> bool {
+        matches!(self, Sequence::Single | Sequence::Last)
+    }
+}
+
+// The frame rate of the video that a PCS is timed against. Blu-ray only allows 23.976, but some
+// authoring tools write the others and players accept them. Codes outside the spec are kept as
+// they are so that they can be written back unchanged. Serialized as the code itself.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(from = "u8", into = "u8"))]
+pub enum FrameRate {
+    #[default]
+    Fps23_976,
+    Fps24,
+    Fps25,
+    Fps29_97,
+    Fps50,
+    Fps59_94,
+    // Any other code.
+    Other(u8),
+}
+
+impl FrameRate {
+
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x10 => FrameRate::Fps23_976,
+            0x20 => FrameRate::Fps24,
+            0x30 => FrameRate::Fps25,
+            0x40 => FrameRate::Fps29_97,
+            0x60 => FrameRate::Fps50,
+            0x70 => FrameRate::Fps59_94,
+            code => FrameRate::Other(code),
+        }
+    }
+
+    pub const fn code(self) -> u8 {
+        match self {
+            FrameRate::Fps23_976 => 0x10,
+            FrameRate::Fps24 => 0x20,
+            FrameRate::Fps25 => 0x30,
+            FrameRate::Fps29_97 => 0x40,
+            FrameRate::Fps50 => 0x60,
+            FrameRate::Fps59_94 => 0x70,
+            FrameRate::Other(code) => code,
+        }
+    }
+}
+
+impl From<u8> for FrameRate {
+
+    fn from(code: u8) -> Self {
+        FrameRate::from_code(code)
+    }
+}
+
+impl From<FrameRate> for u8 {
+
+    fn from(frame_rate: FrameRate) -> Self {
+        frame_rate.code()
+    }
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PresentationCompositionSegment {
     pub pts: u32,
     pub dts: u32,
     pub width: u16,
     pub height: u16,
-    pub frame_rate: u8,
+    pub frame_rate: FrameRate,
     pub composition_number: u16,
     pub composition_state: CompositionState,
-    pub palette_update_id: Option<u8>,
+    pub palette_update_id: Option<PaletteId>,
     pub composition_objects: Vec<CompositionObject>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub raw: Raw,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CompositionObject {
-    pub object_id: u16,
-    pub window_id: u8,
+    pub object_id: ObjectId,
+    pub window_id: WindowId,
     pub x: u16,
     pub y: u16,
     pub crop: Option<Crop>,
+    // Shown even when subtitles are turned off, usually to translate signs or foreign dialogue.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub forced: bool,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Crop {
     pub x: u16,
     pub y: u16,
@@ -74,32 +443,40 @@ pub struct Crop {
     pub height: u16,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct WindowDefinitionSegment {
     pub pts: u32,
     pub dts: u32,
     pub windows: Vec<WindowDefinition>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub raw: Raw,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct WindowDefinition {
-    pub id: u8,
+    pub id: WindowId,
     pub x: u16,
     pub y: u16,
     pub width: u16,
     pub height: u16,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PaletteDefinitionSegment {
     pub pts: u32,
     pub dts: u32,
-    pub id: u8,
+    pub id: PaletteId,
     pub version: u8,
     pub entries: Vec<PaletteEntry>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub raw: Raw,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PaletteEntry {
     pub id: u8,
     pub y: u8,
@@ -108,20 +485,251 @@ pub struct PaletteEntry {
     pub alpha: u8,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+// Carries an object's RLE data, or one fragment of it. Only the first fragment has a header.
+#[derive(Clone, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ObjectDefinitionSegment {
     pub pts: u32,
     pub dts: u32,
-    pub id: u16,
+    pub id: ObjectId,
     pub version: u8,
     pub sequence: Sequence,
+    pub header: Option<ObjectHeader>,
+    pub data: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub raw: Raw,
+}
+
+impl ObjectDefinitionSegment {
+
+    // Puts an object's data back together from its fragments, which must be given in order, from
+    // the one with its header through the last. Returns the header and the whole of the data.
+    pub fn join(fragments: &[Self]) -> Result<(ObjectHeader, Vec<u8>), FragmentError> {
+
+        let first = fragments.first().ok_or(FragmentError::NoFragments)?;
+        let header = match (&first.header, first.sequence.is_first()) {
+            (Some(header), true) => header.clone(),
+            _ => return Err(FragmentError::MissingFirstFragment),
+        };
+        // The declared length is only checked once the fragments are joined.
+        let mut data = Vec::with_capacity(
+            fragments.iter().map(|fragment| fragment.data.len()).sum()
+        );
+
+        for (index, fragment) in fragments.iter().enumerate() {
+            if fragment.id != first.id || fragment.version != first.version {
+                return Err(FragmentError::MismatchedFragment { index })
+            }
+            if index > 0 && fragment.sequence.is_first() {
+                return Err(FragmentError::UnexpectedFirstFragment { index })
+            }
+            if index < fragments.len() - 1 && fragment.sequence.is_last() {
+                return Err(FragmentError::UnexpectedLastFragment { index })
+            }
+            data.extend_from_slice(&fragment.data);
+        }
+
+        if !fragments[fragments.len() - 1].sequence.is_last() {
+            return Err(FragmentError::MissingLastFragment)
+        }
+        if data.len() != header.data_length {
+            return Err(
+                FragmentError::DataLengthMismatch {
+                    declared: header.data_length,
+                    actual: data.len(),
+                }
+            )
+        }
+
+        Ok((header, data))
+    }
+}
+
+impl Debug for ObjectDefinitionSegment {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("ObjectDefinitionSegment")
+            .field("pts", &self.pts)
+            .field("dts", &self.dts)
+            .field("id", &self.id)
+            .field("version", &self.version)
+            .field("sequence", &self.sequence)
+            .field("header", &self.header)
+            .field("data", &DebugBytes(&self.data))
+            .field("raw", &self.raw)
+            .finish()
+    }
+}
+
+#[derive(ThisError, Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FragmentError {
+    #[error("no object fragments were given")]
+    NoFragments,
+    #[error("object fragments do not begin with one that carries the object's header")]
+    MissingFirstFragment,
+    #[error("object fragment {index} starts the object over")]
+    UnexpectedFirstFragment {
+        index: usize,
+    },
+    #[error("object fragment {index} ends the object before the fragments that follow it")]
+    UnexpectedLastFragment {
+        index: usize,
+    },
+    #[error("object fragments end before the object's last fragment")]
+    MissingLastFragment,
+    #[error("object fragment {index} belongs to a different object ID or version")]
+    MismatchedFragment {
+        index: usize,
+    },
+    #[error("object fragments hold {actual} bytes of data where {declared} were declared")]
+    DataLengthMismatch {
+        declared: usize,
+        actual: usize,
+    },
+}
+
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ObjectHeader {
+    // The length of the RLE data across all of the object's fragments.
+    pub data_length: usize,
     pub width: u16,
     pub height: u16,
-    pub lines: Vec<Vec<u8>>,
 }
 
-#[derive(Clone, Debug, Default, Hash, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EndSegment {
     pub pts: u32,
     pub dts: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub raw: Raw,
+}
+
+// A segment of a kind the crate does not model, carried through as the payload it came with.
+#[derive(Clone, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UnknownSegment {
+    pub pts: u32,
+    pub dts: u32,
+    pub kind: u8,
+    pub data: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub raw: Raw,
+}
+
+impl Debug for UnknownSegment {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("UnknownSegment")
+            .field("pts", &self.pts)
+            .field("dts", &self.dts)
+            .field("kind", &self.kind)
+            .field("data", &DebugBytes(&self.data))
+            .field("raw", &self.raw)
+            .finish()
+    }
+}
+
+// A segment parsed from bytes in memory, whose object data or unknown payload is borrowed from
+// them rather than copied. The other kinds are small enough to be parsed as they always are.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SegmentRef<'a> {
+    PresentationComposition(PresentationCompositionSegment),
+    WindowDefinition(WindowDefinitionSegment),
+    PaletteDefinition(PaletteDefinitionSegment),
+    ObjectDefinition(ObjectDefinitionRef<'a>),
+    End(EndSegment),
+    Unknown(UnknownSegmentRef<'a>),
+}
+
+impl SegmentRef<'_> {
+
+    pub fn kind(&self) -> SegmentKind {
+        match self {
+            SegmentRef::PresentationComposition(_) => SegmentKind::PresentationComposition,
+            SegmentRef::WindowDefinition(_) => SegmentKind::WindowDefinition,
+            SegmentRef::PaletteDefinition(_) => SegmentKind::PaletteDefinition,
+            SegmentRef::ObjectDefinition(_) => SegmentKind::ObjectDefinition,
+            SegmentRef::End(_) => SegmentKind::End,
+            SegmentRef::Unknown(us) => SegmentKind::Unknown(us.kind),
+        }
+    }
+
+    // Copies whatever is borrowed.
+    pub fn into_owned(self) -> Segment {
+        match self {
+            SegmentRef::PresentationComposition(pcs) => Segment::PresentationComposition(pcs),
+            SegmentRef::WindowDefinition(wds) => Segment::WindowDefinition(wds),
+            SegmentRef::PaletteDefinition(pds) => Segment::PaletteDefinition(pds),
+            SegmentRef::ObjectDefinition(ods) => Segment::ObjectDefinition(
+                ObjectDefinitionSegment {
+                    pts: ods.pts,
+                    dts: ods.dts,
+                    id: ods.id,
+                    version: ods.version,
+                    sequence: ods.sequence,
+                    header: ods.header,
+                    data: ods.data.to_vec(),
+                    raw: Raw::default(),
+                }
+            ),
+            SegmentRef::End(es) => Segment::End(es),
+            SegmentRef::Unknown(us) => Segment::Unknown(
+                UnknownSegment {
+                    pts: us.pts,
+                    dts: us.dts,
+                    kind: us.kind,
+                    data: us.data.to_vec(),
+                    raw: Raw::default(),
+                }
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct ObjectDefinitionRef<'a> {
+    pub pts: u32,
+    pub dts: u32,
+    pub id: ObjectId,
+    pub version: u8,
+    pub sequence: Sequence,
+    pub header: Option<ObjectHeader>,
+    pub data: &'a [u8],
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct UnknownSegmentRef<'a> {
+    pub pts: u32,
+    pub dts: u32,
+    pub kind: u8,
+    pub data: &'a [u8],
+}
+
+impl Debug for ObjectDefinitionRef<'_> {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("ObjectDefinitionRef")
+            .field("pts", &self.pts)
+            .field("dts", &self.dts)
+            .field("id", &self.id)
+            .field("version", &self.version)
+            .field("sequence", &self.sequence)
+            .field("header", &self.header)
+            .field("data", &DebugBytes(self.data))
+            .finish()
+    }
+}
+
+impl Debug for UnknownSegmentRef<'_> {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("UnknownSegmentRef")
+            .field("pts", &self.pts)
+            .field("dts", &self.dts)
+            .field("kind", &self.kind)
+            .field("data", &DebugBytes(self.data))
+            .finish()
+    }
 }
@@ -0,0 +1,605 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+use super::{
+    CompositionObject,
+    Crop,
+    CompositionState,
+    EndSegment,
+    FrameRate,
+    ObjectDefinitionRef,
+    ObjectHeader,
+    PaletteDefinitionSegment,
+    PaletteEntry,
+    PresentationCompositionSegment,
+    Raw,
+    Segment,
+    SegmentHeader,
+    SegmentKind,
+    SegmentRef,
+    Sequence,
+    UnknownSegmentRef,
+    WindowDefinition,
+    WindowDefinitionSegment,
+    super::id::{ObjectId, PaletteId, WindowId},
+};
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+#[cfg(feature = "std")]
+use std::io::Error as IoError;
+use thiserror::Error as ThisError;
+
+pub type ReadResult<T> = Result<T, ReadError>;
+
+#[derive(ThisError, Debug)]
+pub enum ReadError {
+    #[cfg(feature = "std")]
+    #[error("segment IO error")]
+    IoError {
+        #[from]
+        source: IoError,
+    },
+    #[error("segment ends before its header and payload do")]
+    IncompleteSegment,
+    #[error("segment declares {declared} bytes but its fields run to at least {consumed}")]
+    SizeMismatch {
+        declared: usize,
+        consumed: usize,
+    },
+    #[error("segment has unrecognized magic number")]
+    UnrecognizedMagicNumber,
+    #[error("segment has unrecognized kind")]
+    UnrecognizedKind,
+    #[error("presentation composition segment has unrecognized frame rate")]
+    UnrecognizedFrameRate,
+    #[error("presentation composition segment has unrecognized composition state")]
+    UnrecognizedCompositionState,
+    #[error("presentation composition segment has unrecognized palette update flag")]
+    UnrecognizedPaletteUpdateFlag,
+    #[error("composition object has unrecognized flags")]
+    UnrecognizedCompositionObjectFlags,
+    #[error("palette definition segment ends partway through an entry")]
+    TruncatedPaletteDefinition,
+    #[error("unrecognized object definition sequence flag")]
+    UnrecognizedObjectSequenceFlag,
+    #[error("invalid object data length")]
+    InvalidObjectDataLength,
+    #[error("incomplete RLE sequence")]
+    IncompleteRleSequence,
+    #[error("invalid RLE sequence")]
+    InvalidRleSequence,
+    #[error("incomplete RLE line")]
+    IncompleteRleLine,
+    #[error("{limit}")]
+    LimitExceeded {
+        limit: Limit,
+    },
+    #[error("{source} (segment at {offset:#X}{})", within_segment(.within))]
+    At {
+        offset: u64,
+        within: Option<u64>,
+        source: Box<ReadError>,
+    },
+}
+
+impl ReadError {
+
+    // Notes where in the stream the segment that failed began. IO errors are left as they are, so
+    // that the end of the stream can still be told apart from anything else.
+    pub fn at(self, offset: u64) -> Self {
+
+        let within = match &self {
+            ReadError::UnrecognizedMagicNumber => Some(0),
+            ReadError::UnrecognizedKind => Some(10),
+            ReadError::SizeMismatch { declared, .. } => Some((2 + HEADER_LENGTH + declared) as u64),
+            _ => None,
+        };
+
+        match self {
+            #[cfg(feature = "std")]
+            ReadError::IoError { .. } => self,
+            ReadError::At { .. } => self,
+            _ => ReadError::At { offset, within, source: Box::new(self) },
+        }
+    }
+
+    // The error itself, without where it happened.
+    pub fn bare(&self) -> &Self {
+        match self {
+            ReadError::At { source, .. } => source.bare(),
+            _ => self,
+        }
+    }
+}
+
+fn within_segment(within: &Option<u64>) -> String {
+    match within {
+        Some(within) => format!(", byte {:#X}", within),
+        None => String::new(),
+    }
+}
+
+#[derive(ThisError, Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Limit {
+    #[error("maximum windows per epoch exceeded")]
+    Windows,
+    #[error("maximum palettes per epoch exceeded")]
+    Palettes,
+    #[error("maximum objects per epoch exceeded")]
+    Objects,
+    #[error("maximum decoded pixels per display set exceeded")]
+    DecodedPixels,
+    #[error("maximum object data per display set exceeded")]
+    ObjectData,
+    #[error("maximum display sets per epoch exceeded")]
+    DisplaySetsPerEpoch,
+}
+
+// Bounds on what untrusted input can make the reader allocate. Segment payloads need no limit of
+// their own as their 16-bit size field already caps them at 65,535 bytes. The per-epoch limits
+// are also enforced on each display set as it is read, and across display sets by
+// EpochState::apply_with.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Limits {
+    pub max_windows: usize,
+    pub max_palettes: usize,
+    pub max_objects: usize,
+    pub max_decoded_pixels: usize,
+    // Bytes of RLE data, as the objects of a display set declare them. Objects are only decoded
+    // once all of their fragments have been read, so this bounds what is held until then.
+    pub max_object_data: usize,
+    pub max_display_sets_per_epoch: usize,
+}
+
+impl Default for Limits {
+
+    // Generous enough for any real disc, including UHD ones, while keeping memory use of a
+    // single display set in the tens of megabytes.
+    fn default() -> Self {
+        Self {
+            max_windows: 16,
+            max_palettes: 64,
+            max_objects: 256,
+            max_decoded_pixels: 3840 * 2160 * 2,
+            max_object_data: 3840 * 2160 * 4,
+            max_display_sets_per_epoch: 65_536,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ReadOptions {
+    // Retains the bytes each segment was read from; see Segment::raw.
+    pub keep_raw: bool,
+    // Absorbs known benign structural noise, recording it instead of failing.
+    pub lenient: bool,
+    // Reads segments of unrecognized kinds as Segment::Unknown instead of failing. Resyncing
+    // then only happens on unrecognized magic numbers.
+    pub keep_unknown: bool,
+    // Fails on a PCS whose frame rate is FrameRate::Other, which is otherwise carried through as
+    // it is.
+    pub strict_frame_rate: bool,
+    // Fails on a display set whose windows, palettes, and objects are not defined in that order,
+    // which is the one Blu-ray lays them out in. Unknown segments may fall anywhere.
+    pub strict_order: bool,
+    // Fails on an END segment that ends no display set, and on a display set that another PCS
+    // starts before it is ended, instead of absorbing either when reading leniently.
+    pub require_end: bool,
+    pub limits: Limits,
+}
+
+
+pub(crate) const MAGIC_NUMBER: u16 = 0x5047;
+
+// What follows the magic number: the PTS, DTS, kind, and payload size.
+pub(crate) const HEADER_LENGTH: usize = 11;
+
+pub(crate) fn payload_size(header: &[u8; HEADER_LENGTH]) -> usize {
+    u16::from_be_bytes([header[9], header[10]]) as usize
+}
+
+pub(crate) fn parse_header(header: &[u8; HEADER_LENGTH]) -> SegmentHeader {
+    SegmentHeader {
+        pts: u32::from_be_bytes([header[0], header[1], header[2], header[3]]),
+        dts: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+        kind: SegmentKind::from_code(header[8]),
+        size: u16::from_be_bytes([header[9], header[10]]),
+    }
+}
+
+// Parses a segment from the header that followed its magic number and its payload, however they
+// were read, borrowing what can be borrowed from the payload.
+fn parse_segment_ref<'a>(
+    header: &[u8; HEADER_LENGTH],
+    payload: &'a [u8],
+    options: &ReadOptions,
+) -> ReadResult<SegmentRef<'a>> {
+
+    let SegmentHeader { pts, dts, kind, .. } = parse_header(header);
+
+    Ok(
+        match kind {
+            SegmentKind::PaletteDefinition => {
+                SegmentRef::PaletteDefinition(parse_pds(pts, dts, payload)?)
+            }
+            SegmentKind::ObjectDefinition => {
+                SegmentRef::ObjectDefinition(parse_ods(pts, dts, payload)?)
+            }
+            SegmentKind::PresentationComposition => {
+                let pcs = parse_pcs(pts, dts, payload)?;
+                if options.strict_frame_rate && matches!(pcs.frame_rate, FrameRate::Other(_)) {
+                    return Err(ReadError::UnrecognizedFrameRate)
+                }
+                SegmentRef::PresentationComposition(pcs)
+            }
+            SegmentKind::WindowDefinition => {
+                SegmentRef::WindowDefinition(parse_wds(pts, dts, payload)?)
+            }
+            SegmentKind::End => SegmentRef::End(EndSegment { pts, dts, raw: Raw::default() }),
+            SegmentKind::Unknown(kind) if options.keep_unknown => {
+                SegmentRef::Unknown(UnknownSegmentRef { pts, dts, kind, data: payload })
+            }
+            SegmentKind::Unknown(_) => return Err(ReadError::UnrecognizedKind),
+        }
+    )
+}
+
+pub(crate) fn parse_segment(
+    header: &[u8; HEADER_LENGTH],
+    payload: &[u8],
+    options: &ReadOptions,
+) -> ReadResult<Segment> {
+
+    let mut segment = parse_segment_ref(header, payload, options)?.into_owned();
+
+    if options.keep_raw {
+
+        let mut raw = Vec::with_capacity(2 + HEADER_LENGTH + payload.len());
+
+        raw.extend_from_slice(&MAGIC_NUMBER.to_be_bytes());
+        raw.extend_from_slice(header);
+        raw.extend_from_slice(payload);
+        segment.set_raw(raw);
+    }
+
+    Ok(segment)
+}
+
+// Parses the segment at the start of the bytes, returning it along with how many bytes it took
+// up. This needs neither std nor a reader, and is what reading a segment from a stream comes down
+// to.
+pub fn decode_segment(bytes: &[u8], options: &ReadOptions) -> ReadResult<(Segment, usize)> {
+
+    let (header, payload) = split_segment(bytes)?;
+    let length = 2 + HEADER_LENGTH + payload.len();
+
+    Ok((parse_segment(&header, payload, options)?, length))
+}
+
+// Parses the segment at the start of the bytes without copying its object data or unknown
+// payload, returning it along with the bytes that follow it. ReadOptions::keep_raw is ignored, as
+// the raw bytes are already at hand.
+pub fn decode_segment_ref<'a>(
+    bytes: &'a [u8],
+    options: &ReadOptions,
+) -> ReadResult<(SegmentRef<'a>, &'a [u8])> {
+
+    let (header, payload) = split_segment(bytes)?;
+    let rest = &bytes[2 + HEADER_LENGTH + payload.len()..];
+
+    Ok((parse_segment_ref(&header, payload, options)?, rest))
+}
+
+fn split_segment(bytes: &[u8]) -> ReadResult<([u8; HEADER_LENGTH], &[u8])> {
+
+    if bytes.len() < 2 + HEADER_LENGTH {
+        return Err(ReadError::IncompleteSegment)
+    }
+    if u16::from_be_bytes([bytes[0], bytes[1]]) != MAGIC_NUMBER {
+        return Err(ReadError::UnrecognizedMagicNumber)
+    }
+
+    let mut header = [0u8; HEADER_LENGTH];
+
+    header.copy_from_slice(&bytes[2..2 + HEADER_LENGTH]);
+
+    let length = 2 + HEADER_LENGTH + payload_size(&header);
+    let payload = bytes.get(2 + HEADER_LENGTH..length).ok_or(ReadError::IncompleteSegment)?;
+
+    Ok((header, payload))
+}
+
+// Reads big-endian fields off the front of a payload, never past the size its header declared.
+// Whatever is left once every field has been read is padding, and is skipped.
+struct Fields<'a> {
+    bytes: &'a [u8],
+    consumed: usize,
+}
+
+impl<'a> Fields<'a> {
+
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, consumed: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.consumed
+    }
+
+    // Checks that what is left has room for the given number of fields of at least the given
+    // size each, before anything is allocated for them.
+    fn expect(&self, count: usize, size: usize) -> ReadResult<()> {
+
+        if self.remaining() < count * size {
+            return Err(
+                ReadError::SizeMismatch {
+                    declared: self.bytes.len(),
+                    consumed: self.consumed + count * size,
+                }
+            )
+        }
+
+        Ok(())
+    }
+
+    fn rest(self) -> &'a [u8] {
+        &self.bytes[self.consumed..]
+    }
+
+    fn take<const N: usize>(&mut self) -> ReadResult<[u8; N]> {
+
+        if self.remaining() < N {
+            return Err(
+                ReadError::SizeMismatch {
+                    declared: self.bytes.len(),
+                    consumed: self.consumed + N,
+                }
+            )
+        }
+
+        let mut value = [0u8; N];
+
+        value.copy_from_slice(&self.bytes[self.consumed..self.consumed + N]);
+        self.consumed += N;
+
+        Ok(value)
+    }
+
+    fn read_u8(&mut self) -> ReadResult<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn read_u16(&mut self) -> ReadResult<u16> {
+        Ok(u16::from_be_bytes(self.take()?))
+    }
+
+    fn read_u24(&mut self) -> ReadResult<u32> {
+
+        let [high, middle, low] = self.take()?;
+
+        Ok(u32::from_be_bytes([0, high, middle, low]))
+    }
+}
+
+fn parse_pcs(
+    pts: u32,
+    dts: u32,
+    payload: &[u8],
+) -> ReadResult<PresentationCompositionSegment> {
+
+    let mut input = Fields::new(payload);
+    let width = input.read_u16()?;
+    let height = input.read_u16()?;
+    let frame_rate = FrameRate::from_code(input.read_u8()?);
+
+    let composition_number = input.read_u16()?;
+    let composition_state = match input.read_u8()? {
+        0x00 => CompositionState::Normal,
+        0x40 => CompositionState::AcquisitionPoint,
+        0x80 => CompositionState::EpochStart,
+        _ => return Err(ReadError::UnrecognizedCompositionState),
+    };
+    let palette_update_id = match input.read_u8()? {
+        0x00 => {
+            input.read_u8()?;
+            None
+        }
+        0x80 => {
+            Some(PaletteId(input.read_u8()?))
+        }
+        _ => {
+            return Err(ReadError::UnrecognizedPaletteUpdateFlag)
+        }
+    };
+    let comp_obj_count = input.read_u8()? as usize;
+
+    // Each composition object takes eight bytes, or sixteen if it is cropped.
+    input.expect(comp_obj_count, 8)?;
+
+    let mut composition_objects = Vec::with_capacity(comp_obj_count);
+
+    for _ in 0..comp_obj_count {
+
+        let object_id = ObjectId(input.read_u16()?);
+        let window_id = WindowId(input.read_u8()?);
+        let flags = input.read_u8()?;
+
+        if flags & 0x3F != 0 {
+            return Err(ReadError::UnrecognizedCompositionObjectFlags)
+        }
+
+        let cropped = flags & 0x80 != 0;
+        let forced = flags & 0x40 != 0;
+        let x = input.read_u16()?;
+        let y = input.read_u16()?;
+
+        // For some reason, the U.S. release of Final Fantasy VII: Advent Children Complete
+        // declares that the object is cropped, but then the segment's payload ends. A crop that
+        // the payload has no room for is ignored rather than read from the next composition
+        // object.
+        let crop = if cropped && input.remaining() >= 8 {
+            Some(
+                Crop {
+                    x: input.read_u16()?,
+                    y: input.read_u16()?,
+                    width: input.read_u16()?,
+                    height: input.read_u16()?,
+                }
+            )
+        } else {
+            None
+        };
+
+        composition_objects.push(
+            CompositionObject {
+                object_id,
+                window_id,
+                x,
+                y,
+                crop,
+                forced,
+            }
+        );
+    }
+
+    Ok(
+        PresentationCompositionSegment {
+            pts,
+            dts,
+            width,
+            height,
+            frame_rate,
+            composition_number,
+            composition_state,
+            palette_update_id,
+            composition_objects,
+            raw: Raw::default(),
+        }
+    )
+}
+
+fn parse_wds(
+    pts: u32,
+    dts: u32,
+    payload: &[u8],
+) -> ReadResult<WindowDefinitionSegment> {
+
+    let mut input = Fields::new(payload);
+    let count = input.read_u8()? as usize;
+
+    input.expect(count, 9)?;
+
+    let mut windows = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        windows.push(
+            WindowDefinition {
+                id: WindowId(input.read_u8()?),
+                x: input.read_u16()?,
+                y: input.read_u16()?,
+                width: input.read_u16()?,
+                height: input.read_u16()?,
+            }
+        );
+    }
+
+    Ok(
+        WindowDefinitionSegment {
+            pts,
+            dts,
+            windows,
+            raw: Raw::default(),
+        }
+    )
+}
+
+fn parse_pds(
+    pts: u32,
+    dts: u32,
+    payload: &[u8],
+) -> ReadResult<PaletteDefinitionSegment> {
+
+    // The entry count is implied by the segment size, which must then hold whole entries.
+    if payload.len() < 2 || !(payload.len() - 2).is_multiple_of(5) {
+        return Err(ReadError::TruncatedPaletteDefinition)
+    }
+
+    let mut input = Fields::new(payload);
+    let count = (payload.len() - 2) / 5;
+    let id = PaletteId(input.read_u8()?);
+    let version = input.read_u8()?;
+    let mut entries = Vec::with_capacity(count);
+
+    for _ in 0..count {
+
+        let id = input.read_u8()?;
+        let y = input.read_u8()?;
+        let cr = input.read_u8()?;
+        let cb = input.read_u8()?;
+        let alpha = input.read_u8()?;
+
+        entries.push(PaletteEntry { id, y, cr, cb, alpha });
+    }
+
+    Ok(
+        PaletteDefinitionSegment {
+            pts,
+            dts,
+            id,
+            version,
+            entries,
+            raw: Raw::default(),
+        }
+    )
+}
+
+fn parse_ods(
+    pts: u32,
+    dts: u32,
+    payload: &[u8],
+) -> ReadResult<ObjectDefinitionRef<'_>> {
+
+    let mut input = Fields::new(payload);
+    let id = ObjectId(input.read_u16()?);
+    let version = input.read_u8()?;
+    let sequence = match input.read_u8()? {
+        0xC0 => Sequence::Single,
+        0x80 => Sequence::First,
+        0x00 => Sequence::Middle,
+        0x40 => Sequence::Last,
+        _ => return Err(ReadError::UnrecognizedObjectSequenceFlag),
+    };
+    let header = if sequence.is_first() {
+
+        // I have no idea why PGS streams record +4 bytes for the object data size, but they do.
+        let data_length = (input.read_u24()? as usize)
+            .checked_sub(4)
+            .ok_or(ReadError::InvalidObjectDataLength)?;
+        let width = input.read_u16()?;
+        let height = input.read_u16()?;
+        let fragment_length = input.remaining();
+
+        if data_length < fragment_length
+            || (sequence == Sequence::Single && data_length != fragment_length) {
+            return Err(ReadError::InvalidObjectDataLength)
+        }
+
+        Some(ObjectHeader { data_length, width, height })
+    } else {
+        None
+    };
+
+    Ok(
+        ObjectDefinitionRef {
+            pts,
+            dts,
+            id,
+            version,
+            sequence,
+            header,
+            data: input.rest(),
+        }
+    )
+}
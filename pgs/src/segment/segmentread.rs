@@ -5,364 +5,336 @@
  */
 
 use super::{
-    CompositionObject,
-    Crop,
-    CompositionState,
-    EndSegment,
-    ObjectDefinitionSegment,
-    PaletteDefinitionSegment,
-    PaletteEntry,
-    PresentationCompositionSegment,
     Segment,
-    Sequence,
-    WindowDefinition,
-    WindowDefinitionSegment,
+    SegmentHeader,
+    super::rle::{Run, Runs},
+    segmentparse::{
+        parse_header,
+        parse_segment,
+        payload_size,
+        ReadError,
+        ReadOptions,
+        ReadResult,
+        Limit,
+        HEADER_LENGTH,
+        MAGIC_NUMBER,
+    },
 };
 use std::{
-    io::{Cursor, Error as IoError, Read},
+    collections::VecDeque,
+    io::{self, ErrorKind, Read, Result as IoResult},
 };
 use byteorder::{BigEndian, ReadBytesExt};
-use thiserror::Error as ThisError;
-
-pub type ReadResult<T> = Result<T, ReadError>;
-
-#[derive(ThisError, Debug)]
-pub enum ReadError {
-    #[error("segment IO error")]
-    IoError {
-        #[from]
-        source: IoError,
-    },
-    #[error("segment has unrecognized magic number")]
-    UnrecognizedMagicNumber,
-    #[error("segment has unrecognized kind")]
-    UnrecognizedKind,
-    #[error("presentation composition segment has unrecognized composition state")]
-    UnrecognizedCompositionState,
-    #[error("presentation composition segment has unrecognized palette update flag")]
-    UnrecognizedPaletteUpdateFlag,
-    #[error("composition object has unrecognized cropped flag")]
-    UnrecognizedCropFlag,
-    #[error("unrecognized object definition sequence flag")]
-    UnrecognizedObjectSequenceFlag,
-    #[error("invalid object data length")]
-    InvalidObjectDataLength,
-    #[error("incomplete RLE sequence")]
-    IncompleteRleSequence,
-    #[error("invalid RLE sequence")]
-    InvalidRleSequence,
-    #[error("incomplete RLE line")]
-    IncompleteRleLine,
-}
 
 pub trait ReadSegmentExt {
     fn read_segment(&mut self) -> ReadResult<Segment>;
+    fn read_segment_with(&mut self, options: &ReadOptions) -> ReadResult<Segment>;
+    fn read_segment_counted(
+        &mut self,
+        offset: &mut u64,
+        options: &ReadOptions,
+    ) -> ReadResult<Segment>;
+    fn segments(&mut self, options: &ReadOptions) -> Segments<'_, Self> where Self: Sized;
+    fn read_segment_header(&mut self) -> ReadResult<SegmentHeader>;
+    fn skip_segment_payload(&mut self, header: &SegmentHeader) -> ReadResult<()>;
 }
 
 impl<T: Read> ReadSegmentExt for T {
 
     fn read_segment(&mut self) -> ReadResult<Segment> {
+        self.read_segment_with(&ReadOptions::default())
+    }
+
+    fn read_segment_with(&mut self, options: &ReadOptions) -> ReadResult<Segment> {
 
-        if self.read_u16::<BigEndian>()? != 0x5047 {
+        if self.read_u16::<BigEndian>()? != MAGIC_NUMBER {
             return Err(ReadError::UnrecognizedMagicNumber)
         }
 
-        let pts = self.read_u32::<BigEndian>()?;
-        let dts = self.read_u32::<BigEndian>()?;
-        let kind = self.read_u8()?;
-        let size = self.read_u16::<BigEndian>()? as usize;
+        let mut header = [0u8; HEADER_LENGTH];
+        self.read_exact(&mut header)?;
 
-        let mut payload = vec![0u8; size];
+        let mut payload = vec![0u8; payload_size(&header)];
         self.read_exact(&mut payload)?;
 
-        Ok(
-            match kind {
-                0x14 => Segment::PaletteDefinition(parse_pds(pts, dts, &payload)?),
-                0x15 => Segment::ObjectDefinition(parse_ods(pts, dts, &payload)?),
-                0x16 => Segment::PresentationComposition(parse_pcs(pts, dts, &payload)?),
-                0x17 => Segment::WindowDefinition(parse_wds(pts, dts, &payload)?),
-                0x80 => Segment::End(EndSegment { pts, dts }),
-                _ => return Err(ReadError::UnrecognizedKind),
-            }
-        )
+        parse_segment(&header, &payload, options)
     }
-}
 
-fn parse_pcs(
-    pts: u32,
-    dts: u32,
-    payload: &[u8],
-) -> ReadResult<PresentationCompositionSegment> {
-
-    let mut pos = 11;
-    let mut input = Cursor::new(payload);
-    let width = input.read_u16::<BigEndian>()?;
-    let height = input.read_u16::<BigEndian>()?;
-    let frame_rate = input.read_u8()?;
-
-    let composition_number = input.read_u16::<BigEndian>()?;
-    let composition_state = match input.read_u8()? {
-        0x00 => CompositionState::Normal,
-        0x40 => CompositionState::AcquisitionPoint,
-        0x80 => CompositionState::EpochStart,
-        _ => return Err(ReadError::UnrecognizedCompositionState),
-    };
-    let palette_update_id = match input.read_u8()? {
-        0x00 => {
-            input.read_u8()?;
-            None
+    // Reads a segment from the given offset into the stream, advancing it past whatever was read.
+    // Errors other than IO errors say where the segment began; see ReadError::at.
+    fn read_segment_counted(
+        &mut self,
+        offset: &mut u64,
+        options: &ReadOptions,
+    ) -> ReadResult<Segment> {
+
+        let start = *offset;
+        let mut counted = Counted { inner: self, count: 0 };
+        let result = counted.read_segment_with(options);
+
+        *offset += counted.count;
+
+        result.map_err(|err| err.at(start))
+    }
+
+    fn segments(&mut self, options: &ReadOptions) -> Segments<'_, Self> {
+        Segments { inner: self, options: *options, offset: 0, done: false }
+    }
+
+    // Reads only as far as the end of a segment's header, leaving its payload to be read or
+    // skipped. Neither the kind nor anything past the header is checked.
+    fn read_segment_header(&mut self) -> ReadResult<SegmentHeader> {
+
+        if self.read_u16::<BigEndian>()? != MAGIC_NUMBER {
+            return Err(ReadError::UnrecognizedMagicNumber)
         }
-        0x80 => {
-            Some(input.read_u8()?)
+
+        let mut header = [0u8; HEADER_LENGTH];
+        self.read_exact(&mut header)?;
+
+        Ok(parse_header(&header))
+    }
+
+    // Reads past the payload of the segment whose header was just read, without keeping it.
+    fn skip_segment_payload(&mut self, header: &SegmentHeader) -> ReadResult<()> {
+
+        let size = header.size as u64;
+
+        if io::copy(&mut self.take(size), &mut io::sink())? < size {
+            return Err(ReadError::IoError { source: ErrorKind::UnexpectedEof.into() })
         }
-        _ => {
-            return Err(ReadError::UnrecognizedPaletteUpdateFlag)
+
+        Ok(())
+    }
+}
+
+// Iterates over the segments of a stream up to its end. A stream that ends between segments
+// just ends the iteration, whereas one that ends partway through a segment yields an error. So
+// does anything else that keeps a segment from being read, after which the iteration ends.
+pub struct Segments<'a, R> {
+    inner: &'a mut R,
+    options: ReadOptions,
+    offset: u64,
+    done: bool,
+}
+
+impl<R: Read> Segments<'_, R> {
+
+    // Where the next segment will be read from, counting from where the iteration began.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl<R: Read> Iterator for Segments<'_, R> {
+
+    type Item = ReadResult<Segment>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        if self.done {
+            return None
         }
-    };
-    let comp_obj_count = input.read_u8()? as usize;
-    let mut composition_objects = Vec::new();
-
-    for _ in 0..comp_obj_count {
-        if payload.len() - pos >= 8 {
-
-            let object_id = input.read_u16::<BigEndian>()?;
-            let window_id = input.read_u8()?;
-            let cropped = match input.read_u8()? {
-                0x40 => true,
-                0x00 => false,
-                _ => return Err(ReadError::UnrecognizedCropFlag),
-            };
-            let x = input.read_u16::<BigEndian>()?;
-            let y = input.read_u16::<BigEndian>()?;
-
-            pos += 8;
-
-            // For some reason, the U.S. release of Final Fantasy VII: Advent Children Complete
-            // declares that the object is cropped, but then the segment's payload ends.
-            let crop = if cropped && payload.len() - pos >= 8 {
-                pos += 8;
-                Some(
-                    Crop {
-                        x: input.read_u16::<BigEndian>()?,
-                        y: input.read_u16::<BigEndian>()?,
-                        width: input.read_u16::<BigEndian>()?,
-                        height: input.read_u16::<BigEndian>()?,
-                    }
-                )
-            } else {
+
+        let start = self.offset;
+
+        match self.inner.read_segment_counted(&mut self.offset, &self.options) {
+            Ok(segment) => Some(Ok(segment)),
+            Err(ReadError::IoError { source })
+                if source.kind() == ErrorKind::UnexpectedEof && self.offset == start => {
+                self.done = true;
                 None
-            };
-
-            composition_objects.push(
-                CompositionObject {
-                    object_id,
-                    window_id,
-                    x,
-                    y,
-                    crop,
-                }
-            );
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
         }
     }
+}
 
-    Ok(
-        PresentationCompositionSegment {
-            pts,
-            dts,
-            width,
-            height,
-            frame_rate,
-            composition_number,
-            composition_state,
-            palette_update_id,
-            composition_objects,
-        }
-    )
+struct Counted<'a, R: Read> {
+    inner: &'a mut R,
+    count: u64,
 }
 
-fn parse_wds(
-    pts: u32,
-    dts: u32,
-    payload: &[u8],
-) -> ReadResult<WindowDefinitionSegment> {
-
-    let mut input = Cursor::new(payload);
-    let mut windows = Vec::new();
-    let count = input.read_u8()?;
-
-    for _ in 0..count {
-        windows.push(
-            WindowDefinition {
-                id: input.read_u8()?,
-                x: input.read_u16::<BigEndian>()?,
-                y: input.read_u16::<BigEndian>()?,
-                width: input.read_u16::<BigEndian>()?,
-                height: input.read_u16::<BigEndian>()?,
-            }
-        );
+impl<R: Read> Read for Counted<'_, R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+
+        let read = self.inner.read(buf)?;
+
+        self.count += read as u64;
+
+        Ok(read)
     }
+}
 
-    Ok(
-        WindowDefinitionSegment {
-            pts,
-            dts,
-            windows,
-        }
-    )
+// Reads segments from a stream that may be corrupt. Each segment's bytes are kept while it is
+// read so that, should its magic number, kind, or size be wrong, reading can start over one byte
+// past where it began and skip ahead to the next magic number.
+pub struct ResyncReader<R: Read> {
+    inner: R,
+    // Bytes to hand out again before reading any more from the inner reader.
+    pending: VecDeque<u8>,
+    recorded: Vec<u8>,
+    recording: bool,
+    // Bytes taken from the inner reader, some of which may be pending again.
+    taken: u64,
+    skipped: u64,
+    resyncs: usize,
 }
 
-fn parse_pds(
-    pts: u32,
-    dts: u32,
-    payload: &[u8],
-) -> ReadResult<PaletteDefinitionSegment> {
+impl<R: Read> ResyncReader<R> {
+
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            pending: VecDeque::new(),
+            recorded: Vec::new(),
+            recording: false,
+            taken: 0,
+            skipped: 0,
+            resyncs: 0,
+        }
+    }
 
-    let mut input = Cursor::new(payload);
-    let count = (payload.len() - 2) / 5;
-    let id = input.read_u8()?;
-    let version = input.read_u8()?;
-    let mut entries = Vec::new();
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
 
-    for _ in 0..count {
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
 
-        let id = input.read_u8()?;
-        let y = input.read_u8()?;
-        let cr = input.read_u8()?;
-        let cb = input.read_u8()?;
-        let alpha = input.read_u8()?;
+    // Where the next segment will be read from, counting from where the inner reader began.
+    pub fn offset(&self) -> u64 {
+        self.taken - self.pending.len() as u64
+    }
 
-        entries.push(PaletteEntry { id, y, cr, cb, alpha });
+    // Bytes passed over while looking for a magic number.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
     }
 
-    Ok(
-        PaletteDefinitionSegment {
-            pts,
-            dts,
-            id,
-            version,
-            entries,
+    pub fn resyncs(&self) -> usize {
+        self.resyncs
+    }
+
+    // Reads one segment. Should it have an unrecognized magic number or kind, or fields that do
+    // not fit its size, the error is still returned, but the next read starts at the next magic
+    // number after where this one began. A corrupt size can otherwise swallow the segments after
+    // it. Errors other than IO errors say where the segment began.
+    pub fn read_segment_resync(&mut self, options: &ReadOptions) -> ReadResult<Segment> {
+
+        let start = self.offset();
+
+        self.recorded.clear();
+        self.recording = true;
+
+        let result = self.read_segment_with(options);
+
+        self.recording = false;
+
+        match result {
+            Err(ReadError::UnrecognizedMagicNumber)
+            | Err(ReadError::UnrecognizedKind)
+            | Err(ReadError::SizeMismatch { .. }) => {
+                self.resync()?;
+            }
+            _ => {}
+        }
+
+        result.map_err(|err| err.at(start))
+    }
+
+    fn resync(&mut self) -> IoResult<()> {
+
+        let mut recorded = std::mem::take(&mut self.recorded);
+
+        recorded.drain(..recorded.len().min(1));
+        for byte in recorded.into_iter().rev() {
+            self.pending.push_front(byte);
+        }
+
+        // The byte the bad segment began at.
+        self.skipped += 1;
+        self.resyncs += 1;
+
+        while let Some(byte) = self.next_byte()? {
+            if byte == 0x50 {
+                match self.next_byte()? {
+                    Some(0x47) => {
+                        self.pending.push_front(0x47);
+                        self.pending.push_front(0x50);
+                        break
+                    }
+                    Some(other) => self.pending.push_front(other),
+                    None => {}
+                }
+            }
+            self.skipped += 1;
         }
-    )
-}
 
-fn parse_ods(
-    pts: u32,
-    dts: u32,
-    payload: &[u8],
-) -> ReadResult<ObjectDefinitionSegment> {
-
-    let mut input = Cursor::new(&payload);
-    let id = input.read_u16::<BigEndian>()?;
-    let version = input.read_u8()?;
-    let sequence = match input.read_u8()? {
-        0xC0 => Sequence::Single,
-        0x80 => Sequence::First,
-        0x40 => Sequence::Last,
-        _ => return Err(ReadError::UnrecognizedObjectSequenceFlag),
-    };
-
-    // I have no idea why PGS streams record +4 bytes for the object data size, but they do.
-    if input.read_u24::<BigEndian>()? as usize - 4 != payload.len() - 11 {
-        return Err(ReadError::InvalidObjectDataLength)
+        Ok(())
     }
 
-    let width = input.read_u16::<BigEndian>()?;
-    let height = input.read_u16::<BigEndian>()?;
-    let lines = rle_decompress(&input.into_inner()[11..])?;
-
-    Ok(
-        ObjectDefinitionSegment {
-            pts,
-            dts,
-            id,
-            version,
-            sequence,
-            width,
-            height,
-            lines,
+    fn next_byte(&mut self) -> IoResult<Option<u8>> {
+
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(Some(byte))
         }
-    )
+
+        let mut byte = [0u8];
+
+        match self.inner.read(&mut byte)? {
+            0 => Ok(None),
+            _ => {
+                self.taken += 1;
+                Ok(Some(byte[0]))
+            }
+        }
+    }
 }
 
-fn rle_decompress(input: &[u8]) -> ReadResult<Vec<Vec<u8>>> {
+impl<R: Read> Read for ResyncReader<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+
+        let read = if self.pending.is_empty() {
+            let read = self.inner.read(buf)?;
+            self.taken += read as u64;
+            read
+        } else {
+            let count = buf.len().min(self.pending.len());
+            for (slot, byte) in buf.iter_mut().zip(self.pending.drain(..count)) {
+                *slot = byte;
+            }
+            count
+        };
+
+        if self.recording {
+            self.recorded.extend_from_slice(&buf[..read]);
+        }
+
+        Ok(read)
+    }
+}
+
+pub(crate) fn rle_decompress(input: &[u8], max_pixels: usize) -> ReadResult<Vec<Vec<u8>>> {
 
     let mut output = Vec::<Vec<u8>>::new();
     let mut line = vec![];
-    let mut iter = input.iter();
-
-    loop {
-        match iter.next() {
-            Some(byte_1) => {
-                if *byte_1 == 0x00 {
-                    match iter.next() {
-                        Some(byte_2) => {
-                            if *byte_2 == 0x00 {
-                                output.push(line);
-                                line = vec![];
-                            } else if *byte_2 >> 6 == 0 {
-                                for _ in 0..(*byte_2 & 0x3F) {
-                                    line.push(0);
-                                }
-                            } else if *byte_2 >> 6 == 1 {
-                                match iter.next() {
-                                    Some(byte_3) => {
-                                        for _ in 0..(
-                                            (*byte_2 as u16 & 0x3F) << 8
-                                            | *byte_3 as u16
-                                        ) {
-                                            line.push(0);
-                                        }
-                                    }
-                                    None => {
-                                        return Err(ReadError::IncompleteRleSequence)
-                                    }
-                                }
-                            } else if *byte_2 >> 6 == 2 {
-                                match iter.next() {
-                                    Some(byte_3) => {
-                                        for _ in 0..(*byte_2 & 0x3F) {
-                                            line.push(*byte_3);
-                                        }
-                                    }
-                                    None => {
-                                        return Err(ReadError::IncompleteRleSequence)
-                                    }
-                                }
-                            } else if *byte_2 >> 6 == 3 {
-                                match iter.next() {
-                                    Some(byte_3) => {
-                                        match iter.next() {
-                                            Some(byte_4) => {
-                                                for _ in 0..(
-                                                    (*byte_2 as u16 & 0x3F) << 8
-                                                    | *byte_3 as u16
-                                                ) {
-                                                    line.push(*byte_4);
-                                                }
-                                            }
-                                            None => {
-                                                return Err(ReadError::IncompleteRleSequence)
-                                            }
-                                        }
-                                    }
-                                    None => {
-                                        return Err(ReadError::IncompleteRleSequence)
-                                    }
-                                }
-                            } else {
-                                return Err(ReadError::InvalidRleSequence)
-                            }
-                        }
-                        None => {
-                            return Err(ReadError::IncompleteRleSequence)
-                        }
-                    }
-                } else {
-                    line.push(*byte_1);
-                }
+    let mut remaining = max_pixels;
+
+    for run in Runs::new(input) {
+        match run.map_err(|_| ReadError::IncompleteRleSequence)? {
+            Run::Pixels { length, index } => {
+                push_run(&mut line, length, index, &mut remaining)?;
             }
-            None => {
-                break
+            Run::EndOfLine => {
+                output.push(line);
+                line = vec![];
             }
         }
     }
@@ -373,3 +345,17 @@ fn rle_decompress(input: &[u8]) -> ReadResult<Vec<Vec<u8>>> {
 
     Ok(output)
 }
+
+// A few bytes of RLE can expand into hundreds of megabytes, so runs are counted before they are
+// allocated.
+fn push_run(line: &mut Vec<u8>, length: usize, value: u8, remaining: &mut usize) -> ReadResult<()> {
+
+    if length > *remaining {
+        return Err(ReadError::LimitExceeded { limit: Limit::DecodedPixels })
+    }
+
+    *remaining -= length;
+    line.resize(line.len() + length, value);
+
+    Ok(())
+}
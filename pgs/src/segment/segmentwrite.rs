@@ -9,9 +9,11 @@ use super::{
     ObjectDefinitionSegment,
     PaletteDefinitionSegment,
     PresentationCompositionSegment,
+    Raw,
     Segment,
     Sequence,
     WindowDefinitionSegment,
+    super::rle::encode_line,
 };
 use std::io::{
     Error as IoError,
@@ -22,6 +24,11 @@ use thiserror::Error as ThisError;
 
 pub type WriteResult<T> = Result<T, WriteError>;
 
+// How much object data fits in a segment, after the first fragment's eleven bytes of ID, version,
+// sequence flag, data length, and dimensions, or the four bytes every later fragment starts with.
+pub const MAX_FIRST_FRAGMENT_DATA: usize = 65_535 - 11;
+pub const MAX_FRAGMENT_DATA: usize = 65_535 - 4;
+
 #[derive(ThisError, Debug)]
 pub enum WriteError {
     #[error("segment IO error")]
@@ -29,14 +36,16 @@ pub enum WriteError {
         #[from]
         source: IoError,
     },
+    #[error("segment payload is too large")]
+    PayloadTooLarge,
     #[error("too many composition objects in presentation composition segment")]
     TooManyCompositionObjects,
     #[error("too many window definitions")]
     TooManyWindowDefinitions,
     #[error("object data is too large")]
     ObjectDataTooLarge,
-    #[error("object line too long")]
-    ObjectLineTooLong,
+    #[error("object header does not match sequence")]
+    InconsistentObjectHeader,
 }
 
 pub trait WriteSegmentExt {
@@ -47,41 +56,25 @@ impl<T: Write> WriteSegmentExt for T {
 
     fn write_segment(&mut self, segment: &Segment) -> WriteResult<()> {
 
-        self.write_u16::<BigEndian>(0x5047)?;
-
-        let payload = match &segment {
-            Segment::PresentationComposition(pcs) => {
-                self.write_u32::<BigEndian>(pcs.pts)?;
-                self.write_u32::<BigEndian>(pcs.dts)?;
-                self.write_u8(0x16)?;
-                generate_pcs(pcs)?
-            }
-            Segment::WindowDefinition(wds) => {
-                self.write_u32::<BigEndian>(wds.pts)?;
-                self.write_u32::<BigEndian>(wds.dts)?;
-                self.write_u8(0x17)?;
-                generate_wds(wds)?
-            }
-            Segment::PaletteDefinition(pds) => {
-                self.write_u32::<BigEndian>(pds.pts)?;
-                self.write_u32::<BigEndian>(pds.dts)?;
-                self.write_u8(0x14)?;
-                generate_pds(pds)?
-            }
-            Segment::ObjectDefinition(ods) => {
-                self.write_u32::<BigEndian>(ods.pts)?;
-                self.write_u32::<BigEndian>(ods.dts)?;
-                self.write_u8(0x15)?;
-                generate_ods(ods)?
-            }
-            Segment::End(es) => {
-                self.write_u32::<BigEndian>(es.pts)?;
-                self.write_u32::<BigEndian>(es.dts)?;
-                self.write_u8(0x80)?;
-                vec![]
-            }
+        // The payload is put together first, so that nothing is written of a segment that
+        // cannot be.
+        let (pts, dts, payload) = match &segment {
+            Segment::PresentationComposition(pcs) => (pcs.pts, pcs.dts, generate_pcs(pcs)?),
+            Segment::WindowDefinition(wds) => (wds.pts, wds.dts, generate_wds(wds)?),
+            Segment::PaletteDefinition(pds) => (pds.pts, pds.dts, generate_pds(pds)?),
+            Segment::ObjectDefinition(ods) => (ods.pts, ods.dts, generate_ods(ods)?),
+            Segment::End(es) => (es.pts, es.dts, vec![]),
+            Segment::Unknown(us) => (us.pts, us.dts, us.data.clone()),
         };
 
+        if payload.len() > 65_535 {
+            return Err(WriteError::PayloadTooLarge)
+        }
+
+        self.write_u16::<BigEndian>(0x5047)?;
+        self.write_u32::<BigEndian>(pts)?;
+        self.write_u32::<BigEndian>(dts)?;
+        self.write_u8(segment.kind().code())?;
         self.write_u16::<BigEndian>(payload.len() as u16)?;
         self.write_all(&payload)?;
 
@@ -95,7 +88,7 @@ fn generate_pcs(pcs: &PresentationCompositionSegment) -> WriteResult<Vec<u8>> {
 
     payload.write_u16::<BigEndian>(pcs.width)?;
     payload.write_u16::<BigEndian>(pcs.height)?;
-    payload.write_u8(pcs.frame_rate)?;
+    payload.write_u8(pcs.frame_rate.code())?;
     payload.write_u16::<BigEndian>(pcs.composition_number)?;
     payload.write_u8(
         match pcs.composition_state {
@@ -108,7 +101,7 @@ fn generate_pcs(pcs: &PresentationCompositionSegment) -> WriteResult<Vec<u8>> {
     match pcs.palette_update_id {
         Some(pal_id) => {
             payload.write_u8(0x80)?;
-            payload.write_u8(pal_id)?;
+            payload.write_u8(pal_id.0)?;
         }
         None => {
             payload.write_u8(0x00)?;
@@ -124,17 +117,13 @@ fn generate_pcs(pcs: &PresentationCompositionSegment) -> WriteResult<Vec<u8>> {
 
     for comp_obj in &pcs.composition_objects {
 
-        payload.write_u16::<BigEndian>(comp_obj.object_id)?;
-        payload.write_u8(comp_obj.window_id)?;
+        payload.write_u16::<BigEndian>(comp_obj.object_id.0)?;
+        payload.write_u8(comp_obj.window_id.0)?;
 
         let cropped = comp_obj.crop.is_some();
 
         payload.write_u8(
-            if cropped {
-                0x40
-            } else {
-                0x00
-            }
+            if cropped { 0x80 } else { 0x00 } | if comp_obj.forced { 0x40 } else { 0x00 }
         )?;
         payload.write_u16::<BigEndian>(comp_obj.x)?;
         payload.write_u16::<BigEndian>(comp_obj.y)?;
@@ -164,7 +153,7 @@ fn generate_wds(wds: &WindowDefinitionSegment) -> WriteResult<Vec<u8>> {
     }
 
     for window in wds.windows.iter() {
-        payload.write_u8(window.id)?;
+        payload.write_u8(window.id.0)?;
         payload.write_u16::<BigEndian>(window.x)?;
         payload.write_u16::<BigEndian>(window.y)?;
         payload.write_u16::<BigEndian>(window.width)?;
@@ -178,7 +167,7 @@ fn generate_pds(pds: &PaletteDefinitionSegment) -> WriteResult<Vec<u8>> {
 
     let mut payload = vec![];
 
-    payload.write_u8(pds.id)?;
+    payload.write_u8(pds.id.0)?;
     payload.write_u8(pds.version)?;
 
     for entry in &pds.entries {
@@ -195,111 +184,87 @@ fn generate_pds(pds: &PaletteDefinitionSegment) -> WriteResult<Vec<u8>> {
 fn generate_ods(ods: &ObjectDefinitionSegment) -> WriteResult<Vec<u8>> {
 
     let mut payload = vec![];
-    let data = rle_compress(&ods.lines)?;
 
-    payload.write_u16::<BigEndian>(ods.id)?;
+    payload.write_u16::<BigEndian>(ods.id.0)?;
     payload.write_u8(ods.version)?;
     payload.write_u8(
         match &ods.sequence {
             Sequence::Single => 0xC0,
             Sequence::First => 0x80,
+            Sequence::Middle => 0x00,
             Sequence::Last => 0x40,
         }
     )?;
 
-    // I have no idea why PGS streams record +4 bytes for the object data size, but they do.
-    if data.len() <= 16_777_211 {
-        payload.write_u24::<BigEndian>((data.len() + 4) as u32)?;
-    } else {
-        return Err(WriteError::ObjectDataTooLarge)
+    match (ods.sequence.is_first(), &ods.header) {
+        (true, Some(header)) => {
+
+            // I have no idea why PGS streams record +4 bytes for the object data size, but they
+            // do.
+            if header.data_length <= 16_777_211 {
+                payload.write_u24::<BigEndian>((header.data_length + 4) as u32)?;
+            } else {
+                return Err(WriteError::ObjectDataTooLarge)
+            }
+
+            payload.write_u16::<BigEndian>(header.width)?;
+            payload.write_u16::<BigEndian>(header.height)?;
+        }
+        (false, None) => {
+        }
+        _ => {
+            return Err(WriteError::InconsistentObjectHeader)
+        }
     }
 
-    payload.write_u16::<BigEndian>(ods.width)?;
-    payload.write_u16::<BigEndian>(ods.height)?;
-    payload.write_all(&data)?;
+    payload.write_all(&ods.data)?;
 
     Ok(payload)
 }
 
-fn rle_compress(input: &Vec<Vec<u8>>) -> WriteResult<Vec<u8>> {
+// Splits a single object definition whose data is too large for one segment into a first, any
+// number of middle, and a last fragment. Anything that already fits is returned as it is.
+pub fn split_object_definition(ods: ObjectDefinitionSegment) -> Vec<ObjectDefinitionSegment> {
 
-    let mut output = Vec::<u8>::new();
-    let mut byte = 0_u8;
-    let mut count = 0_usize;
-
-    for line in input.iter() {
-
-        for next_byte in line.iter() {
-            if *next_byte == byte {
-                count += 1;
-            } else {
-                if count > 0 {
-                    output_rle_sequence(&mut output, byte, count)?;
-                }
-                byte = *next_byte;
-                count = 1;
-            }
-        }
-
-        output_rle_sequence(&mut output, byte, count)?;
-        byte = 0;
-        count = 0;
-
-        output.push(0x00);
-        output.push(0x00);
+    if ods.sequence != Sequence::Single || ods.data.len() <= MAX_FIRST_FRAGMENT_DATA {
+        return vec![ods]
     }
 
-    Ok(output)
+    let (first, rest) = ods.data.split_at(MAX_FIRST_FRAGMENT_DATA);
+    let mut fragments = vec![first];
+
+    fragments.extend(rest.chunks(MAX_FRAGMENT_DATA));
+
+    let last = fragments.len() - 1;
+
+    fragments.into_iter()
+        .enumerate()
+        .map(|(index, data)| {
+            ObjectDefinitionSegment {
+                pts: ods.pts,
+                dts: ods.dts,
+                id: ods.id,
+                version: ods.version,
+                sequence: match index {
+                    0 => Sequence::First,
+                    _ if index == last => Sequence::Last,
+                    _ => Sequence::Middle,
+                },
+                header: if index == 0 { ods.header.clone() } else { None },
+                data: data.to_vec(),
+                raw: Raw::default(),
+            }
+        })
+        .collect()
 }
 
-fn output_rle_sequence(output: &mut Vec<u8>, byte: u8, count: usize) -> WriteResult<()> {
+pub(crate) fn rle_compress(input: &[Vec<u8>]) -> Vec<u8> {
 
-    if byte == 0x00 {
-        match count {
-            0 => {
-                //panic!("attempted to handle zero-byte sequence in PGS line")
-            }
-            1 ..= 63 => {
-                output.push(0x00);
-                output.push(count as u8);
-            }
-            64 ..= 16383 => {
-                output.push(0x00);
-                output.push(0x40 | (count >> 8) as u8);
-                output.push((count & 0xFF) as u8);
-            }
-            _ => {
-                return Err(WriteError::ObjectLineTooLong)
-            }
-        }
-    } else {
-        match count {
-            0 => {
-                //panic!("attempted to handle zero-byte sequence in PGS line")
-            }
-            1 => {
-                output.push(byte);
-            }
-            2 => {
-                output.push(byte);
-                output.push(byte);
-            }
-            3 ..= 63 => {
-                output.push(0x00);
-                output.push(0x80 | count as u8);
-                output.push(byte);
-            }
-            64 ..= 16383 => {
-                output.push(0x00);
-                output.push(0xC0 | (count >> 8) as u8);
-                output.push((count & 0xFF) as u8);
-                output.push(byte);
-            }
-            _ => {
-                return Err(WriteError::ObjectLineTooLong)
-            }
-        }
+    let mut output = Vec::<u8>::new();
+
+    for line in input.iter() {
+        encode_line(&mut output, line);
     }
 
-    Ok(())
+    output
 }
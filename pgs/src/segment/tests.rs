@@ -6,10 +6,11 @@
 
 use super::{
     *,
-    segmentread::ReadSegmentExt,
-    segmentwrite::WriteSegmentExt,
+    super::id::{ObjectId, PaletteId, WindowId},
+    segmentread::{rle_decompress, ReadSegmentExt, ResyncReader},
+    segmentwrite::{rle_compress, WriteSegmentExt},
 };
-use std::io::Cursor;
+use std::{collections::HashSet, io::Cursor};
 use rand::{thread_rng, Rng};
 
 #[test]
@@ -22,11 +23,12 @@ fn test_pcs_cycle_no_pui_no_co() {
             dts: rng.gen(),
             width: rng.gen(),
             height: rng.gen(),
-            frame_rate: rng.gen(),
+            frame_rate: FrameRate::from_code(rng.gen()),
             composition_number: rng.gen(),
             composition_state: CompositionState::Normal,
             palette_update_id: None,
             composition_objects: vec![],
+            raw: Raw::default(),
         }
     );
 
@@ -43,21 +45,22 @@ fn test_pcs_cycle_no_pui_co() {
             dts: rng.gen(),
             width: rng.gen(),
             height: rng.gen(),
-            frame_rate: rng.gen(),
+            frame_rate: FrameRate::from_code(rng.gen()),
             composition_number: rng.gen(),
             composition_state: CompositionState::Normal,
             palette_update_id: None,
             composition_objects: vec![
                 CompositionObject {
-                    object_id: rng.gen(),
-                    window_id: rng.gen(),
+                    object_id: ObjectId(rng.gen()),
+                    window_id: WindowId(rng.gen()),
                     x: rng.gen(),
                     y: rng.gen(),
                     crop: None,
+                    forced: rng.gen(),
                 },
                 CompositionObject {
-                    object_id: rng.gen(),
-                    window_id: rng.gen(),
+                    object_id: ObjectId(rng.gen()),
+                    window_id: WindowId(rng.gen()),
                     x: rng.gen(),
                     y: rng.gen(),
                     crop: Some(
@@ -68,8 +71,10 @@ fn test_pcs_cycle_no_pui_co() {
                             height: rng.gen(),
                         }
                     ),
+                    forced: rng.gen(),
                 },
             ],
+            raw: Raw::default(),
         }
     );
 
@@ -86,11 +91,12 @@ fn test_pcs_cycle_pui_no_co() {
             dts: rng.gen(),
             width: rng.gen(),
             height: rng.gen(),
-            frame_rate: rng.gen(),
+            frame_rate: FrameRate::from_code(rng.gen()),
             composition_number: rng.gen(),
             composition_state: CompositionState::Normal,
-            palette_update_id: Some(rng.gen()),
+            palette_update_id: Some(PaletteId(rng.gen())),
             composition_objects: vec![],
+            raw: Raw::default(),
         }
     );
 
@@ -107,21 +113,22 @@ fn test_pcs_cycle_pui_co() {
             dts: rng.gen(),
             width: rng.gen(),
             height: rng.gen(),
-            frame_rate: rng.gen(),
+            frame_rate: FrameRate::from_code(rng.gen()),
             composition_number: rng.gen(),
             composition_state: CompositionState::Normal,
-            palette_update_id: Some(rng.gen()),
+            palette_update_id: Some(PaletteId(rng.gen())),
             composition_objects: vec![
                 CompositionObject {
-                    object_id: rng.gen(),
-                    window_id: rng.gen(),
+                    object_id: ObjectId(rng.gen()),
+                    window_id: WindowId(rng.gen()),
                     x: rng.gen(),
                     y: rng.gen(),
                     crop: None,
+                    forced: rng.gen(),
                 },
                 CompositionObject {
-                    object_id: rng.gen(),
-                    window_id: rng.gen(),
+                    object_id: ObjectId(rng.gen()),
+                    window_id: WindowId(rng.gen()),
                     x: rng.gen(),
                     y: rng.gen(),
                     crop: Some(
@@ -132,8 +139,10 @@ fn test_pcs_cycle_pui_co() {
                             height: rng.gen(),
                         }
                     ),
+                    forced: rng.gen(),
                 },
             ],
+            raw: Raw::default(),
         }
     );
 
@@ -149,6 +158,7 @@ fn test_wds_empty() {
             pts: rng.gen(),
             dts: rng.gen(),
             windows: vec![],
+            raw: Raw::default(),
         }
     );
 
@@ -165,27 +175,28 @@ fn test_wds_not_empty() {
             dts: rng.gen(),
             windows: vec![
                 WindowDefinition {
-                    id: rng.gen(),
+                    id: WindowId(rng.gen()),
                     x: rng.gen(),
                     y: rng.gen(),
                     width: rng.gen(),
                     height: rng.gen(),
                 },
                 WindowDefinition {
-                    id: rng.gen(),
+                    id: WindowId(rng.gen()),
                     x: rng.gen(),
                     y: rng.gen(),
                     width: rng.gen(),
                     height: rng.gen(),
                 },
                 WindowDefinition {
-                    id: rng.gen(),
+                    id: WindowId(rng.gen()),
                     x: rng.gen(),
                     y: rng.gen(),
                     width: rng.gen(),
                     height: rng.gen(),
                 },
             ],
+            raw: Raw::default(),
         }
     );
 
@@ -200,9 +211,10 @@ fn test_pds_empty() {
         PaletteDefinitionSegment {
             pts: rng.gen(),
             dts: rng.gen(),
-            id: rng.gen(),
+            id: PaletteId(rng.gen()),
             version: rng.gen(),
             entries: vec![],
+            raw: Raw::default(),
         }
     );
 
@@ -217,7 +229,7 @@ fn test_pds_not_empty() {
         PaletteDefinitionSegment {
             pts: rng.gen(),
             dts: rng.gen(),
-            id: rng.gen(),
+            id: PaletteId(rng.gen()),
             version: rng.gen(),
             entries: vec![
                 PaletteEntry {
@@ -242,110 +254,121 @@ fn test_pds_not_empty() {
                     alpha: rng.gen(),
                 },
             ],
+            raw: Raw::default(),
         }
     );
 
     cycle(&segment);
 }
 
+#[test]
+fn test_rle_cycle() {
+
+    let lines = vec![
+        vec![],
+        vec![0],
+        vec![],
+        vec![1],
+        vec![],
+        vec![0, 0],
+        vec![],
+        vec![1, 1],
+        vec![],
+        vec![0, 0, 0],
+        vec![],
+        vec![1, 1, 1],
+        vec![],
+        vec![
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ],
+        vec![],
+        vec![
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        ],
+        vec![],
+        vec![
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ],
+        vec![],
+        vec![
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        ],
+        vec![],
+        vec![],
+        vec![
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ],
+        vec![],
+        vec![
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        ],
+        vec![
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+            21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39,
+            40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58,
+            59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77,
+            78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95, 96,
+            97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111, 112,
+            113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 124, 125, 126, 127,
+            128, 129, 130, 131, 132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142,
+            143, 144, 145, 146, 147, 148, 149, 150, 151, 152, 153, 154, 155, 156, 157,
+            158, 159, 160, 161, 162, 163, 164, 165, 166, 167, 168, 169, 170, 171, 172,
+            173, 174, 175, 176, 177, 178, 179, 180, 181, 182, 183, 184, 185, 186, 187,
+            188, 189, 190, 191, 192, 193, 194, 195, 196, 197, 198, 199, 200, 201, 202,
+            203, 204, 205, 206, 207, 208, 209, 210, 211, 212, 213, 214, 215, 216, 217,
+            218, 219, 220, 221, 222, 223, 224, 225, 226, 227, 228, 229, 230, 231, 232,
+            233, 234, 235, 236, 237, 238, 239, 240, 241, 242, 243, 244, 245, 246, 247,
+            248, 249, 250, 251, 252, 253, 254, 255,
+        ],
+        vec![],
+        vec![],
+    ];
+    let data = rle_compress(&lines);
+
+    assert_eq!(rle_decompress(&data, usize::MAX).unwrap(), lines);
+}
+
 #[test]
 fn test_ods_single() {
 
     let mut rng = thread_rng();
+    let data = rle_compress(&[vec![1, 1, 1], vec![0, 2]]);
     let segment = Segment::ObjectDefinition(
         ObjectDefinitionSegment {
             pts: rng.gen(),
             dts: rng.gen(),
-            id: rng.gen(),
+            id: ObjectId(rng.gen()),
             version: rng.gen(),
             sequence: Sequence::Single,
-            width: rng.gen(),
-            height: rng.gen(),
-            lines: vec![
-                vec![],
-                vec![0],
-                vec![],
-                vec![1],
-                vec![],
-                vec![0, 0],
-                vec![],
-                vec![1, 1],
-                vec![],
-                vec![0, 0, 0],
-                vec![],
-                vec![1, 1, 1],
-                vec![],
-                vec![
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                ],
-                vec![],
-                vec![
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                ],
-                vec![],
-                vec![
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                ],
-                vec![],
-                vec![
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                ],
-                vec![],
-                vec![],
-                vec![
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                ],
-                vec![],
-                vec![
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-                ],
-                vec![
-                    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
-                    21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39,
-                    40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58,
-                    59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77,
-                    78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95, 96,
-                    97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111, 112,
-                    113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 124, 125, 126, 127,
-                    128, 129, 130, 131, 132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142,
-                    143, 144, 145, 146, 147, 148, 149, 150, 151, 152, 153, 154, 155, 156, 157,
-                    158, 159, 160, 161, 162, 163, 164, 165, 166, 167, 168, 169, 170, 171, 172,
-                    173, 174, 175, 176, 177, 178, 179, 180, 181, 182, 183, 184, 185, 186, 187,
-                    188, 189, 190, 191, 192, 193, 194, 195, 196, 197, 198, 199, 200, 201, 202,
-                    203, 204, 205, 206, 207, 208, 209, 210, 211, 212, 213, 214, 215, 216, 217,
-                    218, 219, 220, 221, 222, 223, 224, 225, 226, 227, 228, 229, 230, 231, 232,
-                    233, 234, 235, 236, 237, 238, 239, 240, 241, 242, 243, 244, 245, 246, 247,
-                    248, 249, 250, 251, 252, 253, 254, 255,
-                ],
-                vec![],
-                vec![],
-            ],
+            header: Some(ObjectHeader { data_length: data.len(), width: 3, height: 2 }),
+            data,
+            raw: Raw::default(),
         }
     );
 
@@ -353,43 +376,107 @@ fn test_ods_single() {
 }
 
 #[test]
-fn test_ods_first() {
+fn test_ods_fragments() {
 
     let mut rng = thread_rng();
-    let segment = Segment::ObjectDefinition(
+    let fragment = |sequence, header, data: &[u8]| Segment::ObjectDefinition(
         ObjectDefinitionSegment {
-            pts: rng.gen(),
-            dts: rng.gen(),
-            id: rng.gen(),
-            version: rng.gen(),
-            sequence: Sequence::First,
-            width: rng.gen(),
-            height: rng.gen(),
-            lines: vec![],
+            pts: 900,
+            dts: 0,
+            id: ObjectId(1),
+            version: 0,
+            sequence,
+            header,
+            data: data.to_vec(),
+            raw: Raw::default(),
         }
     );
+    let data = (0..30).map(|_| rng.gen()).collect::<Vec<u8>>();
+    let header = ObjectHeader { data_length: data.len(), width: rng.gen(), height: rng.gen() };
 
-    cycle(&segment);
+    cycle(&fragment(Sequence::First, Some(header.clone()), &data[..10]));
+    cycle(&fragment(Sequence::Middle, None, &data[10..20]));
+    cycle(&fragment(Sequence::Last, None, &data[20..]));
+
+    // A fragment cannot declare less data than it carries itself.
+    let mut buffer = vec![];
+
+    buffer.write_segment(&fragment(Sequence::First, Some(header.clone()), &data)).unwrap();
+    buffer[17..20].copy_from_slice(&[0, 0, 14]);
+
+    assert!(matches!(
+        Cursor::new(&buffer).read_segment(),
+        Err(ReadError::InvalidObjectDataLength),
+    ));
+    assert!(matches!(
+        Vec::new().write_segment(&fragment(Sequence::Last, Some(header), &data)),
+        Err(WriteError::InconsistentObjectHeader),
+    ));
 }
 
 #[test]
-fn test_ods_last() {
+fn test_ods_from_bytes() {
 
-    let mut rng = thread_rng();
-    let segment = Segment::ObjectDefinition(
-        ObjectDefinitionSegment {
-            pts: rng.gen(),
-            dts: rng.gen(),
-            id: rng.gen(),
-            version: rng.gen(),
-            sequence: Sequence::Last,
-            width: rng.gen(),
-            height: rng.gen(),
-            lines: vec![],
-        }
+    // An object of eight bytes of RLE data split across two fragments, five bytes in the first.
+    let first = raw_segment(
+        0x15,
+        &[0x00, 0x03, 0x01, 0x80, 0x00, 0x00, 0x0C, 0x00, 0x06, 0x00, 0x01, 0x00, 0x86, 0x02, 0x00,
+            0x00],
     );
+    let last = raw_segment(0x15, &[0x00, 0x03, 0x01, 0x40, 0x01, 0x00, 0x00]);
+    let mut cursor = Cursor::new([&first[..], &last].concat());
+    let segments = [cursor.read_segment().unwrap(), cursor.read_segment().unwrap()];
 
-    cycle(&segment);
+    assert_eq!(
+        segments,
+        [
+            Segment::ObjectDefinition(
+                ObjectDefinitionSegment {
+                    pts: 0,
+                    dts: 0,
+                    id: ObjectId(3),
+                    version: 1,
+                    sequence: Sequence::First,
+                    header: Some(ObjectHeader { data_length: 8, width: 6, height: 1 }),
+                    data: vec![0x00, 0x86, 0x02, 0x00, 0x00],
+                    raw: Raw::default(),
+                }
+            ),
+            Segment::ObjectDefinition(
+                ObjectDefinitionSegment {
+                    pts: 0,
+                    dts: 0,
+                    id: ObjectId(3),
+                    version: 1,
+                    sequence: Sequence::Last,
+                    header: None,
+                    data: vec![0x01, 0x00, 0x00],
+                    raw: Raw::default(),
+                }
+            ),
+        ],
+    );
+
+    for (segment, bytes) in segments.iter().zip([&first, &last].iter()) {
+
+        let mut written = vec![];
+
+        written.write_segment(segment).unwrap();
+
+        assert_eq!(&written, *bytes);
+    }
+
+    // The segment size follows the data that is written rather than what was read.
+    let mut shorter = segments[1].clone();
+    let mut written = vec![];
+
+    if let Segment::ObjectDefinition(ods) = &mut shorter {
+        ods.data.pop();
+    }
+    written.write_segment(&shorter).unwrap();
+
+    assert_eq!(written[11..13], [0x00, 0x06]);
+    assert_eq!(written.len(), 13 + 6);
 }
 
 #[test]
@@ -400,20 +487,952 @@ fn test_es() {
         EndSegment {
             pts: rng.gen(),
             dts: rng.gen(),
+            raw: Raw::default(),
         }
     );
 
     cycle(&segment);
 }
 
+#[test]
+fn test_keep_raw() {
+
+    let mut buffer = vec![];
+    let options = ReadOptions { keep_raw: true, ..Default::default() };
+
+    buffer.write_segment(&Segment::WindowDefinition(
+        WindowDefinitionSegment {
+            pts: 900,
+            dts: 0,
+            windows: vec![WindowDefinition { id: WindowId(0), x: 1, y: 2, width: 3, height: 4 }],
+            raw: Raw::default(),
+        }
+    )).unwrap();
+
+    assert_eq!(Cursor::new(&buffer).read_segment().unwrap().raw(), None);
+
+    let mut segment = Cursor::new(&buffer).read_segment_with(&options).unwrap();
+
+    assert_eq!(segment.raw(), Some(buffer.as_slice()));
+
+    if let Segment::WindowDefinition(wds) = &mut segment {
+        wds.windows[0].x = 5;
+    }
+
+    assert_eq!(segment.raw(), None);
+}
+
+#[test]
+fn test_stream_cycle() {
+
+    let mut input = vec![];
+    let mut output = vec![];
+    let data = rle_compress(&[vec![1; 8], vec![0, 2, 2, 0]]);
+    let pcs = [
+        0x07, 0x80, 0x04, 0x38, 0x10, 0x00, 0x01, 0x80, 0x00, 0x00, 0x02,
+        0x00, 0x01, 0x00, 0x80, 0x00, 0x64, 0x03, 0x84,
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x04, 0x00, 0x01,
+        0x00, 0x02, 0x00, 0x00, 0x00, 0xC8, 0x03, 0x84,
+    ];
+    let ods = [
+        &[0x00, 0x01, 0x00, 0xC0, 0x00, 0x00, data.len() as u8 + 4, 0x00, 0x08, 0x00, 0x02][..],
+        &data,
+    ].concat();
+    // A palette update that also defines an object split across three fragments.
+    let update = [0x07, 0x80, 0x04, 0x38, 0x10, 0x00, 0x02, 0x00, 0x80, 0x00, 0x00];
+    let fragments = [
+        &[0x00, 0x02, 0x00, 0x80, 0x00, 0x00, data.len() as u8 + 4, 0x00, 0x08, 0x00, 0x02][..],
+        &data[..4],
+        &[0x00, 0x02, 0x00, 0x00],
+        &data[4..6],
+        &[0x00, 0x02, 0x00, 0x40],
+        &data[6..],
+    ];
+
+    for (kind, payload) in [
+        (0x16, &pcs[..]),
+        (0x17, &[0x01, 0x00, 0x00, 0x64, 0x03, 0x84, 0x00, 0x08, 0x00, 0x02]),
+        (0x14, &[0x00, 0x00, 0x01, 0xEB, 0x80, 0x80, 0xFF, 0x02, 0x10, 0x80, 0x80, 0x80]),
+        (0x15, &ods),
+        (0x80, &[]),
+        (0x16, &update),
+        (0x14, &[0x00, 0x01, 0x01, 0xEB, 0x80, 0x80, 0x80]),
+        (0x15, &fragments[..2].concat()),
+        (0x15, &fragments[2..4].concat()),
+        (0x15, &fragments[4..].concat()),
+        (0x80, &[]),
+    ].iter() {
+        input.extend_from_slice(&raw_segment(*kind, payload));
+    }
+
+    let mut cursor = Cursor::new(&input);
+
+    while (cursor.position() as usize) < input.len() {
+        output.write_segment(&cursor.read_segment().unwrap()).unwrap();
+    }
+
+    assert_eq!(output, input);
+}
+
+#[test]
+fn test_fixture_cycle() {
+
+    let input = include_bytes!("../../../test-data/fragmented-object.sup");
+    let mut output = vec![];
+    let mut sequences = vec![];
+    let mut cursor = Cursor::new(&input[..]);
+    let hash = |bytes: &[u8]| {
+        let mut hasher = Fnv::default();
+        hasher.write(bytes);
+        hasher.finish()
+    };
+
+    while (cursor.position() as usize) < input.len() {
+
+        let segment = cursor.read_segment().unwrap();
+
+        if let Segment::ObjectDefinition(ods) = &segment {
+            sequences.push(ods.sequence);
+        }
+
+        output.write_segment(&segment).unwrap();
+    }
+
+    assert_eq!(sequences, [Sequence::First, Sequence::Last]);
+    assert_eq!(hash(&output), hash(input));
+}
+
+#[test]
+fn test_fixtures_copy_through() {
+
+    let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/../test-data");
+    let mut copied = 0;
+
+    for entry in std::fs::read_dir(directory).unwrap() {
+
+        let path = entry.unwrap().path();
+
+        if path.extension().is_none_or(|extension| extension != "sup") {
+            continue
+        }
+
+        let input = std::fs::read(&path).unwrap();
+        let mut output = vec![];
+        let mut ends = 0;
+        let mut cursor = Cursor::new(&input);
+
+        while (cursor.position() as usize) < input.len() {
+
+            let segment = cursor.read_segment().unwrap();
+
+            if segment.kind() == SegmentKind::End {
+                ends += 1;
+            }
+
+            output.write_segment(&segment).unwrap();
+        }
+
+        assert!(ends > 0, "{}", path.display());
+        assert!(output == input, "{}", path.display());
+        copied += 1;
+    }
+
+    assert!(copied >= 2);
+}
+
+#[test]
+fn test_payload_too_large() {
+
+    let segment = Segment::PaletteDefinition(
+        PaletteDefinitionSegment {
+            pts: 0,
+            dts: 0,
+            id: PaletteId(0),
+            version: 0,
+            entries: vec![PaletteEntry::default(); 13_106],
+            raw: Raw::default(),
+        }
+    );
+    let mut buffer = vec![];
+
+    assert!(buffer.write_segment(&segment).is_ok());
+
+    if let Segment::PaletteDefinition(mut pds) = segment {
+
+        pds.entries.push(PaletteEntry::default());
+
+        assert!(matches!(
+            buffer.write_segment(&Segment::PaletteDefinition(pds)),
+            Err(WriteError::PayloadTooLarge),
+        ));
+    }
+}
+
 fn cycle(segment: &Segment) {
 
     let mut buffer = vec![];
 
-    buffer.write_segment(&segment).unwrap();
+    buffer.write_segment(segment).unwrap();
 
     let mut cursor = Cursor::new(buffer);
     let cycled_segment = cursor.read_segment().unwrap();
 
     assert_eq!(cycled_segment, *segment);
 }
+
+fn raw_segment(kind: u8, payload: &[u8]) -> Vec<u8> {
+
+    let mut buffer = vec![0x50, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, kind];
+
+    buffer.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(payload);
+
+    buffer
+}
+
+#[test]
+fn test_pds_from_bytes() {
+
+    let pds = raw_segment(
+        0x14,
+        &[0x01, 0x02, 0x00, 0x10, 0x80, 0x80, 0x00, 0x01, 0xEB, 0x80, 0x80, 0xFF, 0xFF, 0x51, 0x5A,
+            0xF0, 0x80],
+    );
+    let mut buffer = pds.clone();
+
+    buffer.extend_from_slice(&raw_segment(0x80, &[]));
+
+    let mut cursor = Cursor::new(buffer);
+    let segment = cursor.read_segment().unwrap();
+    let mut written = vec![];
+
+    assert_eq!(
+        segment,
+        Segment::PaletteDefinition(
+            PaletteDefinitionSegment {
+                pts: 0,
+                dts: 0,
+                id: PaletteId(1),
+                version: 2,
+                entries: vec![
+                    PaletteEntry { id: 0x00, y: 0x10, cr: 0x80, cb: 0x80, alpha: 0x00 },
+                    PaletteEntry { id: 0x01, y: 0xEB, cr: 0x80, cb: 0x80, alpha: 0xFF },
+                    PaletteEntry { id: 0xFF, y: 0x51, cr: 0x5A, cb: 0xF0, alpha: 0x80 },
+                ],
+                raw: Raw::default(),
+            }
+        ),
+    );
+    assert!(matches!(cursor.read_segment().unwrap(), Segment::End(_)));
+
+    written.write_segment(&segment).unwrap();
+
+    assert_eq!(written, pds);
+}
+
+#[test]
+fn test_pds_truncated() {
+    for payload in [&[0x01][..], &[0x01, 0x02, 0x00, 0x10, 0x80, 0x80]].iter() {
+        assert!(matches!(
+            Cursor::new(raw_segment(0x14, payload)).read_segment(),
+            Err(ReadError::TruncatedPaletteDefinition),
+        ));
+    }
+}
+
+#[test]
+fn test_wds_from_bytes() {
+
+    let bytes = [
+        [0x01, 0x00, 0x10, 0x03, 0x84, 0x02, 0x80, 0x00, 0x40],
+        [0x02, 0x01, 0x00, 0x00, 0x20, 0x00, 0x64, 0x00, 0x32],
+    ];
+    let windows = [
+        WindowDefinition { id: WindowId(1), x: 16, y: 900, width: 640, height: 64 },
+        WindowDefinition { id: WindowId(2), x: 256, y: 32, width: 100, height: 50 },
+    ];
+
+    for count in 0..=2 {
+
+        let payload = [&[count as u8][..], &bytes[..count].concat()].concat();
+        let wds = raw_segment(0x17, &payload);
+        let mut buffer = wds.clone();
+
+        buffer.extend_from_slice(&raw_segment(0x80, &[]));
+
+        let mut cursor = Cursor::new(buffer);
+        let segment = cursor.read_segment().unwrap();
+        let mut written = vec![];
+
+        written.write_segment(&segment).unwrap();
+
+        assert_eq!(written, wds);
+        assert_eq!(
+            segment,
+            Segment::WindowDefinition(
+                WindowDefinitionSegment {
+                    pts: 0,
+                    dts: 0,
+                    windows: windows[..count].to_vec(),
+                    raw: Raw::default(),
+                }
+            ),
+        );
+        assert_eq!(
+            cursor.read_segment().unwrap(),
+            Segment::End(EndSegment { pts: 0, dts: 0, raw: Raw::default() }),
+        );
+        assert!(matches!(
+            cursor.read_segment(),
+            Err(ReadError::IoError { ref source })
+                if source.kind() == std::io::ErrorKind::UnexpectedEof,
+        ));
+    }
+}
+
+#[test]
+fn test_composition_object_flags() {
+
+    // One forced object, one that is both forced and cropped, and one that is only cropped.
+    let payload = [
+        0x07, 0x80, 0x04, 0x38, 0x10, 0x00, 0x01, 0x80, 0x00, 0x00, 0x03,
+        0x00, 0x00, 0x00, 0x40, 0x00, 0x64, 0x03, 0x84,
+        0x00, 0x01, 0x00, 0xC0, 0x00, 0x64, 0x03, 0xC0,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x08,
+        0x00, 0x02, 0x00, 0x80, 0x00, 0x64, 0x04, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x08,
+    ];
+    let raw = raw_segment(0x16, &payload);
+    let segment = Cursor::new(&raw).read_segment().unwrap();
+    let pcs = match &segment {
+        Segment::PresentationComposition(pcs) => pcs,
+        _ => panic!("expected a presentation composition segment"),
+    };
+    let flags = pcs.composition_objects.iter()
+        .map(|co| (co.forced, co.crop.is_some()))
+        .collect::<Vec<(bool, bool)>>();
+    let mut written = vec![];
+
+    assert_eq!(flags, vec![(true, false), (true, true), (false, true)]);
+
+    written.write_segment(&segment).unwrap();
+
+    assert_eq!(written, raw);
+
+    let mut unknown = payload;
+
+    unknown[14] = 0x41;
+
+    assert!(matches!(
+        Cursor::new(raw_segment(0x16, &unknown)).read_segment(),
+        Err(ReadError::UnrecognizedCompositionObjectFlags),
+    ));
+}
+
+#[test]
+fn test_wds_sizes() {
+
+    let window = [0x01, 0x00, 0x10, 0x03, 0x84, 0x02, 0x80, 0x00, 0x40];
+    let windows = |payload: &[u8]| match Cursor::new(raw_segment(0x17, payload)).read_segment() {
+        Ok(Segment::WindowDefinition(wds)) => Ok(wds.windows.len()),
+        Ok(other) => panic!("{:?}", other),
+        Err(err) => Err(err),
+    };
+
+    assert_eq!(windows(&[&[0x01][..], &window].concat()).unwrap(), 1);
+    // Padded out past its one window, as some muxers do.
+    assert_eq!(windows(&[&[0x01][..], &window, &[0x00, 0x00]].concat()).unwrap(), 1);
+    assert_eq!(windows(&[&[0x00][..], &window].concat()).unwrap(), 0);
+
+    for (payload, declared, consumed) in [
+        (&[0x01][..], 1, 10),
+        (&[&[0x02][..], &window].concat(), 10, 19),
+    ].iter() {
+        match windows(payload) {
+            Err(err @ ReadError::SizeMismatch { .. }) => {
+                assert_eq!(
+                    err.to_string(),
+                    format!(
+                        "segment declares {} bytes but its fields run to at least {}",
+                        declared,
+                        consumed,
+                    ),
+                );
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_pcs_sizes() {
+
+    let pcs = [0x07, 0x80, 0x04, 0x38, 0x10, 0x00, 0x01, 0x80, 0x00, 0x00, 0x01];
+    let object = [0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x20];
+    let composition_objects = |payload: &[u8]| {
+        match Cursor::new(raw_segment(0x16, payload)).read_segment() {
+            Ok(Segment::PresentationComposition(pcs)) => Ok(pcs.composition_objects.len()),
+            Ok(other) => panic!("{:?}", other),
+            Err(err) => Err(err),
+        }
+    };
+
+    assert_eq!(composition_objects(&[&pcs[..], &object].concat()).unwrap(), 1);
+    assert_eq!(composition_objects(&[&pcs[..], &object, &[0xFF; 3]].concat()).unwrap(), 1);
+    // The one composition object it declares is cut short.
+    assert!(matches!(
+        composition_objects(&[&pcs[..], &object[..5]].concat()),
+        Err(ReadError::SizeMismatch { declared: 16, consumed: 19 }),
+    ));
+    assert!(matches!(
+        composition_objects(&pcs),
+        Err(ReadError::SizeMismatch { declared: 11, consumed: 19 }),
+    ));
+}
+
+#[test]
+fn test_counts_beyond_size() {
+
+    // Counts checked against the size before anything is read or allocated for them.
+    let pcs = [0x07, 0x80, 0x04, 0x38, 0x10, 0x00, 0x01, 0x80, 0x00, 0x00, 0xFF];
+
+    assert!(matches!(
+        Cursor::new(raw_segment(0x16, &pcs)).read_segment(),
+        Err(ReadError::SizeMismatch { declared: 11, consumed }) if consumed == 11 + 255 * 8,
+    ));
+    assert!(matches!(
+        Cursor::new(raw_segment(0x17, &[0xFF, 0x00])).read_segment(),
+        Err(ReadError::SizeMismatch { declared: 2, consumed }) if consumed == 1 + 255 * 9,
+    ));
+}
+
+#[test]
+fn test_ods_and_end_sizes() {
+
+    let ods = [0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x06, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00];
+    let read = |kind: u8, payload: &[u8]| Cursor::new(raw_segment(kind, payload)).read_segment();
+
+    assert!(matches!(read(0x15, &ods), Ok(Segment::ObjectDefinition(_))));
+    // An object's data runs to the end of its segment, so that padding would be taken for data.
+    assert!(matches!(
+        read(0x15, &[&ods[..], &[0x00]].concat()),
+        Err(ReadError::InvalidObjectDataLength),
+    ));
+    assert!(matches!(
+        read(0x15, &ods[..9]),
+        Err(ReadError::SizeMismatch { declared: 9, consumed: 11 }),
+    ));
+    assert!(matches!(read(0x80, &[]), Ok(Segment::End(_))));
+    assert!(matches!(read(0x80, &[0x00; 4]), Ok(Segment::End(_))));
+}
+
+#[test]
+fn test_rle_pixel_limit() {
+
+    let data = rle_compress(&vec![vec![1; 10]; 10]);
+
+    assert!(rle_decompress(&data, 100).is_ok());
+    assert!(matches!(
+        rle_decompress(&data, 99),
+        Err(ReadError::LimitExceeded { limit: Limit::DecodedPixels }),
+    ));
+}
+
+#[test]
+fn test_rle_bomb() {
+
+    // Every four bytes claim 16,383 pixels, which would be about a gigabyte in total.
+    let mut data = vec![];
+
+    for _ in 0..16_000 {
+        data.extend_from_slice(&[0x00, 0xFF, 0xFF, 0x01]);
+    }
+
+    assert!(matches!(
+        rle_decompress(&data, Limits::default().max_decoded_pixels),
+        Err(ReadError::LimitExceeded { limit: Limit::DecodedPixels }),
+    ));
+}
+
+#[test]
+fn test_truncated_payloads() {
+    for &kind in [0x14, 0x15, 0x16, 0x17, 0x80].iter() {
+        for length in 0..24 {
+            for &fill in [0x00, 0x40, 0xFF].iter() {
+                // Only the absence of a panic matters here.
+                let _ = Cursor::new(raw_segment(kind, &vec![fill; length])).read_segment();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_resync_after_size_mismatch() {
+
+    let end = |pts| {
+        let mut buffer = vec![];
+        buffer.write_segment(&Segment::End(EndSegment { pts, ..Default::default() })).unwrap();
+        buffer
+    };
+    // A PCS with one composition object, whose size has been corrupted to leave the object out.
+    let pcs = [0x07, 0x80, 0x04, 0x38, 0x10, 0x00, 0x01, 0x80, 0x00, 0x00, 0x01];
+    let object = [0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x20];
+    let stream = [end(1), raw_segment(0x16, &pcs), object.to_vec(), end(2)].concat();
+    let mut reader = ResyncReader::new(Cursor::new(stream));
+    let options = ReadOptions::default();
+
+    assert!(matches!(reader.read_segment_resync(&options), Ok(Segment::End(_))));
+    assert!(matches!(
+        reader.read_segment_resync(&options),
+        Err(ReadError::At { offset: 13, within: Some(24), source })
+            if matches!(*source, ReadError::SizeMismatch { declared: 11, consumed: 19 }),
+    ));
+    assert_eq!(reader.offset(), 13 + 13 + 11 + 8);
+    assert!(matches!(
+        reader.read_segment_resync(&options),
+        Ok(Segment::End(EndSegment { pts: 2, .. })),
+    ));
+    assert_eq!((reader.skipped(), reader.resyncs()), (13 + 11 + 8, 1));
+}
+
+#[test]
+fn test_read_segment_counted() {
+
+    let mut buffer = vec![];
+
+    buffer.write_segment(&Segment::End(EndSegment::default())).unwrap();
+    buffer.extend(raw_segment(0x16, &[0x07, 0x80, 0x04]));
+    buffer.write_segment(&Segment::End(EndSegment::default())).unwrap();
+
+    let mut cursor = Cursor::new(&buffer);
+    let mut offset = 0;
+    let options = ReadOptions::default();
+
+    assert!(matches!(cursor.read_segment_counted(&mut offset, &options), Ok(Segment::End(_))));
+    assert_eq!(offset, 13);
+    assert_eq!(
+        cursor.read_segment_counted(&mut offset, &options).unwrap_err().to_string(),
+        "segment declares 3 bytes but its fields run to at least 4 (segment at 0xD, byte 0x10)",
+    );
+    assert_eq!(offset, 13 + 16);
+    assert!(matches!(cursor.read_segment_counted(&mut offset, &options), Ok(Segment::End(_))));
+    assert!(matches!(
+        cursor.read_segment_counted(&mut offset, &options),
+        Err(ReadError::IoError { .. }),
+    ));
+}
+
+#[test]
+fn test_segments() {
+
+    let mut buffer = vec![];
+
+    for pts in 1..=3 {
+        buffer.write_segment(&Segment::End(EndSegment { pts, ..Default::default() })).unwrap();
+    }
+
+    let pts = |bytes: &[u8]| {
+        Cursor::new(bytes).segments(&ReadOptions::default())
+            .map(|segment| match segment {
+                Ok(Segment::End(es)) => Ok(es.pts),
+                Ok(other) => panic!("{:?}", other),
+                Err(err) => Err(err),
+            })
+            .collect::<Vec<ReadResult<u32>>>()
+    };
+    let whole = pts(&buffer);
+    let truncated = pts(&buffer[..buffer.len() - 5]);
+
+    assert_eq!(whole.into_iter().collect::<ReadResult<Vec<u32>>>().unwrap(), [1, 2, 3]);
+    assert_eq!(truncated.len(), 3);
+    assert!(matches!(truncated[2], Err(ReadError::IoError { .. })));
+    assert!(pts(&[]).is_empty());
+}
+
+#[test]
+fn test_skim_segment_headers() {
+
+    let mut buffer = vec![];
+
+    buffer.write_segment(&Segment::PaletteDefinition(PaletteDefinitionSegment {
+        pts: 900,
+        dts: 800,
+        entries: vec![PaletteEntry { id: 1, y: 235, cr: 128, cb: 128, alpha: 255 }],
+        ..Default::default()
+    })).unwrap();
+    buffer.extend(raw_segment(0x99, &[1, 2, 3]));
+    buffer.write_segment(&Segment::End(EndSegment { pts: 900, dts: 850, ..Default::default() }))
+        .unwrap();
+
+    let mut cursor = Cursor::new(&buffer);
+    let mut headers = vec![];
+
+    while (cursor.position() as usize) < buffer.len() {
+
+        let start = cursor.position();
+        let header = cursor.read_segment_header().unwrap();
+
+        cursor.skip_segment_payload(&header).unwrap();
+        assert_eq!(cursor.position() - start, header.length() as u64);
+        headers.push(header);
+    }
+
+    assert_eq!(
+        headers,
+        vec![
+            SegmentHeader { pts: 900, dts: 800, kind: SegmentKind::PaletteDefinition, size: 7 },
+            SegmentHeader { pts: 0, dts: 0, kind: SegmentKind::Unknown(0x99), size: 3 },
+            SegmentHeader { pts: 900, dts: 850, kind: SegmentKind::End, size: 0 },
+        ],
+    );
+
+    // A header is only read after a magic number, and a payload cut short can't be skipped.
+    assert!(matches!(
+        Cursor::new(&buffer[1..]).read_segment_header(),
+        Err(ReadError::UnrecognizedMagicNumber),
+    ));
+
+    let mut cursor = Cursor::new(&buffer[..headers[0].length() - 1]);
+    let header = cursor.read_segment_header().unwrap();
+
+    assert!(matches!(
+        cursor.skip_segment_payload(&header),
+        Err(ReadError::IoError { source }) if source.kind() == std::io::ErrorKind::UnexpectedEof,
+    ));
+}
+
+#[test]
+fn test_segment_kind_codes() {
+
+    for code in 0..=255u8 {
+        assert_eq!(u8::from(SegmentKind::from(code)), code);
+    }
+
+    assert_eq!(SegmentKind::from(0x16), SegmentKind::PresentationComposition);
+    assert_eq!(SegmentKind::End.code(), 0x80);
+    assert_eq!(format!("{:>4}", SegmentKind::PaletteDefinition), " PDS");
+
+    // What cannot be written leaves nothing behind, not even the header.
+    let mut buffer = vec![];
+    let wds = WindowDefinitionSegment {
+        windows: vec![WindowDefinition::default(); 256],
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        buffer.write_segment(&Segment::WindowDefinition(wds)),
+        Err(WriteError::TooManyWindowDefinitions),
+    ));
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn test_frame_rate_codes() {
+
+    let known = [0x10, 0x20, 0x30, 0x40, 0x60, 0x70];
+
+    for code in 0..=255u8 {
+
+        let frame_rate = FrameRate::from(code);
+
+        assert_eq!(u8::from(frame_rate), code);
+        assert_eq!(frame_rate == FrameRate::Other(code), !known.contains(&code));
+    }
+
+    assert_eq!(FrameRate::default().code(), 0x10);
+    assert_eq!(FrameRate::from(0x40), FrameRate::Fps29_97);
+}
+
+#[test]
+fn test_unknown_kind_kept() {
+
+    let bytes = [raw_segment(0x99, &[1, 2, 3]), raw_segment(0x80, &[])].concat();
+    let options = ReadOptions { keep_unknown: true, ..Default::default() };
+    let mut cursor = Cursor::new(&bytes);
+    let segment = cursor.read_segment_with(&options).unwrap();
+    let mut buffer = vec![];
+
+    assert_eq!(segment.kind(), SegmentKind::Unknown(0x99));
+    assert_eq!(segment.kind().to_string(), "0x99");
+    match &segment {
+        Segment::Unknown(us) => assert_eq!(us.data, [1, 2, 3]),
+        _ => panic!("read an unknown kind as something else"),
+    }
+    assert!(matches!(cursor.read_segment_with(&options).unwrap(), Segment::End(_)));
+
+    buffer.write_segment(&segment).unwrap();
+
+    assert_eq!(buffer, bytes[..16]);
+    assert!(matches!(
+        Cursor::new(&bytes).read_segment(),
+        Err(ReadError::UnrecognizedKind),
+    ));
+}
+
+#[test]
+fn test_resync_skips_to_next_magic() {
+
+    let end = |pts| {
+        let mut buffer = vec![];
+        buffer.write_segment(&Segment::End(EndSegment { pts, ..Default::default() })).unwrap();
+        buffer
+    };
+    // A lone 0x50 in the junk must not be taken for the start of a magic number.
+    let stream = [end(1), vec![0x12, 0x50, 0x34, 0x50], end(2), raw_segment(0x99, &[0; 4]), end(3)]
+        .concat();
+    let mut reader = ResyncReader::new(Cursor::new(stream));
+    let options = ReadOptions::default();
+    let mut read = || match reader.read_segment_resync(&options) {
+        Ok(Segment::End(es)) => Ok(es.pts),
+        Ok(_) => panic!("read a segment that was never written"),
+        Err(err) => Err(err),
+    };
+
+    assert_eq!(read().unwrap(), 1);
+    assert!(matches!(read().unwrap_err().bare(), ReadError::UnrecognizedMagicNumber));
+    assert_eq!(read().unwrap(), 2);
+    assert_eq!(
+        read().unwrap_err().to_string(),
+        "segment has unrecognized kind (segment at 0x1E, byte 0xA)",
+    );
+    assert_eq!(read().unwrap(), 3);
+    assert!(matches!(read(), Err(ReadError::IoError { .. })));
+    assert_eq!((reader.skipped(), reader.resyncs()), (4 + 17, 2));
+}
+
+// A PCS for a 1920x1080 epoch start with one composition object, followed by an END.
+static PCS_AND_END: [u8; 45] = [
+    0x50, 0x47, 0x00, 0x01, 0x5F, 0x90, 0x00, 0x00, 0x00, 0x00, 0x16, 0x00, 0x13,
+    0x07, 0x80, 0x04, 0x38, 0x10, 0x00, 0x05, 0x80, 0x00, 0x00, 0x01,
+    0x00, 0x02, 0x00, 0x40, 0x01, 0x00, 0x03, 0x84,
+    0x50, 0x47, 0x00, 0x01, 0x5F, 0x90, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00,
+];
+
+#[test]
+fn test_decode_pcs_from_static_bytes() {
+
+    let (segment, length) = decode_segment(&PCS_AND_END, &ReadOptions::default()).unwrap();
+
+    assert_eq!(length, 32);
+    assert_eq!(
+        segment,
+        Segment::PresentationComposition(
+            PresentationCompositionSegment {
+                pts: 90_000,
+                dts: 0,
+                width: 1920,
+                height: 1080,
+                frame_rate: FrameRate::Fps23_976,
+                composition_number: 5,
+                composition_state: CompositionState::EpochStart,
+                palette_update_id: None,
+                composition_objects: vec![
+                    CompositionObject {
+                        object_id: ObjectId(2),
+                        window_id: WindowId(0),
+                        x: 256,
+                        y: 900,
+                        crop: None,
+                        forced: true,
+                    },
+                ],
+                raw: Raw::default(),
+            }
+        ),
+    );
+    assert_eq!(
+        decode_segment(&PCS_AND_END[length..], &ReadOptions::default()).unwrap(),
+        (Segment::End(EndSegment { pts: 90_000, dts: 0, raw: Raw::default() }), 13),
+    );
+    assert_eq!(Cursor::new(&PCS_AND_END[..]).read_segment().unwrap(), segment);
+}
+
+#[test]
+fn test_decode_segment_ref() {
+
+    let mut bytes = vec![];
+    let ods = Segment::ObjectDefinition(
+        ObjectDefinitionSegment {
+            pts: 900,
+            id: ObjectId(3),
+            sequence: Sequence::Single,
+            header: Some(ObjectHeader { data_length: 5, width: 4, height: 1 }),
+            data: vec![0x00, 0x84, 0x01, 0x00, 0x00],
+            ..Default::default()
+        }
+    );
+    let options = ReadOptions { keep_unknown: true, ..Default::default() };
+
+    bytes.write_segment(&ods).unwrap();
+    bytes.extend(raw_segment(0x99, &[1, 2, 3]));
+
+    let (first, rest) = decode_segment_ref(&bytes, &options).unwrap();
+
+    match &first {
+        SegmentRef::ObjectDefinition(odr) => {
+            assert_eq!(odr.data, [0x00, 0x84, 0x01, 0x00, 0x00]);
+            assert!(bytes.as_ptr_range().contains(&odr.data.as_ptr()));
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(first.into_owned(), ods);
+    assert_eq!(rest.len(), 16);
+
+    let (second, rest) = decode_segment_ref(rest, &options).unwrap();
+
+    assert_eq!(second.kind(), SegmentKind::Unknown(0x99));
+    assert_eq!(
+        second.into_owned(),
+        decode_segment(&bytes[bytes.len() - 16..], &options).unwrap().0,
+    );
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn test_decode_incomplete_segment() {
+    for length in [0, 12, 31].iter() {
+        assert!(matches!(
+            decode_segment(&PCS_AND_END[..*length], &ReadOptions::default()),
+            Err(ReadError::IncompleteSegment),
+        ));
+    }
+}
+
+#[test]
+fn test_truncated_pcs_payload() {
+
+    // The header gives a size of three bytes, which ends partway through the height.
+    let raw = raw_segment(0x16, &[0x07, 0x80, 0x04]);
+
+    assert!(matches!(
+        decode_segment(&raw, &ReadOptions::default()),
+        Err(ReadError::SizeMismatch { declared: 3, consumed: 4 }),
+    ));
+    assert!(matches!(
+        Cursor::new(&raw).read_segment(),
+        Err(ReadError::SizeMismatch { declared: 3, consumed: 4 }),
+    ));
+}
+
+fn fragments(sequences: &[Sequence], data_length: usize) -> Vec<ObjectDefinitionSegment> {
+    sequences.iter()
+        .enumerate()
+        .map(|(index, &sequence)| {
+            ObjectDefinitionSegment {
+                id: ObjectId(4),
+                version: 2,
+                sequence,
+                header: if sequence.is_first() {
+                    Some(ObjectHeader { data_length, width: 3840, height: 400 })
+                } else {
+                    None
+                },
+                data: vec![index as u8; 4],
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_join_fragments() {
+
+    let (header, data) = ObjectDefinitionSegment::join(
+        &fragments(&[Sequence::First, Sequence::Middle, Sequence::Middle, Sequence::Last], 16),
+    ).unwrap();
+
+    assert_eq!(header, ObjectHeader { data_length: 16, width: 3840, height: 400 });
+    assert_eq!(data, [[0; 4], [1; 4], [2; 4], [3; 4]].concat());
+    assert_eq!(
+        ObjectDefinitionSegment::join(&fragments(&[Sequence::Single], 4)),
+        Ok((ObjectHeader { data_length: 4, width: 3840, height: 400 }, vec![0; 4])),
+    );
+}
+
+#[test]
+fn test_join_fragments_out_of_sequence() {
+
+    let join = |sequences: &[Sequence], data_length| {
+        ObjectDefinitionSegment::join(&fragments(sequences, data_length))
+    };
+
+    assert_eq!(join(&[], 0), Err(FragmentError::NoFragments));
+    assert_eq!(
+        join(&[Sequence::Middle, Sequence::Last], 8),
+        Err(FragmentError::MissingFirstFragment),
+    );
+    assert_eq!(
+        join(&[Sequence::First, Sequence::Middle], 8),
+        Err(FragmentError::MissingLastFragment),
+    );
+    assert_eq!(
+        join(&[Sequence::First, Sequence::First, Sequence::Last], 12),
+        Err(FragmentError::UnexpectedFirstFragment { index: 1 }),
+    );
+    assert_eq!(
+        join(&[Sequence::First, Sequence::Last, Sequence::Last], 12),
+        Err(FragmentError::UnexpectedLastFragment { index: 1 }),
+    );
+    assert_eq!(
+        join(&[Sequence::First, Sequence::Last], 9),
+        Err(FragmentError::DataLengthMismatch { declared: 9, actual: 8 }),
+    );
+}
+
+#[test]
+fn test_join_fragments_of_different_objects() {
+
+    let mut fragments = fragments(&[Sequence::First, Sequence::Last], 8);
+
+    fragments[1].version = 3;
+
+    assert_eq!(
+        ObjectDefinitionSegment::join(&fragments),
+        Err(FragmentError::MismatchedFragment { index: 1 }),
+    );
+}
+
+#[test]
+fn test_debug_truncates_payloads() {
+
+    let segment = Segment::ObjectDefinition(ObjectDefinitionSegment {
+        id: ObjectId(1),
+        sequence: Sequence::Last,
+        data: (0..=255).collect(),
+        ..Default::default()
+    });
+    let debug = format!("{:?}", segment);
+
+    assert!(
+        debug.contains("data: 256 bytes [00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F ..]"),
+        "{}",
+        debug,
+    );
+    assert!(!debug.contains("FF"));
+
+    let segment = UnknownSegment { kind: 0x99, data: vec![7, 8], ..Default::default() };
+
+    assert!(format!("{:?}", segment).contains("data: 2 bytes [07 08]"));
+}
+
+#[test]
+fn test_segments_deduplicate() {
+
+    let pds = |y: u8| Segment::PaletteDefinition(PaletteDefinitionSegment {
+        entries: vec![PaletteEntry { id: 1, y, cr: 128, cb: 128, alpha: 255 }],
+        ..Default::default()
+    });
+    let mut buffer = vec![];
+
+    buffer.write_segment(&pds(16)).unwrap();
+
+    // What was read keeps its raw bytes, which take no part in comparisons.
+    let read = Cursor::new(buffer).read_segment_with(&ReadOptions {
+        keep_raw: true,
+        ..Default::default()
+    }).unwrap();
+    let segments = vec![read, pds(16), pds(235)].into_iter().collect::<HashSet<Segment>>();
+
+    assert_eq!(segments.len(), 2);
+    assert!(segments.contains(&pds(235)));
+}
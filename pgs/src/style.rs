@@ -0,0 +1,91 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::ts_to_timestamp;
+use std::{
+    env,
+    fmt::Display,
+    io::{stdout, IsTerminal},
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    Dim,
+}
+
+impl Color {
+
+    fn code(self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Blue => "34",
+            Color::Magenta => "35",
+            Color::Cyan => "36",
+            Color::Dim => "2",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Style {
+    pub enabled: bool,
+    pub truecolor: bool,
+}
+
+impl Style {
+
+    // Colors are only used when writing to a terminal and nobody has asked for them to be off.
+    pub fn detect(no_color: bool) -> Self {
+
+        let enabled = !no_color
+            && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            && stdout().is_terminal();
+
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+
+        Self {
+            enabled,
+            truecolor: enabled && (colorterm == "truecolor" || colorterm == "24bit"),
+        }
+    }
+
+    pub fn paint(&self, text: impl Display, color: Color) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    // Pads before painting so that escape sequences never throw off column alignment.
+    pub fn pad(&self, text: impl Display, width: usize, color: Color) -> String {
+        self.paint(format!("{:<width$}", text.to_string(), width = width), color)
+    }
+
+    pub fn timestamp(&self, ts: u32) -> String {
+        self.paint(ts_to_timestamp(ts), Color::Cyan)
+    }
+
+    // A block of the given color, or nothing when the terminal cannot show arbitrary colors.
+    pub fn swatch(&self, rgb: [u8; 3]) -> String {
+        if self.truecolor {
+            format!("\x1b[48;2;{};{};{}m  \x1b[0m", rgb[0], rgb[1], rgb[2])
+        } else {
+            String::new()
+        }
+    }
+}
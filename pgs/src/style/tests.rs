@@ -0,0 +1,33 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+#[test]
+fn test_plain_output() {
+
+    let style = Style { enabled: false, truecolor: false };
+
+    assert_eq!(style.paint("error", Color::Red), "error");
+    assert_eq!(style.pad("error", 8, Color::Red), "error   ");
+    assert_eq!(style.timestamp(90_000), "00:00:01.000");
+    assert_eq!(style.swatch([255, 0, 0]), "");
+}
+
+#[test]
+fn test_colored_output_keeps_columns() {
+
+    let style = Style { enabled: true, truecolor: true };
+
+    assert_eq!(style.paint("error", Color::Red), "\x1b[31merror\x1b[0m");
+    assert_eq!(style.pad("error", 8, Color::Red), "\x1b[31merror   \x1b[0m");
+    assert_eq!(style.swatch([255, 0, 16]), "\x1b[48;2;255;0;16m  \x1b[0m");
+}
+
+#[test]
+fn test_no_color_flag() {
+    assert!(!Style::detect(true).enabled);
+}
@@ -0,0 +1,465 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    cache::{object_rgba, Generations, ObjectCache},
+    displayset::{CompositionObject, DisplaySet, Object, Palette, Window},
+    id::{ObjectId, PaletteId, WindowId},
+    segment::{CompositionState, Limit, Limits},
+};
+use std::{collections::BTreeMap, rc::Rc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OccupancyMode {
+    Fast,
+    Precise,
+}
+
+// Mirrors the decoder's buffers so that later display sets can refer to earlier definitions.
+#[derive(Clone, Debug, Default)]
+pub struct EpochState {
+    pub windows: BTreeMap<WindowId, Window>,
+    pub palettes: BTreeMap<PaletteId, Palette>,
+    pub objects: BTreeMap<ObjectId, Object>,
+    pub display_sets: usize,
+}
+
+impl EpochState {
+
+    pub fn apply(&mut self, display_set: &DisplaySet) {
+
+        if display_set.composition.state == CompositionState::EpochStart {
+            self.windows.clear();
+            self.palettes.clear();
+            self.objects.clear();
+            self.display_sets = 0;
+        }
+
+        self.display_sets += 1;
+
+        for (&id, window) in display_set.windows.iter() {
+            self.windows.insert(id, window.clone());
+        }
+        for (vid, palette) in display_set.palettes.iter() {
+            self.palettes.insert(vid.id, palette.clone());
+        }
+        for (vid, object) in display_set.objects.iter() {
+            self.objects.insert(vid.id, object.clone());
+        }
+    }
+
+    // Applies the display set and then fails if the epoch has outgrown any of the limits. The
+    // display set stays applied either way.
+    pub fn apply_with(&mut self, display_set: &DisplaySet, limits: &Limits) -> Result<(), Limit> {
+
+        self.apply(display_set);
+
+        if self.windows.len() > limits.max_windows {
+            return Err(Limit::Windows)
+        }
+        if self.palettes.len() > limits.max_palettes {
+            return Err(Limit::Palettes)
+        }
+        if self.objects.len() > limits.max_objects {
+            return Err(Limit::Objects)
+        }
+        if self.display_sets > limits.max_display_sets_per_epoch {
+            return Err(Limit::DisplaySetsPerEpoch)
+        }
+
+        Ok(())
+    }
+
+    pub fn palette(&self, display_set: &DisplaySet) -> Option<&Palette> {
+        match display_set.palette_update_id {
+            Some(id) => self.palettes.get(&id),
+            None => self.palettes.values().next(),
+        }
+    }
+}
+
+// Accumulates how long each pixel of the canvas is covered by visible subtitle pixels.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Heatmap {
+    pub width: u16,
+    pub height: u16,
+    pub seconds: Vec<f32>,
+}
+
+impl Heatmap {
+
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            seconds: vec![0.0; width as usize * height as usize],
+        }
+    }
+
+    // Adds a display set that stays on screen for the given number of 90 kHz ticks. The state
+    // must be the epoch state after the display set has been applied.
+    pub fn add(&mut self, state: &EpochState, display_set: &DisplaySet, duration: u32) {
+        self.add_cached(
+            state,
+            display_set,
+            duration,
+            &Generations::default(),
+            &mut ObjectCache::new(0),
+        )
+    }
+
+    // Like add, but takes each object's pixels from the cache. The generations must have been
+    // applied to the same display sets as the state.
+    pub fn add_cached(
+        &mut self,
+        state: &EpochState,
+        display_set: &DisplaySet,
+        duration: u32,
+        generations: &Generations,
+        cache: &mut ObjectCache,
+    ) {
+
+        let seconds = duration as f32 / 90_000.0;
+
+        for_each_shown_pixel(state, display_set, generations, cache, |x, y| {
+            if x < self.width as u32 && y < self.height as u32 {
+                self.seconds[y as usize * self.width as usize + x as usize] += seconds;
+            }
+        });
+    }
+
+    pub fn max(&self) -> f32 {
+        self.seconds.iter().copied().fold(0.0, f32::max)
+    }
+
+    // Renders the heatmap as false-color RGB with a legend along the bottom-right corner that
+    // runs from zero at the bottom to the maximum at the top.
+    pub fn render(&self) -> Vec<u8> {
+
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let max = self.max();
+        let mut rgb = Vec::with_capacity(width * height * 3);
+
+        for &value in self.seconds.iter() {
+            rgb.extend_from_slice(&heat_color(if max > 0.0 { value / max } else { 0.0 }));
+        }
+
+        let legend_width = (width / 64).max(4).min(width);
+        let legend_height = (height / 4).max(2).min(height);
+        let legend_x = width - legend_width - (width - legend_width).min(legend_width);
+        let legend_y = height - legend_height - (height - legend_height).min(legend_width);
+
+        for y in 0..legend_height {
+
+            let color = heat_color(1.0 - y as f32 / (legend_height - 1) as f32);
+
+            for x in 0..legend_width {
+                let offset = ((legend_y + y) * width + legend_x + x) * 3;
+                rgb[offset..offset + 3].copy_from_slice(&color);
+            }
+        }
+
+        rgb
+    }
+}
+
+// Black through blue, red, and yellow to white.
+fn heat_color(fraction: f32) -> [u8; 3] {
+
+    const STOPS: [[f32; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 255.0],
+        [255.0, 0.0, 0.0],
+        [255.0, 255.0, 0.0],
+        [255.0, 255.0, 255.0],
+    ];
+
+    let position = fraction.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let index = (position.floor() as usize).min(STOPS.len() - 2);
+    let local = position - index as f32;
+    let mut color = [0; 3];
+
+    for (channel, value) in color.iter_mut().enumerate() {
+        let from = STOPS[index][channel];
+        let to = STOPS[index + 1][channel];
+        *value = (from + (to - from) * local).round() as u8;
+    }
+
+    color
+}
+
+// Fractions of the canvas covered by visible subtitle pixels and by the windows being shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Coverage {
+    pub pixels: f64,
+    pub windows: f64,
+}
+
+// The state must be the epoch state after the display set has been applied.
+pub fn coverage(state: &EpochState, display_set: &DisplaySet) -> Coverage {
+    coverage_cached(state, display_set, &Generations::default(), &mut ObjectCache::new(0))
+}
+
+// Like coverage, but takes each object's pixels from the cache. The generations must have been
+// applied to the same display sets as the state.
+pub fn coverage_cached(
+    state: &EpochState,
+    display_set: &DisplaySet,
+    generations: &Generations,
+    cache: &mut ObjectCache,
+) -> Coverage {
+
+    let resolution = (display_set.width, display_set.height);
+    let area = display_set.width as f64 * display_set.height as f64;
+
+    if area == 0.0 {
+        return Coverage::default()
+    }
+
+    let area_of = |rects: Vec<Rect>| {
+        rects.iter().map(|rect| rect.width as f64 * rect.height as f64).sum::<f64>()
+    };
+    let mut pixels = 0u64;
+
+    for_each_shown_pixel(state, display_set, generations, cache, |x, y| {
+        if x < display_set.width as u32 && y < display_set.height as u32 {
+            pixels += 1;
+        }
+    });
+
+    Coverage {
+        pixels: pixels as f64 / area,
+        windows: area_of(visible_rects(state, display_set, resolution, OccupancyMode::Fast))
+            / area,
+    }
+}
+
+pub fn occupancy(
+    display_sets: &[DisplaySet],
+    resolution: (u16, u16),
+    step_ms: Option<u32>,
+    mode: OccupancyMode,
+) -> Vec<(u32, Vec<Rect>)> {
+
+    let mut state = EpochState::default();
+    let mut changes = Vec::<(u32, Vec<Rect>)>::new();
+
+    for display_set in display_sets.iter() {
+        state.apply(display_set);
+        changes.push((display_set.pts, visible_rects(&state, display_set, resolution, mode)));
+    }
+
+    match step_ms {
+        Some(step) => sample_occupancy(changes, step),
+        None => changes,
+    }
+}
+
+// Takes what was occupied at each step of the given milliseconds from the first change point to
+// the last, out of change points as occupancy gives them. A step of zero leaves them as they are.
+pub fn sample_occupancy(changes: Vec<(u32, Vec<Rect>)>, step_ms: u32) -> Vec<(u32, Vec<Rect>)> {
+
+    if step_ms == 0 || changes.is_empty() {
+        return changes
+    }
+
+    let step_ticks = step_ms as u64 * 90;
+    let last_pts = changes[changes.len() - 1].0 as u64;
+    let mut samples = Vec::new();
+    let mut index = 0;
+    let mut ts = changes[0].0 as u64;
+
+    while ts <= last_pts {
+        while index + 1 < changes.len() && changes[index + 1].0 as u64 <= ts {
+            index += 1;
+        }
+        samples.push((ts as u32, changes[index].1.clone()));
+        ts += step_ticks;
+    }
+
+    samples
+}
+
+// What the display set leaves occupied, clipped to the resolution, for callers that follow a
+// stream one display set at a time. The state must be the epoch state after the display set has
+// been applied.
+pub fn visible_rects(
+    state: &EpochState,
+    display_set: &DisplaySet,
+    resolution: (u16, u16),
+    mode: OccupancyMode,
+) -> Vec<Rect> {
+
+    let mut rects = Vec::new();
+
+    for (cid, composition_object) in display_set.composition.objects.iter() {
+
+        let rect = match mode {
+            OccupancyMode::Fast => {
+                state.windows.get(&cid.window_id).map(|window| Rect {
+                    x: window.x,
+                    y: window.y,
+                    width: window.width,
+                    height: window.height,
+                })
+            }
+            OccupancyMode::Precise => {
+                match (state.objects.get(&cid.object_id), state.palette(display_set)) {
+                    (Some(object), Some(palette)) => {
+                        opaque_bounds(object, palette, composition_object)
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        if let Some(clipped) = rect.and_then(|rect| clip(rect, resolution)) {
+            rects.push(clipped);
+        }
+    }
+
+    rects
+}
+
+fn opaque_bounds(
+    object: &Object,
+    palette: &Palette,
+    composition_object: &CompositionObject,
+) -> Option<Rect> {
+
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+
+    for_each_opaque_pixel(object, palette, composition_object, |x, y| {
+        bounds = Some(match bounds {
+            Some((x1, y1, x2, y2)) => (x1.min(x), y1.min(y), x2.max(x), y2.max(y)),
+            None => (x, y, x, y),
+        });
+    });
+
+    bounds.map(|(x1, y1, x2, y2)| Rect {
+        x: x1 as u16,
+        y: y1 as u16,
+        width: (x2 - x1 + 1) as u16,
+        height: (y2 - y1 + 1) as u16,
+    })
+}
+
+// Calls back with the canvas coordinates of every pixel of a composition object that is visible.
+fn for_each_opaque_pixel(
+    object: &Object,
+    palette: &Palette,
+    composition_object: &CompositionObject,
+    mut callback: impl FnMut(u32, u32),
+) {
+
+    let (crop_x, crop_y, crop_width, crop_height) = match &composition_object.crop {
+        Some(crop) => (crop.x as usize, crop.y as usize, crop.width as usize, crop.height as usize),
+        None => (0, 0, object.width as usize, object.height as usize),
+    };
+
+    for (y, line) in object.lines.iter().enumerate().skip(crop_y).take(crop_height) {
+        for (x, index) in line.iter().enumerate().skip(crop_x).take(crop_width) {
+
+            // Indices without a palette entry are treated as transparent.
+            let opaque = palette.entries.get(index).is_some_and(|entry| entry.alpha > 0);
+
+            if opaque {
+                callback(
+                    (composition_object.x as usize + x - crop_x) as u32,
+                    (composition_object.y as usize + y - crop_y) as u32,
+                );
+            }
+        }
+    }
+}
+
+// Calls back with the canvas coordinates of every visible pixel of the display set's composition
+// objects, going by their alpha once converted to RGBA.
+fn for_each_shown_pixel(
+    state: &EpochState,
+    display_set: &DisplaySet,
+    generations: &Generations,
+    cache: &mut ObjectCache,
+    mut callback: impl FnMut(u32, u32),
+) {
+
+    let palette_id = match display_set.palette_update_id
+        .or_else(|| state.palettes.keys().next().copied()) {
+        Some(id) => id,
+        None => return,
+    };
+    let palette = match state.palettes.get(&palette_id) {
+        Some(palette) => palette,
+        None => return,
+    };
+    let matrix = cache.matrix(display_set.width);
+    let range = cache.range();
+
+    for (cid, composition_object) in display_set.composition.objects.iter() {
+        if let Some(object) = state.objects.get(&cid.object_id) {
+
+            let convert = || object_rgba(object, palette, matrix, range);
+            let rgba = match generations.key(cid.object_id, palette_id, matrix) {
+                Some(key) => cache.get_or_insert_with(key, convert),
+                None => Rc::new(convert()),
+            };
+            let width = object.width as usize;
+            let (crop_x, crop_y, crop_width, crop_height) = match &composition_object.crop {
+                Some(crop) => {
+                    (crop.x as usize, crop.y as usize, crop.width as usize, crop.height as usize)
+                }
+                None => (0, 0, width, object.height as usize),
+            };
+            let right = (crop_x + crop_width).min(width);
+            let bottom = (crop_y + crop_height).min(object.height as usize);
+
+            for y in crop_y..bottom {
+                for x in crop_x..right {
+                    if rgba[(y * width + x) * 4 + 3] > 0 {
+                        callback(
+                            (composition_object.x as usize + x - crop_x) as u32,
+                            (composition_object.y as usize + y - crop_y) as u32,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn clip(rect: Rect, resolution: (u16, u16)) -> Option<Rect> {
+
+    let (width, height) = resolution;
+
+    if rect.x >= width || rect.y >= height || rect.width == 0 || rect.height == 0 {
+        return None
+    }
+
+    Some(
+        Rect {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width.min(width - rect.x),
+            height: rect.height.min(height - rect.y),
+        }
+    )
+}
@@ -0,0 +1,282 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::{
+    *,
+    super::{
+        displayset::{Cid, Composition, PaletteEntry},
+        id::{ObjectId, PaletteId, VersionedId, WindowId},
+        segment::Sequence,
+    },
+};
+
+fn epoch_start(pts: u32) -> DisplaySet {
+
+    let mut display_set = DisplaySet {
+        pts,
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+    let mut palette = Palette::default();
+
+    palette.entries.insert(0, PaletteEntry { y: 16, cr: 128, cb: 128, alpha: 0 });
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+
+    display_set.windows.insert(WindowId(0), Window { x: 100, y: 900, width: 400, height: 100 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object {
+            width: 4,
+            height: 3,
+            sequence: Sequence::Single,
+            lines: vec![
+                vec![0, 0, 0, 0],
+                vec![0, 1, 1, 0],
+                vec![0, 0, 0, 0],
+            ],
+        },
+    );
+    display_set.composition = Composition {
+        number: 0,
+        state: CompositionState::EpochStart,
+        objects: vec![(
+            Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+            CompositionObject { x: 100, y: 900, crop: None, forced: false },
+        )].into_iter().collect(),
+    };
+
+    display_set
+}
+
+fn normal(pts: u32, shown: bool) -> DisplaySet {
+
+    let mut display_set = DisplaySet {
+        pts,
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+
+    display_set.composition.state = CompositionState::Normal;
+
+    if shown {
+        display_set.composition.objects.insert(
+            Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+            CompositionObject { x: 100, y: 900, crop: None, forced: false },
+        );
+    }
+
+    display_set
+}
+
+#[test]
+fn test_fast_change_points() {
+
+    let display_sets = vec![epoch_start(900), normal(1800, false), normal(2700, true)];
+    let window = Rect { x: 100, y: 900, width: 400, height: 100 };
+
+    assert_eq!(
+        occupancy(&display_sets, (1920, 1080), None, OccupancyMode::Fast),
+        vec![(900, vec![window]), (1800, vec![]), (2700, vec![window])],
+    );
+}
+
+#[test]
+fn test_precise_uses_opaque_pixels() {
+
+    let display_sets = vec![epoch_start(900), normal(1800, true)];
+    let bounds = Rect { x: 101, y: 901, width: 2, height: 1 };
+
+    assert_eq!(
+        occupancy(&display_sets, (1920, 1080), None, OccupancyMode::Precise),
+        vec![(900, vec![bounds]), (1800, vec![bounds])],
+    );
+}
+
+#[test]
+fn test_fixed_step() {
+
+    let display_sets = vec![epoch_start(900), normal(1800, false)];
+    let window = Rect { x: 100, y: 900, width: 400, height: 100 };
+
+    assert_eq!(
+        occupancy(&display_sets, (1920, 1080), Some(5), OccupancyMode::Fast),
+        vec![(900, vec![window]), (1350, vec![window]), (1800, vec![])],
+    );
+
+    let changes = occupancy(&display_sets, (1920, 1080), None, OccupancyMode::Fast);
+
+    assert_eq!(sample_occupancy(changes.clone(), 0), changes);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_occupancy_json_matches_golden() {
+
+    use super::super::{displayset::{ReadDisplaySetExt, ReadResult}, segment::ReadOptions};
+    use std::io::Cursor;
+
+    let input = include_bytes!("../../../test-data/fade.sup");
+    let display_sets = Cursor::new(&input[..]).display_sets(&ReadOptions::default())
+        .collect::<ReadResult<Vec<DisplaySet>>>()
+        .unwrap();
+    let changes = occupancy(&display_sets, (1920, 1080), None, OccupancyMode::Fast);
+    let json = format!("{}\n", serde_json::to_string(&changes).unwrap());
+
+    assert_eq!(json, include_str!("../../../test-data/fade-occupancy.json"));
+    assert_eq!(serde_json::from_str::<Vec<(u32, Vec<Rect>)>>(&json).unwrap(), changes);
+}
+
+#[test]
+fn test_clipped_to_resolution() {
+
+    let display_sets = vec![epoch_start(900)];
+
+    assert_eq!(
+        occupancy(&display_sets, (300, 950), None, OccupancyMode::Fast),
+        vec![(900, vec![Rect { x: 100, y: 900, width: 200, height: 50 }])],
+    );
+}
+
+#[test]
+fn test_epoch_start_resets_state() {
+
+    let mut display_sets = vec![epoch_start(900), normal(1800, true)];
+
+    display_sets[1].composition.state = CompositionState::EpochStart;
+
+    assert_eq!(
+        occupancy(&display_sets, (1920, 1080), None, OccupancyMode::Fast),
+        vec![(900, vec![Rect { x: 100, y: 900, width: 400, height: 100 }]), (1800, vec![])],
+    );
+}
+
+#[test]
+fn test_heatmap() {
+
+    let display_set = epoch_start(900);
+    let mut state = EpochState::default();
+    let mut heatmap = Heatmap::new(1920, 1080);
+
+    state.apply(&display_set);
+    heatmap.add(&state, &display_set, 45_000);
+    heatmap.add(&state, &display_set, 90_000);
+
+    assert_eq!(heatmap.seconds[901 * 1920 + 101], 1.5);
+    assert_eq!(heatmap.seconds[901 * 1920 + 102], 1.5);
+    assert_eq!(heatmap.seconds[901 * 1920 + 100], 0.0);
+    assert_eq!(heatmap.seconds.iter().filter(|&&value| value > 0.0).count(), 2);
+    assert_eq!(heatmap.max(), 1.5);
+
+    let rgb = heatmap.render();
+
+    assert_eq!(rgb.len(), 1920 * 1080 * 3);
+    assert_eq!(rgb[(901 * 1920 + 101) * 3..(901 * 1920 + 102) * 3], [255, 255, 255]);
+    assert_eq!(rgb[..3], [0, 0, 0]);
+}
+
+#[test]
+fn test_coverage() {
+
+    let display_set = epoch_start(900);
+    let mut state = EpochState::default();
+
+    state.apply(&display_set);
+
+    let coverage = coverage(&state, &display_set);
+
+    assert_eq!(coverage.pixels, 2.0 / (1920.0 * 1080.0));
+    assert_eq!(coverage.windows, 400.0 * 100.0 / (1920.0 * 1080.0));
+
+    state.apply(&normal(1800, false));
+
+    assert_eq!(super::coverage(&state, &normal(1800, false)), Coverage::default());
+}
+
+// Shows the object, then shows it again with a palette that makes its background opaque.
+fn cached_stream() -> Vec<DisplaySet> {
+
+    let mut palette_update = normal(2700, true);
+    let mut palette = Palette::default();
+
+    palette.entries.insert(0, PaletteEntry { y: 16, cr: 128, cb: 128, alpha: 255 });
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+    palette_update.palette_update_id = Some(PaletteId(0));
+    palette_update.palettes.insert(VersionedId { id: PaletteId(0), version: 1 }, palette);
+
+    vec![epoch_start(900), normal(1800, true), palette_update, normal(3600, true)]
+}
+
+#[test]
+fn test_coverage_does_not_depend_on_cache() {
+
+    let stream = cached_stream();
+    let coverages = |cache: &mut ObjectCache| {
+
+        let mut state = EpochState::default();
+        let mut generations = Generations::default();
+
+        stream.iter()
+            .map(|display_set| {
+                state.apply(display_set);
+                generations.apply(display_set);
+                coverage_cached(&state, display_set, &generations, cache).pixels
+            })
+            .collect::<Vec<f64>>()
+    };
+    let mut cache = ObjectCache::new(1_048_576);
+    let area = 1920.0 * 1080.0;
+
+    assert_eq!(coverages(&mut ObjectCache::new(0)), coverages(&mut cache));
+    assert_eq!(coverages(&mut cache), coverages(&mut ObjectCache::new(0)));
+    assert_eq!(coverages(&mut cache), vec![2.0 / area, 2.0 / area, 12.0 / area, 12.0 / area]);
+    assert_eq!((cache.hits(), cache.misses()), (10, 2));
+}
+
+#[test]
+fn test_heatmap_does_not_depend_on_cache() {
+
+    let stream = cached_stream();
+    let heatmap = |cache: &mut ObjectCache| {
+
+        let mut state = EpochState::default();
+        let mut generations = Generations::default();
+        let mut heatmap = Heatmap::new(1920, 1080);
+
+        for pair in stream.windows(2) {
+            state.apply(&pair[0]);
+            generations.apply(&pair[0]);
+            heatmap.add_cached(&state, &pair[0], pair[1].pts - pair[0].pts, &generations, cache);
+        }
+
+        heatmap
+    };
+    let mut cache = ObjectCache::new(1_048_576);
+
+    assert!(heatmap(&mut ObjectCache::new(0)) == heatmap(&mut cache));
+    assert!(heatmap(&mut cache) == heatmap(&mut ObjectCache::new(0)));
+    assert_eq!(heatmap(&mut cache).seconds[900 * 1920 + 100], 0.01);
+    assert_eq!((cache.hits(), cache.misses()), (7, 2));
+}
+
+#[test]
+fn test_epoch_limits() {
+
+    let limits = Limits { max_display_sets_per_epoch: 2, max_objects: 1, ..Default::default() };
+    let mut state = EpochState::default();
+    let mut second_object = normal(2700, false);
+
+    second_object.objects.insert(VersionedId { id: ObjectId(1), version: 0 }, Object::default());
+
+    assert_eq!(state.apply_with(&epoch_start(900), &limits), Ok(()));
+    assert_eq!(state.apply_with(&normal(1800, true), &limits), Ok(()));
+    assert_eq!(state.apply_with(&normal(2700, false), &limits), Err(Limit::DisplaySetsPerEpoch));
+    assert_eq!(state.apply_with(&epoch_start(3600), &limits), Ok(()));
+    assert_eq!(state.display_sets, 1);
+    assert_eq!(state.apply_with(&second_object, &limits), Err(Limit::Objects));
+}
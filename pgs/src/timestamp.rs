@@ -0,0 +1,171 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    cmp::Ordering,
+    convert::TryFrom,
+    fmt::{Display, Formatter, Result as FmtResult},
+    str::FromStr,
+};
+
+const TICKS_PER_MILLISECOND: u64 = 90;
+
+// A PTS or DTS as stored in a segment holds the low 32 bits of a 90 kHz MPEG clock, and so wraps
+// around to zero every 13 hours and a quarter or so. Streams cut from long broadcast captures can
+// cross the wraparound.
+pub const PTS_WRAP: u64 = 1 << 32;
+
+// A point in time split into its parts. Hours are not wrapped at 24. Converting from 90 kHz ticks
+// truncates to the millisecond, so going from a timestamp to ticks and back always yields the same
+// timestamp, while going from ticks to a timestamp and back may lose up to 89 ticks.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Timestamp {
+    pub hours: u32,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub milliseconds: u16,
+}
+
+impl Timestamp {
+
+    // Ticks of a 90 kHz clock that has been counted on past any wraparound; see PtsUnwrapper.
+    pub fn from_ticks(ticks: u64) -> Self {
+        Timestamp::from_milliseconds(ticks / TICKS_PER_MILLISECOND)
+    }
+
+    pub fn from_milliseconds(milliseconds: u64) -> Self {
+        Timestamp {
+            hours: (milliseconds / 3_600_000) as u32,
+            minutes: (milliseconds / 60_000 % 60) as u8,
+            seconds: (milliseconds / 1_000 % 60) as u8,
+            milliseconds: (milliseconds % 1_000) as u16,
+        }
+    }
+
+    pub fn milliseconds(&self) -> u64 {
+        self.hours as u64 * 3_600_000
+            + self.minutes as u64 * 60_000
+            + self.seconds as u64 * 1_000
+            + self.milliseconds as u64
+    }
+
+    // The value in 90 kHz ticks, unless it is too late to fit in a PTS.
+    pub fn ts(&self) -> Option<u32> {
+        u32::try_from(self.milliseconds() * TICKS_PER_MILLISECOND).ok()
+    }
+}
+
+impl From<u32> for Timestamp {
+
+    fn from(ts: u32) -> Self {
+        Timestamp::from_ticks(ts as u64)
+    }
+}
+
+impl Display for Timestamp {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{:02}:{:02}:{:02}.{:03}",
+            self.hours, self.minutes, self.seconds, self.milliseconds,
+        )
+    }
+}
+
+impl FromStr for Timestamp {
+
+    type Err = ();
+
+    // Accepts HH:MM:SS.mmm as well as the SRT form, HH:MM:SS,mmm. Hours may have any number of
+    // digits, while the other fields must have exactly as many as shown.
+    fn from_str(value: &str) -> Result<Self, ()> {
+
+        let (hms, milliseconds) = value.split_once(['.', ',']).ok_or(())?;
+        let mut fields = hms.split(':');
+        let hours = fields.next().ok_or(())?;
+        let minutes = fields.next().ok_or(())?;
+        let seconds = fields.next().ok_or(())?;
+        let digits = |field: &str, count: Option<usize>| {
+            !field.is_empty()
+                && count.is_none_or(|count| field.len() == count)
+                && field.bytes().all(|byte| byte.is_ascii_digit())
+        };
+
+        if fields.next().is_some()
+            || !digits(hours, None)
+            || !digits(minutes, Some(2))
+            || !digits(seconds, Some(2))
+            || !digits(milliseconds, Some(3))
+        {
+            return Err(())
+        }
+
+        let timestamp = Timestamp {
+            hours: hours.parse().map_err(|_| ())?,
+            minutes: minutes.parse().map_err(|_| ())?,
+            seconds: seconds.parse().map_err(|_| ())?,
+            milliseconds: milliseconds.parse().map_err(|_| ())?,
+        };
+
+        if timestamp.minutes > 59 || timestamp.seconds > 59 {
+            return Err(())
+        }
+
+        Ok(timestamp)
+    }
+}
+
+pub fn ts_to_timestamp(ts: u32) -> String {
+    Timestamp::from(ts).to_string()
+}
+
+pub fn ticks_to_timestamp(ticks: u64) -> String {
+    Timestamp::from_ticks(ticks).to_string()
+}
+
+pub fn timestamp_to_ts(value: &str) -> Option<u32> {
+    value.parse::<Timestamp>().ok()?.ts()
+}
+
+// Moves a PTS or DTS by some number of ticks, either way, wrapping around as the clock would.
+pub fn pts_add(ts: u32, ticks: i64) -> u32 {
+    // Only the low 32 bits of the ticks can make any difference.
+    ts.wrapping_add(ticks as u32)
+}
+
+// Orders two PTS or DTS values the shorter way around the clock, so that one just past the
+// wraparound comes after one just before it. Of two values exactly half the clock apart, the
+// first is taken to be the earlier.
+pub fn pts_cmp_wrapping(ts_1: u32, ts_2: u32) -> Ordering {
+    (ts_1.wrapping_sub(ts_2) as i32).cmp(&0)
+}
+
+// Counts PTS values on past each wraparound, so that the times of a stream that crosses one go on
+// rising rather than starting over. Each value is taken to be whichever one is closest to the
+// value before it, which lets a stream step back a little without being taken to have wrapped.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PtsUnwrapper {
+    last: Option<u64>,
+}
+
+impl PtsUnwrapper {
+
+    pub fn unwrap_pts(&mut self, ts: u32) -> u64 {
+
+        let unwrapped = match self.last {
+            Some(last) => last.saturating_add_signed(ts.wrapping_sub(last as u32) as i32 as i64),
+            None => ts as u64,
+        };
+
+        self.last = Some(unwrapped);
+
+        unwrapped
+    }
+}
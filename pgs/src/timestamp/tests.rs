@@ -0,0 +1,130 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+#[test]
+fn test_from_ts() {
+    assert_eq!(Timestamp::from(0), Timestamp::default());
+    assert_eq!(
+        Timestamp::from(90 * 3_723_004 + 89),
+        Timestamp { hours: 1, minutes: 2, seconds: 3, milliseconds: 4 },
+    );
+    assert_eq!(ts_to_timestamp(90_000), "00:00:01.000");
+    assert_eq!(ts_to_timestamp(u32::MAX), "13:15:21.858");
+}
+
+#[test]
+fn test_display_does_not_wrap_hours() {
+    assert_eq!(
+        Timestamp { hours: 123, minutes: 4, seconds: 5, milliseconds: 6 }.to_string(),
+        "123:04:05.006",
+    );
+    assert_eq!(Timestamp::from_milliseconds(25 * 3_600_000).to_string(), "25:00:00.000");
+}
+
+#[test]
+fn test_parse() {
+
+    let timestamp = Timestamp { hours: 1, minutes: 2, seconds: 3, milliseconds: 45 };
+
+    assert_eq!("01:02:03.045".parse(), Ok(timestamp));
+    assert_eq!("01:02:03,045".parse(), Ok(timestamp));
+    assert_eq!("1:02:03.045".parse(), Ok(timestamp));
+    assert_eq!(
+        "48:00:00.000".parse(),
+        Ok(Timestamp { hours: 48, minutes: 0, seconds: 0, milliseconds: 0 }),
+    );
+
+    for value in [
+        "", "01:02:03", "01:02.045", "01:02:03:04.045", "01:2:03.045", "01:02:03.45",
+        "01:02:03.0450", "01:60:00.000", "01:00:60.000", "-1:02:03.045", "01:+2:03.045",
+        "01:02:03.04a", ":02:03.045",
+    ].iter() {
+        assert_eq!(value.parse::<Timestamp>(), Err(()), "{}", value);
+    }
+}
+
+#[test]
+fn test_to_ts() {
+    assert_eq!(timestamp_to_ts("00:00:01.000"), Some(90_000));
+    assert_eq!(timestamp_to_ts("00:00:01,001"), Some(90_090));
+    assert_eq!(timestamp_to_ts("13:15:21.858"), Some(4_294_967_220));
+    assert_eq!(timestamp_to_ts("13:15:21.859"), None);
+    assert_eq!(timestamp_to_ts("25:00:00.000"), None);
+    assert_eq!(timestamp_to_ts("nonsense"), None);
+}
+
+#[test]
+fn test_round_trips() {
+    for &ts in [0, 1, 89, 90, 91, 90_000, 324_000_089, u32::MAX].iter() {
+
+        let timestamp = Timestamp::from(ts);
+        let cycled_ts = timestamp.ts().unwrap();
+
+        assert!(ts - cycled_ts < 90);
+        assert_eq!(Timestamp::from(cycled_ts), timestamp);
+        assert_eq!(timestamp.to_string().parse(), Ok(timestamp));
+    }
+}
+
+#[test]
+fn test_from_ticks_past_wraparound() {
+    assert_eq!(Timestamp::from_ticks(u32::MAX as u64), Timestamp::from(u32::MAX));
+    assert_eq!(ticks_to_timestamp(PTS_WRAP + 90_000), "13:15:22.858");
+}
+
+#[test]
+fn test_pts_add() {
+    assert_eq!(pts_add(90_000, 45_000), 135_000);
+    assert_eq!(pts_add(90_000, -45_000), 45_000);
+    assert_eq!(pts_add(u32::MAX, 91), 90);
+    assert_eq!(pts_add(90, -91), u32::MAX);
+    assert_eq!(pts_add(90_000, PTS_WRAP as i64), 90_000);
+    assert_eq!(pts_add(90_000, -(PTS_WRAP as i64) * 3 + 1), 90_001);
+}
+
+#[test]
+fn test_pts_cmp_wrapping() {
+    assert_eq!(pts_cmp_wrapping(90_000, 90_000), Ordering::Equal);
+    assert_eq!(pts_cmp_wrapping(45_000, 90_000), Ordering::Less);
+    assert_eq!(pts_cmp_wrapping(90, u32::MAX - 90), Ordering::Greater);
+    assert_eq!(pts_cmp_wrapping(u32::MAX - 90, 90), Ordering::Less);
+    assert_eq!(pts_cmp_wrapping(0, 1 << 31), Ordering::Less);
+    assert_eq!(pts_cmp_wrapping(1 << 31, 0), Ordering::Less);
+}
+
+#[test]
+fn test_unwrap_pts() {
+
+    let mut unwrapper = PtsUnwrapper::default();
+    let unwrapped = [u32::MAX - 1, 10, 5, u32::MAX, 1 << 30, (1 << 31) + 5, 3 << 30, 10, 20]
+        .iter()
+        .map(|&ts| unwrapper.unwrap_pts(ts))
+        .collect::<Vec<u64>>();
+
+    assert_eq!(
+        unwrapped,
+        [
+            PTS_WRAP - 2,
+            PTS_WRAP + 10,
+            PTS_WRAP + 5,
+            PTS_WRAP - 1,
+            PTS_WRAP + (1 << 30),
+            PTS_WRAP + (1 << 31) + 5,
+            PTS_WRAP + (3 << 30),
+            PTS_WRAP * 2 + 10,
+            PTS_WRAP * 2 + 20,
+        ],
+    );
+
+    // Nothing goes below zero, however far back the stream steps.
+    let mut unwrapper = PtsUnwrapper::default();
+
+    unwrapper.unwrap_pts(10);
+
+    assert_eq!(unwrapper.unwrap_pts(u32::MAX), 0);
+}
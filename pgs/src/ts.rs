@@ -0,0 +1,403 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    pes::{decode_pts, PTS_LENGTH, START_CODE},
+    segment::SegmentKind,
+};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{ErrorKind, Read, Result as IoResult},
+    mem::take,
+};
+
+pub const PACKET_SIZE: usize = 188;
+// Blu-ray captures put a four-byte arrival timestamp in front of every packet.
+pub const M2TS_PACKET_SIZE: usize = 192;
+
+const SYNC_BYTE: u8 = 0x47;
+// PES streams that have no optional header, and so no PTS.
+const PADDING_STREAM: u8 = 0xBE;
+const PRIVATE_STREAM_2: u8 = 0xBF;
+const PCS_TYPE: u8 = SegmentKind::PresentationComposition.code();
+const END_TYPE: u8 = SegmentKind::End.code();
+
+// Damage in the transport stream that was skipped over. The display set it happened in is dropped
+// as a whole, and reading picks up again with the next one.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TsWarning {
+    // Packets of the PID went missing, as their continuity counter skipped ahead.
+    ContinuityGap { expected: u8, found: u8 },
+    // The sync byte was not where it belonged, and this many bytes were skipped to find it again.
+    LostSync { skipped: usize },
+    // A PES packet whose header or segments did not add up.
+    MalformedPes,
+    // The stream ended partway through a PES packet.
+    IncompletePes,
+}
+
+impl Display for TsWarning {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            TsWarning::ContinuityGap { expected, found } => write!(
+                f,
+                "continuity counter jumped from {} to {}",
+                expected, found,
+            ),
+            TsWarning::LostSync { skipped } => {
+                write!(f, "lost sync; skipped {} bytes", skipped)
+            }
+            TsWarning::MalformedPes => {
+                write!(f, "dropped a malformed PES packet")
+            }
+            TsWarning::IncompletePes => {
+                write!(f, "dropped a PES packet cut off by the end of the stream")
+            }
+        }
+    }
+}
+
+// Demuxes one PID of an MPEG transport stream, with either 188 or 192-byte packets, back into the
+// segments of a bare PGS stream so that they can be read with ReadSegmentExt or
+// ReadDisplaySetExt. Segments are only passed on once their display set's END segment arrives, so
+// that damage never leaves half a display set behind, and anything before the first presentation
+// composition segment is skipped, as captures may start partway through a display set.
+//
+// Blu-ray muxers drop each segment's own header and carry its timestamps in the PES header
+// instead, often with decode times that differ from one segment to the next. As bare streams keep
+// a display set's timestamps consistent, every segment is given those of the presentation
+// composition segment that started its display set. PES packets that already carry segments with
+// their headers, such as the ones write_pes produces, pass through unchanged.
+pub struct TsReader<R: Read> {
+    inner: R,
+    pid: u16,
+    // Whatever comes in front of the sync byte in each packet, once it is known.
+    prefix: Option<usize>,
+    buffer: Vec<u8>,
+    continuity: Option<u8>,
+    // The PES packet being reassembled, if its start has been seen.
+    pes: Option<Vec<u8>>,
+    timestamps: (u32, u32),
+    // Segments of the display set that has not ended yet.
+    pending: Vec<u8>,
+    // Set until the next presentation composition segment after damage.
+    skipping: bool,
+    output: Vec<u8>,
+    position: usize,
+    warnings: Vec<TsWarning>,
+}
+
+impl<R: Read> TsReader<R> {
+
+    pub fn new(inner: R, pid: u16) -> Self {
+        Self {
+            inner,
+            pid,
+            prefix: None,
+            buffer: Vec::with_capacity(M2TS_PACKET_SIZE),
+            continuity: None,
+            pes: None,
+            timestamps: (0, 0),
+            pending: Vec::new(),
+            skipping: true,
+            output: Vec::new(),
+            position: 0,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    // Everything that has gone wrong since the last call.
+    pub fn take_warnings(&mut self) -> Vec<TsWarning> {
+        take(&mut self.warnings)
+    }
+
+    // Reads packets until at least one PES packet has been turned into segments. Returns false
+    // once the stream has nothing more to give.
+    fn fill(&mut self) -> IoResult<bool> {
+
+        while self.output.is_empty() {
+            match self.read_packet()? {
+                Some(packet) => {
+                    self.handle_packet(&packet);
+                }
+                None => {
+                    if let Some(pes) = self.pes.take() {
+                        match pes_length(&pes) {
+                            Some(length) if pes.len() < length => {
+                                self.discard(TsWarning::IncompletePes);
+                            }
+                            _ => self.finish_pes(&pes),
+                        }
+                    }
+                    return Ok(!self.output.is_empty())
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Returns the packet from its sync byte on, or None at the end of the stream.
+    fn read_packet(&mut self) -> IoResult<Option<Vec<u8>>> {
+
+        let prefix = match self.prefix {
+            Some(prefix) => prefix,
+            None => {
+                if !self.buffer_up_to(5)? {
+                    return Ok(None)
+                }
+                let prefix = if self.buffer[0] != SYNC_BYTE && self.buffer[4] == SYNC_BYTE {
+                    M2TS_PACKET_SIZE - PACKET_SIZE
+                } else {
+                    0
+                };
+                self.prefix = Some(prefix);
+                prefix
+            }
+        };
+        let mut skipped = 0;
+
+        loop {
+
+            if !self.buffer_up_to(prefix + PACKET_SIZE)? {
+                return Ok(None)
+            }
+
+            if self.buffer[prefix] == SYNC_BYTE {
+                break
+            }
+
+            let skip = match self.buffer[prefix + 1..].iter().position(|&byte| byte == SYNC_BYTE) {
+                Some(position) => position + 1,
+                None => self.buffer.len() - prefix,
+            };
+
+            self.buffer.drain(..skip);
+            skipped += skip;
+        }
+
+        if skipped > 0 {
+            self.discard(TsWarning::LostSync { skipped });
+            self.continuity = None;
+            self.pes = None;
+        }
+
+        let packet = self.buffer[prefix..prefix + PACKET_SIZE].to_vec();
+
+        self.buffer.clear();
+
+        Ok(Some(packet))
+    }
+
+    // Returns false if the stream ended first.
+    fn buffer_up_to(&mut self, length: usize) -> IoResult<bool> {
+
+        let mut chunk = [0u8; M2TS_PACKET_SIZE];
+
+        while self.buffer.len() < length {
+            match self.inner.read(&mut chunk[..length - self.buffer.len()]) {
+                Ok(0) => return Ok(false),
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn handle_packet(&mut self, packet: &[u8]) {
+
+        let pid = ((packet[1] & 0x1F) as u16) << 8 | packet[2] as u16;
+
+        // Packets flagged with transport errors are left for the continuity check to notice.
+        if pid != self.pid || packet[1] & 0x80 != 0 {
+            return
+        }
+
+        let unit_start = packet[1] & 0x40 != 0;
+        let control = (packet[3] >> 4) & 0x03;
+        let counter = packet[3] & 0x0F;
+        let mut offset = 4;
+        let mut discontinuity = false;
+
+        if control & 0x02 != 0 {
+            let length = packet[4] as usize;
+            discontinuity = length > 0 && packet[5] & 0x80 != 0;
+            offset += 1 + length;
+        }
+
+        // Only packets with a payload count towards continuity.
+        if control & 0x01 == 0 || offset >= PACKET_SIZE {
+            return
+        }
+
+        match self.continuity {
+            // A repeated packet, which is allowed once.
+            Some(last) if counter == last => return,
+            Some(last) if !discontinuity && counter != (last + 1) & 0x0F => {
+                self.discard(
+                    TsWarning::ContinuityGap { expected: (last + 1) & 0x0F, found: counter }
+                );
+                self.pes = None;
+            }
+            _ => {}
+        }
+
+        self.continuity = Some(counter);
+
+        let payload = &packet[offset..];
+
+        if unit_start {
+            if let Some(pes) = self.pes.replace(payload.to_vec()) {
+                self.finish_pes(&pes);
+            }
+        } else if let Some(pes) = self.pes.as_mut() {
+            pes.extend_from_slice(payload);
+        }
+
+        // PES packets that give their length can be finished without waiting for the next one.
+        if let Some(length) = self.pes.as_deref().and_then(pes_length) {
+            if self.pes.as_ref().unwrap().len() >= length {
+                let pes = self.pes.take().unwrap();
+                self.finish_pes(&pes[..length]);
+            }
+        }
+    }
+
+    fn finish_pes(&mut self, pes: &[u8]) {
+
+        if pes.len() < 6 || pes[..3] != START_CODE {
+            self.discard(TsWarning::MalformedPes);
+            return
+        }
+        if pes[3] == PADDING_STREAM || pes[3] == PRIVATE_STREAM_2 {
+            return
+        }
+        if pes.len() < 9 || pes.len() < 9 + pes[8] as usize {
+            self.discard(TsWarning::MalformedPes);
+            return
+        }
+
+        let flags = pes[7];
+        let header_length = pes[8] as usize;
+        let header = &pes[9..9 + header_length];
+        // Bare streams only have room for the lower 32 bits.
+        let pts = if flags & 0x80 != 0 && header_length >= PTS_LENGTH {
+            Some(decode_pts(&header[..PTS_LENGTH]) as u32)
+        } else {
+            None
+        };
+        let dts = if flags & 0xC0 == 0xC0 && header_length >= 2 * PTS_LENGTH {
+            Some(decode_pts(&header[PTS_LENGTH..]) as u32)
+        } else {
+            None
+        };
+        let payload = &pes[9 + header_length..];
+        let headed = payload.starts_with(b"PG");
+        // The type and size, plus the magic number and timestamps when the segment has them.
+        let segment_header_length = if headed { 13 } else { 3 };
+        let mut segments = payload;
+
+        while !segments.is_empty() {
+
+            if segments.len() < segment_header_length || headed && !segments.starts_with(b"PG") {
+                self.discard(TsWarning::MalformedPes);
+                return
+            }
+
+            let kind = segments[segment_header_length - 3];
+            let length = segment_header_length
+                + ((segments[segment_header_length - 2] as usize) << 8
+                    | segments[segment_header_length - 1] as usize);
+
+            if segments.len() < length {
+                self.discard(TsWarning::MalformedPes);
+                return
+            }
+
+            if headed {
+                self.push_segment(kind, &segments[..length]);
+            } else {
+
+                if kind == PCS_TYPE {
+                    self.timestamps = (pts.unwrap_or(self.timestamps.0), dts.unwrap_or(0));
+                }
+
+                let mut segment = Vec::with_capacity(10 + length);
+
+                segment.extend_from_slice(b"PG");
+                segment.extend_from_slice(&self.timestamps.0.to_be_bytes());
+                segment.extend_from_slice(&self.timestamps.1.to_be_bytes());
+                segment.extend_from_slice(&segments[..length]);
+                self.push_segment(kind, &segment);
+            }
+
+            segments = &segments[length..];
+        }
+    }
+
+    fn push_segment(&mut self, kind: u8, segment: &[u8]) {
+
+        if self.skipping {
+            if kind != PCS_TYPE {
+                return
+            }
+            self.skipping = false;
+        }
+
+        self.pending.extend_from_slice(segment);
+
+        if kind == END_TYPE {
+            self.output.append(&mut self.pending);
+        }
+    }
+
+    fn discard(&mut self, warning: TsWarning) {
+        self.warnings.push(warning);
+        self.pending.clear();
+        self.skipping = true;
+    }
+}
+
+impl<R: Read> Read for TsReader<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+
+        if self.position == self.output.len() {
+
+            self.output.clear();
+            self.position = 0;
+
+            if !self.fill()? {
+                return Ok(0)
+            }
+        }
+
+        let count = buf.len().min(self.output.len() - self.position);
+
+        buf[..count].copy_from_slice(&self.output[self.position..self.position + count]);
+        self.position += count;
+
+        Ok(count)
+    }
+}
+
+// The whole PES packet's length, if its header gives one.
+fn pes_length(pes: &[u8]) -> Option<usize> {
+    match pes.get(4..6) {
+        Some(&[0, 0]) | None => None,
+        Some(length) => Some(6 + ((length[0] as usize) << 8 | length[1] as usize)),
+    }
+}
@@ -0,0 +1,247 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use super::super::{
+    displayset::{
+        Cid,
+        Composition,
+        CompositionObject,
+        DisplaySet,
+        Object,
+        Palette,
+        PaletteEntry,
+        ReadDisplaySetExt,
+        Window,
+        WriteDisplaySetExt,
+    },
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    pes::write_pes,
+    segment::{CompositionState, FrameRate},
+};
+use rand::{thread_rng, Rng};
+
+const PID: u16 = 0x1200;
+
+fn display_set(pts: u32, number: u16) -> DisplaySet {
+
+    let mut rng = thread_rng();
+    let mut display_set = DisplaySet {
+        pts,
+        width: 1920,
+        height: 1080,
+        frame_rate: FrameRate::Fps23_976,
+        composition: Composition {
+            number,
+            state: CompositionState::EpochStart,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut palette = Palette::default();
+
+    for id in 0..4 {
+        palette.entries.insert(id, PaletteEntry { y: id * 60, cr: 128, cb: 128, alpha: 255 });
+    }
+
+    display_set.windows.insert(WindowId(0), Window { x: 100, y: 900, width: 64, height: 32 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    // Noise, so that the object spans several packets.
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object {
+            width: 64,
+            height: 32,
+            lines: (0..32).map(|_| (0..64).map(|_| rng.gen_range(0..4)).collect()).collect(),
+            ..Default::default()
+        },
+    );
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 100, y: 900, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+// One PES packet per segment with the segment headers stripped, as Blu-ray muxers do, each with a
+// PTS of its own.
+fn blu_ray_pes(display_set: &DisplaySet) -> Vec<Vec<u8>> {
+
+    let mut bytes = vec![];
+    let mut packets = vec![];
+    let mut position = 0;
+
+    bytes.write_display_set(display_set).unwrap();
+
+    while position < bytes.len() {
+
+        let length = 13 + ((bytes[position + 11] as usize) << 8 | bytes[position + 12] as usize);
+        let mut pes = vec![];
+
+        write_pes(
+            &mut pes,
+            display_set.pts - packets.len() as u32 * 1_000,
+            &bytes[position + 10..position + length],
+        ).unwrap();
+        packets.push(pes);
+        position += length;
+    }
+
+    packets
+}
+
+fn packetize(pid: u16, pes: &[u8], counter: &mut u8) -> Vec<Vec<u8>> {
+    pes.chunks(PACKET_SIZE - 4)
+        .enumerate()
+        .map(|(index, chunk)| {
+
+            let mut packet = vec![
+                SYNC_BYTE,
+                if index == 0 { 0x40 } else { 0x00 } | (pid >> 8) as u8,
+                pid as u8,
+            ];
+
+            if chunk.len() == PACKET_SIZE - 4 {
+                packet.push(0x10 | *counter);
+            } else {
+                // The last packet is filled out with stuffing in its adaptation field.
+                let stuffing = PACKET_SIZE - 5 - chunk.len();
+                packet.push(0x30 | *counter);
+                packet.push(stuffing as u8);
+                if stuffing > 0 {
+                    packet.push(0x00);
+                    packet.resize(6 + stuffing - 1, 0xFF);
+                }
+            }
+
+            packet.extend_from_slice(chunk);
+            *counter = (*counter + 1) & 0x0F;
+
+            packet
+        })
+        .collect()
+}
+
+// Packets for the PID, each followed by one for another PID.
+fn transport_stream(pes_packets: &[Vec<u8>]) -> Vec<Vec<u8>> {
+
+    let mut counter = 0;
+    let mut other_counter = 0;
+
+    pes_packets.iter()
+        .flat_map(|pes| packetize(PID, pes, &mut counter))
+        .flat_map(|packet| {
+            let other = packetize(0x1011, &[SYNC_BYTE; 184], &mut other_counter);
+            vec![packet, other[0].clone()]
+        })
+        .collect()
+}
+
+fn read_all(bytes: &[u8]) -> (Vec<DisplaySet>, Vec<TsWarning>) {
+
+    let mut reader = TsReader::new(bytes, PID);
+    let mut display_sets = vec![];
+
+    while let Ok(display_set) = reader.read_display_set() {
+        display_sets.push(display_set);
+    }
+
+    (display_sets, reader.take_warnings())
+}
+
+#[test]
+fn test_read_blu_ray_segments() {
+
+    let display_sets = vec![display_set(90_000, 0), display_set(180_000, 1)];
+    let pes_packets = display_sets.iter().flat_map(blu_ray_pes).collect::<Vec<Vec<u8>>>();
+
+    assert_eq!(read_all(&transport_stream(&pes_packets).concat()), (display_sets, vec![]));
+}
+
+#[test]
+fn test_read_m2ts_packets() {
+
+    let display_sets = vec![display_set(90_000, 0), display_set(180_000, 1)];
+    let pes_packets = display_sets.iter().flat_map(blu_ray_pes).collect::<Vec<Vec<u8>>>();
+    let bytes = transport_stream(&pes_packets).iter()
+        .flat_map(|packet| [0x00, 0x12, 0x34, 0x56].iter().chain(packet.iter()).copied())
+        .collect::<Vec<u8>>();
+
+    assert_eq!(read_all(&bytes), (display_sets, vec![]));
+}
+
+#[test]
+fn test_read_headed_segments() {
+
+    let display_sets = vec![display_set(90_000, 0), display_set(180_000, 1)];
+    let pes_packets = display_sets.iter()
+        .map(|display_set| {
+            let mut segments = vec![];
+            let mut pes = vec![];
+            segments.write_display_set(display_set).unwrap();
+            write_pes(&mut pes, display_set.pts, &segments).unwrap();
+            pes
+        })
+        .collect::<Vec<Vec<u8>>>();
+
+    assert_eq!(read_all(&transport_stream(&pes_packets).concat()), (display_sets, vec![]));
+}
+
+#[test]
+fn test_continuity_gap() {
+
+    let display_sets = [display_set(90_000, 0), display_set(180_000, 1)];
+    let pes_packets = display_sets.iter().flat_map(blu_ray_pes).collect::<Vec<Vec<u8>>>();
+    let mut packets = transport_stream(&pes_packets);
+    // The PCS, WDS, and PDS take a packet each, and every other packet belongs to another PID.
+    let lost = packets.remove(2 * 4);
+
+    assert_eq!(lost[3] & 0x0F, 4);
+    assert_eq!(
+        read_all(&packets.concat()),
+        (display_sets[1..].to_vec(), vec![TsWarning::ContinuityGap { expected: 4, found: 5 }]),
+    );
+}
+
+#[test]
+fn test_starts_partway_through_display_set() {
+
+    let display_sets = [display_set(90_000, 0), display_set(180_000, 1)];
+    let pes_packets = display_sets.iter().flat_map(blu_ray_pes).collect::<Vec<Vec<u8>>>();
+
+    assert_eq!(
+        read_all(&transport_stream(&pes_packets[1..]).concat()),
+        (display_sets[1..].to_vec(), vec![]),
+    );
+}
+
+#[test]
+fn test_lost_sync() {
+
+    let display_sets = vec![display_set(90_000, 0), display_set(180_000, 1)];
+    let first = transport_stream(&blu_ray_pes(&display_sets[0])).concat();
+    let second = transport_stream(&blu_ray_pes(&display_sets[1])).concat();
+    let bytes = [first, vec![0xAA; 50], second].concat();
+
+    assert_eq!(read_all(&bytes), (display_sets, vec![TsWarning::LostSync { skipped: 50 }]));
+}
+
+#[test]
+fn test_incomplete_pes() {
+
+    let mut pes_packets = blu_ray_pes(&display_set(90_000, 0));
+
+    pes_packets.pop();
+
+    let object = pes_packets.pop().unwrap();
+    let mut bytes = transport_stream(&pes_packets).concat();
+    let mut counter = 3;
+
+    bytes.extend(packetize(PID, &object, &mut counter)[0].iter());
+
+    assert_eq!(read_all(&bytes), (vec![], vec![TsWarning::IncompletePes]));
+}
@@ -5,16 +5,18 @@
  */
 
 use pgs::{
-    ts_to_timestamp,
     segment::{
         CompositionState,
+        ReadOptions,
         ReadSegmentExt,
         Segment,
         Sequence,
         ReadError,
     },
+    style::{Color, Style},
 };
 use std::{
+    collections::BTreeMap,
     fs::File,
     io::{stdin, BufReader, ErrorKind, Read},
 };
@@ -23,6 +25,18 @@ use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_v
 fn main() {
 
     let matches = app_from_crate!()
+        .arg(Arg::with_name("no-color")
+            .long("no-color")
+            .help("Disables colored output; NO_COLOR is also honored")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("summary-only")
+            .long("summary-only")
+            .help("Prints only the number of segments of each kind")
+            .takes_value(false)
+            .required(false)
+        )
         .arg(Arg::with_name("input")
             .index(1)
             .value_name("INPUT-FILE")
@@ -34,6 +48,8 @@ fn main() {
             Licensed under the Open Software License version 3.0\n\
             <{}>", env!("CARGO_PKG_REPOSITORY")).as_str())
         .get_matches();
+    let style = Style::detect(matches.is_present("no-color"));
+    let summary_only = matches.is_present("summary-only");
     let input_value = matches.value_of("input").unwrap();
     let (mut stdin_read, mut file_read);
     let mut input = BufReader::<&mut dyn Read>::new(
@@ -47,6 +63,10 @@ fn main() {
         }
     );
 
+    let mut counts = BTreeMap::<&'static str, usize>::new();
+    let options = ReadOptions { keep_unknown: true, ..Default::default() };
+    let mut offset = 0;
+
     eprintln!("Iterating through PGS segments...");
 
     //
@@ -55,23 +75,27 @@ fn main() {
 
     loop {
 
-        match input.read_segment() {
+        match input.read_segment_counted(&mut offset, &options) {
             Ok(segment) => {
+
+                *counts.entry(segment_name(&segment)).or_insert(0) += 1;
+
+                if summary_only {
+                    continue
+                }
+
+                print_header(&style, &segment);
+
                 match segment {
                     Segment::PresentationComposition(pcs) => {
-                        println!(
-                            "presentation_composition_segment({})",
-                            ts_to_timestamp(pcs.pts),
-                        );
                         println!("  composition_number = {}", pcs.composition_number);
                         println!("  composition_state = {}", match pcs.composition_state {
                             CompositionState::EpochStart => "EPOCH_START",
                             CompositionState::Normal => "NORMAL_CASE",
                             CompositionState::AcquisitionPoint => "ACQUISITION_POINT",
                         });
-                        match pcs.palette_update_id {
-                            Some(pal_id) => println!("  palette_update_id = {}", pal_id),
-                            None => (),
+                        if let Some(pal_id) = pcs.palette_update_id {
+                            println!("  palette_update_id = {}", pal_id);
                         }
                         for comp_obj in pcs.composition_objects.iter() {
                             println!("  window_information");
@@ -79,25 +103,24 @@ fn main() {
                             println!("    window_id = {}", comp_obj.window_id);
                             println!("    object_horizontal_position = {}", comp_obj.x);
                             println!("    object_vertical_position = {}", comp_obj.y);
-                            match &comp_obj.crop {
-                                Some(crop) => {
-                                    println!(
-                                        "    object_cropping_horizontal_position = {}",
-                                        crop.x,
-                                    );
-                                    println!(
-                                        "    object_cropping_vertical_position = {}",
-                                        crop.y,
-                                    );
-                                    println!("    object_cropping_width = {}", crop.width);
-                                    println!("    object_crooping_height = {}", crop.height);
-                                }
-                                None => { }
+                            if comp_obj.forced {
+                                println!("    forced_on_flag = 1");
+                            }
+                            if let Some(crop) = &comp_obj.crop {
+                                println!(
+                                    "    object_cropping_horizontal_position = {}",
+                                    crop.x,
+                                );
+                                println!(
+                                    "    object_cropping_vertical_position = {}",
+                                    crop.y,
+                                );
+                                println!("    object_cropping_width = {}", crop.width);
+                                println!("    object_crooping_height = {}", crop.height);
                             }
                         }
                     }
                     Segment::WindowDefinition(wds) => {
-                        println!("window_definition_segment({})", ts_to_timestamp(wds.pts));
                         for wd in wds.windows.iter() {
                             println!("  window_id = {}", wd.id);
                             println!("  window_horizontal_position = {}", wd.x);
@@ -108,20 +131,22 @@ fn main() {
 
                     }
                     Segment::ObjectDefinition(ods) => {
-                        println!("object_definition_segment({})", ts_to_timestamp(ods.pts));
                         println!("  object_id = {}", ods.id);
                         println!("  object_version_number = {}", ods.version);
                         println!("  object_sequence = {}", match ods.sequence {
                             Sequence::Single => "SINGLE",
                             Sequence::First => "FIRST",
+                            Sequence::Middle => "MIDDLE",
                             Sequence::Last => "LAST",
                         });
-                        println!("  object_width = {}", ods.width);
-                        println!("  object_height = {}", ods.height);
-                        println!("  object_data = [{} lines]", ods.lines.len());
+                        if let Some(header) = &ods.header {
+                            println!("  object_data_length = {}", header.data_length);
+                            println!("  object_width = {}", header.width);
+                            println!("  object_height = {}", header.height);
+                        }
+                        println!("  object_data = [{} bytes]", ods.data.len());
                     }
                     Segment::PaletteDefinition(pds) => {
-                        println!("palette_definition_segment({})", ts_to_timestamp(pds.pts));
                         println!("  palette_id = {}", pds.id);
                         println!("  palette_version_number = {}", pds.version);
                         for pe in pds.entries.iter() {
@@ -132,10 +157,13 @@ fn main() {
                             println!("    t_value = {}", pe.alpha);
                         }
                     }
-                    Segment::End(es) => {
-                        println!("end_segment({})", ts_to_timestamp(es.pts));
+                    Segment::End(_) => {
                         println!();
                     }
+                    Segment::Unknown(us) => {
+                        println!("  segment_type = 0x{:02X}", us.kind);
+                        println!("  segment_data = [{} bytes]", us.data.len());
+                    }
                 }
             }
             Err(err) => {
@@ -151,4 +179,35 @@ fn main() {
             }
         };
     }
+
+    if summary_only {
+        for (name, count) in counts.iter() {
+            println!("{:<34} {:>8}", name, count);
+        }
+    }
+}
+
+fn segment_name(segment: &Segment) -> &'static str {
+    match segment {
+        Segment::PresentationComposition(_) => "presentation_composition_segment",
+        Segment::WindowDefinition(_) => "window_definition_segment",
+        Segment::PaletteDefinition(_) => "palette_definition_segment",
+        Segment::ObjectDefinition(_) => "object_definition_segment",
+        Segment::End(_) => "end_segment",
+        Segment::Unknown(_) => "unknown_segment",
+    }
+}
+
+fn print_header(style: &Style, segment: &Segment) {
+
+    let (pts, color) = match segment {
+        Segment::PresentationComposition(pcs) => (pcs.pts, Color::Magenta),
+        Segment::WindowDefinition(wds) => (wds.pts, Color::Blue),
+        Segment::PaletteDefinition(pds) => (pds.pts, Color::Yellow),
+        Segment::ObjectDefinition(ods) => (ods.pts, Color::Green),
+        Segment::End(es) => (es.pts, Color::Dim),
+        Segment::Unknown(us) => (us.pts, Color::Red),
+    };
+
+    println!("{}({})", style.paint(segment_name(segment), color), style.timestamp(pts));
 }
@@ -0,0 +1,99 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::crop::Placement;
+
+// The process exit code used when a dry run finds something that cannot fit the new margins.
+pub const EXIT_UNFIT: i32 = 1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlacementCounts {
+    pub clamped: usize,
+    pub fallback: usize,
+    pub unfit: usize,
+}
+
+impl PlacementCounts {
+
+    // Counts whatever happened on the worse of the two axes.
+    pub fn record(&mut self, x: Placement, y: Placement) {
+        match (x, y) {
+            (Placement::Unfit, _) | (_, Placement::Unfit) => self.unfit += 1,
+            (Placement::Fallback(_), _) | (_, Placement::Fallback(_)) => self.fallback += 1,
+            (Placement::Clamped(_), _) | (_, Placement::Clamped(_)) => self.clamped += 1,
+            _ => {}
+        }
+    }
+}
+
+// What the pipeline found along the way, whether or not anything is being written.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Analysis {
+    pub display_sets: usize,
+    pub epochs: usize,
+    pub resolutions: Vec<(u16, u16)>,
+    pub windows: PlacementCounts,
+    pub objects: PlacementCounts,
+    pub events: PlacementCounts,
+    pub overlaps: usize,
+    pub diagnostics: usize,
+    // Anything else that would have stopped a real run.
+    pub failures: usize,
+}
+
+impl Analysis {
+
+    // Returns whether this resolution has not been seen before.
+    pub fn record_resolution(&mut self, width: u16, height: u16) -> bool {
+        if self.resolutions.contains(&(width, height)) {
+            false
+        } else {
+            self.resolutions.push((width, height));
+            true
+        }
+    }
+
+    // Anything that could not be kept within the margin counts, whether it was dropped or placed
+    // anyway.
+    pub fn failed(&self) -> bool {
+        self.failures > 0 || [self.windows, self.objects, self.events].iter()
+            .any(|counts| counts.fallback > 0 || counts.unfit > 0)
+    }
+
+    pub fn table(&self) -> String {
+
+        let resolutions = self.resolutions.iter()
+            .map(|(width, height)| format!("{}x{}", width, height))
+            .collect::<Vec<String>>();
+        let mut rows = vec![
+            ("display sets", self.display_sets.to_string()),
+            ("epochs", self.epochs.to_string()),
+            ("resolutions", resolutions.join(", ")),
+        ];
+
+        for (name, counts) in [
+            ("windows", self.windows),
+            ("objects", self.objects),
+            ("events", self.events),
+        ].iter() {
+            rows.push((name, format!(
+                "{} clamped, {} placed without margin, {} unfit",
+                counts.clamped, counts.fallback, counts.unfit,
+            )));
+        }
+
+        rows.push(("window overlaps", self.overlaps.to_string()));
+        rows.push(("other findings", self.diagnostics.to_string()));
+        rows.push(("failures", self.failures.to_string()));
+
+        rows.iter()
+            .map(|(name, value)| format!("{:<16} {}\n", format!("{}:", name), value))
+            .collect()
+    }
+}
@@ -0,0 +1,72 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+#[test]
+fn test_record_counts_worse_axis() {
+
+    let mut counts = PlacementCounts::default();
+
+    counts.record(Placement::Fits(0), Placement::Fits(0));
+    counts.record(Placement::Clamped(30), Placement::Fits(0));
+    counts.record(Placement::Clamped(30), Placement::Fallback(0));
+    counts.record(Placement::Fits(0), Placement::Unfit);
+    counts.record(Placement::Unfit, Placement::Fallback(0));
+
+    assert_eq!(counts, PlacementCounts { clamped: 1, fallback: 1, unfit: 2 });
+}
+
+#[test]
+fn test_failed() {
+
+    let mut analysis = Analysis::default();
+
+    analysis.objects.clamped = 3;
+    analysis.overlaps = 1;
+
+    assert!(!analysis.failed());
+
+    analysis.events.fallback = 1;
+
+    assert!(analysis.failed());
+
+    analysis.events.fallback = 0;
+    analysis.windows.unfit = 1;
+
+    assert!(analysis.failed());
+
+    analysis.windows.unfit = 0;
+    analysis.failures = 1;
+
+    assert!(analysis.failed());
+}
+
+#[test]
+fn test_table() {
+
+    let mut analysis = Analysis { display_sets: 12, epochs: 2, ..Default::default() };
+
+    assert!(analysis.record_resolution(1920, 1080));
+    assert!(analysis.record_resolution(1280, 720));
+    assert!(!analysis.record_resolution(1920, 1080));
+
+    analysis.windows.clamped = 2;
+    analysis.objects.unfit = 1;
+
+    assert_eq!(
+        analysis.table(),
+        "display sets:    12\n\
+        epochs:          2\n\
+        resolutions:     1920x1080, 1280x720\n\
+        windows:         2 clamped, 0 placed without margin, 0 unfit\n\
+        objects:         0 clamped, 0 placed without margin, 1 unfit\n\
+        events:          0 clamped, 0 placed without margin, 0 unfit\n\
+        window overlaps: 0\n\
+        other findings:  0\n\
+        failures:        0\n",
+    );
+}
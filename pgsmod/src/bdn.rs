@@ -0,0 +1,593 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::quantize::quantize;
+use pgs::{
+    check::windows_overlap,
+    color::{ColorMatrix, Range},
+    displayset::{
+        clear_display_set,
+        Cid,
+        Composition,
+        CompositionObject,
+        DisplaySet,
+        Object,
+        Window,
+    },
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    png::PngImage,
+    segment::{CompositionState, FrameRate as PcsFrameRate},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+// How long an event is shown when nothing after it takes it down, in 90 kHz ticks.
+pub const FINAL_EVENT_DURATION: u32 = 5 * 90_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameRate {
+    Film,
+    Fps24,
+    Fps25,
+    Ntsc,
+}
+
+impl FrameRate {
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "23.976" => Some(FrameRate::Film),
+            "24" => Some(FrameRate::Fps24),
+            "25" => Some(FrameRate::Fps25),
+            "29.97" => Some(FrameRate::Ntsc),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FrameRate::Film => "23.976",
+            FrameRate::Fps24 => "24",
+            FrameRate::Fps25 => "25",
+            FrameRate::Ntsc => "29.97",
+        }
+    }
+
+    // What a presentation composition segment records for this rate.
+    pub fn pcs_frame_rate(self) -> PcsFrameRate {
+        match self {
+            FrameRate::Film => PcsFrameRate::Fps23_976,
+            FrameRate::Fps24 => PcsFrameRate::Fps24,
+            FrameRate::Fps25 => PcsFrameRate::Fps25,
+            FrameRate::Ntsc => PcsFrameRate::Fps29_97,
+        }
+    }
+
+    // Frames per second as a fraction.
+    fn fraction(self) -> (u64, u64) {
+        match self {
+            FrameRate::Film => (24_000, 1_001),
+            FrameRate::Fps24 => (24, 1),
+            FrameRate::Fps25 => (25, 1),
+            FrameRate::Ntsc => (30_000, 1_001),
+        }
+    }
+
+    // Frames per timecode second. The NTSC rates are counted as if they ran at full speed, which
+    // is what non-drop-frame timecode means.
+    fn base(self) -> u64 {
+        match self {
+            FrameRate::Film | FrameRate::Fps24 => 24,
+            FrameRate::Fps25 => 25,
+            FrameRate::Ntsc => 30,
+        }
+    }
+}
+
+// The frame nearest to the timestamp.
+pub fn frame_number(pts: u32, rate: FrameRate) -> u64 {
+
+    let (numerator, denominator) = rate.fraction();
+
+    (pts as u64 * numerator + 45_000 * denominator) / (90_000 * denominator)
+}
+
+// Non-drop-frame HH:MM:SS:FF.
+pub fn timecode(pts: u32, rate: FrameRate) -> String {
+
+    let frames = frame_number(pts, rate);
+    let base = rate.base();
+    let seconds = frames / base;
+
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60,
+        frames % base,
+    )
+}
+
+// The reverse of timecode. Drop-frame timecode, which skips the first two frame numbers of every
+// minute except each tenth, only exists at 29.97.
+pub fn timecode_pts(timecode: &str, rate: FrameRate, drop_frame: bool) -> Option<u32> {
+
+    let fields = timecode.split([':', ';'])
+        .map(|field| field.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    let base = rate.base();
+
+    if fields.len() != 4 || fields[1] >= 60 || fields[2] >= 60 || fields[3] >= base
+        || (drop_frame && rate != FrameRate::Ntsc) {
+        return None
+    }
+
+    let minutes = fields[0] * 60 + fields[1];
+    let mut frames = (minutes * 60 + fields[2]) * base + fields[3];
+
+    if drop_frame {
+        frames -= 2 * (minutes - minutes / 10);
+    }
+
+    let (numerator, denominator) = rate.fraction();
+    let pts = (frames * 90_000 * denominator + numerator / 2) / numerator;
+
+    if pts <= u32::MAX as u64 {
+        Some(pts as u32)
+    } else {
+        None
+    }
+}
+
+pub fn video_format(height: u16) -> String {
+    match height {
+        576 => "576i".to_string(),
+        480 => "480i".to_string(),
+        _ => format!("{}p", height),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BdnEvent {
+    pub in_pts: u32,
+    pub out_pts: u32,
+    pub file: String,
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+pub fn bdn_xml(title: &str, rate: FrameRate, screen_height: u16, events: &[BdnEvent]) -> String {
+
+    let first = events.first().map_or(0, |event| event.in_pts);
+    let last = events.last().map_or(0, |event| event.out_pts);
+    let mut xml = String::new();
+
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(
+        "<BDN Version=\"0.93\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+        xsi:noNamespaceSchemaLocation=\"BD-03-006-0093b BDN File Format.xsd\">\n"
+    );
+    xml.push_str("  <Description>\n");
+    xml.push_str(&format!("    <Name Title=\"{}\" Content=\"\"/>\n", escape(title)));
+    xml.push_str("    <Language Code=\"und\"/>\n");
+    xml.push_str(&format!(
+        "    <Format VideoFormat=\"{}\" FrameRate=\"{}\" DropFrame=\"False\"/>\n",
+        video_format(screen_height), rate.name(),
+    ));
+    xml.push_str(&format!(
+        "    <Events Type=\"Graphic\" FirstEventInTC=\"{}\" LastEventOutTC=\"{}\" \
+        NumberofEvents=\"{}\"/>\n",
+        timecode(first, rate), timecode(last, rate), events.len(),
+    ));
+    xml.push_str("  </Description>\n");
+    xml.push_str("  <Events>\n");
+
+    for event in events.iter() {
+        xml.push_str(&format!(
+            "    <Event InTC=\"{}\" OutTC=\"{}\" Forced=\"False\">\n",
+            timecode(event.in_pts, rate), timecode(event.out_pts, rate),
+        ));
+        xml.push_str(&format!(
+            "      <Graphic Width=\"{}\" Height=\"{}\" X=\"{}\" Y=\"{}\">{}</Graphic>\n",
+            event.width, event.height, event.x, event.y, escape(&event.file),
+        ));
+        xml.push_str("    </Event>\n");
+    }
+
+    xml.push_str("  </Events>\n");
+    xml.push_str("</BDN>\n");
+
+    xml
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BdnError {
+    MissingElement(&'static str),
+    MissingAttribute {
+        element: &'static str,
+        attribute: &'static str,
+    },
+    BadValue {
+        attribute: &'static str,
+        value: String,
+    },
+    UnknownFrameRate(String),
+    UnknownVideoFormat(String),
+    BadTimecode(String),
+    EmptyEvent(String),
+    TooManyGraphics(String),
+    GraphicOffScreen(String),
+    BadImage {
+        file: String,
+        message: String,
+    },
+}
+
+impl Display for BdnError {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            BdnError::MissingElement(element) => write!(f, "no {} element", element),
+            BdnError::MissingAttribute { element, attribute } => {
+                write!(f, "{} element has no {} attribute", element, attribute)
+            }
+            BdnError::BadValue { attribute, value } => {
+                write!(f, "{} attribute has a bad value: {}", attribute, value)
+            }
+            BdnError::UnknownFrameRate(rate) => write!(f, "unsupported frame rate: {}", rate),
+            BdnError::UnknownVideoFormat(format) => {
+                write!(f, "unsupported video format: {}", format)
+            }
+            BdnError::BadTimecode(timecode) => write!(f, "bad timecode: {}", timecode),
+            BdnError::EmptyEvent(timecode) => {
+                write!(f, "event at {} does not end after it starts", timecode)
+            }
+            BdnError::TooManyGraphics(timecode) => {
+                write!(f, "event at {} has more than two graphics", timecode)
+            }
+            BdnError::GraphicOffScreen(file) => write!(f, "{} does not fit on screen", file),
+            BdnError::BadImage { file, message } => write!(f, "{}: {}", file, message),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BdnDocument {
+    pub frame_rate: FrameRate,
+    pub width: u16,
+    pub height: u16,
+    // One per graphic, so an event with two graphics appears twice.
+    pub events: Vec<BdnEvent>,
+}
+
+// Reads the parts of a BDN file that describe graphics and their timing. The Forced flag is not
+// carried over.
+pub fn parse_bdn(xml: &str) -> Result<BdnDocument, BdnError> {
+
+    let elements = elements(xml);
+    let format = elements.iter()
+        .find(|element| element.name == "Format")
+        .ok_or(BdnError::MissingElement("Format"))?;
+    let rate_name = format.attribute("Format", "FrameRate")?;
+    let frame_rate = FrameRate::from_name(rate_name)
+        .ok_or_else(|| BdnError::UnknownFrameRate(rate_name.to_string()))?;
+    let video_format = format.attribute("Format", "VideoFormat")?;
+    let (width, height) = match video_format {
+        "1080p" | "1080i" => (1920, 1080),
+        "720p" => (1280, 720),
+        "576p" | "576i" => (720, 576),
+        "480p" | "480i" => (720, 480),
+        _ => return Err(BdnError::UnknownVideoFormat(video_format.to_string())),
+    };
+    let drop_frame = format.attributes.get("DropFrame")
+        .is_some_and(|value| value.eq_ignore_ascii_case("true"));
+    let mut times = None;
+    let mut events = Vec::new();
+
+    for element in elements.iter() {
+        match element.name.as_str() {
+            "Event" => {
+
+                let pts = |attribute| {
+                    let timecode = element.attribute("Event", attribute)?;
+                    timecode_pts(timecode, frame_rate, drop_frame)
+                        .ok_or_else(|| BdnError::BadTimecode(timecode.to_string()))
+                };
+
+                times = Some((pts("InTC")?, pts("OutTC")?));
+            }
+            "Graphic" => {
+
+                let (in_pts, out_pts) = times.ok_or(BdnError::MissingElement("Event"))?;
+                let number = |attribute| {
+                    let value = element.attribute("Graphic", attribute)?;
+                    value.parse::<u16>().map_err(|_| {
+                        BdnError::BadValue { attribute, value: value.to_string() }
+                    })
+                };
+
+                events.push(BdnEvent {
+                    in_pts,
+                    out_pts,
+                    file: element.text.clone(),
+                    x: number("X")?,
+                    y: number("Y")?,
+                    width: number("Width")?,
+                    height: number("Height")?,
+                });
+            }
+            _ => (),
+        }
+    }
+
+    Ok(BdnDocument { frame_rate, width, height, events })
+}
+
+// Makes an epoch of every event: an epoch start that shows its graphics, sharing one quantized
+// palette, and a normal case that clears them unless the next event starts first. Events that
+// overlap are cut short by the one after. The PNG dimensions win over those in the XML.
+pub fn import_bdn(
+    document: &BdnDocument,
+    mut load: impl FnMut(&str) -> Result<PngImage, String>,
+) -> Result<Vec<DisplaySet>, BdnError> {
+
+    let mut events = document.events.iter().collect::<Vec<&BdnEvent>>();
+    let mut groups = Vec::<Vec<&BdnEvent>>::new();
+    let mut display_sets = Vec::new();
+    let mut number = 0u16;
+
+    events.sort_by_key(|event| event.in_pts);
+
+    for event in events {
+        match groups.last_mut() {
+            Some(group) if (group[0].in_pts, group[0].out_pts) == (event.in_pts, event.out_pts) => {
+                group.push(event)
+            }
+            _ => groups.push(vec![event]),
+        }
+    }
+
+    for (index, group) in groups.iter().enumerate() {
+
+        let (in_pts, out_pts) = (group[0].in_pts, group[0].out_pts);
+        let at = || timecode(in_pts, document.frame_rate);
+
+        if out_pts <= in_pts {
+            return Err(BdnError::EmptyEvent(at()))
+        }
+        if group.len() > 2 {
+            return Err(BdnError::TooManyGraphics(at()))
+        }
+
+        let images = group.iter()
+            .map(|event| {
+                let image = load(&event.file)
+                    .map_err(|message| BdnError::BadImage { file: event.file.clone(), message })?;
+                if event.x as u32 + image.width > document.width as u32
+                    || event.y as u32 + image.height > document.height as u32 {
+                    return Err(BdnError::GraphicOffScreen(event.file.clone()))
+                }
+                Ok(image)
+            })
+            .collect::<Result<Vec<PngImage>, BdnError>>()?;
+        let quantized = quantize(
+            &images.iter().collect::<Vec<&PngImage>>(),
+            ColorMatrix::for_width(document.width),
+            Range::Limited,
+        );
+        let mut windows = group.iter()
+            .zip(images.iter())
+            .map(|(event, image)| Window {
+                x: event.x,
+                y: event.y,
+                width: image.width as u16,
+                height: image.height as u16,
+            })
+            .collect::<Vec<Window>>();
+
+        // Windows may not overlap, so overlapping graphics share one that covers both.
+        if windows.len() == 2 && windows_overlap(&windows[0], &windows[1]) {
+            windows = vec![bounds(&windows[0], &windows[1])];
+        }
+
+        let mut display_set = DisplaySet {
+            pts: in_pts,
+            dts: 0,
+            width: document.width,
+            height: document.height,
+            frame_rate: document.frame_rate.pcs_frame_rate(),
+            composition: Composition {
+                number,
+                state: CompositionState::EpochStart,
+                objects: BTreeMap::new(),
+            },
+            ..Default::default()
+        };
+
+        display_set.palettes.insert(
+            VersionedId { id: PaletteId(0), version: 0 },
+            quantized.palette,
+        );
+
+        for (object_id, (event, lines)) in group.iter().zip(quantized.lines).enumerate() {
+
+            let window_id = WindowId(object_id.min(windows.len() - 1) as u8);
+            let object_id = ObjectId(object_id as u16);
+
+            display_set.objects.insert(
+                VersionedId { id: object_id, version: 0 },
+                Object {
+                    width: lines.first().map_or(0, |line| line.len() as u16),
+                    height: lines.len() as u16,
+                    lines,
+                    ..Default::default()
+                },
+            );
+            display_set.composition.objects.insert(
+                Cid { object_id, window_id },
+                CompositionObject { x: event.x, y: event.y, crop: None, forced: false },
+            );
+        }
+        for (window_id, window) in windows.into_iter().enumerate() {
+            display_set.windows.insert(WindowId(window_id as u8), window);
+        }
+
+        let next_in_pts = groups.get(index + 1).map(|next| next[0].in_pts);
+
+        display_sets.push(display_set);
+        number = number.wrapping_add(1);
+
+        if next_in_pts.is_none_or(|next_in_pts| next_in_pts > out_pts) {
+
+            let clear = DisplaySet {
+                dts: 0,
+                ..clear_display_set(display_sets.last().unwrap(), out_pts)
+            };
+
+            display_sets.push(clear);
+            number = number.wrapping_add(1);
+        }
+    }
+
+    Ok(display_sets)
+}
+
+fn bounds(window_1: &Window, window_2: &Window) -> Window {
+
+    let x = window_1.x.min(window_2.x);
+    let y = window_1.y.min(window_2.y);
+
+    Window {
+        x,
+        y,
+        width: (window_1.x + window_1.width).max(window_2.x + window_2.width) - x,
+        height: (window_1.y + window_1.height).max(window_2.y + window_2.height) - y,
+    }
+}
+
+struct Element {
+    name: String,
+    attributes: BTreeMap<String, String>,
+    text: String,
+}
+
+impl Element {
+
+    fn attribute(&self, element: &'static str, attribute: &'static str)
+        -> Result<&str, BdnError> {
+        self.attributes.get(attribute)
+            .map(String::as_str)
+            .ok_or(BdnError::MissingAttribute { element, attribute })
+    }
+}
+
+// Every start tag in the document, in order, with the text that follows it. This is only as much
+// XML as BDN files need; there is no nesting, and declarations, comments, and end tags are
+// skipped.
+fn elements(xml: &str) -> Vec<Element> {
+
+    let mut elements = Vec::new();
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+
+        rest = &rest[start + 1..];
+
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue
+        }
+
+        let end = match tag_end(rest) {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[..end];
+
+        rest = &rest[end + 1..];
+
+        if tag.starts_with(['/', '?', '!']) {
+            continue
+        }
+
+        let tag = tag.strip_suffix('/').unwrap_or(tag);
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let text = &rest[..rest.find('<').unwrap_or(rest.len())];
+
+        elements.push(Element {
+            name: tag[..name_end].to_string(),
+            attributes: attributes(&tag[name_end..]),
+            text: unescape(text.trim()),
+        });
+    }
+
+    elements
+}
+
+// The closing bracket of a tag, which may also appear inside quoted attribute values.
+fn tag_end(tag: &str) -> Option<usize> {
+
+    let mut quote = None;
+
+    for (index, character) in tag.char_indices() {
+        match (quote, character) {
+            (None, '>') => return Some(index),
+            (None, '"') | (None, '\'') => quote = Some(character),
+            (Some(open), _) if open == character => quote = None,
+            _ => (),
+        }
+    }
+
+    None
+}
+
+fn attributes(text: &str) -> BTreeMap<String, String> {
+
+    let mut attributes = BTreeMap::new();
+    let mut rest = text;
+
+    while let Some(equals) = rest.find('=') {
+
+        let name = rest[..equals].trim().to_string();
+        let value = rest[equals + 1..].trim_start();
+        let quote = match value.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => quote,
+            _ => break,
+        };
+        let value = &value[1..];
+        let end = match value.find(quote) {
+            Some(end) => end,
+            None => break,
+        };
+
+        attributes.insert(name, unescape(&value[..end]));
+        rest = &value[end + 1..];
+    }
+
+    attributes
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
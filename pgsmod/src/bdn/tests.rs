@@ -0,0 +1,292 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::{Window},
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+};
+
+#[test]
+fn test_frame_rate_names() {
+    for name in ["23.976", "24", "25", "29.97"].iter() {
+        assert_eq!(FrameRate::from_name(name).unwrap().name(), *name);
+    }
+    assert_eq!(FrameRate::from_name("30"), None);
+}
+
+#[test]
+fn test_timecode() {
+    assert_eq!(timecode(0, FrameRate::Fps24), "00:00:00:00");
+    assert_eq!(timecode(3_750, FrameRate::Fps24), "00:00:00:01");
+    assert_eq!(timecode(1_874, FrameRate::Fps24), "00:00:00:00");
+    assert_eq!(timecode(1_875, FrameRate::Fps24), "00:00:00:01");
+    assert_eq!(timecode(90_000 * 3_661 + 3_600 * 24, FrameRate::Fps25), "01:01:01:24");
+    // A second of film at 23.976 takes 1.001 seconds of the clock.
+    assert_eq!(timecode(90_090, FrameRate::Film), "00:00:01:00");
+    assert_eq!(timecode(90_000 * 3_600, FrameRate::Film), "00:59:56:10");
+    assert_eq!(timecode(90_090 * 60, FrameRate::Ntsc), "00:01:00:00");
+}
+
+#[test]
+fn test_video_format() {
+    assert_eq!(video_format(1080), "1080p");
+    assert_eq!(video_format(720), "720p");
+    assert_eq!(video_format(576), "576i");
+    assert_eq!(video_format(480), "480i");
+}
+
+fn events() -> Vec<BdnEvent> {
+    vec![
+    BdnEvent {
+        in_pts: 90_000,
+        out_pts: 270_000,
+        file: "movie_0001.png".to_string(),
+        x: 810,
+        y: 900,
+        width: 300,
+        height: 60,
+    },
+    BdnEvent {
+        in_pts: 360_000,
+        out_pts: 450_000,
+        file: "movie_0002.png".to_string(),
+        x: 700,
+        y: 880,
+        width: 520,
+        height: 100,
+    },
+]
+}
+
+#[test]
+fn test_bdn_xml() {
+
+    let events = events();
+
+    assert_eq!(
+        bdn_xml("Tom & Jerry", FrameRate::Fps25, 1080, &events),
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <BDN Version=\"0.93\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+        xsi:noNamespaceSchemaLocation=\"BD-03-006-0093b BDN File Format.xsd\">\n  \
+        <Description>\n    \
+        <Name Title=\"Tom &amp; Jerry\" Content=\"\"/>\n    \
+        <Language Code=\"und\"/>\n    \
+        <Format VideoFormat=\"1080p\" FrameRate=\"25\" DropFrame=\"False\"/>\n    \
+        <Events Type=\"Graphic\" FirstEventInTC=\"00:00:01:00\" LastEventOutTC=\"00:00:05:00\" \
+        NumberofEvents=\"2\"/>\n  \
+        </Description>\n  \
+        <Events>\n    \
+        <Event InTC=\"00:00:01:00\" OutTC=\"00:00:03:00\" Forced=\"False\">\n      \
+        <Graphic Width=\"300\" Height=\"60\" X=\"810\" Y=\"900\">movie_0001.png</Graphic>\n    \
+        </Event>\n    \
+        <Event InTC=\"00:00:04:00\" OutTC=\"00:00:05:00\" Forced=\"False\">\n      \
+        <Graphic Width=\"520\" Height=\"100\" X=\"700\" Y=\"880\">movie_0002.png</Graphic>\n    \
+        </Event>\n  \
+        </Events>\n\
+        </BDN>\n",
+    );
+}
+
+#[test]
+fn test_timecode_pts() {
+    for &rate in [FrameRate::Film, FrameRate::Fps24, FrameRate::Fps25, FrameRate::Ntsc].iter() {
+        for &pts in [0, 3_754, 90_090, 324_000_000, 4_000_000_000].iter() {
+
+            let timecode = timecode(pts, rate);
+
+            assert_eq!(frame_number(timecode_pts(&timecode, rate, false).unwrap(), rate),
+                frame_number(pts, rate));
+        }
+    }
+    assert_eq!(timecode_pts("00:00:01:00", FrameRate::Film, false), Some(90_090));
+    assert_eq!(timecode_pts("00:00:00:25", FrameRate::Fps25, false), None);
+    assert_eq!(timecode_pts("00:60:00:00", FrameRate::Fps25, false), None);
+    assert_eq!(timecode_pts("00:00:01", FrameRate::Fps25, false), None);
+    assert_eq!(timecode_pts("99:00:00:00", FrameRate::Fps25, false), None);
+    assert_eq!(timecode_pts("00:00:01:00", FrameRate::Fps25, true), None);
+}
+
+#[test]
+fn test_drop_frame_timecode_pts() {
+
+    let ntsc = FrameRate::Ntsc;
+
+    // The first frame of the second minute is numbered 02, and every tenth minute keeps 00.
+    assert_eq!(timecode_pts("00:01:00;02", ntsc, true), Some(1_800 * 3_003));
+    assert_eq!(timecode_pts("00:10:00;00", ntsc, true), Some(17_982 * 3_003));
+    assert_eq!(timecode_pts("01:00:00;00", ntsc, true), Some(107_892 * 3_003));
+}
+
+#[test]
+fn test_parse_bdn() {
+
+    let document = parse_bdn(&bdn_xml("<Movie>", FrameRate::Fps25, 1080, &events())).unwrap();
+
+    assert_eq!(
+        document,
+        BdnDocument { frame_rate: FrameRate::Fps25, width: 1920, height: 1080, events: events() },
+    );
+}
+
+#[test]
+fn test_parse_bdn_markup() {
+
+    let document = parse_bdn(
+        "<?xml version='1.0'?>\n\
+        <BDN><!-- <Event InTC=\"00:00:09:00\" OutTC=\"00:00:10:00\"> -->\n\
+        <Description><Format VideoFormat='576i' FrameRate='25' Comment='a > b'/></Description>\n\
+        <Events><Event InTC=\"00:00:01:00\" OutTC=\"00:00:02:00\" Forced=\"True\">\n\
+        <Graphic Width=\"4\" Height=\"2\" X=\"10\" Y=\"20\"> A &amp; B.png </Graphic>\n\
+        <Graphic Width=\"4\" Height=\"2\" X=\"10\" Y=\"40\">c.png</Graphic>\n\
+        </Event></Events></BDN>",
+    ).unwrap();
+
+    assert_eq!((document.width, document.height), (720, 576));
+    assert_eq!(document.events.len(), 2);
+    assert_eq!(document.events[0].file, "A & B.png");
+    assert_eq!((document.events[1].in_pts, document.events[1].out_pts), (90_000, 180_000));
+    assert_eq!((document.events[1].x, document.events[1].y), (10, 40));
+}
+
+#[test]
+fn test_parse_bdn_errors() {
+
+    let format = "<Format VideoFormat=\"1080p\" FrameRate=\"25\"/>";
+
+    assert_eq!(parse_bdn("<BDN/>"), Err(BdnError::MissingElement("Format")));
+    assert_eq!(
+        parse_bdn("<Format VideoFormat=\"1080p\" FrameRate=\"50\"/>"),
+        Err(BdnError::UnknownFrameRate("50".to_string())),
+    );
+    assert_eq!(
+        parse_bdn("<Format VideoFormat=\"2160p\" FrameRate=\"25\"/>"),
+        Err(BdnError::UnknownVideoFormat("2160p".to_string())),
+    );
+    assert_eq!(
+        parse_bdn(&format!("{}<Event InTC=\"00:00:01:30\" OutTC=\"00:00:02:00\">", format)),
+        Err(BdnError::BadTimecode("00:00:01:30".to_string())),
+    );
+    assert_eq!(
+        parse_bdn(&format!("{}<Graphic X=\"0\" Y=\"0\">a.png</Graphic>", format)),
+        Err(BdnError::MissingElement("Event")),
+    );
+    assert_eq!(
+        parse_bdn(&format!(
+            "{}<Event InTC=\"00:00:01:00\" OutTC=\"00:00:02:00\">\
+            <Graphic Width=\"4\" Height=\"2\" X=\"-1\" Y=\"0\">a.png</Graphic>",
+            format,
+        )),
+        Err(BdnError::BadValue { attribute: "X", value: "-1".to_string() }),
+    );
+}
+
+fn document(events: &[(u32, u32, u16, u16)]) -> BdnDocument {
+    BdnDocument {
+        frame_rate: FrameRate::Fps25,
+        width: 1920,
+        height: 1080,
+        events: events.iter()
+            .map(|&(in_pts, out_pts, x, y)| BdnEvent {
+                in_pts,
+                out_pts,
+                file: format!("{}x{}.png", x, y),
+                x,
+                y,
+                width: 4,
+                height: 2,
+            })
+            .collect(),
+    }
+}
+
+fn load(_: &str) -> Result<PngImage, String> {
+
+    let white = [255, 255, 255, 255];
+    let clear = [0, 0, 0, 0];
+
+    Ok(PngImage {
+        width: 4,
+        height: 2,
+        rgba: [clear, white, white, clear, white, [255, 255, 255, 128], white, white].concat(),
+    })
+}
+
+#[test]
+fn test_import_bdn() {
+
+    let display_sets = import_bdn(
+        &document(&[(90_000, 180_000, 100, 900), (180_000, 270_000, 100, 900),
+            (360_000, 450_000, 100, 900), (360_000, 450_000, 100, 1000)]),
+        load,
+    ).unwrap();
+    let shape = display_sets.iter()
+        .map(|display_set| (
+            display_set.pts,
+            display_set.composition.number,
+            display_set.composition.state,
+            display_set.composition.objects.len(),
+        ))
+        .collect::<Vec<(u32, u16, CompositionState, usize)>>();
+
+    assert_eq!(
+        shape,
+        [
+            (90_000, 0, CompositionState::EpochStart, 1),
+            (180_000, 1, CompositionState::EpochStart, 1),
+            (270_000, 2, CompositionState::Normal, 0),
+            (360_000, 3, CompositionState::EpochStart, 2),
+            (450_000, 4, CompositionState::Normal, 0),
+        ],
+    );
+    assert!(display_sets.iter().all(|display_set| display_set.dts == 0));
+    assert!(display_sets.iter().all(|display_set| display_set.frame_rate == PcsFrameRate::Fps25));
+
+    let shown = &display_sets[3];
+    let object = &shown.objects[&VersionedId { id: ObjectId(1), version: 0 }];
+
+    assert_eq!(shown.windows.len(), 2);
+    assert_eq!(shown.windows[&WindowId(1)], Window { x: 100, y: 1000, width: 4, height: 2 });
+    assert_eq!((object.width, object.height), (4, 2));
+    assert_eq!(object.lines[0][0], 0);
+    assert_eq!(shown.palettes[&VersionedId { id: PaletteId(0), version: 0 }].entries.len(), 3);
+}
+
+#[test]
+fn test_import_bdn_shares_window_when_graphics_overlap() {
+
+    let display_sets = import_bdn(
+        &document(&[(90_000, 180_000, 100, 900), (90_000, 180_000, 102, 901)]),
+        load,
+    ).unwrap();
+
+    assert_eq!(display_sets[0].windows.len(), 1);
+    assert_eq!(
+        display_sets[0].windows[&WindowId(0)],
+        Window { x: 100, y: 900, width: 6, height: 3 },
+    );
+    assert!(display_sets[0].composition.objects.keys().all(|cid| cid.window_id == WindowId(0)));
+}
+
+#[test]
+fn test_import_bdn_errors() {
+
+    let import = |events: &[(u32, u32, u16, u16)]| import_bdn(&document(events), load);
+
+    assert_eq!(import(&[(90_000, 90_000, 0, 0)]), Err(BdnError::EmptyEvent("00:00:01:00".into())));
+    assert_eq!(
+        import(&[(90_000, 180_000, 0, 0), (90_000, 180_000, 0, 10), (90_000, 180_000, 0, 20)]),
+        Err(BdnError::TooManyGraphics("00:00:01:00".to_string())),
+    );
+    assert_eq!(
+        import(&[(90_000, 180_000, 1917, 0)]),
+        Err(BdnError::GraphicOffScreen("1917x0.png".to_string())),
+    );
+    assert_eq!(
+        import_bdn(&document(&[(90_000, 180_000, 0, 0)]), |_| Err("not a PNG file".to_string())),
+        Err(BdnError::BadImage { file: "0x0.png".to_string(), message: "not a PNG file".into() }),
+    );
+}
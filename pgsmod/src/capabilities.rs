@@ -0,0 +1,142 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::sink::JSON_SCHEMA_VERSION;
+
+// Bumped whenever the capabilities document itself changes incompatibly.
+pub const CAPABILITIES_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Subcommand,
+    Input,
+    Output,
+    Transform,
+    Report,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Capability {
+    pub kind: Kind,
+    pub name: &'static str,
+    // The subcommand or the long argument that enables it, if any.
+    pub argument: Option<&'static str>,
+    pub schema_version: Option<u32>,
+}
+
+const fn capability(kind: Kind, name: &'static str, argument: Option<&'static str>) -> Capability {
+    Capability {
+        kind,
+        name,
+        argument,
+        schema_version: None,
+    }
+}
+
+// Everything a script may want to probe for. Tests hold this against the command line so that
+// neither can change without the other.
+pub const CAPABILITIES: &[Capability] = &[
+    capability(Kind::Subcommand, "fix-continuity", Some("fix-continuity")),
+    capability(Kind::Subcommand, "concat", Some("concat")),
+    capability(Kind::Subcommand, "export-json", Some("export-json")),
+    capability(Kind::Subcommand, "import-json", Some("import-json")),
+    capability(Kind::Subcommand, "report", Some("report")),
+    capability(Kind::Subcommand, "export-bdn", Some("export-bdn")),
+    capability(Kind::Subcommand, "import-bdn", Some("import-bdn")),
+    capability(Kind::Input, "sup", None),
+    capability(Kind::Input, "m2ts", Some("input-format")),
+    capability(Kind::Output, "sup", None),
+    capability(Kind::Output, "pes", Some("output-format")),
+    capability(Kind::Output, "json-lines", Some("dump-json")),
+    capability(Kind::Output, "png", Some("export-png")),
+    capability(Kind::Output, "contact-sheet", Some("contact-sheet")),
+    capability(Kind::Transform, "crop", Some("crop-width")),
+    capability(Kind::Transform, "uncrop", Some("uncrop-to")),
+    capability(Kind::Transform, "scale", Some("scale-width")),
+    capability(Kind::Transform, "resample", Some("scale-filter")),
+    capability(Kind::Transform, "place", Some("place")),
+    capability(Kind::Transform, "position", Some("position")),
+    capability(Kind::Transform, "hue", Some("hue")),
+    capability(Kind::Transform, "saturation", Some("saturation")),
+    capability(Kind::Transform, "tint", Some("tint")),
+    capability(Kind::Transform, "lum-scale", Some("lum-scale")),
+    capability(Kind::Transform, "tone-map", Some("tone-map")),
+    capability(Kind::Transform, "only-forced", Some("only-forced")),
+    capability(Kind::Transform, "strip-forced", Some("strip-forced")),
+    capability(Kind::Transform, "trim", Some("trim-start")),
+    capability(Kind::Transform, "dedup", Some("dedup")),
+    capability(Kind::Transform, "retime", Some("retime")),
+    capability(Kind::Transform, "pts-offset", Some("pts-offset")),
+    capability(Kind::Transform, "min-duration", Some("min-duration")),
+    capability(Kind::Transform, "min-gap", Some("min-gap")),
+    capability(Kind::Transform, "single-window", Some("single-window")),
+    capability(Kind::Transform, "fix-missing-indices", Some("fix-missing-indices")),
+    capability(Kind::Transform, "prune-palettes", Some("prune-palettes")),
+    capability(Kind::Transform, "optimize", Some("optimize")),
+    capability(Kind::Transform, "smooth-fades", Some("smooth-fades")),
+    capability(Kind::Transform, "drop-above", Some("drop-above")),
+    capability(Kind::Transform, "insert-clears", Some("insert-clears")),
+    capability(Kind::Transform, "fix-epochs", Some("fix-epochs")),
+    capability(Kind::Transform, "fix-dts", Some("fix-dts")),
+    Capability {
+        kind: Kind::Report,
+        name: "json-lines",
+        argument: Some("dump-json"),
+        schema_version: Some(JSON_SCHEMA_VERSION),
+    },
+    capability(Kind::Report, "preview-palette", Some("preview-palette")),
+    capability(Kind::Report, "dry-run", Some("dry-run")),
+    capability(Kind::Report, "verify-lossless", Some("verify-lossless")),
+    Capability {
+        kind: Kind::Report,
+        name: "capabilities",
+        argument: Some("capabilities"),
+        schema_version: Some(CAPABILITIES_SCHEMA_VERSION),
+    },
+];
+
+pub fn capabilities_json() -> String {
+
+    let list = |kind: Kind| {
+        CAPABILITIES.iter()
+            .filter(|capability| capability.kind == kind)
+            .map(|capability| {
+                let mut fields = vec![format!("\"name\":\"{}\"", capability.name)];
+                if let Some(argument) = capability.argument {
+                    fields.push(format!("\"argument\":\"{}\"", argument));
+                }
+                if let Some(version) = capability.schema_version {
+                    fields.push(format!("\"schema_version\":{}", version));
+                }
+                format!("{{{}}}", fields.join(","))
+            })
+            .collect::<Vec<String>>()
+            .join(",")
+    };
+    let features = pgs::FEATURES.iter()
+        .map(|feature| format!("\"{}\"", feature))
+        .collect::<Vec<String>>();
+
+    format!(
+        "{{\"schema_version\":{},\"name\":\"{}\",\"version\":\"{}\",\
+        \"library\":{{\"version\":\"{}\",\"features\":[{}]}},\
+        \"subcommands\":[{}],\"inputs\":[{}],\"outputs\":[{}],\"transforms\":[{}],\
+        \"reports\":[{}]}}",
+        CAPABILITIES_SCHEMA_VERSION,
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        pgs::VERSION,
+        features.join(","),
+        list(Kind::Subcommand),
+        list(Kind::Input),
+        list(Kind::Output),
+        list(Kind::Transform),
+        list(Kind::Report),
+    )
+}
@@ -0,0 +1,66 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use super::super::app;
+use clap::ErrorKind;
+
+#[test]
+fn test_registry_matches_command_line() {
+    for capability in CAPABILITIES.iter() {
+
+        let argument = match capability.argument {
+            Some(argument) => argument,
+            None => continue,
+        };
+
+        if capability.kind == Kind::Subcommand {
+            let matches = app()
+                .get_matches_from_safe(vec!["pgsmod", argument, "in.sup", "out.sup"])
+                .unwrap();
+            assert_eq!(matches.subcommand_name(), Some(argument));
+        } else {
+            // Missing values are fine; only an unknown argument means the two have drifted.
+            let long = format!("--{}", argument);
+            if let Err(err) = app().get_matches_from_safe(vec!["pgsmod", "--capabilities", &long]) {
+                assert_ne!(err.kind, ErrorKind::UnknownArgument, "{}", long);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_capabilities_json() {
+
+    let json = capabilities_json();
+    let features = pgs::FEATURES.iter()
+        .map(|feature| format!("\"{}\"", feature))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    assert!(json.starts_with("{\"schema_version\":1,\"name\":\"pgsmod\","));
+    assert!(json.contains(&format!(
+        "\"library\":{{\"version\":\"{}\",\"features\":[{}]}}",
+        pgs::VERSION,
+        features,
+    )));
+    assert!(pgs::FEATURES.contains(&"std"));
+    assert!(json.contains(
+        "\"subcommands\":[{\"name\":\"fix-continuity\",\"argument\":\"fix-continuity\"},\
+        {\"name\":\"concat\",\"argument\":\"concat\"},\
+        {\"name\":\"export-json\",\"argument\":\"export-json\"},\
+        {\"name\":\"import-json\",\"argument\":\"import-json\"},\
+        {\"name\":\"report\",\"argument\":\"report\"},\
+        {\"name\":\"export-bdn\",\"argument\":\"export-bdn\"},\
+        {\"name\":\"import-bdn\",\"argument\":\"import-bdn\"}]"
+    ));
+    assert!(json.contains(
+        "\"inputs\":[{\"name\":\"sup\"},{\"name\":\"m2ts\",\"argument\":\"input-format\"}]"
+    ));
+    assert!(json.contains(
+        "{\"name\":\"json-lines\",\"argument\":\"dump-json\",\"schema_version\":1}"
+    ));
+}
@@ -0,0 +1,161 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::{
+    displayset::{ReadDisplaySetExt, WriteDisplaySetExt},
+    segment::{CompositionState, ReadOptions},
+    ts_to_timestamp,
+};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Write},
+};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConcatOptions {
+    // How far to shift each input after the first, in 90 kHz ticks. Inputs without one start
+    // the gap after the end of the input before them.
+    pub offsets: Vec<u32>,
+    pub gap: u32,
+    pub allow_resolution_change: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConcatTotals {
+    pub display_sets: usize,
+    // The shift that was applied to each input, including the first one's zero.
+    pub offsets: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConcatError {
+    Read {
+        input: usize,
+        message: String,
+    },
+    Write(String),
+    ResolutionChange {
+        input: usize,
+        from: (u16, u16),
+        to: (u16, u16),
+    },
+    Overlap {
+        input: usize,
+        pts: u32,
+    },
+    PtsOverflow {
+        input: usize,
+    },
+}
+
+impl Display for ConcatError {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ConcatError::Read { input, message } => {
+                write!(f, "could not read input {}: {}", input + 1, message)
+            }
+            ConcatError::Write(message) => write!(f, "could not write output: {}", message),
+            ConcatError::ResolutionChange { input, from, to } => {
+                write!(
+                    f,
+                    "input {} is {}x{} instead of {}x{}",
+                    input + 1, to.0, to.1, from.0, from.1,
+                )
+            }
+            ConcatError::Overlap { input, pts } => {
+                write!(
+                    f,
+                    "input {} would start at {}, before the input ahead of it ends",
+                    input + 1, ts_to_timestamp(*pts),
+                )
+            }
+            ConcatError::PtsOverflow { input } => {
+                write!(f, "input {} would run past the largest timestamp", input + 1)
+            }
+        }
+    }
+}
+
+// Writes each input after the one before it as a single stream. Every input after the first is
+// shifted later, has its compositions numbered on from where the last input left off, and starts
+// with an epoch start so that nothing carries over from it.
+pub fn concat<R: Read, W: Write>(
+    inputs: &mut [R],
+    output: &mut W,
+    options: &ConcatOptions,
+) -> Result<ConcatTotals, ConcatError> {
+
+    let read_options = ReadOptions { lenient: true, ..Default::default() };
+    let mut totals = ConcatTotals::default();
+    let mut end = None::<u32>;
+    let mut next_number = None::<u16>;
+    let mut resolution = None::<(u16, u16)>;
+
+    for (index, input) in inputs.iter_mut().enumerate() {
+
+        let offset = match index {
+            0 => 0,
+            _ => match options.offsets.get(index - 1) {
+                Some(&offset) => offset,
+                None => end.unwrap_or(0).checked_add(options.gap)
+                    .ok_or(ConcatError::PtsOverflow { input: index })?,
+            },
+        };
+        let mut first = true;
+
+        totals.offsets.push(offset);
+
+        for display_set in input.display_sets(&read_options) {
+
+            let mut display_set = display_set
+                .map_err(|err| ConcatError::Read { input: index, message: err.to_string() })?;
+
+            display_set.pts = display_set.pts.checked_add(offset)
+                .ok_or(ConcatError::PtsOverflow { input: index })?;
+            if display_set.dts != 0 {
+                display_set.dts = display_set.dts.checked_add(offset)
+                    .ok_or(ConcatError::PtsOverflow { input: index })?;
+            }
+
+            if index > 0 {
+                if let Some(number) = next_number {
+                    display_set.composition.number = number;
+                }
+            }
+            if index > 0 && first {
+
+                let to = (display_set.width, display_set.height);
+
+                if let Some(from) = resolution {
+                    if from != to && !options.allow_resolution_change {
+                        return Err(ConcatError::ResolutionChange { input: index, from, to })
+                    }
+                }
+                if end.is_some_and(|end| display_set.pts < end) {
+                    return Err(ConcatError::Overlap { input: index, pts: display_set.pts })
+                }
+
+                display_set.composition.state = CompositionState::EpochStart;
+                display_set.palette_update_id = None;
+            }
+
+            output.write_display_set(&display_set)
+                .map_err(|err| ConcatError::Write(err.to_string()))?;
+
+            first = false;
+            end = Some(display_set.pts);
+            next_number = Some(display_set.composition.number.wrapping_add(1));
+            resolution = Some((display_set.width, display_set.height));
+            totals.display_sets += 1;
+        }
+    }
+
+    Ok(totals)
+}
@@ -0,0 +1,146 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::{
+        clear_display_set,
+        Cid,
+        Composition,
+        CompositionObject,
+        DisplaySet,
+        Object,
+        Palette,
+        Window,
+    },
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+};
+use std::io::Cursor;
+
+// An event from one to two seconds and another from three to four seconds, numbered from five.
+fn stream(width: u16, height: u16) -> Vec<u8> {
+
+    let mut shown = DisplaySet { pts: 90_000, dts: 85_000, width, height, ..Default::default() };
+    let mut output = vec![];
+
+    shown.composition.number = 5;
+    shown.composition.state = CompositionState::EpochStart;
+    shown.windows.insert(WindowId(0), Window { x: 0, y: 0, width: 2, height: 1 });
+    shown.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, Palette::default());
+    shown.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width: 2, height: 1, lines: vec![vec![1, 1]], ..Default::default() },
+    );
+    shown.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 0, y: 0, crop: None, forced: false },
+    );
+
+    let cleared = clear_display_set(&shown, 180_000);
+    let shown_again = DisplaySet {
+        pts: 270_000,
+        dts: 265_000,
+        composition: Composition {
+            number: 7,
+            ..shown.composition.clone()
+        },
+        ..shown.clone()
+    };
+    let cleared_again = clear_display_set(&shown_again, 360_000);
+
+    for display_set in [shown, cleared, shown_again, cleared_again].iter() {
+        output.write_display_set(display_set).unwrap();
+    }
+
+    output
+}
+
+fn read_all(mut input: &[u8]) -> Vec<DisplaySet> {
+
+    let mut display_sets = vec![];
+
+    while !input.is_empty() {
+        display_sets.push(input.read_display_set().unwrap());
+    }
+
+    display_sets
+}
+
+#[test]
+fn test_concat_file_to_itself() {
+
+    let input = stream(1920, 1080);
+    let mut inputs = [Cursor::new(&input), Cursor::new(&input)];
+    let mut output = vec![];
+    let options = ConcatOptions { gap: 90_000, ..Default::default() };
+    let totals = concat(&mut inputs, &mut output, &options).unwrap();
+
+    assert_eq!(totals, ConcatTotals { display_sets: 8, offsets: vec![0, 450_000] });
+
+    let display_sets = read_all(&output);
+
+    assert_eq!(
+        display_sets.iter()
+            .map(|display_set| (display_set.pts, display_set.dts))
+            .collect::<Vec<_>>(),
+        vec![
+            (90_000, 85_000), (180_000, 180_000), (270_000, 265_000), (360_000, 360_000),
+            (540_000, 535_000), (630_000, 630_000), (720_000, 715_000), (810_000, 810_000),
+        ],
+    );
+    assert_eq!(
+        display_sets.iter()
+            .map(|display_set| display_set.composition.number)
+            .collect::<Vec<u16>>(),
+        vec![5, 6, 7, 8, 9, 10, 11, 12],
+    );
+    assert_eq!(display_sets[4].composition.state, CompositionState::EpochStart);
+    assert_eq!(display_sets[4].objects.len(), 1);
+}
+
+#[test]
+fn test_concat_with_offset() {
+
+    let input = stream(1920, 1080);
+    let mut inputs = [Cursor::new(&input), Cursor::new(&input), Cursor::new(&input)];
+    let mut output = vec![];
+    let options = ConcatOptions { offsets: vec![900_000], gap: 0, ..Default::default() };
+    let totals = concat(&mut inputs, &mut output, &options).unwrap();
+
+    assert_eq!(totals.offsets, vec![0, 900_000, 1_260_000]);
+    assert_eq!(read_all(&output)[4].pts, 990_000);
+}
+
+#[test]
+fn test_concat_rejects_overlap() {
+
+    let input = stream(1920, 1080);
+    let mut inputs = [Cursor::new(&input), Cursor::new(&input)];
+    let options = ConcatOptions { offsets: vec![180_000], ..Default::default() };
+
+    assert_eq!(
+        concat(&mut inputs, &mut vec![], &options),
+        Err(ConcatError::Overlap { input: 1, pts: 270_000 }),
+    );
+}
+
+#[test]
+fn test_concat_resolution_change() {
+
+    let first = stream(1920, 1080);
+    let second = stream(1280, 720);
+    let mut options = ConcatOptions::default();
+
+    assert_eq!(
+        concat(&mut [Cursor::new(&first), Cursor::new(&second)], &mut vec![], &options),
+        Err(ConcatError::ResolutionChange { input: 1, from: (1920, 1080), to: (1280, 720) }),
+    );
+
+    options.allow_resolution_change = true;
+
+    assert!(concat(&mut [Cursor::new(&first), Cursor::new(&second)], &mut vec![], &options)
+        .is_ok());
+}
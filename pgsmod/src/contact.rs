@@ -0,0 +1,174 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::ts_to_timestamp;
+
+pub const THUMBNAIL_HEIGHT: usize = 48;
+pub const CELL_WIDTH: usize = 256;
+pub const ROWS_PER_PAGE: usize = 40;
+
+const PADDING: usize = 6;
+const GLYPH_SCALE: usize = 2;
+const LABEL_HEIGHT: usize = 5 * GLYPH_SCALE + PADDING;
+const CELL_HEIGHT: usize = THUMBNAIL_HEIGHT + LABEL_HEIGHT + PADDING;
+const BACKGROUND: [u8; 3] = [16, 16, 16];
+const CELL_BACKGROUND: [u8; 3] = [48, 48, 48];
+const LABEL_COLOR: [u8; 3] = [224, 224, 224];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Thumbnail {
+    pub index: usize,
+    pub pts: u32,
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+impl Thumbnail {
+
+    // Composites an RGBA rendering over the cell background and scales it to the thumbnail
+    // height, or narrower if it would not otherwise fit within a cell.
+    pub fn new(index: usize, pts: u32, width: usize, height: usize, rgba: &[u8]) -> Self {
+
+        let scale = (THUMBNAIL_HEIGHT as f64 / height.max(1) as f64)
+            .min((CELL_WIDTH - 2 * PADDING) as f64 / width.max(1) as f64);
+        let scaled_width = ((width as f64 * scale).round() as usize).max(1);
+        let scaled_height = ((height as f64 * scale).round() as usize).max(1);
+        let mut rgb = Vec::with_capacity(scaled_width * scaled_height * 3);
+
+        for y in 0..scaled_height {
+
+            let source_y = (y * height / scaled_height).min(height.saturating_sub(1));
+
+            for x in 0..scaled_width {
+
+                let source_x = (x * width / scaled_width).min(width.saturating_sub(1));
+                let offset = (source_y * width + source_x) * 4;
+                let pixel = rgba.get(offset..offset + 4).unwrap_or(&[0, 0, 0, 0]);
+                let alpha = pixel[3] as u32;
+
+                for channel in 0..3 {
+                    rgb.push(
+                        ((pixel[channel] as u32 * alpha
+                            + CELL_BACKGROUND[channel] as u32 * (255 - alpha)
+                            + 127) / 255) as u8
+                    );
+                }
+            }
+        }
+
+        Self {
+            index,
+            pts,
+            width: scaled_width,
+            height: scaled_height,
+            rgb,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+// Tiles thumbnails left to right and top to bottom, labeling each with its event index and
+// start timestamp.
+pub fn contact_sheet(thumbnails: &[Thumbnail], columns: usize) -> Page {
+
+    let columns = columns.max(1).min(thumbnails.len().max(1));
+    let rows = thumbnails.len().div_ceil(columns);
+    let width = PADDING + columns * (CELL_WIDTH + PADDING);
+    let height = PADDING + rows * (CELL_HEIGHT + PADDING);
+    let mut page = Page {
+        width,
+        height,
+        rgb: BACKGROUND.repeat(width * height),
+    };
+
+    for (position, thumbnail) in thumbnails.iter().enumerate() {
+
+        let cell_x = PADDING + (position % columns) * (CELL_WIDTH + PADDING);
+        let cell_y = PADDING + (position / columns) * (CELL_HEIGHT + PADDING);
+
+        fill(&mut page, cell_x, cell_y, CELL_WIDTH, CELL_HEIGHT, CELL_BACKGROUND);
+
+        let thumbnail_x = cell_x + (CELL_WIDTH - thumbnail.width) / 2;
+        let thumbnail_y = cell_y + PADDING / 2 + (THUMBNAIL_HEIGHT - thumbnail.height) / 2;
+
+        for y in 0..thumbnail.height {
+            let source = y * thumbnail.width * 3;
+            let target = ((thumbnail_y + y) * page.width + thumbnail_x) * 3;
+            page.rgb[target..target + thumbnail.width * 3]
+                .copy_from_slice(&thumbnail.rgb[source..source + thumbnail.width * 3]);
+        }
+
+        draw_text(
+            &mut page,
+            cell_x + PADDING,
+            cell_y + PADDING / 2 + THUMBNAIL_HEIGHT + PADDING,
+            &format!("#{} {}", thumbnail.index, ts_to_timestamp(thumbnail.pts)),
+        );
+    }
+
+    page
+}
+
+fn fill(page: &mut Page, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+    for row in y..y + height {
+        for column in x..x + width {
+            let offset = (row * page.width + column) * 3;
+            page.rgb[offset..offset + 3].copy_from_slice(&color);
+        }
+    }
+}
+
+fn draw_text(page: &mut Page, x: usize, y: usize, text: &str) {
+    for (position, c) in text.chars().enumerate() {
+
+        let glyph_x = x + position * 4 * GLYPH_SCALE;
+
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..3 {
+                if bits >> (2 - column) & 1 == 1 {
+                    fill(
+                        page,
+                        glyph_x + column * GLYPH_SCALE,
+                        y + row * GLYPH_SCALE,
+                        GLYPH_SCALE,
+                        GLYPH_SCALE,
+                        LABEL_COLOR,
+                    );
+                }
+            }
+        }
+    }
+}
+
+// A 3x5 bitmap font covering only what labels need; anything else is left blank.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        _ => [0; 5],
+    }
+}
@@ -0,0 +1,67 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+fn pixel(page: &Page, x: usize, y: usize) -> [u8; 3] {
+    let offset = (y * page.width + x) * 3;
+    [page.rgb[offset], page.rgb[offset + 1], page.rgb[offset + 2]]
+}
+
+#[test]
+fn test_thumbnail_scales_to_height() {
+
+    let rgba = [255, 255, 255, 255, 0, 0, 0, 0].repeat(4 * 4);
+    let thumbnail = Thumbnail::new(0, 0, 8, 4, &rgba);
+
+    assert_eq!((thumbnail.width, thumbnail.height), (96, THUMBNAIL_HEIGHT));
+    assert_eq!(thumbnail.rgb[..3], [255, 255, 255]);
+    assert_eq!(thumbnail.rgb[12 * 3..12 * 3 + 3], CELL_BACKGROUND);
+}
+
+#[test]
+fn test_thumbnail_fits_cell_width() {
+
+    let thumbnail = Thumbnail::new(0, 0, 1920, 60, &vec![255; 1920 * 60 * 4]);
+
+    assert_eq!(thumbnail.width, CELL_WIDTH - 2 * PADDING);
+    assert!(thumbnail.height < THUMBNAIL_HEIGHT);
+}
+
+#[test]
+fn test_contact_sheet_layout() {
+
+    let thumbnails = (0..7)
+        .map(|index| Thumbnail::new(index, index as u32 * 90_000, 1, 1, &[255, 0, 0, 255]))
+        .collect::<Vec<Thumbnail>>();
+    let page = contact_sheet(&thumbnails, 3);
+
+    assert_eq!(page.width, PADDING + 3 * (CELL_WIDTH + PADDING));
+    assert_eq!(page.height, PADDING + 3 * (CELL_HEIGHT + PADDING));
+    assert_eq!(pixel(&page, 0, 0), BACKGROUND);
+    assert_eq!(pixel(&page, PADDING, PADDING), CELL_BACKGROUND);
+    assert_eq!(pixel(&page, PADDING + CELL_WIDTH / 2, PADDING + PADDING / 2), [255, 0, 0]);
+
+    // The last row only has its first cell filled in.
+    let last_row = PADDING + 2 * (CELL_HEIGHT + PADDING);
+
+    assert_eq!(pixel(&page, PADDING, last_row), CELL_BACKGROUND);
+    assert_eq!(pixel(&page, 2 * PADDING + CELL_WIDTH, last_row), BACKGROUND);
+}
+
+#[test]
+fn test_contact_sheet_labels() {
+
+    let page = contact_sheet(&[Thumbnail::new(0, 0, 1, 1, &[0, 0, 0, 0])], 6);
+    let label_y = PADDING + PADDING / 2 + THUMBNAIL_HEIGHT + PADDING;
+
+    assert_eq!(page.width, PADDING + CELL_WIDTH + PADDING);
+
+    // The left column of the '#' glyph is solid.
+    for y in label_y..label_y + 5 * GLYPH_SCALE {
+        assert_eq!(pixel(&page, 2 * PADDING, y), LABEL_COLOR);
+    }
+}
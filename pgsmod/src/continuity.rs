@@ -0,0 +1,76 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::segment::SegmentKind;
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+
+const HEADER_SIZE: usize = 13;
+const PCS_KIND: u8 = SegmentKind::PresentationComposition.code();
+const COMPOSITION_NUMBER_OFFSET: usize = 5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ContinuityTotals {
+    pub display_sets: usize,
+    pub renumbered: usize,
+}
+
+// Copies segments straight through, only rewriting each PCS composition number so that they run
+// sequentially from the first one.
+pub fn fix_continuity(
+    input: &mut impl Read,
+    output: &mut impl Write,
+) -> IoResult<ContinuityTotals> {
+
+    let mut totals = ContinuityTotals::default();
+    let mut next_number = None::<u16>;
+    let mut header = [0u8; HEADER_SIZE];
+    let mut payload = Vec::new();
+
+    loop {
+
+        match input.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        }
+
+        if header[0..2] != *b"PG" {
+            return Err(
+                IoError::new(ErrorKind::InvalidData, "segment has unrecognized magic number")
+            )
+        }
+
+        payload.resize(u16::from_be_bytes([header[11], header[12]]) as usize, 0);
+        input.read_exact(&mut payload)?;
+
+        if header[10] == PCS_KIND && payload.len() >= COMPOSITION_NUMBER_OFFSET + 2 {
+
+            let field = &mut payload[COMPOSITION_NUMBER_OFFSET..COMPOSITION_NUMBER_OFFSET + 2];
+            let number = u16::from_be_bytes([field[0], field[1]]);
+
+            match next_number {
+                Some(expected) if expected != number => {
+                    field.copy_from_slice(&expected.to_be_bytes());
+                    totals.renumbered += 1;
+                    next_number = Some(expected.wrapping_add(1));
+                }
+                _ => {
+                    next_number = Some(number.wrapping_add(1));
+                }
+            }
+
+            totals.display_sets += 1;
+        }
+
+        output.write_all(&header)?;
+        output.write_all(&payload)?;
+    }
+
+    Ok(totals)
+}
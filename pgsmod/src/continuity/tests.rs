@@ -0,0 +1,75 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::segment::{
+    CompositionState,
+    EndSegment,
+    FrameRate,
+    PresentationCompositionSegment,
+    Raw,
+    ReadSegmentExt,
+    Segment,
+    WriteSegmentExt,
+};
+use std::io::Cursor;
+
+fn display_set(buffer: &mut Vec<u8>, pts: u32, composition_number: u16) {
+    buffer.write_segment(
+        &Segment::PresentationComposition(
+            PresentationCompositionSegment {
+                pts,
+                dts: pts,
+                width: 1920,
+                height: 1080,
+                frame_rate: FrameRate::Fps23_976,
+                composition_number,
+                composition_state: CompositionState::EpochStart,
+                palette_update_id: None,
+                composition_objects: vec![],
+                raw: Raw::default(),
+            }
+        )
+    ).unwrap();
+    buffer.write_segment(&Segment::End(EndSegment { pts, dts: pts, raw: Raw::default() })).unwrap();
+}
+
+#[test]
+fn test_fix_continuity() {
+
+    let mut input = vec![];
+    let mut output = vec![];
+
+    display_set(&mut input, 900, 7);
+    display_set(&mut input, 1800, 8);
+    display_set(&mut input, 2700, 0);
+    display_set(&mut input, 3600, 1);
+
+    let totals = fix_continuity(&mut Cursor::new(&input), &mut output).unwrap();
+
+    assert_eq!(totals, ContinuityTotals { display_sets: 4, renumbered: 2 });
+    assert_eq!(output.len(), input.len());
+
+    let mut reader = Cursor::new(&output);
+    let mut numbers = vec![];
+
+    while let Ok(segment) = reader.read_segment() {
+        if let Segment::PresentationComposition(pcs) = segment {
+            numbers.push(pcs.composition_number);
+        }
+    }
+
+    assert_eq!(numbers, vec![7, 8, 9, 10]);
+}
+
+#[test]
+fn test_fix_continuity_rejects_garbage() {
+
+    let mut output = vec![];
+    let result = fix_continuity(&mut Cursor::new(vec![0u8; 20]), &mut output);
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+}
@@ -0,0 +1,319 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::{
+    displayset::{CompositionObject, Object, Window},
+    id::{ObjectId, VersionedId},
+    segment::Crop,
+    timeline::EpochState,
+};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reframe {
+    Crop,
+    Uncrop,
+    Scale,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnfitPolicy {
+    Center,
+    Edge,
+    Error,
+    Drop,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    Start,
+    Center,
+    End,
+}
+
+// Where along the full screen a crop is taken from, which is also where uncropping puts it back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CropAnchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl CropAnchor {
+
+    pub const NAMES: [&'static str; 9] = [
+        "top-left",
+        "top",
+        "top-right",
+        "left",
+        "center",
+        "right",
+        "bottom-left",
+        "bottom",
+        "bottom-right",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "top-left" => Some(CropAnchor::TopLeft),
+            "top" => Some(CropAnchor::Top),
+            "top-right" => Some(CropAnchor::TopRight),
+            "left" => Some(CropAnchor::Left),
+            "center" => Some(CropAnchor::Center),
+            "right" => Some(CropAnchor::Right),
+            "bottom-left" => Some(CropAnchor::BottomLeft),
+            "bottom" => Some(CropAnchor::Bottom),
+            "bottom-right" => Some(CropAnchor::BottomRight),
+            _ => None,
+        }
+    }
+
+    // The horizontal anchor, then the vertical one.
+    pub fn anchors(self) -> (Anchor, Anchor) {
+        match self {
+            CropAnchor::TopLeft => (Anchor::Start, Anchor::Start),
+            CropAnchor::Top => (Anchor::Center, Anchor::Start),
+            CropAnchor::TopRight => (Anchor::End, Anchor::Start),
+            CropAnchor::Left => (Anchor::Start, Anchor::Center),
+            CropAnchor::Center => (Anchor::Center, Anchor::Center),
+            CropAnchor::Right => (Anchor::End, Anchor::Center),
+            CropAnchor::BottomLeft => (Anchor::Start, Anchor::End),
+            CropAnchor::Bottom => (Anchor::Center, Anchor::End),
+            CropAnchor::BottomRight => (Anchor::End, Anchor::End),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Placement {
+    Fits(u16),
+    // Moved inward so that the margin is kept, as the crop put it too close to or past an edge.
+    Clamped(u16),
+    // The margin could not be honored, so the policy's fallback position was used instead.
+    Fallback(u16),
+    Unfit,
+}
+
+impl Placement {
+
+    // Where it ends up, unless it is to be dropped.
+    pub fn offset(self) -> Option<u16> {
+        match self {
+            Placement::Fits(offset) | Placement::Clamped(offset) | Placement::Fallback(offset) => {
+                Some(offset)
+            }
+            Placement::Unfit => None,
+        }
+    }
+}
+
+impl Reframe {
+
+    // Scaling takes the whole screen, so the anchor only matters when cropping or uncropping.
+    #[allow(clippy::too_many_arguments)]
+    pub fn offset(
+        self,
+        screen_size: u16,
+        screen_new_size: u16,
+        size: u16,
+        offset: u16,
+        margin: u16,
+        anchor: Anchor,
+        policy: UnfitPolicy,
+    ) -> Placement {
+        match self {
+            Reframe::Crop => {
+                cropped_offset(screen_size, screen_new_size, size, offset, margin, anchor)
+                    .unwrap_or_else(|| unfit_offset(screen_new_size, size, policy))
+            }
+            Reframe::Uncrop => {
+                Placement::Fits(uncropped_offset(screen_size, screen_new_size, offset, anchor))
+            }
+            Reframe::Scale => {
+                Placement::Fits(scaled_offset(screen_size, screen_new_size, offset))
+            }
+        }
+    }
+}
+
+// Where the crop starts along the full screen, as the anchor puts it there. Negative when the crop
+// is larger than the screen being cropped, in which case the anchor puts the screen within it.
+pub fn crop_shift(screen_full_size: u16, screen_crop_size: u16, anchor: Anchor) -> i32 {
+
+    let start = |screen_size, size| {
+        anchored_offset(screen_size, size, 0, anchor, UnfitPolicy::Error).offset().unwrap() as i32
+    };
+
+    if screen_crop_size <= screen_full_size {
+        start(screen_full_size, screen_crop_size)
+    } else {
+        -start(screen_crop_size, screen_full_size)
+    }
+}
+
+// Returns None if the size and margins together do not fit the crop at all.
+pub fn cropped_offset(
+    screen_full_size: u16,
+    screen_crop_size: u16,
+    size: u16,
+    offset: u16,
+    margin: u16,
+    anchor: Anchor,
+) -> Option<Placement> {
+
+    if size as u32 + 2 * margin as u32 > screen_crop_size as u32 {
+        return None
+    }
+
+    let new_offset = offset as i32 - crop_shift(screen_full_size, screen_crop_size, anchor);
+    let max_offset = screen_crop_size as i32 - size as i32 - margin as i32;
+
+    Some(
+        if new_offset < margin as i32 {
+            Placement::Clamped(margin)
+        } else if new_offset > max_offset {
+            Placement::Clamped(max_offset as u16)
+        } else {
+            Placement::Fits(new_offset as u16)
+        }
+    )
+}
+
+// Puts something against one edge of the screen at the margin, or centers it.
+pub fn anchored_offset(
+    screen_size: u16,
+    size: u16,
+    margin: u16,
+    anchor: Anchor,
+    policy: UnfitPolicy,
+) -> Placement {
+
+    if size as u32 + 2 * margin as u32 > screen_size as u32 {
+        return unfit_offset(screen_size, size, policy)
+    }
+
+    Placement::Fits(
+        match anchor {
+            Anchor::Start => margin,
+            Anchor::Center => (screen_size - size) / 2,
+            Anchor::End => screen_size - size - margin,
+        }
+    )
+}
+
+// Centering gives up an equal amount of margin on either side, which is as little as possible.
+pub fn unfit_offset(screen_crop_size: u16, size: u16, policy: UnfitPolicy) -> Placement {
+    match policy {
+        UnfitPolicy::Center => Placement::Fallback(screen_crop_size.saturating_sub(size) / 2),
+        UnfitPolicy::Edge => Placement::Fallback(0),
+        UnfitPolicy::Error | UnfitPolicy::Drop => Placement::Unfit,
+    }
+}
+
+// Positions that were clamped to the margin when cropping cannot be recovered, so they come back
+// at the margin shifted into the larger canvas. The anchor must be the one the crop was taken with.
+pub fn uncropped_offset(
+    screen_crop_size: u16,
+    screen_full_size: u16,
+    offset: u16,
+    anchor: Anchor,
+) -> u16 {
+
+    let new_offset = offset as i32 + crop_shift(screen_full_size, screen_crop_size, anchor);
+
+    new_offset.clamp(0, u16::MAX as i32) as u16
+}
+
+fn scale(value: u32, screen_size: u16, screen_new_size: u16) -> u32 {
+
+    let screen_size = screen_size.max(1) as u32;
+
+    (value * screen_new_size as u32 * 2 + screen_size) / (screen_size * 2)
+}
+
+// Rounds to the nearest pixel.
+pub fn scaled_offset(screen_size: u16, screen_new_size: u16, offset: u16) -> u16 {
+    scale(offset as u32, screen_size, screen_new_size).min(u16::MAX as u32) as u16
+}
+
+// Sizes are taken between the scaled edges rather than scaled on their own, so that anything that
+// ended within the screen still does.
+pub fn scaled_size(screen_size: u16, screen_new_size: u16, size: u16, offset: u16) -> u16 {
+
+    let start = scale(offset as u32, screen_size, screen_new_size);
+    let end = scale(offset as u32 + size as u32, screen_size, screen_new_size);
+
+    (end - start).min(u16::MAX as u32) as u16
+}
+
+// The size of an object that a composition refers to: the largest of its definitions in the display
+// set, or the one the epoch already holds for display sets that only refer back to it, as palette
+// updates do.
+pub fn object_size(
+    objects: &BTreeMap<VersionedId<ObjectId>, Object>,
+    state: &EpochState,
+    object_id: ObjectId,
+) -> Option<(u16, u16)> {
+
+    let defined = objects.iter()
+        .filter(|(vid, _)| vid.id == object_id)
+        .map(|(_, object)| object)
+        .collect::<Vec<&Object>>();
+
+    if defined.is_empty() {
+        return state.objects.get(&object_id).map(|object| (object.width, object.height))
+    }
+
+    Some((
+        defined.iter().map(|object| object.width).max().unwrap(),
+        defined.iter().map(|object| object.height).max().unwrap(),
+    ))
+}
+
+// How much of an object is shown, which is where it gets placed on screen.
+pub fn shown_size(
+    composition_object: &CompositionObject,
+    object_width: u16,
+    object_height: u16,
+) -> (u16, u16) {
+    match &composition_object.crop {
+        Some(crop) => (crop.width, crop.height),
+        None => (object_width, object_height),
+    }
+}
+
+// A crop is relative to its object, so it shows the same part of the picture wherever the object
+// is moved. Only a crop that would now reach past the screen needs to be cut back to end there.
+pub fn clamp_crop(crop: &mut Crop, x: u16, y: u16, screen_width: u16, screen_height: u16) {
+    crop.width = crop.width.min(screen_width.saturating_sub(x));
+    crop.height = crop.height.min(screen_height.saturating_sub(y));
+}
+
+// Whether the shown part of an object, whose bitmap was left at its original size, reaches past
+// its window.
+pub fn overflows_window(
+    window: &Window,
+    composition_object: &CompositionObject,
+    object_width: u16,
+    object_height: u16,
+) -> bool {
+
+    let (width, height) = shown_size(composition_object, object_width, object_height);
+
+    composition_object.x < window.x
+        || composition_object.y < window.y
+        || composition_object.x as u32 + width as u32 > window.x as u32 + window.width as u32
+        || composition_object.y as u32 + height as u32 > window.y as u32 + window.height as u32
+}
@@ -0,0 +1,284 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::segment::Crop;
+
+fn centered_crop(
+    screen_full_size: u16,
+    screen_crop_size: u16,
+    size: u16,
+    offset: u16,
+    margin: u16,
+) -> Option<Placement> {
+    cropped_offset(screen_full_size, screen_crop_size, size, offset, margin, Anchor::Center)
+}
+
+fn centered_uncrop(screen_crop_size: u16, screen_full_size: u16, offset: u16) -> u16 {
+    uncropped_offset(screen_crop_size, screen_full_size, offset, Anchor::Center)
+}
+
+#[test]
+fn test_crop_then_uncrop_round_trips() {
+
+    for offset in [300, 500, 800, 1000].iter() {
+
+        let cropped = centered_crop(1920, 1440, 200, *offset, 30).unwrap().offset().unwrap();
+
+        assert_eq!(centered_uncrop(1440, 1920, cropped), *offset);
+    }
+}
+
+#[test]
+fn test_anchored_crop_then_uncrop_round_trips() {
+
+    for anchor in [Anchor::Start, Anchor::Center, Anchor::End].iter() {
+        for offset in [600, 800, 1000].iter() {
+
+            let cropped = cropped_offset(1920, 1440, 200, *offset, 30, *anchor).unwrap();
+
+            assert!(matches!(cropped, Placement::Fits(_)));
+            assert_eq!(uncropped_offset(1440, 1920, cropped.offset().unwrap(), *anchor), *offset);
+        }
+    }
+
+    // Undone from the wrong anchor, the crop lands somewhere else.
+    let cropped = cropped_offset(1920, 1440, 200, 800, 30, Anchor::End).unwrap().offset().unwrap();
+
+    assert_eq!(cropped, 320);
+    assert_eq!(uncropped_offset(1440, 1920, cropped, Anchor::Start), 320);
+    assert_eq!(
+        Reframe::Uncrop.offset(1440, 1920, 200, cropped, 30, Anchor::End, UnfitPolicy::Error),
+        Placement::Fits(800),
+    );
+}
+
+#[test]
+fn test_crop_shift_follows_anchor() {
+    assert_eq!(crop_shift(1920, 1440, Anchor::Start), 0);
+    assert_eq!(crop_shift(1920, 1440, Anchor::Center), 240);
+    assert_eq!(crop_shift(1920, 1440, Anchor::End), 480);
+    assert_eq!(crop_shift(1080, 801, Anchor::Center), 139);
+    assert_eq!(crop_shift(1280, 1920, Anchor::Start), 0);
+    assert_eq!(crop_shift(1280, 1920, Anchor::End), -640);
+}
+
+#[test]
+fn test_crop_anchor_names() {
+    for name in CropAnchor::NAMES.iter() {
+        assert!(CropAnchor::from_name(name).is_some());
+    }
+    assert_eq!(CropAnchor::BottomLeft.anchors(), (Anchor::Start, Anchor::End));
+    assert_eq!(CropAnchor::from_name("top"), Some(CropAnchor::Top));
+    assert_eq!(CropAnchor::from_name("middle"), None);
+}
+
+#[test]
+fn test_uncrop_centers_in_larger_canvas() {
+    assert_eq!(centered_uncrop(800, 1080, 700), 840);
+    assert_eq!(
+        Reframe::Uncrop.offset(800, 1080, 60, 700, 30, Anchor::Center, UnfitPolicy::Error),
+        Placement::Fits(840),
+    );
+}
+
+#[test]
+fn test_clamped_offset_returns_at_margin() {
+
+    assert_eq!(centered_crop(1080, 800, 60, 1000, 30), Some(Placement::Clamped(710)));
+    assert_eq!(centered_uncrop(800, 1080, 710), 850);
+    assert_eq!(centered_crop(1080, 800, 60, 150, 30), Some(Placement::Clamped(30)));
+    assert_eq!(centered_uncrop(800, 1080, 30), 30 + 140);
+}
+
+#[test]
+fn test_cropped_offset_at_zero() {
+    assert_eq!(centered_crop(1920, 1440, 200, 0, 30), Some(Placement::Clamped(30)));
+    assert_eq!(centered_crop(1080, 800, 60, 0, 0), Some(Placement::Clamped(0)));
+}
+
+#[test]
+fn test_cropped_offset_at_crop_boundaries() {
+    assert_eq!(centered_crop(1920, 1440, 200, 240 + 30, 30), Some(Placement::Fits(30)));
+    assert_eq!(centered_crop(1920, 1440, 200, 240 + 29, 30), Some(Placement::Clamped(30)));
+    assert_eq!(centered_crop(1920, 1440, 200, 240 + 1210, 30), Some(Placement::Fits(1210)));
+    assert_eq!(centered_crop(1920, 1440, 200, 240 + 1211, 30), Some(Placement::Clamped(1210)));
+    assert_eq!(centered_crop(1920, 1440, 200, u16::MAX, 30), Some(Placement::Clamped(1210)));
+}
+
+#[test]
+fn test_cropped_offset_wider_than_crop() {
+    assert_eq!(centered_crop(1920, 1440, 1441, 0, 0), None);
+    assert_eq!(centered_crop(1920, 1440, 1381, 240, 30), None);
+    assert_eq!(centered_crop(1920, 1440, u16::MAX, 0, u16::MAX), None);
+    assert_eq!(
+        Reframe::Crop.offset(1920, 1440, 1441, 0, 0, Anchor::Center, UnfitPolicy::Error),
+        Placement::Unfit,
+    );
+}
+
+#[test]
+fn test_cropped_offset_into_larger_screen() {
+    assert_eq!(crop_shift(1280, 1920, Anchor::Center), -320);
+    assert_eq!(centered_crop(1280, 1920, 200, 0, 30), Some(Placement::Fits(320)));
+    assert_eq!(centered_uncrop(1920, 1280, 0), 0);
+}
+
+fn placements(size: u16) -> Vec<Placement> {
+    [UnfitPolicy::Center, UnfitPolicy::Edge, UnfitPolicy::Error, UnfitPolicy::Drop].iter()
+        .map(|&policy| Reframe::Crop.offset(1080, 800, size, 500, 30, Anchor::Center, policy))
+        .collect()
+}
+
+#[test]
+fn test_unfit_policy_when_exactly_fitting() {
+    assert_eq!(placements(740), vec![Placement::Clamped(30); 4]);
+}
+
+#[test]
+fn test_unfit_policy_when_one_pixel_too_big() {
+    assert_eq!(
+        placements(741),
+        vec![Placement::Fallback(29), Placement::Fallback(0), Placement::Unfit, Placement::Unfit],
+    );
+}
+
+#[test]
+fn test_unfit_policy_when_absurdly_oversized() {
+    assert_eq!(
+        placements(u16::MAX),
+        vec![Placement::Fallback(0), Placement::Fallback(0), Placement::Unfit, Placement::Unfit],
+    );
+}
+
+#[test]
+fn test_anchored_offset() {
+
+    let error = UnfitPolicy::Error;
+
+    assert_eq!(anchored_offset(1080, 100, 30, Anchor::Start, error), Placement::Fits(30));
+    assert_eq!(anchored_offset(1080, 100, 30, Anchor::Center, error), Placement::Fits(490));
+    assert_eq!(anchored_offset(1080, 100, 30, Anchor::End, error), Placement::Fits(950));
+    assert_eq!(anchored_offset(160, 100, 30, Anchor::End, error), Placement::Fits(30));
+    assert_eq!(anchored_offset(159, 100, 30, Anchor::End, error), Placement::Unfit);
+    assert_eq!(
+        anchored_offset(159, 100, 30, Anchor::End, UnfitPolicy::Center),
+        Placement::Fallback(29),
+    );
+}
+
+#[test]
+fn test_scaled_offset() {
+    assert_eq!(scaled_offset(1920, 1280, 0), 0);
+    assert_eq!(scaled_offset(1920, 1280, 1), 1);
+    assert_eq!(scaled_offset(1920, 1280, 100), 67);
+    assert_eq!(scaled_offset(1080, 720, 900), 600);
+    assert_eq!(scaled_offset(1920, 1280, 1920), 1280);
+    assert_eq!(scaled_offset(720, 1080, 600), 900);
+    assert_eq!(
+        Reframe::Scale.offset(1080, 720, 60, 900, 30, Anchor::Center, UnfitPolicy::Error),
+        Placement::Fits(600),
+    );
+}
+
+#[test]
+fn test_scaled_size_stays_on_screen() {
+    for offset in 0..1920 {
+        for &size in [1, 2, 3, 7, 100, 1919].iter() {
+            if offset + size <= 1920 {
+                assert!(
+                    scaled_offset(1920, 1280, offset) + scaled_size(1920, 1280, size, offset)
+                        <= 1280
+                );
+            }
+        }
+    }
+    assert_eq!(scaled_size(1920, 1280, 1920, 0), 1280);
+    assert_eq!(scaled_size(1920, 1280, 3, 1), 2);
+}
+
+#[test]
+fn test_overflows_window() {
+
+    let window = Window { x: 100, y: 600, width: 400, height: 40 };
+    let mut composition_object = CompositionObject { x: 100, y: 600, crop: None, forced: false };
+
+    assert!(!overflows_window(&window, &composition_object, 400, 40));
+    assert!(overflows_window(&window, &composition_object, 401, 40));
+    assert!(overflows_window(&window, &composition_object, 400, 41));
+
+    composition_object.crop = Some(Crop { x: 0, y: 0, width: 400, height: 40 });
+
+    assert!(!overflows_window(&window, &composition_object, 600, 60));
+
+    composition_object.x = 99;
+
+    assert!(overflows_window(&window, &composition_object, 400, 40));
+}
+
+#[test]
+fn test_cropped_object_keeps_its_place_in_the_picture() {
+
+    // A tall object of which only a 100 line strip, shown at line 1000, is visible.
+    let mut composition_object = CompositionObject {
+        x: 0,
+        y: 1000,
+        crop: Some(Crop { x: 0, y: 1100, width: 1920, height: 100 }),
+        forced: false,
+    };
+    let (width, height) = shown_size(&composition_object, 1920, 1200);
+    let y = Reframe::Crop.offset(
+        1440,
+        1080,
+        height,
+        composition_object.y,
+        30,
+        Anchor::Center,
+        UnfitPolicy::Error,
+    );
+
+    assert_eq!((width, height), (1920, 100));
+    assert_eq!(y, Placement::Fits(820));
+    assert_eq!(centered_uncrop(1080, 1440, y.offset().unwrap()), composition_object.y);
+
+    composition_object.y = y.offset().unwrap();
+    clamp_crop(composition_object.crop.as_mut().unwrap(), 0, 820, 1920, 1080);
+
+    assert_eq!(
+        composition_object.crop,
+        Some(Crop { x: 0, y: 1100, width: 1920, height: 100 }),
+    );
+}
+
+#[test]
+fn test_clamp_crop() {
+
+    let mut crop = Crop { x: 10, y: 20, width: 400, height: 100 };
+
+    clamp_crop(&mut crop, 1700, 1000, 1920, 1080);
+
+    assert_eq!(crop, Crop { x: 10, y: 20, width: 220, height: 80 });
+
+    clamp_crop(&mut crop, 2000, 1000, 1920, 1080);
+
+    assert_eq!(crop.width, 0);
+}
+
+#[test]
+fn test_object_size() {
+
+    let object = |width, height| Object { width, height, ..Default::default() };
+    let mut objects = BTreeMap::new();
+    let mut state = EpochState::default();
+
+    state.objects.insert(ObjectId(0), object(30, 10));
+    objects.insert(VersionedId { id: ObjectId(1), version: 0 }, object(20, 40));
+    objects.insert(VersionedId { id: ObjectId(1), version: 1 }, object(50, 5));
+
+    assert_eq!(object_size(&objects, &state, ObjectId(1)), Some((50, 40)));
+    assert_eq!(object_size(&objects, &state, ObjectId(0)), Some((30, 10)));
+    assert_eq!(object_size(&objects, &state, ObjectId(2)), None);
+}
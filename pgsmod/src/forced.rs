@@ -0,0 +1,63 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::offset::carry_definitions;
+use pgs::displayset::DisplaySet;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ForcedFilter {
+    // Keeps only forced composition objects, as for a track that is always on.
+    Only,
+    // Removes forced composition objects, as for a track shown alongside a forced one.
+    Strip,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Filtered {
+    pub removed: usize,
+    pub dropped: usize,
+}
+
+// Removes the composition objects that the filter doesn't keep. Once something has been removed,
+// display sets left with nothing to show while nothing is showing anyway are dropped, and
+// whatever they defined is carried forward to the next display set kept. What remains shows
+// only what was kept, along with the clears that take it away again.
+pub fn filter_forced(epoch: &mut Vec<DisplaySet>, filter: ForcedFilter) -> Filtered {
+
+    let mut filtered = Filtered::default();
+    let mut kept = Vec::with_capacity(epoch.len());
+    let mut dropped = Vec::<DisplaySet>::new();
+    let mut showing = false;
+
+    for mut display_set in epoch.drain(..) {
+
+        let before = display_set.composition.objects.len();
+
+        display_set.composition.objects.retain(|_, co| co.forced == (filter == ForcedFilter::Only));
+        filtered.removed += before - display_set.composition.objects.len();
+
+        let empty = display_set.composition.objects.is_empty();
+
+        if empty && !showing && filtered.removed > 0 {
+            dropped.push(display_set);
+            continue
+        }
+
+        carry_definitions(&dropped, &mut display_set);
+        filtered.dropped += dropped.len();
+        dropped.clear();
+        showing = !empty;
+        kept.push(display_set);
+    }
+
+    filtered.dropped += dropped.len();
+    *epoch = kept;
+
+    filtered
+}
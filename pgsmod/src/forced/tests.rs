@@ -0,0 +1,128 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::{Cid, CompositionObject, Object, Palette, Window},
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    segment::CompositionState,
+};
+
+fn show(display_set: &mut DisplaySet, object_id: u16, forced: bool) {
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(object_id), window_id: WindowId(object_id as u8) },
+        CompositionObject { x: 0, y: object_id * 10, crop: None, forced },
+    );
+}
+
+// An epoch start at one second that defines everything and shows dialogue, a sign translation at
+// two seconds, and then a clear at three seconds.
+fn epoch() -> Vec<DisplaySet> {
+
+    let mut start = DisplaySet { pts: 90_000, ..Default::default() };
+
+    start.composition.state = CompositionState::EpochStart;
+    start.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, Palette::default());
+    for id in 0..2 {
+        start.windows.insert(WindowId(id as u8), Window { x: 0, y: id * 10, width: 2, height: 1 });
+        start.objects.insert(
+            VersionedId { id: ObjectId(id), version: 0 },
+            Object { width: 2, height: 1, lines: vec![vec![1, 1]], ..Default::default() },
+        );
+    }
+    show(&mut start, 0, false);
+
+    let mut sign = DisplaySet { pts: 180_000, ..Default::default() };
+
+    sign.composition.number = 1;
+    show(&mut sign, 1, true);
+
+    let mut clear = DisplaySet { pts: 270_000, ..Default::default() };
+
+    clear.composition.number = 2;
+
+    vec![start, sign, clear]
+}
+
+fn pts(epoch: &[DisplaySet]) -> Vec<u32> {
+    epoch.iter().map(|display_set| display_set.pts).collect()
+}
+
+#[test]
+fn test_only_forced() {
+
+    let mut epoch = epoch();
+
+    assert_eq!(
+        filter_forced(&mut epoch, ForcedFilter::Only),
+        Filtered { removed: 1, dropped: 1 },
+    );
+    assert_eq!(pts(&epoch), vec![180_000, 270_000]);
+    assert_eq!(epoch[0].composition.state, CompositionState::EpochStart);
+    assert_eq!(epoch[0].windows.len(), 2);
+    assert_eq!(epoch[0].objects.len(), 2);
+    assert_eq!(epoch[0].palettes.len(), 1);
+    assert!(epoch[1].composition.objects.is_empty());
+}
+
+#[test]
+fn test_strip_forced() {
+
+    let mut epoch = epoch();
+
+    assert_eq!(
+        filter_forced(&mut epoch, ForcedFilter::Strip),
+        Filtered { removed: 1, dropped: 1 },
+    );
+    assert_eq!(pts(&epoch), vec![90_000, 180_000]);
+    // The sign's display set now clears the dialogue.
+    assert!(epoch[1].composition.objects.is_empty());
+}
+
+#[test]
+fn test_mixed_display_set_keeps_forced_objects() {
+
+    let mut epoch = epoch();
+
+    show(&mut epoch[0], 1, true);
+
+    assert_eq!(
+        filter_forced(&mut epoch, ForcedFilter::Only),
+        Filtered { removed: 1, dropped: 0 },
+    );
+    assert_eq!(pts(&epoch), vec![90_000, 180_000, 270_000]);
+    assert_eq!(
+        epoch[0].composition.objects.keys().collect::<Vec<&Cid>>(),
+        vec![&Cid { object_id: ObjectId(1), window_id: WindowId(1) }],
+    );
+}
+
+#[test]
+fn test_nothing_to_filter_changes_nothing() {
+
+    let mut epoch = epoch();
+
+    epoch[1].composition.objects.clear();
+
+    let expected = epoch.clone();
+
+    assert_eq!(filter_forced(&mut epoch, ForcedFilter::Strip), Filtered::default());
+    assert_eq!(epoch, expected);
+}
+
+#[test]
+fn test_only_forced_drops_unforced_epoch() {
+
+    let mut epoch = epoch();
+
+    epoch[1].composition.objects.clear();
+
+    assert_eq!(
+        filter_forced(&mut epoch, ForcedFilter::Only),
+        Filtered { removed: 1, dropped: 3 },
+    );
+    assert!(epoch.is_empty());
+}
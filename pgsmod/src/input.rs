@@ -0,0 +1,67 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::ts::{TsReader, TsWarning};
+use std::io::{Read, Result as IoResult};
+
+// Where display sets are read from: a bare PGS stream, or one PID of an MPEG transport stream.
+pub enum Input<R: Read> {
+    Sup(R),
+    Ts(TsReader<R>),
+}
+
+impl<R: Read> Input<R> {
+
+    pub fn new(inner: R, pid: Option<u16>) -> Self {
+        match pid {
+            Some(pid) => Input::Ts(TsReader::new(inner, pid)),
+            None => Input::Sup(inner),
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        match self {
+            Input::Sup(inner) => inner,
+            Input::Ts(reader) => reader.get_ref(),
+        }
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<TsWarning> {
+        match self {
+            Input::Sup(_) => Vec::new(),
+            Input::Ts(reader) => reader.take_warnings(),
+        }
+    }
+}
+
+impl<R: Read> Read for Input<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        match self {
+            Input::Sup(inner) => inner.read(buf),
+            Input::Ts(reader) => reader.read(buf),
+        }
+    }
+}
+
+// Takes hexadecimal with a leading 0x, as PIDs are usually written, or decimal.
+pub fn parse_pid(value: &str) -> Option<u16> {
+
+    let pid = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok()?,
+        None => value.parse::<u16>().ok()?,
+    };
+
+    // PIDs are 13 bits wide.
+    if pid < 0x2000 {
+        Some(pid)
+    } else {
+        None
+    }
+}
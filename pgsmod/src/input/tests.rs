@@ -0,0 +1,32 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+#[test]
+fn test_parse_pid() {
+    assert_eq!(parse_pid("0x1200"), Some(0x1200));
+    assert_eq!(parse_pid("0X1a00"), Some(0x1A00));
+    assert_eq!(parse_pid("4608"), Some(0x1200));
+    assert_eq!(parse_pid("0x1FFF"), Some(0x1FFF));
+    assert_eq!(parse_pid("0x2000"), None);
+    assert_eq!(parse_pid("8192"), None);
+    assert_eq!(parse_pid("0x"), None);
+    assert_eq!(parse_pid("1200h"), None);
+    assert_eq!(parse_pid("-1"), None);
+}
+
+#[test]
+fn test_sup_input_passes_through() {
+
+    let mut input = Input::new(&[1u8, 2, 3][..], None);
+    let mut bytes = vec![];
+
+    input.read_to_end(&mut bytes).unwrap();
+
+    assert_eq!(bytes, [1, 2, 3]);
+    assert!(input.take_warnings().is_empty());
+}
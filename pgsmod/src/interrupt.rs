@@ -0,0 +1,38 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// The process exit code used when a run stops early because of Ctrl-C.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Makes the first Ctrl-C request a cooperative stop; a second one terminates immediately.
+#[cfg(unix)]
+pub fn install() {
+
+    extern "C" fn handle(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+
+    unsafe {
+        libc::signal(libc::SIGINT, handle as *const () as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
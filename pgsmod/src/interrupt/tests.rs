@@ -0,0 +1,22 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+#[cfg(unix)]
+#[test]
+fn test_sigint_requests_stop() {
+
+    install();
+
+    assert!(!interrupted());
+
+    unsafe {
+        libc::raise(libc::SIGINT);
+    }
+
+    assert!(interrupted());
+}
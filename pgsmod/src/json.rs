@@ -0,0 +1,565 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::{
+    id::{ObjectId, PaletteId, WindowId},
+    segment::{
+        CompositionObject,
+        CompositionState,
+        Crop,
+        EndSegment,
+        FrameRate,
+        ObjectDefinitionSegment,
+        ObjectHeader,
+        PaletteDefinitionSegment,
+        PaletteEntry,
+        PresentationCompositionSegment,
+        Raw,
+        Segment,
+        Sequence,
+        UnknownSegment,
+        WindowDefinition,
+        WindowDefinitionSegment,
+    },
+};
+use std::{
+    convert::TryFrom,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonError {
+    // A byte offset into the text, and what was wrong there.
+    Syntax(usize, &'static str),
+    // The index of the segment, and what was wrong with it.
+    Segment(usize, String),
+}
+
+impl Display for JsonError {
+
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            JsonError::Syntax(offset, message) => write!(f, "{} at byte {}", message, offset),
+            JsonError::Segment(index, message) => write!(f, "segment {}: {}", index, message),
+        }
+    }
+}
+
+// Every field of the segment on one line, with object data in base64.
+pub fn segment_json(segment: &Segment) -> String {
+    match segment {
+        Segment::PresentationComposition(pcs) => {
+
+            let composition_objects = pcs.composition_objects.iter()
+                .map(|co| format!(
+                    "{{\"object_id\":{},\"window_id\":{},\"x\":{},\"y\":{},\"crop\":{},\
+                    \"forced\":{}}}",
+                    co.object_id,
+                    co.window_id,
+                    co.x,
+                    co.y,
+                    match &co.crop {
+                        Some(crop) => format!(
+                            "{{\"x\":{},\"y\":{},\"width\":{},\"height\":{}}}",
+                            crop.x, crop.y, crop.width, crop.height,
+                        ),
+                        None => "null".to_string(),
+                    },
+                    co.forced,
+                ))
+                .collect::<Vec<String>>();
+
+            format!(
+                "{{\"kind\":\"pcs\",\"pts\":{},\"dts\":{},\"width\":{},\"height\":{},\
+                \"frame_rate\":{},\"composition_number\":{},\"composition_state\":\"{}\",\
+                \"palette_update_id\":{},\"composition_objects\":[{}]}}",
+                pcs.pts,
+                pcs.dts,
+                pcs.width,
+                pcs.height,
+                pcs.frame_rate.code(),
+                pcs.composition_number,
+                match pcs.composition_state {
+                    CompositionState::EpochStart => "epoch_start",
+                    CompositionState::AcquisitionPoint => "acquisition_point",
+                    CompositionState::Normal => "normal",
+                },
+                pcs.palette_update_id.map_or("null".to_string(), |id| id.to_string()),
+                composition_objects.join(","),
+            )
+        }
+        Segment::WindowDefinition(wds) => {
+
+            let windows = wds.windows.iter()
+                .map(|window| format!(
+                    "{{\"id\":{},\"x\":{},\"y\":{},\"width\":{},\"height\":{}}}",
+                    window.id, window.x, window.y, window.width, window.height,
+                ))
+                .collect::<Vec<String>>();
+
+            format!(
+                "{{\"kind\":\"wds\",\"pts\":{},\"dts\":{},\"windows\":[{}]}}",
+                wds.pts, wds.dts, windows.join(","),
+            )
+        }
+        Segment::PaletteDefinition(pds) => {
+
+            let entries = pds.entries.iter()
+                .map(|entry| format!(
+                    "{{\"id\":{},\"y\":{},\"cr\":{},\"cb\":{},\"alpha\":{}}}",
+                    entry.id, entry.y, entry.cr, entry.cb, entry.alpha,
+                ))
+                .collect::<Vec<String>>();
+
+            format!(
+                "{{\"kind\":\"pds\",\"pts\":{},\"dts\":{},\"id\":{},\"version\":{},\
+                \"entries\":[{}]}}",
+                pds.pts, pds.dts, pds.id, pds.version, entries.join(","),
+            )
+        }
+        Segment::ObjectDefinition(ods) => {
+            format!(
+                "{{\"kind\":\"ods\",\"pts\":{},\"dts\":{},\"id\":{},\"version\":{},\
+                \"sequence\":\"{}\",\"header\":{},\"data\":\"{}\"}}",
+                ods.pts,
+                ods.dts,
+                ods.id,
+                ods.version,
+                match ods.sequence {
+                    Sequence::Single => "single",
+                    Sequence::First => "first",
+                    Sequence::Middle => "middle",
+                    Sequence::Last => "last",
+                },
+                match &ods.header {
+                    Some(header) => format!(
+                        "{{\"data_length\":{},\"width\":{},\"height\":{}}}",
+                        header.data_length, header.width, header.height,
+                    ),
+                    None => "null".to_string(),
+                },
+                base64_encode(&ods.data),
+            )
+        }
+        Segment::End(es) => {
+            format!("{{\"kind\":\"end\",\"pts\":{},\"dts\":{}}}", es.pts, es.dts)
+        }
+        Segment::Unknown(us) => {
+            format!(
+                "{{\"kind\":\"unknown\",\"pts\":{},\"dts\":{},\"code\":{},\"data\":\"{}\"}}",
+                us.pts, us.dts, us.kind, base64_encode(&us.data),
+            )
+        }
+    }
+}
+
+// Reads back a JSON array of segments as written by segment_json.
+pub fn parse_segments_json(text: &str) -> Result<Vec<Segment>, JsonError> {
+
+    let mut parser = Parser { text: text.as_bytes(), position: 0 };
+    let value = parser.value()?;
+
+    parser.whitespace();
+    if parser.position != parser.text.len() {
+        return Err(JsonError::Syntax(parser.position, "unexpected text after the array"))
+    }
+
+    match value {
+        Value::Array(values) => values.iter()
+            .enumerate()
+            .map(|(index, value)| {
+                segment(value).map_err(|message| JsonError::Segment(index, message))
+            })
+            .collect(),
+        _ => Err(JsonError::Syntax(0, "expected an array of segments")),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Null,
+    Boolean(bool),
+    Number(u64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+
+    fn field(&self, name: &str) -> Result<&Value, String> {
+        match self {
+            Value::Object(fields) => fields.iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| format!("missing {}", name)),
+            _ => Err("expected an object".to_string()),
+        }
+    }
+
+    fn number<T: TryFrom<u64>>(&self, name: &str) -> Result<T, String> {
+        match self.field(name)? {
+            Value::Number(number) => {
+                T::try_from(*number).map_err(|_| format!("{} is out of range", name))
+            }
+            _ => Err(format!("{} must be a number", name)),
+        }
+    }
+
+    fn optional_number<T: TryFrom<u64>>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.field(name)? {
+            Value::Null => Ok(None),
+            _ => self.number(name).map(Some),
+        }
+    }
+
+    // Older exports have no such field, which reads as false.
+    fn optional_boolean(&self, name: &str) -> Result<bool, String> {
+        match self.field(name) {
+            Ok(Value::Boolean(boolean)) => Ok(*boolean),
+            Ok(_) => Err(format!("{} must be true or false", name)),
+            Err(_) => Ok(false),
+        }
+    }
+
+    fn string(&self, name: &str) -> Result<&str, String> {
+        match self.field(name)? {
+            Value::String(string) => Ok(string),
+            _ => Err(format!("{} must be a string", name)),
+        }
+    }
+
+    fn array(&self, name: &str) -> Result<&[Value], String> {
+        match self.field(name)? {
+            Value::Array(values) => Ok(values),
+            _ => Err(format!("{} must be an array", name)),
+        }
+    }
+}
+
+fn segment(value: &Value) -> Result<Segment, String> {
+
+    let pts = value.number("pts")?;
+    let dts = value.number("dts")?;
+
+    match value.string("kind")? {
+        "pcs" => Ok(Segment::PresentationComposition(PresentationCompositionSegment {
+            pts,
+            dts,
+            width: value.number("width")?,
+            height: value.number("height")?,
+            frame_rate: FrameRate::from_code(value.number("frame_rate")?),
+            composition_number: value.number("composition_number")?,
+            composition_state: match value.string("composition_state")? {
+                "epoch_start" => CompositionState::EpochStart,
+                "acquisition_point" => CompositionState::AcquisitionPoint,
+                "normal" => CompositionState::Normal,
+                other => return Err(format!("unknown composition state: {}", other)),
+            },
+            palette_update_id: value.optional_number("palette_update_id")?.map(PaletteId),
+            composition_objects: value.array("composition_objects")?.iter()
+                .map(|co| Ok(CompositionObject {
+                    object_id: ObjectId(co.number("object_id")?),
+                    window_id: WindowId(co.number("window_id")?),
+                    x: co.number("x")?,
+                    y: co.number("y")?,
+                    crop: match co.field("crop")? {
+                        Value::Null => None,
+                        crop => Some(Crop {
+                            x: crop.number("x")?,
+                            y: crop.number("y")?,
+                            width: crop.number("width")?,
+                            height: crop.number("height")?,
+                        }),
+                    },
+                    forced: co.optional_boolean("forced")?,
+                }))
+                .collect::<Result<Vec<CompositionObject>, String>>()?,
+            raw: Raw::default(),
+        })),
+        "wds" => Ok(Segment::WindowDefinition(WindowDefinitionSegment {
+            pts,
+            dts,
+            windows: value.array("windows")?.iter()
+                .map(|window| Ok(WindowDefinition {
+                    id: WindowId(window.number("id")?),
+                    x: window.number("x")?,
+                    y: window.number("y")?,
+                    width: window.number("width")?,
+                    height: window.number("height")?,
+                }))
+                .collect::<Result<Vec<WindowDefinition>, String>>()?,
+            raw: Raw::default(),
+        })),
+        "pds" => Ok(Segment::PaletteDefinition(PaletteDefinitionSegment {
+            pts,
+            dts,
+            id: PaletteId(value.number("id")?),
+            version: value.number("version")?,
+            entries: value.array("entries")?.iter()
+                .map(|entry| Ok(PaletteEntry {
+                    id: entry.number("id")?,
+                    y: entry.number("y")?,
+                    cr: entry.number("cr")?,
+                    cb: entry.number("cb")?,
+                    alpha: entry.number("alpha")?,
+                }))
+                .collect::<Result<Vec<PaletteEntry>, String>>()?,
+            raw: Raw::default(),
+        })),
+        "ods" => Ok(Segment::ObjectDefinition(ObjectDefinitionSegment {
+            pts,
+            dts,
+            id: ObjectId(value.number("id")?),
+            version: value.number("version")?,
+            sequence: match value.string("sequence")? {
+                "single" => Sequence::Single,
+                "first" => Sequence::First,
+                "middle" => Sequence::Middle,
+                "last" => Sequence::Last,
+                other => return Err(format!("unknown sequence: {}", other)),
+            },
+            header: match value.field("header")? {
+                Value::Null => None,
+                header => Some(ObjectHeader {
+                    data_length: header.number("data_length")?,
+                    width: header.number("width")?,
+                    height: header.number("height")?,
+                }),
+            },
+            data: base64_decode(value.string("data")?).ok_or("data is not valid base64")?,
+            raw: Raw::default(),
+        })),
+        "end" => Ok(Segment::End(EndSegment { pts, dts, raw: Raw::default() })),
+        "unknown" => Ok(Segment::Unknown(UnknownSegment {
+            pts,
+            dts,
+            kind: value.number("code")?,
+            data: base64_decode(value.string("data")?).ok_or("data is not valid base64")?,
+            raw: Raw::default(),
+        })),
+        other => Err(format!("unknown kind: {}", other)),
+    }
+}
+
+// Just enough JSON for what segment_json writes: objects, arrays, strings, null, and whole
+// non-negative numbers.
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+
+    fn whitespace(&mut self) {
+        while self.text.get(self.position).is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8, message: &'static str) -> Result<(), JsonError> {
+
+        self.whitespace();
+
+        if self.text.get(self.position) != Some(&byte) {
+            return Err(JsonError::Syntax(self.position, message))
+        }
+
+        self.position += 1;
+
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+
+        self.whitespace();
+
+        match self.text.get(self.position) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'n') if self.text[self.position..].starts_with(b"null") => {
+                self.position += 4;
+                Ok(Value::Null)
+            }
+            Some(b't') if self.text[self.position..].starts_with(b"true") => {
+                self.position += 4;
+                Ok(Value::Boolean(true))
+            }
+            Some(b'f') if self.text[self.position..].starts_with(b"false") => {
+                self.position += 5;
+                Ok(Value::Boolean(false))
+            }
+            Some(byte) if byte.is_ascii_digit() => self.number(),
+            Some(_) => Err(JsonError::Syntax(self.position, "unexpected character")),
+            None => Err(JsonError::Syntax(self.position, "unexpected end of text")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, JsonError> {
+
+        let mut fields = Vec::new();
+
+        self.expect(b'{', "expected {")?;
+        self.whitespace();
+
+        if self.text.get(self.position) == Some(&b'}') {
+            self.position += 1;
+            return Ok(Value::Object(fields))
+        }
+
+        loop {
+
+            self.whitespace();
+
+            let key = self.string()?;
+
+            self.expect(b':', "expected :")?;
+            fields.push((key, self.value()?));
+            self.whitespace();
+
+            match self.text.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Value::Object(fields))
+                }
+                _ => return Err(JsonError::Syntax(self.position, "expected , or }")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, JsonError> {
+
+        let mut values = Vec::new();
+
+        self.expect(b'[', "expected [")?;
+        self.whitespace();
+
+        if self.text.get(self.position) == Some(&b']') {
+            self.position += 1;
+            return Ok(Value::Array(values))
+        }
+
+        loop {
+
+            values.push(self.value()?);
+            self.whitespace();
+
+            match self.text.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Value::Array(values))
+                }
+                _ => return Err(JsonError::Syntax(self.position, "expected , or ]")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+
+        self.expect(b'"', "expected a string")?;
+
+        let start = self.position;
+
+        loop {
+            match self.text.get(self.position) {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    return Err(JsonError::Syntax(self.position, "escapes are not supported"))
+                }
+                Some(_) => self.position += 1,
+                None => return Err(JsonError::Syntax(start, "unterminated string")),
+            }
+        }
+
+        let string = String::from_utf8(self.text[start..self.position].to_vec())
+            .map_err(|_| JsonError::Syntax(start, "string is not UTF-8"))?;
+
+        self.position += 1;
+
+        Ok(string)
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+
+        let start = self.position;
+        let mut number = 0u64;
+
+        while let Some(&byte) = self.text.get(self.position).filter(|byte| byte.is_ascii_digit()) {
+            number = number.checked_mul(10)
+                .and_then(|number| number.checked_add((byte - b'0') as u64))
+                .ok_or(JsonError::Syntax(start, "number is too large"))?;
+            self.position += 1;
+        }
+
+        if self.text.get(self.position).is_some_and(|&byte| byte == b'.' || byte == b'e') {
+            return Err(JsonError::Syntax(start, "numbers must be whole"))
+        }
+
+        Ok(Value::Number(number))
+    }
+}
+
+fn base64_encode(data: &[u8]) -> String {
+
+    let mut output = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+
+        let bits = chunk.iter()
+            .enumerate()
+            .fold(0u32, |bits, (index, &byte)| bits | (byte as u32) << (16 - 8 * index));
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                output.push(BASE64[(bits >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+
+    output
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+
+    let text = text.as_bytes();
+
+    if !text.len().is_multiple_of(4) {
+        return None
+    }
+
+    let mut output = Vec::with_capacity(text.len() / 4 * 3);
+
+    for (chunk_index, chunk) in text.chunks(4).enumerate() {
+
+        let last = chunk_index == text.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&byte| byte == b'=').count();
+
+        if padding > 2 || (padding > 0 && !last) {
+            return None
+        }
+
+        let mut bits = 0u32;
+
+        for &byte in chunk[..4 - padding].iter() {
+            bits = bits << 6 | BASE64.iter().position(|&symbol| symbol == byte)? as u32;
+        }
+        bits <<= 6 * padding;
+
+        output.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+
+    Some(output)
+}
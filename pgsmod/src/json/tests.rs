@@ -0,0 +1,204 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::{
+        Cid,
+        CompositionObject as DisplaySetCompositionObject,
+        DisplaySet,
+        Object,
+        Palette,
+        PaletteEntry as DisplaySetPaletteEntry,
+        Window,
+        WriteDisplaySetExt,
+    },
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    segment::{ReadSegmentExt, WriteSegmentExt},
+};
+use std::io::{Cursor, ErrorKind};
+
+// An epoch start with a cropped object too large for one segment, then a palette update.
+fn stream() -> Vec<u8> {
+
+    let mut shown = DisplaySet {
+        pts: 90_000,
+        dts: 85_000,
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+    let mut palette = Palette::default();
+    let mut output = vec![];
+
+    for index in 0..=255u8 {
+        palette.entries.insert(
+            index,
+            DisplaySetPaletteEntry { y: index, cr: 128, cb: 255 - index, alpha: 255 },
+        );
+    }
+
+    shown.composition.state = CompositionState::EpochStart;
+    shown.windows.insert(WindowId(0), Window { x: 100, y: 100, width: 300, height: 300 });
+    shown.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette.clone());
+    shown.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object {
+            width: 300,
+            height: 300,
+            lines: (0..300u32)
+                .map(|y| (0..300u32).map(|x| ((x * 7 + y * 13) % 255 + 1) as u8).collect())
+                .collect(),
+            ..Default::default()
+        },
+    );
+    shown.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        DisplaySetCompositionObject {
+            x: 100,
+            y: 100,
+            crop: Some(Crop { x: 0, y: 0, width: 300, height: 200 }),
+            forced: true,
+        },
+    );
+
+    let mut update = DisplaySet { pts: 180_000, ..shown.clone() };
+
+    update.composition.number = 1;
+    update.composition.state = CompositionState::Normal;
+    update.palette_update_id = Some(PaletteId(0));
+    update.objects.clear();
+    update.palettes.clear();
+    update.palettes.insert(VersionedId { id: PaletteId(0), version: 1 }, palette);
+
+    output.write_display_set(&shown).unwrap();
+    output.write_display_set(&update).unwrap();
+
+    output
+}
+
+fn read_segments(input: &[u8]) -> Vec<Segment> {
+
+    let mut input = Cursor::new(input);
+    let mut segments = vec![];
+
+    loop {
+        match input.read_segment() {
+            Ok(segment) => segments.push(segment),
+            Err(pgs::segment::ReadError::IoError { source })
+                if source.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    segments
+}
+
+fn to_json(segments: &[Segment]) -> String {
+    format!(
+        "[\n{}\n]\n",
+        segments.iter().map(segment_json).collect::<Vec<String>>().join(",\n"),
+    )
+}
+
+#[test]
+fn test_round_trip_is_byte_identical() {
+
+    let input = stream();
+    let segments = read_segments(&input);
+
+    assert!(segments.iter().any(|segment| matches!(
+        segment,
+        Segment::ObjectDefinition(ObjectDefinitionSegment { sequence: Sequence::Last, .. })
+    )));
+
+    let mut output = vec![];
+
+    for segment in parse_segments_json(&to_json(&segments)).unwrap().iter() {
+        output.write_segment(segment).unwrap();
+    }
+
+    assert_eq!(output, input);
+}
+
+#[test]
+fn test_edited_field() {
+
+    let json = to_json(&read_segments(&stream()))
+        .replacen("\"composition_number\":1", "\"composition_number\":9", 1);
+    let segments = parse_segments_json(&json).unwrap();
+
+    match &segments.iter().filter(|segment| matches!(segment, Segment::PresentationComposition(_)))
+        .nth(1) {
+        Some(Segment::PresentationComposition(pcs)) => {
+            assert_eq!(pcs.composition_number, 9);
+            assert_eq!(pcs.palette_update_id, Some(PaletteId(0)));
+        }
+        _ => panic!("no second PCS"),
+    }
+}
+
+#[test]
+fn test_segment_json() {
+    assert_eq!(
+        segment_json(&Segment::End(EndSegment { pts: 90_000, dts: 0, raw: Raw::default() })),
+        "{\"kind\":\"end\",\"pts\":90000,\"dts\":0}",
+    );
+}
+
+#[test]
+fn test_unknown_segment_json() {
+
+    let segment = Segment::Unknown(
+        UnknownSegment { pts: 90_000, dts: 0, kind: 0x99, data: vec![1, 2, 3], raw: Raw::default() }
+    );
+    let json = segment_json(&segment);
+
+    assert_eq!(
+        json,
+        "{\"kind\":\"unknown\",\"pts\":90000,\"dts\":0,\"code\":153,\"data\":\"AQID\"}",
+    );
+    assert_eq!(parse_segments_json(&format!("[{}]", json)).unwrap(), vec![segment]);
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(
+        parse_segments_json("[{\"kind\":\"end\",\"pts\":1}]"),
+        Err(JsonError::Segment(0, "missing dts".to_string())),
+    );
+    assert_eq!(
+        parse_segments_json("[{\"kind\":\"end\",\"pts\":1,\"dts\":4294967296}]"),
+        Err(JsonError::Segment(0, "dts is out of range".to_string())),
+    );
+    assert_eq!(
+        parse_segments_json("[{\"kind\":\"end\",\"pts\":1,\"dts\":0}"),
+        Err(JsonError::Syntax(31, "expected , or ]")),
+    );
+    assert_eq!(
+        parse_segments_json("[] x"),
+        Err(JsonError::Syntax(3, "unexpected text after the array")),
+    );
+    assert_eq!(
+        parse_segments_json("[{\"kind\":\"end\",\"pts\":1.5,\"dts\":0}]"),
+        Err(JsonError::Syntax(21, "numbers must be whole")),
+    );
+}
+
+#[test]
+fn test_base64() {
+
+    for data in [&b""[..], b"f", b"fo", b"foo", b"foob", b"fooba", b"foobar"].iter() {
+        assert_eq!(base64_decode(&base64_encode(data)).as_deref(), Some(*data));
+    }
+
+    assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+    assert_eq!(base64_encode(b"fooba"), "Zm9vYmE=");
+    assert_eq!(base64_encode(&[0xFF, 0xFE]), "//4=");
+    assert_eq!(base64_decode("Zm9v!mFy"), None);
+    assert_eq!(base64_decode("Zm9"), None);
+    assert_eq!(base64_decode("Zg==Zg=="), None);
+}
@@ -0,0 +1,93 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::identity::{Identity, Pass, SegmentDiff};
+use std::collections::BTreeSet;
+
+// The process exit code used when the input cannot be reproduced exactly, unless forced.
+pub const EXIT_LOSSY: i32 = 2;
+
+// How many differences are listed before the rest are only counted.
+const MAX_LISTED: usize = 20;
+
+// How many bytes of each side of a difference are shown.
+const MAX_BYTES: usize = 16;
+
+pub fn hex(bytes: &[u8]) -> String {
+
+    if bytes.is_empty() {
+        return "(nothing)".to_string()
+    }
+
+    let mut hex = bytes.iter()
+        .take(MAX_BYTES)
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    if bytes.len() > MAX_BYTES {
+        hex.push_str(&format!(" ... ({} bytes)", bytes.len()));
+    }
+
+    hex
+}
+
+pub fn describe(diff: &SegmentDiff) -> String {
+
+    let written = match diff.pass {
+        Pass::Segment => "alone",
+        Pass::DisplaySet => "with its display set",
+    };
+    let kind = diff.kind.map_or("unknown".to_string(), |kind| kind.to_string());
+
+    // A segment that is missing from one side or the other.
+    if diff.field == "segment" && diff.rewritten.is_empty() {
+        return format!("Segment {} ({}) is dropped when written {}", diff.index, kind, written)
+    }
+    if diff.field == "segment" && diff.original.is_empty() {
+        return format!("Segment {} ({}) is added when written {}", diff.index, kind, written)
+    }
+
+    format!(
+        "Segment {} ({}) {} differs when written {}: {} became {}",
+        diff.index,
+        kind,
+        diff.field,
+        written,
+        hex(&diff.original),
+        hex(&diff.rewritten),
+    )
+}
+
+// The lines printed for an input that cannot be reproduced exactly.
+pub fn identity_report(identity: &Identity) -> Vec<String> {
+
+    let kinds = identity.kinds().into_iter()
+        .map(|kind| kind.map_or("unknown".to_string(), |kind| kind.to_string()))
+        .collect::<Vec<String>>();
+    let segments = identity.diffs.iter()
+        .map(|diff| diff.index)
+        .collect::<BTreeSet<usize>>();
+    let mut lines = vec![format!(
+        "Could not reproduce {} of {} segments exactly, of kinds {}:",
+        segments.len(),
+        identity.segments,
+        kinds.join(", "),
+    )];
+
+    lines.extend(
+        identity.diffs.iter().take(MAX_LISTED).map(|diff| format!("  {}", describe(diff)))
+    );
+
+    if identity.diffs.len() > MAX_LISTED {
+        lines.push(format!("  ...and {} more differences.", identity.diffs.len() - MAX_LISTED));
+    }
+
+    lines
+}
@@ -0,0 +1,63 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::segment::SegmentKind;
+
+fn diff(index: usize, kind: Option<SegmentKind>, field: &'static str) -> SegmentDiff {
+    SegmentDiff {
+        pass: Pass::DisplaySet,
+        index,
+        kind,
+        field,
+        original: vec![1, 1, 1, 1, 0, 0],
+        rewritten: vec![0, 0x84, 1, 0, 0],
+    }
+}
+
+#[test]
+fn test_hex() {
+    assert_eq!(hex(&[]), "(nothing)");
+    assert_eq!(hex(&[0x50, 0x47, 0x0A]), "50 47 0A");
+    assert_eq!(hex(&[0xFF; 17]), format!("{} ... (17 bytes)", vec!["FF"; 16].join(" ")));
+}
+
+#[test]
+fn test_describe() {
+    assert_eq!(
+        describe(&diff(3, Some(SegmentKind::ObjectDefinition), "object data")),
+        "Segment 3 (ODS) object data differs when written with its display set: \
+        01 01 01 01 00 00 became 00 84 01 00 00",
+    );
+    assert_eq!(
+        describe(&SegmentDiff { pass: Pass::Segment, ..diff(9, None, "palette entries") }),
+        "Segment 9 (unknown) palette entries differs when written alone: \
+        01 01 01 01 00 00 became 00 84 01 00 00",
+    );
+    assert_eq!(
+        describe(&SegmentDiff { rewritten: vec![], ..diff(5, Some(SegmentKind::End), "segment") }),
+        "Segment 5 (END) is dropped when written with its display set",
+    );
+}
+
+#[test]
+fn test_identity_report() {
+
+    let identity = Identity {
+        segments: 40,
+        display_sets: 8,
+        diffs: (0..25)
+            .map(|index| diff(index / 2, Some(SegmentKind::ObjectDefinition), "object data"))
+            .chain(Some(diff(30, Some(SegmentKind::End), "segment")))
+            .collect(),
+    };
+    let lines = identity_report(&identity);
+
+    assert_eq!(lines.len(), 22);
+    assert_eq!(lines[0], "Could not reproduce 14 of 40 segments exactly, of kinds ODS, END:");
+    assert!(lines[1].starts_with("  Segment 0 (ODS) object data differs"));
+    assert_eq!(lines[21], "  ...and 6 more differences.");
+}
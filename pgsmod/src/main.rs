@@ -4,25 +4,144 @@
  * SPDX-License-Identifier: OSL-3.0
  */
 
-mod rgb;
+mod analysis;
+mod bdn;
+mod capabilities;
+mod concat;
+mod contact;
+mod continuity;
+mod crop;
+mod forced;
+mod input;
+mod interrupt;
+mod json;
+mod lossless;
+mod merge;
+mod offset;
+mod pacing;
+mod place;
+mod preview;
+mod quantize;
+mod report;
+mod resample;
+mod retime;
+mod sink;
+mod timings;
+mod trim;
 
 use pgs::{
+    ticks_to_timestamp,
     ts_to_timestamp,
+    cache::{Generations, ObjectCache},
+    check::windows_overlap,
+    color::{ColorMatrix, Range, ToneMap},
+    event::{event_ids, TimingAdjustment, TimingRules},
+    fade::smooth_fades,
+    style::Style,
     displayset::{
+        clear_display_set,
+        Cid,
+        dedup_display_sets,
+        DtsScheduler,
+        fix_missing_indices,
+        frame_duration,
+        prune_palettes,
+        share_objects,
+        SharedObjects,
+        Diagnostic,
+        DisplaySet,
+        EpochRepairer,
+        IndexFix,
+        LossyReader,
         ReadDisplaySetExt,
         ReadError as DisplaySetReadError,
+        ReadResult as DisplaySetReadResult,
+        ReadWarning,
+        Window,
         WriteDisplaySetExt,
     },
+    id::WindowId,
+    identity::check_identity,
+    png::read_png,
+    progress::{CountingReader, ProgressThrottle, ProgressUpdate},
     segment::{
+        CompositionState,
+        Limits,
         ReadError as SegmentReadError,
+        ReadOptions,
+        ReadSegmentExt,
+        WriteSegmentExt,
     },
+    timeline::{coverage_cached, sample_occupancy, EpochState, OccupancyMode},
+    timestamp::PtsUnwrapper,
 };
-use rgb::{rgb_pixel, ycbcr_pixel, YcbcrPixel};
+use analysis::{Analysis, EXIT_UNFIT};
+use bdn::{import_bdn, parse_bdn, FrameRate};
+use capabilities::capabilities_json;
+use concat::{concat, ConcatOptions};
+use continuity::fix_continuity;
+use crop::{
+    clamp_crop,
+    object_size,
+    overflows_window,
+    scaled_offset,
+    scaled_size,
+    shown_size,
+    Anchor,
+    CropAnchor,
+    Placement,
+    Reframe,
+    UnfitPolicy,
+};
+use input::{parse_pid, Input};
+use interrupt::EXIT_INTERRUPTED;
+use json::{parse_segments_json, segment_json};
+use lossless::{identity_report, EXIT_LOSSY};
+use merge::{merge_windows, MergeOutcome};
+use forced::{filter_forced, ForcedFilter};
+use offset::{offset_epoch, parse_offset, EarlyPolicy, Offset};
+use pacing::pace_epoch;
+use place::{is_sign, place_event, position_event, Position, Preset, BARS_ASPECT};
+use preview::{palette_preview, print_preview, PaletteTransforms, Selector};
+use report::{occupancy_json, report};
+use resample::{resample_display_set, Filter, Versions};
+use retime::{retime_epoch, Retime, RetimeMode};
+use sink::{
+    finish_sinks,
+    write_to_sinks,
+    BdnSink,
+    ContactSheetSink,
+    DisplaySetSink,
+    JsonSink,
+    PesSink,
+    PngSink,
+    SupSink,
+};
+use timings::Timings;
+use trim::{parse_trim_point, trim_epoch, Trim};
 use std::{
-    fs::File,
-    io::{stdin, stdout, BufReader, BufWriter, ErrorKind, Read, Write},
+    collections::BTreeMap,
+    fs::{create_dir_all, read_to_string, remove_file, rename, File},
+    iter::{from_fn, once},
+    io::{
+        stdin,
+        stdout,
+        BufReader,
+        BufWriter,
+        Cursor,
+        ErrorKind,
+        Read,
+        Result as IoResult,
+        Write,
+    },
+    path::{Path, PathBuf},
+    process::exit,
+    time::{Duration, Instant},
+};
+use clap::{
+    app_from_crate, crate_authors, crate_description, crate_name, crate_version, App, AppSettings,
+    Arg, ArgGroup, ArgMatches, SubCommand,
 };
-use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg};
 
 #[derive(Clone, Copy, PartialEq)]
 struct Size {
@@ -30,16 +149,795 @@ struct Size {
     height: u16,
 }
 
-fn main() {
+// How often to print a progress line while reading, whichever comes first.
+const PROGRESS_DISPLAY_SETS: usize = 10_000;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+// The transforms that need to see a whole epoch at once.
+struct EpochOptions {
+    forced: Option<ForcedFilter>,
+    trim: Option<Trim>,
+    dedup: bool,
+    retime: Option<Retime>,
+    offset: Option<Offset>,
+    pacing: Option<TimingRules>,
+    fix_indices: Option<IndexFix>,
+    prune: bool,
+    optimize: bool,
+    smooth_fps: Option<f64>,
+    fix_dts: bool,
+}
+
+#[derive(Default)]
+struct EpochTotals {
+    forced_removed: usize,
+    forced_dropped: usize,
+    trimmed: usize,
+    carried_in: usize,
+    cut_off: usize,
+    deduped: usize,
+    shortened: usize,
+    early: usize,
+    paced: usize,
+    unpaced: usize,
+    remapped: usize,
+    pruned: usize,
+    shared: SharedObjects,
+    interpolated: usize,
+    clears: usize,
+    dropped: usize,
+    next_composition_number: Option<u16>,
+    dts_scheduler: DtsScheduler,
+    timings: Timings,
+    analysis: Analysis,
+}
+
+struct Placer {
+    reframe: Reframe,
+    policy: UnfitPolicy,
+    margin: u16,
+    // Unwrapped; see PipelineState.
+    pts: u64,
+    kind: &'static str,
+    dry_run: bool,
+}
+
+impl Placer {
+
+    // Returns Unfit when the window or object should be dropped.
+    fn place(
+        &self,
+        axis: &str,
+        anchor: Anchor,
+        screen_size: u16,
+        screen_new_size: u16,
+        size: u16,
+        offset: u16,
+    ) -> Placement {
+
+        let placement = self.reframe.offset(
+            screen_size,
+            screen_new_size,
+            size,
+            offset,
+            self.margin,
+            anchor,
+            self.policy,
+        );
+        let describe = || format!(
+            "{} of {} pixels cannot fit {} within {} pixels and a {} pixel margin at {}",
+            self.kind, size, axis, screen_new_size, self.margin, ticks_to_timestamp(self.pts),
+        );
+
+        match placement {
+            Placement::Fits(_) => {
+            }
+            Placement::Clamped(offset) => {
+                eprintln!(
+                    "WARNING: {} at {} was moved {} to {} to keep a {} pixel margin.",
+                    self.kind, ticks_to_timestamp(self.pts), axis, offset, self.margin,
+                );
+            }
+            Placement::Fallback(offset) => {
+                eprintln!("WARNING: {}; placed at {}.", describe(), offset);
+            }
+            Placement::Unfit if self.policy == UnfitPolicy::Drop => {
+                eprintln!("WARNING: {}; dropped.", describe());
+            }
+            Placement::Unfit if self.dry_run => {
+                eprintln!("ERROR: {}.", describe());
+            }
+            Placement::Unfit => {
+                panic!("{}", describe())
+            }
+        }
+
+        placement
+    }
+}
+
+// Everything done to one display set at a time, shared by real and dry runs.
+struct Pipeline {
+    reframe: Reframe,
+    new_width: u16,
+    new_height: u16,
+    anchor: CropAnchor,
+    margin: u16,
+    on_resize: ResizePolicy,
+    on_unfit: UnfitPolicy,
+    place: Option<Preset>,
+    position: Option<Position>,
+    vertical_offset: i32,
+    bars_aspect: f64,
+    place_all: bool,
+    single_window: bool,
+    scale_filter: Option<Filter>,
+    hue: Option<f64>,
+    saturation: Option<f64>,
+    tint: Option<[u8; 3]>,
+    lum_scale: Option<f64>,
+    tone_map: Option<ToneMap>,
+    matrix: Option<ColorMatrix>,
+    range: Range,
+    drop_above: Option<f64>,
+    strict: bool,
+    dry_run: bool,
+}
+
+#[derive(Default)]
+struct PipelineState {
+    epoch_state: EpochState,
+    // Mirrors what the output's decoder will hold, as opposed to what the input's did.
+    output_state: EpochState,
+    versions: Versions,
+    // Follow the input's definitions for --drop-above. These are kept apart from the cache the
+    // sinks share because the pipeline changes objects after they are keyed here.
+    generations: Generations,
+    coverage_cache: ObjectCache,
+    // The PTS of the display set being processed, counted on past each wraparound of the clock so
+    // that messages give its place in the stream.
+    pts: u64,
+}
+
+impl Pipeline {
+
+    fn process(
+        &self,
+        display_set: &mut DisplaySet,
+        state: &mut PipelineState,
+        limits: &Limits,
+        totals: &mut EpochTotals,
+    ) {
+
+        let Pipeline {
+            reframe,
+            new_width,
+            new_height,
+            anchor,
+            margin,
+            on_resize,
+            on_unfit,
+            place,
+            position,
+            vertical_offset,
+            bars_aspect,
+            place_all,
+            single_window,
+            scale_filter,
+            hue,
+            saturation,
+            tint,
+            lum_scale,
+            tone_map,
+            matrix,
+            range,
+            drop_above,
+            strict,
+            dry_run,
+        } = *self;
+        let PipelineState {
+            epoch_state,
+            output_state,
+            versions,
+            generations,
+            coverage_cache,
+            pts,
+        } = state;
+        let pts = *pts;
+
+        let stage_start = Instant::now();
+
+        let full_width = display_set.width;
+        let full_height = display_set.height;
+        let screen_size = Size {
+            width: full_width,
+            height: full_height,
+        };
+
+        totals.analysis.display_sets += 1;
+
+        if totals.analysis.record_resolution(full_width, full_height) {
+            eprintln!(
+                "New resolution encountered: {}x{}",
+                screen_size.width, screen_size.height,
+            );
+        }
+
+        let resized_from = display_set.warnings.iter().find_map(|warning| match *warning {
+            ReadWarning::ResizedMidEpoch { width, height } => Some(Size { width, height }),
+            _ => None,
+        });
+
+        // Epoch repairs may already have started a new epoch here.
+        if display_set.composition.state != CompositionState::EpochStart {
+            if let Some(size) = resized_from {
+                if on_resize == ResizePolicy::Error {
+                    self.fail(
+                        format!(
+                            "resolution changed mid-epoch from {}x{} to {}x{} at {}",
+                            size.width, size.height,
+                            screen_size.width, screen_size.height,
+                            ticks_to_timestamp(pts),
+                        ),
+                        totals,
+                    );
+                } else {
+                    eprintln!(
+                        "WARNING: Resolution changed mid-epoch from {}x{} to {}x{} at {}.",
+                        size.width, size.height,
+                        screen_size.width, screen_size.height,
+                        ticks_to_timestamp(pts),
+                    );
+                }
+                if on_resize == ResizePolicy::SplitEpoch {
+                    display_set.composition.state = CompositionState::EpochStart;
+                }
+            }
+        }
+
+        if display_set.composition.state == CompositionState::EpochStart {
+            totals.analysis.epochs += 1;
+        }
+
+        totals.timings.record("resize-check", stage_start.elapsed());
+
+        let stage_start = Instant::now();
+
+        if let Err(limit) = epoch_state.apply_with(display_set, limits) {
+            panic!(
+                "Could not accept display set at {}: {}",
+                ticks_to_timestamp(pts), limit,
+            )
+        }
+
+        generations.apply(display_set);
+
+        if single_window && merge_windows(display_set, epoch_state)
+            == MergeOutcome::DroppedUpper {
+            eprintln!(
+                "WARNING: Windows too far apart to merge at {}; dropped the upper one.",
+                ticks_to_timestamp(pts),
+            );
+        }
+
+        totals.timings.record("single-window", stage_start.elapsed());
+
+        let stage_start = Instant::now();
+
+        if let Some(filter) = scale_filter.filter(|_| reframe == Reframe::Scale) {
+            resample_display_set(
+                display_set,
+                epoch_state,
+                versions,
+                (full_width, full_height),
+                (new_width, new_height),
+                filter,
+                matrix.unwrap_or_else(|| ColorMatrix::for_width(full_width)),
+                range,
+            );
+        }
+
+        totals.timings.record("resample", stage_start.elapsed());
+
+        let stage_start = Instant::now();
+
+        if reframe == Reframe::Uncrop
+            && (new_width < full_width || new_height < full_height) {
+            self.fail(
+                format!(
+                    "cannot uncrop {}x{} to the smaller {}x{} at {}",
+                    full_width, full_height, new_width, new_height,
+                    ticks_to_timestamp(pts),
+                ),
+                totals,
+            );
+        }
+
+        display_set.width = new_width;
+        display_set.height = new_height;
+
+        let (x_anchor, y_anchor) = anchor.anchors();
+
+        let mut unfit_objects = Vec::<Cid>::new();
+        let mut unfit_windows = Vec::<WindowId>::new();
+
+        for (cid, composition_object) in display_set.composition.objects.iter_mut() {
+
+            let (object_width, object_height) =
+                match object_size(&display_set.objects, epoch_state, cid.object_id) {
+                    Some(size) => size,
+                    None => continue,
+                };
+            let (shown_width, shown_height) =
+                shown_size(composition_object, object_width, object_height);
+
+            let placer = Placer {
+                reframe,
+                policy: on_unfit,
+                margin,
+                pts,
+                kind: "object",
+                dry_run,
+            };
+            let x = placer.place(
+                "horizontally",
+                x_anchor,
+                full_width,
+                new_width,
+                shown_width,
+                composition_object.x,
+            );
+            let y = placer.place(
+                "vertically",
+                y_anchor,
+                full_height,
+                new_height,
+                shown_height,
+                composition_object.y,
+            );
+
+            totals.analysis.objects.record(x, y);
+
+            match (x.offset(), y.offset()) {
+                (Some(x), Some(y)) => {
+                    if let (Reframe::Scale, Some(crop)) =
+                        (reframe, composition_object.crop.as_mut()) {
+                        crop.width = scaled_size(full_width, new_width, crop.width, crop.x);
+                        crop.height =
+                            scaled_size(full_height, new_height, crop.height, crop.y);
+                        crop.x = scaled_offset(full_width, new_width, crop.x);
+                        crop.y = scaled_offset(full_height, new_height, crop.y);
+                    }
+                    if let Some(crop) = composition_object.crop.as_mut() {
+                        clamp_crop(crop, x, y, new_width, new_height);
+                    }
+                    composition_object.x = x;
+                    composition_object.y = y;
+                }
+                _ => {
+                    unfit_objects.push(cid.clone());
+                }
+            }
+        }
+
+        for (&window_id, window) in display_set.windows.iter_mut() {
+
+            let placer = Placer {
+                reframe,
+                policy: on_unfit,
+                margin,
+                pts,
+                kind: "window",
+                dry_run,
+            };
+            let x = placer.place(
+                "horizontally",
+                x_anchor,
+                full_width,
+                new_width,
+                window.width,
+                window.x,
+            );
+            let y = placer.place(
+                "vertically",
+                y_anchor,
+                full_height,
+                new_height,
+                window.height,
+                window.y,
+            );
+
+            totals.analysis.windows.record(x, y);
+
+            match (x.offset(), y.offset()) {
+                (Some(x), Some(y)) => {
+                    if reframe == Reframe::Scale {
+                        window.width =
+                            scaled_size(full_width, new_width, window.width, window.x);
+                        window.height =
+                            scaled_size(full_height, new_height, window.height, window.y);
+                    }
+                    window.x = x;
+                    window.y = y;
+                }
+                _ => {
+                    unfit_windows.push(window_id);
+                }
+            }
+        }
+
+        display_set.windows.retain(|window_id, _| !unfit_windows.contains(window_id));
+        display_set.composition.objects.retain(|cid, _| {
+            !unfit_objects.contains(cid) && !unfit_windows.contains(&cid.window_id)
+        });
+
+        // Bitmaps are rounded to whole pixels apart from their windows, or keep their size when
+        // only coordinates are scaled, so they may no longer fit their windows.
+        if reframe == Reframe::Scale {
+            for (cid, composition_object) in display_set.composition.objects.iter() {
+
+                let objects = if scale_filter.is_some() { &*output_state } else { &*epoch_state };
+
+                if let (Some(window), Some((width, height))) = (
+                    display_set.windows.get(&cid.window_id),
+                    object_size(&display_set.objects, objects, cid.object_id),
+                ) {
+                    if overflows_window(window, composition_object, width, height) {
+                        eprintln!(
+                            "WARNING: Object {} of {}x{} pixels overflows window {} \
+                            after scaling at {}.",
+                            cid.object_id, width, height, cid.window_id,
+                            ticks_to_timestamp(pts),
+                        );
+                    }
+                }
+            }
+        }
+
+        totals.timings.record("reframe", stage_start.elapsed());
+
+        let stage_start = Instant::now();
+
+        if (place.is_some() || position.is_some()) && (place_all || !is_sign(display_set)) {
+
+            let placements = match (place, position) {
+                (Some(preset), _) => place_event(display_set, preset, margin, on_unfit),
+                (None, Some(position)) => position_event(
+                    display_set,
+                    position,
+                    vertical_offset,
+                    bars_aspect,
+                    margin,
+                    on_unfit,
+                ),
+                (None, None) => None,
+            };
+            let describe = || format!(
+                "event cannot be placed within {}x{} pixels and a {} pixel margin at {}",
+                new_width, new_height, margin, ticks_to_timestamp(pts),
+            );
+
+            if let Some((x, y)) = placements {
+                totals.analysis.events.record(x, y);
+            }
+
+            match placements {
+                Some((Placement::Unfit, _)) | Some((_, Placement::Unfit)) => {
+                    if on_unfit == UnfitPolicy::Drop {
+                        eprintln!("WARNING: {}; dropped.", describe());
+                        display_set.windows.clear();
+                        display_set.composition.objects.clear();
+                    } else if dry_run {
+                        eprintln!("ERROR: {}.", describe());
+                    } else {
+                        panic!("{}", describe())
+                    }
+                }
+                Some((Placement::Fallback(_), _)) | Some((_, Placement::Fallback(_))) => {
+                    eprintln!("WARNING: {}; placed anyway.", describe());
+                }
+                _ => {}
+            }
+        }
+
+        totals.timings.record("place", stage_start.elapsed());
+
+        let stage_start = Instant::now();
+        let matrix = matrix.unwrap_or_else(|| ColorMatrix::for_width(display_set.width));
+
+        if hue.is_some() || saturation.is_some() {
+            for palette in display_set.palettes.values_mut() {
+                palette.recolor(hue.unwrap_or(0.0), saturation.unwrap_or(1.0), 1.0, matrix, range);
+            }
+        }
+        if let Some(rgb) = tint {
+            for palette in display_set.palettes.values_mut() {
+                palette.tint(rgb, matrix, range);
+            }
+        }
+
+        totals.timings.record("recolor", stage_start.elapsed());
+
+        let stage_start = Instant::now();
+
+        if let Some(factor) = lum_scale {
+            for palette in display_set.palettes.values_mut() {
+                palette.scale_luminance(factor, matrix, range);
+            }
+        }
+
+        totals.timings.record("lum-scale", stage_start.elapsed());
+
+        let stage_start = Instant::now();
+
+        if let Some(mode) = tone_map {
+            for palette in display_set.palettes.values_mut() {
+                palette.tone_map(mode, matrix, range);
+            }
+        }
+
+        totals.timings.record("tone-map", stage_start.elapsed());
+
+        if let Some(max_percent) = drop_above {
+
+            let percent = coverage_cached(epoch_state, display_set, generations, coverage_cache)
+                .pixels * 100.0;
+
+            // Definitions are kept so that later display sets in the epoch still decode.
+            if percent > max_percent {
+                eprintln!(
+                    "WARNING: Dropping subtitles at {} covering {:.1}% of the frame.",
+                    ticks_to_timestamp(pts), percent,
+                );
+                display_set.composition.objects.clear();
+                totals.dropped += 1;
+            }
+        }
+
+        let stage_start = Instant::now();
+
+        output_state.apply(display_set);
+
+        let diagnostics = display_set.validate(output_state);
+
+        for diagnostic in diagnostics.iter() {
+            match diagnostic {
+                Diagnostic::WindowOverlap { .. } => totals.analysis.overlaps += 1,
+                _ => totals.analysis.diagnostics += 1,
+            }
+
+            eprintln!(
+                "{}: {}{}.",
+                if strict { "ERROR" } else { "WARNING" },
+                diagnostic,
+                match diagnostic {
+                    Diagnostic::WindowOverlap { window_ids, .. } => {
+                        overlap_origin(epoch_state, display_set, *window_ids)
+                    }
+                    _ => String::new(),
+                },
+            );
+        }
+        if strict && !diagnostics.is_empty() {
+            self.fail(
+                format!("display set at {} failed validation", ticks_to_timestamp(pts)),
+                totals,
+            );
+        }
+
+        totals.timings.record("validate", stage_start.elapsed());
+    }
+
+    // Stops the run, unless this is a dry run, in which case it is only reported and counted.
+    fn fail(&self, message: String, totals: &mut EpochTotals) {
+
+        if !self.dry_run {
+            panic!("{}", message)
+        }
+
+        eprintln!("ERROR: {}.", message);
+        totals.analysis.failures += 1;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ResizePolicy {
+    Error,
+    SplitEpoch,
+    Ignore,
+}
 
-    let matches = app_from_crate!()
+fn app() -> App<'static, 'static> {
+    app_from_crate!()
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(SubCommand::with_name("fix-continuity")
+            .about("Renumbers compositions sequentially without otherwise touching the stream")
+            .arg(Arg::with_name("input")
+                .index(1)
+                .value_name("INPUT-FILE")
+                .help("Input PGS file; use - for STDIN")
+                .required(true)
+            )
+            .arg(Arg::with_name("output")
+                .index(2)
+                .value_name("OUTPUT-FILE")
+                .help("Output PGS file; use - for STDOUT")
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("concat")
+            .about("Joins PGS files one after another into a single stream")
+            .arg(Arg::with_name("inputs")
+                .index(1)
+                .value_name("INPUT-FILE")
+                .help("Input PGS files, in order; use - for STDIN")
+                .multiple(true)
+                .required(true)
+            )
+            .arg(Arg::with_name("output")
+                .index(2)
+                .value_name("OUTPUT-FILE")
+                .help("Output PGS file; use - for STDOUT")
+                .required(true)
+            )
+            .arg(Arg::with_name("offset")
+                .long("offset")
+                .value_name("OFFSETS")
+                .help("Comma-separated milliseconds or HH:MM:SS.mmm to shift each input after the \
+                    first by; inputs without one start --gap after the end of the one before")
+                .takes_value(true)
+                .use_delimiter(true)
+                .required(false)
+                .validator(|value| {
+                    match parse_trim_point(&value) {
+                        Some(_) => Ok(()),
+                        None => Err("must be milliseconds or HH:MM:SS.mmm".to_string()),
+                    }
+                })
+            )
+            .arg(Arg::with_name("gap")
+                .long("gap")
+                .value_name("MILLISECONDS")
+                .help("Time between the last display set of one input and the first of the next \
+                    when its offset is detected")
+                .takes_value(true)
+                .required(false)
+                .default_value("1000")
+                .validator(|value| {
+                    match parse_trim_point(&value) {
+                        Some(_) => Ok(()),
+                        None => Err("must be milliseconds or HH:MM:SS.mmm".to_string()),
+                    }
+                })
+            )
+            .arg(Arg::with_name("allow-resolution-change")
+                .long("allow-resolution-change")
+                .help("Joins inputs even if their resolutions differ")
+            )
+        )
+        .subcommand(SubCommand::with_name("export-json")
+            .about("Writes every field of every segment as a JSON array")
+            .arg(Arg::with_name("input")
+                .index(1)
+                .value_name("INPUT-FILE")
+                .help("Input PGS file; use - for STDIN")
+                .required(true)
+            )
+            .arg(Arg::with_name("output")
+                .index(2)
+                .value_name("JSON-FILE")
+                .help("JSON file to write; use - for STDOUT")
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("import-json")
+            .about("Builds a PGS stream from segments written by export-json")
+            .arg(Arg::with_name("input")
+                .index(1)
+                .value_name("JSON-FILE")
+                .help("JSON file to read; use - for STDIN")
+                .required(true)
+            )
+            .arg(Arg::with_name("output")
+                .index(2)
+                .value_name("OUTPUT-FILE")
+                .help("Output PGS file; use - for STDOUT")
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("report")
+            .about("Summarizes the events, definitions, and validation findings of a stream")
+            .arg(Arg::with_name("input")
+                .index(1)
+                .value_name("INPUT-FILE")
+                .help("Input PGS file; use - for STDIN")
+                .required(true)
+            )
+            .arg(Arg::with_name("output")
+                .index(2)
+                .value_name("OUTPUT-FILE")
+                .help("File to write the report to; use - for STDOUT")
+                .required(false)
+                .default_value("-")
+            )
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("Writes the report as a single JSON object")
+            )
+            .arg(Arg::with_name("occupancy-json")
+                .long("occupancy-json")
+                .value_name("PATH")
+                .help("Also writes the screen areas occupied from each change point on as JSON")
+                .takes_value(true)
+                .required(false)
+            )
+            .arg(Arg::with_name("occupancy-step")
+                .long("occupancy-step")
+                .value_name("MILLISECONDS")
+                .help("Samples the occupied areas at a fixed step instead of at change points")
+                .takes_value(true)
+                .required(false)
+                .requires("occupancy-json")
+                .validator(|value| {
+                    match value.parse::<u32>() {
+                        Ok(ms) if ms > 0 => Ok(()),
+                        _ => Err("must be a positive integer".to_string()),
+                    }
+                })
+            )
+            .arg(Arg::with_name("precise-occupancy")
+                .long("precise-occupancy")
+                .help("Takes the bounds of visible pixels as occupied instead of whole windows")
+                .requires("occupancy-json")
+            )
+        )
+        .subcommand(SubCommand::with_name("export-bdn")
+            .about("Writes every subtitle event as a PNG file listed in a BDN XML file")
+            .arg(Arg::with_name("input")
+                .index(1)
+                .value_name("INPUT-FILE")
+                .help("Input PGS file; use - for STDIN")
+                .required(true)
+            )
+            .arg(Arg::with_name("output")
+                .index(2)
+                .value_name("XML-FILE")
+                .help("BDN XML file to write; the PNG files are written beside it")
+                .required(true)
+            )
+            .arg(Arg::with_name("fps")
+                .long("fps")
+                .value_name("RATE")
+                .help("Frame rate that timecodes are counted in")
+                .takes_value(true)
+                .required(false)
+                .possible_values(&["23.976", "24", "25", "29.97"])
+                .default_value("23.976")
+            )
+        )
+        .subcommand(SubCommand::with_name("import-bdn")
+            .about("Builds a PGS stream from a BDN XML file and the PNG files it lists")
+            .arg(Arg::with_name("input")
+                .index(1)
+                .value_name("XML-FILE")
+                .help("BDN XML file to read; PNG files are looked for beside it")
+                .required(true)
+            )
+            .arg(Arg::with_name("output")
+                .index(2)
+                .value_name("OUTPUT-FILE")
+                .help("Output PGS file; use - for STDOUT")
+                .required(true)
+            )
+        )
         .arg(Arg::with_name("crop-width")
             .long("crop-width")
             .short("w")
             .value_name("PIXELS")
             .help("Width to crop each subtitle frame to")
             .takes_value(true)
-            .required(true)
+            .required_unless_one(&["uncrop-to", "scale-width", "capabilities", "preview-palette"])
+            .conflicts_with_all(&["uncrop-to", "scale-width"])
             .validator(|value| {
                 if value.parse::<usize>().is_ok() {
                     Ok(())
@@ -54,7 +952,8 @@ fn main() {
             .value_name("PIXELS")
             .help("Height to crop each subtitle frame to")
             .takes_value(true)
-            .required(true)
+            .required_unless_one(&["uncrop-to", "scale-width", "capabilities", "preview-palette"])
+            .conflicts_with_all(&["uncrop-to", "scale-width"])
             .validator(|value| {
                 if value.parse::<usize>().is_ok() {
                     Ok(())
@@ -63,6 +962,68 @@ fn main() {
                 }
             })
         )
+        .arg(Arg::with_name("uncrop-to")
+            .long("uncrop-to")
+            .value_name("WIDTHxHEIGHT")
+            .help("Restores previously cropped subtitles to a larger frame size")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                if parse_size(&value).is_some() {
+                    Ok(())
+                } else {
+                    Err("must be in the form WIDTHxHEIGHT".to_string())
+                }
+            })
+        )
+        .arg(Arg::with_name("anchor")
+            .long("anchor")
+            .value_name("ANCHOR")
+            .help("Where the crop is taken from within the full frame, or put back by --uncrop-to; \
+                center if not given")
+            .takes_value(true)
+            .required(false)
+            .conflicts_with("scale-width")
+            .possible_values(&CropAnchor::NAMES)
+        )
+        .arg(Arg::with_name("scale-width")
+            .long("scale-width")
+            .value_name("PIXELS")
+            .help("Width to rescale each subtitle frame to, along with its bitmaps")
+            .takes_value(true)
+            .required(false)
+            .requires("scale-height")
+            .conflicts_with("uncrop-to")
+            .validator(|value| {
+                match value.parse::<u16>() {
+                    Ok(pixels) if pixels > 0 => Ok(()),
+                    _ => Err("must be a positive integer".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("scale-height")
+            .long("scale-height")
+            .value_name("PIXELS")
+            .help("Height to rescale each subtitle frame to, along with its bitmaps")
+            .takes_value(true)
+            .required(false)
+            .requires("scale-width")
+            .validator(|value| {
+                match value.parse::<u16>() {
+                    Ok(pixels) if pixels > 0 => Ok(()),
+                    _ => Err("must be a positive integer".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("scale-filter")
+            .long("scale-filter")
+            .value_name("FILTER")
+            .help("How bitmaps are resampled when scaling, or none to only scale coordinates")
+            .takes_value(true)
+            .required(false)
+            .possible_values(&["nearest", "bilinear", "none"])
+            .default_value("bilinear")
+        )
         .arg(Arg::with_name("margin")
             .long("margin")
             .short("m")
@@ -83,7 +1044,7 @@ fn main() {
             .long("lum-scale")
             .short("l")
             .value_name("FACTOR")
-            .help("Scales the gamma brightness of the subtitles by the specified factor")
+            .help("Scales the luminance of the subtitles in linear light by the specified factor")
             .takes_value(true)
             .required(false)
             .validator(|value| {
@@ -101,219 +1062,1769 @@ fn main() {
                 Ok(())
             })
         )
-        .arg(Arg::with_name("input")
-            .index(1)
-            .value_name("INPUT-FILE")
-            .help("Input PGS file; use - for STDIN")
-            .required(true)
-        )
-        .arg(Arg::with_name("output")
-            .index(2)
-            .value_name("OUTPUT-FILE")
-            .help("Output PGS file; use - for STDOUT")
-            .required(true)
+        .arg(Arg::with_name("hue")
+            .long("hue")
+            .value_name("DEGREES")
+            .help("Rotates the hue of the subtitles, leaving nearly gray colors mostly alone")
+            .takes_value(true)
+            .required(false)
+            .allow_hyphen_values(true)
+            .validator(|value| {
+                match value.parse::<f64>() {
+                    Ok(degrees) if degrees.is_finite() => Ok(()),
+                    _ => Err("must be a number of degrees".to_string()),
+                }
+            })
         )
-        .after_help(format!("This utility will crop PGS subtitles found in Blu-ray discs so \
-            that they can match any cropping that has been done to the main video stream, \
-            thereby preventing the subtitles from appearing squished or distorted by the \
-            player.\n\n\
-            Copyright © 2021 William Swartzendruber\n\
-            Licensed under the Open Software License version 3.0\n\
-            <{}>", env!("CARGO_PKG_REPOSITORY")).as_str())
-        .get_matches();
-    let crop_width = matches.value_of("crop-width").unwrap().parse::<u16>().unwrap();
-    let crop_height = matches.value_of("crop-height").unwrap().parse::<u16>().unwrap();
-    let margin = matches.value_of("margin").unwrap().parse::<u16>().unwrap();
-    let lum_scale = match matches.value_of("lum-scale") {
-        Some(factor) => Some(factor.parse::<f64>().unwrap()),
-        None => None,
-    };
-    let input_value = matches.value_of("input").unwrap();
-    let (mut stdin_read, mut file_read);
-    let mut input = BufReader::<&mut dyn Read>::new(
-        if input_value == "-" {
-            stdin_read = stdin();
-            &mut stdin_read
-        } else {
-            file_read = File::open(input_value)
-                .expect("Could not open input file for writing.");
-            &mut file_read
-        }
-    );
-    let output_value = matches.value_of("output").unwrap();
-    let (mut stdout_write, mut file_write);
-    let mut output = BufWriter::<&mut dyn Write>::new(
-        if output_value == "-" {
-            stdout_write = stdout();
-            &mut stdout_write
-        } else {
-            file_write = File::create(output_value)
-                .expect("Could not open output file for writing.");
-            &mut file_write
-        }
-    );
-    let mut screen_sizes = Vec::<Size>::new();
-
-    loop {
-
-        match &mut input.read_display_set() {
-            Ok(display_set) => {
-
-                let full_width = display_set.width;
-                let full_height = display_set.height;
-                let screen_size = Size {
-                    width: full_width,
-                    height: full_height,
-                };
-
-                if !screen_sizes.contains(&screen_size) {
-                    eprintln!(
-                        "New resolution encountered: {}x{}",
-                        screen_size.width, screen_size.height,
-                    );
-                    screen_sizes.push(screen_size);
-                }
-
-                display_set.width = crop_width;
-                display_set.height = crop_height;
-
-                for (cid, composition_object) in display_set.composition.objects.iter_mut() {
-
-                    let object_sizes = display_set.objects.iter()
-                        .filter(|(object_vid, _)| object_vid.id == cid.object_id)
-                        .map(|(_, object)| Size { width: object.width, height: object.height })
-                        .collect::<Vec<Size>>();
-                    let object_width = object_sizes.iter()
-                        .map(|size| size.width)
-                        .max()
-                        .unwrap();
-                    let object_height = object_sizes.iter()
-                        .map(|size| size.height)
-                        .max()
-                        .unwrap();
-
-                    composition_object.x = cropped_offset(
-                        full_width,
-                        crop_width,
-                        object_width,
-                        composition_object.x,
-                        margin,
-                    );
-                    composition_object.y = cropped_offset(
-                        full_height,
-                        crop_height,
-                        object_height,
-                        composition_object.y,
-                        margin,
-                    );
+        .arg(Arg::with_name("saturation")
+            .long("saturation")
+            .value_name("FACTOR")
+            .help("Multiplies the saturation of the subtitles, leaving nearly gray colors mostly \
+                alone")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                match value.parse::<f64>() {
+                    Ok(factor) if factor.is_finite() && factor >= 0.0 => Ok(()),
+                    _ => Err("must be a non-negative number".to_string()),
                 }
-
-                for window in display_set.windows.values_mut() {
-                    window.x = cropped_offset(
-                        full_width,
-                        crop_width,
-                        window.width,
-                        window.x,
-                        margin,
-                    );
-                    window.y = cropped_offset(
-                        full_height,
-                        crop_height,
-                        window.height,
-                        window.y,
-                        margin,
-                    );
+            })
+        )
+        .arg(Arg::with_name("tint")
+            .long("tint")
+            .value_name("R,G,B")
+            .help("Multiplies the colors of the subtitles by the given color, turning white text \
+                into that color")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                if parse_tint(&value).is_some() {
+                    Ok(())
+                } else {
+                    Err("must be three values from 0 to 255 separated by commas".to_string())
+                }
+            })
+        )
+        .arg(Arg::with_name("tone-map")
+            .long("tone-map")
+            .value_name("CURVE")
+            .help("Converts the subtitles for HDR video as pq:NITS, hlg, or hlg:NITS, where \
+                NITS is how bright SDR white becomes (HLG defaults to 203)")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                if ToneMap::parse(&value).is_some() {
+                    Ok(())
+                } else {
+                    Err("must be pq:NITS up to 10000, hlg, or hlg:NITS up to 1000".to_string())
+                }
+            })
+        )
+        .arg(Arg::with_name("matrix")
+            .long("matrix")
+            .value_name("MATRIX")
+            .help("YCbCr matrix the palettes use, otherwise BT.601 up to 720 pixels wide and \
+                BT.709 above")
+            .takes_value(true)
+            .required(false)
+            .possible_values(ColorMatrix::NAMES)
+        )
+        .arg(Arg::with_name("range")
+            .long("range")
+            .value_name("RANGE")
+            .help("Whether the palettes use limited (16-235) or full (0-255) range YCbCr")
+            .takes_value(true)
+            .required(false)
+            .possible_values(Range::NAMES)
+            .default_value("limited")
+        )
+        .arg(Arg::with_name("on-resize")
+            .long("on-resize")
+            .value_name("POLICY")
+            .help("Action to take when the resolution changes without a new epoch starting")
+            .takes_value(true)
+            .required(false)
+            .possible_values(&["error", "split-epoch", "ignore"])
+            .default_value("ignore")
+        )
+        .arg(Arg::with_name("on-unfit")
+            .long("on-unfit")
+            .value_name("POLICY")
+            .help("Action to take when a window or object cannot fit within the margins")
+            .takes_value(true)
+            .required(false)
+            .possible_values(&["center", "edge", "error", "drop"])
+            .default_value("center")
+        )
+        .arg(Arg::with_name("place")
+            .long("place")
+            .value_name("PRESET")
+            .help("Moves each event to a standard location on the output canvas")
+            .takes_value(true)
+            .required(false)
+            .possible_values(&Preset::NAMES)
+        )
+        .arg(Arg::with_name("position")
+            .long("position")
+            .value_name("POSITION")
+            .help("Moves each event up or down to a standard location, leaving it where it is \
+                across")
+            .takes_value(true)
+            .required(false)
+            .possible_values(&Position::NAMES)
+            .conflicts_with("place")
+        )
+        .arg(Arg::with_name("vertical-offset")
+            .long("vertical-offset")
+            .value_name("PIXELS")
+            .help("Moves each positioned event further down by the specified pixels, or up if \
+                negative")
+            .takes_value(true)
+            .required(false)
+            .allow_hyphen_values(true)
+            .requires("position")
+            .validator(|value| {
+                if value.parse::<i32>().is_ok() {
+                    Ok(())
+                } else {
+                    Err("must be an integer".to_string())
+                }
+            })
+        )
+        .arg(Arg::with_name("bars-aspect")
+            .long("bars-aspect")
+            .value_name("RATIO")
+            .help("Aspect ratio of the letterboxed picture whose bars events are positioned in, \
+                which is 2.39 unless specified")
+            .takes_value(true)
+            .required(false)
+            .requires("position")
+            .validator(|value| {
+                match value.parse::<f64>() {
+                    Ok(ratio) if ratio.is_normal() && ratio.is_sign_positive() => Ok(()),
+                    _ => Err("must be a positive number".to_string()),
+                }
+            })
+        )
+        .group(ArgGroup::with_name("placement")
+            .args(&["place", "position"])
+        )
+        .arg(Arg::with_name("place-all")
+            .long("place-all")
+            .help("Also moves forced events and events near the middle of the screen, which are \
+                taken to be signs")
+            .takes_value(false)
+            .required(false)
+            .requires("placement")
+        )
+        .arg(Arg::with_name("single-window")
+            .long("single-window")
+            .help("Composites two-window display sets into a single window and object")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("fix-missing-indices")
+            .long("fix-missing-indices")
+            .value_name("MODE")
+            .help("Redraws pixels whose palette index is undefined with a transparent entry or \
+                the nearest defined one")
+            .takes_value(true)
+            .required(false)
+            .possible_values(&["transparent", "nearest"])
+        )
+        .arg(Arg::with_name("prune-palettes")
+            .long("prune-palettes")
+            .help("Removes palette entries not referenced by any object in the epoch")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("optimize")
+            .long("optimize")
+            .help("Removes object definitions that repeat one the decoder already holds from \
+                earlier in the epoch, demoting acquisition points that lose any to normal cases")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("dedup")
+            .long("dedup")
+            .help("Removes display sets that repeat the one before them within an epoch")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("retime")
+            .long("retime")
+            .value_name("FACTOR")
+            .help("Multiplies every timestamp by the specified factor")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                match value.parse::<f64>() {
+                    Ok(factor) if factor.is_normal() && factor.is_sign_positive() => Ok(()),
+                    _ => Err("must be a positive number".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("retime-mode")
+            .long("retime-mode")
+            .value_name("MODE")
+            .help("Scales event durations along with everything else, or only the gaps between \
+                events")
+            .takes_value(true)
+            .required(false)
+            .possible_values(&["scale-all", "scale-gaps"])
+            .default_value("scale-all")
+        )
+        .arg(Arg::with_name("min-duration")
+            .long("min-duration")
+            .value_name("MILLISECONDS")
+            .help("Extends events shorter than this by clearing them later")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                match parse_trim_point(&value) {
+                    Some(_) => Ok(()),
+                    None => Err("must be milliseconds or HH:MM:SS.mmm".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("min-gap")
+            .long("min-gap")
+            .value_name("MILLISECONDS")
+            .help("Clears events earlier to leave at least this much time before the next one, \
+                unless one follows the other directly")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                match parse_trim_point(&value) {
+                    Some(_) => Ok(()),
+                    None => Err("must be milliseconds or HH:MM:SS.mmm".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("only-forced")
+            .long("only-forced")
+            .help("Keeps only forced subtitles, along with the clears that end them")
+            .takes_value(false)
+            .required(false)
+            .conflicts_with("strip-forced")
+        )
+        .arg(Arg::with_name("strip-forced")
+            .long("strip-forced")
+            .help("Removes forced subtitles")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("trim-start")
+            .long("trim-start")
+            .value_name("TIMESTAMP")
+            .help("Drops everything before the specified milliseconds or HH:MM:SS.mmm, showing \
+                whatever is still on screen there again right at it")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                match parse_trim_point(&value) {
+                    Some(_) => Ok(()),
+                    None => Err("must be milliseconds or HH:MM:SS.mmm".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("trim-end")
+            .long("trim-end")
+            .value_name("TIMESTAMP")
+            .help("Drops everything from the specified milliseconds or HH:MM:SS.mmm on, clearing \
+                whatever is still on screen there")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                match parse_trim_point(&value) {
+                    Some(_) => Ok(()),
+                    None => Err("must be milliseconds or HH:MM:SS.mmm".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("no-rebase")
+            .long("no-rebase")
+            .help("Keeps the original timestamps instead of moving --trim-start to zero")
+            .requires("trim-start")
+        )
+        .arg(Arg::with_name("pts-offset")
+            .long("pts-offset")
+            .value_name("OFFSET")
+            .help("Shifts every timestamp by the specified milliseconds or HH:MM:SS.mmm, which may \
+                be negative")
+            .takes_value(true)
+            .required(false)
+            .allow_hyphen_values(true)
+            .validator(|value| {
+                match parse_offset(&value) {
+                    Some(_) => Ok(()),
+                    None => Err("must be milliseconds or HH:MM:SS.mmm".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("drop-early")
+            .long("drop-early")
+            .help("Drops display sets that --pts-offset would move before zero instead of moving \
+                them to zero")
+            .requires("pts-offset")
+        )
+        .arg(Arg::with_name("smooth-fades")
+            .long("smooth-fades")
+            .value_name("FPS")
+            .help("Interpolates palette fades so that they update at up to this rate")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                match value.parse::<f64>() {
+                    Ok(fps) if fps.is_normal() && fps.is_sign_positive() => Ok(()),
+                    _ => Err("must be a positive number".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("drop-above")
+            .long("drop-above")
+            .value_name("PERCENT")
+            .help("Removes subtitles that cover more than this percentage of the frame")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                match value.parse::<f64>() {
+                    Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(()),
+                    _ => Err("must be a percentage from 0 to 100".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("insert-clears")
+            .long("insert-clears")
+            .help("Clears the screen one frame before an epoch starts if nothing else has")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("fix-epochs")
+            .long("fix-epochs")
+            .help("Numbers compositions from zero, starts an epoch wherever one is missing, and \
+                demotes epoch starts that define nothing new")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("fix-dts")
+            .long("fix-dts")
+            .help("Recomputes every DTS from its PTS using the decoder model's decode durations")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("cache-size")
+            .long("cache-size")
+            .value_name("MIB")
+            .help("Sets how much memory rendered objects may be cached in; 0 turns caching off")
+            .takes_value(true)
+            .required(false)
+            .default_value("64")
+            .validator(|value| {
+                match value.parse::<usize>() {
+                    Ok(size) if size <= usize::MAX / 1_048_576 => Ok(()),
+                    _ => Err("must be a whole number of mebibytes".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("strict")
+            .long("strict")
+            .help("Stops at the first display set that fails validation instead of warning")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("lossy")
+            .long("lossy")
+            .alias("resync")
+            .help("Skips past corrupt segments, dropping what they belong to up to the next \
+                epoch start, instead of stopping")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("verify-lossless")
+            .long("verify-lossless")
+            .help("Checks that the input is written back exactly when nothing is changed before \
+                doing anything else, and stops with 2 if it is not")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("force")
+            .long("force")
+            .help("Carries on even if --verify-lossless finds that the input cannot be written \
+                back exactly")
+            .takes_value(false)
+            .required(false)
+            .requires("verify-lossless")
+        )
+        .arg(Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("Reports what a run would do without writing anything; exits with 1 if \
+                anything cannot fit")
+            .takes_value(false)
+            .required(false)
+            .conflicts_with_all(&["output", "dump-json", "export-png", "contact-sheet"])
+        )
+        .arg(Arg::with_name("allow-partial")
+            .long("allow-partial")
+            .help("Keeps the output written so far if the run is interrupted")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("timings")
+            .long("timings")
+            .help("Prints throughput and the time spent in each processing stage")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("quiet")
+            .long("quiet")
+            .short("q")
+            .help("Suppresses the progress lines printed while reading")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("input")
+            .index(1)
+            .value_name("INPUT-FILE")
+            .help("Input PGS file; use - for STDIN")
+            .required_unless("capabilities")
+        )
+        .arg(Arg::with_name("output")
+            .index(2)
+            .value_name("OUTPUT-FILE")
+            .help("Output PGS file; use - for STDOUT")
+            .required_unless_one(
+                &[
+                    "dump-json",
+                    "export-png",
+                    "contact-sheet",
+                    "dry-run",
+                    "capabilities",
+                    "preview-palette",
+                ]
+            )
+        )
+        .arg(Arg::with_name("input-format")
+            .long("input-format")
+            .value_name("FORMAT")
+            .help("Reads the input as a bare PGS stream or as one PID of an MPEG transport stream")
+            .takes_value(true)
+            .required(false)
+            .possible_values(&["sup", "m2ts"])
+            .default_value("sup")
+        )
+        .arg(Arg::with_name("pid")
+            .long("pid")
+            .value_name("PID")
+            .help("Selects the transport stream PID that carries the subtitles")
+            .takes_value(true)
+            .required(false)
+            .default_value("0x1200")
+            .validator(|value| {
+                if parse_pid(&value).is_some() {
+                    Ok(())
+                } else {
+                    Err("must be a PID from 0 to 0x1FFF".to_string())
+                }
+            })
+        )
+        .arg(Arg::with_name("output-format")
+            .long("output-format")
+            .value_name("FORMAT")
+            .help("Writes the output as a bare PGS stream or wrapped in PES packets")
+            .takes_value(true)
+            .required(false)
+            .possible_values(&["sup", "pes"])
+            .default_value("sup")
+        )
+        .arg(Arg::with_name("zero-segment-pts")
+            .long("zero-segment-pts")
+            .help("Leaves the PTS and DTS of each segment header at zero in PES output")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("dump-json")
+            .long("dump-json")
+            .value_name("JSON-FILE")
+            .help("Also writes each output display set as a line of JSON; use - for STDOUT")
+            .takes_value(true)
+            .required(false)
+        )
+        .arg(Arg::with_name("export-png")
+            .long("export-png")
+            .value_name("DIRECTORY")
+            .help("Also renders each output display set that shows something to a PNG file")
+            .takes_value(true)
+            .required(false)
+        )
+        .arg(Arg::with_name("contact-sheet")
+            .long("contact-sheet")
+            .value_name("PNG-FILE")
+            .help("Also tiles a thumbnail of each output event into a PNG, paginated if long")
+            .takes_value(true)
+            .required(false)
+        )
+        .arg(Arg::with_name("columns")
+            .long("columns")
+            .value_name("COUNT")
+            .help("Sets how many thumbnails each contact sheet row holds")
+            .takes_value(true)
+            .required(false)
+            .default_value("6")
+            .validator(|value| {
+                match value.parse::<usize>() {
+                    Ok(columns) if columns > 0 => Ok(()),
+                    _ => Err("must be a positive whole number".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("preview-palette")
+            .long("preview-palette")
+            .value_name("INDEX|TIMESTAMP")
+            .help("Prints the palette of one display set before and after the palette transforms \
+                and exits; takes a zero-based index or HH:MM:SS.mmm")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                match Selector::parse(&value) {
+                    Some(_) => Ok(()),
+                    None => Err("must be a display set index or HH:MM:SS.mmm".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("capabilities")
+            .long("capabilities")
+            .help("Prints the supported subcommands, formats, and transforms as JSON and exits")
+            .takes_value(false)
+            .required(false)
+        )
+        .after_help(concat!("This utility will crop PGS subtitles found in Blu-ray discs so \
+            that they can match any cropping that has been done to the main video stream, \
+            thereby preventing the subtitles from appearing squished or distorted by the \
+            player.\n\n\
+            Copyright © 2021 William Swartzendruber\n\
+            Licensed under the Open Software License version 3.0\n\
+            <", env!("CARGO_PKG_REPOSITORY"), ">"))
+}
+
+fn main() {
+
+    let matches = app().get_matches();
+
+    if matches.is_present("capabilities") {
+        println!("{}", capabilities_json());
+        return
+    }
+
+    if let Some(matches) = matches.subcommand_matches("fix-continuity") {
+        run_fix_continuity(matches);
+        return
+    }
+
+    if let Some(matches) = matches.subcommand_matches("concat") {
+        run_concat(matches);
+        return
+    }
+
+    if let Some(matches) = matches.subcommand_matches("export-json") {
+        run_export_json(matches);
+        return
+    }
+
+    if let Some(matches) = matches.subcommand_matches("import-json") {
+        run_import_json(matches);
+        return
+    }
+
+    if let Some(matches) = matches.subcommand_matches("report") {
+        run_report(matches);
+        return
+    }
+
+    if let Some(matches) = matches.subcommand_matches("export-bdn") {
+        run_export_bdn(matches);
+        return
+    }
+
+    if let Some(matches) = matches.subcommand_matches("import-bdn") {
+        run_import_bdn(matches);
+        return
+    }
+
+    if matches.is_present("preview-palette") {
+        run_preview_palette(&matches);
+        return
+    }
+
+    let (reframe, new_width, new_height) = match (
+        matches.value_of("uncrop-to"),
+        matches.value_of("scale-width"),
+    ) {
+        (Some(size), _) => {
+            let size = parse_size(size).unwrap();
+            (Reframe::Uncrop, size.width, size.height)
+        }
+        (None, Some(width)) => (
+            Reframe::Scale,
+            width.parse::<u16>().unwrap(),
+            matches.value_of("scale-height").unwrap().parse::<u16>().unwrap(),
+        ),
+        (None, None) => (
+            Reframe::Crop,
+            matches.value_of("crop-width").unwrap().parse::<u16>().unwrap(),
+            matches.value_of("crop-height").unwrap().parse::<u16>().unwrap(),
+        ),
+    };
+    let margin = matches.value_of("margin").unwrap().parse::<u16>().unwrap();
+    let on_resize = match matches.value_of("on-resize").unwrap() {
+        "error" => ResizePolicy::Error,
+        "split-epoch" => ResizePolicy::SplitEpoch,
+        _ => ResizePolicy::Ignore,
+    };
+    let on_unfit = match matches.value_of("on-unfit").unwrap() {
+        "edge" => UnfitPolicy::Edge,
+        "error" => UnfitPolicy::Error,
+        "drop" => UnfitPolicy::Drop,
+        _ => UnfitPolicy::Center,
+    };
+    let place = matches.value_of("place").and_then(Preset::from_name);
+    let position = matches.value_of("position").and_then(Position::from_name);
+    let vertical_offset = matches.value_of("vertical-offset")
+        .map_or(0, |pixels| pixels.parse::<i32>().unwrap());
+    let bars_aspect = matches.value_of("bars-aspect")
+        .map_or(BARS_ASPECT, |ratio| ratio.parse::<f64>().unwrap());
+    let place_all = matches.is_present("place-all");
+    let single_window = matches.is_present("single-window");
+    let strict = matches.is_present("strict");
+    let dry_run = matches.is_present("dry-run");
+    let trim = match (matches.value_of("trim-start"), matches.value_of("trim-end")) {
+        (None, None) => None,
+        (start, end) => Some(Trim {
+            start: start.map(|value| parse_trim_point(value).unwrap()).unwrap_or(0),
+            end: end.map(|value| parse_trim_point(value).unwrap()),
+            rebase: !matches.is_present("no-rebase"),
+        }),
+    };
+
+    if let Some(Trim { start, end: Some(end), .. }) = trim {
+        if start >= end {
+            panic!("--trim-start must come before --trim-end");
+        }
+    }
+
+    let epoch_options = EpochOptions {
+        forced: if matches.is_present("only-forced") {
+            Some(ForcedFilter::Only)
+        } else if matches.is_present("strip-forced") {
+            Some(ForcedFilter::Strip)
+        } else {
+            None
+        },
+        trim,
+        dedup: matches.is_present("dedup"),
+        retime: matches.value_of("retime").map(|factor| Retime {
+            rate: factor.parse::<f64>().unwrap(),
+            mode: match matches.value_of("retime-mode").unwrap() {
+                "scale-gaps" => RetimeMode::ScaleGaps,
+                _ => RetimeMode::ScaleAll,
+            },
+        }),
+        offset: matches.value_of("pts-offset").map(|value| Offset {
+            ticks: parse_offset(value).unwrap(),
+            early: if matches.is_present("drop-early") {
+                EarlyPolicy::Drop
+            } else {
+                EarlyPolicy::Clamp
+            },
+        }),
+        pacing: if matches.is_present("min-duration") || matches.is_present("min-gap") {
+            Some(TimingRules {
+                min_duration: matches.value_of("min-duration")
+                    .map_or(0, |value| parse_trim_point(value).unwrap()),
+                min_gap: matches.value_of("min-gap")
+                    .map_or(0, |value| parse_trim_point(value).unwrap()),
+            })
+        } else {
+            None
+        },
+        fix_indices: matches.value_of("fix-missing-indices").map(|mode| match mode {
+            "transparent" => IndexFix::Transparent,
+            _ => IndexFix::Nearest,
+        }),
+        prune: matches.is_present("prune-palettes"),
+        optimize: matches.is_present("optimize"),
+        smooth_fps: matches.value_of("smooth-fades").map(|fps| fps.parse::<f64>().unwrap()),
+        fix_dts: matches.is_present("fix-dts"),
+    };
+    let print_timings = matches.is_present("timings");
+    let insert_clears = matches.is_present("insert-clears");
+    let mut epoch_repairer = if matches.is_present("fix-epochs") {
+        Some(EpochRepairer::default())
+    } else {
+        None
+    };
+    let drop_above = matches.value_of("drop-above").map(|value| value.parse::<f64>().unwrap());
+    let hue = matches.value_of("hue").map(|degrees| degrees.parse::<f64>().unwrap());
+    let saturation = matches.value_of("saturation").map(|factor| factor.parse::<f64>().unwrap());
+    let tint = matches.value_of("tint").and_then(parse_tint);
+    let lum_scale = matches.value_of("lum-scale").map(|factor| factor.parse::<f64>().unwrap());
+    let tone_map = matches.value_of("tone-map").and_then(ToneMap::parse);
+    let matrix = matches.value_of("matrix").and_then(ColorMatrix::from_name);
+    let range = Range::from_name(matches.value_of("range").unwrap()).unwrap();
+    let pipeline = Pipeline {
+        reframe,
+        new_width,
+        new_height,
+        anchor: CropAnchor::from_name(matches.value_of("anchor").unwrap_or("center")).unwrap(),
+        margin,
+        on_resize,
+        on_unfit,
+        place,
+        position,
+        vertical_offset,
+        bars_aspect,
+        place_all,
+        single_window,
+        scale_filter: match matches.value_of("scale-filter").unwrap() {
+            "nearest" => Some(Filter::Nearest),
+            "bilinear" => Some(Filter::Bilinear),
+            _ => None,
+        },
+        hue,
+        saturation,
+        tint,
+        lum_scale,
+        tone_map,
+        matrix,
+        range,
+        drop_above,
+        strict,
+        dry_run,
+    };
+    // Segments of kinds that are not understood are copied through as they are.
+    let read_options = ReadOptions { lenient: true, keep_unknown: true, ..Default::default() };
+    let input_value = matches.value_of("input").unwrap();
+    let (mut stdin_read, mut file_read, mut verified_read);
+    let mut source: &mut dyn Read = if input_value == "-" {
+        stdin_read = stdin();
+        &mut stdin_read
+    } else {
+        file_read = File::open(input_value).expect("Could not open input file for writing.");
+        &mut file_read
+    };
+
+    // The input is read whole to be checked, and then read again from memory.
+    if matches.is_present("verify-lossless") {
+
+        let mut bytes = vec![];
+
+        source.read_to_end(&mut bytes).expect("Could not read input file.");
+        verify_lossless(&bytes, input_pid(&matches), &read_options, matches.is_present("force"));
+        verified_read = Cursor::new(bytes);
+        source = &mut verified_read;
+    }
+
+    let mut input = LossyReader::new(Input::new(
+        BufReader::new(CountingReader::new(source)),
+        input_pid(&matches),
+    ));
+    let lossy = matches.is_present("lossy");
+
+    let allow_partial = matches.is_present("allow-partial");
+    let mut sinks = Vec::<Box<dyn DisplaySetSink>>::new();
+
+    // Output files are written under a temporary name and only renamed once they are complete.
+    let output_paths = matches.value_of("output")
+        .filter(|value| *value != "-")
+        .map(|value| (format!("{}.partial", value), value.to_string()));
+
+    if let Some(output_value) = matches.value_of("output") {
+
+        let path = match &output_paths {
+            Some((partial_path, _)) => partial_path.as_str(),
+            None => output_value,
+        };
+
+        let output = BufWriter::new(
+            open_output(path).expect("Could not open output file for writing.")
+        );
+
+        match matches.value_of("output-format").unwrap() {
+            "pes" => sinks.push(
+                Box::new(PesSink::new(output, matches.is_present("zero-segment-pts")))
+            ),
+            _ => sinks.push(Box::new(SupSink::new(output))),
+        }
+    }
+    if let Some(json_value) = matches.value_of("dump-json") {
+        sinks.push(Box::new(JsonSink::new(BufWriter::new(
+            open_output(json_value).expect("Could not open JSON file for writing.")
+        ))));
+    }
+    let cache_size = matches.value_of("cache-size").unwrap().parse::<usize>().unwrap() * 1_048_576;
+    // Shared by everything that renders so that each object is only converted to RGBA once.
+    let object_cache = ObjectCache::shared(cache_size);
+
+    object_cache.borrow_mut().set_matrix(matrix);
+    object_cache.borrow_mut().set_range(range);
+
+    if let Some(directory) = matches.value_of("export-png") {
+        create_dir_all(directory).expect("Could not create PNG export directory.");
+        sinks.push(Box::new(PngSink::new(PathBuf::from(directory), object_cache.clone())));
+    }
+    if let Some(path) = matches.value_of("contact-sheet") {
+        sinks.push(Box::new(ContactSheetSink::new(
+            PathBuf::from(path),
+            matches.value_of("columns").unwrap().parse::<usize>().unwrap(),
+            object_cache.clone(),
+        )));
+    }
+
+    let mut state = PipelineState {
+        coverage_cache: ObjectCache::new(cache_size),
+        ..Default::default()
+    };
+
+    state.coverage_cache.set_matrix(matrix);
+    state.coverage_cache.set_range(range);
+
+    let mut unwrapper = PtsUnwrapper::default();
+    let mut epoch = Vec::<DisplaySet>::new();
+    let mut totals = EpochTotals::default();
+    let started = Instant::now();
+    let mut last_pts = None::<u32>;
+    let mut unknown_kinds = BTreeMap::<u8, usize>::new();
+    let mut interrupted = false;
+    let mut progress = if matches.is_present("quiet") {
+        None
+    } else {
+        Some(ProgressThrottle::new(
+            |update: &ProgressUpdate| eprintln!(
+                "Read {:.1} MiB and {} display sets, up to {}.",
+                update.offset as f64 / 1_048_576.0,
+                update.display_sets,
+                ts_to_timestamp(update.pts),
+            ),
+            PROGRESS_DISPLAY_SETS,
+            PROGRESS_INTERVAL,
+        ))
+    };
+
+    // What the demultiplexer dropped is reported as each display set is read, which comes with
+    // how much of the input has been read by then.
+    let display_sets: Box<dyn Iterator<Item = (DisplaySetReadResult<DisplaySet>, u64)>> = if lossy {
+        let mut display_sets = input.display_sets_lossy(&read_options);
+        Box::new(from_fn(move || {
+            let next = display_sets.next();
+            let bytes_read = take_input_warnings(display_sets.get_mut());
+            next.map(|result| (result, bytes_read))
+        }))
+    } else {
+        let mut display_sets = input.display_sets(&read_options);
+        Box::new(from_fn(move || {
+            let next = display_sets.next();
+            let bytes_read = take_input_warnings(display_sets.get_mut());
+            next.map(|result| (result, bytes_read))
+        }))
+    };
+    let mut stage_start = Instant::now();
+
+    interrupt::install();
+
+    for (result, bytes_read) in display_sets {
+
+        totals.timings.record("read/decode", stage_start.elapsed());
+
+        if interrupt::interrupted() {
+            interrupted = true;
+            break
+        }
+
+        match result {
+            Ok(mut display_set) => {
+
+                last_pts = Some(display_set.pts);
+                state.pts = unwrapper.unwrap_pts(display_set.pts);
+
+                if let Some(progress) = progress.as_mut() {
+                    progress.display_set(bytes_read, display_set.pts);
+                }
+
+                for warning in display_set.warnings.iter() {
+
+                    let discarded = match warning {
+                        ReadWarning::StrayEnd => "stray END segment",
+                        ReadWarning::SupersededEmptyPcs => "superseded empty PCS",
+                        // The pipeline applies --on-resize to these.
+                        ReadWarning::ResizedMidEpoch { .. } => continue,
+                    };
+
+                    eprintln!(
+                        "WARNING: Discarded {} before display set at {}",
+                        discarded,
+                        ticks_to_timestamp(state.pts),
+                    );
+                }
+                for unknown_segment in display_set.unknown_segments.iter() {
+                    *unknown_kinds.entry(unknown_segment.kind).or_insert(0) += 1;
+                }
+
+                if let Some(epoch_repairer) = epoch_repairer.as_mut() {
+                    let stage_start = Instant::now();
+                    epoch_repairer.repair(&mut display_set);
+                    totals.timings.record("fix-epochs", stage_start.elapsed());
+                }
+
+                pipeline.process(&mut display_set, &mut state, &read_options.limits, &mut totals);
+
+                if display_set.composition.state == CompositionState::EpochStart {
+                    if insert_clears {
+                        insert_clear(&mut epoch, display_set.pts, &mut totals);
+                    }
+                    write_epoch(
+                        &mut sinks,
+                        &mut epoch,
+                        &epoch_options,
+                        Some(display_set.pts),
+                        &mut totals,
+                    );
+                }
+
+                epoch.push(display_set);
+            }
+            Err(err) => {
+                match err {
+                    DisplaySetReadError::SegmentError { source } => {
+                        match source {
+                            SegmentReadError::IoError { source } => {
+                                if source.kind() != ErrorKind::UnexpectedEof {
+                                    panic!("Could not read segment due to IO error: {}", source)
+                                }
+                                eprintln!(
+                                    "WARNING: Dropped the display set the input ends partway \
+                                    through."
+                                );
+                            }
+                            _ => {
+                                panic!(
+                                    "Could not read display set due to segment error: {}",
+                                    source,
+                                )
+                            }
+                        }
+                    }
+                    _ => panic!("Could not read display set due to bitstream error: {}", err)
+                }
+                break
+            }
+        };
+
+        stage_start = Instant::now();
+    }
+
+    write_epoch(&mut sinks, &mut epoch, &epoch_options, None, &mut totals);
+    finish_sinks(&mut sinks);
+    drop(sinks);
+
+    if let Some((partial_path, path)) = output_paths {
+        if interrupted && !allow_partial {
+            remove_file(&partial_path).expect("Could not remove partial output file.");
+        } else {
+            rename(&partial_path, &path).expect("Could not move output file into place.");
+        }
+    }
+
+    if !unknown_kinds.is_empty() {
+        eprintln!(
+            "WARNING: Copied {} segments of unknown kinds through unchanged: {}.",
+            unknown_kinds.values().sum::<usize>(),
+            unknown_kinds.iter()
+                .map(|(kind, count)| format!("0x{:02X} ({} times)", kind, count))
+                .collect::<Vec<String>>()
+                .join(", "),
+        );
+    }
+    if let Some(epoch_repairer) = epoch_repairer {
+
+        let repairs = epoch_repairer.repairs;
+
+        eprintln!(
+            "Renumbered {} display sets, started {} missing epochs, and demoted {} epoch starts \
+            to acquisition points and {} to normal cases.",
+            repairs.renumbered, repairs.promoted,
+            repairs.demoted_to_acquisition_point, repairs.demoted_to_normal,
+        );
+    }
+    if lossy {
+        eprintln!(
+            "Skipped {} corrupt bytes in {} places and dropped {} display sets.",
+            input.skipped(), input.resyncs(), input.dropped(),
+        );
+    }
+    if epoch_options.forced.is_some() {
+        eprintln!(
+            "Removed {} {} composition objects and dropped {} display sets.",
+            totals.forced_removed,
+            if epoch_options.forced == Some(ForcedFilter::Only) { "unforced" } else { "forced" },
+            totals.forced_dropped,
+        );
+    }
+    if epoch_options.trim.is_some() {
+        eprintln!(
+            "Trimmed {} display sets, showing {} events again at the start and clearing {} at \
+            the end.",
+            totals.trimmed, totals.carried_in, totals.cut_off,
+        );
+    }
+    if epoch_options.dedup {
+        eprintln!("Removed {} duplicate display sets.", totals.deduped);
+    }
+    if epoch_options.retime.is_some() {
+        eprintln!("Shortened {} display sets to keep them from overlapping.", totals.shortened);
+    }
+    if let Some(offset) = epoch_options.offset {
+        eprintln!(
+            "{} {} display sets that would have started before zero.",
+            if offset.early == EarlyPolicy::Drop { "Dropped" } else { "Moved" },
+            totals.early,
+        );
+    }
+    if epoch_options.pacing.is_some() {
+        eprintln!(
+            "Moved the clears of {} events to meet the minimum duration and gap, and left {} \
+            timing problems as they were.",
+            totals.paced, totals.unpaced,
+        );
+    }
+    if epoch_options.fix_indices.is_some() {
+        eprintln!("Remapped {} missing palette indices.", totals.remapped);
+    }
+    if epoch_options.prune {
+        eprintln!("Pruned {} unreferenced palette entries.", totals.pruned);
+    }
+    if epoch_options.optimize {
+        eprintln!(
+            "Shared {} repeated object definitions, demoting {} acquisition points to normal \
+            cases and saving {} bytes.",
+            totals.shared.dropped, totals.shared.demoted, totals.shared.bytes,
+        );
+    }
+    if epoch_options.smooth_fps.is_some() {
+        eprintln!("Inserted {} interpolated palette updates.", totals.interpolated);
+    }
+    if drop_above.is_some() {
+        eprintln!("Dropped {} display sets above the coverage limit.", totals.dropped);
+    }
+    if insert_clears {
+        eprintln!("Inserted {} clearing display sets.", totals.clears);
+    }
+    if epoch_options.fix_dts {
+        eprintln!("Recomputed the DTS of {} display sets.", totals.dts_scheduler.changed);
+    }
+    if print_timings {
+
+        let object_cache = object_cache.borrow();
+
+        totals.timings.record_cache(
+            object_cache.hits() + state.coverage_cache.hits(),
+            object_cache.misses() + state.coverage_cache.misses(),
+        );
+        totals.timings.report(input.get_ref().get_ref().get_ref().count(), started.elapsed());
+    }
+    if dry_run {
+        print!("{}", totals.analysis.table());
+    }
+    if interrupted {
+        eprintln!(
+            "Interrupted at PTS {}; {}.",
+            last_pts.map_or("(none)".to_string(), ts_to_timestamp),
+            if allow_partial { "kept the partial output" } else { "discarded the partial output" },
+        );
+        exit(EXIT_INTERRUPTED)
+    }
+    if dry_run && totals.analysis.failed() {
+        exit(EXIT_UNFIT)
+    }
+}
+
+fn write_epoch(
+    sinks: &mut Vec<Box<dyn DisplaySetSink>>,
+    epoch: &mut Vec<DisplaySet>,
+    options: &EpochOptions,
+    next_pts: Option<u32>,
+    totals: &mut EpochTotals,
+) {
+
+    let epoch_pts = match epoch.first() {
+        Some(display_set) => display_set.pts,
+        None => return,
+    };
+
+    if let Some(filter) = options.forced {
+
+        let stage_start = Instant::now();
+        let filtered = filter_forced(epoch, filter);
+
+        totals.forced_removed += filtered.removed;
+        totals.forced_dropped += filtered.dropped;
+        totals.timings.record("forced", stage_start.elapsed());
+    }
+    if let Some(trim) = options.trim {
+
+        let stage_start = Instant::now();
+        let trimmed = trim_epoch(epoch, trim, next_pts);
+
+        totals.trimmed += trimmed.dropped;
+        totals.carried_in += trimmed.carried_in;
+        totals.cut_off += trimmed.cut_off;
+        totals.timings.record("trim", stage_start.elapsed());
+    }
+    if options.dedup {
+        let stage_start = Instant::now();
+        totals.deduped += dedup_display_sets(epoch);
+        totals.timings.record("dedup", stage_start.elapsed());
+    }
+    if let Some(retime) = options.retime {
+
+        let stage_start = Instant::now();
+
+        for shortened in retime_epoch(epoch, retime, next_pts) {
+            eprintln!(
+                "WARNING: Shortened display set at {} by {} ms so that it ends before the next \
+                one starts.",
+                ts_to_timestamp(epoch[shortened.index].pts),
+                shortened.by.div_ceil(90),
+            );
+            totals.shortened += 1;
+        }
+        totals.timings.record("retime", stage_start.elapsed());
+    }
+    if let Some(offset) = options.offset {
+
+        let stage_start = Instant::now();
+
+        match offset_epoch(epoch, offset) {
+            Ok(early) => totals.early += early,
+            Err(pts) => panic!(
+                "PTS offset moves display set at {} past the largest timestamp",
+                ts_to_timestamp(pts),
+            ),
+        }
+        totals.timings.record("pts-offset", stage_start.elapsed());
+    }
+    if let Some(rules) = options.pacing {
+
+        let stage_start = Instant::now();
+
+        for (pts, adjustment) in pace_epoch(epoch, rules, next_pts) {
+
+            let pts = ts_to_timestamp(pts);
+
+            match adjustment {
+                TimingAdjustment::Extended { by, .. } => {
+                    eprintln!("Extended event at {} by {} ms.", pts, by.div_ceil(90));
+                    totals.paced += 1;
+                }
+                TimingAdjustment::Shortened { by, .. } => {
+                    eprintln!(
+                        "Shortened event at {} by {} ms to leave a gap before the next one.",
+                        pts,
+                        by.div_ceil(90),
+                    );
+                    totals.paced += 1;
+                }
+                TimingAdjustment::TooShort { by, .. } => {
+                    eprintln!(
+                        "WARNING: Event at {} is still {} ms shorter than the minimum duration.",
+                        pts,
+                        by.div_ceil(90),
+                    );
+                    totals.unpaced += 1;
+                }
+                TimingAdjustment::TooClose { by, .. } => {
+                    eprintln!(
+                        "WARNING: Gap after event at {} is still {} ms shorter than the minimum.",
+                        pts,
+                        by.div_ceil(90),
+                    );
+                    totals.unpaced += 1;
+                }
+                TimingAdjustment::Overlapping { .. } => {
+                    eprintln!(
+                        "WARNING: Event at {} already overlaps the next one; leaving it alone.",
+                        pts,
+                    );
+                    totals.unpaced += 1;
                 }
+            }
+        }
+        totals.timings.record("pacing", stage_start.elapsed());
+    }
+    if let Some(fix) = options.fix_indices {
+        let stage_start = Instant::now();
+        totals.remapped += fix_missing_indices(epoch, fix);
+        totals.timings.record("fix-missing-indices", stage_start.elapsed());
+    }
+    if let Some(fps) = options.smooth_fps {
+        let stage_start = Instant::now();
+        match smooth_fades(epoch, fps) {
+            Ok(inserted) => totals.interpolated += inserted,
+            Err(err) => eprintln!(
+                "WARNING: Left the fades as they were in the epoch at {}: {}.",
+                ts_to_timestamp(epoch[0].pts),
+                err,
+            ),
+        }
+        totals.timings.record("smooth-fades", stage_start.elapsed());
+    }
+    if options.prune {
+        let stage_start = Instant::now();
+        totals.pruned += prune_palettes(epoch);
+        totals.timings.record("prune-palettes", stage_start.elapsed());
+    }
+    if options.optimize {
 
-                for (window_id_1, window_1) in display_set.windows.iter() {
-                    for (window_id_2, window_2) in display_set.windows.iter() {
-                        if window_id_1 != window_id_2 {
+        let stage_start = Instant::now();
+        let shared = share_objects(epoch);
 
-                            let window_1_ex = window_1.x + window_1.width;
-                            let window_1_ey = window_1.y + window_1.height;
+        totals.shared.dropped += shared.dropped;
+        totals.shared.demoted += shared.demoted;
+        totals.shared.bytes += shared.bytes;
+        totals.timings.record("optimize", stage_start.elapsed());
+    }
 
-                            if window_1.x <= window_2.x && window_2.x <= window_1_ex
-                                && window_1.y <= window_2.y && window_2.y <= window_1_ey {
-                                panic!(
-                                    "window collision detected at {}",
-                                    ts_to_timestamp(display_set.pts),
-                                )
-                            }
-                        }
-                    }
-                }
+    let stage_start = Instant::now();
+    let event_ids = event_ids(epoch);
 
-                match lum_scale {
-                    Some(factor) => {
-                        for palette in display_set.palettes.values_mut() {
-                            for entry in palette.entries.values_mut() {
-                                let mut rgb = rgb_pixel(
-                                    YcbcrPixel { y: entry.y, cb: entry.cb, cr: entry.cr }
-                                );
-                                rgb.red *= factor;
-                                rgb.green *= factor;
-                                rgb.blue *= factor;
-                                let ycbcr = ycbcr_pixel(rgb);
-                                entry.y = ycbcr.y;
-                                entry.cb = ycbcr.cb;
-                                entry.cr = ycbcr.cr;
-                            }
-                        }
-                    }
-                    None => {
-                    }
-                }
+    for (mut display_set, event_id) in epoch.drain(..).zip(event_ids) {
 
-                if let Err(err) = output.write_display_set(display_set) {
-                    panic!("Could not write display set to output stream: {:?}", err)
-                }
+        // Once any display set has been inserted or removed, every later number needs to shift as
+        // well.
+        if totals.interpolated > 0
+            || totals.clears > 0
+            || totals.early > 0
+            || totals.deduped > 0
+            || totals.forced_dropped > 0
+            || totals.trimmed > 0
+            || totals.cut_off > 0 {
+            if let Some(number) = totals.next_composition_number {
+                display_set.composition.number = number;
             }
-            Err(err) => {
-                match err {
-                    DisplaySetReadError::SegmentError { source } => {
-                        match source {
-                            SegmentReadError::IoError { source } => {
-                                if source.kind() != ErrorKind::UnexpectedEof {
-                                    panic!("Could not read segment due to IO error: {}", source)
-                                }
-                            }
-                            _ => {
-                                panic!(
-                                    "Could not read display set due to segment error: {}",
-                                    source,
-                                )
-                            }
-                        }
-                    }
-                    _ => panic!("Could not read display set due to bitstream error: {}", err)
+        }
+        totals.next_composition_number = Some(display_set.composition.number.wrapping_add(1));
+
+        if options.fix_dts {
+            totals.dts_scheduler.schedule(&mut display_set);
+        }
+
+        write_to_sinks(sinks, &display_set, event_id);
+    }
+
+    totals.timings.record("encode/write", stage_start.elapsed());
+    totals.timings.finish_epoch(epoch_pts);
+}
+
+fn open_output(value: &str) -> IoResult<Box<dyn Write>> {
+    if value == "-" {
+        Ok(Box::new(stdout()))
+    } else {
+        Ok(Box::new(File::create(value)?))
+    }
+}
+
+fn run_fix_continuity(matches: &ArgMatches) {
+
+    let input_value = matches.value_of("input").unwrap();
+    let (mut stdin_read, mut file_read);
+    let mut input = BufReader::<&mut dyn Read>::new(
+        if input_value == "-" {
+            stdin_read = stdin();
+            &mut stdin_read
+        } else {
+            file_read = File::open(input_value)
+                .expect("Could not open input file for writing.");
+            &mut file_read
+        }
+    );
+    let output_value = matches.value_of("output").unwrap();
+    let (mut stdout_write, mut file_write);
+    let mut output = BufWriter::<&mut dyn Write>::new(
+        if output_value == "-" {
+            stdout_write = stdout();
+            &mut stdout_write
+        } else {
+            file_write = File::create(output_value)
+                .expect("Could not open output file for writing.");
+            &mut file_write
+        }
+    );
+
+    match fix_continuity(&mut input, &mut output).and_then(|totals| {
+        output.flush()?;
+        Ok(totals)
+    }) {
+        Ok(totals) => {
+            eprintln!(
+                "Renumbered {} of {} display sets.",
+                totals.renumbered, totals.display_sets,
+            );
+        }
+        Err(err) => {
+            panic!("Could not fix composition continuity: {}", err)
+        }
+    }
+}
+
+fn run_concat(matches: &ArgMatches) {
+
+    let input_values = matches.values_of("inputs").unwrap().collect::<Vec<&str>>();
+    let output_value = matches.value_of("output").unwrap();
+
+    if input_values.iter().filter(|&&value| value == "-").count() > 1 {
+        panic!("STDIN can only be read once.");
+    }
+
+    let mut inputs = input_values.iter()
+        .map(|&value| {
+            BufReader::<Box<dyn Read>>::new(
+                if value == "-" {
+                    Box::new(stdin())
+                } else {
+                    Box::new(File::open(value).expect("Could not open input file for reading."))
                 }
-                break
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut output = BufWriter::new(
+        open_output(output_value).expect("Could not open output file for writing.")
+    );
+    let options = ConcatOptions {
+        offsets: matches.values_of("offset")
+            .map(|values| values.map(|value| parse_trim_point(value).unwrap()).collect())
+            .unwrap_or_default(),
+        gap: parse_trim_point(matches.value_of("gap").unwrap()).unwrap(),
+        allow_resolution_change: matches.is_present("allow-resolution-change"),
+    };
+
+    if options.offsets.len() >= input_values.len() {
+        panic!("--offset takes at most one value for each input after the first.");
+    }
+
+    match concat(&mut inputs, &mut output, &options) {
+        Ok(totals) => {
+            output.flush().expect("Could not write output file.");
+            for (value, offset) in input_values.iter().zip(totals.offsets.iter()).skip(1) {
+                eprintln!("Shifted {} by {}.", value, ts_to_timestamp(*offset));
             }
+            eprintln!("Joined {} display sets.", totals.display_sets);
+        }
+        Err(err) => panic!("Could not concatenate: {}", err),
+    }
+}
+
+fn run_export_json(matches: &ArgMatches) {
+
+    let input_value = matches.value_of("input").unwrap();
+    let (mut stdin_read, mut file_read);
+    let mut input = BufReader::<&mut dyn Read>::new(
+        if input_value == "-" {
+            stdin_read = stdin();
+            &mut stdin_read
+        } else {
+            file_read = File::open(input_value)
+                .expect("Could not open input file for reading.");
+            &mut file_read
+        }
+    );
+    let mut output = BufWriter::new(
+        open_output(matches.value_of("output").unwrap())
+            .expect("Could not open output file for writing.")
+    );
+    let read_options = ReadOptions { keep_unknown: true, ..Default::default() };
+    let mut count = 0;
+
+    output.write_all(b"[").expect("Could not write output file.");
+
+    for segment in input.segments(&read_options) {
+
+        let segment = segment.unwrap_or_else(|err| panic!("Could not read segment: {}", err));
+
+        write!(output, "{}\n{}", if count == 0 { "" } else { "," }, segment_json(&segment))
+            .expect("Could not write output file.");
+        count += 1;
+    }
+
+    output.write_all(b"\n]\n").expect("Could not write output file.");
+    output.flush().expect("Could not write output file.");
+
+    eprintln!("Exported {} segments.", count);
+}
+
+fn run_import_json(matches: &ArgMatches) {
+
+    let input_value = matches.value_of("input").unwrap();
+    let mut text = String::new();
+
+    if input_value == "-" {
+        stdin().read_to_string(&mut text)
+    } else {
+        File::open(input_value).and_then(|mut file| file.read_to_string(&mut text))
+    }.expect("Could not read input file.");
+
+    let segments = match parse_segments_json(&text) {
+        Ok(segments) => segments,
+        Err(err) => panic!("Could not read JSON file: {}", err),
+    };
+    let mut output = BufWriter::new(
+        open_output(matches.value_of("output").unwrap())
+            .expect("Could not open output file for writing.")
+    );
+
+    for segment in segments.iter() {
+        if let Err(err) = output.write_segment(segment) {
+            panic!("Could not write segment: {}", err)
+        }
+    }
+
+    output.flush().expect("Could not write output file.");
+
+    eprintln!("Imported {} segments.", segments.len());
+}
+
+fn run_report(matches: &ArgMatches) {
+
+    let input_value = matches.value_of("input").unwrap();
+    let (mut stdin_read, mut file_read);
+    let mut input = BufReader::<&mut dyn Read>::new(
+        if input_value == "-" {
+            stdin_read = stdin();
+            &mut stdin_read
+        } else {
+            file_read = File::open(input_value)
+                .expect("Could not open input file for reading.");
+            &mut file_read
+        }
+    );
+    let mut output = BufWriter::new(
+        open_output(matches.value_of("output").unwrap())
+            .expect("Could not open output file for writing.")
+    );
+
+    let occupancy_mode = matches.value_of("occupancy-json").map(|_| {
+        if matches.is_present("precise-occupancy") {
+            OccupancyMode::Precise
+        } else {
+            OccupancyMode::Fast
+        }
+    });
+    let stream_totals = report(&mut input, &mut output, matches.is_present("json"), occupancy_mode)
+        .and_then(|(stream_totals, _)| output.flush().map(|_| stream_totals))
+        .unwrap_or_else(|err| panic!("Could not write report: {}", err));
+
+    if let Some(path) = matches.value_of("occupancy-json") {
+
+        let occupancy = match matches.value_of("occupancy-step") {
+            Some(step) => sample_occupancy(stream_totals.occupancy, step.parse::<u32>().unwrap()),
+            None => stream_totals.occupancy,
         };
+        let mut output = BufWriter::new(
+            open_output(path).expect("Could not open occupancy file for writing.")
+        );
+
+        if let Err(err) = writeln!(output, "{}", occupancy_json(&occupancy))
+            .and_then(|_| output.flush()) {
+            panic!("Could not write occupancy: {}", err)
+        }
     }
 }
 
-fn cropped_offset(
-    screen_full_size: u16,
-    screen_crop_size: u16,
-    size: u16,
-    offset: u16,
-    margin: u16,
-) -> u16 {
+fn run_export_bdn(matches: &ArgMatches) {
+
+    let input_value = matches.value_of("input").unwrap();
+    let (mut stdin_read, mut file_read);
+    let mut input = BufReader::<&mut dyn Read>::new(
+        if input_value == "-" {
+            stdin_read = stdin();
+            &mut stdin_read
+        } else {
+            file_read = File::open(input_value)
+                .expect("Could not open input file for writing.");
+            &mut file_read
+        }
+    );
+    let mut sink = BdnSink::new(
+        PathBuf::from(matches.value_of("output").unwrap()),
+        FrameRate::from_name(matches.value_of("fps").unwrap()).unwrap(),
+        ObjectCache::shared(64 * 1_048_576),
+    );
+    let read_options = ReadOptions { lenient: true, ..Default::default() };
 
-    if size + 2 * margin > screen_crop_size {
-        eprintln!("WARNING: Window cannot fit within new margins.");
-        return 0
+    for display_set in input.display_sets(&read_options) {
+
+        let display_set = display_set
+            .unwrap_or_else(|err| panic!("Could not read display set: {}", err));
+
+        if let Err(err) = sink.write(&display_set, None) {
+            panic!("Could not write BDN export: {}", err.message)
+        }
+    }
+
+    if let Err(err) = sink.finish() {
+        panic!("Could not write BDN export: {}", err.message)
     }
 
-    let new_offset = offset - (screen_full_size - screen_crop_size) / 2;
+    eprintln!("Exported {} events.", sink.events());
+}
+
+fn run_import_bdn(matches: &ArgMatches) {
 
-    match new_offset {
-        o if o < margin =>
-            margin,
-        o if o + size + margin > screen_crop_size =>
-            screen_crop_size - size - margin,
-        _ =>
-            new_offset,
+    let path = Path::new(matches.value_of("input").unwrap());
+    let xml = read_to_string(path).expect("Could not read BDN file.");
+    let document = match parse_bdn(&xml) {
+        Ok(document) => document,
+        Err(err) => panic!("Could not read BDN file: {}", err),
+    };
+    let load = |file: &str| {
+        File::open(path.with_file_name(file))
+            .map_err(|err| err.to_string())
+            .and_then(|file| {
+                read_png(&mut BufReader::new(file)).map_err(|err| err.to_string())
+            })
+    };
+    let display_sets = match import_bdn(&document, load) {
+        Ok(display_sets) => display_sets,
+        Err(err) => panic!("Could not import BDN file: {}", err),
+    };
+    let mut output = BufWriter::new(
+        open_output(matches.value_of("output").unwrap())
+            .expect("Could not open output file for writing.")
+    );
+
+    for display_set in display_sets.iter() {
+        if let Err(err) = output.write_display_set(display_set) {
+            panic!("Could not write display set: {}", err)
+        }
+    }
+
+    output.flush().expect("Could not write output file.");
+
+    eprintln!(
+        "Imported {} events.",
+        display_sets.iter()
+            .filter(|display_set| display_set.composition.state == CompositionState::EpochStart)
+            .count(),
+    );
+}
+
+fn run_preview_palette(matches: &ArgMatches) {
+
+    let selector = Selector::parse(matches.value_of("preview-palette").unwrap()).unwrap();
+    let transforms = PaletteTransforms {
+        hue: matches.value_of("hue").map(|degrees| degrees.parse::<f64>().unwrap()),
+        saturation: matches.value_of("saturation").map(|factor| factor.parse::<f64>().unwrap()),
+        tint: matches.value_of("tint").and_then(parse_tint),
+        lum_scale: matches.value_of("lum-scale").map(|factor| factor.parse::<f64>().unwrap()),
+    };
+    let matrix = matches.value_of("matrix").and_then(ColorMatrix::from_name);
+    let range = Range::from_name(matches.value_of("range").unwrap()).unwrap();
+    let input_value = matches.value_of("input").unwrap();
+    let (mut stdin_read, mut file_read);
+    let mut input = Input::new(
+        BufReader::<&mut dyn Read>::new(
+            if input_value == "-" {
+                stdin_read = stdin();
+                &mut stdin_read
+            } else {
+                file_read = File::open(input_value)
+                    .expect("Could not open input file for writing.");
+                &mut file_read
+            }
+        ),
+        input_pid(matches),
+    );
+    let read_options = ReadOptions { lenient: true, ..Default::default() };
+    let style = Style::detect(false);
+    let mut state = EpochState::default();
+    let mut current = None::<DisplaySet>;
+    let mut index = 0;
+    let mut display_sets = input.display_sets(&read_options);
+    let display_sets = from_fn(move || {
+        let next = display_sets.next();
+        print_input_warnings(display_sets.get_mut());
+        next
+    });
+
+    // Each display set is looked at once the one after it has been read, so a last None stands
+    // for the end of the stream.
+    for next in display_sets.map(Some).chain(once(None)) {
+
+        let next = next.map(|display_set| {
+            display_set.unwrap_or_else(|err| panic!("Could not read display set: {}", err))
+        });
+
+        // The state still reflects the current display set until the next one is applied.
+        if let Some(display_set) = current.take() {
+
+            if selector.selects(index, &display_set, next.as_ref()) {
+                println!(
+                    "Display set {} at {}:",
+                    index, style.timestamp(display_set.pts),
+                );
+                let matrix = matrix.unwrap_or_else(|| ColorMatrix::for_width(display_set.width));
+
+                print_preview(
+                    &palette_preview(&state, &display_set, &transforms, matrix, range),
+                    &style,
+                );
+                return
+            }
+
+            index += 1;
+        }
+
+        if let Some(display_set) = next {
+            state.apply(&display_set);
+            current = Some(display_set);
+        }
+    }
+
+    eprintln!("No display set matches the preview selection.");
+    exit(1)
+}
+
+fn input_pid(matches: &ArgMatches) -> Option<u16> {
+    match matches.value_of("input-format").unwrap() {
+        "m2ts" => parse_pid(matches.value_of("pid").unwrap()),
+        _ => None,
+    }
+}
+
+fn verify_lossless(bytes: &[u8], pid: Option<u16>, options: &ReadOptions, force: bool) {
+
+    let mut stream = vec![];
+
+    Input::new(bytes, pid).read_to_end(&mut stream).expect("Could not demultiplex input file.");
+
+    match check_identity(&stream, options) {
+        Ok(identity) if identity.is_lossless() => {
+            eprintln!(
+                "Verified that all {} segments of {} display sets are written back exactly.",
+                identity.segments,
+                identity.display_sets,
+            );
+            return
+        }
+        Ok(identity) => {
+            for line in identity_report(&identity) {
+                eprintln!("{}", line);
+            }
+        }
+        Err(err) => {
+            eprintln!("Could not check whether the input is written back exactly: {}.", err);
+        }
+    }
+
+    if force {
+        eprintln!("WARNING: Carrying on anyway, as --force was given.");
+    } else {
+        eprintln!("Stopping; use --force to carry on anyway.");
+        exit(EXIT_LOSSY)
+    }
+}
+
+fn print_input_warnings<R: Read>(input: &mut Input<R>) {
+    for warning in input.take_warnings() {
+        eprintln!("WARNING: Dropped a display set from the transport stream: {}.", warning);
+    }
+}
+
+// Prints the input's warnings, returning how many bytes have been read from it.
+fn take_input_warnings<R: Read>(
+    input: &mut LossyReader<Input<BufReader<CountingReader<R>>>>,
+) -> u64 {
+
+    print_input_warnings(input.get_mut());
+
+    input.get_ref().get_ref().get_ref().count()
+}
+
+fn insert_clear(epoch: &mut Vec<DisplaySet>, epoch_start_pts: u32, totals: &mut EpochTotals) {
+
+    let last = match epoch.last() {
+        Some(display_set) if !display_set.composition.objects.is_empty() => display_set,
+        _ => return,
+    };
+    let frame = frame_duration(last.frame_rate);
+
+    // The last composition must still be shown for at least one frame before it is cleared.
+    if epoch_start_pts < last.pts.saturating_add(frame.saturating_mul(2)) {
+        eprintln!(
+            "WARNING: No room to clear the screen before the epoch starting at {}.",
+            ts_to_timestamp(epoch_start_pts),
+        );
+        return
+    }
+
+    let clear = clear_display_set(last, epoch_start_pts - frame);
+
+    epoch.push(clear);
+    totals.clears += 1;
+}
+
+// Tells apart windows that already overlapped in the input from ones that reframing pushed
+// together, by giving their coordinates both before and after.
+fn overlap_origin(
+    input: &EpochState,
+    output: &DisplaySet,
+    window_ids: (WindowId, WindowId),
+) -> String {
+
+    let describe = |window: &Window| {
+        format!("{}x{}+{}+{}", window.width, window.height, window.x, window.y)
+    };
+
+    match (
+        input.windows.get(&window_ids.0),
+        input.windows.get(&window_ids.1),
+        output.windows.get(&window_ids.0),
+        output.windows.get(&window_ids.1),
+    ) {
+        (Some(input_1), Some(input_2), Some(output_1), Some(output_2)) => {
+            format!(
+                "; now {} and {}, originally {} and {}, {}",
+                describe(output_1), describe(output_2), describe(input_1), describe(input_2),
+                if windows_overlap(input_1, input_2) {
+                    "which already overlapped"
+                } else {
+                    "which did not overlap"
+                },
+            )
+        }
+        _ => String::new(),
+    }
+}
+
+fn parse_tint(value: &str) -> Option<[u8; 3]> {
+
+    let mut parts = value.split(',').map(|part| part.trim().parse::<u8>());
+
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(red)), Some(Ok(green)), Some(Ok(blue)), None) => Some([red, green, blue]),
+        _ => None,
+    }
+}
+
+fn parse_size(value: &str) -> Option<Size> {
+
+    let mut parts = value.split('x');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(width), Some(height), None) => {
+            match (width.parse::<u16>(), height.parse::<u16>()) {
+                (Ok(width), Ok(height)) => Some(Size { width, height }),
+                _ => None,
+            }
+        }
+        _ => None,
     }
 }
@@ -0,0 +1,164 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::{
+    displayset::{Cid, CompositionObject, DisplaySet, Object, Window},
+    id::{VersionedId, WindowId},
+    segment::Sequence,
+    timeline::EpochState,
+};
+
+// One frame's worth of the decoder's 128 Mbit/s graphics transfer rate at 24 Hz.
+pub const MAX_WINDOW_AREA: u32 = 128_000_000 / 8 / 24;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeOutcome {
+    Unchanged,
+    Merged,
+    DroppedUpper,
+}
+
+pub fn merge_windows(display_set: &mut DisplaySet, state: &EpochState) -> MergeOutcome {
+
+    if display_set.windows.len() != 2 {
+        return MergeOutcome::Unchanged
+    }
+
+    let window_ids = display_set.windows.keys().copied().collect::<Vec<WindowId>>();
+    let cids = display_set.composition.objects.keys().cloned().collect::<Vec<Cid>>();
+
+    if cids.len() != 2 || cids[0].window_id == cids[1].window_id {
+        return MergeOutcome::Unchanged
+    }
+
+    let window_1 = display_set.windows[&window_ids[0]].clone();
+    let window_2 = display_set.windows[&window_ids[1]].clone();
+    let x1 = window_1.x.min(window_2.x);
+    let y1 = window_1.y.min(window_2.y);
+    let x2 = (window_1.x as u32 + window_1.width as u32)
+        .max(window_2.x as u32 + window_2.width as u32);
+    let y2 = (window_1.y as u32 + window_1.height as u32)
+        .max(window_2.y as u32 + window_2.height as u32);
+    let union = Window {
+        x: x1,
+        y: y1,
+        width: (x2 - x1 as u32) as u16,
+        height: (y2 - y1 as u32) as u16,
+    };
+
+    if union.width as u32 * union.height as u32 > MAX_WINDOW_AREA {
+
+        let upper_id = if window_1.y < window_2.y { window_ids[0] } else { window_ids[1] };
+
+        display_set.windows.remove(&upper_id);
+        display_set.composition.objects.retain(|cid, _| cid.window_id != upper_id);
+
+        return MergeOutcome::DroppedUpper
+    }
+
+    let palette = match state.palette(display_set) {
+        Some(palette) => palette,
+        None => return MergeOutcome::Unchanged,
+    };
+
+    // Palette entries that are never defined are rendered fully transparent by the decoder.
+    let transparent = match palette.entries.iter().find(|(_, entry)| entry.alpha == 0) {
+        Some((&index, _)) => index,
+        None => match (0..=255).find(|index| !palette.entries.contains_key(index)) {
+            Some(index) => index,
+            None => return MergeOutcome::Unchanged,
+        },
+    };
+    let mut lines = vec![vec![transparent; union.width as usize]; union.height as usize];
+
+    for cid in cids.iter() {
+
+        let object = match state.objects.get(&cid.object_id) {
+            Some(object) => object,
+            None => return MergeOutcome::Unchanged,
+        };
+        let composition_object = &display_set.composition.objects[cid];
+
+        draw(&mut lines, &union, object, composition_object, transparent);
+    }
+
+    let object_id = cids[0].object_id;
+    let version = display_set.objects.keys()
+        .find(|vid| vid.id == object_id)
+        .map_or(0, |vid| vid.version);
+
+    display_set.objects.retain(|vid, _| !cids.iter().any(|cid| cid.object_id == vid.id));
+    display_set.objects.insert(
+        VersionedId {
+            id: object_id,
+            version,
+        },
+        Object {
+            width: union.width,
+            height: union.height,
+            sequence: Sequence::Single,
+            lines,
+        },
+    );
+    // A merged object can only stay forced if everything merged into it was.
+    let forced = display_set.composition.objects.values().all(|co| co.forced);
+
+    display_set.composition.objects.clear();
+    display_set.composition.objects.insert(
+        Cid {
+            object_id,
+            window_id: window_ids[0],
+        },
+        CompositionObject {
+            x: union.x,
+            y: union.y,
+            crop: None,
+            forced,
+        },
+    );
+    display_set.windows.clear();
+    display_set.windows.insert(window_ids[0], union);
+
+    MergeOutcome::Merged
+}
+
+fn draw(
+    lines: &mut [Vec<u8>],
+    union: &Window,
+    object: &Object,
+    composition_object: &CompositionObject,
+    transparent: u8,
+) {
+
+    let (crop_x, crop_y, crop_width, crop_height) = match &composition_object.crop {
+        Some(crop) => (crop.x as usize, crop.y as usize, crop.width as usize, crop.height as usize),
+        None => (0, 0, object.width as usize, object.height as usize),
+    };
+
+    for (source_y, source_line) in object.lines.iter().enumerate().skip(crop_y).take(crop_height) {
+
+        let target_y = composition_object.y as i64 + (source_y - crop_y) as i64 - union.y as i64;
+
+        if target_y < 0 || target_y >= lines.len() as i64 {
+            continue
+        }
+
+        let target_line = &mut lines[target_y as usize];
+
+        for (source_x, &index) in source_line.iter().enumerate().skip(crop_x).take(crop_width) {
+
+            let target_x = composition_object.x as i64 + (source_x - crop_x) as i64
+                - union.x as i64;
+
+            if target_x >= 0 && target_x < target_line.len() as i64 && index != transparent {
+                target_line[target_x as usize] = index;
+            }
+        }
+    }
+}
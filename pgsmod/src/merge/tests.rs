@@ -0,0 +1,149 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::{Palette, PaletteEntry},
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    segment::CompositionState,
+};
+
+fn two_window_display_set(upper_y: u16) -> DisplaySet {
+
+    let mut display_set = DisplaySet {
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+    let mut palette = Palette::default();
+
+    palette.entries.insert(0, PaletteEntry { y: 16, cr: 128, cb: 128, alpha: 0 });
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+    palette.entries.insert(2, PaletteEntry { y: 81, cr: 240, cb: 90, alpha: 255 });
+
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.windows.insert(WindowId(0), Window { x: 10, y: upper_y, width: 3, height: 2 });
+    display_set.windows.insert(WindowId(1), Window { x: 12, y: 100, width: 2, height: 1 });
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object {
+            width: 3,
+            height: 2,
+            sequence: Sequence::Single,
+            lines: vec![vec![1, 1, 1], vec![1, 0, 1]],
+        },
+    );
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(1), version: 0 },
+        Object {
+            width: 2,
+            height: 1,
+            sequence: Sequence::Single,
+            lines: vec![vec![2, 2]],
+        },
+    );
+    display_set.composition.state = CompositionState::EpochStart;
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 10, y: upper_y, crop: None, forced: false },
+    );
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(1), window_id: WindowId(1) },
+        CompositionObject { x: 12, y: 100, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+#[test]
+fn test_merge_two_windows() {
+
+    let mut display_set = two_window_display_set(97);
+    let mut state = EpochState::default();
+
+    state.apply(&display_set);
+
+    assert_eq!(merge_windows(&mut display_set, &state), MergeOutcome::Merged);
+    assert_eq!(
+        display_set.windows.into_iter().collect::<Vec<(WindowId, Window)>>(),
+        vec![(WindowId(0), Window { x: 10, y: 97, width: 4, height: 4 })],
+    );
+    assert_eq!(
+        display_set.composition.objects.into_iter().collect::<Vec<(Cid, CompositionObject)>>(),
+        vec![(
+            Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+            CompositionObject { x: 10, y: 97, crop: None, forced: false },
+        )],
+    );
+    assert_eq!(
+        display_set.objects.into_iter().collect::<Vec<(VersionedId<ObjectId>, Object)>>(),
+        vec![(
+            VersionedId { id: ObjectId(0), version: 0 },
+            Object {
+                width: 4,
+                height: 4,
+                sequence: Sequence::Single,
+                lines: vec![
+                    vec![1, 1, 1, 0],
+                    vec![1, 0, 1, 0],
+                    vec![0, 0, 0, 0],
+                    vec![0, 0, 2, 2],
+                ],
+            },
+        )],
+    );
+}
+
+#[test]
+fn test_merge_uses_epoch_objects() {
+
+    let original = two_window_display_set(97);
+    let mut display_set = original.clone();
+    let mut state = EpochState::default();
+
+    state.apply(&original);
+    display_set.objects.clear();
+    display_set.composition.state = CompositionState::Normal;
+
+    assert_eq!(merge_windows(&mut display_set, &state), MergeOutcome::Merged);
+    assert_eq!(display_set.objects.len(), 1);
+}
+
+#[test]
+fn test_far_apart_windows_drop_upper() {
+
+    let mut display_set = two_window_display_set(97);
+    let mut state = EpochState::default();
+
+    display_set.windows.get_mut(&WindowId(0)).unwrap().x = 0;
+    display_set.windows.get_mut(&WindowId(0)).unwrap().y = 0;
+    display_set.windows.get_mut(&WindowId(1)).unwrap().x = 1900;
+    display_set.windows.get_mut(&WindowId(1)).unwrap().y = 1070;
+    state.apply(&display_set);
+
+    assert_eq!(merge_windows(&mut display_set, &state), MergeOutcome::DroppedUpper);
+    assert_eq!(display_set.windows.keys().copied().collect::<Vec<WindowId>>(), vec![WindowId(1)]);
+    assert_eq!(
+        display_set.composition.objects.keys().cloned().collect::<Vec<Cid>>(),
+        vec![Cid { object_id: ObjectId(1), window_id: WindowId(1) }],
+    );
+}
+
+#[test]
+fn test_single_window_unchanged() {
+
+    let mut display_set = two_window_display_set(97);
+    let mut state = EpochState::default();
+
+    display_set.windows.remove(&WindowId(1));
+    display_set.composition.objects.remove(&Cid { object_id: ObjectId(1), window_id: WindowId(1) });
+    state.apply(&display_set);
+
+    let expected = display_set.clone();
+
+    assert_eq!(merge_windows(&mut display_set, &state), MergeOutcome::Unchanged);
+    assert_eq!(display_set, expected);
+}
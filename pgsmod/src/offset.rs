@@ -0,0 +1,108 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::{
+    displayset::DisplaySet,
+    segment::CompositionState,
+    timestamp::Timestamp,
+};
+use std::convert::TryFrom;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EarlyPolicy {
+    Clamp,
+    Drop,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Offset {
+    // In 90 kHz ticks.
+    pub ticks: i64,
+    pub early: EarlyPolicy,
+}
+
+// Takes a whole number of milliseconds or an HH:MM:SS.mmm timestamp, either of which may have a
+// leading minus sign, and returns it in 90 kHz ticks. Every millisecond is exactly 90 ticks, so
+// nothing drifts however long the stream is.
+pub fn parse_offset(value: &str) -> Option<i64> {
+
+    let (sign, magnitude) = match value.strip_prefix('-') {
+        Some(magnitude) => (-1, magnitude),
+        None => (1, value),
+    };
+    let milliseconds = if magnitude.bytes().all(|byte| byte.is_ascii_digit()) {
+        magnitude.parse::<u64>().ok()?
+    } else {
+        magnitude.parse::<Timestamp>().ok()?.milliseconds()
+    };
+
+    i64::try_from(milliseconds.checked_mul(90)?).ok().map(|ticks| sign * ticks)
+}
+
+// Shifts the PTS and DTS of every display set of the epoch, returning how many display sets
+// would have started before zero, or the first PTS that would end up past what fits in 32 bits.
+// Early display sets are either moved to zero or dropped; when dropping, whatever they defined is
+// carried over to the first display set that is kept so that the rest of the epoch still decodes.
+pub fn offset_epoch(epoch: &mut Vec<DisplaySet>, offset: Offset) -> Result<usize, u32> {
+
+    let shift = |ts: u32| ts as i64 + offset.ticks;
+
+    if let Some(late) = epoch.iter().find(|display_set| shift(display_set.pts) > u32::MAX as i64) {
+        return Err(late.pts)
+    }
+
+    let early = epoch.iter()
+        .take_while(|display_set| shift(display_set.pts) < 0)
+        .count();
+
+    if offset.early == EarlyPolicy::Drop && early > 0 {
+
+        let dropped = epoch.drain(..early).collect::<Vec<DisplaySet>>();
+
+        if let Some(first) = epoch.first_mut() {
+            carry_definitions(&dropped, first);
+        }
+    }
+
+    for display_set in epoch.iter_mut() {
+        if display_set.dts != 0 {
+            display_set.dts = shift(display_set.dts).max(0) as u32;
+        }
+        display_set.pts = shift(display_set.pts).max(0) as u32;
+    }
+
+    Ok(early)
+}
+
+// Gives the first display set kept after dropping the ones before it every definition they made
+// that it doesn't redefine itself, and their epoch start, so that the rest of the epoch still
+// decodes.
+pub fn carry_definitions(dropped: &[DisplaySet], first: &mut DisplaySet) {
+
+    for display_set in dropped.iter().rev() {
+        for (id, window) in display_set.windows.iter() {
+            first.windows.entry(*id).or_insert_with(|| window.clone());
+        }
+        for (vid, palette) in display_set.palettes.iter() {
+            if !first.palettes.keys().any(|kept| kept.id == vid.id) {
+                first.palettes.insert(*vid, palette.clone());
+            }
+        }
+        for (vid, object) in display_set.objects.iter() {
+            if !first.objects.keys().any(|kept| kept.id == vid.id) {
+                first.objects.insert(*vid, object.clone());
+            }
+        }
+    }
+    if dropped.first().is_some_and(|display_set| {
+        display_set.composition.state == CompositionState::EpochStart
+    }) {
+        first.composition.state = CompositionState::EpochStart;
+    }
+}
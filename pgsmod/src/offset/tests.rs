@@ -0,0 +1,119 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::{Cid, CompositionObject, Object, Palette, Window},
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+};
+
+// An epoch start that defines everything, a palette update, and then a clear.
+fn epoch() -> Vec<DisplaySet> {
+
+    let mut start = DisplaySet { pts: 90_000, dts: 85_000, ..Default::default() };
+
+    start.composition.state = CompositionState::EpochStart;
+    start.windows.insert(WindowId(0), Window { x: 0, y: 0, width: 2, height: 1 });
+    start.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, Palette::default());
+    start.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width: 2, height: 1, lines: vec![vec![1, 1]], ..Default::default() },
+    );
+    start.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 0, y: 0, crop: None, forced: false },
+    );
+
+    let mut update = DisplaySet { pts: 180_000, ..Default::default() };
+
+    update.palette_update_id = Some(PaletteId(0));
+    update.palettes.insert(VersionedId { id: PaletteId(0), version: 1 }, Palette::default());
+    update.composition.objects = start.composition.objects.clone();
+
+    vec![start, update, DisplaySet { pts: 270_000, ..Default::default() }]
+}
+
+#[test]
+fn test_parse_offset() {
+    assert_eq!(parse_offset("0"), Some(0));
+    assert_eq!(parse_offset("1500"), Some(135_000));
+    assert_eq!(parse_offset("-1500"), Some(-135_000));
+    assert_eq!(parse_offset("00:00:01.500"), Some(135_000));
+    assert_eq!(parse_offset("-01:00:00,001"), Some(-324_000_090));
+    assert_eq!(parse_offset(""), None);
+    assert_eq!(parse_offset("-"), None);
+    assert_eq!(parse_offset("+1500"), None);
+    assert_eq!(parse_offset("1.5"), None);
+    assert_eq!(parse_offset("--1500"), None);
+}
+
+#[test]
+fn test_offset_is_exact() {
+
+    // Two hours in milliseconds, then as a timestamp.
+    assert_eq!(parse_offset("7200000"), Some(648_000_000));
+    assert_eq!(parse_offset("02:00:00.000"), Some(648_000_000));
+}
+
+#[test]
+fn test_offset_epoch() {
+
+    let mut epoch = epoch();
+
+    assert_eq!(offset_epoch(&mut epoch, Offset { ticks: 9_000, early: EarlyPolicy::Clamp }), Ok(0));
+    assert_eq!(
+        epoch.iter().map(|display_set| (display_set.pts, display_set.dts)).collect::<Vec<_>>(),
+        vec![(99_000, 94_000), (189_000, 0), (279_000, 0)],
+    );
+}
+
+#[test]
+fn test_offset_epoch_clamps_early() {
+
+    let mut epoch = epoch();
+
+    assert_eq!(
+        offset_epoch(&mut epoch, Offset { ticks: -180_000, early: EarlyPolicy::Clamp }),
+        Ok(1),
+    );
+    assert_eq!(
+        epoch.iter().map(|display_set| (display_set.pts, display_set.dts)).collect::<Vec<_>>(),
+        vec![(0, 0), (0, 0), (90_000, 0)],
+    );
+}
+
+#[test]
+fn test_offset_epoch_drops_early() {
+
+    let mut epoch = epoch();
+
+    assert_eq!(
+        offset_epoch(&mut epoch, Offset { ticks: -180_001, early: EarlyPolicy::Drop }),
+        Ok(2),
+    );
+    assert_eq!(epoch.len(), 1);
+    assert_eq!(epoch[0].pts, 89_999);
+    assert_eq!(epoch[0].composition.state, CompositionState::EpochStart);
+    assert!(epoch[0].windows.contains_key(&WindowId(0)));
+    assert!(epoch[0].objects.contains_key(&VersionedId { id: ObjectId(0), version: 0 }));
+    assert_eq!(
+        epoch[0].palettes.keys().cloned().collect::<Vec<_>>(),
+        vec![VersionedId { id: PaletteId(0), version: 1 }],
+    );
+}
+
+#[test]
+fn test_offset_epoch_rejects_late() {
+
+    let mut epoch = epoch();
+    let ticks = u32::MAX as i64 - 180_000 + 1;
+
+    assert_eq!(
+        offset_epoch(&mut epoch, Offset { ticks, early: EarlyPolicy::Clamp }),
+        Err(180_000),
+    );
+    assert_eq!(epoch[0].pts, 90_000);
+}
@@ -0,0 +1,66 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::{
+    displayset::DisplaySet,
+    event::{enforce_timing, events, Event, TimingAdjustment, TimingRules},
+};
+
+// Applies minimum durations and gaps to the events of an epoch by moving the display sets that
+// clear them, given the PTS of whatever follows the epoch. A clear is never moved past a display
+// set next to it, so when something other than the next event sits in the way it moves less than
+// asked. Each adjustment is returned with the PTS of the event it applies to, with the amounts of
+// any moves being what was actually applied.
+pub fn pace_epoch(
+    epoch: &mut [DisplaySet],
+    rules: TimingRules,
+    next: Option<u32>,
+) -> Vec<(u32, TimingAdjustment)> {
+
+    let mut events = events(epoch.iter()).collect::<Vec<Event>>();
+    let ends = events.iter().map(|event| event.end_pts).collect::<Vec<Option<u32>>>();
+    let mut paced = Vec::new();
+
+    for adjustment in enforce_timing(&mut events, next, rules) {
+
+        let index = adjustment.index();
+        let event = &events[index];
+
+        if !matches!(
+            adjustment,
+            TimingAdjustment::Extended { .. } | TimingAdjustment::Shortened { .. },
+        ) {
+            paced.push((event.start_pts, adjustment));
+            continue
+        }
+
+        let (end, new_end) = (ends[index].unwrap(), event.end_pts.unwrap());
+        let clear = epoch.iter()
+            .position(|display_set| display_set.pts == end && display_set.pts > event.start_pts)
+            .unwrap();
+        let floor = epoch[clear - 1].pts.saturating_add(1);
+        let ceiling = epoch.get(clear + 1).map(|display_set| display_set.pts).or(next)
+            .map_or(u32::MAX, |pts| pts.saturating_sub(1));
+        let pts = new_end.clamp(floor, ceiling.max(floor));
+        let display_set = &mut epoch[clear];
+
+        if display_set.dts != 0 {
+            display_set.dts = pts.saturating_sub(display_set.pts.saturating_sub(display_set.dts));
+        }
+        display_set.pts = pts;
+
+        if pts > end {
+            paced.push((event.start_pts, TimingAdjustment::Extended { index, by: pts - end }));
+        } else if pts < end {
+            paced.push((event.start_pts, TimingAdjustment::Shortened { index, by: end - pts }));
+        }
+    }
+
+    paced
+}
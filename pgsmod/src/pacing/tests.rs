@@ -0,0 +1,87 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::Cid,
+    id::{ObjectId, WindowId},
+};
+
+const RULES: TimingRules = TimingRules { min_duration: 90_000, min_gap: 7_200 };
+
+// Display sets at the given times, each showing something or not.
+fn epoch(times: &[(u32, bool)]) -> Vec<DisplaySet> {
+    times.iter()
+        .map(|&(pts, shows)| {
+
+            let mut display_set = DisplaySet {
+                pts,
+                dts: pts.saturating_sub(900),
+                ..Default::default()
+            };
+
+            if shows {
+                display_set.composition.objects.insert(
+                    Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+                    Default::default(),
+                );
+            }
+
+            display_set
+        })
+        .collect()
+}
+
+fn times(epoch: &[DisplaySet]) -> Vec<(u32, u32)> {
+    epoch.iter().map(|display_set| (display_set.pts, display_set.dts)).collect()
+}
+
+#[test]
+fn test_pace_epoch_moves_clears() {
+
+    let mut epoch = epoch(&[(0, true), (45_000, false), (200_000, true), (295_000, false)]);
+
+    assert_eq!(
+        pace_epoch(&mut epoch, RULES, Some(300_000)),
+        vec![
+            (0, TimingAdjustment::Extended { index: 0, by: 45_000 }),
+            (200_000, TimingAdjustment::Shortened { index: 1, by: 2_200 }),
+        ],
+    );
+    assert_eq!(
+        times(&epoch),
+        vec![(0, 0), (90_000, 89_100), (200_000, 199_100), (292_800, 291_900)],
+    );
+}
+
+#[test]
+fn test_pace_epoch_keeps_display_set_order() {
+
+    // Another clear follows the first one before the next event starts.
+    let mut epoch = epoch(&[(0, true), (45_000, false), (50_000, false), (200_000, true)]);
+
+    assert_eq!(
+        pace_epoch(&mut epoch, RULES, None),
+        vec![(0, TimingAdjustment::Extended { index: 0, by: 4_999 })],
+    );
+    assert_eq!(epoch[1].pts, 49_999);
+}
+
+#[test]
+fn test_pace_epoch_reports_what_it_cannot_fix() {
+
+    let mut epoch = epoch(&[(0, true), (45_000, true), (90_000, false)]);
+
+    // The first event is replaced by the second, so it has no clear to move.
+    assert_eq!(
+        pace_epoch(&mut epoch, RULES, None),
+        vec![
+            (0, TimingAdjustment::TooShort { index: 0, by: 45_000 }),
+            (45_000, TimingAdjustment::Extended { index: 1, by: 45_000 }),
+        ],
+    );
+    assert_eq!(epoch[2].pts, 135_000);
+}
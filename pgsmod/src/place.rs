@@ -0,0 +1,223 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::crop::{anchored_offset, unfit_offset, Anchor, Placement, UnfitPolicy};
+use pgs::displayset::DisplaySet;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preset {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl Preset {
+
+    pub const NAMES: [&'static str; 6] = [
+        "top-left",
+        "top-center",
+        "top-right",
+        "bottom-left",
+        "bottom-center",
+        "bottom-right",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "top-left" => Some(Preset::TopLeft),
+            "top-center" => Some(Preset::TopCenter),
+            "top-right" => Some(Preset::TopRight),
+            "bottom-left" => Some(Preset::BottomLeft),
+            "bottom-center" => Some(Preset::BottomCenter),
+            "bottom-right" => Some(Preset::BottomRight),
+            _ => None,
+        }
+    }
+
+    fn anchors(self) -> (Anchor, Anchor) {
+        match self {
+            Preset::TopLeft => (Anchor::Start, Anchor::Start),
+            Preset::TopCenter => (Anchor::Center, Anchor::Start),
+            Preset::TopRight => (Anchor::End, Anchor::Start),
+            Preset::BottomLeft => (Anchor::Start, Anchor::End),
+            Preset::BottomCenter => (Anchor::Center, Anchor::End),
+            Preset::BottomRight => (Anchor::End, Anchor::End),
+        }
+    }
+}
+
+// Where an event goes vertically, keeping whatever horizontal position it has.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position {
+    Bottom,
+    Top,
+    // Centered within the black bar below or above a letterboxed picture.
+    BarsBottom,
+    BarsTop,
+}
+
+impl Position {
+
+    pub const NAMES: [&'static str; 4] = ["bottom", "top", "bars-bottom", "bars-top"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bottom" => Some(Position::Bottom),
+            "top" => Some(Position::Top),
+            "bars-bottom" => Some(Position::BarsBottom),
+            "bars-top" => Some(Position::BarsTop),
+            _ => None,
+        }
+    }
+}
+
+// The usual aspect ratio of a film letterboxed into a 16:9 frame.
+pub const BARS_ASPECT: f64 = 2.39;
+
+// The height of each black bar above and below a picture of the given aspect ratio, letterboxed
+// into the full width of the screen.
+pub fn bar_height(width: u16, height: u16, aspect: f64) -> u16 {
+
+    let picture = (width as f64 / aspect).round();
+
+    ((height as f64 - picture) / 2.0).floor().clamp(0.0, height as f64) as u16
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+// The area covered by the windows that the display set defines.
+pub fn event_bounds(display_set: &DisplaySet) -> Option<Bounds> {
+
+    let windows = display_set.windows.values();
+    let x1 = windows.clone().map(|window| window.x as u32).min()?;
+    let y1 = windows.clone().map(|window| window.y as u32).min()?;
+    let x2 = windows.clone().map(|window| window.x as u32 + window.width as u32).max()?;
+    let y2 = windows.map(|window| window.y as u32 + window.height as u32).max()?;
+
+    Some(
+        Bounds {
+            x: x1 as u16,
+            y: y1 as u16,
+            width: (x2 - x1).min(u16::MAX as u32) as u16,
+            height: (y2 - y1).min(u16::MAX as u32) as u16,
+        }
+    )
+}
+
+// Dialogue sits near the top or bottom of the screen; anything centered in the middle half is
+// taken to be positioned on purpose, like a sign, and is left where it is. So is anything forced,
+// as forced subtitles translate signs and on-screen text wherever they are placed.
+pub fn is_sign(display_set: &DisplaySet) -> bool {
+
+    if display_set.composition.objects.values().any(|co| co.forced) {
+        return true
+    }
+
+    match event_bounds(display_set) {
+        Some(bounds) => {
+            let middle = bounds.y as u32 * 2 + bounds.height as u32;
+            let height = display_set.height as u32;
+            middle > height / 2 && middle < height * 3 / 2
+        }
+        None => false,
+    }
+}
+
+// Moves every window and composition object of the display set together so that their bounds
+// land on the preset's location. Display sets without windows of their own are left alone and
+// return None; otherwise the placements are returned and nothing moves if either is unfit.
+pub fn place_event(
+    display_set: &mut DisplaySet,
+    preset: Preset,
+    margin: u16,
+    policy: UnfitPolicy,
+) -> Option<(Placement, Placement)> {
+
+    let bounds = event_bounds(display_set)?;
+    let (x_anchor, y_anchor) = preset.anchors();
+    let x = anchored_offset(display_set.width, bounds.width, margin, x_anchor, policy);
+    let y = anchored_offset(display_set.height, bounds.height, margin, y_anchor, policy);
+
+    if let (Some(new_x), Some(new_y)) = (x.offset(), y.offset()) {
+        shift_event(
+            display_set,
+            new_x as i32 - bounds.x as i32,
+            new_y as i32 - bounds.y as i32,
+        );
+    }
+
+    Some((x, y))
+}
+
+// Moves every window and composition object of the display set up or down together so that their
+// bounds land on the position, then by the vertical offset, which is positive going down. The
+// result is clamped within the margins. As with place_event, the placements are returned, of
+// which the horizontal one always fits as it stays where it is.
+pub fn position_event(
+    display_set: &mut DisplaySet,
+    position: Position,
+    vertical_offset: i32,
+    bars_aspect: f64,
+    margin: u16,
+    policy: UnfitPolicy,
+) -> Option<(Placement, Placement)> {
+
+    let bounds = event_bounds(display_set)?;
+    let height = display_set.height as i32;
+    let y = if bounds.height as u32 + 2 * margin as u32 > height as u32 {
+        unfit_offset(display_set.height, bounds.height, policy)
+    } else {
+
+        let (top, bottom) = (margin as i32, height - margin as i32 - bounds.height as i32);
+        let bar = bar_height(display_set.width, display_set.height, bars_aspect) as i32;
+        let target = vertical_offset + match position {
+            Position::Bottom => bottom,
+            Position::Top => top,
+            Position::BarsBottom => height - bar + (bar - bounds.height as i32) / 2,
+            Position::BarsTop => (bar - bounds.height as i32) / 2,
+        };
+
+        if target < top {
+            Placement::Clamped(top as u16)
+        } else if target > bottom {
+            Placement::Clamped(bottom as u16)
+        } else {
+            Placement::Fits(target as u16)
+        }
+    };
+
+    if let Some(new_y) = y.offset() {
+        shift_event(display_set, 0, new_y as i32 - bounds.y as i32);
+    }
+
+    Some((Placement::Fits(bounds.x), y))
+}
+
+fn shift_event(display_set: &mut DisplaySet, shift_x: i32, shift_y: i32) {
+
+    let shift = |offset: u16, by: i32| (offset as i32 + by).max(0) as u16;
+
+    for window in display_set.windows.values_mut() {
+        window.x = shift(window.x, shift_x);
+        window.y = shift(window.y, shift_y);
+    }
+    for composition_object in display_set.composition.objects.values_mut() {
+        composition_object.x = shift(composition_object.x, shift_x);
+        composition_object.y = shift(composition_object.y, shift_y);
+    }
+}
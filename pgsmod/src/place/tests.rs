@@ -0,0 +1,245 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::{Cid, CompositionObject, Window},
+    id::{ObjectId, WindowId},
+};
+
+// A two-line event: 400x100 across two windows with an object in each.
+fn event(width: u16, height: u16) -> DisplaySet {
+
+    let mut display_set = DisplaySet { width, height, ..Default::default() };
+
+    display_set.windows.insert(WindowId(0), Window { x: 10, y: 20, width: 400, height: 40 });
+    display_set.windows.insert(WindowId(1), Window { x: 60, y: 80, width: 300, height: 40 });
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 10, y: 20, crop: None, forced: false },
+    );
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(1), window_id: WindowId(1) },
+        CompositionObject { x: 70, y: 85, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+fn placed(width: u16, height: u16, preset: Preset) -> (u16, u16) {
+
+    let mut display_set = event(width, height);
+
+    place_event(&mut display_set, preset, 30, UnfitPolicy::Error).unwrap();
+
+    let bounds = event_bounds(&display_set).unwrap();
+
+    assert_eq!((bounds.width, bounds.height), (400, 100));
+    assert_eq!(
+        display_set.composition.objects[&Cid { object_id: ObjectId(1), window_id: WindowId(1) }].x,
+        display_set.windows[&WindowId(1)].x + 10,
+    );
+
+    (bounds.x, bounds.y)
+}
+
+#[test]
+fn test_presets_1080p() {
+    assert_eq!(placed(1920, 1080, Preset::TopLeft), (30, 30));
+    assert_eq!(placed(1920, 1080, Preset::TopCenter), (760, 30));
+    assert_eq!(placed(1920, 1080, Preset::TopRight), (1490, 30));
+    assert_eq!(placed(1920, 1080, Preset::BottomLeft), (30, 950));
+    assert_eq!(placed(1920, 1080, Preset::BottomCenter), (760, 950));
+    assert_eq!(placed(1920, 1080, Preset::BottomRight), (1490, 950));
+}
+
+#[test]
+fn test_presets_480p() {
+    assert_eq!(placed(720, 480, Preset::TopLeft), (30, 30));
+    assert_eq!(placed(720, 480, Preset::TopCenter), (160, 30));
+    assert_eq!(placed(720, 480, Preset::TopRight), (290, 30));
+    assert_eq!(placed(720, 480, Preset::BottomLeft), (30, 350));
+    assert_eq!(placed(720, 480, Preset::BottomCenter), (160, 350));
+    assert_eq!(placed(720, 480, Preset::BottomRight), (290, 350));
+}
+
+#[test]
+fn test_presets_barely_fit() {
+    for name in Preset::NAMES.iter() {
+        assert_eq!(placed(460, 160, Preset::from_name(name).unwrap()), (30, 30));
+    }
+}
+
+#[test]
+fn test_unfit_event_stays() {
+
+    let mut display_set = event(459, 1080);
+
+    assert_eq!(
+        place_event(&mut display_set, Preset::BottomCenter, 30, UnfitPolicy::Drop),
+        Some((Placement::Unfit, Placement::Fits(950))),
+    );
+    assert_eq!(display_set, event(459, 1080));
+    assert_eq!(
+        place_event(&mut DisplaySet::default(), Preset::BottomCenter, 30, UnfitPolicy::Drop),
+        None,
+    );
+}
+
+#[test]
+fn test_is_sign() {
+
+    let mut display_set = event(1920, 1080);
+
+    assert!(!is_sign(&display_set));
+
+    place_event(&mut display_set, Preset::BottomCenter, 30, UnfitPolicy::Error);
+
+    assert!(!is_sign(&display_set));
+
+    for window in display_set.windows.values_mut() {
+        window.y -= 450;
+    }
+
+    assert!(is_sign(&display_set));
+}
+
+#[test]
+fn test_forced_bottom_line_is_sign() {
+
+    let mut display_set = line();
+
+    position_event(&mut display_set, Position::Bottom, 0, BARS_ASPECT, 30, UnfitPolicy::Error);
+
+    assert!(!is_sign(&display_set));
+
+    for composition_object in display_set.composition.objects.values_mut() {
+        composition_object.forced = true;
+    }
+
+    assert!(is_sign(&display_set));
+}
+
+// A one-line event: 400x40 in a single window, centered near the top of the screen.
+fn line() -> DisplaySet {
+
+    let mut display_set = DisplaySet { width: 1920, height: 1080, ..Default::default() };
+
+    display_set.windows.insert(WindowId(0), Window { x: 760, y: 100, width: 400, height: 40 });
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 760, y: 100, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+fn positioned(
+    mut display_set: DisplaySet,
+    position: Position,
+    vertical_offset: i32,
+) -> (DisplaySet, Placement) {
+
+    let (x, y) = position_event(
+        &mut display_set,
+        position,
+        vertical_offset,
+        BARS_ASPECT,
+        30,
+        UnfitPolicy::Error,
+    ).unwrap();
+
+    assert!(matches!(x, Placement::Fits(_)));
+
+    (display_set, y)
+}
+
+#[test]
+fn test_bar_height() {
+    assert_eq!(bar_height(1920, 1080, 2.39), 138);
+    assert_eq!(bar_height(720, 480, 2.39), 89);
+    assert_eq!(bar_height(1920, 1080, 16.0 / 9.0), 0);
+    assert_eq!(bar_height(1920, 1080, 1.0), 0);
+}
+
+#[test]
+fn test_positions_one_window() {
+
+    let y = |position, vertical_offset| {
+
+        let (display_set, y) = positioned(line(), position, vertical_offset);
+        let window = &display_set.windows[&WindowId(0)];
+        let cid = Cid { object_id: ObjectId(0), window_id: WindowId(0) };
+        let composition_object = &display_set.composition.objects[&cid];
+
+        assert_eq!((window.x, composition_object.x), (760, 760));
+        assert_eq!(window.y, composition_object.y);
+        assert_eq!(Placement::Fits(window.y), y);
+
+        window.y
+    };
+
+    assert_eq!(y(Position::Bottom, 0), 1010);
+    assert_eq!(y(Position::Top, 0), 30);
+    assert_eq!(y(Position::BarsBottom, 0), 991);
+    assert_eq!(y(Position::BarsTop, 0), 49);
+    assert_eq!(y(Position::Bottom, -20), 990);
+    assert_eq!(y(Position::BarsTop, 10), 59);
+}
+
+#[test]
+fn test_positions_two_windows_move_together() {
+
+    let (display_set, y) = positioned(event(1920, 1080), Position::Bottom, 0);
+    let object = |id: u8| {
+        let cid = Cid { object_id: ObjectId(id as u16), window_id: WindowId(id) };
+        &display_set.composition.objects[&cid]
+    };
+    let windows = (&display_set.windows[&WindowId(0)], &display_set.windows[&WindowId(1)]);
+
+    assert_eq!(y, Placement::Fits(950));
+    assert_eq!(event_bounds(&display_set), Some(Bounds { x: 10, y: 950, width: 400, height: 100 }));
+    assert_eq!((windows.0.y, windows.1.y), (950, 1010));
+    assert_eq!((object(0).x, object(0).y), (10, 950));
+    assert_eq!((object(1).x, object(1).y), (70, 1015));
+}
+
+#[test]
+fn test_positions_past_the_top_margin_are_clamped() {
+
+    // Two lines are too tall to fit within the bar above the picture and its margin.
+    let (display_set, y) = positioned(event(1920, 1080), Position::BarsTop, 0);
+
+    assert_eq!(y, Placement::Clamped(30));
+    assert_eq!(event_bounds(&display_set), Some(Bounds { x: 10, y: 30, width: 400, height: 100 }));
+    assert_eq!(display_set.windows[&WindowId(1)].y, 90);
+
+    let (display_set, y) = positioned(event(1920, 1080), Position::Top, -50);
+
+    assert_eq!(y, Placement::Clamped(30));
+    assert_eq!(event_bounds(&display_set).unwrap().y, 30);
+
+    let (_, y) = positioned(event(1920, 1080), Position::Bottom, 500);
+
+    assert_eq!(y, Placement::Clamped(950));
+}
+
+#[test]
+fn test_unfit_position() {
+
+    let mut display_set = event(1920, 159);
+
+    assert_eq!(
+        position_event(&mut display_set, Position::Bottom, 0, BARS_ASPECT, 30, UnfitPolicy::Drop),
+        Some((Placement::Fits(10), Placement::Unfit)),
+    );
+    assert_eq!(display_set, event(1920, 159));
+    assert_eq!(
+        position_event(&mut display_set, Position::Top, 0, BARS_ASPECT, 30, UnfitPolicy::Center),
+        Some((Placement::Fits(10), Placement::Fallback(29))),
+    );
+    assert_eq!(event_bounds(&display_set).unwrap().y, 29);
+}
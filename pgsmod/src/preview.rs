@@ -0,0 +1,174 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::{
+    color::{rgb_bytes, ColorMatrix, Range},
+    displayset::DisplaySet,
+    style::Style,
+    timeline::EpochState,
+};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selector {
+    Index(usize),
+    // The display set on screen at this PTS.
+    Pts(u32),
+}
+
+impl Selector {
+
+    // Takes either a zero-based display set index or an HH:MM:SS.mmm timestamp.
+    pub fn parse(value: &str) -> Option<Self> {
+
+        if let Ok(index) = value.parse::<usize>() {
+            return Some(Selector::Index(index))
+        }
+
+        let (hms, ms) = value.split_once('.').unwrap_or((value, "0"));
+        let parts = hms.split(':')
+            .map(|part| part.parse::<u64>().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        let ms = format!("{:0<3}", ms).get(..3)?.parse::<u64>().ok()?;
+        let seconds = match parts.as_slice() {
+            [h, m, s] if *m < 60 && *s < 60 => h * 3600 + m * 60 + s,
+            [m, s] if *s < 60 => m * 60 + s,
+            _ => return None,
+        };
+        let pts = (seconds * 1000 + ms) * 90;
+
+        if pts > u32::MAX as u64 {
+            return None
+        }
+
+        Some(Selector::Pts(pts as u32))
+    }
+
+    // Selecting by PTS needs the next display set to know how long this one stays on screen.
+    pub fn selects(
+        self,
+        index: usize,
+        display_set: &DisplaySet,
+        next: Option<&DisplaySet>,
+    ) -> bool {
+        match self {
+            Selector::Index(wanted) => index == wanted,
+            Selector::Pts(pts) => {
+                display_set.pts <= pts && next.is_none_or(|next| next.pts > pts)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreviewRow {
+    pub index: u8,
+    pub old: [u8; 3],
+    pub new: [u8; 3],
+    pub alpha: u8,
+    pub pixels: usize,
+}
+
+// The palette transforms that a preview shows, applied in the same order as a real run does.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PaletteTransforms {
+    pub hue: Option<f64>,
+    pub saturation: Option<f64>,
+    pub tint: Option<[u8; 3]>,
+    pub lum_scale: Option<f64>,
+}
+
+// Lists each palette entry that the display set's composition objects actually draw with, before
+// and after the palette transforms. The state must be the epoch state after the display set has
+// been applied.
+pub fn palette_preview(
+    state: &EpochState,
+    display_set: &DisplaySet,
+    transforms: &PaletteTransforms,
+    matrix: ColorMatrix,
+    range: Range,
+) -> Vec<PreviewRow> {
+
+    let palette = match state.palette(display_set) {
+        Some(palette) => palette,
+        None => return Vec::new(),
+    };
+    let mut transformed = palette.clone();
+    let mut usage = BTreeMap::<u8, usize>::new();
+
+    if transforms.hue.is_some() || transforms.saturation.is_some() {
+        transformed.recolor(
+            transforms.hue.unwrap_or(0.0),
+            transforms.saturation.unwrap_or(1.0),
+            1.0,
+            matrix,
+            range,
+        );
+    }
+    if let Some(rgb) = transforms.tint {
+        transformed.tint(rgb, matrix, range);
+    }
+    if let Some(factor) = transforms.lum_scale {
+        transformed.scale_luminance(factor, matrix, range);
+    }
+
+    for (cid, composition_object) in display_set.composition.objects.iter() {
+        if let Some(object) = state.objects.get(&cid.object_id) {
+
+            let (x, y, width, height) = match &composition_object.crop {
+                Some(crop) => (crop.x, crop.y, crop.width, crop.height),
+                None => (0, 0, object.width, object.height),
+            };
+
+            for line in object.lines.iter().skip(y as usize).take(height as usize) {
+                for index in line.iter().skip(x as usize).take(width as usize) {
+                    *usage.entry(*index).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    usage.iter()
+        .filter_map(|(index, &pixels)| {
+            let old = palette.entries.get(index)?;
+            let new = transformed.entries.get(index)?;
+            Some(
+                PreviewRow {
+                    index: *index,
+                    old: rgb_bytes(old, matrix, range),
+                    new: rgb_bytes(new, matrix, range),
+                    alpha: old.alpha,
+                    pixels,
+                }
+            )
+        })
+        .collect()
+}
+
+pub fn print_preview(rows: &[PreviewRow], style: &Style) {
+
+    println!("index  old         new         alpha   pixels");
+
+    for row in rows.iter() {
+        println!(
+            "{:>5}  {} {:<2}  {} {:<2}  {:>5}  {:>7}",
+            row.index,
+            hex(row.old),
+            style.swatch(row.old),
+            hex(row.new),
+            style.swatch(row.new),
+            row.alpha,
+            row.pixels,
+        );
+    }
+}
+
+fn hex(rgb: [u8; 3]) -> String {
+    format!("#{:02X}{:02X}{:02X}", rgb[0], rgb[1], rgb[2])
+}
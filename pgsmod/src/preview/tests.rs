@@ -0,0 +1,124 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::{Cid, CompositionObject, Object, Palette, PaletteEntry},
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    segment::{CompositionState, Crop, Sequence},
+};
+
+fn display_set() -> DisplaySet {
+
+    let mut display_set = DisplaySet { pts: 90_000, ..Default::default() };
+    let mut palette = Palette::default();
+
+    palette.entries.insert(0, PaletteEntry { y: 16, cr: 128, cb: 128, alpha: 0 });
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+    palette.entries.insert(2, PaletteEntry { y: 126, cr: 128, cb: 128, alpha: 128 });
+
+    display_set.composition.state = CompositionState::EpochStart;
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object {
+            width: 4,
+            height: 2,
+            sequence: Sequence::Single,
+            lines: vec![vec![0, 1, 1, 0], vec![0, 1, 1, 2]],
+        },
+    );
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject::default(),
+    );
+
+    display_set
+}
+
+#[test]
+fn test_selector_parse() {
+    assert_eq!(Selector::parse("12"), Some(Selector::Index(12)));
+    assert_eq!(Selector::parse("00:01:02.5"), Some(Selector::Pts(62_500 * 90)));
+    assert_eq!(Selector::parse("1:02.250"), Some(Selector::Pts(62_250 * 90)));
+    assert_eq!(Selector::parse("00:61:00.000"), None);
+    assert_eq!(Selector::parse("soon"), None);
+}
+
+#[test]
+fn test_selector_pts() {
+
+    let first = DisplaySet { pts: 900, ..Default::default() };
+    let second = DisplaySet { pts: 1800, ..Default::default() };
+
+    assert!(Selector::Pts(1000).selects(0, &first, Some(&second)));
+    assert!(!Selector::Pts(1800).selects(0, &first, Some(&second)));
+    assert!(Selector::Pts(5000).selects(1, &second, None));
+    assert!(!Selector::Pts(800).selects(0, &first, Some(&second)));
+}
+
+fn preview(
+    state: &EpochState,
+    display_set: &DisplaySet,
+    transforms: &PaletteTransforms,
+) -> Vec<PreviewRow> {
+    palette_preview(state, display_set, transforms, ColorMatrix::Bt709, Range::Limited)
+}
+
+#[test]
+fn test_palette_preview() {
+
+    let display_set = display_set();
+    let mut state = EpochState::default();
+
+    state.apply(&display_set);
+
+    let halved = PaletteTransforms { lum_scale: Some(0.5), ..Default::default() };
+    let rows = preview(&state, &display_set, &halved);
+
+    assert_eq!(rows.iter().map(|row| row.index).collect::<Vec<u8>>(), vec![0, 1, 2]);
+    assert_eq!(rows.iter().map(|row| row.pixels).collect::<Vec<usize>>(), vec![3, 4, 1]);
+    assert_eq!(rows[1].old, [255, 255, 255]);
+    assert!(rows[1].new[0] < 255 && rows[1].new[0] == rows[1].new[1]);
+    assert_eq!(rows[1].alpha, 255);
+    assert_eq!(
+        preview(&state, &display_set, &PaletteTransforms::default())[1].new,
+        [255, 255, 255],
+    );
+}
+
+#[test]
+fn test_palette_preview_tints_before_scaling() {
+
+    let display_set = display_set();
+    let mut state = EpochState::default();
+
+    state.apply(&display_set);
+
+    let tinted = PaletteTransforms { tint: Some([255, 255, 0]), ..Default::default() };
+    let dimmed = PaletteTransforms { lum_scale: Some(0.5), ..tinted };
+    let tinted = preview(&state, &display_set, &tinted)[1].new;
+    let dimmed = preview(&state, &display_set, &dimmed)[1].new;
+
+    assert!(tinted[0] > 250 && tinted[1] > 250 && tinted[2] < 5, "{:?}", tinted);
+    assert!(dimmed[0] < tinted[0] && dimmed[1] < tinted[1] && dimmed[2] < 5, "{:?}", dimmed);
+}
+
+#[test]
+fn test_palette_preview_honors_crop() {
+
+    let mut display_set = display_set();
+    let mut state = EpochState::default();
+
+    display_set.composition.objects.values_mut().next().unwrap().crop =
+        Some(Crop { x: 1, y: 0, width: 2, height: 2 });
+    state.apply(&display_set);
+
+    let rows = preview(&state, &display_set, &PaletteTransforms::default());
+
+    assert_eq!(rows.len(), 1);
+    assert_eq!((rows[0].index, rows[0].pixels), (1, 4));
+}
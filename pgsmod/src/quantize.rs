@@ -0,0 +1,162 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::{
+    color::{palette_entry, ColorMatrix, Range},
+    displayset::{Palette, PaletteEntry},
+    png::PngImage,
+};
+use std::collections::BTreeMap;
+
+// Every fully transparent pixel gets this index, which run-length encodes the most compactly.
+pub const TRANSPARENT: u8 = 0;
+pub const MAX_COLORS: usize = 255;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Quantized {
+    pub palette: Palette,
+    // Index lines for each image, in the order the images were given.
+    pub lines: Vec<Vec<Vec<u8>>>,
+}
+
+// Reduces images to one shared palette of at most 255 colors plus transparent, using median cut
+// when there are more colors than that. Fully transparent pixels stay fully transparent.
+pub fn quantize(images: &[&PngImage], matrix: ColorMatrix, range: Range) -> Quantized {
+
+    let mut counts = BTreeMap::<[u8; 4], u32>::new();
+
+    for image in images.iter() {
+        for pixel in image.rgba.chunks_exact(4) {
+            if pixel[3] != 0 {
+                *counts.entry([pixel[0], pixel[1], pixel[2], pixel[3]]).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut boxes = vec![ColorBox::new(counts.into_iter().collect())];
+
+    while boxes.len() < MAX_COLORS {
+
+        let index = match boxes.iter()
+            .enumerate()
+            .filter(|(_, color_box)| color_box.range > 0)
+            .max_by_key(|(_, color_box)| color_box.range) {
+            Some((index, _)) => index,
+            None => break,
+        };
+        let ColorBox { mut colors, channel, .. } = boxes.swap_remove(index);
+
+        colors.sort_by_key(|&(color, _)| color[channel]);
+
+        let upper = colors.split_off(median(&colors));
+
+        boxes.push(ColorBox::new(colors));
+        boxes.push(ColorBox::new(upper));
+    }
+
+    let mut palette = Palette::default();
+    let mut indices = BTreeMap::<[u8; 4], u8>::new();
+
+    palette.entries.insert(
+        TRANSPARENT,
+        PaletteEntry { y: range.luma().0, cr: 128, cb: 128, alpha: 0 },
+    );
+
+    for (index, color_box) in boxes.iter().filter(|color_box| !color_box.colors.is_empty())
+        .enumerate() {
+
+        let id = index as u8 + 1;
+
+        palette.entries.insert(id, palette_entry(average(&color_box.colors), matrix, range));
+        for &(color, _) in color_box.colors.iter() {
+            indices.insert(color, id);
+        }
+    }
+
+    let lines = images.iter()
+        .map(|image| {
+            image.rgba.chunks_exact(4)
+                .map(|pixel| match pixel[3] {
+                    0 => TRANSPARENT,
+                    _ => indices[&[pixel[0], pixel[1], pixel[2], pixel[3]]],
+                })
+                .collect::<Vec<u8>>()
+                .chunks(image.width.max(1) as usize)
+                .map(<[u8]>::to_vec)
+                .collect()
+        })
+        .collect();
+
+    Quantized { palette, lines }
+}
+
+// Distinct colors with their pixel counts, and the channel they vary the most in.
+struct ColorBox {
+    colors: Vec<([u8; 4], u32)>,
+    channel: usize,
+    range: u8,
+}
+
+impl ColorBox {
+
+    fn new(colors: Vec<([u8; 4], u32)>) -> Self {
+
+        let mut low = [255u8; 4];
+        let mut high = [0u8; 4];
+
+        for (color, _) in colors.iter() {
+            for channel in 0..4 {
+                low[channel] = low[channel].min(color[channel]);
+                high[channel] = high[channel].max(color[channel]);
+            }
+        }
+
+        let (channel, range) = (0..4)
+            .map(|channel| (channel, high[channel].saturating_sub(low[channel])))
+            .max_by_key(|&(_, range)| range)
+            .unwrap();
+
+        Self { colors, channel, range }
+    }
+}
+
+// Where to split sorted colors so that each side has about half of the pixels, leaving neither
+// side empty.
+fn median(colors: &[([u8; 4], u32)]) -> usize {
+
+    let total = colors.iter().map(|&(_, count)| count as u64).sum::<u64>();
+    let mut running = 0u64;
+
+    for (index, &(_, count)) in colors.iter().enumerate() {
+        running += count as u64;
+        if running * 2 >= total {
+            return (index + 1).clamp(1, colors.len() - 1)
+        }
+    }
+
+    colors.len() - 1
+}
+
+// Weighted by pixel count. Every color in a box has some alpha, so the average does too.
+fn average(colors: &[([u8; 4], u32)]) -> [u8; 4] {
+
+    let total = colors.iter().map(|&(_, count)| count as u64).sum::<u64>();
+    let mut average = [0u8; 4];
+
+    for (channel, value) in average.iter_mut().enumerate() {
+
+        let sum = colors.iter()
+            .map(|&(color, count)| color[channel] as u64 * count as u64)
+            .sum::<u64>();
+
+        *value = ((sum + total / 2) / total) as u8;
+    }
+
+    average
+}
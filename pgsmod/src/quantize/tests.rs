@@ -0,0 +1,68 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+fn image(width: u32, pixels: &[[u8; 4]]) -> PngImage {
+    PngImage {
+        width,
+        height: pixels.len() as u32 / width,
+        rgba: pixels.iter().flatten().copied().collect(),
+    }
+}
+
+#[test]
+fn test_quantize_keeps_few_colors_exactly() {
+
+    let white = [255, 255, 255, 255];
+    let edge = [255, 255, 255, 96];
+    let first = image(3, &[[0, 0, 0, 0], white, edge, white, white, [9, 9, 9, 0]]);
+    let second = image(1, &[edge, [0, 0, 0, 255]]);
+    let quantized = quantize(&[&first, &second], ColorMatrix::Bt709, Range::Limited);
+
+    assert_eq!(quantized.palette.entries.len(), 4);
+    assert_eq!(quantized.palette.entries[&TRANSPARENT].alpha, 0);
+    assert_eq!(
+        quantized.palette.entries[&quantized.lines[0][0][1]],
+        palette_entry(white, ColorMatrix::Bt709, Range::Limited),
+    );
+    assert_eq!(
+        quantized.palette.entries[&quantized.lines[0][0][2]],
+        palette_entry(edge, ColorMatrix::Bt709, Range::Limited),
+    );
+    assert_eq!(quantized.lines[0][0][0], TRANSPARENT);
+    assert_eq!(quantized.lines[0][1][2], TRANSPARENT);
+    assert_eq!(quantized.lines[1][0][0], quantized.lines[0][0][2]);
+    assert_eq!(quantized.lines[0].len(), 2);
+    assert_eq!(quantized.lines[1].len(), 2);
+}
+
+#[test]
+fn test_quantize_limits_colors() {
+
+    let pixels = (0..4_096)
+        .map(|index| {
+            let alpha = if index % 7 == 0 { 0 } else { (index % 256) as u8 | 1 };
+            [(index / 16) as u8, (index % 16 * 16) as u8, (index % 256) as u8, alpha]
+        })
+        .collect::<Vec<[u8; 4]>>();
+    let quantized = quantize(&[&image(64, &pixels)], ColorMatrix::Bt709, Range::Limited);
+
+    assert_eq!(quantized.palette.entries.len(), MAX_COLORS + 1);
+    for (index, pixel) in quantized.lines[0].iter().flatten().zip(pixels.iter()) {
+        assert_eq!(*index == TRANSPARENT, pixel[3] == 0);
+        assert_ne!(quantized.palette.entries[index].alpha == 0, pixel[3] != 0);
+    }
+}
+
+#[test]
+fn test_quantize_fully_transparent_image() {
+
+    let quantized = quantize(&[&image(2, &[[0, 0, 0, 0]; 4])], ColorMatrix::Bt709, Range::Limited);
+
+    assert_eq!(quantized.palette.entries.len(), 1);
+    assert_eq!(quantized.lines, vec![vec![vec![TRANSPARENT; 2]; 2]]);
+}
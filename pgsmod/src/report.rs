@@ -0,0 +1,312 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::{
+    ts_to_timestamp,
+    displayset::{
+        Diagnostic,
+        DisplaySet,
+        ReadDisplaySetExt,
+        ReadError as DisplaySetReadError,
+    },
+    event::{events, Event},
+    segment::{CompositionState, ReadError as SegmentReadError, ReadOptions},
+    timeline::{visible_rects, EpochState, OccupancyMode, Rect},
+};
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult, Write};
+
+// Findings past this many are only counted.
+pub const MAX_FINDINGS: usize = 100;
+
+// Everything about a stream that can be added up one display set at a time.
+#[derive(Clone, Debug, Default)]
+pub struct StreamTotals {
+    pub display_sets: usize,
+    pub epochs: usize,
+    pub resolutions: Vec<(u16, u16)>,
+    pub object_definitions: usize,
+    pub palette_definitions: usize,
+    pub largest_object: Option<(u16, u16)>,
+    // One byte per pixel, as objects are held once decoded.
+    pub bitmap_bytes: u64,
+    pub findings: Vec<Diagnostic>,
+    pub finding_count: usize,
+    // What each display set leaves occupied, if asked for, as timeline::occupancy gives it.
+    pub occupancy: Vec<(u32, Vec<Rect>)>,
+    occupancy_mode: Option<OccupancyMode>,
+    state: EpochState,
+}
+
+impl StreamTotals {
+
+    pub fn record(&mut self, display_set: &DisplaySet) {
+
+        self.display_sets += 1;
+
+        if display_set.composition.state == CompositionState::EpochStart {
+            self.epochs += 1;
+        }
+        if !self.resolutions.contains(&(display_set.width, display_set.height)) {
+            self.resolutions.push((display_set.width, display_set.height));
+        }
+
+        self.object_definitions += display_set.objects.len();
+        self.palette_definitions += display_set.palettes.len();
+
+        for object in display_set.objects.values() {
+
+            let area = |(width, height): (u16, u16)| width as u64 * height as u64;
+
+            self.bitmap_bytes += area((object.width, object.height));
+            if self.largest_object.is_none_or(|largest| {
+                area((object.width, object.height)) > area(largest)
+            }) {
+                self.largest_object = Some((object.width, object.height));
+            }
+        }
+
+        self.state.apply(display_set);
+
+        if let Some(mode) = self.occupancy_mode {
+            self.occupancy.push((
+                display_set.pts,
+                visible_rects(
+                    &self.state,
+                    display_set,
+                    (display_set.width, display_set.height),
+                    mode,
+                ),
+            ));
+        }
+
+        for diagnostic in display_set.validate(&self.state) {
+            if self.findings.len() < MAX_FINDINGS {
+                self.findings.push(diagnostic);
+            }
+            self.finding_count += 1;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EventTotals {
+    pub events: usize,
+    // Events still showing when the stream ends, which have no duration.
+    pub unended: usize,
+    pub shortest: Option<u32>,
+    pub longest: Option<u32>,
+    pub total_duration: u64,
+}
+
+impl EventTotals {
+
+    pub fn record(&mut self, event: &Event) {
+
+        self.events += 1;
+
+        match event.duration() {
+            Some(duration) => {
+                self.shortest = Some(self.shortest.map_or(duration, |ts| ts.min(duration)));
+                self.longest = Some(self.longest.map_or(duration, |ts| ts.max(duration)));
+                self.total_duration += duration as u64;
+            }
+            None => self.unended += 1,
+        }
+    }
+
+    pub fn mean(&self) -> Option<u32> {
+        match self.events - self.unended {
+            0 => None,
+            ended => Some((self.total_duration / ended as u64) as u32),
+        }
+    }
+}
+
+// Reads a whole stream, writing each event as it ends and then the totals, either as text or as
+// a single JSON object. Screen occupancy is only worked out if a mode is given for it.
+pub fn report<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    json: bool,
+    occupancy_mode: Option<OccupancyMode>,
+) -> IoResult<(StreamTotals, EventTotals)> {
+
+    let read_options = ReadOptions { lenient: true, ..Default::default() };
+    let mut stream_totals = StreamTotals { occupancy_mode, ..Default::default() };
+    let mut event_totals = EventTotals::default();
+    let mut read_error = None;
+    let display_sets = std::iter::from_fn(|| {
+        match input.read_display_set_with(&read_options) {
+            Ok(display_set) => {
+                stream_totals.record(&display_set);
+                Some(display_set)
+            }
+            Err(DisplaySetReadError::SegmentError {
+                source: SegmentReadError::IoError { source },
+            }) if source.kind() == ErrorKind::UnexpectedEof => None,
+            Err(err) => {
+                read_error = Some(err);
+                None
+            }
+        }
+    });
+
+    match json {
+        true => write!(output, "{{\"events\":[")?,
+        false => writeln!(output, "Events:")?,
+    }
+
+    for event in events(display_sets) {
+
+        match json {
+            true if event_totals.events > 0 => write!(output, ",{}", event_json(&event))?,
+            true => write!(output, "{}", event_json(&event))?,
+            false => writeln!(output, "  {}", event_text(&event))?,
+        }
+
+        event_totals.record(&event);
+    }
+
+    if let Some(err) = read_error {
+        return Err(IoError::new(ErrorKind::InvalidData, err.to_string()))
+    }
+
+    match json {
+        true => writeln!(output, "],{}}}", totals_json(&stream_totals, &event_totals))?,
+        false => write!(output, "\n{}", totals_text(&stream_totals, &event_totals))?,
+    }
+
+    Ok((stream_totals, event_totals))
+}
+
+fn event_text(event: &Event) -> String {
+    match (event.end_pts, event.duration()) {
+        (Some(end_pts), Some(duration)) => format!(
+            "{} --> {} ({})",
+            ts_to_timestamp(event.start_pts),
+            ts_to_timestamp(end_pts),
+            ts_to_timestamp(duration),
+        ),
+        _ => format!("{} --> end of stream", ts_to_timestamp(event.start_pts)),
+    }
+}
+
+fn event_json(event: &Event) -> String {
+    format!(
+        "{{\"start_pts\":{},\"end_pts\":{},\"duration\":{}}}",
+        event.start_pts,
+        event.end_pts.map_or("null".to_string(), |pts| pts.to_string()),
+        event.duration().map_or("null".to_string(), |duration| duration.to_string()),
+    )
+}
+
+fn totals_text(stream_totals: &StreamTotals, event_totals: &EventTotals) -> String {
+
+    let duration = |ts: Option<u32>| ts.map_or("-".to_string(), ts_to_timestamp);
+    let mut rows = vec![
+        ("display sets", stream_totals.display_sets.to_string()),
+        ("epochs", stream_totals.epochs.to_string()),
+        ("events", event_totals.events.to_string()),
+        ("shortest event", duration(event_totals.shortest)),
+        ("longest event", duration(event_totals.longest)),
+        ("mean event", duration(event_totals.mean())),
+        ("resolutions", resolutions(stream_totals).join(", ")),
+        ("objects", stream_totals.object_definitions.to_string()),
+        ("palettes", stream_totals.palette_definitions.to_string()),
+        (
+            "largest object",
+            stream_totals.largest_object
+                .map_or("-".to_string(), |(width, height)| format!("{}x{}", width, height)),
+        ),
+        ("bitmap bytes", stream_totals.bitmap_bytes.to_string()),
+        ("findings", stream_totals.finding_count.to_string()),
+    ];
+
+    for finding in stream_totals.findings.iter() {
+        rows.push(("", finding.to_string()));
+    }
+    if stream_totals.finding_count > stream_totals.findings.len() {
+        rows.push((
+            "",
+            format!("and {} more", stream_totals.finding_count - stream_totals.findings.len()),
+        ));
+    }
+
+    rows.iter()
+        .map(|(name, value)| match name.is_empty() {
+            true => format!("{:<16} {}\n", "", value),
+            false => format!("{:<16} {}\n", format!("{}:", name), value),
+        })
+        .collect()
+}
+
+fn totals_json(stream_totals: &StreamTotals, event_totals: &EventTotals) -> String {
+
+    let number = |ts: Option<u32>| ts.map_or("null".to_string(), |ts| ts.to_string());
+    let findings = stream_totals.findings.iter()
+        .map(|finding| format!(
+            "{{\"pts\":{},\"message\":\"{}\"}}",
+            finding.pts(),
+            finding.to_string().replace('\\', "\\\\").replace('"', "\\\""),
+        ))
+        .collect::<Vec<String>>();
+
+    format!(
+        "\"display_sets\":{},\"epochs\":{},\"events\":{},\"unended_events\":{},\
+        \"shortest_event\":{},\"longest_event\":{},\"mean_event\":{},\"resolutions\":[{}],\
+        \"objects\":{},\"palettes\":{},\"largest_object\":{},\"bitmap_bytes\":{},\
+        \"finding_count\":{},\"findings\":[{}]",
+        stream_totals.display_sets,
+        stream_totals.epochs,
+        event_totals.events,
+        event_totals.unended,
+        number(event_totals.shortest),
+        number(event_totals.longest),
+        number(event_totals.mean()),
+        resolutions(stream_totals).iter()
+            .map(|resolution| format!("\"{}\"", resolution))
+            .collect::<Vec<String>>()
+            .join(","),
+        stream_totals.object_definitions,
+        stream_totals.palette_definitions,
+        stream_totals.largest_object.map_or("null".to_string(), |(width, height)| {
+            format!("{{\"width\":{},\"height\":{}}}", width, height)
+        }),
+        stream_totals.bitmap_bytes,
+        stream_totals.finding_count,
+        findings.join(","),
+    )
+}
+
+// Laid out as pgs serializes what timeline::occupancy returns: a list of change points, each a
+// PTS and the rectangles occupied from then on.
+pub fn occupancy_json(occupancy: &[(u32, Vec<Rect>)]) -> String {
+
+    let changes = occupancy.iter()
+        .map(|(pts, rects)| format!(
+            "[{},[{}]]",
+            pts,
+            rects.iter()
+                .map(|rect| format!(
+                    "{{\"x\":{},\"y\":{},\"width\":{},\"height\":{}}}",
+                    rect.x, rect.y, rect.width, rect.height,
+                ))
+                .collect::<Vec<String>>()
+                .join(","),
+        ))
+        .collect::<Vec<String>>();
+
+    format!("[{}]", changes.join(","))
+}
+
+fn resolutions(stream_totals: &StreamTotals) -> Vec<String> {
+    stream_totals.resolutions.iter()
+        .map(|(width, height)| format!("{}x{}", width, height))
+        .collect()
+}
@@ -0,0 +1,160 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::{
+        clear_display_set,
+        Cid,
+        CompositionObject,
+        Object,
+        Palette,
+        PaletteEntry,
+        Window,
+        WriteDisplaySetExt,
+    },
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+};
+
+fn shown(pts: u32, width: u16, entries: bool) -> DisplaySet {
+
+    let mut display_set = DisplaySet { pts, width: 1920, height: 1080, ..Default::default() };
+    let mut palette = Palette::default();
+
+    if entries {
+        palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+    }
+
+    display_set.composition.state = CompositionState::EpochStart;
+    display_set.windows.insert(WindowId(0), Window { x: 0, y: 0, width, height: 2 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width, height: 2, lines: vec![vec![1; width as usize]; 2], ..Default::default() },
+    );
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 0, y: 0, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+// An event from one to two seconds, then one from three to five seconds that the stream never
+// clears.
+fn stream() -> Vec<u8> {
+
+    let mut output = vec![];
+    let first = shown(90_000, 4, true);
+
+    for display_set in [
+        first.clone(),
+        clear_display_set(&first, 180_000),
+        shown(270_000, 8, true),
+        shown(450_000, 6, true),
+    ].iter() {
+        output.write_display_set(display_set).unwrap();
+    }
+
+    output
+}
+
+#[test]
+fn test_report_text() {
+
+    let mut output = vec![];
+    let (stream_totals, event_totals) =
+        report(&mut &stream()[..], &mut output, false, None).unwrap();
+    let text = String::from_utf8(output).unwrap();
+
+    assert_eq!(stream_totals.display_sets, 4);
+    assert_eq!(stream_totals.epochs, 3);
+    assert_eq!(stream_totals.largest_object, Some((8, 2)));
+    assert_eq!(stream_totals.bitmap_bytes, 8 + 16 + 12);
+    assert_eq!(
+        event_totals,
+        EventTotals {
+            events: 3,
+            unended: 1,
+            shortest: Some(90_000),
+            longest: Some(180_000),
+            total_duration: 270_000,
+        },
+    );
+    assert_eq!(event_totals.mean(), Some(135_000));
+    assert!(text.starts_with(
+        "Events:\n  00:00:01.000 --> 00:00:02.000 (00:00:01.000)\n\
+        \x20 00:00:03.000 --> 00:00:05.000 (00:00:02.000)\n\
+        \x20 00:00:05.000 --> end of stream\n"
+    ));
+    assert!(text.contains("mean event:      00:00:01.500\n"));
+    assert!(text.contains("resolutions:     1920x1080\n"));
+    assert!(text.ends_with("findings:        0\n"));
+}
+
+#[test]
+fn test_report_json() {
+
+    let mut output = vec![];
+
+    report(&mut &stream()[..], &mut output, true, None).unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "{\"events\":[{\"start_pts\":90000,\"end_pts\":180000,\"duration\":90000},\
+        {\"start_pts\":270000,\"end_pts\":450000,\"duration\":180000},\
+        {\"start_pts\":450000,\"end_pts\":null,\"duration\":null}],\
+        \"display_sets\":4,\"epochs\":3,\"events\":3,\"unended_events\":1,\
+        \"shortest_event\":90000,\"longest_event\":180000,\"mean_event\":135000,\
+        \"resolutions\":[\"1920x1080\"],\"objects\":3,\"palettes\":3,\
+        \"largest_object\":{\"width\":8,\"height\":2},\"bitmap_bytes\":36,\
+        \"finding_count\":0,\"findings\":[]}\n",
+    );
+}
+
+#[test]
+fn test_occupancy_json_matches_golden() {
+
+    let input = include_bytes!("../../../test-data/fade.sup");
+    let (stream_totals, _) =
+        report(&mut &input[..], &mut vec![], false, Some(OccupancyMode::Fast)).unwrap();
+
+    assert_eq!(
+        format!("{}\n", occupancy_json(&stream_totals.occupancy)),
+        include_str!("../../../test-data/fade-occupancy.json"),
+    );
+
+    // Nothing is worked out unless asked for.
+    let (stream_totals, _) = report(&mut &input[..], &mut vec![], false, None).unwrap();
+
+    assert!(stream_totals.occupancy.is_empty());
+}
+
+#[test]
+fn test_report_rejects_garbage() {
+
+    let mut output = vec![];
+    let result = report(&mut &[0u8; 20][..], &mut output, false, None);
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_findings_are_bounded() {
+
+    let mut stream_totals = StreamTotals::default();
+
+    for index in 0..MAX_FINDINGS as u32 + 5 {
+        stream_totals.record(&shown(index * 90_000, 4, false));
+    }
+
+    assert_eq!(stream_totals.finding_count, MAX_FINDINGS + 5);
+    assert_eq!(stream_totals.findings.len(), MAX_FINDINGS);
+    assert_eq!(
+        stream_totals.findings[0].to_string(),
+        "object 0 draws with palette indices 1 that its palette lacks at 00:00:00.000",
+    );
+}
@@ -0,0 +1,274 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    crop::scaled_size,
+    quantize::quantize,
+};
+use pgs::{
+    cache::object_rgba,
+    color::{ColorMatrix, Range},
+    displayset::{DisplaySet, Object, Palette},
+    id::{ObjectId, PaletteId, VersionedId},
+    png::PngImage,
+    segment::CompositionState,
+    timeline::EpochState,
+};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+// Scales RGBA pixels to a new size. Bilinear filtering works on premultiplied alpha, so that the
+// colors of fully transparent pixels never bleed into the edges of what is next to them.
+pub fn scale_rgba(
+    rgba: &[u8],
+    width: u16,
+    height: u16,
+    new_width: u16,
+    new_height: u16,
+    filter: Filter,
+) -> Vec<u8> {
+
+    let mut scaled = vec![0u8; new_width as usize * new_height as usize * 4];
+
+    if width == 0 || height == 0 {
+        return scaled
+    }
+
+    let pixel = |x: usize, y: usize| {
+        let offset = (y * width as usize + x) * 4;
+        let alpha = rgba[offset + 3] as f64 / 255.0;
+        [
+            rgba[offset] as f64 * alpha,
+            rgba[offset + 1] as f64 * alpha,
+            rgba[offset + 2] as f64 * alpha,
+            rgba[offset + 3] as f64,
+        ]
+    };
+    // Where the center of a new pixel falls among the old ones.
+    let source = |position: u16, size: u16, new_size: u16| {
+        ((position as f64 + 0.5) * size as f64 / new_size as f64 - 0.5)
+            .clamp(0.0, size as f64 - 1.0)
+    };
+
+    for y in 0..new_height {
+        for x in 0..new_width {
+
+            let (source_x, source_y) = (source(x, width, new_width), source(y, height, new_height));
+            let value = match filter {
+                Filter::Nearest => pixel(source_x.round() as usize, source_y.round() as usize),
+                Filter::Bilinear => {
+
+                    let (left, top) = (source_x.floor() as usize, source_y.floor() as usize);
+                    let right = (left + 1).min(width as usize - 1);
+                    let bottom = (top + 1).min(height as usize - 1);
+                    let (across, down) = (source_x - left as f64, source_y - top as f64);
+                    let corners = [
+                        (pixel(left, top), (1.0 - across) * (1.0 - down)),
+                        (pixel(right, top), across * (1.0 - down)),
+                        (pixel(left, bottom), (1.0 - across) * down),
+                        (pixel(right, bottom), across * down),
+                    ];
+                    let mut value = [0.0; 4];
+
+                    for (corner, weight) in corners.iter() {
+                        for channel in 0..4 {
+                            value[channel] += corner[channel] * weight;
+                        }
+                    }
+
+                    value
+                }
+            };
+            let offset = (y as usize * new_width as usize + x as usize) * 4;
+            let alpha = value[3].round().clamp(0.0, 255.0);
+
+            if alpha > 0.0 {
+                for channel in 0..3 {
+                    scaled[offset + channel] =
+                        (value[channel] * 255.0 / value[3]).round().clamp(0.0, 255.0) as u8;
+                }
+                scaled[offset + 3] = alpha as u8;
+            }
+        }
+    }
+
+    scaled
+}
+
+// Scales objects drawn with the same palette to new sizes and quantizes them back down to a single
+// palette between them, which keeps fully transparent pixels fully transparent.
+pub fn resample(
+    objects: &[(&Object, u16, u16)],
+    palette: &Palette,
+    filter: Filter,
+    matrix: ColorMatrix,
+    range: Range,
+) -> (Vec<Object>, Palette) {
+
+    let images = objects.iter()
+        .map(|&(object, new_width, new_height)| {
+            PngImage {
+                width: new_width as u32,
+                height: new_height as u32,
+                rgba: scale_rgba(
+                    &object_rgba(object, palette, matrix, range),
+                    object.width,
+                    object.height,
+                    new_width,
+                    new_height,
+                    filter,
+                ),
+            }
+        })
+        .collect::<Vec<PngImage>>();
+    let quantized = quantize(&images.iter().collect::<Vec<&PngImage>>(), matrix, range);
+    let objects = objects.iter()
+        .zip(quantized.lines)
+        .map(|(&(object, new_width, new_height), lines)| {
+            Object {
+                width: new_width,
+                height: new_height,
+                sequence: object.sequence,
+                lines,
+            }
+        })
+        .collect();
+
+    (objects, quantized.palette)
+}
+
+// The version last given to each definition written out in the current epoch, along with what it
+// defined. Identical definitions keep their version, and anything else gets the next one.
+#[derive(Default)]
+pub struct Versions {
+    objects: BTreeMap<ObjectId, (u8, Object)>,
+    palettes: BTreeMap<PaletteId, (u8, Palette)>,
+}
+
+impl Versions {
+
+    fn object(&mut self, id: ObjectId, object: &Object) -> VersionedId<ObjectId> {
+        VersionedId { id, version: next_version(&mut self.objects, id, object) }
+    }
+
+    fn palette(&mut self, id: PaletteId, palette: &Palette) -> VersionedId<PaletteId> {
+        VersionedId { id, version: next_version(&mut self.palettes, id, palette) }
+    }
+}
+
+fn next_version<K: Ord, V: Clone + PartialEq>(
+    versions: &mut BTreeMap<K, (u8, V)>,
+    id: K,
+    definition: &V,
+) -> u8 {
+
+    let version = match versions.get(&id) {
+        Some((version, previous)) if previous == definition => return *version,
+        Some((version, _)) => version.wrapping_add(1),
+        None => 0,
+    };
+
+    versions.insert(id, (version, definition.clone()));
+
+    version
+}
+
+// Resamples the bitmaps of a display set that is being scaled from one screen size to another,
+// given the epoch's state with the display set already applied. Whatever the display set draws
+// or defines is drawn again from the original definitions with the palette now in effect, so a
+// palette update becomes a display set that defines its objects and windows over again with one
+// new palette, as indices no longer mean what they did. Every palette written out has the lowest
+// palette ID of the epoch, which is the one a display set without a palette update draws with.
+// Returns whether anything was resampled.
+#[allow(clippy::too_many_arguments)]
+pub fn resample_display_set(
+    display_set: &mut DisplaySet,
+    state: &EpochState,
+    versions: &mut Versions,
+    screen: (u16, u16),
+    new_screen: (u16, u16),
+    filter: Filter,
+    matrix: ColorMatrix,
+    range: Range,
+) -> bool {
+
+    if display_set.composition.state == CompositionState::EpochStart {
+        *versions = Versions::default();
+    }
+    // Palettes defined without anything to draw are only ever used by later display sets, which
+    // define them over again here.
+    if display_set.objects.is_empty()
+        && display_set.palette_update_id.is_none()
+        && (display_set.palettes.is_empty() || display_set.composition.objects.is_empty()) {
+        display_set.palettes.clear();
+        return false
+    }
+
+    let palette = match state.palette(display_set) {
+        Some(palette) => palette,
+        None => return false,
+    };
+    let mut offsets = BTreeMap::<ObjectId, (u16, u16)>::new();
+
+    for (cid, composition_object) in display_set.composition.objects.iter() {
+        offsets.entry(cid.object_id).or_insert((composition_object.x, composition_object.y));
+    }
+    for vid in display_set.objects.keys() {
+        offsets.entry(vid.id).or_insert((0, 0));
+    }
+
+    let originals = offsets.iter()
+        .filter_map(|(id, &(x, y))| {
+            let object = state.objects.get(id)?;
+            Some((
+                *id,
+                object,
+                scaled_size(screen.0, new_screen.0, object.width, x).max(1),
+                scaled_size(screen.1, new_screen.1, object.height, y).max(1),
+            ))
+        })
+        .collect::<Vec<(ObjectId, &Object, u16, u16)>>();
+
+    if originals.is_empty() {
+        return false
+    }
+
+    let (objects, palette) = resample(
+        &originals.iter()
+            .map(|&(_, object, width, height)| (object, width, height))
+            .collect::<Vec<(&Object, u16, u16)>>(),
+        palette,
+        filter,
+        matrix,
+        range,
+    );
+    let palette_id = *state.palettes.keys().next().unwrap();
+
+    display_set.objects.clear();
+    for (&(id, ..), object) in originals.iter().zip(objects) {
+        display_set.objects.insert(versions.object(id, &object), object);
+    }
+    display_set.palettes.clear();
+    display_set.palettes.insert(versions.palette(palette_id, &palette), palette);
+
+    if display_set.palette_update_id.take().is_some() {
+        for cid in display_set.composition.objects.keys() {
+            if let Some(window) = state.windows.get(&cid.window_id) {
+                display_set.windows.insert(cid.window_id, window.clone());
+            }
+        }
+    }
+
+    true
+}
@@ -0,0 +1,264 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use super::super::quantize::quantize;
+use pgs::{
+    displayset::{Cid, CompositionObject, Window},
+    id::WindowId,
+};
+
+const MATRIX: ColorMatrix = ColorMatrix::Bt709;
+const RANGE: Range = Range::Limited;
+
+// A white ring with a black outline on a transparent background, as a glyph would be drawn, with
+// each pixel covered as much as it lies within the shapes. The size is of the square drawn into.
+fn ring(size: u16) -> Vec<u8> {
+
+    let scale = size as f64 / 90.0;
+    let mut rgba = Vec::with_capacity(size as usize * size as usize * 4);
+
+    for y in 0..size {
+        for x in 0..size {
+
+            let mut coverage = [0.0f64; 2];
+
+            // Supersampled four by four.
+            for sample in 0..16 {
+
+                let sample_x = (x as f64 + (sample % 4) as f64 / 4.0 + 0.125) / scale - 45.0;
+                let sample_y = (y as f64 + (sample / 4) as f64 / 4.0 + 0.125) / scale - 45.0;
+                let distance = (sample_x * sample_x + sample_y * sample_y).sqrt();
+
+                if (24.0..36.0).contains(&distance) {
+                    coverage[0] += 1.0 / 16.0;
+                } else if (18.0..42.0).contains(&distance) {
+                    coverage[1] += 1.0 / 16.0;
+                }
+            }
+
+            let alpha = coverage[0] + coverage[1];
+            let value = if alpha > 0.0 { coverage[0] / alpha } else { 0.0 };
+            let value = (value * 255.0).round() as u8;
+
+            rgba.extend_from_slice(&[value, value, value, (alpha * 255.0).round() as u8]);
+        }
+    }
+
+    rgba
+}
+
+// The object and palette a ring is stored as.
+fn ring_object(size: u16) -> (Object, Palette) {
+
+    let image = PngImage { width: size as u32, height: size as u32, rgba: ring(size) };
+    let quantized = quantize(&[&image], MATRIX, RANGE);
+    let lines = quantized.lines.into_iter().next().unwrap();
+
+    (Object { width: size, height: size, lines, ..Default::default() }, quantized.palette)
+}
+
+// Luminance premultiplied by alpha, as the pixels would look over black.
+fn luminance(rgba: &[u8]) -> Vec<f64> {
+    rgba.chunks_exact(4)
+        .map(|pixel| {
+            (0.2126 * pixel[0] as f64 + 0.7152 * pixel[1] as f64 + 0.0722 * pixel[2] as f64)
+                * pixel[3] as f64 / 255.0
+        })
+        .collect()
+}
+
+// The structural similarity of two images, averaged over the eight by eight windows that are not
+// empty in both.
+fn ssim(first: &[u8], second: &[u8], width: usize) -> f64 {
+
+    let (first, second) = (luminance(first), luminance(second));
+    let height = first.len() / width;
+    let (c1, c2) = ((0.01 * 255.0f64).powi(2), (0.03 * 255.0f64).powi(2));
+    let mut total = 0.0;
+    let mut windows = 0;
+
+    for top in (0..height - 7).step_by(4) {
+        for left in (0..width - 7).step_by(4) {
+
+            let samples = (0..64)
+                .map(|index| (top + index / 8) * width + left + index % 8)
+                .map(|index| (first[index], second[index]))
+                .collect::<Vec<(f64, f64)>>();
+
+            if samples.iter().all(|&(a, b)| a == 0.0 && b == 0.0) {
+                continue
+            }
+            let mean = |values: &dyn Fn(&(f64, f64)) -> f64| {
+                samples.iter().map(values).sum::<f64>() / 64.0
+            };
+            let (mean_a, mean_b) = (mean(&|&(a, _)| a), mean(&|&(_, b)| b));
+            let variance_a = mean(&|&(a, _)| (a - mean_a).powi(2));
+            let variance_b = mean(&|&(_, b)| (b - mean_b).powi(2));
+            let covariance = mean(&|&(a, b)| (a - mean_a) * (b - mean_b));
+
+            total += (2.0 * mean_a * mean_b + c1) * (2.0 * covariance + c2)
+                / ((mean_a.powi(2) + mean_b.powi(2) + c1) * (variance_a + variance_b + c2));
+            windows += 1;
+        }
+    }
+
+    total / windows as f64
+}
+
+#[test]
+fn test_scale_rgba_nearest() {
+
+    let rgba = [
+        [255, 0, 0, 255], [0, 255, 0, 255],
+        [0, 0, 255, 255], [0, 0, 0, 0],
+    ].concat();
+
+    assert_eq!(
+        scale_rgba(&rgba, 2, 2, 4, 2, Filter::Nearest),
+        [
+            [255, 0, 0, 255], [255, 0, 0, 255], [0, 255, 0, 255], [0, 255, 0, 255],
+            [0, 0, 255, 255], [0, 0, 255, 255], [0, 0, 0, 0], [0, 0, 0, 0],
+        ].concat(),
+    );
+}
+
+#[test]
+fn test_scale_rgba_bilinear_keeps_edge_colors() {
+
+    // Transparent black next to opaque white blends into partly transparent white, not gray.
+    let rgba = [[255, 255, 255, 255], [0, 0, 0, 0]].concat();
+    let scaled = scale_rgba(&rgba, 2, 1, 4, 1, Filter::Bilinear);
+
+    assert_eq!(
+        scaled,
+        [[255, 255, 255, 255], [255, 255, 255, 191], [255, 255, 255, 64], [0, 0, 0, 0]].concat(),
+    );
+}
+
+#[test]
+fn test_resample_matches_golden_image() {
+
+    let (object, palette) = ring_object(90);
+    let golden = ring(60);
+
+    for &(filter, threshold) in [(Filter::Bilinear, 0.99), (Filter::Nearest, 0.95)].iter() {
+
+        let (objects, palette) = resample(&[(&object, 60, 60)], &palette, filter, MATRIX, RANGE);
+        let resampled = object_rgba(&objects[0], &palette, MATRIX, RANGE);
+        let similarity = ssim(&resampled, &golden, 60);
+
+        assert_eq!((objects[0].width, objects[0].height), (60, 60));
+        assert!(similarity > threshold, "{:?}: {}", filter, similarity);
+    }
+}
+
+#[test]
+fn test_resample_quantizes_without_banding() {
+
+    let (object, palette) = ring_object(90);
+    let scaled = scale_rgba(
+        &object_rgba(&object, &palette, MATRIX, RANGE),
+        90,
+        90,
+        60,
+        60,
+        Filter::Bilinear,
+    );
+    let (objects, palette) =
+        resample(&[(&object, 60, 60)], &palette, Filter::Bilinear, MATRIX, RANGE);
+    let resampled = object_rgba(&objects[0], &palette, MATRIX, RANGE);
+
+    assert!(palette.entries.len() <= 256);
+    assert_eq!(resampled[..4], [0, 0, 0, 0]);
+    assert_eq!(resampled[(30 * 60 + 30) * 4 + 3], 0);
+    for (pixel, scaled) in resampled.chunks_exact(4).zip(scaled.chunks_exact(4)) {
+        assert_eq!(pixel[3] == 0, scaled[3] == 0);
+    }
+    assert!(ssim(&resampled, &scaled, 60) > 0.999);
+}
+
+fn shown_display_set() -> DisplaySet {
+
+    let (object, palette) = ring_object(90);
+    let mut display_set = DisplaySet {
+        pts: 90_000,
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+
+    display_set.windows.insert(WindowId(0), Window { x: 300, y: 900, width: 90, height: 90 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.objects.insert(VersionedId { id: ObjectId(0), version: 0 }, object);
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 300, y: 900, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+#[test]
+fn test_resample_display_set() {
+
+    let mut display_set = shown_display_set();
+    let mut state = EpochState::default();
+    let mut versions = Versions::default();
+
+    state.apply(&display_set);
+
+    assert!(resample_display_set(
+        &mut display_set,
+        &state,
+        &mut versions,
+        (1920, 1080),
+        (1280, 720),
+        Filter::Bilinear,
+        MATRIX,
+        RANGE,
+    ));
+    assert_eq!(display_set.objects.len(), 1);
+    assert_eq!(display_set.objects[&VersionedId { id: ObjectId(0), version: 0 }].width, 60);
+    assert_eq!(
+        display_set.palettes.keys().next(),
+        Some(&VersionedId { id: PaletteId(0), version: 0 }),
+    );
+
+    // A palette update draws the objects again, defining them anew along with their windows.
+    let mut palette_update = DisplaySet {
+        pts: 99_000,
+        palette_update_id: Some(PaletteId(0)),
+        composition: display_set.composition.clone(),
+        ..Default::default()
+    };
+    let mut palette = state.palettes[&PaletteId(0)].clone();
+
+    palette_update.composition.state = CompositionState::Normal;
+    for entry in palette.entries.values_mut() {
+        entry.alpha /= 2;
+    }
+    palette_update.palettes.insert(VersionedId { id: PaletteId(0), version: 1 }, palette);
+    state.apply(&palette_update);
+
+    assert!(resample_display_set(
+        &mut palette_update,
+        &state,
+        &mut versions,
+        (1920, 1080),
+        (1280, 720),
+        Filter::Bilinear,
+        MATRIX,
+        RANGE,
+    ));
+    assert_eq!(palette_update.palette_update_id, None);
+    assert_eq!(palette_update.windows.keys().collect::<Vec<_>>(), vec![&WindowId(0)]);
+    assert_eq!(
+        palette_update.palettes.keys().next(),
+        Some(&VersionedId { id: PaletteId(0), version: 1 }),
+    );
+    assert_eq!(palette_update.objects.len(), 1);
+}
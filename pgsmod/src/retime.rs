@@ -0,0 +1,103 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::displayset::DisplaySet;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RetimeMode {
+    ScaleAll,
+    // Scales when events start but keeps how long each one stays on screen.
+    ScaleGaps,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Retime {
+    pub rate: f64,
+    pub mode: RetimeMode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shortened {
+    pub index: usize,
+    pub by: u32,
+}
+
+impl Retime {
+
+    fn scale(self, pts: u32) -> u32 {
+        (pts as f64 * self.rate).round().clamp(0.0, u32::MAX as f64) as u32
+    }
+
+    // Maps the PTS of each display set of an epoch, given whether each one shows anything and
+    // the original PTS of whatever follows the epoch. An event is a run of display sets that show
+    // something; under scale-gaps it moves as a whole along with its first display set, and
+    // anything that would then land on or after its successor is pulled back just before it.
+    pub fn map(self, times: &[(u32, bool)], next: Option<u32>) -> (Vec<u32>, Vec<Shortened>) {
+
+        let mut mapped = Vec::with_capacity(times.len());
+        let mut event = None::<(u32, u32)>;
+
+        for (index, &(pts, shows)) in times.iter().enumerate() {
+
+            let previous_shows = index > 0 && times[index - 1].1;
+
+            if shows && !previous_shows {
+                event = Some((pts, self.scale(pts)));
+            }
+
+            mapped.push(
+                match (self.mode, event) {
+                    (RetimeMode::ScaleGaps, Some((start, new_start))) => {
+                        new_start.saturating_add(pts.saturating_sub(start))
+                    }
+                    _ => self.scale(pts),
+                }
+            );
+
+            if !shows {
+                event = None;
+            }
+        }
+
+        let mut shortened = Vec::new();
+        let mut limit = next.map(|pts| self.scale(pts));
+
+        for (index, pts) in mapped.iter_mut().enumerate().rev() {
+            if let Some(limit) = limit {
+                if *pts >= limit {
+                    shortened.push(Shortened { index, by: *pts - limit + 1 });
+                    *pts = limit.saturating_sub(1);
+                }
+            }
+            limit = Some(*pts);
+        }
+
+        shortened.reverse();
+
+        (mapped, shortened)
+    }
+}
+
+// Retimes the epoch in place, keeping each display set's DTS the same distance before its PTS.
+pub fn retime_epoch(epoch: &mut [DisplaySet], retime: Retime, next: Option<u32>) -> Vec<Shortened> {
+
+    let times = epoch.iter()
+        .map(|display_set| (display_set.pts, !display_set.composition.objects.is_empty()))
+        .collect::<Vec<(u32, bool)>>();
+    let (mapped, shortened) = retime.map(&times, next);
+
+    for (display_set, pts) in epoch.iter_mut().zip(mapped) {
+        if display_set.dts != 0 {
+            display_set.dts = pts.saturating_sub(display_set.pts.saturating_sub(display_set.dts));
+        }
+        display_set.pts = pts;
+    }
+
+    shortened
+}
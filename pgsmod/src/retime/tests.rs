@@ -0,0 +1,77 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::Cid,
+    id::{ObjectId, WindowId},
+};
+
+// Two events with a fade step in the first, each ended by a clear.
+const TIMES: [(u32, bool); 5] = [
+    (90_000, true),
+    (99_000, true),
+    (180_000, false),
+    (270_000, true),
+    (360_000, false),
+];
+
+#[test]
+fn test_scale_all() {
+
+    let retime = Retime { rate: 1.5, mode: RetimeMode::ScaleAll };
+
+    assert_eq!(
+        retime.map(&TIMES, Some(450_000)),
+        (vec![135_000, 148_500, 270_000, 405_000, 540_000], vec![]),
+    );
+}
+
+#[test]
+fn test_scale_gaps_keeps_durations() {
+
+    let retime = Retime { rate: 1.5, mode: RetimeMode::ScaleGaps };
+
+    assert_eq!(
+        retime.map(&TIMES, Some(450_000)),
+        (vec![135_000, 144_000, 225_000, 405_000, 495_000], vec![]),
+    );
+}
+
+#[test]
+fn test_scale_gaps_shortens_overlaps() {
+
+    let retime = Retime { rate: 0.5, mode: RetimeMode::ScaleGaps };
+
+    // The first event would end at 135000, after the second starts at 135000.
+    assert_eq!(
+        retime.map(&TIMES, Some(400_000)),
+        (
+            vec![45_000, 54_000, 134_999, 135_000, 199_999],
+            vec![Shortened { index: 2, by: 1 }, Shortened { index: 4, by: 25_001 }],
+        ),
+    );
+}
+
+#[test]
+fn test_retime_epoch() {
+
+    let mut epoch = vec![
+        DisplaySet { pts: 90_000, dts: 89_000, ..Default::default() },
+        DisplaySet { pts: 180_000, ..Default::default() },
+    ];
+
+    epoch[0].composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        Default::default(),
+    );
+
+    let retime = Retime { rate: 2.0, mode: RetimeMode::ScaleGaps };
+
+    assert!(retime_epoch(&mut epoch, retime, None).is_empty());
+    assert_eq!((epoch[0].pts, epoch[0].dts), (180_000, 179_000));
+    assert_eq!((epoch[1].pts, epoch[1].dts), (270_000, 0));
+}
@@ -0,0 +1,667 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::{
+    bdn::{bdn_xml, frame_number, BdnEvent, FrameRate, FINAL_EVENT_DURATION},
+    contact::{contact_sheet, Thumbnail, ROWS_PER_PAGE},
+};
+use pgs::{
+    ts_to_timestamp,
+    cache::{object_rgba, Generations, SharedCache},
+    event::EventId,
+    pes::write_pes,
+    displayset::{CompositionObject, DisplaySet, Object, WriteDisplaySetExt},
+    png::{write_png, write_png_rgba},
+    segment::{CompositionState, Crop},
+    timeline::EpochState,
+};
+use std::{
+    fs::{write, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SinkError {
+    pub message: String,
+    // Fatal errors stop the whole run; anything else only stops the sink that raised it.
+    pub fatal: bool,
+}
+
+impl SinkError {
+
+    fn new(message: impl ToString, fatal: bool) -> Self {
+        Self {
+            message: message.to_string(),
+            fatal,
+        }
+    }
+}
+
+pub trait DisplaySetSink {
+    fn name(&self) -> &str;
+    // The event ID is only given to display sets that show something; see pgs::event.
+    fn write(
+        &mut self,
+        display_set: &DisplaySet,
+        event_id: Option<EventId>,
+    ) -> Result<(), SinkError>;
+    fn finish(&mut self) -> Result<(), SinkError>;
+}
+
+// Feeds a display set to every sink, dropping any sink that fails so that the rest carry on.
+pub fn write_to_sinks(
+    sinks: &mut Vec<Box<dyn DisplaySetSink>>,
+    display_set: &DisplaySet,
+    event_id: Option<EventId>,
+) {
+    sinks.retain_mut(|sink| {
+        let result = sink.write(display_set, event_id);
+        report(sink.as_ref(), result)
+    });
+}
+
+pub fn finish_sinks(sinks: &mut Vec<Box<dyn DisplaySetSink>>) {
+    sinks.retain_mut(|sink| {
+        let result = sink.finish();
+        report(sink.as_ref(), result)
+    });
+}
+
+fn report(sink: &dyn DisplaySetSink, result: Result<(), SinkError>) -> bool {
+    match result {
+        Ok(()) => {
+            true
+        }
+        Err(err) if err.fatal => {
+            panic!("Could not write to {}: {}", sink.name(), err.message)
+        }
+        Err(err) => {
+            eprintln!(
+                "WARNING: Could not write to {}: {}; it has been stopped.",
+                sink.name(), err.message,
+            );
+            false
+        }
+    }
+}
+
+pub struct SupSink<W: Write> {
+    output: W,
+}
+
+impl<W: Write> SupSink<W> {
+
+    pub fn new(output: W) -> Self {
+        Self { output }
+    }
+}
+
+impl<W: Write> DisplaySetSink for SupSink<W> {
+
+    fn name(&self) -> &str {
+        "PGS output"
+    }
+
+    fn write(&mut self, display_set: &DisplaySet, _: Option<EventId>) -> Result<(), SinkError> {
+        self.output.write_display_set(display_set).map_err(|err| SinkError::new(err, true))
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        self.output.flush().map_err(|err| SinkError::new(err, true))
+    }
+}
+
+// Wraps each display set's segments in PES packets stamped with its PTS. Some authoring chains
+// expect the PTS to only be carried there, with the segment headers left at zero.
+pub struct PesSink<W: Write> {
+    output: W,
+    zero_segment_pts: bool,
+}
+
+impl<W: Write> PesSink<W> {
+
+    pub fn new(output: W, zero_segment_pts: bool) -> Self {
+        Self {
+            output,
+            zero_segment_pts,
+        }
+    }
+}
+
+impl<W: Write> DisplaySetSink for PesSink<W> {
+
+    fn name(&self) -> &str {
+        "PES output"
+    }
+
+    fn write(&mut self, display_set: &DisplaySet, _: Option<EventId>) -> Result<(), SinkError> {
+
+        let mut segments = Vec::new();
+
+        if self.zero_segment_pts {
+            segments.write_display_set(
+                &DisplaySet {
+                    pts: 0,
+                    dts: 0,
+                    ..display_set.clone()
+                }
+            )
+        } else {
+            segments.write_display_set(display_set)
+        }.map_err(|err| SinkError::new(err, true))?;
+
+        write_pes(&mut self.output, display_set.pts, &segments)
+            .map_err(|err| SinkError::new(err, true))
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        self.output.flush().map_err(|err| SinkError::new(err, true))
+    }
+}
+
+// Writes one JSON object per display set, one per line.
+pub struct JsonSink<W: Write> {
+    output: W,
+}
+
+impl<W: Write> JsonSink<W> {
+
+    pub fn new(output: W) -> Self {
+        Self { output }
+    }
+}
+
+impl<W: Write> DisplaySetSink for JsonSink<W> {
+
+    fn name(&self) -> &str {
+        "JSON dump"
+    }
+
+    fn write(
+        &mut self,
+        display_set: &DisplaySet,
+        event_id: Option<EventId>,
+    ) -> Result<(), SinkError> {
+        writeln!(self.output, "{}", json_line(display_set, event_id))
+            .map_err(|err| SinkError::new(err, false))
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        self.output.flush().map_err(|err| SinkError::new(err, false))
+    }
+}
+
+// Bumped whenever the fields of a JSON line change incompatibly.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+fn json_line(display_set: &DisplaySet, event_id: Option<EventId>) -> String {
+
+    let windows = display_set.windows.iter().map(|(id, window)| format!(
+        "{{\"id\":{},\"x\":{},\"y\":{},\"width\":{},\"height\":{}}}",
+        id, window.x, window.y, window.width, window.height,
+    )).collect::<Vec<String>>();
+    let palettes = display_set.palettes.iter().map(|(vid, palette)| format!(
+        "{{\"id\":{},\"version\":{},\"entries\":{}}}",
+        vid.id, vid.version, palette.entries.len(),
+    )).collect::<Vec<String>>();
+    let objects = display_set.objects.iter().map(|(vid, object)| format!(
+        "{{\"id\":{},\"version\":{},\"width\":{},\"height\":{}}}",
+        vid.id, vid.version, object.width, object.height,
+    )).collect::<Vec<String>>();
+    let composition_objects = display_set.composition.objects.iter().map(|(cid, co)| format!(
+        "{{\"object_id\":{},\"window_id\":{},\"x\":{},\"y\":{},\"crop\":{},\"forced\":{}}}",
+        cid.object_id,
+        cid.window_id,
+        co.x,
+        co.y,
+        match &co.crop {
+            Some(crop) => format!(
+                "{{\"x\":{},\"y\":{},\"width\":{},\"height\":{}}}",
+                crop.x, crop.y, crop.width, crop.height,
+            ),
+            None => "null".to_string(),
+        },
+        co.forced,
+    )).collect::<Vec<String>>();
+
+    format!(
+        "{{\"pts\":{},\"dts\":{},\"timestamp\":\"{}\",\"event_id\":{},\"width\":{},\
+        \"height\":{},\"composition_number\":{},\"composition_state\":\"{}\",\
+        \"palette_update_id\":{},\"windows\":[{}],\"palettes\":[{}],\"objects\":[{}],\
+        \"composition_objects\":[{}]}}",
+        display_set.pts,
+        display_set.dts,
+        ts_to_timestamp(display_set.pts),
+        event_id.map_or("null".to_string(), |id| format!("\"{}\"", id)),
+        display_set.width,
+        display_set.height,
+        display_set.composition.number,
+        match display_set.composition.state {
+            CompositionState::EpochStart => "epoch_start",
+            CompositionState::AcquisitionPoint => "acquisition_point",
+            CompositionState::Normal => "normal",
+        },
+        display_set.palette_update_id.map_or("null".to_string(), |id| id.to_string()),
+        windows.join(","),
+        palettes.join(","),
+        objects.join(","),
+        composition_objects.join(","),
+    )
+}
+
+// Renders what each display set puts on screen, cut down to the area its objects cover, and lists
+// every image along with its event ID in manifest.csv.
+pub struct PngSink {
+    directory: PathBuf,
+    state: EpochState,
+    generations: Generations,
+    cache: SharedCache,
+    count: usize,
+    manifest: Option<BufWriter<File>>,
+}
+
+impl PngSink {
+
+    pub fn new(directory: PathBuf, cache: SharedCache) -> Self {
+        Self {
+            directory,
+            state: EpochState::default(),
+            generations: Generations::default(),
+            cache,
+            count: 0,
+            manifest: None,
+        }
+    }
+}
+
+impl DisplaySetSink for PngSink {
+
+    fn name(&self) -> &str {
+        "PNG export"
+    }
+
+    fn write(
+        &mut self,
+        display_set: &DisplaySet,
+        event_id: Option<EventId>,
+    ) -> Result<(), SinkError> {
+
+        self.state.apply(display_set);
+        self.generations.apply(display_set);
+
+        let image = match render(&self.state, display_set, &self.generations, &self.cache) {
+            Some(image) => image,
+            None => return Ok(()),
+        };
+        let name = format!(
+            "{:05}-{}-{}x{}+{}+{}.png",
+            self.count, display_set.pts, image.width, image.height, image.x, image.y,
+        );
+        let file = File::create(self.directory.join(&name))
+            .map_err(|err| SinkError::new(err, false))?;
+
+        self.count += 1;
+
+        write_png_rgba(
+            &mut BufWriter::new(file),
+            image.width as u32,
+            image.height as u32,
+            &image.rgba,
+        ).map_err(|err| SinkError::new(err, false))?;
+
+        if self.manifest.is_none() {
+
+            let file = File::create(self.directory.join("manifest.csv"))
+                .map_err(|err| SinkError::new(err, false))?;
+            let mut manifest = BufWriter::new(file);
+
+            writeln!(manifest, "{}", MANIFEST_HEADER).map_err(|err| SinkError::new(err, false))?;
+            self.manifest = Some(manifest);
+        }
+
+        if let Some(manifest) = self.manifest.as_mut() {
+            writeln!(manifest, "{}", manifest_row(&name, display_set.pts, event_id, &image))
+                .map_err(|err| SinkError::new(err, false))?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        match self.manifest.as_mut() {
+            Some(manifest) => manifest.flush().map_err(|err| SinkError::new(err, false)),
+            None => Ok(()),
+        }
+    }
+}
+
+const MANIFEST_HEADER: &str = "file,event_id,pts,timestamp,x,y,width,height";
+
+fn manifest_row(name: &str, pts: u32, event_id: Option<EventId>, image: &Image) -> String {
+    format!(
+        "{},{},{},{},{},{},{},{}",
+        name,
+        event_id.map_or(String::new(), |id| id.to_string()),
+        pts,
+        ts_to_timestamp(pts),
+        image.x,
+        image.y,
+        image.width,
+        image.height,
+    )
+}
+
+// Collects a thumbnail of every display set that shows something and tiles them into one PNG
+// file per page. The first page goes to the given path and later ones get a -2, -3, ... suffix.
+pub struct ContactSheetSink {
+    path: PathBuf,
+    columns: usize,
+    state: EpochState,
+    generations: Generations,
+    cache: SharedCache,
+    count: usize,
+    pages: usize,
+    thumbnails: Vec<Thumbnail>,
+}
+
+impl ContactSheetSink {
+
+    pub fn new(path: PathBuf, columns: usize, cache: SharedCache) -> Self {
+        Self {
+            path,
+            columns,
+            state: EpochState::default(),
+            generations: Generations::default(),
+            cache,
+            count: 0,
+            pages: 0,
+            thumbnails: Vec::new(),
+        }
+    }
+
+    fn write_page(&mut self) -> Result<(), SinkError> {
+
+        let page = contact_sheet(&self.thumbnails, self.columns);
+        let path = page_path(&self.path, self.pages + 1);
+        let file = File::create(&path).map_err(|err| SinkError::new(err, false))?;
+
+        self.pages += 1;
+        self.thumbnails.clear();
+
+        write_png(&mut BufWriter::new(file), page.width as u32, page.height as u32, &page.rgb)
+            .map_err(|err| SinkError::new(err, false))
+    }
+}
+
+impl DisplaySetSink for ContactSheetSink {
+
+    fn name(&self) -> &str {
+        "contact sheet"
+    }
+
+    fn write(&mut self, display_set: &DisplaySet, _: Option<EventId>) -> Result<(), SinkError> {
+
+        self.state.apply(display_set);
+        self.generations.apply(display_set);
+
+        if let Some(image) = render(&self.state, display_set, &self.generations, &self.cache) {
+
+            self.thumbnails.push(Thumbnail::new(
+                self.count,
+                display_set.pts,
+                image.width as usize,
+                image.height as usize,
+                &image.rgba,
+            ));
+            self.count += 1;
+
+            if self.thumbnails.len() >= self.columns.max(1) * ROWS_PER_PAGE {
+                self.write_page()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        if self.thumbnails.is_empty() {
+            Ok(())
+        } else {
+            self.write_page()
+        }
+    }
+}
+
+// Turns every stretch of time that something is on screen into an event of a BDN XML file, with
+// its image written beside the file as a PNG. An event lasts until a display set shows something
+// else or clears the screen.
+pub struct BdnSink {
+    path: PathBuf,
+    frame_rate: FrameRate,
+    state: EpochState,
+    generations: Generations,
+    cache: SharedCache,
+    screen_height: Option<u16>,
+    shown: Option<(u32, Image)>,
+    events: Vec<BdnEvent>,
+}
+
+impl BdnSink {
+
+    pub fn new(path: PathBuf, frame_rate: FrameRate, cache: SharedCache) -> Self {
+        Self {
+            path,
+            frame_rate,
+            state: EpochState::default(),
+            generations: Generations::default(),
+            cache,
+            screen_height: None,
+            shown: None,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn events(&self) -> usize {
+        self.events.len()
+    }
+
+    fn stem(&self) -> &str {
+        self.path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("bdn")
+    }
+
+    // Events too short to last a single frame are left out.
+    fn end_event(&mut self, pts: u32) -> Result<(), SinkError> {
+
+        let (in_pts, image) = match self.shown.take() {
+            Some(shown) => shown,
+            None => return Ok(()),
+        };
+
+        if frame_number(pts, self.frame_rate) <= frame_number(in_pts, self.frame_rate) {
+            return Ok(())
+        }
+
+        let file = format!("{}_{:04}.png", self.stem(), self.events.len() + 1);
+        let output = File::create(self.path.with_file_name(&file))
+            .map_err(|err| SinkError::new(err, true))?;
+
+        write_png_rgba(
+            &mut BufWriter::new(output),
+            image.width as u32,
+            image.height as u32,
+            &image.rgba,
+        ).map_err(|err| SinkError::new(err, true))?;
+
+        self.events.push(BdnEvent {
+            in_pts,
+            out_pts: pts,
+            file,
+            x: image.x,
+            y: image.y,
+            width: image.width,
+            height: image.height,
+        });
+
+        Ok(())
+    }
+}
+
+impl DisplaySetSink for BdnSink {
+
+    fn name(&self) -> &str {
+        "BDN export"
+    }
+
+    fn write(&mut self, display_set: &DisplaySet, _: Option<EventId>) -> Result<(), SinkError> {
+
+        self.state.apply(display_set);
+        self.generations.apply(display_set);
+        self.screen_height.get_or_insert(display_set.height);
+
+        let image = render(&self.state, display_set, &self.generations, &self.cache);
+
+        // Display sets that only repeat what is already shown, like acquisition points, carry on
+        // the same event.
+        if let (Some((_, shown)), Some(image)) = (&self.shown, &image) {
+            if shown == image {
+                return Ok(())
+            }
+        }
+
+        self.end_event(display_set.pts)?;
+
+        if let Some(image) = image {
+            self.shown = Some((display_set.pts, image));
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+
+        if let Some((in_pts, _)) = self.shown {
+            self.end_event(in_pts.saturating_add(FINAL_EVENT_DURATION))?;
+        }
+
+        let xml = bdn_xml(
+            self.stem(),
+            self.frame_rate,
+            self.screen_height.unwrap_or(1080),
+            &self.events,
+        );
+
+        write(&self.path, xml).map_err(|err| SinkError::new(err, true))
+    }
+}
+
+fn page_path(path: &Path, page: usize) -> PathBuf {
+
+    if page == 1 {
+        return path.to_path_buf()
+    }
+
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("contact-sheet");
+    let name = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => format!("{}-{}.{}", stem, page, extension),
+        None => format!("{}-{}", stem, page),
+    };
+
+    path.with_file_name(name)
+}
+
+#[derive(PartialEq)]
+struct Image {
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+    rgba: Vec<u8>,
+}
+
+// Covers the area that the composition objects occupy on screen.
+fn render(
+    state: &EpochState,
+    display_set: &DisplaySet,
+    generations: &Generations,
+    cache: &SharedCache,
+) -> Option<Image> {
+
+    let palette_id = display_set.palette_update_id
+        .or_else(|| state.palettes.keys().next().copied())?;
+    let palette = state.palettes.get(&palette_id)?;
+    let matrix = cache.borrow().matrix(display_set.width);
+    let range = cache.borrow().range();
+    let mut placed = Vec::<(&Object, &CompositionObject, Crop, Rc<Vec<u8>>)>::new();
+
+    for (cid, composition_object) in display_set.composition.objects.iter() {
+        if let Some(object) = state.objects.get(&cid.object_id) {
+
+            let crop = match &composition_object.crop {
+                Some(crop) => crop.clone(),
+                None => Crop { x: 0, y: 0, width: object.width, height: object.height },
+            };
+            let convert = || object_rgba(object, palette, matrix, range);
+            let rgba = match generations.key(cid.object_id, palette_id, matrix) {
+                Some(key) => cache.borrow_mut().get_or_insert_with(key, convert),
+                None => Rc::new(convert()),
+            };
+
+            placed.push((object, composition_object, crop, rgba));
+        }
+    }
+
+    let x1 = placed.iter().map(|(_, co, _, _)| co.x as u32).min()?;
+    let y1 = placed.iter().map(|(_, co, _, _)| co.y as u32).min()?;
+    let x2 = placed.iter().map(|(_, co, crop, _)| co.x as u32 + crop.width as u32).max()?;
+    let y2 = placed.iter().map(|(_, co, crop, _)| co.y as u32 + crop.height as u32).max()?;
+    let width = (x2 - x1) as usize;
+    let height = (y2 - y1) as usize;
+
+    if width == 0 || height == 0 {
+        return None
+    }
+
+    let mut rgba = vec![0u8; width * height * 4];
+
+    for (object, co, crop, pixels) in placed {
+
+        let object_width = object.width as usize;
+        let bottom = (crop.y as usize + crop.height as usize).min(object.height as usize);
+        let right = (crop.x as usize + crop.width as usize).min(object_width);
+
+        for source_y in crop.y as usize..bottom {
+            for source_x in crop.x as usize..right {
+
+                let source = (source_y * object_width + source_x) * 4;
+
+                if pixels[source + 3] == 0 {
+                    continue
+                }
+
+                let target_x = co.x as usize + source_x - crop.x as usize - x1 as usize;
+                let target_y = co.y as usize + source_y - crop.y as usize - y1 as usize;
+                let offset = (target_y * width + target_x) * 4;
+
+                rgba[offset..offset + 4].copy_from_slice(&pixels[source..source + 4]);
+            }
+        }
+    }
+
+    Some(
+        Image {
+            x: x1 as u16,
+            y: y1 as u16,
+            width: width as u16,
+            height: height as u16,
+            rgba,
+        }
+    )
+}
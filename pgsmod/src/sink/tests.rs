@@ -0,0 +1,284 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use super::super::bdn::FrameRate;
+use pgs::{
+    cache::ObjectCache,
+    displayset::{Cid, Palette, PaletteEntry, ReadDisplaySetExt, Window},
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+    segment::ReadOptions,
+};
+use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+struct FailingSink {
+    fatal: bool,
+}
+
+impl DisplaySetSink for FailingSink {
+
+    fn name(&self) -> &str {
+        "failing sink"
+    }
+
+    fn write(&mut self, _: &DisplaySet, _: Option<EventId>) -> Result<(), SinkError> {
+        Err(SinkError::new("disk full", self.fatal))
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+struct CountingSink {
+    count: Rc<RefCell<usize>>,
+}
+
+impl DisplaySetSink for CountingSink {
+
+    fn name(&self) -> &str {
+        "counting sink"
+    }
+
+    fn write(&mut self, _: &DisplaySet, _: Option<EventId>) -> Result<(), SinkError> {
+        *self.count.borrow_mut() += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+fn shown_display_set() -> DisplaySet {
+
+    let mut display_set = DisplaySet {
+        pts: 90_000,
+        width: 1920,
+        height: 1080,
+        ..Default::default()
+    };
+    let mut palette = Palette::default();
+
+    palette.entries.insert(1, PaletteEntry { y: 235, cr: 128, cb: 128, alpha: 255 });
+    display_set.windows.insert(WindowId(0), Window { x: 100, y: 900, width: 3, height: 2 });
+    display_set.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, palette);
+    display_set.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object {
+            width: 3,
+            height: 2,
+            lines: vec![vec![0, 1, 0], vec![1, 1, 1]],
+            ..Default::default()
+        },
+    );
+    display_set.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 100, y: 900, crop: None, forced: false },
+    );
+
+    display_set
+}
+
+#[test]
+fn test_failing_sink_does_not_stop_others() {
+
+    let count = Rc::new(RefCell::new(0));
+    let mut sinks: Vec<Box<dyn DisplaySetSink>> = vec![
+        Box::new(FailingSink { fatal: false }),
+        Box::new(CountingSink { count: count.clone() }),
+    ];
+
+    write_to_sinks(&mut sinks, &DisplaySet::default(), None);
+    write_to_sinks(&mut sinks, &DisplaySet::default(), None);
+
+    assert_eq!(sinks.len(), 1);
+    assert_eq!(*count.borrow(), 2);
+}
+
+#[test]
+#[should_panic(expected = "Could not write to failing sink: disk full")]
+fn test_fatal_sink_error_panics() {
+
+    let mut sinks: Vec<Box<dyn DisplaySetSink>> = vec![Box::new(FailingSink { fatal: true })];
+
+    write_to_sinks(&mut sinks, &DisplaySet::default(), None);
+}
+
+#[test]
+fn test_json_line() {
+    assert_eq!(
+        json_line(&shown_display_set(), Some(EventId { epoch_hash: 0xABCD, ordinal: 2 })),
+        "{\"pts\":90000,\"dts\":0,\"timestamp\":\"00:00:01.000\",\
+        \"event_id\":\"000000000000abcd-2\",\"width\":1920,\"height\":1080,\
+        \"composition_number\":0,\"composition_state\":\"epoch_start\",\"palette_update_id\":null,\
+        \"windows\":[{\"id\":0,\"x\":100,\"y\":900,\"width\":3,\"height\":2}],\
+        \"palettes\":[{\"id\":0,\"version\":0,\"entries\":1}],\
+        \"objects\":[{\"id\":0,\"version\":0,\"width\":3,\"height\":2}],\
+        \"composition_objects\":[{\"object_id\":0,\"window_id\":0,\"x\":100,\"y\":900,\
+        \"crop\":null,\"forced\":false}]}",
+    );
+}
+
+#[test]
+fn test_manifest_row() {
+
+    let image = Image { x: 100, y: 900, width: 3, height: 2, rgba: Vec::new() };
+
+    assert_eq!(
+        manifest_row("00000-90000-3x2+100+900.png", 90_000, None, &image),
+        "00000-90000-3x2+100+900.png,,90000,00:00:01.000,100,900,3,2",
+    );
+    assert_eq!(
+        manifest_row(
+            "00001-90000-3x2+100+900.png",
+            90_000,
+            Some(EventId { epoch_hash: 1, ordinal: 0 }),
+            &image,
+        ),
+        "00001-90000-3x2+100+900.png,0000000000000001-0,90000,00:00:01.000,100,900,3,2",
+    );
+}
+
+#[test]
+fn test_render() {
+
+    let display_set = shown_display_set();
+    let cache = ObjectCache::shared(0);
+    let mut state = EpochState::default();
+    let mut generations = Generations::default();
+
+    state.apply(&display_set);
+    generations.apply(&display_set);
+
+    let image = render(&state, &display_set, &generations, &cache).unwrap();
+
+    assert_eq!((image.x, image.y, image.width, image.height), (100, 900, 3, 2));
+    assert_eq!(image.rgba[..4], [0, 0, 0, 0]);
+    assert_eq!(image.rgba[4..8], [255, 255, 255, 255]);
+
+    state.apply(&DisplaySet::default());
+
+    assert!(render(&state, &DisplaySet::default(), &generations, &cache).is_none());
+}
+
+#[test]
+fn test_render_does_not_depend_on_cache() {
+
+    let mut palette_update = shown_display_set();
+    let mut palette = Palette::default();
+
+    palette.entries.insert(1, PaletteEntry { y: 16, cr: 240, cb: 90, alpha: 128 });
+    palette_update.composition.state = CompositionState::Normal;
+    palette_update.palette_update_id = Some(PaletteId(0));
+    palette_update.objects.clear();
+    palette_update.palettes.clear();
+    palette_update.palettes.insert(VersionedId { id: PaletteId(0), version: 1 }, palette);
+
+    let stream = [shown_display_set(), palette_update, shown_display_set()];
+    let renders = |cache: &SharedCache| {
+
+        let mut state = EpochState::default();
+        let mut generations = Generations::default();
+
+        stream.iter()
+            .map(|display_set| {
+                state.apply(display_set);
+                generations.apply(display_set);
+                render(&state, display_set, &generations, cache).unwrap().rgba
+            })
+            .collect::<Vec<Vec<u8>>>()
+    };
+    let cache = ObjectCache::shared(1_048_576);
+
+    assert_eq!(renders(&ObjectCache::shared(0)), renders(&cache));
+    assert_eq!(renders(&cache), renders(&ObjectCache::shared(0)));
+    assert_eq!((cache.borrow().hits(), cache.borrow().misses()), (3, 3));
+}
+
+#[test]
+fn test_page_path() {
+    assert_eq!(page_path(Path::new("out/sheet.png"), 1), PathBuf::from("out/sheet.png"));
+    assert_eq!(page_path(Path::new("out/sheet.png"), 3), PathBuf::from("out/sheet-3.png"));
+    assert_eq!(page_path(Path::new("sheet"), 2), PathBuf::from("sheet-2"));
+}
+
+#[test]
+fn test_pes_sink_zeroes_segment_pts() {
+
+    let mut output = vec![];
+    let mut sink = PesSink::new(&mut output, true);
+
+    sink.write(&shown_display_set(), None).unwrap();
+    sink.finish().unwrap();
+
+    // PES header, then the presentation composition segment's header.
+    assert_eq!(output[..4], [0x00, 0x00, 0x01, 0xBD]);
+    assert_eq!(output[9..14], [0x21, 0x00, 0x05, 0xBF, 0x21]);
+    assert_eq!(output[14..24], [b'P', b'G', 0, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_pes_sink_matches_fixture() {
+
+    let input = include_bytes!("../../../test-data/fade.sup");
+    let expected = include_bytes!("../../../test-data/fade.pes");
+    let mut output = vec![];
+    let mut sink = PesSink::new(&mut output, false);
+
+    for display_set in Cursor::new(&input[..]).display_sets(&ReadOptions::default()) {
+        sink.write(&display_set.unwrap(), None).unwrap();
+    }
+    sink.finish().unwrap();
+
+    assert!(output == expected[..]);
+}
+
+#[test]
+fn test_bdn_sink_pairs_events() {
+
+    let directory = std::env::temp_dir().join(format!("pgsmod-bdn-{}", std::process::id()));
+
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let path = directory.join("movie.xml");
+    let mut sink = BdnSink::new(path.clone(), FrameRate::Fps25, ObjectCache::shared(0));
+    let shown = shown_display_set();
+    let mut shown_again = shown_display_set();
+    let mut repeated = shown_display_set();
+
+    shown_again.pts = 270_000;
+    repeated.pts = 360_000;
+    repeated.composition.state = CompositionState::AcquisitionPoint;
+
+    for display_set in [
+        shown.clone(),
+        pgs::displayset::clear_display_set(&shown, 180_000),
+        // Too short for a frame.
+        DisplaySet { pts: 180_500, ..shown.clone() },
+        pgs::displayset::clear_display_set(&shown, 181_000),
+        shown_again,
+        repeated,
+    ].iter() {
+        sink.write(display_set, None).unwrap();
+    }
+
+    sink.finish().unwrap();
+
+    let xml = std::fs::read_to_string(&path).unwrap();
+
+    assert_eq!(sink.events(), 2);
+    assert!(xml.contains("<Event InTC=\"00:00:01:00\" OutTC=\"00:00:02:00\" Forced=\"False\">"));
+    assert!(xml.contains("<Event InTC=\"00:00:03:00\" OutTC=\"00:00:08:00\" Forced=\"False\">"));
+    assert!(xml.contains(
+        "<Graphic Width=\"3\" Height=\"2\" X=\"100\" Y=\"900\">movie_0002.png</Graphic>"
+    ));
+    assert!(directory.join("movie_0001.png").exists());
+    assert!(directory.join("movie_0002.png").exists());
+    assert!(!directory.join("movie_0003.png").exists());
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,85 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use pgs::ts_to_timestamp;
+use std::time::Duration;
+
+#[derive(Default)]
+pub struct Timings {
+    stages: Vec<(&'static str, Duration)>,
+    epochs: Vec<(u32, Duration)>,
+    current_epoch: Duration,
+    cache: Option<(u64, u64)>,
+}
+
+impl Timings {
+
+    pub fn record(&mut self, stage: &'static str, elapsed: Duration) {
+
+        match self.stages.iter_mut().find(|(name, _)| *name == stage) {
+            Some((_, total)) => *total += elapsed,
+            None => self.stages.push((stage, elapsed)),
+        }
+
+        self.current_epoch += elapsed;
+    }
+
+    pub fn finish_epoch(&mut self, pts: u32) {
+        self.epochs.push((pts, self.current_epoch));
+        self.current_epoch = Duration::default();
+    }
+
+    pub fn record_cache(&mut self, hits: u64, misses: u64) {
+        self.cache = Some((hits, misses));
+    }
+
+    pub fn report(&self, bytes_read: u64, elapsed: Duration) {
+        eprint!("{}", self.summary(bytes_read, elapsed));
+    }
+
+    fn summary(&self, bytes_read: u64, elapsed: Duration) -> String {
+
+        let seconds = elapsed.as_secs_f64();
+        let mut summary = String::from("Timings:\n");
+
+        summary.push_str(&format!(
+            "  input: {} bytes in {:.3} s ({:.1} MB/s)\n",
+            bytes_read,
+            seconds,
+            if seconds > 0.0 { bytes_read as f64 / seconds / 1_000_000.0 } else { 0.0 },
+        ));
+
+        for (stage, total) in self.stages.iter() {
+            summary.push_str(&format!(
+                "  {:<16} {:>10.3} ms\n",
+                stage,
+                total.as_secs_f64() * 1_000.0,
+            ));
+        }
+
+        if let Some((hits, misses)) = self.cache {
+            summary.push_str(&format!("  object cache: {} hits, {} misses\n", hits, misses));
+        }
+
+        if let Some((pts, slowest)) = self.epochs.iter().max_by_key(|(_, duration)| *duration) {
+
+            let sum = self.epochs.iter().map(|(_, duration)| *duration).sum::<Duration>();
+
+            summary.push_str(&format!(
+                "  epochs: {}, mean {:.3} ms, slowest {:.3} ms at {}\n",
+                self.epochs.len(),
+                sum.as_secs_f64() * 1_000.0 / self.epochs.len() as f64,
+                slowest.as_secs_f64() * 1_000.0,
+                ts_to_timestamp(*pts),
+            ));
+        }
+
+        summary
+    }
+}
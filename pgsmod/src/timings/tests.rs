@@ -0,0 +1,40 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+
+#[test]
+fn test_stages_accumulate() {
+
+    let mut timings = Timings::default();
+
+    timings.record("read/decode", Duration::from_millis(2));
+    timings.record("resize-check", Duration::from_micros(250));
+    timings.record("read/decode", Duration::from_millis(3));
+    timings.finish_epoch(90_000);
+    timings.record("read/decode", Duration::from_millis(1));
+    timings.finish_epoch(180_000);
+    timings.record_cache(7, 3);
+
+    // Stages keep the order they were first seen in, and the slowest epoch is named by its PTS.
+    assert_eq!(
+        timings.summary(4_000_000, Duration::from_secs(2)),
+        "Timings:\n\
+        \x20 input: 4000000 bytes in 2.000 s (2.0 MB/s)\n\
+        \x20 read/decode           6.000 ms\n\
+        \x20 resize-check          0.250 ms\n\
+        \x20 object cache: 7 hits, 3 misses\n\
+        \x20 epochs: 2, mean 3.125 ms, slowest 5.250 ms at 00:00:01.000\n",
+    );
+}
+
+#[test]
+fn test_nothing_recorded() {
+    assert_eq!(
+        Timings::default().summary(0, Duration::ZERO),
+        "Timings:\n  input: 0 bytes in 0.000 s (0.0 MB/s)\n",
+    );
+}
@@ -0,0 +1,107 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: OSL-3.0
+ */
+
+#[cfg(test)]
+mod tests;
+
+use super::offset::{carry_definitions, parse_offset};
+use pgs::{
+    displayset::{clear_display_set, DisplaySet},
+    segment::CompositionState,
+};
+use std::convert::TryFrom;
+
+// The range to keep, in 90 kHz ticks, including its start but not its end.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trim {
+    pub start: u32,
+    pub end: Option<u32>,
+    // Moves the start of the range to zero.
+    pub rebase: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Trimmed {
+    pub dropped: usize,
+    // Events that were already showing when the range started and were shown again at its start.
+    pub carried_in: usize,
+    // Events that were still showing when the range ended and were cleared there.
+    pub cut_off: usize,
+}
+
+// Takes a whole number of milliseconds or an HH:MM:SS.mmm timestamp and returns it in 90 kHz
+// ticks.
+pub fn parse_trim_point(value: &str) -> Option<u32> {
+    match value.starts_with('-') {
+        true => None,
+        false => u32::try_from(parse_offset(value)?).ok(),
+    }
+}
+
+// Drops the display sets of the epoch that fall outside of the range. Whatever is still on screen
+// when the range starts is shown again right at its start, as an epoch start carrying everything
+// that the dropped display sets defined, and whatever is still on screen when the range ends is
+// cleared right there. The next PTS is where the following epoch starts, if there is one.
+pub fn trim_epoch(epoch: &mut Vec<DisplaySet>, trim: Trim, next_pts: Option<u32>) -> Trimmed {
+
+    let mut trimmed = Trimmed::default();
+    let before = epoch.iter().take_while(|display_set| display_set.pts < trim.start).count();
+
+    if before > 0 {
+
+        let mut dropped = epoch.drain(..before).collect::<Vec<DisplaySet>>();
+        let replaced_at = epoch.first().map(|display_set| display_set.pts).or(next_pts);
+        let showing = !dropped[before - 1].composition.objects.is_empty()
+            && replaced_at.is_none_or(|pts| pts > trim.start);
+
+        if showing {
+
+            let mut carried = dropped.pop().unwrap();
+
+            carry_definitions(&dropped, &mut carried);
+            carried.pts = trim.start;
+            carried.dts = 0;
+            carried.palette_update_id = None;
+            carried.composition.state = CompositionState::EpochStart;
+            epoch.insert(0, carried);
+            trimmed.carried_in += 1;
+        } else if let Some(first) = epoch.first_mut() {
+            carry_definitions(&dropped, first);
+        }
+
+        trimmed.dropped += dropped.len();
+    }
+
+    if let Some(end) = trim.end {
+
+        let kept = epoch.iter()
+            .position(|display_set| display_set.pts >= end)
+            .unwrap_or(epoch.len());
+        let cut = epoch.len() - kept;
+
+        epoch.truncate(kept);
+        trimmed.dropped += cut;
+
+        if let Some(last) = epoch.last() {
+            if !last.composition.objects.is_empty()
+                && (cut > 0 || next_pts.is_none_or(|pts| pts >= end)) {
+                epoch.push(clear_display_set(last, end));
+                trimmed.cut_off += 1;
+            }
+        }
+    }
+
+    if trim.rebase {
+        for display_set in epoch.iter_mut() {
+            display_set.pts -= trim.start;
+            if display_set.dts != 0 {
+                display_set.dts = display_set.dts.saturating_sub(trim.start);
+            }
+        }
+    }
+
+    trimmed
+}
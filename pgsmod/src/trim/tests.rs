@@ -0,0 +1,186 @@
+/*
+ * SPDX-FileCopyrightText: 2021 William Swartzendruber <wswartzendruber@gmail.com>
+ *
+ * SPDX-License-Identifier: CC0-1.0
+ */
+
+use super::*;
+use pgs::{
+    displayset::{Cid, CompositionObject, Object, Palette, Window},
+    id::{ObjectId, PaletteId, VersionedId, WindowId},
+};
+
+// An epoch start at one second that defines everything, a palette update at two seconds, and then
+// a clear at three seconds.
+fn epoch() -> Vec<DisplaySet> {
+
+    let mut start = DisplaySet { pts: 90_000, dts: 85_000, ..Default::default() };
+
+    start.composition.state = CompositionState::EpochStart;
+    start.windows.insert(WindowId(0), Window { x: 0, y: 0, width: 2, height: 1 });
+    start.palettes.insert(VersionedId { id: PaletteId(0), version: 0 }, Palette::default());
+    start.objects.insert(
+        VersionedId { id: ObjectId(0), version: 0 },
+        Object { width: 2, height: 1, lines: vec![vec![1, 1]], ..Default::default() },
+    );
+    start.composition.objects.insert(
+        Cid { object_id: ObjectId(0), window_id: WindowId(0) },
+        CompositionObject { x: 0, y: 0, crop: None, forced: false },
+    );
+
+    let mut update = DisplaySet { pts: 180_000, ..Default::default() };
+
+    update.palette_update_id = Some(PaletteId(0));
+    update.palettes.insert(VersionedId { id: PaletteId(0), version: 1 }, Palette::default());
+    update.composition.objects = start.composition.objects.clone();
+
+    vec![start, update, DisplaySet { pts: 270_000, ..Default::default() }]
+}
+
+fn timestamps(epoch: &[DisplaySet]) -> Vec<(u32, u32)> {
+    epoch.iter().map(|display_set| (display_set.pts, display_set.dts)).collect()
+}
+
+#[test]
+fn test_parse_trim_point() {
+    assert_eq!(parse_trim_point("1500"), Some(135_000));
+    assert_eq!(parse_trim_point("00:00:01.500"), Some(135_000));
+    assert_eq!(parse_trim_point("-1500"), None);
+    assert_eq!(parse_trim_point("13:16:00.000"), None);
+}
+
+#[test]
+fn test_trim_within_range_keeps_everything() {
+
+    let mut epoch = epoch();
+    let trim = Trim { start: 45_000, end: Some(360_000), rebase: false };
+
+    assert_eq!(trim_epoch(&mut epoch, trim, None), Trimmed::default());
+    assert_eq!(timestamps(&epoch), vec![(90_000, 85_000), (180_000, 0), (270_000, 0)]);
+}
+
+#[test]
+fn test_trim_rebases() {
+
+    let mut epoch = epoch();
+    let trim = Trim { start: 45_000, end: None, rebase: true };
+
+    trim_epoch(&mut epoch, trim, None);
+
+    assert_eq!(timestamps(&epoch), vec![(45_000, 40_000), (135_000, 0), (225_000, 0)]);
+}
+
+#[test]
+fn test_trim_start_shows_event_again() {
+
+    let mut epoch = epoch();
+    let trim = Trim { start: 225_000, end: None, rebase: true };
+
+    assert_eq!(
+        trim_epoch(&mut epoch, trim, None),
+        Trimmed { dropped: 1, carried_in: 1, cut_off: 0 },
+    );
+    assert_eq!(timestamps(&epoch), vec![(0, 0), (45_000, 0)]);
+    assert_eq!(epoch[0].composition.state, CompositionState::EpochStart);
+    assert_eq!(epoch[0].palette_update_id, None);
+    assert_eq!(epoch[0].windows.len(), 1);
+    assert_eq!(epoch[0].objects.len(), 1);
+    assert_eq!(
+        epoch[0].palettes.keys().cloned().collect::<Vec<_>>(),
+        vec![VersionedId { id: PaletteId(0), version: 1 }],
+    );
+}
+
+#[test]
+fn test_trim_start_after_clear_carries_definitions() {
+
+    let mut epoch = epoch();
+    let trim = Trim { start: 270_000, end: None, rebase: false };
+
+    assert_eq!(
+        trim_epoch(&mut epoch, trim, None),
+        Trimmed { dropped: 2, carried_in: 0, cut_off: 0 },
+    );
+    assert_eq!(timestamps(&epoch), vec![(270_000, 0)]);
+    assert_eq!(epoch[0].composition.state, CompositionState::EpochStart);
+    assert!(epoch[0].composition.objects.is_empty());
+    assert_eq!(epoch[0].objects.len(), 1);
+}
+
+#[test]
+fn test_trim_start_after_whole_epoch() {
+
+    let mut epoch = epoch();
+
+    epoch.pop();
+
+    // Another epoch replaces this one before the range starts.
+    let trim = Trim { start: 450_000, end: None, rebase: false };
+
+    assert_eq!(
+        trim_epoch(&mut epoch, trim, Some(360_000)),
+        Trimmed { dropped: 2, carried_in: 0, cut_off: 0 },
+    );
+    assert!(epoch.is_empty());
+
+    // Nothing replaces it, so it is still showing.
+    let mut epoch = self::epoch();
+
+    epoch.pop();
+
+    assert_eq!(
+        trim_epoch(&mut epoch, trim, Some(540_000)),
+        Trimmed { dropped: 1, carried_in: 1, cut_off: 0 },
+    );
+    assert_eq!(timestamps(&epoch), vec![(450_000, 0)]);
+}
+
+#[test]
+fn test_trim_end_clears_event() {
+
+    let mut epoch = epoch();
+    let trim = Trim { start: 0, end: Some(225_000), rebase: true };
+
+    assert_eq!(
+        trim_epoch(&mut epoch, trim, None),
+        Trimmed { dropped: 1, carried_in: 0, cut_off: 1 },
+    );
+    assert_eq!(
+        timestamps(&epoch),
+        vec![(90_000, 85_000), (180_000, 0), (225_000, 225_000)],
+    );
+    assert!(epoch[2].composition.objects.is_empty());
+}
+
+#[test]
+fn test_trim_end_before_next_epoch_clears_event() {
+
+    let mut epoch = epoch();
+
+    epoch.pop();
+
+    let trim = Trim { start: 0, end: Some(360_000), rebase: false };
+
+    assert_eq!(
+        trim_epoch(&mut epoch.clone(), trim, Some(270_000)),
+        Trimmed::default(),
+    );
+    assert_eq!(
+        trim_epoch(&mut epoch, trim, Some(450_000)),
+        Trimmed { dropped: 0, carried_in: 0, cut_off: 1 },
+    );
+    assert_eq!(epoch.last().unwrap().pts, 360_000);
+}
+
+#[test]
+fn test_trim_both_ends_of_one_event() {
+
+    let mut epoch = epoch();
+    let trim = Trim { start: 135_000, end: Some(225_000), rebase: true };
+
+    assert_eq!(
+        trim_epoch(&mut epoch, trim, None),
+        Trimmed { dropped: 1, carried_in: 1, cut_off: 1 },
+    );
+    assert_eq!(timestamps(&epoch), vec![(0, 0), (45_000, 0), (90_000, 90_000)]);
+}
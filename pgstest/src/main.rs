@@ -5,34 +5,156 @@
  */
 
 use pgs::{
+    ts_to_timestamp,
+    cache::{Generations, ObjectCache},
+    check::{Checker, ExcessiveCoverage, Finding, Severity},
+    png::write_png,
+    style::{Color, Style},
     displayset::{
+        DisplaySet,
         ReadDisplaySetExt,
         ReadError as DisplaySetReadError,
     },
     segment::{
+        CompositionState,
         ReadError as SegmentReadError,
+        ReadOptions,
     },
+    timeline::{EpochState, Heatmap},
 };
 use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashSet},
     fs::File,
-    io::{stdin, BufReader, ErrorKind, Read},
+    hash::{Hash, Hasher},
+    io::{stdin, BufReader, BufWriter, ErrorKind, Read},
+    path::Path,
+    process::exit,
 };
 use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version, Arg};
 
+struct RuleSummary {
+    name: &'static str,
+    severity: Severity,
+    count: usize,
+    first: Finding,
+    last: Finding,
+}
+
 fn main() {
 
     let matches = app_from_crate!()
+        .arg(Arg::with_name("info")
+            .long("info")
+            .help("Prints a summary of the stream once it has been read")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("suppress")
+            .long("suppress")
+            .value_name("RULE-ID")
+            .help("Ignores findings from the specified rule")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .required(false)
+        )
+        .arg(Arg::with_name("error-on")
+            .long("error-on")
+            .value_name("RULE-ID")
+            .help("Treats findings from the specified rule as errors")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .required(false)
+        )
+        .arg(Arg::with_name("json")
+            .long("json")
+            .help("Prints findings as JSON")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("summary-only")
+            .long("summary-only")
+            .help("Prints one line per rule without the example finding")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("no-color")
+            .long("no-color")
+            .help("Disables colored output; NO_COLOR is also honored")
+            .takes_value(false)
+            .required(false)
+        )
+        .arg(Arg::with_name("max-coverage")
+            .long("max-coverage")
+            .value_name("PERCENT")
+            .help("Flags display sets covering more of the canvas than this; defaults to 40")
+            .takes_value(true)
+            .required(false)
+            .validator(|value| {
+                match value.parse::<f64>() {
+                    Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(()),
+                    _ => Err("must be a percentage from 0 to 100".to_string()),
+                }
+            })
+        )
+        .arg(Arg::with_name("heatmap")
+            .long("heatmap")
+            .value_name("PNG-FILE")
+            .help("Renders how long each pixel is covered by subtitles over the whole stream")
+            .takes_value(true)
+            .required(false)
+        )
+        .arg(Arg::with_name("list-rules")
+            .long("list-rules")
+            .help("Lists every rule that can be checked and exits")
+            .takes_value(false)
+            .required(false)
+        )
         .arg(Arg::with_name("input")
             .index(1)
             .value_name("INPUT-FILE")
             .help("Input PGS file; use - for STDIN")
-            .required(true)
+            .required_unless("list-rules")
         )
         .after_help(format!("This utility will test PGS subtitles.\n\n\
             Copyright © 2021 William Swartzendruber\n\
             Licensed under the Open Software License version 3.0\n\
             <{}>", env!("CARGO_PKG_REPOSITORY")).as_str())
         .get_matches();
+    let info = matches.is_present("info");
+    let json = matches.is_present("json");
+    let summary_only = matches.is_present("summary-only");
+    let style = Style::detect(matches.is_present("no-color"));
+    let heatmap_path = matches.value_of("heatmap");
+    let mut checker = Checker::default();
+
+    if matches.is_present("list-rules") {
+        for rule in checker.rules() {
+            println!(
+                "{}  {}  {}",
+                rule.id(),
+                style.pad(rule.severity(), 7, severity_color(rule.severity())),
+                rule.name(),
+            );
+        }
+        return
+    }
+
+    if let Some(value) = matches.value_of("max-coverage") {
+        checker.replace(Box::new(ExcessiveCoverage { max_percent: value.parse().unwrap() }));
+    }
+    for id in matches.values_of("suppress").into_iter().flatten() {
+        if !checker.suppress(id) {
+            panic!("unknown rule ID: {}", id)
+        }
+    }
+    for id in matches.values_of("error-on").into_iter().flatten() {
+        if !checker.set_severity(id, Severity::Error) {
+            panic!("unknown rule ID: {}", id)
+        }
+    }
+
     let input_value = matches.value_of("input").unwrap();
     let (mut stdin_read, mut file_read);
     let mut input = BufReader::<&mut dyn Read>::new(
@@ -46,6 +168,19 @@ fn main() {
         }
     );
 
+    let mut display_set_count = 0;
+    let mut epoch_count = 0;
+    let mut palette_count = 0;
+    let mut palette_hashes = HashSet::<u64>::new();
+    let mut findings = Vec::<Finding>::new();
+    let mut heatmaps = BTreeMap::<(u16, u16), Heatmap>::new();
+    let mut heatmap_state = EpochState::default();
+    let mut heatmap_previous = None::<DisplaySet>;
+    let mut heatmap_generations = Generations::default();
+    let mut heatmap_cache = ObjectCache::new(64 * 1_048_576);
+
+    let read_options = ReadOptions { lenient: true, ..Default::default() };
+
     eprintln!("Iterating through PGS display sets...");
 
     //
@@ -54,8 +189,43 @@ fn main() {
 
     loop {
 
-        match input.read_display_set() {
-            Ok(_) => {
+        match input.read_display_set_with(&read_options) {
+            Ok(display_set) => {
+
+                findings.extend(checker.check(&display_set));
+                display_set_count += 1;
+
+                if display_set.composition.state == CompositionState::EpochStart {
+                    epoch_count += 1;
+                }
+
+                for palette in display_set.palettes.values() {
+
+                    let mut hasher = DefaultHasher::new();
+
+                    palette.hash(&mut hasher);
+                    palette_hashes.insert(hasher.finish());
+                    palette_count += 1;
+                }
+
+                // A display set stays on screen until the next one arrives, so it is accumulated
+                // once its duration is known.
+                if heatmap_path.is_some() {
+                    if let Some(previous) = heatmap_previous.take() {
+                        heatmaps.entry((previous.width, previous.height))
+                            .or_insert_with(|| Heatmap::new(previous.width, previous.height))
+                            .add_cached(
+                                &heatmap_state,
+                                &previous,
+                                display_set.pts.saturating_sub(previous.pts),
+                                &heatmap_generations,
+                                &mut heatmap_cache,
+                            );
+                    }
+                    heatmap_state.apply(&display_set);
+                    heatmap_generations.apply(&display_set);
+                    heatmap_previous = Some(display_set);
+                }
             }
             Err(err) => {
                 match err {
@@ -80,4 +250,161 @@ fn main() {
             }
         };
     }
+    if info {
+        println!("display sets: {}", display_set_count);
+        println!("epochs: {}", epoch_count);
+        println!(
+            "palettes: {} unique across {} definitions in {} epochs",
+            palette_hashes.len(), palette_count, epoch_count,
+        );
+    }
+
+    if let Some(path) = heatmap_path {
+        write_heatmaps(path, &heatmaps);
+    }
+
+    let mut summaries = BTreeMap::<&'static str, RuleSummary>::new();
+
+    for finding in findings.iter() {
+        match summaries.get_mut(finding.rule) {
+            Some(summary) => {
+                summary.count += 1;
+                summary.last = finding.clone();
+            }
+            None => {
+                summaries.insert(
+                    finding.rule,
+                    RuleSummary {
+                        name: checker.rule(finding.rule).map_or("", |rule| rule.name()),
+                        severity: finding.severity,
+                        count: 1,
+                        first: finding.clone(),
+                        last: finding.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    if json {
+        print_json(&summaries, &findings);
+    } else {
+        for (id, summary) in summaries.iter() {
+            println!(
+                "{}  {}  {:>6}  {}  {}  {}",
+                id,
+                style.pad(summary.severity, 7, severity_color(summary.severity)),
+                summary.count,
+                style.timestamp(summary.first.pts),
+                style.timestamp(summary.last.pts),
+                summary.name,
+            );
+            if !summary_only {
+                println!("    {}", summary.first.message);
+            }
+        }
+    }
+
+    if findings.iter().any(|finding| finding.severity == Severity::Error) {
+        exit(1)
+    }
+}
+
+fn write_heatmaps(path: &str, heatmaps: &BTreeMap<(u16, u16), Heatmap>) {
+
+    for ((width, height), heatmap) in heatmaps.iter() {
+
+        // Streams that change resolution get one image per resolution.
+        let path = if heatmaps.len() == 1 {
+            path.to_string()
+        } else {
+            let path = Path::new(path);
+            let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("heatmap");
+            let name = match path.extension().and_then(|extension| extension.to_str()) {
+                Some(extension) => format!("{}-{}x{}.{}", stem, width, height, extension),
+                None => format!("{}-{}x{}", stem, width, height),
+            };
+            path.with_file_name(name).to_string_lossy().into_owned()
+        };
+        let file = File::create(&path).expect("Could not open heatmap file for writing.");
+
+        if let Err(err) = write_png(
+            &mut BufWriter::new(file),
+            *width as u32,
+            *height as u32,
+            &heatmap.render(),
+        ) {
+            panic!("Could not write heatmap: {}", err)
+        }
+
+        eprintln!(
+            "Wrote {}x{} heatmap to {}; the top of the legend is {:.1} seconds.",
+            width, height, path, heatmap.max(),
+        );
+    }
+}
+
+fn severity_color(severity: Severity) -> Color {
+    match severity {
+        Severity::Info => Color::Blue,
+        Severity::Warning => Color::Yellow,
+        Severity::Error => Color::Red,
+    }
+}
+
+fn print_json(summaries: &BTreeMap<&'static str, RuleSummary>, findings: &[Finding]) {
+
+    println!("{{");
+    println!("  \"rules\": [");
+
+    for (index, (id, summary)) in summaries.iter().enumerate() {
+        println!(
+            "    {{\"id\": {}, \"name\": {}, \"severity\": \"{}\", \"count\": {}, \
+            \"first_pts\": {}, \"last_pts\": {}}}{}",
+            json_string(id),
+            json_string(summary.name),
+            summary.severity,
+            summary.count,
+            summary.first.pts,
+            summary.last.pts,
+            if index + 1 < summaries.len() { "," } else { "" },
+        );
+    }
+
+    println!("  ],");
+    println!("  \"findings\": [");
+
+    for (index, finding) in findings.iter().enumerate() {
+        println!(
+            "    {{\"rule\": {}, \"severity\": \"{}\", \"pts\": {}, \"timestamp\": \"{}\", \
+            \"message\": {}}}{}",
+            json_string(finding.rule),
+            finding.severity,
+            finding.pts,
+            ts_to_timestamp(finding.pts),
+            json_string(&finding.message),
+            if index + 1 < findings.len() { "," } else { "" },
+        );
+    }
+
+    println!("  ]");
+    println!("}}");
+}
+
+fn json_string(value: &str) -> String {
+
+    let mut output = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+
+    output.push('"');
+
+    output
 }